@@ -1,5 +1,5 @@
 use axum::{
-    extract::State,
+    extract::{Path, State},
     http::StatusCode,
     response::{IntoResponse, Json},
     routing::{get, post},
@@ -8,76 +8,178 @@ use axum::{
 use serde::{Deserialize, Serialize};
 use tracing::{error, info};
 
-use crate::garbage_collector::{GarbageCollector, GarbageCollectorMetrics};
+use crate::api::registry::quota::organization_usage;
+use crate::garbage_collector::{GarbageCollector, GcProgress};
 use crate::server::AppState;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct GarbageCollectionRequest {
     pub dry_run: Option<bool>,
+    pub max_blobs: Option<usize>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GarbageCollectionResponse {
     pub success: bool,
     pub message: String,
-    pub metrics: Option<GarbageCollectorMetrics>,
+    pub progress: Option<GcProgress>,
 }
 
 pub fn router() -> Router<AppState> {
     Router::new()
-        .route("/gc", post(trigger_garbage_collection))
+        .route(
+            "/gc",
+            get(get_gc_progress)
+                .post(trigger_garbage_collection)
+                .delete(cancel_garbage_collection),
+        )
         .route("/gc/status", get(get_gc_status))
+        .route("/gc/plan", get(get_gc_plan))
+        .route("/organizations/:id/usage", get(get_organization_usage))
+        .route("/storage/manifest-index", post(rebuild_manifest_index))
+        .route("/storage/fsck", post(fsck_storage))
 }
 
+/// Start a collection in the background; poll `GET /gc` for progress
 async fn trigger_garbage_collection(
     State(state): State<AppState>,
-    Json(request): Json<GarbageCollectionRequest>,
+    request: Option<Json<GarbageCollectionRequest>>,
 ) -> impl IntoResponse {
     info!("Admin API: Triggering garbage collection");
+    let request = request.map(|Json(request)| request).unwrap_or_default();
 
     // Get garbage collector config
     let gc_config = match &state.config.garbage_collector {
         Some(config) => config.clone(),
         None => {
-            return Json(GarbageCollectionResponse {
-                success: false,
-                message: "Garbage collection is not configured".to_string(),
-                metrics: None,
-            })
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(GarbageCollectionResponse {
+                    success: false,
+                    message: "Garbage collection is not configured".to_string(),
+                    progress: None,
+                }),
+            )
         }
     };
 
-    // Override dry_run if specified in request
+    // Override dry_run and the per-run blob limit if specified in request
     let mut gc_config = gc_config;
     if let Some(dry_run) = request.dry_run {
         gc_config.dry_run = dry_run;
     }
+    if let Some(max_blobs) = request.max_blobs {
+        gc_config.max_blobs_per_run = max_blobs;
+    }
+
+    let dry_run = gc_config.dry_run;
+    if !state.gc.begin(dry_run) {
+        return (
+            StatusCode::CONFLICT,
+            Json(GarbageCollectionResponse {
+                success: false,
+                message: "Garbage collection is already running".to_string(),
+                progress: Some(state.gc.progress()),
+            }),
+        );
+    }
 
     // Create garbage collector instance
-    let gc = GarbageCollector::new(gc_config, state.storage.clone());
+    let mut gc = GarbageCollector::new(
+        gc_config,
+        state.storage.clone(),
+        state.config.registry.upload_session_ttl_hours,
+    )
+    .with_handle(state.gc.clone())
+    .with_holds(state.holds.clone(), state.repository_locks.clone());
+    if let Some(optimization) = &state.optimization {
+        gc = gc.with_optimization(optimization.clone());
+    }
 
-    // Run garbage collection
-    match gc.trigger_manual_run().await {
-        Ok(metrics) => {
-            info!("Manual garbage collection completed successfully");
-            Json(GarbageCollectionResponse {
-                success: true,
-                message: format!(
-                    "Garbage collection completed: {} blobs deleted, {} manifests deleted, {} bytes freed",
-                    metrics.blobs_deleted,
-                    metrics.manifests_deleted,
-                    metrics.bytes_freed
-                ),
-                metrics: Some(metrics),
-            })
+    let metrics = state.metrics.clone();
+    let rbac = state.rbac.clone();
+    tokio::spawn(async move {
+        match gc.execute().await {
+            Ok(result) => {
+                info!(
+                    "Manual garbage collection finished: {} blobs deleted, {} manifests deleted, {} bytes freed",
+                    result.blobs_deleted, result.manifests_deleted, result.bytes_freed
+                );
+                if !dry_run {
+                    metrics.record_gc_run(result.bytes_freed);
+                    // Organization usage is measured again on the next quota check
+                    if let Some(rbac) = rbac {
+                        rbac.invalidate_organization_usage().await;
+                    }
+                }
+            }
+            Err(e) => error!("Manual garbage collection failed: {}", e),
         }
-        Err(e) => {
-            error!("Manual garbage collection failed: {}", e);
+    });
+
+    (
+        StatusCode::ACCEPTED,
+        Json(GarbageCollectionResponse {
+            success: true,
+            message: "Garbage collection started".to_string(),
+            progress: Some(state.gc.progress()),
+        }),
+    )
+}
+
+/// Progress of the current run, or the outcome of the last one
+async fn get_gc_progress(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.gc.progress())
+}
+
+/// Ask the running collection to stop; it finishes its current item first
+async fn cancel_garbage_collection(State(state): State<AppState>) -> impl IntoResponse {
+    if !state.gc.cancel() {
+        return (
+            StatusCode::CONFLICT,
             Json(GarbageCollectionResponse {
                 success: false,
-                message: format!("Garbage collection failed: {}", e),
-                metrics: None,
-            })
+                message: "No garbage collection is running".to_string(),
+                progress: Some(state.gc.progress()),
+            }),
+        );
+    }
+
+    info!("Admin API: Cancelling garbage collection");
+    (
+        StatusCode::ACCEPTED,
+        Json(GarbageCollectionResponse {
+            success: true,
+            message: "Garbage collection cancellation requested".to_string(),
+            progress: Some(state.gc.progress()),
+        }),
+    )
+}
+
+/// Preview what a collection would remove without deleting anything
+async fn get_gc_plan(State(state): State<AppState>) -> impl IntoResponse {
+    let Some(gc_config) = state.config.garbage_collector.clone() else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": "Garbage collection is not configured" })),
+        );
+    };
+
+    // Not attached to the shared handle, so a plan never shows up as a run
+    let mut gc = GarbageCollector::new(gc_config, state.storage.clone(), state.config.registry.upload_session_ttl_hours)
+        .with_holds(state.holds.clone(), state.repository_locks.clone());
+    if let Some(optimization) = &state.optimization {
+        gc = gc.with_optimization(optimization.clone());
+    }
+
+    match gc.plan().await {
+        Ok(report) => (StatusCode::OK, Json(serde_json::json!(report))),
+        Err(e) => {
+            error!("Failed to plan garbage collection: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({ "error": "Failed to plan garbage collection" })),
+            )
         }
     }
 }
@@ -92,6 +194,7 @@ async fn get_gc_status(State(state): State<AppState>) -> impl IntoResponse {
             "grace_period_hours": config.grace_period_hours,
             "dry_run": config.dry_run,
             "max_blobs_per_run": config.max_blobs_per_run,
+            "collect_untagged_manifests": config.collect_untagged_manifests,
             "status": "configured"
         }),
         None => serde_json::json!({
@@ -103,3 +206,61 @@ async fn get_gc_status(State(state): State<AppState>) -> impl IntoResponse {
     Json(response)
 }
 
+/// Storage used by an organization's repositories against its quota
+async fn get_organization_usage(State(state): State<AppState>, Path(id): Path<String>) -> impl IntoResponse {
+    let Some(rbac) = state.rbac.as_ref() else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": "RBAC is not enabled" })),
+        );
+    };
+    let Some(organization) = rbac.get_organization(&id).await else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": format!("Organization {} not found", id) })),
+        );
+    };
+
+    match organization_usage(&state, &organization).await {
+        Ok(usage) => (StatusCode::OK, Json(serde_json::json!(usage))),
+        Err(e) => {
+            error!("Failed to measure storage usage of organization {}: {}", id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({ "error": "Failed to measure storage usage" })),
+            )
+        }
+    }
+}
+
+/// Backfill the storage backend's manifest digest index for content pushed before it existed
+async fn rebuild_manifest_index(State(state): State<AppState>) -> impl IntoResponse {
+    info!("Admin API: Rebuilding manifest digest index");
+
+    match state.storage.rebuild_manifest_index().await {
+        Ok(indexed) => (StatusCode::OK, Json(serde_json::json!({ "indexed": indexed }))),
+        Err(e) => {
+            error!("Failed to rebuild manifest digest index: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({ "error": "Failed to rebuild manifest digest index" })),
+            )
+        }
+    }
+}
+
+/// Check stored manifests against their blobs and rebuild the backend's reference tables
+async fn fsck_storage(State(state): State<AppState>) -> impl IntoResponse {
+    info!("Admin API: Checking storage");
+
+    match state.storage.fsck().await {
+        Ok(report) => (StatusCode::OK, Json(serde_json::json!(report))),
+        Err(e) => {
+            error!("Storage check failed: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({ "error": "Storage check failed" })),
+            )
+        }
+    }
+}
@@ -1,15 +1,19 @@
+use crate::auth::oauth::{OAuthError, OAuthProvider};
 use crate::auth::User;
 use crate::server::AppState;
 use axum::{
-    extract::State,
-    http::StatusCode,
-    response::IntoResponse,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Redirect, Response},
     routing::{get, post},
     Json, Router,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tracing::{info, warn};
+use tracing::{error, info, warn};
+
+/// Cookie carrying the drift JWT for browser sessions
+pub const SESSION_COOKIE: &str = "drift_session";
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LoginRequest {
@@ -24,6 +28,13 @@ pub struct LoginResponse {
     pub expires_in: u64,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct OAuthCallbackParams {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RegisterRequest {
     pub username: String,
@@ -38,6 +49,115 @@ pub fn router() -> Router<AppState> {
         .route("/refresh", post(refresh_token))
         .route("/logout", post(logout))
         .route("/whoami", get(whoami))
+        .route("/:provider", get(oauth_login))
+        .route("/:provider/callback", get(oauth_callback))
+}
+
+/// Redirect the browser to the provider's consent page
+pub async fn oauth_login(
+    State(state): State<AppState>,
+    Path(provider): Path<String>,
+) -> Response {
+    let Some(oauth) = state.oauth.as_ref() else {
+        return login_error_redirect("oauth_disabled");
+    };
+    let provider = match OAuthProvider::parse(&provider) {
+        Ok(provider) => provider,
+        Err(e) => {
+            warn!("{}", e);
+            return login_error_redirect("unknown_provider");
+        }
+    };
+
+    match oauth.authorize_url(provider) {
+        Ok(url) => Redirect::to(&url).into_response(),
+        Err(e) => {
+            error!("Failed to start {} login: {}", provider.as_str(), e);
+            login_error_redirect("provider_error")
+        }
+    }
+}
+
+/// Provider callback: exchange the code, provision the user and start a session
+pub async fn oauth_callback(
+    State(state): State<AppState>,
+    Path(provider): Path<String>,
+    Query(params): Query<OAuthCallbackParams>,
+) -> Response {
+    let Some(oauth) = state.oauth.as_ref() else {
+        return login_error_redirect("oauth_disabled");
+    };
+    let Ok(provider) = OAuthProvider::parse(&provider) else {
+        return login_error_redirect("unknown_provider");
+    };
+
+    if let Some(provider_error) = params.error {
+        warn!("{} login failed: {}", provider.as_str(), provider_error);
+        return login_error_redirect(&provider_error);
+    }
+    let (Some(code), Some(login_state)) = (params.code, params.state) else {
+        return login_error_redirect("invalid_request");
+    };
+
+    let oauth_user = match oauth.complete_login(provider, &code, &login_state).await {
+        Ok(user) => user,
+        Err(e) => {
+            warn!("{} login failed: {}", provider.as_str(), e);
+            return match e.downcast_ref::<OAuthError>() {
+                Some(OAuthError::StateMismatch) => login_error_redirect("state_mismatch"),
+                _ => login_error_redirect("provider_error"),
+            };
+        }
+    };
+
+    let user_id = format!("{}:{}", provider.as_str(), oauth_user.id);
+    let roles = match &state.rbac {
+        Some(rbac) => match rbac.provision_user(&user_id, &oauth_user.email, &oauth_user.name).await {
+            Ok(user) => user.direct_roles.into_iter().collect(),
+            Err(e) => {
+                warn!("Refusing login for {}: {}", user_id, e);
+                return login_error_redirect("access_denied");
+            }
+        },
+        None => Vec::new(),
+    };
+
+    let user = User {
+        username: user_id,
+        roles,
+        scopes: state
+            .config
+            .auth
+            .oauth
+            .as_ref()
+            .map(|oauth| oauth.default_scopes.clone())
+            .unwrap_or_default(),
+    };
+
+    let expires_in = state.config.auth.token_expiry_hours * 60 * 60;
+    let token = match state.auth.generate_token(&user, expires_in) {
+        Ok(token) => token,
+        Err(e) => {
+            error!("Failed to issue session token for {}: {}", user.username, e);
+            return login_error_redirect("provider_error");
+        }
+    };
+
+    let mut cookie = format!(
+        "{}={}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}",
+        SESSION_COOKIE, token, expires_in
+    );
+    if oauth.uses_https(provider) {
+        cookie.push_str("; Secure");
+    }
+
+    info!("Started session for {} via {}", user.username, provider.as_str());
+    ([(header::SET_COOKIE, cookie)], Redirect::to("/")).into_response()
+}
+
+/// Send the browser back to the login page with an error the form can display
+fn login_error_redirect(error: &str) -> Response {
+    Redirect::to(&format!("/login?error={}", urlencoding::encode(error))).into_response()
 }
 
 pub async fn login(
@@ -99,9 +219,14 @@ pub async fn logout(
     State(_state): State<AppState>,
 ) -> Result<impl IntoResponse, StatusCode> {
     // TODO: Implement token invalidation
-    Ok(Json(json!({
-        "message": "Logged out successfully"
-    })))
+    // Expire the browser session started by an OAuth login
+    let cookie = format!("{}=; Path=/; HttpOnly; SameSite=Lax; Max-Age=0", SESSION_COOKIE);
+    Ok((
+        [(header::SET_COOKIE, cookie)],
+        Json(json!({
+            "message": "Logged out successfully"
+        })),
+    ))
 }
 
 pub async fn whoami(
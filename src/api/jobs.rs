@@ -0,0 +1,31 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json},
+    routing::get,
+    Extension, Router,
+};
+use serde_json::json;
+
+use crate::auth::User;
+use crate::server::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/:id", get(get_job))
+}
+
+/// Status of a background job, with its result once finished
+async fn get_job(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    user: Option<Extension<User>>,
+) -> impl IntoResponse {
+    if user.is_none() {
+        return (StatusCode::UNAUTHORIZED, Json(json!({ "error": "Authentication required" })));
+    }
+
+    match state.jobs.get(&id) {
+        Some(job) => (StatusCode::OK, Json(json!(job))),
+        None => (StatusCode::NOT_FOUND, Json(json!({ "error": format!("Job {} not found", id) }))),
+    }
+}
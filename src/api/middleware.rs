@@ -1,137 +1,648 @@
-use crate::auth::User;
+use crate::api::auth::SESSION_COOKIE;
+use crate::api::registry::{OciError, RegistryError};
+use crate::audit::{AuditService, EventType, RequestContext, UserInfo};
+use crate::auth::robots::ROBOT_PREFIX;
+use crate::auth::{RegistryToken, User};
+use crate::config::AuthMode;
+use crate::rate_limit::RateLimitClass;
+use crate::rbac::{Action, AuthzRequest, ResourceType};
 use crate::server::AppState;
+use crate::tls::TlsConnection;
 use axum::{
-    extract::{Request, State},
-    http::{header, StatusCode},
+    extract::{ConnectInfo, MatchedPath, OriginalUri, Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
     middleware::Next,
     response::{IntoResponse, Response},
-    Extension,
+    Json,
 };
 use base64::{engine::general_purpose, Engine as _};
+use ipnet::IpNet;
+use serde_json::json;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::sync::LazyLock;
+use std::time::Instant;
 use tracing::{debug, warn};
 
+/// Matches repository-scoped registry paths, capturing the repository name
+static REPOSITORY_PATH: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(r"^/v2/(.+?)/(manifests|blobs|tags|signatures)/").unwrap()
+});
+
+/// Header carrying the id that ties a request to its audit events
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest client-supplied request id that is kept; longer ones are replaced
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Credentials presented on a request
+enum Credentials<'a> {
+    Bearer(&'a str),
+    Basic { username: String, password: String },
+}
+
 pub async fn auth_middleware(
     State(state): State<AppState>,
     mut request: Request,
     next: Next,
-) -> Result<Response, StatusCode> {
+) -> Response {
+    // Nested routers see a stripped path, so match against the original URI
+    let path = request
+        .extensions()
+        .get::<OriginalUri>()
+        .map(|uri| uri.path().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+
     // Skip auth for health checks and public endpoints
-    let path = request.uri().path();
     if path.starts_with("/health") || path.starts_with("/readyz") || path.starts_with("/metrics") {
-        return Ok(next.run(request).await);
+        return next.run(request).await;
     }
 
-    // Skip auth for registry version endpoint
-    if path == "/v2/" {
-        return Ok(next.run(request).await);
+    // The token endpoint authenticates its own callers
+    if path == "/v2/token" {
+        return next.run(request).await;
     }
 
-    // Extract authorization header
-    let auth_header = request
-        .headers()
-        .get(header::AUTHORIZATION)
-        .and_then(|h| h.to_str().ok());
-
-    let user = if let Some(auth_header) = auth_header {
-        if let Some(token) = auth_header.strip_prefix("Bearer ") {
-            // JWT token authentication
-            match state.auth.validate_token(token) {
-                Ok(Some(user)) => Some(user),
+    let required_scope = determine_required_scope(&path, request.method());
+
+    let credentials = match parse_credentials(request.headers()) {
+        Ok(credentials) => credentials,
+        Err(reason) => {
+            warn!("Rejected credentials for {}: {}", path, reason);
+            return unauthorized(&state, request.headers(), &required_scope, reason);
+        }
+    };
+
+    let mut registry_token = false;
+    let user = match credentials {
+        Some(Credentials::Bearer(token)) => match state.auth.validate_bearer(token).await {
+            Ok(Some(bearer)) => {
+                registry_token = bearer.registry_token;
+                bearer.user
+            }
+            Ok(None) => {
+                warn!("Invalid or expired token");
+                return unauthorized(&state, request.headers(), &required_scope, "invalid or expired token");
+            }
+            Err(e) => {
+                warn!("Token validation error: {}", e);
+                return unauthorized(&state, request.headers(), &required_scope, "invalid token");
+            }
+        },
+        // In token mode credentials are only exchanged at the token endpoint
+        Some(Credentials::Basic { .. }) if matches!(state.auth.mode(), AuthMode::Token) => {
+            debug!("Basic credentials sent to {} in token mode", path);
+            return unauthorized(&state, request.headers(), &required_scope, "bearer token required");
+        }
+        Some(Credentials::Basic { username, password }) => {
+            match state.auth.authenticate(&username, &password).await {
+                Ok(Some(user)) => user,
                 Ok(None) => {
-                    warn!("Invalid or expired token");
-                    return Err(StatusCode::UNAUTHORIZED);
+                    warn!("Invalid credentials for user: {}", username);
+                    return unauthorized(&state, request.headers(), &required_scope, "invalid credentials");
                 }
                 Err(e) => {
-                    warn!("Token validation error: {}", e);
-                    return Err(StatusCode::UNAUTHORIZED);
+                    warn!("Authentication error: {}", e);
+                    return unauthorized(&state, request.headers(), &required_scope, "authentication failed");
                 }
             }
-        } else if let Some(basic) = auth_header.strip_prefix("Basic ") {
-            // Basic authentication
-            match general_purpose::STANDARD.decode(basic) {
-                Ok(decoded) => {
-                    if let Ok(credentials) = String::from_utf8(decoded) {
-                        if let Some((username, password)) = credentials.split_once(':') {
-                            match state.auth.authenticate(username, password).await {
-                                Ok(Some(user)) => Some(user),
-                                Ok(None) => {
-                                    warn!("Invalid credentials for user: {}", username);
-                                    return Err(StatusCode::UNAUTHORIZED);
-                                }
-                                Err(e) => {
-                                    warn!("Authentication error: {}", e);
-                                    return Err(StatusCode::UNAUTHORIZED);
-                                }
-                            }
-                        } else {
-                            warn!("Invalid basic auth format");
-                            return Err(StatusCode::UNAUTHORIZED);
-                        }
-                    } else {
-                        warn!("Invalid basic auth encoding");
-                        return Err(StatusCode::UNAUTHORIZED);
-                    }
-                }
-                Err(_) => {
-                    warn!("Failed to decode basic auth");
-                    return Err(StatusCode::UNAUTHORIZED);
+        }
+        None => match client_certificate_user(&state, &request) {
+            Some(user) => user,
+            None => {
+                // Anonymous pulls are allowed when configured; everything else needs credentials
+                if state.config.registry.anonymous_pull && is_pull_request(&path, request.method()) {
+                    debug!("Anonymous pull for path: {}", path);
+                    request.extensions_mut().insert(User::anonymous());
+                    return next.run(request).await;
                 }
+
+                debug!("Missing authorization header for path: {}", path);
+                return unauthorized(&state, request.headers(), &required_scope, "authentication required");
             }
-        } else {
-            warn!("Unsupported authorization scheme");
-            return Err(StatusCode::UNAUTHORIZED);
+        },
+    };
+
+    // The version check only proves the credentials are valid
+    if path != "/v2/" && path != "/v2" && !state.auth.check_scope(&user, &required_scope) {
+        warn!("User {} lacks required scope: {}", user.username, required_scope);
+
+        // Token clients fetch a wider token when challenged, e.g. pull-only before a push
+        if matches!(state.auth.mode(), AuthMode::Token) {
+            return insufficient_scope(&state, request.headers(), &required_scope);
         }
-    } else {
-        // No authorization header
-        warn!("Missing authorization header for path: {}", path);
-        return Err(StatusCode::UNAUTHORIZED);
+
+        return RegistryError {
+            code: OciError::Denied,
+            message: format!("Requested access to the resource is denied: {}", required_scope),
+            detail: None,
+        }
+        .into_response();
+    }
+
+    // Registry tokens were filtered through RBAC when issued; check other credentials here
+    if !registry_token && !rbac_allows_repository(&state, &user, &required_scope, authz_context(&request)).await {
+        return RegistryError {
+            code: OciError::Denied,
+            message: format!("Requested access to the resource is denied: {}", required_scope),
+            detail: None,
+        }
+        .into_response();
+    }
+
+    debug!("Authenticated user: {} for path: {}", user.username, path);
+
+    // Robot usage is audited separately from human activity
+    if user.username.starts_with(ROBOT_PREFIX)
+        && let Some(audit) = state.audit.as_ref()
+    {
+        let event = AuditService::robot_event(
+            UserInfo::from_user(Some(&user)),
+            EventType::RobotAuthenticated,
+            user.username.clone(),
+            request.method().as_str(),
+            path.clone(),
+        );
+        if let Err(e) = audit.log(event).await {
+            warn!("Failed to record robot audit event: {}", e);
+        }
+    }
+
+    request.extensions_mut().insert(user);
+    if registry_token {
+        request.extensions_mut().insert(RegistryToken);
+    }
+
+    next.run(request).await
+}
+
+/// Admin endpoints need RBAC admin rights on the registry when RBAC is enabled, and
+/// the `registry:*` scope otherwise. Also guards the UI's session-authenticated routes,
+/// which don't pass through `auth_middleware`.
+pub async fn require_registry_admin(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    require_registry_action(&state, request, next, Action::Admin).await
+}
+
+/// Like `require_registry_admin`, for endpoints that start image optimization
+pub async fn require_registry_optimize(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    require_registry_action(&state, request, next, Action::Optimize).await
+}
+
+async fn require_registry_action(
+    state: &AppState,
+    request: Request,
+    next: Next,
+    action: Action,
+) -> Response {
+    let Some(user) = request.extensions().get::<User>() else {
+        return admin_forbidden();
+    };
+    // Without RBAC, only the full registry scope confers admin rights
+    let Some(rbac) = state.rbac.as_ref() else {
+        if state.auth.check_scope(user, "registry:*") {
+            return next.run(request).await;
+        }
+        warn!("Denied {:?} access to {}: missing registry:* scope", action, user.username);
+        return admin_forbidden();
     };
 
-    if let Some(user) = user {
-        // Check scope authorization for specific operations
-        let required_scope = determine_required_scope(path, request.method());
-        if !state.auth.check_scope(&user, &required_scope) {
-            warn!("User {} lacks required scope: {}", user.username, required_scope);
-            return Err(StatusCode::FORBIDDEN);
+    let authz = AuthzRequest {
+        user_id: user.username.clone(),
+        resource: ResourceType::Registry,
+        resource_id: "*".to_string(),
+        action: action.clone(),
+        context: authz_context(&request),
+    };
+    match rbac.authorize(authz).await {
+        Ok(response) if response.allowed => next.run(request).await,
+        Ok(response) => {
+            warn!("Denied {:?} access to {}: {}", action, user.username, response.reason);
+            admin_forbidden()
+        }
+        Err(e) => {
+            warn!("Denied {:?} access to {}: {}", action, user.username, e);
+            admin_forbidden()
         }
+    }
+}
 
-        debug!("Authenticated user: {} for path: {}", user.username, path);
-        request.extensions_mut().insert(user);
+/// Check a `repository:<name>:<action>` scope against RBAC, when enabled
+async fn rbac_allows_repository(
+    state: &AppState,
+    user: &User,
+    required_scope: &str,
+    context: HashMap<String, String>,
+) -> bool {
+    let Some(rbac) = state.rbac.as_ref() else {
+        return true;
+    };
+    // RBAC doesn't know robots or certificate identities; their scopes were already checked
+    if user.is_service_principal() {
+        return true;
     }
+    let Some((repository, action)) = required_scope
+        .strip_prefix("repository:")
+        .and_then(|rest| rest.rsplit_once(':'))
+    else {
+        return true;
+    };
+
+    let action = match action {
+        "pull" => Action::Pull,
+        "push" => Action::Push,
+        "delete" => Action::Delete,
+        _ => return false,
+    };
+    let authz = AuthzRequest {
+        user_id: user.username.clone(),
+        resource: ResourceType::Repository,
+        resource_id: repository.to_string(),
+        action,
+        context,
+    };
 
-    Ok(next.run(request).await)
+    match rbac.authorize(authz).await {
+        Ok(response) if response.allowed => true,
+        Ok(response) => {
+            warn!("RBAC denied {} for {}: {}", required_scope, user.username, response.reason);
+            false
+        }
+        Err(e) => {
+            warn!("RBAC denied {} for {}: {}", required_scope, user.username, e);
+            false
+        }
+    }
 }
 
-fn determine_required_scope(path: &str, method: &axum::http::Method) -> String {
-    use axum::http::Method;
+/// Whether `user` may pull from `repository`, checked the way `auth_middleware`
+/// checks a pull request: anonymous clients only when anonymous pulls are
+/// allowed, everyone else against their scopes and then RBAC
+pub(crate) async fn may_pull_repository(
+    state: &AppState,
+    user: &User,
+    registry_token: bool,
+    repository: &str,
+    peer: Option<SocketAddr>,
+) -> bool {
+    if user.is_anonymous() {
+        return state.config.registry.anonymous_pull;
+    }
+
+    let required_scope = format!("repository:{}:pull", repository);
+    if !state.auth.check_scope(user, &required_scope) {
+        return false;
+    }
+    // Registry tokens were filtered through RBAC when issued
+    registry_token || rbac_allows_repository(state, user, &required_scope, peer_context(peer)).await
+}
+
+/// Request context for RBAC conditions
+fn authz_context(request: &Request) -> HashMap<String, String> {
+    let peer = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| *addr);
+    peer_context(peer)
+}
+
+fn peer_context(peer: Option<SocketAddr>) -> HashMap<String, String> {
+    let mut context = HashMap::new();
+    if let Some(addr) = peer {
+        context.insert("ip".to_string(), addr.ip().to_string());
+    }
+    context
+}
+
+fn admin_forbidden() -> Response {
+    (
+        StatusCode::FORBIDDEN,
+        Json(json!({ "error": "Registry admin access required" })),
+    )
+        .into_response()
+}
+
+/// Throttle registry requests per user, or per client IP for anonymous requests.
+/// Runs inside `auth_middleware` so the authenticated user is available.
+pub async fn rate_limit_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request
+        .extensions()
+        .get::<OriginalUri>()
+        .map(|uri| uri.path().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+
+    let Some(class) = rate_limit_class(&path, request.method()) else {
+        return next.run(request).await;
+    };
+
+    // Anonymous callers share a username, so they are limited per client IP
+    let username = request
+        .extensions()
+        .get::<User>()
+        .filter(|user| !user.is_anonymous())
+        .map(|user| user.username.clone());
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+
+    if state.rate_limiter.is_exempt(username.as_deref(), ip) {
+        return next.run(request).await;
+    }
+
+    let key = match (&username, ip) {
+        (Some(username), _) => format!("user:{}", username),
+        (None, Some(ip)) => format!("ip:{}", ip),
+        (None, None) => "anonymous".to_string(),
+    };
 
+    if let Err(retry_after) = state.rate_limiter.check(&key, class) {
+        warn!("Rate limit exceeded for {} ({} operations)", key, class.as_str());
+        let mut response = RegistryError {
+            code: OciError::TooManyRequests,
+            message: format!("Too many {} requests; retry after {} seconds", class.as_str(), retry_after.as_secs()),
+            detail: None,
+        }
+        .into_response();
+        response.headers_mut().insert(
+            header::RETRY_AFTER,
+            retry_after.as_secs().max(1).to_string().parse().unwrap(),
+        );
+        return response;
+    }
+
+    next.run(request).await
+}
+
+/// Which rate limit a registry request counts against; None for unlimited endpoints
+fn rate_limit_class(path: &str, method: &Method) -> Option<RateLimitClass> {
+    if path == "/v2/" || path == "/v2" || path == "/v2/token" {
+        return None;
+    }
+    if path == "/v2/_catalog" || path.ends_with("/tags/list") {
+        return Some(RateLimitClass::List);
+    }
+
+    match *method {
+        Method::GET | Method::HEAD => Some(RateLimitClass::Pull),
+        _ => Some(RateLimitClass::Push),
+    }
+}
+
+/// Count requests and observe latency by matched route, so path parameters
+/// like repository names don't explode label cardinality
+pub async fn metrics_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let start = Instant::now();
+    let response = next.run(request).await;
+
+    state
+        .metrics
+        .http_request_duration_seconds
+        .with_label_values(&[&method, &route])
+        .observe(start.elapsed().as_secs_f64());
+    state
+        .metrics
+        .http_requests_total
+        .with_label_values(&[&method, &route, response.status().as_str()])
+        .inc();
+
+    response
+}
+
+/// Reuse the client's `X-Request-Id` or assign one, make it available to handlers
+/// and audit events, and echo it on the response
+pub async fn request_id_middleware(
+    State(trusted_proxies): State<Arc<[IpNet]>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let client_ip = peer.map(|peer| client_ip(peer, request.headers(), &trusted_proxies).to_string());
+
+    let context = RequestContext {
+        request_id: request_id.clone(),
+        client_ip,
+        user_agent: request
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+    };
+
+    // A generated id is valid header text, and a client-supplied one already was
+    let header_value = header::HeaderValue::from_str(&request_id).unwrap();
+    request.headers_mut().insert(REQUEST_ID_HEADER, header_value.clone());
+    request.extensions_mut().insert(context.clone());
+
+    let mut response = context.scope(next.run(request)).await;
+    response.headers_mut().insert(REQUEST_ID_HEADER, header_value);
+    response
+}
+
+/// The client behind a connection from `peer`. Each trusted proxy vouches for the
+/// `X-Forwarded-For` entry it appended, so entries are followed from the right for as
+/// long as they lead to trusted proxies; anything further left could be made up by the client.
+fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpNet]) -> IpAddr {
+    let forwarded: Vec<&str> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect();
+
+    let mut client = peer;
+    for entry in forwarded.iter().rev() {
+        if !trusted_proxies.iter().any(|network| network.contains(&client)) {
+            break;
+        }
+        match entry.trim().parse() {
+            Ok(ip) => client = ip,
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+/// Attach the user from a browser session cookie, if present and valid.
+/// Handlers decide whether a session is required.
+pub async fn session_middleware(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let token = request
+        .headers()
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, value)| value.to_string());
+
+    if let Some(token) = token {
+        match state.auth.validate_token(&token) {
+            Ok(Some(user)) => {
+                debug!("Session for user: {}", user.username);
+                request.extensions_mut().insert(user);
+            }
+            _ => debug!("Ignoring invalid or expired session cookie"),
+        }
+    }
+
+    next.run(request).await
+}
+
+/// The identity of a verified TLS client certificate, used when no credentials are sent
+fn client_certificate_user(state: &AppState, request: &Request) -> Option<User> {
+    let certificate = request
+        .extensions()
+        .get::<TlsConnection>()?
+        .client_certificate
+        .as_ref()?;
+    debug!("Authenticated client certificate {}", certificate.subject);
+    Some(state.auth.certificate_user(certificate))
+}
+
+/// Parse the Authorization header, returning None when it is absent
+fn parse_credentials(headers: &HeaderMap) -> Result<Option<Credentials<'_>>, &'static str> {
+    let Some(auth_header) = headers.get(header::AUTHORIZATION) else {
+        return Ok(None);
+    };
+    let auth_header = auth_header.to_str().map_err(|_| "invalid authorization header")?;
+
+    if let Some(token) = auth_header.strip_prefix("Bearer ") {
+        return Ok(Some(Credentials::Bearer(token)));
+    }
+
+    if let Some(basic) = auth_header.strip_prefix("Basic ") {
+        let decoded = general_purpose::STANDARD
+            .decode(basic)
+            .map_err(|_| "failed to decode basic auth")?;
+        let credentials = String::from_utf8(decoded).map_err(|_| "invalid basic auth encoding")?;
+        let (username, password) = credentials
+            .split_once(':')
+            .ok_or("invalid basic auth format")?;
+
+        return Ok(Some(Credentials::Basic {
+            username: username.to_string(),
+            password: password.to_string(),
+        }));
+    }
+
+    Err("unsupported authorization scheme")
+}
+
+/// 401 with a challenge pointing clients at the token endpoint
+fn unauthorized(state: &AppState, headers: &HeaderMap, scope: &str, reason: &str) -> Response {
+    let challenge = bearer_challenge(state, headers, scope);
+    challenge_response(challenge, format!("Authentication required: {}", reason))
+}
+
+/// A valid token without the scope for this request
+fn insufficient_scope(state: &AppState, headers: &HeaderMap, scope: &str) -> Response {
+    let mut challenge = bearer_challenge(state, headers, scope);
+    challenge.push_str(r#",error="insufficient_scope""#);
+    challenge_response(challenge, format!("Insufficient scope: {}", scope))
+}
+
+fn bearer_challenge(state: &AppState, headers: &HeaderMap, scope: &str) -> String {
+    let mut challenge = format!(r#"Bearer realm="{}/v2/token",service="drift""#, registry_base_url(state, headers));
+    if scope.starts_with("repository:") {
+        challenge.push_str(&format!(r#",scope="{}""#, scope));
+    }
+    challenge
+}
+
+/// URL clients reach the registry at: `server.external_url` when set, otherwise
+/// the request's `Host` with the scheme the API is served over
+fn registry_base_url(state: &AppState, headers: &HeaderMap) -> String {
+    if let Some(external_url) = &state.config.server.external_url {
+        return external_url.trim_end_matches('/').to_string();
+    }
+
+    let host = headers
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or(&state.config.server.bind_addr);
+    let scheme = if state.config.server.tls.is_some() { "https" } else { "http" };
+    format!("{}://{}", scheme, host)
+}
+
+fn challenge_response(challenge: String, message: String) -> Response {
+    let mut response = RegistryError {
+        code: OciError::Unauthorized,
+        message,
+        detail: None,
+    }
+    .into_response();
+
+    response.headers_mut().insert(
+        header::WWW_AUTHENTICATE,
+        challenge.parse().unwrap(),
+    );
+    *response.status_mut() = StatusCode::UNAUTHORIZED;
+
+    response
+}
+
+/// Read-only requests that anonymous pull access may serve
+fn is_pull_request(path: &str, method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD)
+        && (path == "/v2/" || path == "/v2" || REPOSITORY_PATH.is_match(path))
+}
+
+fn determine_required_scope(path: &str, method: &axum::http::Method) -> String {
     // Parse OCI registry paths
-    if let Some(captures) = regex::Regex::new(r"^/v2/([^/]+)/(manifests|blobs)/")
-        .unwrap()
-        .captures(path)
-    {
+    if let Some(captures) = REPOSITORY_PATH.captures(path) {
         let repo = captures.get(1).unwrap().as_str();
-        match method {
-            &Method::GET | &Method::HEAD => format!("repository:{}:pull", repo),
-            &Method::PUT | &Method::POST | &Method::PATCH => format!("repository:{}:push", repo),
-            &Method::DELETE => format!("repository:{}:delete", repo),
+        let is_upload = path.contains("/blobs/uploads/");
+        match *method {
+            _ if is_upload => format!("repository:{}:push", repo),
+            Method::GET | Method::HEAD => format!("repository:{}:pull", repo),
+            Method::PUT | Method::POST | Method::PATCH => format!("repository:{}:push", repo),
+            Method::DELETE => format!("repository:{}:delete", repo),
             _ => format!("repository:{}:pull", repo),
         }
-    } else if path.starts_with("/v2/") && path.contains("/blobs/uploads/") {
-        // Upload endpoints
-        if let Some(repo) = path.split('/').nth(2) {
-            format!("repository:{}:push", repo)
-        } else {
-            "repository:*:push".to_string()
-        }
     } else if path == "/v2/_catalog" {
         "registry:catalog:*".to_string()
     } else if path.starts_with("/v1/") {
         // Bolt API endpoints
-        match method {
-            &Method::GET => "bolt:read".to_string(),
-            &Method::POST | &Method::PUT => "bolt:write".to_string(),
-            &Method::DELETE => "bolt:delete".to_string(),
+        match *method {
+            Method::GET => "bolt:read".to_string(),
+            Method::POST | Method::PUT => "bolt:write".to_string(),
+            Method::DELETE => "bolt:delete".to_string(),
             _ => "bolt:read".to_string(),
         }
     } else {
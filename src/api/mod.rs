@@ -1,6 +1,14 @@
 pub mod admin;
 pub mod auth;
 pub mod bolt;
+pub mod jobs;
 pub mod middleware;
+pub mod notifications;
+pub mod optimization;
 pub mod quic;
-pub mod registry;
\ No newline at end of file
+pub mod registry;
+pub mod replication;
+pub mod repositories;
+pub mod robots;
+pub mod scanning;
+pub mod signing;
\ No newline at end of file
@@ -0,0 +1,18 @@
+use axum::{
+    extract::State,
+    response::{IntoResponse, Json},
+    routing::get,
+    Router,
+};
+use serde_json::json;
+
+use crate::server::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/status", get(get_notification_status))
+}
+
+/// Delivery counters for each webhook endpoint
+async fn get_notification_status(State(state): State<AppState>) -> impl IntoResponse {
+    Json(json!({ "endpoints": state.notifier.status() }))
+}
@@ -0,0 +1,98 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json},
+    routing::{get, post},
+    Extension, Router,
+};
+use serde_json::json;
+use tracing::info;
+
+use crate::auth::User;
+use crate::server::AppState;
+
+/// Kind recorded on the job started by `POST /api/v1/optimization/run`
+const OPTIMIZATION_RUN_JOB: &str = "optimization_run";
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/stats", get(get_optimization_stats))
+        .route("/progress", get(get_optimization_progress))
+        .route("/layers/:digest", get(get_layer))
+}
+
+/// `POST /run`, nested separately so it can require `Action::Optimize`
+pub fn run_router() -> Router<AppState> {
+    Router::new().route("/run", post(run_optimization))
+}
+
+/// Savings totals with the analysis of each optimized layer
+async fn get_optimization_stats(State(state): State<AppState>) -> impl IntoResponse {
+    let Some(optimization) = state.optimization.as_ref() else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "Image optimization is not enabled" })),
+        );
+    };
+
+    (StatusCode::OK, Json(json!(optimization.get_optimization_stats().await)))
+}
+
+/// Progress of the current or most recent background run
+async fn get_optimization_progress(State(state): State<AppState>) -> impl IntoResponse {
+    let Some(optimization) = state.optimization.as_ref() else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "Image optimization is not enabled" })),
+        );
+    };
+
+    (StatusCode::OK, Json(json!(optimization.progress().await)))
+}
+
+/// Index entry and latest optimization result of one layer
+async fn get_layer(State(state): State<AppState>, Path(digest): Path<String>) -> impl IntoResponse {
+    let Some(optimization) = state.optimization.as_ref() else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "Image optimization is not enabled" })),
+        );
+    };
+
+    let layer = optimization.get_layer(&digest).await;
+    let result = optimization.get_optimization_result(&digest).await;
+    if layer.is_none() && result.is_none() {
+        return (StatusCode::NOT_FOUND, Json(json!({ "error": format!("Layer {} not found", digest) })));
+    }
+
+    (StatusCode::OK, Json(json!({ "layer": layer, "result": result })))
+}
+
+/// Optimize every pending layer in the background; poll the returned job
+/// through `GET /api/v1/jobs/:id` and its progress through `GET /progress`
+async fn run_optimization(State(state): State<AppState>, user: Option<Extension<User>>) -> impl IntoResponse {
+    let Some(Extension(user)) = user else {
+        return (StatusCode::UNAUTHORIZED, Json(json!({ "error": "Authentication required" }))).into_response();
+    };
+    let Some(optimization) = state.optimization.clone() else {
+        return (StatusCode::NOT_FOUND, Json(json!({ "error": "Image optimization is not enabled" })))
+            .into_response();
+    };
+    if optimization.is_running() {
+        return (StatusCode::CONFLICT, Json(json!({ "error": "An optimization run is already in progress" })))
+            .into_response();
+    }
+
+    info!("User {} started an optimization run", user.username);
+    let policy = optimization.policy();
+    let job_id = state.jobs.spawn(OPTIMIZATION_RUN_JOB, "registry", async move {
+        optimization.run_background_optimization(&policy).await
+    });
+
+    (
+        StatusCode::ACCEPTED,
+        [(header::LOCATION, format!("/api/v1/jobs/{}", job_id))],
+        Json(json!({ "job_id": job_id })),
+    )
+        .into_response()
+}
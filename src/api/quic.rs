@@ -11,7 +11,7 @@ use serde_json::json;
 use std::net::SocketAddr;
 use tracing::{info, warn};
 
-use crate::quic::{QuicMessage, QuicTransport, QuicTransportBackend};
+use crate::quic::{QuicMessage, QuicTransport};
 use crate::server::AppState;
 
 /// QUIC registry API endpoints for managing QUIC connections and testing
@@ -43,6 +43,9 @@ pub struct QuicPingRequest {
 pub struct QuicTestRequest {
     pub target_addr: String,
     pub timeout_ms: Option<u64>,
+    /// Repository to request a blob from; manifest references name their own
+    #[serde(default)]
+    pub repository: Option<String>,
 }
 
 /// Get QUIC transport status
@@ -61,7 +64,9 @@ pub async fn get_quic_status(State(state): State<AppState>) -> impl IntoResponse
                 }
             };
 
-            let mut supported_features = vec!["ping".to_string(), "blob-transfer".to_string(), "manifest-transfer".to_string()];
+            #[allow(unused_mut)]
+            let mut supported_features =
+                vec!["ping".to_string(), "blob-transfer".to_string(), "manifest-transfer".to_string()];
 
             #[cfg(feature = "quinn-quic")]
             if config.backend == "quinn" {
@@ -211,7 +216,10 @@ pub async fn test_quic_blob_request(
         }
     };
 
-    let message = QuicMessage::BlobRequest { digest: digest.clone() };
+    let Some(repository) = test_req.repository.clone() else {
+        return (StatusCode::BAD_REQUEST, "Blob requests need a repository").into_response();
+    };
+    let message = QuicMessage::BlobRequest { repository, digest: digest.clone() };
 
     match quic.send_message(target_addr, message).await {
         Ok(QuicMessage::BlobResponse { digest: resp_digest, content, metadata }) => {
@@ -319,11 +327,13 @@ impl QuicRegistryIntegration {
     pub async fn upload_blob_quic(
         &self,
         target: SocketAddr,
+        repository: String,
         digest: String,
         content: Vec<u8>,
         metadata: crate::quic::BlobMetadata,
     ) -> Result<()> {
         let message = QuicMessage::BlobUpload {
+            repository,
             digest,
             content,
             metadata,
@@ -365,9 +375,10 @@ impl QuicRegistryIntegration {
     pub async fn download_blob_quic(
         &self,
         target: SocketAddr,
+        repository: String,
         digest: String,
     ) -> Result<Option<(Vec<u8>, crate::quic::BlobMetadata)>> {
-        let message = QuicMessage::BlobRequest { digest };
+        let message = QuicMessage::BlobRequest { repository, digest };
 
         match self.transport.send_message(target, message).await? {
             QuicMessage::BlobResponse { content: Some(content), metadata: Some(metadata), .. } => {
@@ -1,20 +1,32 @@
-use super::RegistryError;
+use super::{reject_proxy_push, validate_digest, OciError, RegistryError};
 use crate::server::AppState;
 use axum::{
+    body::Body,
     extract::{Path, State},
     http::{header, HeaderMap, StatusCode},
     response::IntoResponse,
 };
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 pub async fn get_blob(
     State(state): State<AppState>,
     Path((name, digest)): Path<(String, String)>,
 ) -> Result<impl IntoResponse, RegistryError> {
     info!("Getting blob: {}/{}", name, digest);
+    validate_digest(&digest)?;
 
-    match state.storage.get_blob(&digest).await {
-        Ok(Some(data)) => {
+    let mut blob = state.storage.get_blob_stream(&digest).await;
+    // Blobs of proxied repositories are fetched from upstream on first pull
+    if let (Ok(None), Some(proxy)) = (&blob, &state.proxy) {
+        blob = match proxy.pull_blob(&name, &digest).await {
+            Ok(true) => state.storage.get_blob_stream(&digest).await,
+            Ok(false) => Ok(None),
+            Err(e) => Err(e),
+        };
+    }
+
+    match blob {
+        Ok(Some((size, stream))) => {
             let mut headers = HeaderMap::new();
             headers.insert(
                 header::CONTENT_TYPE,
@@ -22,24 +34,25 @@ pub async fn get_blob(
             );
             headers.insert(
                 header::CONTENT_LENGTH,
-                data.len().to_string().parse().unwrap(),
+                size.to_string().parse().unwrap(),
             );
             headers.insert(
                 "Docker-Content-Digest",
                 digest.parse().unwrap(),
             );
 
-            Ok((headers, data))
+            state.metrics.blob_bytes_downloaded_total.inc_by(size);
+            Ok((headers, Body::from_stream(stream)))
         }
         Ok(None) => Err(RegistryError {
-            code: "BLOB_UNKNOWN".to_string(),
+            code: OciError::BlobUnknown,
             message: format!("Blob {} not found", digest),
             detail: None,
         }),
         Err(e) => {
             error!("Failed to get blob {}: {}", digest, e);
             Err(RegistryError {
-                code: "UNKNOWN".to_string(),
+                code: OciError::Unknown,
                 message: "Failed to retrieve blob".to_string(),
                 detail: None,
             })
@@ -52,56 +65,52 @@ pub async fn head_blob(
     Path((name, digest)): Path<(String, String)>,
 ) -> Result<impl IntoResponse, RegistryError> {
     debug!("Head blob: {}/{}", name, digest);
+    validate_digest(&digest)?;
 
-    match state.storage.blob_exists(&digest).await {
-        Ok(true) => {
-            // For head requests, we need to get the blob to return its size
-            match state.storage.get_blob(&digest).await {
-                Ok(Some(data)) => {
-                    let mut headers = HeaderMap::new();
-                    headers.insert(
-                        header::CONTENT_TYPE,
-                        "application/octet-stream".parse().unwrap(),
-                    );
-                    headers.insert(
-                        header::CONTENT_LENGTH,
-                        data.len().to_string().parse().unwrap(),
-                    );
-                    headers.insert(
-                        "Docker-Content-Digest",
-                        digest.parse().unwrap(),
-                    );
+    // Fetch a proxied blob on first sight so the metadata below can answer
+    if let Some(proxy) = state.proxy.as_ref().filter(|proxy| proxy.is_proxied(&name))
+        && let Ok(false) = state.storage.blob_exists(&digest).await
+        && let Err(e) = proxy.pull_blob(&name, &digest).await
+    {
+        warn!("Failed to fetch blob {} for {} from upstream: {}", digest, name, e);
+    }
 
-                    Ok((StatusCode::OK, headers))
-                }
-                Ok(None) => Err(RegistryError {
-                    code: "BLOB_UNKNOWN".to_string(),
-                    message: format!("Blob {} not found", digest),
-                    detail: None,
-                }),
-                Err(e) => {
-                    error!("Failed to get blob size {}: {}", digest, e);
-                    Err(RegistryError {
-                        code: "UNKNOWN".to_string(),
-                        message: "Failed to check blob".to_string(),
-                        detail: None,
-                    })
-                }
-            }
+    // Metadata alone answers a HEAD; a single HeadObject on S3, no content read
+    match state.storage.get_blob_metadata(&digest).await {
+        Ok(metadata) => {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                header::CONTENT_TYPE,
+                "application/octet-stream".parse().unwrap(),
+            );
+            headers.insert(
+                header::CONTENT_LENGTH,
+                metadata.size.to_string().parse().unwrap(),
+            );
+            headers.insert(header::ACCEPT_RANGES, "bytes".parse().unwrap());
+            headers.insert(
+                "Docker-Content-Digest",
+                digest.parse().unwrap(),
+            );
+
+            Ok((StatusCode::OK, headers))
         }
-        Ok(false) => Err(RegistryError {
-            code: "BLOB_UNKNOWN".to_string(),
-            message: format!("Blob {} not found", digest),
-            detail: None,
-        }),
-        Err(e) => {
-            error!("Failed to check blob {}: {}", digest, e);
-            Err(RegistryError {
-                code: "UNKNOWN".to_string(),
-                message: "Failed to check blob".to_string(),
+        // Backends report a missing blob as an error; tell that apart from a real failure
+        Err(e) => match state.storage.blob_exists(&digest).await {
+            Ok(false) => Err(RegistryError {
+                code: OciError::BlobUnknown,
+                message: format!("Blob {} not found", digest),
                 detail: None,
-            })
-        }
+            }),
+            _ => {
+                error!("Failed to check blob {}: {}", digest, e);
+                Err(RegistryError {
+                    code: OciError::Unknown,
+                    message: "Failed to check blob".to_string(),
+                    detail: None,
+                })
+            }
+        },
     }
 }
 
@@ -110,13 +119,34 @@ pub async fn delete_blob(
     Path((name, digest)): Path<(String, String)>,
 ) -> Result<impl IntoResponse, RegistryError> {
     info!("Deleting blob: {}/{}", name, digest);
+    reject_proxy_push(&state, &name)?;
+    validate_digest(&digest)?;
+
+    match state.storage.blob_exists(&digest).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return Err(RegistryError {
+                code: OciError::BlobUnknown,
+                message: format!("Blob {} not found", digest),
+                detail: None,
+            });
+        }
+        Err(e) => {
+            error!("Failed to check blob {}: {}", digest, e);
+            return Err(RegistryError {
+                code: OciError::Unknown,
+                message: "Failed to delete blob".to_string(),
+                detail: None,
+            });
+        }
+    }
 
     match state.storage.delete_blob(&digest).await {
         Ok(()) => Ok(StatusCode::ACCEPTED),
         Err(e) => {
             error!("Failed to delete blob {}: {}", digest, e);
             Err(RegistryError {
-                code: "UNKNOWN".to_string(),
+                code: OciError::Unknown,
                 message: "Failed to delete blob".to_string(),
                 detail: None,
             })
@@ -0,0 +1,185 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Docker image manifest (schema 2)
+pub const DOCKER_MANIFEST_V2: &str = "application/vnd.docker.distribution.manifest.v2+json";
+/// Docker manifest list (multi-arch)
+pub const DOCKER_MANIFEST_LIST: &str = "application/vnd.docker.distribution.manifest.list.v2+json";
+/// OCI image manifest
+pub const OCI_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+/// OCI image index (multi-arch)
+pub const OCI_INDEX: &str = "application/vnd.oci.image.index.v1+json";
+
+/// Content descriptor referencing a blob or another manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Descriptor {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    pub digest: String,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<HashMap<String, String>>,
+}
+
+/// Platform a per-architecture manifest was built for
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Platform {
+    pub architecture: String,
+    pub os: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+}
+
+impl Platform {
+    pub fn new(os: &str, architecture: &str) -> Self {
+        Self {
+            architecture: architecture.to_string(),
+            os: os.to_string(),
+            variant: None,
+        }
+    }
+
+    /// Whether a manifest built for `other` can run on this platform.
+    /// A missing variant on either side matches any variant.
+    pub fn matches(&self, other: &Platform) -> bool {
+        self.os == other.os
+            && self.architecture == other.architecture
+            && match (&self.variant, &other.variant) {
+                (Some(wanted), Some(actual)) => wanted == actual,
+                _ => true,
+            }
+    }
+}
+
+impl Default for Platform {
+    fn default() -> Self {
+        Self::new("linux", "amd64")
+    }
+}
+
+/// Single-platform image manifest (Docker v2 or OCI)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageManifest {
+    pub schema_version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    pub config: Descriptor,
+    #[serde(default)]
+    pub layers: Vec<Descriptor>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<Descriptor>,
+}
+
+/// Multi-platform image index (OCI index or Docker manifest list)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageIndex {
+    pub schema_version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    pub manifests: Vec<Descriptor>,
+}
+
+/// Parsed manifest document
+#[derive(Debug, Clone)]
+pub enum Manifest {
+    Image(Box<ImageManifest>),
+    Index(ImageIndex),
+}
+
+impl Manifest {
+    /// Parse a manifest, using the declared `mediaType` when present and
+    /// falling back to the document shape otherwise
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_slice(data)?;
+        let media_type = value.get("mediaType").and_then(|m| m.as_str());
+
+        let is_index = match media_type {
+            Some(media_type) => is_index_media_type(media_type),
+            None => value.get("manifests").is_some(),
+        };
+
+        if is_index {
+            Ok(Manifest::Index(serde_json::from_value(value)?))
+        } else {
+            Ok(Manifest::Image(serde_json::from_value(value)?))
+        }
+    }
+
+    /// Check the fields the spec requires beyond what parsing enforces
+    pub fn validate(&self) -> Result<()> {
+        let (schema_version, descriptors): (u32, Vec<&Descriptor>) = match self {
+            Manifest::Image(manifest) => (
+                manifest.schema_version,
+                std::iter::once(&manifest.config).chain(&manifest.layers).collect(),
+            ),
+            Manifest::Index(index) => (index.schema_version, index.manifests.iter().collect()),
+        };
+
+        if schema_version != 2 {
+            anyhow::bail!("unsupported schemaVersion {}", schema_version);
+        }
+        for descriptor in descriptors {
+            if !super::is_supported_digest(&descriptor.digest) {
+                anyhow::bail!("invalid descriptor digest {}", descriptor.digest);
+            }
+        }
+        Ok(())
+    }
+
+    /// Media type of the manifest, defaulting by document shape
+    pub fn media_type(&self) -> &str {
+        match self {
+            Manifest::Image(manifest) => manifest.media_type.as_deref().unwrap_or(DOCKER_MANIFEST_V2),
+            Manifest::Index(index) => index.media_type.as_deref().unwrap_or(OCI_INDEX),
+        }
+    }
+
+    pub fn is_index(&self) -> bool {
+        matches!(self, Manifest::Index(_))
+    }
+
+    /// Digests of the blobs (config and layers) referenced by an image manifest
+    pub fn blob_references(&self) -> Vec<&str> {
+        match self {
+            Manifest::Image(manifest) => std::iter::once(manifest.config.digest.as_str())
+                .chain(manifest.layers.iter().map(|layer| layer.digest.as_str()))
+                .collect(),
+            Manifest::Index(_) => vec![],
+        }
+    }
+
+    /// Digests of the child manifests referenced by an index
+    pub fn manifest_references(&self) -> Vec<&str> {
+        match self {
+            Manifest::Image(_) => vec![],
+            Manifest::Index(index) => index.manifests.iter().map(|m| m.digest.as_str()).collect(),
+        }
+    }
+}
+
+impl ImageIndex {
+    /// Select the child manifest built for the given platform
+    pub fn find_platform(&self, platform: &Platform) -> Option<&Descriptor> {
+        self.manifests.iter().find(|descriptor| {
+            descriptor
+                .platform
+                .as_ref()
+                .is_some_and(|candidate| platform.matches(candidate))
+        })
+    }
+}
+
+pub fn is_index_media_type(media_type: &str) -> bool {
+    media_type == OCI_INDEX || media_type == DOCKER_MANIFEST_LIST
+}
+
+pub fn is_manifest_media_type(media_type: &str) -> bool {
+    media_type == OCI_MANIFEST || media_type == DOCKER_MANIFEST_V2
+}
@@ -1,54 +1,66 @@
-use super::RegistryError;
+use super::manifest_types::{is_index_media_type, Manifest, Platform};
+use super::quota::check_manifest_quota;
+use super::{
+    is_valid_tag, manifest_unknown, reject_proxy_push, validate_digest, OciError, RegistryError,
+};
+use crate::audit::{AuditService, EventType, UserInfo};
+use crate::auth::User;
+use crate::notifications::Event;
 use crate::server::AppState;
+use crate::signing::{cosign_signature_tag, is_cosign_signature_manifest, is_cosign_signature_tag, CosignArtifactChange};
+use crate::storage::{is_digest_reference, StorageError};
+use crate::tag_history::TagAction;
 use axum::{
-    extract::{Path, Request, State},
+    extract::{ConnectInfo, Path, State},
     http::{header, HeaderMap, StatusCode},
-    response::{IntoResponse, Response},
+    response::IntoResponse,
     body::Body,
+    Extension,
 };
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
+use futures::{future::join_all, StreamExt};
 use sha2::{Digest, Sha256};
-use tracing::{debug, error, info};
+use std::collections::BTreeSet;
+use std::net::SocketAddr;
+use tracing::{debug, error, info, warn};
+
+/// Manifests are small JSON documents; anything larger is rejected while it's being received
+const MAX_MANIFEST_SIZE: usize = 4 * 1024 * 1024;
 
 pub async fn get_manifest(
     State(state): State<AppState>,
     Path((name, reference)): Path<(String, String)>,
+    request_headers: HeaderMap,
 ) -> Result<impl IntoResponse, RegistryError> {
     info!("Getting manifest: {}/{}", name, reference);
 
-    match state.storage.get_manifest(&name, &reference).await {
+    match fetch_manifest(&state, &name, &reference).await {
         Ok(Some(data)) => {
-            let mut headers = HeaderMap::new();
-
-            // Calculate content digest
-            let mut hasher = Sha256::new();
-            hasher.update(&data);
-            let digest = format!("sha256:{:x}", hasher.finalize());
-
-            headers.insert(
-                header::CONTENT_TYPE,
-                "application/vnd.docker.distribution.manifest.v2+json".parse().unwrap(),
-            );
-            headers.insert(
-                header::CONTENT_LENGTH,
-                data.len().to_string().parse().unwrap(),
-            );
-            headers.insert(
-                "Docker-Content-Digest",
-                digest.parse().unwrap(),
-            );
-
+            let data = resolve_for_client(&state, &name, data, &request_headers).await?;
+            enforce_signature_policy(&state, &name, &reference, &data).await?;
+            enforce_scan_policy(&state, &name, &data).await?;
+            let data = optimized_for_client(&state, &name, &reference, data, &request_headers).await;
+            let media_type = negotiate_media_type(&state, &name, &data, &request_headers).await?;
+            let headers = manifest_headers(&data, &media_type);
+            state.metrics.manifest_pulls_total.with_label_values(&[&name]).inc();
+            Ok((headers, data))
+        }
+        Ok(None) => {
+            let Some((data, source)) = rebuild_optimized_manifest(&state, &name, &reference).await else {
+                return Err(manifest_unknown(&name, &reference));
+            };
+            // Signatures and scans belong to the pushed manifest
+            enforce_signature_policy(&state, &name, &reference, &source).await?;
+            enforce_scan_policy(&state, &name, &source).await?;
+            let media_type = negotiate_media_type(&state, &name, &data, &request_headers).await?;
+            let headers = manifest_headers(&data, &media_type);
+            state.metrics.manifest_pulls_total.with_label_values(&[&name]).inc();
             Ok((headers, data))
         }
-        Ok(None) => Err(RegistryError {
-            code: "MANIFEST_UNKNOWN".to_string(),
-            message: format!("Manifest {}:{} not found", name, reference),
-            detail: None,
-        }),
         Err(e) => {
             error!("Failed to get manifest {}:{}: {}", name, reference, e);
             Err(RegistryError {
-                code: "UNKNOWN".to_string(),
+                code: OciError::Unknown,
                 message: "Failed to retrieve manifest".to_string(),
                 detail: None,
             })
@@ -59,9 +71,14 @@ pub async fn get_manifest(
 pub async fn put_manifest(
     State(state): State<AppState>,
     Path((name, reference)): Path<(String, String)>,
+    user: Option<Extension<User>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
     headers: HeaderMap,
-    body: Bytes,
+    body: Body,
 ) -> Result<impl IntoResponse, RegistryError> {
+    reject_proxy_push(&state, &name)?;
+    validate_reference(&reference)?;
+    let body = read_manifest_body(&headers, body).await?;
     info!("Putting manifest: {}/{} ({} bytes)", name, reference, body.len());
 
     // Validate content type
@@ -71,22 +88,128 @@ pub async fn put_manifest(
         .unwrap_or("");
 
     if !content_type.contains("application/vnd.docker.distribution.manifest")
-        && !content_type.contains("application/vnd.oci.image.manifest") {
+        && !content_type.contains("application/vnd.oci.image.manifest")
+        && !content_type.contains("application/vnd.oci.image.index")
+    {
         return Err(RegistryError {
-            code: "UNSUPPORTED".to_string(),
+            code: OciError::ManifestInvalid,
             message: "Unsupported manifest media type".to_string(),
             detail: None,
         });
     }
 
-    // Calculate digest
-    let mut hasher = Sha256::new();
-    hasher.update(&body);
-    let digest = format!("sha256:{:x}", hasher.finalize());
+    let manifest = Manifest::parse(&body)
+        .and_then(|manifest| manifest.validate().map(|()| manifest))
+        .map_err(|e| RegistryError {
+            code: OciError::ManifestInvalid,
+            message: format!("Invalid manifest: {}", e),
+            detail: None,
+        })?;
+
+    // Everything the manifest references must already have been pushed
+    let missing = missing_references(&state, &name, &manifest).await?;
+    if !missing.is_empty() {
+        return Err(RegistryError {
+            code: OciError::ManifestBlobUnknown,
+            message: format!("Manifest references {} unknown blobs or manifests", missing.len()),
+            detail: Some(serde_json::json!({ "digests": missing })),
+        });
+    }
+
+    // Calculate digest; a push by digest must match the content
+    let digest = manifest_digest(&body);
+    if is_digest_reference(&reference) && reference != digest {
+        return Err(RegistryError {
+            code: OciError::DigestInvalid,
+            message: format!("Manifest digest {} does not match {}", digest, reference),
+            detail: Some(serde_json::json!({ "expected": reference, "actual": digest })),
+        });
+    }
+
+    enforce_push_signature_policy(&state, &name, &reference, &body).await?;
+
+    // Count the manifest and any layers new to the organization against its storage quota
+    let quota_charge = check_manifest_quota(&state, &name, &manifest, &digest, body.len() as u64).await?;
+
+    // Record the pushed media type so pulls can negotiate against it
+    let media_type = content_type.split(';').next().unwrap_or(content_type).trim().to_string();
+
+    let size = body.len() as u64;
+
+    // Store manifest; GC must not sweep this repository's manifests meanwhile
+    let repository_lock = state.repository_locks.lock(&name).await;
+    let is_tag = !is_digest_reference(&reference);
+    let immutable = is_tag && is_immutable_tag(&state.runtime_config.load().registry.immutable_tags, &reference);
+    let previous_digest = if immutable {
+        // Checked under the repository lock, so concurrent pushes can't both see the tag missing
+        match state.storage.get_manifest(&name, &reference).await {
+            Ok(existing) => existing.map(|data| manifest_digest(&data)),
+            Err(e) => {
+                drop(repository_lock);
+                error!("Failed to read manifest {}:{}: {}", name, reference, e);
+                return Err(RegistryError {
+                    code: OciError::Unknown,
+                    message: "Failed to check immutable tag".to_string(),
+                    detail: None,
+                });
+            }
+        }
+    } else if is_tag {
+        tag_digest(&state, &name, &reference).await
+    } else {
+        None
+    };
+
+    // Immutable tags can be pushed once; re-pushing identical content is allowed
+    if immutable && previous_digest.as_ref().is_some_and(|previous| *previous != digest) {
+        drop(repository_lock);
+        let message = format!("Tag {} is immutable and already exists in {}", reference, name);
+        warn!("{}", message);
+        audit_manifest_decision(
+            &state, user.as_deref(), EventType::ManifestCreated, &name, &reference, "PUT", Some(message.clone()),
+        ).await;
+        return Err(RegistryError {
+            code: OciError::TagImmutable,
+            message,
+            detail: Some(serde_json::json!({ "tag": reference })),
+        });
+    }
+
+    let stored = state.storage.put_manifest(&name, &reference, body.clone(), &media_type).await;
+    if stored.is_ok() && is_tag {
+        record_tag_change(
+            &state, user.as_deref(), &name, &reference, TagAction::Push, previous_digest, Some(digest.clone()),
+        ).await;
+    }
+    drop(repository_lock);
 
-    // Store manifest
-    match state.storage.put_manifest(&name, &reference, body).await {
+    match stored {
         Ok(()) => {
+            state.holds.release(&manifest.blob_references()).await;
+            if let Some(charge) = quota_charge {
+                charge.record(&state).await;
+            }
+            if let Some(replication) = state.replication.as_ref() {
+                replication.enqueue(&name, &reference, &digest).await;
+            }
+            if let Some(scanning) = state.scanning.as_ref() {
+                scanning.manifest_pushed(&name, &digest, &media_type, size, &manifest);
+            }
+            if let Some(optimization) = state.optimization.as_ref()
+                && let Err(e) = optimization.retain_manifest_layers(&name, &digest, &body).await
+            {
+                warn!("Failed to record the layers of {}@{}: {}", name, digest, e);
+            }
+            // A pushed cosign signature may change whether the signed manifest can be pulled
+            let signed_digest = reference.strip_prefix("sha256-").and_then(|tag| tag.strip_suffix(".sig"));
+            if let (Some(signing), Some(signed_digest)) = (state.signing.as_ref(), signed_digest) {
+                signing.forget_verdict(&name, signed_digest).await;
+            }
+            state.notifier.emit(
+                Event::manifest_pushed(&name, &reference, &digest, &media_type, size)
+                    .with_request("PUT", &headers, connect_info.map(|ConnectInfo(addr)| addr))
+                    .with_actor(user.as_deref()),
+            );
             let mut response_headers = HeaderMap::new();
             response_headers.insert(
                 header::LOCATION,
@@ -97,12 +220,22 @@ pub async fn put_manifest(
                 digest.parse().unwrap(),
             );
 
+            state.metrics.manifest_pushes_total.with_label_values(&[&name]).inc();
             Ok((StatusCode::CREATED, response_headers))
         }
         Err(e) => {
+            if let Some(StorageError::QuotaExceeded { limit }) = e.downcast_ref::<StorageError>() {
+                warn!("Storage is full ({} bytes), rejecting manifest {}:{}", limit, name, reference);
+                return Err(RegistryError {
+                    code: OciError::Denied,
+                    message: "Registry storage is full".to_string(),
+                    detail: Some(serde_json::json!({ "limit": limit })),
+                });
+            }
+
             error!("Failed to store manifest {}:{}: {}", name, reference, e);
             Err(RegistryError {
-                code: "UNKNOWN".to_string(),
+                code: OciError::Unknown,
                 message: "Failed to store manifest".to_string(),
                 detail: None,
             })
@@ -113,42 +246,30 @@ pub async fn put_manifest(
 pub async fn head_manifest(
     State(state): State<AppState>,
     Path((name, reference)): Path<(String, String)>,
+    request_headers: HeaderMap,
 ) -> Result<impl IntoResponse, RegistryError> {
     debug!("Head manifest: {}/{}", name, reference);
 
-    match state.storage.get_manifest(&name, &reference).await {
+    match fetch_manifest(&state, &name, &reference).await {
         Ok(Some(data)) => {
-            let mut headers = HeaderMap::new();
-
-            // Calculate content digest
-            let mut hasher = Sha256::new();
-            hasher.update(&data);
-            let digest = format!("sha256:{:x}", hasher.finalize());
-
-            headers.insert(
-                header::CONTENT_TYPE,
-                "application/vnd.docker.distribution.manifest.v2+json".parse().unwrap(),
-            );
-            headers.insert(
-                header::CONTENT_LENGTH,
-                data.len().to_string().parse().unwrap(),
-            );
-            headers.insert(
-                "Docker-Content-Digest",
-                digest.parse().unwrap(),
-            );
-
+            let data = resolve_for_client(&state, &name, data, &request_headers).await?;
+            let data = optimized_for_client(&state, &name, &reference, data, &request_headers).await;
+            let media_type = negotiate_media_type(&state, &name, &data, &request_headers).await?;
+            let headers = manifest_headers(&data, &media_type);
+            Ok((StatusCode::OK, headers))
+        }
+        Ok(None) => {
+            let Some((data, _)) = rebuild_optimized_manifest(&state, &name, &reference).await else {
+                return Err(manifest_unknown(&name, &reference));
+            };
+            let media_type = negotiate_media_type(&state, &name, &data, &request_headers).await?;
+            let headers = manifest_headers(&data, &media_type);
             Ok((StatusCode::OK, headers))
         }
-        Ok(None) => Err(RegistryError {
-            code: "MANIFEST_UNKNOWN".to_string(),
-            message: format!("Manifest {}:{} not found", name, reference),
-            detail: None,
-        }),
         Err(e) => {
             error!("Failed to check manifest {}:{}: {}", name, reference, e);
             Err(RegistryError {
-                code: "UNKNOWN".to_string(),
+                code: OciError::Unknown,
                 message: "Failed to check manifest".to_string(),
                 detail: None,
             })
@@ -159,18 +280,805 @@ pub async fn head_manifest(
 pub async fn delete_manifest(
     State(state): State<AppState>,
     Path((name, reference)): Path<(String, String)>,
+    user: Option<Extension<User>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, RegistryError> {
     info!("Deleting manifest: {}/{}", name, reference);
+    reject_proxy_push(&state, &name)?;
+
+    if !is_digest_reference(&reference) && !state.config.registry.allow_tag_deletion {
+        return Err(RegistryError {
+            code: OciError::Unsupported,
+            message: "Deleting manifests by tag is not enabled; delete by digest instead".to_string(),
+            detail: None,
+        });
+    }
+
+    check_min_age(&state, &name, &reference, user.as_deref()).await?;
+
+    let repository_lock = state.repository_locks.lock(&name).await;
+    let result = delete_manifest_references(&state, user.as_deref(), &name, &reference).await;
+    drop(repository_lock);
 
-    match state.storage.delete_manifest(&name, &reference).await {
-        Ok(()) => Ok(StatusCode::ACCEPTED),
+    match result {
+        Ok(true) => {
+            audit_manifest_decision(
+                &state, user.as_deref(), EventType::ManifestDeleted, &name, &reference, "DELETE", None,
+            ).await;
+            state.notifier.emit(
+                Event::manifest_deleted(&name, &reference)
+                    .with_request("DELETE", &headers, connect_info.map(|ConnectInfo(addr)| addr))
+                    .with_actor(user.as_deref()),
+            );
+            Ok(StatusCode::ACCEPTED)
+        }
+        Ok(false) => Err(manifest_unknown(&name, &reference)),
         Err(e) => {
             error!("Failed to delete manifest {}:{}: {}", name, reference, e);
             Err(RegistryError {
-                code: "UNKNOWN".to_string(),
+                code: OciError::Unknown,
                 message: "Failed to delete manifest".to_string(),
                 detail: None,
             })
         }
     }
-}
\ No newline at end of file
+}
+
+/// Refuse to delete manifests younger than `min_age_days` unless the caller is an admin
+async fn check_min_age(
+    state: &AppState,
+    name: &str,
+    reference: &str,
+    user: Option<&User>,
+) -> Result<(), RegistryError> {
+    let min_age_days = state.config.registry.min_age_days;
+    if min_age_days == 0 {
+        return Ok(());
+    }
+
+    if user.is_some_and(|user| state.auth.check_scope(user, "registry:admin")) {
+        return Ok(());
+    }
+
+    // A manifest whose age can't be established is treated as too young
+    let unknown_age = |e: anyhow::Error| {
+        warn!("Refusing to delete {}:{}, its age is unknown: {}", name, reference, e);
+        RegistryError {
+            code: OciError::Denied,
+            message: format!("Could not determine the age of manifest {}:{}", name, reference),
+            detail: None,
+        }
+    };
+
+    let digest = if is_digest_reference(reference) {
+        reference.to_string()
+    } else {
+        match state.storage.get_manifest(name, reference).await {
+            Ok(Some(data)) => manifest_digest(&data),
+            // Nothing to protect; the delete reports the manifest unknown
+            Ok(None) => return Ok(()),
+            Err(e) => return Err(unknown_age(e)),
+        }
+    };
+
+    let created_at = match state.storage.get_manifest_metadata(name, &digest).await {
+        Ok(metadata) => metadata.created_at,
+        Err(e) => match fetch_stored_manifest(state, name, &digest).await {
+            Ok(None) => return Ok(()),
+            Ok(Some(_)) => return Err(unknown_age(e)),
+            Err(e) => return Err(unknown_age(e)),
+        },
+    };
+
+    if !is_old_enough(created_at, min_age_days, chrono::Utc::now()) {
+        let message = format!(
+            "Manifest {}@{} is younger than the minimum age of {} days",
+            name, digest, min_age_days
+        );
+        warn!("{}", message);
+        audit_manifest_decision(
+            state, user, EventType::ManifestDeleted, name, reference, "DELETE", Some(message.clone()),
+        ).await;
+        return Err(RegistryError {
+            code: OciError::Denied,
+            message,
+            detail: None,
+        });
+    }
+
+    Ok(())
+}
+
+/// A pushed reference must be a well-formed tag or a supported digest
+fn validate_reference(reference: &str) -> Result<(), RegistryError> {
+    if reference.contains(':') {
+        return validate_digest(reference);
+    }
+    if !is_valid_tag(reference) {
+        return Err(RegistryError {
+            code: OciError::TagInvalid,
+            message: format!("Invalid tag: {}", reference),
+            detail: Some(serde_json::json!({ "tag": reference })),
+        });
+    }
+    Ok(())
+}
+
+/// Whether something created at `created_at` is at least `min_age_days` old at `now`
+fn is_old_enough(
+    created_at: chrono::DateTime<chrono::Utc>,
+    min_age_days: u64,
+    now: chrono::DateTime<chrono::Utc>,
+) -> bool {
+    now - created_at >= chrono::Duration::days(min_age_days as i64)
+}
+
+/// Read a manifest body, failing as soon as it grows past `MAX_MANIFEST_SIZE`
+async fn read_manifest_body(headers: &HeaderMap, body: Body) -> Result<Bytes, RegistryError> {
+    let too_large = || RegistryError {
+        code: OciError::SizeInvalid,
+        message: format!("Manifest exceeds the maximum size of {} bytes", MAX_MANIFEST_SIZE),
+        detail: Some(serde_json::json!({ "limit": MAX_MANIFEST_SIZE })),
+    };
+
+    let declared = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if declared.is_some_and(|length| length > MAX_MANIFEST_SIZE) {
+        return Err(too_large());
+    }
+
+    let mut data = BytesMut::new();
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| RegistryError {
+            code: OciError::ManifestInvalid,
+            message: format!("Failed to read manifest: {}", e),
+            detail: None,
+        })?;
+        if data.len() + chunk.len() > MAX_MANIFEST_SIZE {
+            return Err(too_large());
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    Ok(data.freeze())
+}
+
+/// Whether a tag matches one of the configured immutable tag patterns
+pub fn is_immutable_tag(patterns: &[String], tag: &str) -> bool {
+    patterns.iter().any(|pattern| match globset::Glob::new(pattern) {
+        Ok(glob) => glob.compile_matcher().is_match(tag),
+        Err(e) => {
+            warn!("Invalid immutable tag pattern {}: {}", pattern, e);
+            pattern == tag
+        }
+    })
+}
+
+/// Record a manifest policy decision when auditing is enabled
+async fn audit_manifest_decision(
+    state: &AppState,
+    user: Option<&User>,
+    event_type: EventType,
+    name: &str,
+    reference: &str,
+    method: &str,
+    denied_reason: Option<String>,
+) {
+    let Some(audit) = state.audit.as_ref() else {
+        return;
+    };
+
+    let event = AuditService::manifest_policy_event(
+        UserInfo::from_user(user),
+        event_type,
+        name.to_string(),
+        reference.to_string(),
+        method,
+        denied_reason.is_none(),
+        denied_reason,
+    );
+
+    if let Err(e) = audit.log(event).await {
+        error!("Failed to record audit event for {}:{}: {}", name, reference, e);
+    }
+}
+
+/// Delete a manifest reference, and when deleting by digest every tag that
+/// resolves to it, returning false if the manifest doesn't exist
+async fn delete_manifest_references(
+    state: &AppState,
+    user: Option<&User>,
+    name: &str,
+    reference: &str,
+) -> anyhow::Result<bool> {
+    // Manifests pushed by tag are only stored under their tags
+    let Some(data) = fetch_stored_manifest(state, name, reference).await? else {
+        return Ok(false);
+    };
+
+    if is_digest_reference(reference) {
+        for tag in state.storage.list_tags_for_digest(name, reference).await? {
+            // A stale marker must not take a tag that now points elsewhere with it
+            match state.storage.get_manifest(name, &tag).await? {
+                Some(tagged) if manifest_digest(&tagged) == reference => {}
+                _ => continue,
+            }
+            debug!("Untagging {}:{} ({})", name, tag, reference);
+            state.storage.delete_manifest(name, &tag).await?;
+            record_tag_change(state, user, name, &tag, TagAction::Delete, Some(reference.to_string()), None).await;
+        }
+    }
+
+    if state.storage.get_manifest(name, reference).await?.is_some() {
+        state.storage.delete_manifest(name, reference).await?;
+        if !is_digest_reference(reference) {
+            let digest = manifest_digest(&data);
+            record_tag_change(state, user, name, reference, TagAction::Delete, Some(digest), None).await;
+        }
+    }
+
+    // Let the garbage collector reclaim deduplicated layers no image uses any more,
+    // once no tag or digest reference to the manifest is left
+    if let Some(optimization) = &state.optimization
+        && fetch_stored_manifest(state, name, &manifest_digest(&data)).await?.is_none()
+        && let Err(e) = optimization.release_manifest_layers(name, &manifest_digest(&data), &data).await
+    {
+        warn!("Failed to release deduplicated layers of {}:{}: {}", name, reference, e);
+    }
+
+    Ok(true)
+}
+
+/// Blobs of an image manifest, or child manifests of an index, that aren't stored.
+/// The lookups run concurrently, as a manifest may reference dozens of layers.
+async fn missing_references(state: &AppState, name: &str, manifest: &Manifest) -> Result<Vec<String>, RegistryError> {
+    let blobs: BTreeSet<&str> = manifest.blob_references().into_iter().collect();
+    let children: BTreeSet<&str> = manifest.manifest_references().into_iter().collect();
+
+    let blob_checks = join_all(blobs.into_iter().map(|digest| async move {
+        (digest, state.storage.blob_exists(digest).await)
+    }));
+    let child_checks = join_all(children.into_iter().map(|digest| async move {
+        (digest, fetch_manifest(state, name, digest).await.map(|child| child.is_some()))
+    }));
+    let (blob_checks, child_checks) = tokio::join!(blob_checks, child_checks);
+
+    let mut missing = Vec::new();
+    for (digest, exists) in blob_checks.into_iter().chain(child_checks) {
+        match exists {
+            Ok(true) => {}
+            Ok(false) => missing.push(digest.to_string()),
+            Err(e) => {
+                error!("Failed to check {} referenced by a manifest in {}: {}", digest, name, e);
+                return Err(RegistryError {
+                    code: OciError::Unknown,
+                    message: "Failed to validate manifest references".to_string(),
+                    detail: None,
+                });
+            }
+        }
+    }
+
+    Ok(missing)
+}
+
+/// Change the Cosign signature artifact of `content_digest` in `name` the way pushing or
+/// deleting its `sha256-<digest>.sig` tag would: under the repository lock, from reading the
+/// artifact until it's stored, and with the same tag history, replication and notifications
+pub async fn update_cosign_artifact(
+    state: &AppState,
+    user: Option<&User>,
+    name: &str,
+    content_digest: &str,
+    change: impl AsyncFnOnce(Option<&[u8]>) -> anyhow::Result<CosignArtifactChange>,
+) -> anyhow::Result<()> {
+    let tag = cosign_signature_tag(content_digest);
+    let repository_lock = state.repository_locks.lock(name).await;
+    let artifact = state.storage.get_manifest(name, &tag).await?;
+    let change = change(artifact.as_deref()).await?;
+    // Pulls decided with the old set of signatures are decided again
+    if !matches!(change, CosignArtifactChange::Unchanged)
+        && let Some(signing) = state.signing.as_ref()
+    {
+        signing.forget_verdict(name, content_digest).await;
+    }
+    let body = match change {
+        CosignArtifactChange::Unchanged => return Ok(()),
+        CosignArtifactChange::Write(body) => body,
+        CosignArtifactChange::Delete => {
+            delete_manifest_references(state, user, name, &tag).await?;
+            drop(repository_lock);
+            state.notifier.emit(Event::manifest_deleted(name, &tag).with_actor(user));
+            return Ok(());
+        }
+    };
+
+    let digest = manifest_digest(&body);
+    let media_type = manifest_media_type(&body);
+    let previous_digest = artifact.as_deref().map(manifest_digest);
+    state.storage.put_manifest(name, &tag, body.clone(), &media_type).await?;
+    record_tag_change(state, user, name, &tag, TagAction::Push, previous_digest, Some(digest.clone())).await;
+    drop(repository_lock);
+
+    if let Some(replication) = state.replication.as_ref() {
+        replication.enqueue(name, &tag, &digest).await;
+    }
+    if let Some(optimization) = state.optimization.as_ref()
+        && let Err(e) = optimization.retain_manifest_layers(name, &digest, &body).await
+    {
+        warn!("Failed to record the layers of {}@{}: {}", name, digest, e);
+    }
+    state.notifier.emit(
+        Event::manifest_pushed(name, &tag, &digest, &media_type, body.len() as u64).with_actor(user),
+    );
+    info!("Stored Cosign signature artifact {}:{}", name, tag);
+    Ok(())
+}
+
+/// Digest of the manifest a tag points at, if the tag exists
+pub async fn tag_digest(state: &AppState, name: &str, tag: &str) -> Option<String> {
+    match state.storage.get_manifest(name, tag).await {
+        Ok(Some(data)) => Some(manifest_digest(&data)),
+        _ => None,
+    }
+}
+
+/// Add a tag change to the tag's history. The change itself has gone through,
+/// so a failure to record it is only logged.
+pub async fn record_tag_change(
+    state: &AppState,
+    user: Option<&User>,
+    name: &str,
+    tag: &str,
+    action: TagAction,
+    old_digest: Option<String>,
+    new_digest: Option<String>,
+) {
+    let username = user.map(|user| user.username.as_str());
+    if let Err(e) = state.tag_history.record(name, tag, action, old_digest, new_digest, username).await {
+        warn!("Failed to record history of {}:{}: {}", name, tag, e);
+    }
+}
+
+/// Fetch a manifest by tag or digest, going upstream for proxied repositories
+/// when it isn't cached or the cached tag is due for re-validation
+async fn fetch_manifest(state: &AppState, name: &str, reference: &str) -> anyhow::Result<Option<Bytes>> {
+    let stored = fetch_stored_manifest(state, name, reference).await?;
+    match &state.proxy {
+        Some(proxy) => proxy.pull_manifest(name, reference, stored).await,
+        None => Ok(stored),
+    }
+}
+
+/// Fetch a stored manifest by tag or digest. Manifests pushed by tag are only stored
+/// under the tag, so digest lookups fall back to a tag that points at the digest.
+pub async fn fetch_stored_manifest(state: &AppState, name: &str, reference: &str) -> anyhow::Result<Option<Bytes>> {
+    if let Some(data) = state.storage.get_manifest(name, reference).await? {
+        return Ok(Some(data));
+    }
+
+    if !is_digest_reference(reference) {
+        return Ok(None);
+    }
+
+    for tag in state.storage.list_tags_for_digest(name, reference).await? {
+        if let Some(data) = state.storage.get_manifest(name, &tag).await? {
+            // The tag may have moved since the index was read
+            if manifest_digest(&data) == reference {
+                return Ok(Some(data));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Resolve an index to a single-platform manifest for clients that can't handle indexes
+async fn resolve_for_client(
+    state: &AppState,
+    name: &str,
+    data: Bytes,
+    request_headers: &HeaderMap,
+) -> Result<Bytes, RegistryError> {
+    if client_accepts_index(request_headers) {
+        return Ok(data);
+    }
+
+    let index = match Manifest::parse(&data) {
+        Ok(Manifest::Index(index)) => index,
+        _ => return Ok(data),
+    };
+
+    let platform = Platform::default();
+    let descriptor = index.find_platform(&platform).ok_or_else(|| RegistryError {
+        code: OciError::ManifestUnknown,
+        message: format!(
+            "No manifest for platform {}/{} in index",
+            platform.os, platform.architecture
+        ),
+        detail: None,
+    })?;
+
+    debug!("Resolved index for {} to platform manifest {}", name, descriptor.digest);
+
+    match fetch_manifest(state, name, &descriptor.digest).await {
+        Ok(Some(child)) => Ok(child),
+        Ok(None) => Err(RegistryError {
+            code: OciError::ManifestUnknown,
+            message: format!("Manifest {}@{} not found", name, descriptor.digest),
+            detail: None,
+        }),
+        Err(e) => {
+            error!("Failed to get manifest {}@{}: {}", name, descriptor.digest, e);
+            Err(RegistryError {
+                code: OciError::Unknown,
+                message: "Failed to retrieve manifest".to_string(),
+                detail: None,
+            })
+        }
+    }
+}
+
+/// Swap in optimized layer variants for clients that list the variants' layer
+/// media types in Accept; everyone else gets the pushed manifest byte for byte.
+/// Only tag pulls are rewritten, since a pull by digest must return exactly that
+/// digest. The rewritten manifest isn't stored; only the manifest it was made
+/// from is recorded, so `rebuild_optimized_manifest` can serve it by digest.
+async fn optimized_for_client(
+    state: &AppState,
+    name: &str,
+    reference: &str,
+    data: Bytes,
+    request_headers: &HeaderMap,
+) -> Bytes {
+    let Some(optimization) = state.optimization.as_ref() else {
+        return data;
+    };
+    if is_digest_reference(reference) {
+        return data;
+    }
+    let accepted_layer_types: Vec<String> = accepted_media_types(request_headers)
+        .into_iter()
+        .filter(|media_type| media_type.starts_with(OCI_LAYER_MEDIA_TYPE))
+        .collect();
+    if accepted_layer_types.is_empty() {
+        return data;
+    }
+
+    let optimized = match optimization.optimized_manifest(&data, &accepted_layer_types).await {
+        Ok(Some(optimized)) => Bytes::from(optimized),
+        Ok(None) => return data,
+        Err(e) => {
+            debug!("Serving original manifest {}:{}: {}", name, reference, e);
+            return data;
+        }
+    };
+
+    let digest = manifest_digest(&optimized);
+    let source_digest = manifest_digest(&data);
+    match optimization.record_optimized_manifest(name, &digest, &source_digest, &accepted_layer_types).await {
+        Ok(()) => optimized,
+        Err(e) => {
+            warn!("Failed to record optimized manifest {}@{}: {}", name, digest, e);
+            data
+        }
+    }
+}
+
+/// Rebuild an optimized manifest served earlier from the pushed manifest it was
+/// made from, returning both. Gives None once that manifest is gone or its
+/// layers' variants have changed, as the rebuilt digest would no longer match.
+async fn rebuild_optimized_manifest(state: &AppState, name: &str, reference: &str) -> Option<(Bytes, Bytes)> {
+    let optimization = state.optimization.as_ref()?;
+    if !is_digest_reference(reference) {
+        return None;
+    }
+    let optimized = optimization.optimized_manifest_source(name, reference).await?;
+    let source = match fetch_stored_manifest(state, name, &optimized.source_digest).await {
+        Ok(source) => source?,
+        Err(e) => {
+            warn!("Failed to fetch {}@{} to rebuild {}: {}", name, optimized.source_digest, reference, e);
+            return None;
+        }
+    };
+    let data = match optimization.optimized_manifest(&source, &optimized.media_types).await {
+        Ok(data) => Bytes::from(data?),
+        Err(e) => {
+            debug!("Failed to rebuild optimized manifest {}@{}: {}", name, reference, e);
+            return None;
+        }
+    };
+    (manifest_digest(&data) == reference).then_some((data, source))
+}
+
+/// Refuse to serve manifests that don't satisfy the configured signature policy
+async fn enforce_signature_policy(
+    state: &AppState,
+    name: &str,
+    reference: &str,
+    data: &[u8],
+) -> Result<(), RegistryError> {
+    let Some(signing) = state.signing.as_ref() else {
+        return Ok(());
+    };
+
+    // Cosign has to be able to pull the signatures themselves
+    if is_cosign_signature_artifact(state, name, reference, data).await {
+        return Ok(());
+    }
+
+    let digest = manifest_digest(data);
+    match signing.check_pull(name, data).await {
+        Ok(result) if result.allowed => {
+            debug!("Manifest {}@{} satisfies signature policy ({} valid)", name, digest, result.valid_signatures);
+            Ok(())
+        }
+        Ok(result) => {
+            info!("Denying pull of {}@{}: signature policy not satisfied", name, digest);
+            Err(RegistryError {
+                code: OciError::Denied,
+                message: format!("Manifest {}@{} does not satisfy the signature policy", name, digest),
+                detail: Some(serde_json::json!({ "errors": result.errors })),
+            })
+        }
+        Err(e) => {
+            error!("Failed to evaluate signature policy for {}@{}: {}", name, digest, e);
+            Err(RegistryError {
+                code: OciError::Unknown,
+                message: "Failed to verify manifest signatures".to_string(),
+                detail: None,
+            })
+        }
+    }
+}
+
+/// Refuse pushes of manifests past their grace period without enough valid signatures,
+/// when `signing.enforcement.enforce_on_push` is set
+async fn enforce_push_signature_policy(
+    state: &AppState,
+    name: &str,
+    reference: &str,
+    data: &[u8],
+) -> Result<(), RegistryError> {
+    let Some(signing) = state.signing.as_ref() else {
+        return Ok(());
+    };
+    if is_cosign_signature_tag(reference) && is_cosign_signature_manifest(data) {
+        return Ok(());
+    }
+
+    let digest = manifest_digest(data);
+    match signing.check_push(name, data).await {
+        Ok(result) if result.allowed => Ok(()),
+        Ok(result) => {
+            info!("Denying push of {}:{} ({}): signature policy not satisfied", name, reference, digest);
+            Err(RegistryError {
+                code: OciError::Denied,
+                message: format!(
+                    "Manifest {}@{} is past its grace period and does not satisfy the signature policy",
+                    name, digest
+                ),
+                detail: Some(serde_json::json!({ "errors": result.errors })),
+            })
+        }
+        Err(e) => {
+            error!("Failed to evaluate signature policy for {}@{}: {}", name, digest, e);
+            Err(RegistryError {
+                code: OciError::Unknown,
+                message: "Failed to verify manifest signatures".to_string(),
+                detail: None,
+            })
+        }
+    }
+}
+
+/// Whether a manifest is a Cosign signature artifact stored under the tag cosign looks it up by.
+/// The shape alone isn't enough, as anyone can push a manifest of simplesigning layers.
+async fn is_cosign_signature_artifact(state: &AppState, name: &str, reference: &str, data: &[u8]) -> bool {
+    if !is_cosign_signature_manifest(data) {
+        return false;
+    }
+    if !is_digest_reference(reference) {
+        return is_cosign_signature_tag(reference);
+    }
+
+    // Cosign resolves the tag and may then fetch the artifact by its digest
+    match state.storage.list_tags_for_digest(name, reference).await {
+        Ok(tags) => tags.iter().any(|tag| is_cosign_signature_tag(tag)),
+        Err(e) => {
+            warn!("Failed to list the tags of {}@{}: {}", name, reference, e);
+            false
+        }
+    }
+}
+
+/// Deny pulls of images whose vulnerability scan found anything at or above the
+/// configured severity. Images that haven't been scanned, or whose scan failed, can be pulled.
+async fn enforce_scan_policy(state: &AppState, name: &str, data: &[u8]) -> Result<(), RegistryError> {
+    let Some(scanning) = state.scanning.as_ref() else {
+        return Ok(());
+    };
+    let Some(threshold) = scanning.block_threshold(name) else {
+        return Ok(());
+    };
+
+    let digest = manifest_digest(data);
+    match scanning.check_pull(name, &digest).await {
+        Ok(None) => Ok(()),
+        Ok(Some(summary)) => {
+            info!("Denying pull of {}@{}: vulnerabilities at or above {:?}", name, digest, threshold);
+            Err(RegistryError {
+                code: OciError::Denied,
+                message: format!(
+                    "Manifest {}@{} has {} vulnerabilities at or above the {:?} severity threshold",
+                    name, digest, summary.at_or_above(threshold), threshold
+                ),
+                detail: Some(serde_json::json!({ "threshold": threshold, "summary": summary })),
+            })
+        }
+        Err(e) => {
+            error!("Failed to evaluate scan policy for {}@{}: {}", name, digest, e);
+            Err(RegistryError {
+                code: OciError::Unknown,
+                message: "Failed to check vulnerability scan".to_string(),
+                detail: None,
+            })
+        }
+    }
+}
+
+/// Look up the media type recorded at push time and check the client accepts it.
+/// Clients that accept none of the stored types get 406 Not Acceptable.
+async fn negotiate_media_type(
+    state: &AppState,
+    name: &str,
+    data: &[u8],
+    request_headers: &HeaderMap,
+) -> Result<String, RegistryError> {
+    let digest = manifest_digest(data);
+    let media_type = match state.storage.get_manifest_metadata(name, &digest).await {
+        Ok(metadata) => metadata.media_type,
+        Err(e) => {
+            debug!("No metadata for manifest {}@{}: {}", name, digest, e);
+            None
+        }
+    }
+    .unwrap_or_else(|| manifest_media_type(data));
+
+    if !client_accepts(request_headers, &media_type) {
+        return Err(RegistryError {
+            code: OciError::NotAcceptable,
+            message: format!("Manifest {}@{} is not available as an accepted media type", name, digest),
+            detail: Some(serde_json::json!({ "mediaType": media_type })),
+        });
+    }
+
+    Ok(media_type)
+}
+
+/// Media types listed in the request's Accept headers, without parameters
+fn accepted_media_types(headers: &HeaderMap) -> Vec<String> {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|media_type| media_type.split(';').next().unwrap_or("").trim().to_string())
+        .filter(|media_type| !media_type.is_empty())
+        .collect()
+}
+
+/// Clients that send no Accept header get the stored document as-is
+fn client_accepts_index(headers: &HeaderMap) -> bool {
+    let accepted = accepted_media_types(headers);
+    accepted.is_empty()
+        || accepted
+            .iter()
+            .any(|media_type| media_type == "*/*" || is_index_media_type(media_type))
+}
+
+/// Whether the Accept headers allow the given media type, including wildcards
+fn client_accepts(headers: &HeaderMap, media_type: &str) -> bool {
+    let accepted = accepted_media_types(headers);
+    let media_range = media_type.split('/').next().unwrap_or("");
+
+    accepted.is_empty()
+        || accepted.iter().any(|accepted| {
+            accepted == "*/*"
+                || accepted == media_type
+                || accepted.strip_suffix("/*") == Some(media_range)
+        })
+}
+
+/// Media type prefix of OCI layers; clients opt into optimized variants by
+/// accepting one of these
+const OCI_LAYER_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar";
+
+/// Default media type for manifests that don't declare one
+const DEFAULT_MANIFEST_MEDIA_TYPE: &str = "application/vnd.docker.distribution.manifest.v2+json";
+
+/// Compute the content digest of a manifest exactly as it is served
+pub fn manifest_digest(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    format!("sha256:{:x}", hasher.finalize())
+}
+
+/// Read the media type declared inside the stored manifest document
+pub fn manifest_media_type(data: &[u8]) -> String {
+    serde_json::from_slice::<serde_json::Value>(data)
+        .ok()
+        .and_then(|manifest| {
+            manifest
+                .get("mediaType")
+                .and_then(|m| m.as_str())
+                .map(|m| m.to_string())
+        })
+        .unwrap_or_else(|| DEFAULT_MANIFEST_MEDIA_TYPE.to_string())
+}
+
+/// Build the headers shared by manifest GET and HEAD responses
+fn manifest_headers(data: &[u8], media_type: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    headers.insert(
+        header::CONTENT_TYPE,
+        media_type
+            .parse()
+            .unwrap_or_else(|_| DEFAULT_MANIFEST_MEDIA_TYPE.parse().unwrap()),
+    );
+    headers.insert(
+        header::CONTENT_LENGTH,
+        data.len().to_string().parse().unwrap(),
+    );
+    headers.insert(
+        "Docker-Content-Digest",
+        manifest_digest(data).parse().unwrap(),
+    );
+
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone, Utc};
+
+    #[test]
+    fn immutable_tag_patterns_match_globs_and_exact_names() {
+        let patterns = vec!["release".to_string(), "release-*".to_string(), "v?.0".to_string()];
+
+        assert!(is_immutable_tag(&patterns, "release"));
+        assert!(is_immutable_tag(&patterns, "release-1.2"));
+        assert!(is_immutable_tag(&patterns, "v1.0"));
+        assert!(!is_immutable_tag(&patterns, "release1"));
+        assert!(!is_immutable_tag(&patterns, "prerelease-1"));
+        assert!(!is_immutable_tag(&patterns, "v10.0"));
+        assert!(!is_immutable_tag(&[], "release"));
+    }
+
+    #[test]
+    fn invalid_immutable_tag_patterns_only_match_themselves() {
+        let patterns = vec!["release-[".to_string()];
+
+        assert!(is_immutable_tag(&patterns, "release-["));
+        assert!(!is_immutable_tag(&patterns, "release-1"));
+    }
+
+    #[test]
+    fn manifests_are_old_enough_from_exactly_min_age_days() {
+        let created_at = Utc.with_ymd_and_hms(2026, 1, 1, 12, 0, 0).unwrap();
+        let boundary = created_at + Duration::days(7);
+
+        assert!(!is_old_enough(created_at, 7, boundary - Duration::seconds(1)));
+        assert!(is_old_enough(created_at, 7, boundary));
+        assert!(is_old_enough(created_at, 7, boundary + Duration::seconds(1)));
+        // No minimum age, or a creation time in the future
+        assert!(is_old_enough(created_at, 0, created_at));
+        assert!(!is_old_enough(created_at, 0, created_at - Duration::seconds(1)));
+    }
+}
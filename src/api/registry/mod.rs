@@ -1,21 +1,24 @@
 use axum::{
-    extract::{Path, Query, Request, State},
-    http::{header, HeaderMap, StatusCode},
+    extract::{OriginalUri, Path, Query, Request, State},
+    http::{header, uri::PathAndQuery, HeaderMap, StatusCode, Uri},
     response::{IntoResponse, Response},
-    routing::{delete, get, head, patch, post, put},
+    routing::{delete, get, patch, post},
     Json, Router,
 };
-use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::collections::HashMap;
-use tracing::{error, info};
-use uuid::Uuid;
+use std::sync::LazyLock;
+use tracing::error;
 
 use crate::server::AppState;
 
 pub mod blobs;
+pub mod manifest_types;
 pub mod manifests;
+pub mod quota;
+pub mod signatures;
+pub mod token;
 pub mod uploads;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -29,18 +32,216 @@ pub struct TagList {
     pub tags: Vec<String>,
 }
 
+/// An entry of the `errors` array in registry error responses
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RegistryError {
-    pub code: String,
+    pub code: OciError,
     pub message: String,
     pub detail: Option<serde_json::Value>,
 }
 
+/// Error codes defined by the OCI distribution spec, plus the few drift's own
+/// extensions need. The code decides the response status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OciError {
+    BlobUnknown,
+    BlobUploadInvalid,
+    BlobUploadUnknown,
+    DigestInvalid,
+    ManifestBlobUnknown,
+    ManifestInvalid,
+    ManifestUnknown,
+    NameInvalid,
+    NameUnknown,
+    SizeInvalid,
+    Unauthorized,
+    Denied,
+    Unsupported,
+    #[serde(rename = "TOOMANYREQUESTS")]
+    TooManyRequests,
+    /// A chunk that doesn't start where the upload left off
+    RangeInvalid,
+    TagInvalid,
+    SignatureUnknown,
+    /// None of the client's accepted media types match the stored manifest
+    NotAcceptable,
+    /// A push to an immutable tag that already exists. Reported to clients
+    /// as DENIED, but with 409 Conflict rather than 403.
+    #[serde(rename(serialize = "DENIED"))]
+    TagImmutable,
+    Unknown,
+}
+
+impl OciError {
+    pub fn status(self) -> StatusCode {
+        match self {
+            OciError::BlobUnknown
+            | OciError::BlobUploadUnknown
+            | OciError::ManifestUnknown
+            | OciError::NameUnknown
+            | OciError::SignatureUnknown => StatusCode::NOT_FOUND,
+            OciError::BlobUploadInvalid
+            | OciError::DigestInvalid
+            | OciError::ManifestBlobUnknown
+            | OciError::ManifestInvalid
+            | OciError::NameInvalid
+            | OciError::TagInvalid => StatusCode::BAD_REQUEST,
+            OciError::SizeInvalid => StatusCode::PAYLOAD_TOO_LARGE,
+            OciError::RangeInvalid => StatusCode::RANGE_NOT_SATISFIABLE,
+            OciError::Unauthorized => StatusCode::UNAUTHORIZED,
+            OciError::Denied => StatusCode::FORBIDDEN,
+            OciError::Unsupported => StatusCode::METHOD_NOT_ALLOWED,
+            OciError::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            OciError::NotAcceptable => StatusCode::NOT_ACCEPTABLE,
+            OciError::TagImmutable => StatusCode::CONFLICT,
+            OciError::Unknown => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The spec's description of the code, for errors without a more specific message
+    pub fn description(self) -> &'static str {
+        match self {
+            OciError::BlobUnknown => "blob unknown to registry",
+            OciError::BlobUploadInvalid => "blob upload invalid",
+            OciError::BlobUploadUnknown => "blob upload unknown to registry",
+            OciError::DigestInvalid => "provided digest did not match uploaded content",
+            OciError::ManifestBlobUnknown => "manifest references a manifest or blob unknown to registry",
+            OciError::ManifestInvalid => "manifest invalid",
+            OciError::ManifestUnknown => "manifest unknown to registry",
+            OciError::NameInvalid => "invalid repository name",
+            OciError::NameUnknown => "repository name not known to registry",
+            OciError::SizeInvalid => "provided length did not match content length",
+            OciError::Unauthorized => "authentication required",
+            OciError::Denied => "requested access to the resource is denied",
+            OciError::Unsupported => "the operation is unsupported",
+            OciError::TooManyRequests => "too many requests",
+            OciError::RangeInvalid => "invalid content range",
+            OciError::TagInvalid => "invalid tag",
+            OciError::SignatureUnknown => "signature unknown to registry",
+            OciError::NotAcceptable => "no accepted media type is available",
+            OciError::TagImmutable => "tag is immutable",
+            OciError::Unknown => "unknown error",
+        }
+    }
+}
+
+impl From<OciError> for RegistryError {
+    fn from(code: OciError) -> Self {
+        RegistryError {
+            code,
+            message: code.description().to_string(),
+            detail: None,
+        }
+    }
+}
+
+impl IntoResponse for OciError {
+    fn into_response(self) -> Response {
+        RegistryError::from(self).into_response()
+    }
+}
+
+/// Repository names as defined by the OCI distribution spec, e.g. `team/app/frontend`
+static REPOSITORY_NAME: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(r"^[a-z0-9]+(?:[._-][a-z0-9]+)*(?:/[a-z0-9]+(?:[._-][a-z0-9]+)*)*$").unwrap()
+});
+
+/// A repository-scoped registry path, capturing the repository name
+static REPOSITORY_ROUTE: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(r"^/v2/(.+?)/(manifests|blobs|tags|signatures)/").unwrap()
+});
+
+/// Tags as defined by the OCI distribution spec
+static TAG: LazyLock<regex::Regex> = LazyLock::new(|| regex::Regex::new(r"^[a-zA-Z0-9_][a-zA-Z0-9._-]{0,127}$").unwrap());
+
+pub fn is_valid_repository_name(name: &str) -> bool {
+    REPOSITORY_NAME.is_match(name)
+}
+
+pub fn is_valid_tag(tag: &str) -> bool {
+    TAG.is_match(tag)
+}
+
+/// Only sha256 digests are supported for content addressing
+pub fn is_supported_digest(digest: &str) -> bool {
+    match digest.strip_prefix("sha256:") {
+        Some(hex) => hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase()),
+        None => false,
+    }
+}
+
+/// Reject malformed digests up front, rather than letting storage report them as missing
+pub fn validate_digest(digest: &str) -> Result<(), RegistryError> {
+    if is_supported_digest(digest) {
+        return Ok(());
+    }
+    Err(RegistryError {
+        code: OciError::DigestInvalid,
+        message: format!("Unsupported or malformed digest: {}", digest),
+        detail: Some(json!({ "digest": digest })),
+    })
+}
+
+/// Whether a repository holds any tags or manifests
+async fn repository_exists(state: &AppState, name: &str) -> anyhow::Result<bool> {
+    let (tags, manifests) = tokio::try_join!(state.storage.list_tags(name), state.storage.list_manifests(name))?;
+    Ok(!tags.is_empty() || !manifests.is_empty())
+}
+
+/// MANIFEST_UNKNOWN for a missing manifest. Only the requested reference is looked
+/// up, so a missing repository is reported the same way.
+pub fn manifest_unknown(name: &str, reference: &str) -> RegistryError {
+    RegistryError {
+        code: OciError::ManifestUnknown,
+        message: format!("Manifest {}:{} not found", name, reference),
+        detail: Some(json!({ "name": name, "reference": reference })),
+    }
+}
+
+/// Route parameters can't span slashes, so before routing, percent-encode the
+/// slashes of a nested repository name into a single segment; `Path` decodes
+/// them again. Wraps the whole router, as middleware on it runs after routing.
+pub async fn encode_repository_path(mut request: Request) -> Result<Request, RegistryError> {
+    let path = request.uri().path();
+    let Some(name) = REPOSITORY_ROUTE.captures(path).and_then(|captures| captures.get(1)) else {
+        return Ok(request);
+    };
+
+    if !is_valid_repository_name(name.as_str()) {
+        return Err(RegistryError {
+            code: OciError::NameInvalid,
+            message: format!("Invalid repository name: {}", name.as_str()),
+            detail: None,
+        });
+    }
+    if !name.as_str().contains('/') {
+        return Ok(request);
+    }
+
+    let mut encoded = format!("/v2/{}{}", name.as_str().replace('/', "%2F"), &path[name.end()..]);
+    if let Some(query) = request.uri().query() {
+        encoded = format!("{}?{}", encoded, query);
+    }
+
+    // Authorization and logging see the path the client sent
+    let original = request.uri().clone();
+    let mut parts = original.clone().into_parts();
+    parts.path_and_query = Some(PathAndQuery::try_from(encoded).expect("re-encoded a valid path"));
+    *request.uri_mut() = Uri::from_parts(parts).expect("only the path changed");
+    request.extensions_mut().insert(OriginalUri(original));
+
+    Ok(request)
+}
+
 pub fn router() -> Router<AppState> {
     Router::new()
         // Registry API version check
         .route("/", get(api_version))
 
+        // Token authentication
+        .route("/token", get(token::get_token))
+
         // Repository catalog
         .route("/_catalog", get(list_repositories))
 
@@ -73,6 +274,13 @@ pub fn router() -> Router<AppState> {
 
         // Tag listing
         .route("/:name/tags/list", get(list_tags))
+
+        // Signature management
+        .route("/:name/signatures/:digest", get(signatures::list_signatures))
+        .route(
+            "/:name/signatures/:digest/:sig_id",
+            delete(signatures::delete_signature),
+        )
 }
 
 pub async fn api_version() -> impl IntoResponse {
@@ -96,21 +304,17 @@ pub async fn list_repositories(
     let last = params.get("last");
 
     match state.storage.list_repositories().await {
-        Ok(mut repos) => {
-            // Apply pagination
-            if let Some(last_repo) = last {
-                if let Some(pos) = repos.iter().position(|r| r > last_repo) {
-                    repos = repos.into_iter().skip(pos).collect();
-                }
-            }
-
-            repos.truncate(n);
+        Ok(repos) => {
+            let (repos, next) = paginate(repos, n, last);
 
             let mut headers = HeaderMap::new();
             headers.insert(
                 header::CONTENT_TYPE,
                 "application/json".parse().unwrap(),
             );
+            if let Some(next) = next {
+                headers.insert(header::LINK, next_link("/v2/_catalog", n, &next).parse().unwrap());
+            }
 
             let response = RepositoryList { repositories: repos };
             Ok((headers, Json(response)))
@@ -118,7 +322,7 @@ pub async fn list_repositories(
         Err(e) => {
             error!("Failed to list repositories: {}", e);
             Err(RegistryError {
-                code: "UNKNOWN".to_string(),
+                code: OciError::Unknown,
                 message: "Failed to list repositories".to_string(),
                 detail: None,
             })
@@ -139,21 +343,25 @@ pub async fn list_tags(
     let last = params.get("last");
 
     match state.storage.list_tags(&name).await {
-        Ok(mut tags) => {
-            // Apply pagination
-            if let Some(last_tag) = last {
-                if let Some(pos) = tags.iter().position(|t| t > last_tag) {
-                    tags = tags.into_iter().skip(pos).collect();
-                }
-            }
-
-            tags.truncate(n);
+        Ok(tags) if tags.is_empty() && !repository_exists(&state, &name).await.unwrap_or(true) => {
+            Err(RegistryError {
+                code: OciError::NameUnknown,
+                message: format!("Repository {} not found", name),
+                detail: Some(json!({ "name": name })),
+            })
+        }
+        Ok(tags) => {
+            let (tags, next) = paginate(tags, n, last);
 
             let mut headers = HeaderMap::new();
             headers.insert(
                 header::CONTENT_TYPE,
                 "application/json".parse().unwrap(),
             );
+            if let Some(next) = next {
+                let route = format!("/v2/{}/tags/list", name);
+                headers.insert(header::LINK, next_link(&route, n, &next).parse().unwrap());
+            }
 
             let response = TagList { name, tags };
             Ok((headers, Json(response)))
@@ -161,25 +369,50 @@ pub async fn list_tags(
         Err(e) => {
             error!("Failed to list tags for repository {}: {}", name, e);
             Err(RegistryError {
-                code: "NAME_UNKNOWN".to_string(),
-                message: format!("Repository {} not found", name),
+                code: OciError::Unknown,
+                message: "Failed to list tags".to_string(),
                 detail: None,
             })
         }
     }
 }
 
+/// Proxied namespaces mirror an upstream registry and can't be pushed to
+pub fn reject_proxy_push(state: &AppState, name: &str) -> Result<(), RegistryError> {
+    match &state.proxy {
+        Some(proxy) if proxy.is_proxied(name) => Err(RegistryError {
+            code: OciError::Unsupported,
+            message: format!("Repository {} is a read-only mirror of an upstream registry", name),
+            detail: None,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Sort, skip past `last` and truncate to `n`, returning the cursor for the next page if any
+fn paginate(mut items: Vec<String>, n: usize, last: Option<&String>) -> (Vec<String>, Option<String>) {
+    items.sort();
+
+    let mut page: Vec<String> = match last {
+        Some(last) => items.into_iter().filter(|item| item > last).collect(),
+        None => items,
+    };
+
+    let truncated = page.len() > n;
+    page.truncate(n);
+
+    let next = if truncated { page.last().cloned() } else { None };
+    (page, next)
+}
+
+/// RFC 5988 Link header pointing at the next page of a listing
+fn next_link(route: &str, n: usize, last: &str) -> String {
+    format!("<{}?n={}&last={}>; rel=\"next\"", route, n, urlencoding::encode(last))
+}
+
 impl IntoResponse for RegistryError {
     fn into_response(self) -> Response {
-        let status = match self.code.as_str() {
-            "NAME_UNKNOWN" => StatusCode::NOT_FOUND,
-            "MANIFEST_UNKNOWN" => StatusCode::NOT_FOUND,
-            "BLOB_UNKNOWN" => StatusCode::NOT_FOUND,
-            "UNAUTHORIZED" => StatusCode::UNAUTHORIZED,
-            "DENIED" => StatusCode::FORBIDDEN,
-            "UNSUPPORTED" => StatusCode::BAD_REQUEST,
-            _ => StatusCode::INTERNAL_SERVER_ERROR,
-        };
+        let status = self.code.status();
 
         let body = json!({
             "errors": [self]
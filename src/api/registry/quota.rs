@@ -0,0 +1,181 @@
+use super::manifest_types::Manifest;
+use super::manifests::fetch_stored_manifest;
+use super::{OciError, RegistryError};
+use crate::rbac::{Organization, OrganizationUsage};
+use crate::server::AppState;
+use anyhow::Result;
+use serde_json::json;
+use std::collections::HashSet;
+use tracing::{debug, error, warn};
+
+const BYTES_PER_GB: u64 = 1024 * 1024 * 1024;
+
+/// Content a manifest push adds to its organization's usage, recorded once the push succeeds
+pub struct QuotaCharge {
+    organization_id: String,
+    blobs: Vec<(String, u64)>,
+    bytes: u64,
+}
+
+impl QuotaCharge {
+    pub async fn record(self, state: &AppState) {
+        if let Some(rbac) = state.rbac.as_ref() {
+            rbac.record_organization_usage(&self.organization_id, &self.blobs, self.bytes).await;
+        }
+    }
+}
+
+/// Reject a blob upload that would take the repository's organization over its storage quota
+pub async fn check_upload_quota(state: &AppState, repository: &str, digest: &str, size: u64) -> Result<(), RegistryError> {
+    let Some((organization, quota_gb, usage)) = quota_usage(state, repository).await? else {
+        return Ok(());
+    };
+
+    let requested = if usage.blobs.contains(digest) { 0 } else { size };
+    enforce(&organization, quota_gb, &usage, requested)
+}
+
+/// Reject a manifest push that would take the repository's organization over its
+/// storage quota. Layers the organization already stores, and a manifest the
+/// repository already has (e.g. pushed again under another tag), are not counted again.
+pub async fn check_manifest_quota(
+    state: &AppState,
+    repository: &str,
+    manifest: &Manifest,
+    digest: &str,
+    manifest_size: u64,
+) -> Result<Option<QuotaCharge>, RegistryError> {
+    let Some((organization, quota_gb, usage)) = quota_usage(state, repository).await? else {
+        return Ok(None);
+    };
+
+    let manifest_size = match fetch_stored_manifest(state, repository, digest).await {
+        Ok(Some(_)) => 0,
+        _ => manifest_size,
+    };
+
+    let mut blobs = Vec::new();
+    for digest in manifest.blob_references() {
+        if usage.blobs.contains(digest) || blobs.iter().any(|(counted, _)| counted == digest) {
+            continue;
+        }
+        match state.storage.get_blob_metadata(digest).await {
+            Ok(metadata) => blobs.push((digest.to_string(), metadata.size)),
+            Err(e) => debug!("Not counting blob {} against quota: {}", digest, e),
+        }
+    }
+
+    let requested = manifest_size + blobs.iter().map(|(_, size)| size).sum::<u64>();
+    enforce(&organization, quota_gb, &usage, requested)?;
+
+    Ok(Some(QuotaCharge {
+        organization_id: organization.id,
+        blobs,
+        bytes: manifest_size,
+    }))
+}
+
+/// Cached usage of an organization, measured from storage when missing
+pub async fn organization_usage(state: &AppState, organization: &Organization) -> Result<OrganizationUsage> {
+    let rbac = state.rbac.as_ref().ok_or_else(|| anyhow::anyhow!("RBAC is not enabled"))?;
+    if let Some(usage) = rbac.get_organization_usage(&organization.id).await {
+        return Ok(usage);
+    }
+
+    let usage = measure_usage(state, organization).await?;
+    rbac.set_organization_usage(usage.clone()).await;
+    Ok(rbac.get_organization_usage(&organization.id).await.unwrap_or(usage))
+}
+
+/// The repository's organization, its quota in GB and its current usage, when it has a quota
+async fn quota_usage(
+    state: &AppState,
+    repository: &str,
+) -> Result<Option<(Organization, u64, OrganizationUsage)>, RegistryError> {
+    let Some(rbac) = state.rbac.as_ref() else {
+        return Ok(None);
+    };
+    let Some(organization) = rbac.get_repository_organization(repository).await else {
+        return Ok(None);
+    };
+    let Some(quota_gb) = organization.settings.storage_quota_gb else {
+        return Ok(None);
+    };
+
+    match organization_usage(state, &organization).await {
+        Ok(usage) => Ok(Some((organization, quota_gb, usage))),
+        Err(e) => {
+            error!("Failed to measure storage usage of organization {}: {}", organization.id, e);
+            Err(RegistryError {
+                code: OciError::Unknown,
+                message: "Failed to check storage quota".to_string(),
+                detail: None,
+            })
+        }
+    }
+}
+
+fn enforce(organization: &Organization, quota_gb: u64, usage: &OrganizationUsage, requested: u64) -> Result<(), RegistryError> {
+    let quota_bytes = quota_gb * BYTES_PER_GB;
+    if usage.used_bytes + requested <= quota_bytes {
+        return Ok(());
+    }
+
+    warn!(
+        "Organization {} would exceed its {} GB storage quota ({} bytes used, {} requested)",
+        organization.id, quota_gb, usage.used_bytes, requested
+    );
+    Err(RegistryError {
+        code: OciError::Denied,
+        message: format!("Organization {} storage quota of {} GB exceeded", organization.name, quota_gb),
+        detail: Some(json!({
+            "organization": organization.id,
+            "used_bytes": usage.used_bytes,
+            "requested_bytes": requested,
+            "quota_bytes": quota_bytes,
+        })),
+    })
+}
+
+/// Sum the manifests and referenced blobs of an organization's repositories,
+/// counting blobs shared between repositories once
+async fn measure_usage(state: &AppState, organization: &Organization) -> Result<OrganizationUsage> {
+    let mut used_bytes = 0;
+    let mut blobs = HashSet::new();
+
+    for repository in &organization.repositories {
+        // Tags and digest references to the same manifest list the same digest
+        let digests: HashSet<String> = state.storage.list_manifests(repository).await?.into_iter().collect();
+
+        for digest in digests {
+            let data = state.storage.get_manifest_by_digest(repository, &digest).await?;
+            used_bytes += data.len() as u64;
+
+            let manifest = match Manifest::parse(&data) {
+                Ok(manifest) => manifest,
+                Err(e) => {
+                    debug!("Skipping unparseable manifest {}@{}: {}", repository, digest, e);
+                    continue;
+                }
+            };
+            for blob in manifest.blob_references() {
+                if !blobs.insert(blob.to_string()) {
+                    continue;
+                }
+                match state.storage.get_blob_metadata(blob).await {
+                    Ok(metadata) => used_bytes += metadata.size,
+                    Err(e) => debug!("Blob {} referenced by {}@{} not counted: {}", blob, repository, digest, e),
+                }
+            }
+        }
+    }
+
+    debug!("Organization {} uses {} bytes", organization.id, used_bytes);
+    Ok(OrganizationUsage {
+        organization_id: organization.id.clone(),
+        used_bytes,
+        quota_bytes: None,
+        blobs,
+        measured_at: chrono::Utc::now(),
+    })
+}
@@ -0,0 +1,62 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use tracing::{error, info};
+
+use super::{OciError, RegistryError};
+use crate::api::signing::sync_cosign_artifact;
+use crate::auth::User;
+use crate::server::AppState;
+
+/// Every signature of a manifest, verified against the repository's policy.
+/// Unlike the listing of the management API, pull access is enough to read it.
+pub async fn list_signatures(
+    State(state): State<AppState>,
+    Path((name, digest)): Path<(String, String)>,
+) -> Response {
+    crate::api::signing::list_signatures(&state, &name, &digest).await
+}
+
+pub async fn delete_signature(
+    State(state): State<AppState>,
+    Path((name, digest, sig_id)): Path<(String, String, String)>,
+    user: Option<Extension<User>>,
+) -> Result<impl IntoResponse, RegistryError> {
+    info!("Deleting signature {} for {}@{}", sig_id, name, digest);
+
+    let signing = state.signing.as_ref().ok_or_else(|| RegistryError {
+        code: OciError::Unsupported,
+        message: "Content signing is not enabled".to_string(),
+        detail: None,
+    })?;
+
+    // Signatures are keyed by the bare hex digest
+    let content_digest = digest.strip_prefix("sha256:").unwrap_or(&digest);
+
+    let deleted = match signing.delete_signature(&name, content_digest, &sig_id).await {
+        Ok(Some(signature)) => sync_cosign_artifact(&state, user.as_deref(), signing, &signature, false).await,
+        Ok(None) => {
+            return Err(RegistryError {
+                code: OciError::SignatureUnknown,
+                message: format!("Signature {} not found for {}@{}", sig_id, name, digest),
+                detail: None,
+            });
+        }
+        Err(e) => Err(e),
+    };
+
+    match deleted {
+        Ok(()) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => {
+            error!("Failed to delete signature {} for {}@{}: {}", sig_id, name, digest, e);
+            Err(RegistryError {
+                code: OciError::Unknown,
+                message: "Failed to delete signature".to_string(),
+                detail: None,
+            })
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use axum::{
+    extract::{ConnectInfo, RawQuery, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use tracing::{debug, error, info, warn};
+
+use super::{is_valid_repository_name, OciError, RegistryError};
+use crate::auth::User;
+use crate::rbac::{Action, AuthzRequest, ResourceType};
+use crate::server::AppState;
+use crate::tls::TlsConnection;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub token: String,
+    pub access_token: String,
+    pub expires_in: u64,
+    pub issued_at: String,
+}
+
+/// A scope requested by the client, e.g. `repository:library/alpine:pull,push`
+#[derive(Debug)]
+struct RequestedScope {
+    resource_type: String,
+    name: String,
+    actions: Vec<String>,
+}
+
+impl RequestedScope {
+    fn parse(scope: &str) -> Option<Self> {
+        let (resource_type, rest) = scope.split_once(':')?;
+        let (name, actions) = rest.rsplit_once(':')?;
+        if resource_type.is_empty() || name.is_empty() {
+            return None;
+        }
+        // Names are taken literally: a glob would be granted as a wildcard scope
+        if name.contains('*') || (resource_type == "repository" && !is_valid_repository_name(name)) {
+            return None;
+        }
+
+        Some(Self {
+            resource_type: resource_type.to_string(),
+            name: name.to_string(),
+            actions: actions
+                .split(',')
+                .map(str::trim)
+                .filter(|action| !action.is_empty())
+                .map(str::to_string)
+                .collect(),
+        })
+    }
+}
+
+/// Docker token endpoint: exchange credentials for a scoped bearer token
+pub async fn get_token(
+    State(state): State<AppState>,
+    RawQuery(query): RawQuery,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    tls: Option<Extension<TlsConnection>>,
+    headers: HeaderMap,
+) -> Result<Response, RegistryError> {
+    let client_ip = connect_info.map(|ConnectInfo(addr)| addr.ip().to_string());
+    let params = parse_query(query.as_deref().unwrap_or(""));
+    let service = params.get("service").and_then(|values| values.first()).cloned();
+    let requested: Vec<RequestedScope> = params
+        .get("scope")
+        .into_iter()
+        .flatten()
+        .flat_map(|value| value.split(' '))
+        .filter_map(|scope| {
+            let parsed = RequestedScope::parse(scope);
+            if parsed.is_none() {
+                debug!("Ignoring malformed scope: {}", scope);
+            }
+            parsed
+        })
+        .collect();
+
+    let user = match basic_credentials(&headers)? {
+        Some((username, password)) => match state.auth.authenticate(&username, &password).await {
+            Ok(Some(user)) => user,
+            Ok(None) => {
+                warn!("Invalid credentials for token request by user: {}", username);
+                return Err(token_unauthorized("invalid credentials"));
+            }
+            Err(e) => {
+                error!("Authentication error for token request: {}", e);
+                return Err(token_unauthorized("authentication failed"));
+            }
+        },
+        // A verified TLS client certificate stands in for credentials
+        None => match tls.as_ref().and_then(|Extension(tls)| tls.client_certificate.as_ref()) {
+            Some(certificate) => state.auth.certificate_user(certificate),
+            None if state.config.registry.anonymous_pull => User::anonymous(),
+            None => return Err(token_unauthorized("authentication required")),
+        },
+    };
+
+    // Only grant what the user actually holds; denied actions are dropped, not rejected
+    let mut granted = Vec::new();
+    for scope in &requested {
+        for action in &scope.actions {
+            let candidate = format!("{}:{}:{}", scope.resource_type, scope.name, action);
+            if state.auth.check_scope(&user, &candidate)
+                && rbac_allows(&state, &user, client_ip.as_deref(), scope, action).await
+            {
+                granted.push(candidate);
+            } else {
+                debug!("Not granting {} to {}", candidate, user.username);
+            }
+        }
+    }
+
+    let token_user = User {
+        username: user.username.clone(),
+        roles: user.roles.clone(),
+        scopes: granted,
+    };
+
+    let expires_in = state.config.auth.token_expiry_hours * 60 * 60;
+    let token = match state.auth.generate_registry_token(&token_user, expires_in) {
+        Ok(token) => token,
+        Err(e) => {
+            error!("Failed to generate registry token: {}", e);
+            return Err(RegistryError {
+                code: OciError::Unknown,
+                message: "Failed to issue token".to_string(),
+                detail: None,
+            });
+        }
+    };
+
+    info!(
+        "Issued token for {} (service: {}, scopes: {:?})",
+        token_user.username,
+        service.as_deref().unwrap_or("-"),
+        token_user.scopes
+    );
+
+    Ok(Json(TokenResponse {
+        access_token: token.clone(),
+        token,
+        expires_in,
+        issued_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+    })
+    .into_response())
+}
+
+/// Decode query parameters, keeping every value of repeated keys
+fn parse_query(query: &str) -> HashMap<String, Vec<String>> {
+    let mut params: HashMap<String, Vec<String>> = HashMap::new();
+    for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+        params.entry(key.into_owned()).or_default().push(value.into_owned());
+    }
+    params
+}
+
+fn basic_credentials(headers: &HeaderMap) -> Result<Option<(String, String)>, RegistryError> {
+    let Some(auth_header) = headers.get(header::AUTHORIZATION) else {
+        return Ok(None);
+    };
+
+    let encoded = auth_header
+        .to_str()
+        .ok()
+        .and_then(|value| value.strip_prefix("Basic "))
+        .ok_or_else(|| token_unauthorized("basic credentials required"))?;
+
+    let decoded = general_purpose::STANDARD
+        .decode(encoded)
+        .ok()
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .ok_or_else(|| token_unauthorized("invalid basic auth encoding"))?;
+
+    match decoded.split_once(':') {
+        Some((username, password)) => Ok(Some((username.to_string(), password.to_string()))),
+        None => Err(token_unauthorized("invalid basic auth format")),
+    }
+}
+
+/// Consult RBAC when it is enabled; users it does not know about are denied,
+/// except service principals, which RBAC has no record of
+async fn rbac_allows(
+    state: &AppState,
+    user: &User,
+    client_ip: Option<&str>,
+    scope: &RequestedScope,
+    action: &str,
+) -> bool {
+    let Some(rbac) = &state.rbac else {
+        return true;
+    };
+    // Robots and certificate identities are granted from their own scopes
+    if user.is_service_principal() {
+        return true;
+    }
+
+    let resource = match scope.resource_type.as_str() {
+        "repository" => ResourceType::Repository,
+        "registry" => ResourceType::Registry,
+        _ => return false,
+    };
+    let action = match action {
+        "pull" => Action::Pull,
+        "push" => Action::Push,
+        "delete" => Action::Delete,
+        "*" if resource == ResourceType::Registry => Action::List,
+        "*" => Action::Admin,
+        _ => return false,
+    };
+
+    let mut context = HashMap::new();
+    if let Some(ip) = client_ip {
+        context.insert("ip".to_string(), ip.to_string());
+    }
+
+    let request = AuthzRequest {
+        user_id: user.username.clone(),
+        resource,
+        resource_id: scope.name.clone(),
+        action,
+        context,
+    };
+
+    match rbac.authorize(request).await {
+        Ok(response) => response.allowed,
+        Err(e) => {
+            debug!("RBAC denied {}: {}", user.username, e);
+            false
+        }
+    }
+}
+
+fn token_unauthorized(reason: &str) -> RegistryError {
+    RegistryError {
+        code: OciError::Unauthorized,
+        message: format!("Authentication required: {}", reason),
+        detail: None,
+    }
+}
@@ -1,23 +1,57 @@
-use super::RegistryError;
+use super::quota::check_upload_quota;
+use super::{is_supported_digest, reject_proxy_push, validate_digest, OciError, RegistryError};
+use crate::api::middleware::may_pull_repository;
+use crate::auth::{RegistryToken, User};
+use crate::notifications::Event;
 use crate::server::AppState;
+use crate::storage::StorageError;
 use axum::{
-    extract::{Path, Query, State},
+    extract::{ConnectInfo, Path, Query, State},
     http::{header, HeaderMap, StatusCode},
-    response::IntoResponse,
-    body::Bytes,
+    response::{IntoResponse, Response},
+    body::Body,
+    Extension,
 };
-use sha2::{Digest, Sha256};
+use futures::stream::{BoxStream, StreamExt, TryStreamExt};
+use serde_json::json;
 use std::collections::HashMap;
-use tracing::{debug, error, info};
+use std::net::SocketAddr;
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
 pub async fn start_upload(
     State(state): State<AppState>,
     Path(name): Path<String>,
-) -> Result<impl IntoResponse, RegistryError> {
+    Query(params): Query<HashMap<String, String>>,
+    user: Option<Extension<User>>,
+    registry_token: Option<Extension<RegistryToken>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+) -> Result<Response, RegistryError> {
+    reject_proxy_push(&state, &name)?;
+
+    // Cross-repository blob mount: POST ?mount=<digest>&from=<repo>.
+    // A malformed digest can't be mounted and starts a regular upload instead.
+    let mount = params.get("mount").filter(|digest| is_supported_digest(digest));
+    if let (Some(digest), Some(from)) = (mount, params.get("from"))
+        && let Some(response) =
+            try_mount_blob(&state, &name, digest, from, user.as_deref(), registry_token.is_some(), connect_info).await?
+    {
+        return Ok(response);
+    }
+
     let upload_uuid = Uuid::new_v4().to_string();
     info!("Starting upload: {}/{}", name, upload_uuid);
 
+    let session = upload_session_id(&name, &upload_uuid)?;
+    if let Err(e) = state.storage.create_upload(&session).await {
+        error!("Failed to create upload session {}: {}", upload_uuid, e);
+        return Err(RegistryError {
+            code: OciError::Unknown,
+            message: "Failed to start upload".to_string(),
+            detail: None,
+        });
+    }
+
     let mut headers = HeaderMap::new();
     headers.insert(
         header::LOCATION,
@@ -32,86 +66,161 @@ pub async fn start_upload(
         "0-0".parse().unwrap(),
     );
 
-    Ok((StatusCode::ACCEPTED, headers))
+    Ok((StatusCode::ACCEPTED, headers).into_response())
 }
 
-pub async fn upload_chunk(
-    State(state): State<AppState>,
-    Path((name, uuid)): Path<(String, String)>,
-    headers: HeaderMap,
-    body: Bytes,
-) -> Result<impl IntoResponse, RegistryError> {
-    debug!("Uploading chunk: {}/{} ({} bytes)", name, uuid, body.len());
-
-    // Parse Content-Range header
-    let range = if let Some(range_header) = headers.get("Content-Range") {
-        parse_content_range(range_header.to_str().unwrap_or(""))
-    } else {
-        // If no range specified, assume starting at 0
-        (0, body.len() as u64)
-    };
+/// Attempt to mount an existing blob from another repository.
+/// Returns `None` when the mount can't be satisfied and a regular upload
+/// session should be started instead.
+async fn try_mount_blob(
+    state: &AppState,
+    name: &str,
+    digest: &str,
+    from: &str,
+    user: Option<&User>,
+    registry_token: bool,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+) -> Result<Option<Response>, RegistryError> {
+    // The source needs the same pull access as reading from it directly
+    let anonymous = User::anonymous();
+    let user = user.unwrap_or(&anonymous);
+    let peer = connect_info.map(|ConnectInfo(addr)| addr);
+    if !may_pull_repository(state, user, registry_token, from, peer).await {
+        warn!(
+            "User {} cannot pull from {}, falling back to regular upload",
+            user.username, from
+        );
+        return Ok(None);
+    }
 
-    match state.storage.put_upload_chunk(&uuid, range, body).await {
-        Ok(()) => {
-            let mut response_headers = HeaderMap::new();
-            response_headers.insert(
+    match state.storage.blob_exists(digest).await {
+        Ok(true) => {
+            info!("Mounted blob {} from {} into {}", digest, from, name);
+            state.holds.hold(digest).await;
+
+            let mut headers = HeaderMap::new();
+            headers.insert(
                 header::LOCATION,
-                format!("/v2/{}/blobs/uploads/{}", name, uuid).parse().unwrap(),
-            );
-            response_headers.insert(
-                "Docker-Upload-UUID",
-                uuid.parse().unwrap(),
+                format!("/v2/{}/blobs/{}", name, digest).parse().unwrap(),
             );
-            response_headers.insert(
-                "Range",
-                format!("0-{}", range.1 - 1).parse().unwrap(),
+            headers.insert(
+                "Docker-Content-Digest",
+                digest.parse().unwrap(),
             );
 
-            Ok((StatusCode::ACCEPTED, response_headers))
+            Ok(Some((StatusCode::CREATED, headers).into_response()))
+        }
+        Ok(false) => {
+            debug!("Blob {} not found for mount from {}, starting upload", digest, from);
+            Ok(None)
         }
         Err(e) => {
-            error!("Failed to upload chunk {}: {}", uuid, e);
+            error!("Failed to check blob {} for mount: {}", digest, e);
             Err(RegistryError {
-                code: "UNKNOWN".to_string(),
-                message: "Failed to upload chunk".to_string(),
+                code: OciError::Unknown,
+                message: "Failed to mount blob".to_string(),
                 detail: None,
             })
         }
     }
 }
 
+pub async fn upload_chunk(
+    State(state): State<AppState>,
+    Path((name, uuid)): Path<(String, String)>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<impl IntoResponse, RegistryError> {
+    debug!("Uploading chunk: {}/{}", name, uuid);
+
+    let session = upload_session_id(&name, &uuid)?;
+    let offset = upload_offset(&state, &session).await?;
+
+    // Chunks must be sent in order, starting where the previous one ended
+    if let Some(range_header) = headers.get("Content-Range") {
+        let Some((start, _end)) = parse_content_range(range_header.to_str().unwrap_or("")) else {
+            return Err(RegistryError {
+                code: OciError::BlobUploadInvalid,
+                message: "Malformed Content-Range header".to_string(),
+                detail: None,
+            });
+        };
+        if start != offset {
+            return Err(RegistryError {
+                code: OciError::RangeInvalid,
+                message: format!("Chunk starts at {} but upload is at offset {}", start, offset),
+                detail: Some(json!({ "offset": offset })),
+            });
+        }
+    }
+
+    let offset = append_chunk(&state, &session, &headers, body, offset).await?;
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(
+        header::LOCATION,
+        format!("/v2/{}/blobs/uploads/{}", name, uuid).parse().unwrap(),
+    );
+    response_headers.insert(
+        "Docker-Upload-UUID",
+        uuid.parse().unwrap(),
+    );
+    response_headers.insert(
+        "Range",
+        range_header_value(offset).parse().unwrap(),
+    );
+
+    Ok((StatusCode::ACCEPTED, response_headers))
+}
+
 pub async fn complete_upload(
     State(state): State<AppState>,
     Path((name, uuid)): Path<(String, String)>,
     Query(params): Query<HashMap<String, String>>,
-    body: Bytes,
+    user: Option<Extension<User>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    body: Body,
 ) -> Result<impl IntoResponse, RegistryError> {
     let digest = params.get("digest")
         .ok_or_else(|| RegistryError {
-            code: "DIGEST_INVALID".to_string(),
+            code: OciError::DigestInvalid,
             message: "Digest parameter required".to_string(),
             detail: None,
         })?;
 
     info!("Completing upload: {}/{} -> {}", name, uuid, digest);
 
-    // If there's a body, this is the final chunk
-    if !body.is_empty() {
-        // Calculate current size and append final chunk
-        let range = (0, body.len() as u64); // This should be calculated properly
-        if let Err(e) = state.storage.put_upload_chunk(&uuid, range, body).await {
-            error!("Failed to upload final chunk {}: {}", uuid, e);
-            return Err(RegistryError {
-                code: "UNKNOWN".to_string(),
-                message: "Failed to upload final chunk".to_string(),
-                detail: None,
-            });
+    validate_digest(digest)?;
+
+    let session = upload_session_id(&name, &uuid)?;
+    let offset = upload_offset(&state, &session).await?;
+
+    // Any body is the final chunk, appended at the current offset
+    let size = append_chunk(&state, &session, &headers, body, offset).await?;
+
+    // A rejected upload can't be completed later, so don't leave its data behind
+    if let Err(rejected) = check_upload_quota(&state, &name, digest, size).await {
+        if let Err(e) = state.storage.cancel_upload(&session).await {
+            error!("Failed to cancel upload {} over quota: {}", uuid, e);
         }
+        return Err(rejected);
     }
 
     // Complete the upload
-    match state.storage.complete_upload(&uuid, digest).await {
+    match state.storage.complete_upload(&session, digest).await {
         Ok(()) => {
+            // Keep GC off the blob until a manifest references it
+            state.holds.hold(digest).await;
+            if let Some(optimization) = &state.optimization {
+                optimization.layer_uploaded(digest, size);
+            }
+            state.notifier.emit(
+                Event::blob_pushed(&name, digest, size)
+                    .with_request("PUT", &headers, connect_info.map(|ConnectInfo(addr)| addr))
+                    .with_actor(user.as_deref()),
+            );
+
             let mut headers = HeaderMap::new();
             headers.insert(
                 header::LOCATION,
@@ -125,9 +234,18 @@ pub async fn complete_upload(
             Ok((StatusCode::CREATED, headers))
         }
         Err(e) => {
+            if let Some(StorageError::DigestMismatch { expected, actual }) = e.downcast_ref::<StorageError>() {
+                warn!("Rejected upload {}: digest mismatch ({} != {})", uuid, expected, actual);
+                return Err(RegistryError {
+                    code: OciError::DigestInvalid,
+                    message: "Provided digest did not match uploaded content".to_string(),
+                    detail: Some(json!({ "expected": expected, "actual": actual })),
+                });
+            }
+
             error!("Failed to complete upload {}: {}", uuid, e);
             Err(RegistryError {
-                code: "UNKNOWN".to_string(),
+                code: OciError::Unknown,
                 message: "Failed to complete upload".to_string(),
                 detail: None,
             })
@@ -141,34 +259,60 @@ pub async fn get_upload_status(
 ) -> Result<impl IntoResponse, RegistryError> {
     debug!("Getting upload status: {}/{}", name, uuid);
 
-    match state.storage.get_upload_url(&uuid).await {
-        Ok(Some(_)) => {
-            let mut headers = HeaderMap::new();
-            headers.insert(
-                header::LOCATION,
-                format!("/v2/{}/blobs/uploads/{}", name, uuid).parse().unwrap(),
-            );
-            headers.insert(
-                "Docker-Upload-UUID",
-                uuid.parse().unwrap(),
-            );
-            // TODO: Calculate actual range
-            headers.insert(
-                "Range",
-                "0-0".parse().unwrap(),
-            );
+    let offset = upload_offset(&state, &upload_session_id(&name, &uuid)?).await?;
 
-            Ok((StatusCode::NO_CONTENT, headers))
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::LOCATION,
+        format!("/v2/{}/blobs/uploads/{}", name, uuid).parse().unwrap(),
+    );
+    headers.insert(
+        "Docker-Upload-UUID",
+        uuid.parse().unwrap(),
+    );
+    headers.insert(
+        "Range",
+        range_header_value(offset).parse().unwrap(),
+    );
+
+    Ok((StatusCode::NO_CONTENT, headers))
+}
+
+/// Storage ID of the upload session `uuid` of repository `name`. Clients only
+/// see the UUID; the session is stored under a UUID derived from it and the
+/// repository, so it can't be reached through another repository.
+fn upload_session_id(name: &str, uuid: &str) -> Result<String, RegistryError> {
+    let uuid = Uuid::parse_str(uuid).map_err(|_| RegistryError {
+        code: OciError::BlobUploadInvalid,
+        message: format!("Invalid upload ID {:?}", uuid),
+        detail: None,
+    })?;
+    Ok(Uuid::new_v5(&uuid, name.as_bytes()).to_string())
+}
+
+/// Current offset of an upload session, or BLOB_UPLOAD_UNKNOWN if it doesn't exist or has expired
+async fn upload_offset(state: &AppState, session: &str) -> Result<u64, RegistryError> {
+    let ttl = chrono::Duration::hours(state.config.registry.upload_session_ttl_hours as i64);
+
+    match state.storage.get_upload_session(session).await {
+        Ok(Some(upload)) if chrono::Utc::now() - upload.updated_at <= ttl => Ok(upload.size),
+        Ok(Some(_)) => {
+            debug!("Upload session {} has expired", session);
+            Err(RegistryError {
+                code: OciError::BlobUploadUnknown,
+                message: "Upload has expired".to_string(),
+                detail: None,
+            })
         }
         Ok(None) => Err(RegistryError {
-            code: "BLOB_UPLOAD_UNKNOWN".to_string(),
-            message: format!("Upload {} not found", uuid),
+            code: OciError::BlobUploadUnknown,
+            message: "Upload not found".to_string(),
             detail: None,
         }),
         Err(e) => {
-            error!("Failed to get upload status {}: {}", uuid, e);
+            error!("Failed to get upload status {}: {}", session, e);
             Err(RegistryError {
-                code: "UNKNOWN".to_string(),
+                code: OciError::Unknown,
                 message: "Failed to get upload status".to_string(),
                 detail: None,
             })
@@ -176,18 +320,115 @@ pub async fn get_upload_status(
     }
 }
 
+/// Append a request body to an upload, cancelling the upload if it grows past the size limit
+async fn append_chunk(
+    state: &AppState,
+    session: &str,
+    headers: &HeaderMap,
+    body: Body,
+    offset: u64,
+) -> Result<u64, RegistryError> {
+    let limit = max_upload_size(state);
+
+    // Reject up front when the declared length already crosses the limit
+    let declared = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    let result = match (limit, declared) {
+        (Some(limit), Some(length)) if offset + length > limit => {
+            Err(StorageError::SizeExceeded { limit }.into())
+        }
+        _ => state.storage.append_upload(session, body_stream(body, offset, limit)).await,
+    };
+
+    match result {
+        Ok(new_offset) => {
+            state.metrics.blob_bytes_uploaded_total.inc_by(new_offset.saturating_sub(offset));
+            Ok(new_offset)
+        }
+        Err(e) => {
+            if let Some(StorageError::SizeExceeded { limit }) = e.downcast_ref::<StorageError>() {
+                warn!("Upload {} exceeded the maximum size of {} bytes, cancelling", session, limit);
+                if let Err(e) = state.storage.cancel_upload(session).await {
+                    error!("Failed to cancel oversized upload {}: {}", session, e);
+                }
+                return Err(RegistryError {
+                    code: OciError::SizeInvalid,
+                    message: format!("Upload exceeds the maximum size of {} bytes", limit),
+                    detail: Some(json!({ "limit": limit })),
+                });
+            }
+            if let Some(StorageError::QuotaExceeded { limit }) = e.downcast_ref::<StorageError>() {
+                warn!("Storage is full ({} bytes), cancelling upload {}", limit, session);
+                if let Err(e) = state.storage.cancel_upload(session).await {
+                    error!("Failed to cancel upload {}: {}", session, e);
+                }
+                return Err(RegistryError {
+                    code: OciError::Denied,
+                    message: "Registry storage is full".to_string(),
+                    detail: Some(json!({ "limit": limit })),
+                });
+            }
+
+            error!("Failed to upload chunk {}: {}", session, e);
+            Err(RegistryError {
+                code: OciError::Unknown,
+                message: "Failed to upload chunk".to_string(),
+                detail: None,
+            })
+        }
+    }
+}
+
+/// Configured upload size limit in bytes; 0 means unlimited
+fn max_upload_size(state: &AppState) -> Option<u64> {
+    match state.config.registry.max_upload_size_mb {
+        0 => None,
+        mb => Some(mb * 1024 * 1024),
+    }
+}
+
+/// Forward a request body to storage without buffering it, failing once the
+/// upload would grow past `limit`
+fn body_stream(
+    body: Body,
+    offset: u64,
+    limit: Option<u64>,
+) -> BoxStream<'static, anyhow::Result<bytes::Bytes>> {
+    let mut received = offset;
+    body.into_data_stream()
+        .map_err(anyhow::Error::from)
+        .and_then(move |chunk| {
+            received += chunk.len() as u64;
+            let result = match limit {
+                Some(limit) if received > limit => Err(StorageError::SizeExceeded { limit }.into()),
+                _ => Ok(chunk),
+            };
+            futures::future::ready(result)
+        })
+        .boxed()
+}
+
+/// Inclusive byte range received so far, as reported in the Range header
+fn range_header_value(offset: u64) -> String {
+    format!("0-{}", offset.saturating_sub(1))
+}
+
 pub async fn cancel_upload(
     State(state): State<AppState>,
     Path((name, uuid)): Path<(String, String)>,
 ) -> Result<impl IntoResponse, RegistryError> {
     info!("Cancelling upload: {}/{}", name, uuid);
+    let session = upload_session_id(&name, &uuid)?;
+    upload_offset(&state, &session).await?;
 
-    match state.storage.cancel_upload(&uuid).await {
+    match state.storage.cancel_upload(&session).await {
         Ok(()) => Ok(StatusCode::NO_CONTENT),
         Err(e) => {
             error!("Failed to cancel upload {}: {}", uuid, e);
             Err(RegistryError {
-                code: "UNKNOWN".to_string(),
+                code: OciError::Unknown,
                 message: "Failed to cancel upload".to_string(),
                 detail: None,
             })
@@ -195,16 +436,12 @@ pub async fn cancel_upload(
     }
 }
 
-fn parse_content_range(range_str: &str) -> (u64, u64) {
-    // Parse "bytes start-end/total" format
-    if let Some(range_part) = range_str.strip_prefix("bytes ") {
-        if let Some((range, _total)) = range_part.split_once('/') {
-            if let Some((start, end)) = range.split_once('-') {
-                if let (Ok(start), Ok(end)) = (start.parse::<u64>(), end.parse::<u64>()) {
-                    return (start, end + 1);
-                }
-            }
-        }
-    }
-    (0, 0)
+/// Parse a chunk's Content-Range into its start and exclusive end. The spec sends
+/// a bare `start-end`; older clients send `bytes start-end/total`.
+fn parse_content_range(range_str: &str) -> Option<(u64, u64)> {
+    let range = range_str.strip_prefix("bytes ").unwrap_or(range_str);
+    let range = range.split_once('/').map_or(range, |(range, _total)| range);
+    let (start, end) = range.split_once('-')?;
+    let (start, end) = (start.trim().parse::<u64>().ok()?, end.trim().parse::<u64>().ok()?);
+    (start <= end).then_some((start, end + 1))
 }
\ No newline at end of file
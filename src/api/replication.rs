@@ -0,0 +1,26 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json},
+    routing::get,
+    Router,
+};
+use serde_json::json;
+
+use crate::server::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/status", get(get_replication_status))
+}
+
+/// Queue depth and per-target lag of push replication
+async fn get_replication_status(State(state): State<AppState>) -> impl IntoResponse {
+    let Some(replication) = state.replication.as_ref() else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "Replication is not enabled" })),
+        );
+    };
+
+    (StatusCode::OK, Json(json!(replication.status().await)))
+}
@@ -0,0 +1,402 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json},
+    routing::delete,
+    Extension, Router,
+};
+use bytes::Bytes;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeSet, HashMap};
+use tracing::{debug, error, info, warn};
+
+use crate::api::registry::is_supported_digest;
+use crate::api::registry::manifest_types::Manifest;
+use crate::api::scanning;
+use crate::api::signing;
+use crate::api::registry::manifests::{
+    fetch_stored_manifest, is_immutable_tag, manifest_media_type, record_tag_change, tag_digest,
+};
+use crate::audit::{AuditService, UserInfo};
+use crate::auth::User;
+use crate::notifications::Event;
+use crate::server::AppState;
+use crate::storage::is_digest_reference;
+use crate::tag_history::TagAction;
+
+/// Kind recorded on the job started by `DELETE /api/v1/repositories/:name`
+const DELETE_REPOSITORY_JOB: &str = "delete_repository";
+
+/// Nested repository names span slashes, so they can't be matched as a route
+/// parameter; tag and manifest routes are told apart by their
+/// `/tags/<tag>/<action>` or `/manifests/<digest>/<action>` suffix
+pub fn router() -> Router<AppState> {
+    Router::new().route(
+        "/*name",
+        delete(delete_repository).get(get_route).post(post_route),
+    )
+}
+
+/// Split `<name>/tags/<tag>/<action>` into the repository name and tag
+fn split_tag_route<'a>(path: &'a str, action: &str) -> Option<(&'a str, &'a str)> {
+    let (name, tag) = path.strip_suffix(action)?.strip_suffix('/')?.rsplit_once("/tags/")?;
+    (!name.is_empty() && !tag.is_empty() && !tag.contains('/')).then_some((name, tag))
+}
+
+/// Split `<name>/manifests/<digest>/<action>` into the repository name and digest
+fn split_manifest_route<'a>(path: &'a str, action: &str) -> Option<(&'a str, &'a str)> {
+    let (name, digest) = path.strip_suffix(action)?.strip_suffix('/')?.rsplit_once("/manifests/")?;
+    (!name.is_empty() && is_supported_digest(digest)).then_some((name, digest))
+}
+
+async fn get_route(
+    State(state): State<AppState>,
+    Path(path): Path<String>,
+    params: Query<HashMap<String, String>>,
+) -> axum::response::Response {
+    if let Some((name, digest)) = split_manifest_route(&path, "signatures") {
+        return signing::list_signatures(&state, name, digest).await;
+    }
+    if let Some((name, digest)) = split_manifest_route(&path, "scan") {
+        return scanning::get_scan(&state, name, digest).await;
+    }
+    get_tag_history(State(state), Path(path), params).await.into_response()
+}
+
+async fn post_route(
+    State(state): State<AppState>,
+    Path(path): Path<String>,
+    user: Option<Extension<User>>,
+    body: Bytes,
+) -> axum::response::Response {
+    if let Some((name, digest)) = split_manifest_route(&path, "sign") {
+        let Some(Extension(user)) = user else {
+            return (StatusCode::UNAUTHORIZED, Json(json!({ "error": "Authentication required" }))).into_response();
+        };
+        return match parse_body(&body) {
+            Ok(request) => signing::sign_manifest(&state, &user, name, digest, request).await,
+            Err(response) => response,
+        };
+    }
+    if let Some((name, digest)) = split_manifest_route(&path, "verify") {
+        return match parse_body(&body) {
+            Ok(request) => signing::verify_manifest(&state, name, digest, request).await,
+            Err(response) => response,
+        };
+    }
+    match parse_body(&body) {
+        Ok(request) => rollback_tag(State(state), Path(path), user, Json(request)).await.into_response(),
+        Err(response) => response,
+    }
+}
+
+/// Parse a JSON request body; an empty body is read as `{}`
+#[allow(clippy::result_large_err)]
+fn parse_body<T: DeserializeOwned>(body: &[u8]) -> Result<T, axum::response::Response> {
+    let body = if body.is_empty() { b"{}".as_slice() } else { body };
+    serde_json::from_slice(body).map_err(|e| {
+        (StatusCode::BAD_REQUEST, Json(json!({ "error": format!("Invalid request body: {}", e) }))).into_response()
+    })
+}
+
+fn not_found() -> axum::response::Response {
+    (StatusCode::NOT_FOUND, Json(json!({ "error": "Not found" }))).into_response()
+}
+
+/// What deleting a repository removed
+#[derive(Debug, Default, Serialize)]
+pub struct RepositoryDeletion {
+    pub repository: String,
+    pub tags_deleted: usize,
+    pub manifests_deleted: usize,
+    /// Blobs no other repository references, deleted right away
+    pub blobs_deleted: usize,
+    /// Blobs that may still be referenced elsewhere, left for the garbage collector
+    pub blobs_left_for_gc: usize,
+}
+
+/// Delete every tag and manifest of a repository in the background; poll the
+/// returned job through `GET /api/v1/jobs/:id`
+async fn delete_repository(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    user: Option<Extension<User>>,
+) -> impl IntoResponse {
+    let Some(Extension(user)) = user else {
+        return (StatusCode::UNAUTHORIZED, Json(json!({ "error": "Authentication required" }))).into_response();
+    };
+
+    let (tags, manifests) = match tokio::try_join!(state.storage.list_tags(&name), state.storage.list_manifests(&name)) {
+        Ok(contents) => contents,
+        Err(e) => {
+            error!("Failed to list repository {}: {}", name, e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Failed to list repository" })))
+                .into_response();
+        }
+    };
+    if tags.is_empty() && manifests.is_empty() {
+        return (StatusCode::NOT_FOUND, Json(json!({ "error": format!("Repository {} not found", name) })))
+            .into_response();
+    }
+
+    info!("User {} is deleting repository {}", user.username, name);
+    let job_id = state.jobs.spawn(DELETE_REPOSITORY_JOB, &name, {
+        let state = state.clone();
+        let name = name.clone();
+        async move {
+            let result = remove_repository(&state, &user, &name).await;
+            audit_repository_deletion(&state, &user, &name, &result).await;
+            result
+        }
+    });
+
+    (
+        StatusCode::ACCEPTED,
+        [(header::LOCATION, format!("/api/v1/jobs/{}", job_id))],
+        Json(json!({ "job_id": job_id })),
+    )
+        .into_response()
+}
+
+/// Delete a repository's tags and manifests, then the blobs only it referenced
+async fn remove_repository(state: &AppState, user: &User, name: &str) -> anyhow::Result<RepositoryDeletion> {
+    let mut deletion = RepositoryDeletion {
+        repository: name.to_string(),
+        ..Default::default()
+    };
+
+    let repository_lock = state.repository_locks.lock(name).await;
+
+    let tags = state.storage.list_tags(name).await?;
+    let digests: BTreeSet<String> = state.storage.list_manifests(name).await?.into_iter().collect();
+
+    // Read everything first so the blobs are known even if a delete fails part way.
+    // Manifests pushed by tag may only be stored under the tag.
+    let mut blobs = BTreeSet::new();
+    let mut manifests = Vec::new();
+    for reference in tags.iter().chain(&digests) {
+        let Some(data) = state.storage.get_manifest(name, reference).await? else {
+            continue;
+        };
+        match Manifest::parse(&data) {
+            Ok(manifest) => blobs.extend(manifest.blob_references().into_iter().map(str::to_string)),
+            Err(e) => warn!("Failed to parse manifest {}:{}, its blobs are left for GC: {}", name, reference, e),
+        }
+        if is_digest_reference(reference) {
+            manifests.push((reference.clone(), data));
+        }
+    }
+
+    for tag in &tags {
+        debug!("Untagging {}:{}", name, tag);
+        let digest = tag_digest(state, name, tag).await;
+        state.storage.delete_manifest(name, tag).await?;
+        record_tag_change(state, Some(user), name, tag, TagAction::Delete, digest, None).await;
+        deletion.tags_deleted += 1;
+        state.notifier.emit(Event::manifest_deleted(name, tag));
+    }
+
+    for (digest, data) in &manifests {
+        state.storage.delete_manifest(name, digest).await?;
+        deletion.manifests_deleted += 1;
+        state.notifier.emit(Event::manifest_deleted(name, digest));
+
+        if let Some(optimization) = &state.optimization
+            && let Err(e) = optimization.release_manifest_layers(name, digest, data).await
+        {
+            warn!("Failed to release deduplicated layers of {}@{}: {}", name, digest, e);
+        }
+    }
+
+    drop(repository_lock);
+
+    // Without a reference table other repositories' use of a blob isn't known
+    // here, so the garbage collector decides on its next run
+    match state.storage.blob_references().await? {
+        Some(references) => {
+            for digest in &blobs {
+                if references.blobs.contains_key(digest) || state.holds.is_held(digest).await {
+                    deletion.blobs_left_for_gc += 1;
+                    continue;
+                }
+                match state.storage.delete_blob(digest).await {
+                    Ok(()) => deletion.blobs_deleted += 1,
+                    Err(e) => {
+                        warn!("Failed to delete blob {} of {}, leaving it for GC: {}", digest, name, e);
+                        deletion.blobs_left_for_gc += 1;
+                    }
+                }
+            }
+        }
+        None => deletion.blobs_left_for_gc = blobs.len(),
+    }
+
+    if let Some(rbac) = &state.rbac {
+        rbac.invalidate_organization_usage().await;
+    }
+
+    info!(
+        "Deleted repository {}: {} tags, {} manifests, {} blobs ({} left for GC)",
+        name, deletion.tags_deleted, deletion.manifests_deleted, deletion.blobs_deleted, deletion.blobs_left_for_gc
+    );
+    Ok(deletion)
+}
+
+/// `GET <name>/tags/<tag>/history`: the digests a tag pointed at, newest first.
+/// Paged with `page` (from 1) and `per_page`.
+async fn get_tag_history(
+    State(state): State<AppState>,
+    Path(path): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> impl IntoResponse {
+    let Some((name, tag)) = split_tag_route(&path, "history") else {
+        return not_found();
+    };
+    let page = params.get("page").and_then(|p| p.parse::<usize>().ok()).unwrap_or(1).max(1);
+    let per_page = params.get("per_page").and_then(|p| p.parse::<usize>().ok()).unwrap_or(20).clamp(1, 100);
+
+    let mut revisions = match state.tag_history.revisions(name, tag).await {
+        Ok(revisions) => revisions,
+        Err(e) => {
+            error!("Failed to read history of {}:{}: {}", name, tag, e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Failed to read tag history" })))
+                .into_response();
+        }
+    };
+    revisions.reverse();
+
+    let total = revisions.len();
+    let revisions: Vec<_> = revisions.into_iter().skip((page - 1) * per_page).take(per_page).collect();
+
+    Json(json!({
+        "repository": name,
+        "tag": tag,
+        "revisions": revisions,
+        "total": total,
+        "page": page,
+        "per_page": per_page,
+        "pages": total.div_ceil(per_page),
+    }))
+    .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct RollbackRequest {
+    digest: String,
+}
+
+/// `POST <name>/tags/<tag>/rollback`: point a tag back at a digest from its history
+async fn rollback_tag(
+    State(state): State<AppState>,
+    Path(path): Path<String>,
+    user: Option<Extension<User>>,
+    Json(request): Json<RollbackRequest>,
+) -> impl IntoResponse {
+    let Some(Extension(user)) = user else {
+        return (StatusCode::UNAUTHORIZED, Json(json!({ "error": "Authentication required" }))).into_response();
+    };
+    let Some((name, tag)) = split_tag_route(&path, "rollback") else {
+        return not_found();
+    };
+    let digest = request.digest;
+
+    let revisions = match state.tag_history.revisions(name, tag).await {
+        Ok(revisions) => revisions,
+        Err(e) => {
+            error!("Failed to read history of {}:{}: {}", name, tag, e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Failed to read tag history" })))
+                .into_response();
+        }
+    };
+    let known = revisions.iter().any(|revision| {
+        revision.old_digest.as_deref() == Some(digest.as_str()) || revision.new_digest.as_deref() == Some(digest.as_str())
+    });
+    if !known {
+        let error = format!("{}:{} never pointed at {}", name, tag, digest);
+        return (StatusCode::BAD_REQUEST, Json(json!({ "error": error }))).into_response();
+    }
+
+    let repository_lock = state.repository_locks.lock(name).await;
+
+    let previous_digest = tag_digest(&state, name, tag).await;
+    if previous_digest.as_deref() == Some(digest.as_str()) {
+        return Json(json!({ "repository": name, "tag": tag, "digest": digest, "previous_digest": previous_digest }))
+            .into_response();
+    }
+
+    // Immutable tags can't be moved once they exist, not even back
+    if previous_digest.is_some() && is_immutable_tag(&state.runtime_config.load().registry.immutable_tags, tag) {
+        let error = format!("Tag {} is immutable and already exists in {}", tag, name);
+        return (StatusCode::CONFLICT, Json(json!({ "error": error }))).into_response();
+    }
+
+    let data = match fetch_stored_manifest(&state, name, &digest).await {
+        Ok(Some(data)) => data,
+        Ok(None) => {
+            let error = format!("Manifest {}@{} is no longer stored", name, digest);
+            return (StatusCode::NOT_FOUND, Json(json!({ "error": error }))).into_response();
+        }
+        Err(e) => {
+            error!("Failed to read manifest {}@{}: {}", name, digest, e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Failed to read manifest" })))
+                .into_response();
+        }
+    };
+    let media_type = match state.storage.get_manifest_metadata(name, &digest).await {
+        Ok(metadata) => metadata.media_type,
+        Err(_) => None,
+    }
+    .unwrap_or_else(|| manifest_media_type(&data));
+    let size = data.len() as u64;
+
+    if let Err(e) = state.storage.put_manifest(name, tag, data, &media_type).await {
+        error!("Failed to roll back {}:{} to {}: {}", name, tag, digest, e);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Failed to roll back tag" })))
+            .into_response();
+    }
+    record_tag_change(
+        &state, Some(&user), name, tag, TagAction::Rollback, previous_digest.clone(), Some(digest.clone()),
+    ).await;
+    drop(repository_lock);
+
+    info!("User {} rolled back {}:{} to {}", user.username, name, tag, digest);
+    state.notifier.emit(Event::manifest_pushed(name, tag, &digest, &media_type, size).with_actor(Some(&user)));
+
+    Json(json!({ "repository": name, "tag": tag, "digest": digest, "previous_digest": previous_digest }))
+        .into_response()
+}
+
+async fn audit_repository_deletion(
+    state: &AppState,
+    user: &User,
+    name: &str,
+    result: &anyhow::Result<RepositoryDeletion>,
+) {
+    let Some(audit) = state.audit.as_ref() else {
+        return;
+    };
+
+    let (totals, error_message) = match result {
+        Ok(deletion) => {
+            let totals = HashMap::from([
+                ("tags_deleted".to_string(), json!(deletion.tags_deleted)),
+                ("manifests_deleted".to_string(), json!(deletion.manifests_deleted)),
+                ("blobs_deleted".to_string(), json!(deletion.blobs_deleted)),
+                ("blobs_left_for_gc".to_string(), json!(deletion.blobs_left_for_gc)),
+            ]);
+            (totals, None)
+        }
+        Err(e) => (HashMap::new(), Some(format!("{:#}", e))),
+    };
+
+    let event = AuditService::repository_deleted_event(
+        UserInfo::from_user(Some(user)),
+        name.to_string(),
+        totals,
+        error_message,
+    );
+    if let Err(e) = audit.log(event).await {
+        error!("Failed to record audit event for deleting {}: {}", name, e);
+    }
+}
@@ -0,0 +1,153 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json},
+    routing::{delete, get},
+    Extension, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{error, info, warn};
+
+use crate::audit::{AuditService, EventType, UserInfo};
+use crate::auth::robots::RobotAccount;
+use crate::auth::User;
+use crate::server::AppState;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateRobotRequest {
+    pub name: String,
+    pub description: Option<String>,
+    pub scopes: Vec<String>,
+    pub expires_in_days: Option<u64>,
+}
+
+/// A robot as shown through the API, without its secret hash
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RobotInfo {
+    pub name: String,
+    pub username: String,
+    pub description: Option<String>,
+    pub scopes: Vec<String>,
+    pub created_by: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub expired: bool,
+}
+
+impl From<&RobotAccount> for RobotInfo {
+    fn from(robot: &RobotAccount) -> Self {
+        Self {
+            name: robot.name.clone(),
+            username: robot.username(),
+            description: robot.description.clone(),
+            scopes: robot.scopes.clone(),
+            created_by: robot.created_by.clone(),
+            created_at: robot.created_at,
+            expires_at: robot.expires_at,
+            expired: robot.is_expired(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateRobotResponse {
+    pub robot: RobotInfo,
+    /// Only returned once, at creation
+    pub secret: String,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_robots).post(create_robot))
+        .route("/:name", delete(revoke_robot))
+}
+
+async fn list_robots(State(state): State<AppState>) -> impl IntoResponse {
+    let robots: Vec<RobotInfo> = state.robots.list().await.iter().map(RobotInfo::from).collect();
+    Json(json!({ "robots": robots }))
+}
+
+async fn create_robot(
+    State(state): State<AppState>,
+    user: Option<Extension<User>>,
+    Json(request): Json<CreateRobotRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let created_by = user.as_ref().map(|u| u.username.clone()).unwrap_or_default();
+    info!("Creating robot account {} for {}", request.name, created_by);
+
+    if request.scopes.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "A robot needs at least one scope" })),
+        ));
+    }
+
+    let expires_at = request
+        .expires_in_days
+        .map(|days| chrono::Utc::now() + chrono::Duration::days(days as i64));
+
+    match state
+        .robots
+        .create(&request.name, request.description, request.scopes, expires_at, &created_by)
+        .await
+    {
+        Ok((robot, secret)) => {
+            audit_robot(&state, user.as_deref(), EventType::RobotCreated, &robot.username(), "POST").await;
+            Ok((
+                StatusCode::CREATED,
+                Json(CreateRobotResponse {
+                    robot: RobotInfo::from(&robot),
+                    secret,
+                }),
+            ))
+        }
+        Err(e) => {
+            warn!("Failed to create robot account {}: {}", request.name, e);
+            Err((StatusCode::BAD_REQUEST, Json(json!({ "error": e.to_string() }))))
+        }
+    }
+}
+
+async fn revoke_robot(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    user: Option<Extension<User>>,
+) -> impl IntoResponse {
+    match state.robots.revoke(&name).await {
+        Ok(true) => {
+            let username = format!("{}{}", crate::auth::robots::ROBOT_PREFIX, name);
+            audit_robot(&state, user.as_deref(), EventType::RobotRevoked, &username, "DELETE").await;
+            StatusCode::NO_CONTENT
+        }
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            error!("Failed to revoke robot account {}: {}", name, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Record a robot lifecycle event when auditing is enabled
+pub async fn audit_robot(
+    state: &AppState,
+    user: Option<&User>,
+    event_type: EventType,
+    robot: &str,
+    method: &str,
+) {
+    let Some(audit) = state.audit.as_ref() else {
+        return;
+    };
+
+    let event = AuditService::robot_event(
+        UserInfo::from_user(user),
+        event_type,
+        robot.to_string(),
+        method,
+        "/api/v1/robots".to_string(),
+    );
+    if let Err(e) = audit.log(event).await {
+        error!("Failed to record audit event for robot {}: {}", robot, e);
+    }
+}
@@ -0,0 +1,64 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::json;
+use tracing::error;
+
+use crate::server::AppState;
+
+/// `GET /api/v1/repositories/<name>/manifests/<digest>/scan`: the latest
+/// vulnerability scan of a manifest, with its Trivy report once it completed
+pub async fn get_scan(state: &AppState, name: &str, digest: &str) -> Response {
+    let Some(scanning) = state.scanning.as_ref() else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "Vulnerability scanning is not enabled" })),
+        )
+            .into_response();
+    };
+
+    let record = match scanning.get_scan(name, digest).await {
+        Ok(Some(record)) => record,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": format!("Manifest {}@{} has not been scanned", name, digest) })),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            error!("Failed to read scan of {}@{}: {}", name, digest, e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Failed to read scan" }))).into_response();
+        }
+    };
+
+    let report = match scanning.get_report(&record).await {
+        Ok(report) => report,
+        Err(e) => {
+            error!("Failed to read scan report of {}@{}: {}", name, digest, e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Failed to read scan report" })))
+                .into_response();
+        }
+    };
+
+    let blocked = scanning
+        .block_threshold(name)
+        .is_some_and(|threshold| record.summary.at_or_above(threshold) > 0);
+    (
+        StatusCode::OK,
+        Json(json!({
+            "repository": record.repository,
+            "digest": record.digest,
+            "status": record.status,
+            "started_at": record.started_at,
+            "finished_at": record.finished_at,
+            "summary": record.summary,
+            "blocked": blocked,
+            "report_manifest": record.report_manifest,
+            "error": record.error,
+            "report": report,
+        })),
+    )
+        .into_response()
+}
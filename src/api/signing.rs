@@ -0,0 +1,556 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+    routing::{delete, get, post},
+    Extension, Router,
+};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use tracing::{error, info, warn};
+
+use crate::api::registry::manifests::{fetch_stored_manifest, update_cosign_artifact};
+use crate::audit::{AuditService, EventType, UserInfo};
+use crate::auth::User;
+use crate::config::RepositorySignaturePolicy;
+use crate::server::AppState;
+use crate::signing::{
+    ContentSignature, SignatureAlgorithm, SignatureFormat, SignaturePayload, SigningService, VerificationPolicy,
+    VerificationResult,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct UpdatePolicyRequest {
+    /// Replaces every per-repository policy; the first matching pattern wins
+    pub repositories: Vec<RepositorySignaturePolicy>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GenerateKeyRequest {
+    pub key_id: String,
+    pub algorithm: SignatureAlgorithm,
+    pub expires_in_days: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportKeyRequest {
+    pub key_id: String,
+    pub algorithm: SignatureAlgorithm,
+    /// PEM `PUBLIC KEY`
+    pub public_key: String,
+    /// PEM `CERTIFICATE`
+    pub certificate: Option<String>,
+    #[serde(default = "default_trusted")]
+    pub trusted: bool,
+    pub expires_in_days: Option<u32>,
+}
+
+fn default_trusted() -> bool {
+    true
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SignRequest {
+    /// Defaults to `signing.default_key_id`
+    pub key_id: Option<String>,
+    /// Defaults to cosign
+    pub format: Option<SignatureFormat>,
+    /// Tag the manifest was signed as, recorded in the signed payload
+    pub tag: Option<String>,
+    pub metadata: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct VerifyRequest {
+    /// Defaults to the repository's policy
+    pub policy: Option<VerificationPolicy>,
+}
+
+/// Summary for a signature badge: `unsigned` when there are no signatures,
+/// `signed` when at least one verifies, `invalid` otherwise
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureStatus {
+    Signed,
+    Unsigned,
+    Invalid,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VerifiedSignature {
+    pub signature: ContentSignature,
+    pub verification: VerificationResult,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SignatureReport {
+    pub repository: String,
+    pub digest: String,
+    pub status: SignatureStatus,
+    pub signatures: Vec<VerifiedSignature>,
+}
+
+/// `/api/v1/signing`
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/policy", get(get_policy).put(update_policy))
+        .route("/keys", get(list_keys).post(generate_key))
+        .route("/keys/import", post(import_key))
+        .route("/keys/:id", delete(delete_key))
+        .route("/keys/:id/retire", post(retire_key))
+}
+
+/// `/api/v1/signatures`
+pub fn signatures_router() -> Router<AppState> {
+    Router::new().route("/:id", delete(revoke_signature))
+}
+
+fn signing_disabled() -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({ "error": "Content signing is not enabled" })),
+    )
+}
+
+/// The configured default and the per-repository policies in effect
+async fn get_policy(State(state): State<AppState>) -> impl IntoResponse {
+    let Some(signing) = state.signing.as_ref() else {
+        return signing_disabled();
+    };
+
+    let policy = signing.verification_policy();
+    (
+        StatusCode::OK,
+        Json(json!({
+            "require_signatures": policy.require_signatures,
+            "required_signatures_count": policy.required_signatures_count,
+            "repositories": signing.repository_policies().await,
+            "grace_period_secs": signing.grace_period_secs(),
+            "cache_ttl_secs": signing.cache_ttl_secs(),
+        })),
+    )
+}
+
+/// Replace the per-repository policies. The change lasts until restart; make it
+/// permanent under `[signing.enforcement]`.
+async fn update_policy(
+    State(state): State<AppState>,
+    user: Option<Extension<User>>,
+    Json(request): Json<UpdatePolicyRequest>,
+) -> impl IntoResponse {
+    let Some(signing) = state.signing.as_ref() else {
+        return signing_disabled();
+    };
+
+    let previous = signing.repository_policies().await;
+    if let Err(e) = signing.set_repository_policies(request.repositories.clone()).await {
+        warn!("Rejected signature policy update: {}", e);
+        return (StatusCode::BAD_REQUEST, Json(json!({ "error": e.to_string() })));
+    }
+
+    info!(
+        "{} set {} repository signature policies",
+        user.as_ref().map_or("anonymous", |user| user.username.as_str()),
+        request.repositories.len()
+    );
+
+    if let Some(audit) = state.audit.as_ref() {
+        let event = AuditService::signing_policy_event(
+            UserInfo::from_user(user.as_deref()),
+            json!(previous),
+            json!(request.repositories),
+        );
+        if let Err(e) = audit.log(event).await {
+            error!("Failed to record audit event for signature policy update: {}", e);
+        }
+    }
+
+    (StatusCode::OK, Json(json!({ "repositories": request.repositories })))
+}
+
+/// Signing and verification keys, public parts only
+async fn list_keys(State(state): State<AppState>) -> impl IntoResponse {
+    let Some(signing) = state.signing.as_ref() else {
+        return signing_disabled();
+    };
+
+    (StatusCode::OK, Json(json!({ "keys": signing.list_keys().await })))
+}
+
+/// Generate a signing key pair; the private key never leaves the server
+async fn generate_key(
+    State(state): State<AppState>,
+    user: Option<Extension<User>>,
+    Json(request): Json<GenerateKeyRequest>,
+) -> impl IntoResponse {
+    let Some(signing) = state.signing.as_ref() else {
+        return signing_disabled();
+    };
+
+    let expires_at = request
+        .expires_in_days
+        .map(|days| chrono::Utc::now() + chrono::Duration::days(days as i64));
+
+    match signing.generate_key(&request.key_id, request.algorithm, expires_at).await {
+        Ok(key) => {
+            audit_key(&state, user.as_deref(), EventType::SigningKeyCreated, &key.key_id, "generate", "POST",
+                "/api/v1/signing/keys".to_string()).await;
+            (StatusCode::CREATED, Json(json!(key)))
+        }
+        Err(e) => {
+            warn!("Failed to generate signing key {}: {:#}", request.key_id, e);
+            (StatusCode::BAD_REQUEST, Json(json!({ "error": e.to_string() })))
+        }
+    }
+}
+
+/// Import a public key to verify signatures made elsewhere
+async fn import_key(
+    State(state): State<AppState>,
+    user: Option<Extension<User>>,
+    Json(request): Json<ImportKeyRequest>,
+) -> impl IntoResponse {
+    let Some(signing) = state.signing.as_ref() else {
+        return signing_disabled();
+    };
+
+    let expires_at = request
+        .expires_in_days
+        .map(|days| chrono::Utc::now() + chrono::Duration::days(days as i64));
+
+    match signing
+        .import_verification_key(
+            &request.key_id,
+            request.algorithm,
+            &request.public_key,
+            request.certificate.as_deref(),
+            request.trusted,
+            expires_at,
+        )
+        .await
+    {
+        Ok(key) => {
+            audit_key(&state, user.as_deref(), EventType::SigningKeyCreated, &key.key_id, "import", "POST",
+                "/api/v1/signing/keys/import".to_string()).await;
+            (StatusCode::CREATED, Json(json!(key)))
+        }
+        Err(e) => {
+            warn!("Failed to import verification key {}: {:#}", request.key_id, e);
+            (StatusCode::BAD_REQUEST, Json(json!({ "error": e.to_string() })))
+        }
+    }
+}
+
+/// Stop signing with a key; signatures made with it keep verifying
+async fn retire_key(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    user: Option<Extension<User>>,
+) -> impl IntoResponse {
+    let Some(signing) = state.signing.as_ref() else {
+        return signing_disabled();
+    };
+
+    match signing.retire_key(&id).await {
+        Ok(Some(key)) => {
+            audit_key(&state, user.as_deref(), EventType::SigningKeyRetired, &id, "retire", "POST",
+                format!("/api/v1/signing/keys/{}/retire", id)).await;
+            (StatusCode::OK, Json(json!(key)))
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, Json(json!({ "error": format!("Key {} not found", id) }))),
+        Err(e) => {
+            warn!("Failed to retire key {}: {:#}", id, e);
+            (StatusCode::BAD_REQUEST, Json(json!({ "error": e.to_string() })))
+        }
+    }
+}
+
+async fn delete_key(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    user: Option<Extension<User>>,
+) -> Response {
+    let Some(signing) = state.signing.as_ref() else {
+        return signing_disabled().into_response();
+    };
+
+    match signing.delete_key(&id).await {
+        Ok(true) => {
+            audit_key(&state, user.as_deref(), EventType::SigningKeyDeleted, &id, "delete", "DELETE",
+                format!("/api/v1/signing/keys/{}", id)).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => (StatusCode::NOT_FOUND, Json(json!({ "error": format!("Key {} not found", id) }))).into_response(),
+        Err(e) => {
+            warn!("Failed to delete key {}: {:#}", id, e);
+            (StatusCode::BAD_REQUEST, Json(json!({ "error": e.to_string() }))).into_response()
+        }
+    }
+}
+
+/// `GET /api/v1/repositories/<name>/manifests/<digest>/signatures`: every
+/// signature of the manifest, verified against the repository's policy
+pub async fn list_signatures(state: &AppState, name: &str, digest: &str) -> Response {
+    let Some(signing) = state.signing.as_ref() else {
+        return signing_disabled().into_response();
+    };
+    let content = match manifest_content(state, name, digest).await {
+        Ok(content) => content,
+        Err(response) => return response,
+    };
+
+    let policy = signing.verification_policy_for(name).await;
+    verification_report(signing, name, digest, &content, &policy).await
+}
+
+/// `POST /api/v1/repositories/<name>/manifests/<digest>/verify`: like listing
+/// the signatures, but against the policy in the request if there is one
+pub async fn verify_manifest(state: &AppState, name: &str, digest: &str, request: VerifyRequest) -> Response {
+    let Some(signing) = state.signing.as_ref() else {
+        return signing_disabled().into_response();
+    };
+    let content = match manifest_content(state, name, digest).await {
+        Ok(content) => content,
+        Err(response) => return response,
+    };
+
+    let policy = match request.policy {
+        Some(policy) => policy,
+        None => signing.verification_policy_for(name).await,
+    };
+    verification_report(signing, name, digest, &content, &policy).await
+}
+
+/// `POST /api/v1/repositories/<name>/manifests/<digest>/sign`
+pub async fn sign_manifest(
+    state: &AppState,
+    user: &User,
+    name: &str,
+    digest: &str,
+    request: SignRequest,
+) -> Response {
+    let Some(signing) = state.signing.as_ref() else {
+        return signing_disabled().into_response();
+    };
+    let content = match manifest_content(state, name, digest).await {
+        Ok(content) => content,
+        Err(response) => return response,
+    };
+
+    let key_id = request.key_id.unwrap_or_else(|| signing.default_key_id().to_string());
+    if !signing.has_signing_key(&key_id).await {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "error": format!("Unknown or retired signing key {}", key_id) })))
+            .into_response();
+    }
+
+    let payload = SignaturePayload {
+        subject: digest.to_string(),
+        content_type: "manifest".to_string(),
+        repository: name.to_string(),
+        tag: request.tag,
+        metadata: request.metadata,
+        timestamp: chrono::Utc::now(),
+    };
+    let format = request.format.unwrap_or(SignatureFormat::Cosign);
+
+    match signing.sign_content(&content, &key_id, format, payload).await {
+        Ok(signature) => {
+            if let Err(e) = sync_cosign_artifact(state, Some(user), signing, &signature, true).await {
+                error!("Failed to store the Cosign signature artifact of {}@{}: {:#}", name, digest, e);
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Failed to sign manifest" })))
+                    .into_response();
+            }
+            info!(
+                "{} signed {}@{} with key {}",
+                user.username, name, digest, key_id
+            );
+            audit_signature(
+                state, Some(user), EventType::SignatureCreated, name, digest, &signature.signature_id, "POST",
+                format!("/api/v1/repositories/{}/manifests/{}/sign", name, digest),
+            ).await;
+            (StatusCode::CREATED, Json(signature)).into_response()
+        }
+        Err(e) => {
+            error!("Failed to sign {}@{}: {:#}", name, digest, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Failed to sign manifest" }))).into_response()
+        }
+    }
+}
+
+/// `DELETE /api/v1/signatures/:id`
+async fn revoke_signature(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    user: Option<Extension<User>>,
+) -> Response {
+    let Some(signing) = state.signing.as_ref() else {
+        return signing_disabled().into_response();
+    };
+
+    match signing.revoke_signature(&id).await {
+        Ok(Some(signature)) => {
+            if let Err(e) = sync_cosign_artifact(&state, user.as_deref(), signing, &signature, false).await {
+                error!("Failed to remove signature {} from its Cosign signature artifact: {:#}", id, e);
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Failed to revoke signature" })))
+                    .into_response();
+            }
+            let digest = format!("sha256:{}", signature.content_digest);
+            audit_signature(
+                &state, user.as_deref(), EventType::SignatureRevoked, &signature.payload.repository, &digest, &id,
+                "DELETE", format!("/api/v1/signatures/{}", id),
+            ).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(None) => {
+            (StatusCode::NOT_FOUND, Json(json!({ "error": format!("Signature {} not found", id) }))).into_response()
+        }
+        Err(e) => {
+            error!("Failed to revoke signature {}: {:#}", id, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Failed to revoke signature" }))).into_response()
+        }
+    }
+}
+
+/// Add a stored Cosign signature to the signature artifact cosign looks for next to the image,
+/// or take a deleted one out of it. Other formats and signatures of no repository have none.
+pub async fn sync_cosign_artifact(
+    state: &AppState,
+    user: Option<&User>,
+    signing: &SigningService,
+    signature: &ContentSignature,
+    stored: bool,
+) -> anyhow::Result<()> {
+    let repository = &signature.payload.repository;
+    if signature.format != SignatureFormat::Cosign || repository.is_empty() {
+        return Ok(());
+    }
+
+    update_cosign_artifact(state, user, repository, &signature.content_digest, async |artifact| {
+        if stored {
+            signing.cosign_artifact_with(artifact, signature).await
+        } else {
+            signing.cosign_artifact_without(artifact, signature).await
+        }
+    })
+    .await
+}
+
+async fn manifest_content(state: &AppState, name: &str, digest: &str) -> Result<Bytes, Response> {
+    match fetch_stored_manifest(state, name, digest).await {
+        Ok(Some(content)) => Ok(content),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("Manifest {}@{} not found", name, digest) })),
+        )
+            .into_response()),
+        Err(e) => {
+            error!("Failed to read manifest {}@{}: {}", name, digest, e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Failed to read manifest" }))).into_response())
+        }
+    }
+}
+
+async fn verification_report(
+    signing: &SigningService,
+    name: &str,
+    digest: &str,
+    content: &[u8],
+    policy: &VerificationPolicy,
+) -> Response {
+    let content_digest = digest.strip_prefix("sha256:").unwrap_or(digest);
+    let signatures = match signing.get_content_signatures(name, content_digest).await {
+        Ok(signatures) => signatures,
+        Err(e) => {
+            error!("Failed to read signatures of {}@{}: {:#}", name, digest, e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Failed to read signatures" })))
+                .into_response();
+        }
+    };
+
+    let mut verified = Vec::with_capacity(signatures.len());
+    for signature in signatures {
+        match signing.verify_signature(content, &signature, policy).await {
+            Ok(verification) => verified.push(VerifiedSignature { signature, verification }),
+            Err(e) => {
+                error!("Failed to verify signature {} of {}@{}: {:#}", signature.signature_id, name, digest, e);
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Failed to verify signatures" })))
+                    .into_response();
+            }
+        }
+    }
+
+    let status = if verified.is_empty() {
+        SignatureStatus::Unsigned
+    } else if verified.iter().any(|signature| signature.verification.valid) {
+        SignatureStatus::Signed
+    } else {
+        SignatureStatus::Invalid
+    };
+
+    Json(SignatureReport {
+        repository: name.to_string(),
+        digest: digest.to_string(),
+        status,
+        signatures: verified,
+    })
+    .into_response()
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn audit_signature(
+    state: &AppState,
+    user: Option<&User>,
+    event_type: EventType,
+    repository: &str,
+    digest: &str,
+    signature_id: &str,
+    method: &str,
+    path: String,
+) {
+    let Some(audit) = state.audit.as_ref() else {
+        return;
+    };
+
+    let event = AuditService::signature_event(
+        UserInfo::from_user(user),
+        event_type,
+        repository.to_string(),
+        digest.to_string(),
+        signature_id.to_string(),
+        method,
+        path,
+    );
+    if let Err(e) = audit.log(event).await {
+        error!("Failed to record audit event for signature {}: {}", signature_id, e);
+    }
+}
+
+async fn audit_key(
+    state: &AppState,
+    user: Option<&User>,
+    event_type: EventType,
+    key_id: &str,
+    operation: &str,
+    method: &str,
+    path: String,
+) {
+    let Some(audit) = state.audit.as_ref() else {
+        return;
+    };
+
+    let event = AuditService::signing_key_event(
+        UserInfo::from_user(user),
+        event_type,
+        key_id.to_string(),
+        operation,
+        method,
+        path,
+    );
+    if let Err(e) = audit.log(event).await {
+        error!("Failed to record audit event for signing key {}: {}", key_id, e);
+    }
+}
@@ -1,18 +1,23 @@
 use anyhow::Result;
+use arc_swap::ArcSwap;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock};
+use tokio_util::sync::{CancellationToken, DropGuard};
 use tracing::{debug, error, info, warn};
 
-use crate::config::AuditConfig;
+use crate::config::{AuditConfig, ElasticsearchExportConfig, FileExportConfig, WebhookExportConfig};
 use crate::storage::StorageBackend;
 
 /// Comprehensive audit logging system for drift registry
 #[derive(Clone)]
 pub struct AuditService {
-    config: AuditConfig,
+    /// Replaced on config reload
+    config: Arc<ArcSwap<AuditConfig>>,
     storage: Arc<dyn StorageBackend>,
     buffer: Arc<RwLock<Vec<AuditEvent>>>,
     exporters: Arc<RwLock<Vec<Box<dyn AuditExporter>>>>,
@@ -45,6 +50,11 @@ pub enum EventType {
     TokenRevoked,
     AuthenticationFailed,
 
+    // Robot account events
+    RobotCreated,
+    RobotRevoked,
+    RobotAuthenticated,
+
     // Authorization events
     PermissionGranted,
     PermissionDenied,
@@ -59,6 +69,7 @@ pub enum EventType {
     ManifestDeleted,
     BlobUploaded,
     BlobDeleted,
+    RepositoryDeleted,
 
     // Administrative events
     UserCreated,
@@ -73,8 +84,13 @@ pub enum EventType {
 
     // Security events
     SignatureCreated,
+    SignatureRevoked,
     SignatureVerified,
     SignatureInvalid,
+    SigningKeyCreated,
+    SigningKeyRetired,
+    SigningKeyDeleted,
+    SigningKeyExpiring,
     QuotaExceeded,
     RateLimitExceeded,
     SuspiciousActivity,
@@ -87,6 +103,7 @@ pub enum EventType {
     RestoreCompleted,
     GarbageCollectionRun,
     OptimizationRun,
+    ReplicationFailed,
 
     // Custom events
     Custom(String),
@@ -114,6 +131,21 @@ pub struct UserInfo {
     pub service_account: bool,
 }
 
+impl UserInfo {
+    /// Audit identity for an authenticated registry user, or anonymous
+    pub fn from_user(user: Option<&crate::auth::User>) -> Self {
+        Self {
+            id: None,
+            username: user.map(|u| u.username.clone()),
+            email: None,
+            organization: None,
+            teams: vec![],
+            roles: user.map(|u| u.roles.clone()).unwrap_or_default(),
+            service_account: user.is_some_and(|u| u.username.starts_with(crate::auth::robots::ROBOT_PREFIX)),
+        }
+    }
+}
+
 /// Resource information in audit events
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResourceInfo {
@@ -158,6 +190,43 @@ pub struct NetworkInfo {
     pub request_id: Option<String>,
 }
 
+/// The HTTP request being served, set by `request_id_middleware`. Events logged
+/// while handling it share its id as their correlation id.
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    pub request_id: String,
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+tokio::task_local! {
+    static REQUEST_CONTEXT: RequestContext;
+}
+
+impl RequestContext {
+    /// Run `future` with this as the current request
+    pub async fn scope<F: std::future::Future>(self, future: F) -> F::Output {
+        REQUEST_CONTEXT.scope(self, future).await
+    }
+
+    /// The request being served by the current task, if any
+    pub fn current() -> Option<Self> {
+        REQUEST_CONTEXT.try_with(Clone::clone).ok()
+    }
+
+    /// Fill in the request details an event builder left empty
+    fn apply(&self, event: &mut AuditEvent) {
+        event.correlation_id.get_or_insert_with(|| self.request_id.clone());
+        event.network.request_id.get_or_insert_with(|| self.request_id.clone());
+        if event.network.client_ip.is_none() {
+            event.network.client_ip = self.client_ip.clone();
+        }
+        if event.network.user_agent.is_none() {
+            event.network.user_agent = self.user_agent.clone();
+        }
+    }
+}
+
 /// Trait for audit event exporters
 #[async_trait]
 pub trait AuditExporter: Send + Sync {
@@ -167,10 +236,22 @@ pub trait AuditExporter: Send + Sync {
 
 /// File-based audit exporter
 pub struct FileExporter {
-    path: String,
+    path: PathBuf,
     format: ExportFormat,
+    /// Rotate once the active file reaches this size; 0 never rotates
+    rotation_size_bytes: u64,
+    /// Held while writing or rotating, so a flush never writes to a file being renamed
+    write_lock: Mutex<()>,
+    /// Stops the prune task when the exporter is replaced
+    _prune_task: Option<DropGuard>,
 }
 
+/// Suffix of rotated files, e.g. `audit.jsonl.20260101T120000.000Z`
+const ROTATED_SUFFIX_FORMAT: &str = "%Y%m%dT%H%M%S%.3fZ";
+
+/// How often rotated files are checked against `retention_days`
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
 /// Export formats
 #[derive(Debug, Clone)]
 pub enum ExportFormat {
@@ -180,11 +261,81 @@ pub enum ExportFormat {
     Syslog,
 }
 
+impl std::str::FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(format: &str) -> Result<Self> {
+        match format.to_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "jsonlines" | "jsonl" | "ndjson" => Ok(Self::JsonLines),
+            "csv" => Ok(Self::Csv),
+            "syslog" => Ok(Self::Syslog),
+            other => Err(anyhow::anyhow!(
+                "Unknown audit export format \"{}\" (expected json, jsonlines, csv or syslog)",
+                other
+            )),
+        }
+    }
+}
+
+/// Columns of the CSV export, in order. Append new columns at the end so
+/// existing files keep parsing.
+const CSV_COLUMNS: [&str; 23] = [
+    "id",
+    "timestamp",
+    "event_type",
+    "severity",
+    "username",
+    "organization",
+    "service_account",
+    "resource_type",
+    "resource_id",
+    "repository",
+    "tag",
+    "digest",
+    "operation",
+    "method",
+    "path",
+    "success",
+    "status_code",
+    "error_code",
+    "error_message",
+    "client_ip",
+    "user_agent",
+    "request_id",
+    "correlation_id",
+];
+
+/// RFC 5424 facility 13, "log audit"
+const SYSLOG_FACILITY_LOG_AUDIT: u8 = 13;
+
+/// MSGID is limited to 32 printable characters
+const SYSLOG_MSGID_MAX_LEN: usize = 32;
+
+/// Delay before the first retry of a failed webhook batch; doubles with each attempt
+const WEBHOOK_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Upper bound on the delay between webhook retries
+const WEBHOOK_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
 /// Webhook audit exporter
 pub struct WebhookExporter {
     url: String,
     headers: HashMap<String, String>,
     timeout_seconds: u64,
+    /// Retries of a failed batch after the first attempt
+    retry_attempts: u32,
+    /// Events per POST
+    batch_size: usize,
+    client: reqwest::Client,
+}
+
+/// A failed webhook delivery, and whether trying again could help
+enum WebhookError {
+    /// Timeouts, connection failures and 5xx responses
+    Retryable(anyhow::Error),
+    /// 4xx responses; the endpoint won't accept the batch however often it's sent
+    Permanent(anyhow::Error),
 }
 
 /// Elasticsearch audit exporter
@@ -193,6 +344,9 @@ pub struct ElasticsearchExporter {
     index_prefix: String,
     username: Option<String>,
     password: Option<String>,
+    /// Events per `_bulk` request
+    batch_size: usize,
+    client: reqwest::Client,
 }
 
 /// Audit query parameters
@@ -231,7 +385,7 @@ impl AuditService {
         info!("Initializing audit service");
 
         let service = Self {
-            config,
+            config: Arc::new(ArcSwap::from_pointee(config)),
             storage,
             buffer: Arc::new(RwLock::new(Vec::new())),
             exporters: Arc::new(RwLock::new(Vec::new())),
@@ -247,38 +401,39 @@ impl AuditService {
         Ok(service)
     }
 
-    /// Initialize configured exporters
+    /// Apply reloaded settings: filters, buffering and exporters
+    pub async fn reconfigure(&self, config: AuditConfig) -> Result<()> {
+        let previous = self.config.swap(Arc::new(config));
+        if let Err(e) = self.initialize_exporters().await {
+            // Keep the previous exporters and the settings that go with them
+            self.config.store(previous);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Initialize configured exporters, replacing any existing ones
     async fn initialize_exporters(&self) -> Result<()> {
-        let mut exporters = self.exporters.write().await;
+        let config = self.config.load();
+        let mut exporters: Vec<Box<dyn AuditExporter>> = Vec::new();
 
         // File exporter
-        if let Some(file_config) = &self.config.file_export {
-            exporters.push(Box::new(FileExporter {
-                path: file_config.path.clone(),
-                format: ExportFormat::JsonLines,
-            }));
+        if let Some(file_config) = &config.file_export {
+            exporters.push(Box::new(FileExporter::new(file_config)?));
         }
 
         // Webhook exporter
-        if let Some(webhook_config) = &self.config.webhook_export {
-            exporters.push(Box::new(WebhookExporter {
-                url: webhook_config.url.clone(),
-                headers: webhook_config.headers.clone(),
-                timeout_seconds: webhook_config.timeout_seconds,
-            }));
+        if let Some(webhook_config) = &config.webhook_export {
+            exporters.push(Box::new(WebhookExporter::new(webhook_config)));
         }
 
         // Elasticsearch exporter
-        if let Some(es_config) = &self.config.elasticsearch_export {
-            exporters.push(Box::new(ElasticsearchExporter {
-                url: es_config.url.clone(),
-                index_prefix: es_config.index_prefix.clone(),
-                username: es_config.username.clone(),
-                password: es_config.password.clone(),
-            }));
+        if let Some(es_config) = &config.elasticsearch_export {
+            exporters.push(Box::new(ElasticsearchExporter::new(es_config)));
         }
 
         info!("Initialized {} audit exporters", exporters.len());
+        *self.exporters.write().await = exporters;
         Ok(())
     }
 
@@ -287,41 +442,59 @@ impl AuditService {
         let buffer = self.buffer.clone();
         let exporters = self.exporters.clone();
         let storage = self.storage.clone();
-        let flush_interval = self.config.flush_interval_seconds;
+        let config = self.config.clone();
 
         tokio::spawn(async move {
             loop {
+                let flush_interval = config.load().flush_interval_seconds;
                 tokio::time::sleep(tokio::time::Duration::from_secs(flush_interval)).await;
+                Self::flush_buffer(&buffer, &exporters, &storage).await;
+            }
+        });
+    }
 
-                let events = {
-                    let mut buf = buffer.write().await;
-                    std::mem::take(&mut *buf)
-                };
+    /// Export and store buffered events now, e.g. before the server exits
+    pub async fn flush(&self) {
+        Self::flush_buffer(&self.buffer, &self.exporters, &self.storage).await;
+    }
 
-                if !events.is_empty() {
-                    debug!("Flushing {} audit events", events.len());
+    async fn flush_buffer(
+        buffer: &RwLock<Vec<AuditEvent>>,
+        exporters: &RwLock<Vec<Box<dyn AuditExporter>>>,
+        storage: &Arc<dyn StorageBackend>,
+    ) {
+        let events = {
+            let mut buf = buffer.write().await;
+            std::mem::take(&mut *buf)
+        };
 
-                    // Export to all configured exporters
-                    let exporters = exporters.read().await;
-                    for exporter in exporters.iter() {
-                        if let Err(e) = exporter.export(&events).await {
-                            error!("Failed to export audit events via {}: {}", exporter.name(), e);
-                        }
-                    }
+        if events.is_empty() {
+            return;
+        }
+        debug!("Flushing {} audit events", events.len());
 
-                    // Store in primary storage
-                    if let Err(e) = Self::store_events(&storage, &events).await {
-                        error!("Failed to store audit events: {}", e);
-                    }
-                }
+        // Export to all configured exporters
+        let exporters = exporters.read().await;
+        for exporter in exporters.iter() {
+            if let Err(e) = exporter.export(&events).await {
+                error!("Failed to export audit events via {}: {}", exporter.name(), e);
             }
-        });
+        }
+
+        // Store in primary storage
+        if let Err(e) = Self::store_events(storage, &events).await {
+            error!("Failed to store audit events: {}", e);
+        }
     }
 
     /// Log an audit event
-    pub async fn log(&self, event: AuditEvent) -> Result<()> {
+    pub async fn log(&self, mut event: AuditEvent) -> Result<()> {
         debug!("Logging audit event: {:?}", event.event_type);
 
+        if let Some(context) = RequestContext::current() {
+            context.apply(&mut event);
+        }
+
         // Apply filtering rules
         if !self.should_log(&event) {
             return Ok(());
@@ -332,7 +505,7 @@ impl AuditService {
         buffer.push(event.clone());
 
         // Check if immediate flush is needed
-        if buffer.len() >= self.config.buffer_size || event.severity >= Severity::Error {
+        if buffer.len() >= self.config.load().buffer_size || event.severity >= Severity::Error {
             let events = std::mem::take(&mut *buffer);
             drop(buffer); // Release lock
 
@@ -353,8 +526,10 @@ impl AuditService {
 
     /// Check if event should be logged based on configuration
     fn should_log(&self, event: &AuditEvent) -> bool {
+        let config = self.config.load();
+
         // Check severity threshold
-        let min_severity = match self.config.min_severity.as_str() {
+        let min_severity = match config.min_severity.as_str() {
             "debug" => Severity::Debug,
             "info" => Severity::Info,
             "warning" => Severity::Warning,
@@ -367,16 +542,16 @@ impl AuditService {
         }
 
         // Check event type filters
-        if !self.config.enabled_event_types.is_empty() {
-            if !self.config.enabled_event_types.contains(&format!("{:?}", event.event_type)) {
-                return false;
-            }
+        if !config.enabled_event_types.is_empty()
+            && !config.enabled_event_types.contains(&format!("{:?}", event.event_type))
+        {
+            return false;
         }
 
         // Check exclusion patterns
-        for pattern in &self.config.exclude_patterns {
+        for pattern in &config.exclude_patterns {
             if event.resource.id.contains(pattern) ||
-               event.user.username.as_ref().map_or(false, |u| u.contains(pattern)) {
+               event.user.username.as_ref().is_some_and(|u| u.contains(pattern)) {
                 return false;
             }
         }
@@ -404,20 +579,15 @@ impl AuditService {
     pub async fn query(&self, query: AuditQuery) -> Result<Vec<AuditEvent>> {
         debug!("Querying audit events: {:?}", query);
 
-        let mut events = Vec::new();
-        let mut count = 0;
-        let limit = query.limit.unwrap_or(100);
-        let offset = query.offset.unwrap_or(0);
+        let events = Vec::new();
 
         // Build date range for scanning
         let start = query.start_time.unwrap_or_else(|| chrono::Utc::now() - chrono::Duration::days(7));
-        let end = query.end_time.unwrap_or_else(|| chrono::Utc::now());
+        let end = query.end_time.unwrap_or_else(chrono::Utc::now);
 
         // Scan storage for matching events
         let mut current = start.date_naive();
         while current <= end.date_naive() {
-            let prefix = format!("audit/{}/", current.format("%Y/%m/%d"));
-
             // In real implementation, would list and filter blobs
             debug!("Scanning audit events for date: {}", current);
 
@@ -429,7 +599,7 @@ impl AuditService {
 
     /// Get audit statistics
     pub async fn get_stats(&self, duration_hours: u64) -> AuditStats {
-        let since = chrono::Utc::now() - chrono::Duration::hours(duration_hours as i64);
+        debug!("Computing audit statistics for the last {} hours", duration_hours);
 
         // In real implementation, would calculate from stored events
         AuditStats {
@@ -488,6 +658,322 @@ impl AuditService {
         }
     }
 
+    /// Robot account lifecycle or usage, kept apart from human logins
+    pub fn robot_event(
+        user: UserInfo,
+        event_type: EventType,
+        robot: String,
+        method: &str,
+        path: String,
+    ) -> AuditEvent {
+        AuditEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now(),
+            event_type,
+            severity: Severity::Info,
+            user,
+            resource: ResourceInfo {
+                type_: "robot".to_string(),
+                id: robot.clone(),
+                name: Some(robot),
+                namespace: None,
+                repository: None,
+                tag: None,
+                digest: None,
+                size: None,
+            },
+            action: ActionInfo {
+                operation: method.to_lowercase(),
+                method: Some(method.to_string()),
+                path: Some(path),
+                parameters: HashMap::new(),
+            },
+            result: EventResult {
+                success: true,
+                status_code: None,
+                error_message: None,
+                error_code: None,
+                duration_ms: None,
+            },
+            network: NetworkInfo {
+                client_ip: None,
+                client_port: None,
+                server_ip: None,
+                server_port: None,
+                protocol: Some("HTTPS".to_string()),
+                user_agent: None,
+                request_id: None,
+            },
+            metadata: HashMap::new(),
+            correlation_id: None,
+        }
+    }
+
+    /// A signing or verification key generated, imported, retired or deleted through the admin API
+    pub fn signing_key_event(
+        user: UserInfo,
+        event_type: EventType,
+        key_id: String,
+        operation: &str,
+        method: &str,
+        path: String,
+    ) -> AuditEvent {
+        AuditEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now(),
+            event_type,
+            severity: Severity::Warning,
+            user,
+            resource: ResourceInfo {
+                type_: "signing_key".to_string(),
+                id: key_id,
+                name: None,
+                namespace: None,
+                repository: None,
+                tag: None,
+                digest: None,
+                size: None,
+            },
+            action: ActionInfo {
+                operation: operation.to_string(),
+                method: Some(method.to_string()),
+                path: Some(path),
+                parameters: HashMap::new(),
+            },
+            result: EventResult {
+                success: true,
+                status_code: None,
+                error_message: None,
+                error_code: None,
+                duration_ms: None,
+            },
+            network: NetworkInfo {
+                client_ip: None,
+                client_port: None,
+                server_ip: None,
+                server_port: None,
+                protocol: None,
+                user_agent: None,
+                request_id: None,
+            },
+            metadata: HashMap::new(),
+            correlation_id: None,
+        }
+    }
+
+    /// A signing key, or its certificate, close to expiring
+    pub fn signing_key_expiry_event(key_id: String, expires_at: chrono::DateTime<chrono::Utc>) -> AuditEvent {
+        let mut metadata = HashMap::new();
+        metadata.insert("expires_at".to_string(), serde_json::json!(expires_at));
+
+        AuditEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now(),
+            event_type: EventType::SigningKeyExpiring,
+            severity: if expires_at <= chrono::Utc::now() { Severity::Error } else { Severity::Warning },
+            user: UserInfo::from_user(None),
+            resource: ResourceInfo {
+                type_: "signing_key".to_string(),
+                id: key_id,
+                name: None,
+                namespace: None,
+                repository: None,
+                tag: None,
+                digest: None,
+                size: None,
+            },
+            action: ActionInfo {
+                operation: "expiry_check".to_string(),
+                method: None,
+                path: None,
+                parameters: HashMap::new(),
+            },
+            result: EventResult {
+                success: true,
+                status_code: None,
+                error_message: None,
+                error_code: None,
+                duration_ms: None,
+            },
+            network: NetworkInfo {
+                client_ip: None,
+                client_port: None,
+                server_ip: None,
+                server_port: None,
+                protocol: None,
+                user_agent: None,
+                request_id: None,
+            },
+            metadata,
+            correlation_id: None,
+        }
+    }
+
+    /// A signature created or revoked through the admin API
+    pub fn signature_event(
+        user: UserInfo,
+        event_type: EventType,
+        repository: String,
+        digest: String,
+        signature_id: String,
+        method: &str,
+        path: String,
+    ) -> AuditEvent {
+        AuditEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now(),
+            event_type,
+            severity: Severity::Info,
+            user,
+            resource: ResourceInfo {
+                type_: "signature".to_string(),
+                id: signature_id,
+                name: None,
+                namespace: None,
+                repository: Some(repository),
+                tag: None,
+                digest: Some(digest),
+                size: None,
+            },
+            action: ActionInfo {
+                operation: method.to_lowercase(),
+                method: Some(method.to_string()),
+                path: Some(path),
+                parameters: HashMap::new(),
+            },
+            result: EventResult {
+                success: true,
+                status_code: None,
+                error_message: None,
+                error_code: None,
+                duration_ms: None,
+            },
+            network: NetworkInfo {
+                client_ip: None,
+                client_port: None,
+                server_ip: None,
+                server_port: None,
+                protocol: None,
+                user_agent: None,
+                request_id: None,
+            },
+            metadata: HashMap::new(),
+            correlation_id: None,
+        }
+    }
+
+    /// Per-repository signature policies replaced through the admin API
+    pub fn signing_policy_event(
+        user: UserInfo,
+        previous: serde_json::Value,
+        repositories: serde_json::Value,
+    ) -> AuditEvent {
+        let mut metadata = HashMap::new();
+        metadata.insert("previous".to_string(), previous);
+        metadata.insert("repositories".to_string(), repositories);
+
+        AuditEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now(),
+            event_type: EventType::ConfigurationChanged,
+            severity: Severity::Warning,
+            user,
+            resource: ResourceInfo {
+                type_: "signing_policy".to_string(),
+                id: "signing.enforcement".to_string(),
+                name: None,
+                namespace: None,
+                repository: None,
+                tag: None,
+                digest: None,
+                size: None,
+            },
+            action: ActionInfo {
+                operation: "update".to_string(),
+                method: Some("PUT".to_string()),
+                path: Some("/api/v1/signing/policy".to_string()),
+                parameters: HashMap::new(),
+            },
+            result: EventResult {
+                success: true,
+                status_code: None,
+                error_message: None,
+                error_code: None,
+                duration_ms: None,
+            },
+            network: NetworkInfo {
+                client_ip: None,
+                client_port: None,
+                server_ip: None,
+                server_port: None,
+                protocol: None,
+                user_agent: None,
+                request_id: None,
+            },
+            metadata,
+            correlation_id: None,
+        }
+    }
+
+    /// Registry policy decision on a manifest write or delete
+    pub fn manifest_policy_event(
+        user: UserInfo,
+        event_type: EventType,
+        repository: String,
+        reference: String,
+        method: &str,
+        success: bool,
+        error_message: Option<String>,
+    ) -> AuditEvent {
+        let (tag, digest) = if reference.starts_with("sha256:") {
+            (None, Some(reference.clone()))
+        } else {
+            (Some(reference.clone()), None)
+        };
+
+        AuditEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now(),
+            event_type,
+            severity: if success { Severity::Info } else { Severity::Warning },
+            user,
+            resource: ResourceInfo {
+                type_: "manifest".to_string(),
+                id: format!("{}:{}", repository, reference),
+                name: Some(repository.clone()),
+                namespace: None,
+                repository: Some(repository),
+                tag,
+                digest,
+                size: None,
+            },
+            action: ActionInfo {
+                operation: method.to_lowercase(),
+                method: Some(method.to_string()),
+                path: Some("/v2/{name}/manifests/{reference}".to_string()),
+                parameters: HashMap::new(),
+            },
+            result: EventResult {
+                success,
+                status_code: None,
+                error_message,
+                error_code: if success { None } else { Some("DENIED".to_string()) },
+                duration_ms: None,
+            },
+            network: NetworkInfo {
+                client_ip: None,
+                client_port: None,
+                server_ip: None,
+                server_port: None,
+                protocol: Some("HTTPS".to_string()),
+                user_agent: None,
+                request_id: None,
+            },
+            metadata: HashMap::new(),
+            correlation_id: None,
+        }
+    }
+
     pub fn image_pull_event(user: UserInfo, repository: String, tag: String, digest: String, success: bool) -> AuditEvent {
         AuditEvent {
             id: uuid::Uuid::new_v4().to_string(),
@@ -531,6 +1017,206 @@ impl AuditService {
             correlation_id: None,
         }
     }
+
+    /// A whole repository was deleted through the admin API, with what it removed
+    pub fn repository_deleted_event(
+        user: UserInfo,
+        repository: String,
+        totals: HashMap<String, serde_json::Value>,
+        error_message: Option<String>,
+    ) -> AuditEvent {
+        let success = error_message.is_none();
+
+        AuditEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now(),
+            event_type: EventType::RepositoryDeleted,
+            severity: if success { Severity::Warning } else { Severity::Error },
+            user,
+            resource: ResourceInfo {
+                type_: "repository".to_string(),
+                id: repository.clone(),
+                name: Some(repository.clone()),
+                namespace: None,
+                repository: Some(repository),
+                tag: None,
+                digest: None,
+                size: None,
+            },
+            action: ActionInfo {
+                operation: "delete".to_string(),
+                method: Some("DELETE".to_string()),
+                path: Some("/api/v1/repositories/{name}".to_string()),
+                parameters: HashMap::new(),
+            },
+            result: EventResult {
+                success,
+                status_code: None,
+                error_message,
+                error_code: None,
+                duration_ms: None,
+            },
+            network: NetworkInfo {
+                client_ip: None,
+                client_port: None,
+                server_ip: None,
+                server_port: None,
+                protocol: None,
+                user_agent: None,
+                request_id: None,
+            },
+            metadata: totals,
+            correlation_id: None,
+        }
+    }
+
+    /// A failed attempt to copy a pushed manifest to a replication target
+    pub fn replication_failure_event(
+        target: &str,
+        repository: String,
+        reference: String,
+        digest: String,
+        attempts: u32,
+        error_message: String,
+        abandoned: bool,
+    ) -> AuditEvent {
+        let mut metadata = HashMap::new();
+        metadata.insert("target".to_string(), serde_json::json!(target));
+        metadata.insert("attempts".to_string(), serde_json::json!(attempts));
+        metadata.insert("abandoned".to_string(), serde_json::json!(abandoned));
+
+        AuditEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now(),
+            event_type: EventType::ReplicationFailed,
+            severity: if abandoned { Severity::Error } else { Severity::Warning },
+            user: UserInfo::from_user(None),
+            resource: ResourceInfo {
+                type_: "manifest".to_string(),
+                id: format!("{}:{}", repository, reference),
+                name: Some(repository.clone()),
+                namespace: None,
+                repository: Some(repository),
+                tag: Some(reference),
+                digest: Some(digest),
+                size: None,
+            },
+            action: ActionInfo {
+                operation: "replicate".to_string(),
+                method: None,
+                path: None,
+                parameters: HashMap::new(),
+            },
+            result: EventResult {
+                success: false,
+                status_code: None,
+                error_message: Some(error_message),
+                error_code: None,
+                duration_ms: None,
+            },
+            network: NetworkInfo {
+                client_ip: None,
+                client_port: None,
+                server_ip: None,
+                server_port: None,
+                protocol: None,
+                user_agent: None,
+                request_id: None,
+            },
+            metadata,
+            correlation_id: None,
+        }
+    }
+}
+
+impl FileExporter {
+    /// Create the exporter and start pruning rotated files older than `retention_days`
+    pub fn new(config: &FileExportConfig) -> Result<Self> {
+        let path = PathBuf::from(&config.path);
+
+        let prune_task = (config.retention_days > 0).then(|| {
+            let token = CancellationToken::new();
+            let retention = chrono::Duration::days(config.retention_days as i64);
+            let path = path.clone();
+            let cancelled = token.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+                loop {
+                    tokio::select! {
+                        _ = cancelled.cancelled() => break,
+                        _ = interval.tick() => {}
+                    }
+                    match prune_rotated_files(&path, retention).await {
+                        Ok(0) => {}
+                        Ok(count) => info!("Pruned {} rotated audit logs of {}", count, path.display()),
+                        Err(e) => warn!("Failed to prune rotated audit logs of {}: {}", path.display(), e),
+                    }
+                }
+            });
+            token.drop_guard()
+        });
+
+        Ok(Self {
+            path,
+            format: config.format.parse()?,
+            rotation_size_bytes: config.rotation_size_mb * 1024 * 1024,
+            write_lock: Mutex::new(()),
+            _prune_task: prune_task,
+        })
+    }
+
+    /// The file events are currently appended to
+    pub fn active_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Move the active file aside under a timestamped name; the next write starts a new one
+    async fn rotate(&self) -> Result<PathBuf> {
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(format!(".{}", chrono::Utc::now().format(ROTATED_SUFFIX_FORMAT)));
+        let rotated = PathBuf::from(rotated);
+
+        tokio::fs::rename(&self.path, &rotated).await?;
+        info!("Rotated audit log {} to {}", self.path.display(), rotated.display());
+        Ok(rotated)
+    }
+}
+
+/// Delete rotated copies of `path` whose rotation timestamp is older than `retention`.
+/// Files that don't carry a rotation timestamp are left alone.
+async fn prune_rotated_files(path: &Path, retention: chrono::Duration) -> Result<usize> {
+    let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+        return Ok(0);
+    };
+    let prefix = format!("{}.", file_name);
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let cutoff = chrono::Utc::now() - retention;
+
+    let mut pruned = 0;
+    let mut entries = match tokio::fs::read_dir(directory).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        let Some(suffix) = name.to_str().and_then(|name| name.strip_prefix(&prefix)) else {
+            continue;
+        };
+        let Ok(rotated_at) = chrono::NaiveDateTime::parse_from_str(suffix, ROTATED_SUFFIX_FORMAT) else {
+            continue;
+        };
+        if rotated_at.and_utc() < cutoff {
+            tokio::fs::remove_file(entry.path()).await?;
+            debug!("Pruned rotated audit log {}", entry.path().display());
+            pruned += 1;
+        }
+    }
+
+    Ok(pruned)
 }
 
 #[async_trait]
@@ -538,23 +1224,43 @@ impl AuditExporter for FileExporter {
     async fn export(&self, events: &[AuditEvent]) -> Result<()> {
         use tokio::io::AsyncWriteExt;
 
+        let _write = self.write_lock.lock().await;
+
+        if self.rotation_size_bytes > 0 {
+            match tokio::fs::metadata(&self.path).await {
+                Ok(metadata) if metadata.len() >= self.rotation_size_bytes => {
+                    self.rotate().await?;
+                }
+                _ => {}
+            }
+        }
+
         let mut file = tokio::fs::OpenOptions::new()
             .create(true)
             .append(true)
             .open(&self.path)
             .await?;
 
+        // A new CSV file starts with the header row
+        if matches!(self.format, ExportFormat::Csv) && file.metadata().await?.len() == 0 {
+            file.write_all(csv_row(CSV_COLUMNS.iter().map(|column| column.to_string())).as_bytes())
+                .await?;
+        }
+
         for event in events {
             let line = match self.format {
+                ExportFormat::Json => {
+                    let mut json = serde_json::to_string_pretty(event)?;
+                    json.push('\n');
+                    json
+                }
                 ExportFormat::JsonLines => {
                     let mut json = serde_json::to_string(event)?;
                     json.push('\n');
                     json
                 }
-                _ => {
-                    // Other formats not implemented yet
-                    continue;
-                }
+                ExportFormat::Csv => csv_row(csv_fields(event)),
+                ExportFormat::Syslog => syslog_line(event)?,
             };
 
             file.write_all(line.as_bytes()).await?;
@@ -565,26 +1271,26 @@ impl AuditExporter for FileExporter {
     }
 
     fn name(&self) -> String {
-        format!("FileExporter({})", self.path)
+        format!("FileExporter({})", self.path.display())
     }
 }
 
 #[async_trait]
 impl AuditExporter for WebhookExporter {
+    /// Send the events in batches of `batch_size`. A batch that still fails after
+    /// its retries is dropped and the rest are still sent.
     async fn export(&self, events: &[AuditEvent]) -> Result<()> {
-        let client = reqwest::Client::new();
-
-        let response = client
-            .post(&self.url)
-            .timeout(std::time::Duration::from_secs(self.timeout_seconds))
-            .json(events)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!("Webhook export failed: {}", response.status()));
+        let mut dropped = 0;
+        for batch in events.chunks(self.batch_size) {
+            if let Err(e) = self.deliver(batch).await {
+                error!("Dropping {} audit events that couldn't be sent to {}: {:#}", batch.len(), self.url, e);
+                dropped += batch.len();
+            }
         }
 
+        if dropped > 0 {
+            return Err(anyhow::anyhow!("Webhook export dropped {} of {} events", dropped, events.len()));
+        }
         Ok(())
     }
 
@@ -596,8 +1302,9 @@ impl AuditExporter for WebhookExporter {
 #[async_trait]
 impl AuditExporter for ElasticsearchExporter {
     async fn export(&self, events: &[AuditEvent]) -> Result<()> {
-        // Simplified Elasticsearch export
-        warn!("Elasticsearch export not fully implemented");
+        for batch in events.chunks(self.batch_size) {
+            self.export_batch(batch).await?;
+        }
         Ok(())
     }
 
@@ -606,8 +1313,246 @@ impl AuditExporter for ElasticsearchExporter {
     }
 }
 
-impl ToString for EventType {
-    fn to_string(&self) -> String {
-        format!("{:?}", self)
+impl WebhookExporter {
+    pub fn new(config: &WebhookExportConfig) -> Self {
+        Self {
+            url: config.url.clone(),
+            headers: config.headers.clone(),
+            timeout_seconds: config.timeout_seconds,
+            retry_attempts: config.retry_attempts,
+            batch_size: config.batch_size.max(1),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// POST a batch, retrying retryable failures with exponential backoff
+    async fn deliver(&self, batch: &[AuditEvent]) -> Result<()> {
+        let mut backoff = WEBHOOK_INITIAL_BACKOFF;
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+            let error = match self.send(batch).await {
+                Ok(()) => return Ok(()),
+                Err(WebhookError::Permanent(e)) => return Err(e),
+                Err(WebhookError::Retryable(e)) => e,
+            };
+
+            if attempt > self.retry_attempts {
+                return Err(error.context(format!("giving up after {} attempts", attempt)));
+            }
+
+            warn!(
+                "Failed to send {} audit events to {} (attempt {}), retrying in {:?}: {:#}",
+                batch.len(), self.url, attempt, backoff, error
+            );
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(WEBHOOK_MAX_BACKOFF);
+        }
+    }
+
+    async fn send(&self, batch: &[AuditEvent]) -> Result<(), WebhookError> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .timeout(Duration::from_secs(self.timeout_seconds))
+            .json(batch);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+
+        let response = request.send().await.map_err(|e| {
+            if e.is_builder() {
+                WebhookError::Permanent(e.into())
+            } else {
+                WebhookError::Retryable(e.into())
+            }
+        })?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+
+        let error = anyhow::anyhow!("webhook returned {}", status);
+        if status.is_client_error() {
+            Err(WebhookError::Permanent(error))
+        } else {
+            Err(WebhookError::Retryable(error))
+        }
+    }
+}
+
+impl ElasticsearchExporter {
+    pub fn new(config: &ElasticsearchExportConfig) -> Self {
+        Self {
+            url: config.url.clone(),
+            index_prefix: config.index_prefix.clone(),
+            username: config.username.clone(),
+            password: config.password.clone(),
+            batch_size: config.batch_size.max(1),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Daily index the event belongs to, e.g. `drift-audit-2026.01.31`
+    fn index_name(&self, event: &AuditEvent) -> String {
+        format!("{}-{}", self.index_prefix, event.timestamp.format("%Y.%m.%d"))
+    }
+
+    /// Newline-delimited `_bulk` body: an index action followed by the document, for each event.
+    /// The event id is the document id, so a retried batch doesn't duplicate events.
+    fn bulk_body(&self, events: &[AuditEvent]) -> Result<String> {
+        let mut body = String::new();
+        for event in events {
+            let action = serde_json::json!({ "index": { "_index": self.index_name(event), "_id": event.id } });
+            body.push_str(&serde_json::to_string(&action)?);
+            body.push('\n');
+            body.push_str(&serde_json::to_string(event)?);
+            body.push('\n');
+        }
+        Ok(body)
+    }
+
+    async fn export_batch(&self, events: &[AuditEvent]) -> Result<()> {
+        let mut request = self
+            .client
+            .post(format!("{}/_bulk", self.url.trim_end_matches('/')))
+            .header(reqwest::header::CONTENT_TYPE, "application/x-ndjson")
+            .body(self.bulk_body(events)?);
+        if let Some(username) = &self.username {
+            request = request.basic_auth(username, self.password.as_ref());
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Elasticsearch bulk export failed: {}", response.status()));
+        }
+
+        // A 200 can still carry per-document failures; the other documents were indexed
+        let result: serde_json::Value = response.json().await?;
+        if result["errors"].as_bool() != Some(true) {
+            return Ok(());
+        }
+
+        let failures: Vec<String> = result["items"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|item| item.get("index"))
+            .filter(|action| action.get("error").is_some())
+            .map(|action| {
+                format!(
+                    "{} ({}: {})",
+                    action["_id"].as_str().unwrap_or("?"),
+                    action["status"],
+                    action["error"]["reason"].as_str().unwrap_or("unknown error"),
+                )
+            })
+            .collect();
+        warn!(
+            "Elasticsearch rejected {} of {} audit events: {}",
+            failures.len(),
+            events.len(),
+            failures.join(", ")
+        );
+
+        Ok(())
+    }
+}
+
+/// One event flattened into the values of `CSV_COLUMNS`
+fn csv_fields(event: &AuditEvent) -> [String; CSV_COLUMNS.len()] {
+    let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+    [
+        event.id.clone(),
+        event.timestamp.to_rfc3339(),
+        event.event_type.to_string(),
+        format!("{:?}", event.severity),
+        optional(&event.user.username),
+        optional(&event.user.organization),
+        event.user.service_account.to_string(),
+        event.resource.type_.clone(),
+        event.resource.id.clone(),
+        optional(&event.resource.repository),
+        optional(&event.resource.tag),
+        optional(&event.resource.digest),
+        event.action.operation.clone(),
+        optional(&event.action.method),
+        optional(&event.action.path),
+        event.result.success.to_string(),
+        event.result.status_code.map(|code| code.to_string()).unwrap_or_default(),
+        optional(&event.result.error_code),
+        optional(&event.result.error_message),
+        optional(&event.network.client_ip),
+        optional(&event.network.user_agent),
+        optional(&event.network.request_id),
+        optional(&event.correlation_id),
+    ]
+}
+
+/// A CSV record (RFC 4180): fields containing separators, quotes or line
+/// breaks are quoted, with embedded quotes doubled
+fn csv_row(fields: impl IntoIterator<Item = String>) -> String {
+    let mut row = fields
+        .into_iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    row.push_str("\r\n");
+    row
+}
+
+/// An RFC 5424 syslog line: `<PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID - MSG`,
+/// with the event as JSON in MSG
+fn syslog_line(event: &AuditEvent) -> Result<String> {
+    let priority = SYSLOG_FACILITY_LOG_AUDIT * 8 + syslog_severity(&event.severity);
+    let hostname = std::env::var("HOSTNAME")
+        .ok()
+        .map(|hostname| syslog_header_field(&hostname, 255))
+        .unwrap_or_else(|| "-".to_string());
+    let msgid = syslog_header_field(&event.event_type.to_string(), SYSLOG_MSGID_MAX_LEN);
+
+    Ok(format!(
+        "<{}>1 {} {} drift {} {} - {}\n",
+        priority,
+        event.timestamp.to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
+        hostname,
+        std::process::id(),
+        msgid,
+        serde_json::to_string(event)?
+    ))
+}
+
+/// Syslog severity code for an audit severity
+fn syslog_severity(severity: &Severity) -> u8 {
+    match severity {
+        Severity::Critical => 2,
+        Severity::Error => 3,
+        Severity::Warning => 4,
+        Severity::Info => 6,
+        Severity::Debug => 7,
+    }
+}
+
+/// Header fields are printable ASCII without spaces, up to a maximum length
+fn syslog_header_field(value: &str, max_len: usize) -> String {
+    let field: String = value
+        .chars()
+        .map(|c| if c.is_ascii_graphic() { c } else { '_' })
+        .take(max_len)
+        .collect();
+    if field.is_empty() { "-".to_string() } else { field }
+}
+
+impl std::fmt::Display for EventType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
     }
 }
\ No newline at end of file
@@ -2,7 +2,7 @@ use crate::auth::{User, AuthToken};
 use anyhow::Result;
 use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
 
-pub fn generate_token(secret: &str, user: &User, expires_in: u64) -> Result<String> {
+pub fn generate_token(secret: &str, user: &User, expires_in: u64, audience: Option<&str>) -> Result<String> {
     let now = chrono::Utc::now().timestamp() as u64;
     let exp = now + expires_in;
 
@@ -10,6 +10,7 @@ pub fn generate_token(secret: &str, user: &User, expires_in: u64) -> Result<Stri
         user: user.clone(),
         exp,
         iat: now,
+        aud: audience.map(str::to_string),
     };
 
     let header = Header::new(Algorithm::HS256);
@@ -19,15 +20,18 @@ pub fn generate_token(secret: &str, user: &User, expires_in: u64) -> Result<Stri
     Ok(token)
 }
 
-pub fn validate_token(secret: &str, token: &str) -> Result<Option<User>> {
+/// Decode a token, or None if it is invalid or expired
+pub fn decode_token(secret: &str, token: &str) -> Result<Option<AuthToken>> {
     let key = DecodingKey::from_secret(secret.as_ref());
-    let validation = Validation::new(Algorithm::HS256);
+    let mut validation = Validation::new(Algorithm::HS256);
+    // The audience only tells registry tokens apart; callers check it themselves
+    validation.validate_aud = false;
 
     match decode::<AuthToken>(token, &key, &validation) {
         Ok(token_data) => {
             let now = chrono::Utc::now().timestamp() as u64;
             if token_data.claims.exp > now {
-                Ok(Some(token_data.claims.user))
+                Ok(Some(token_data.claims))
             } else {
                 Ok(None) // Token expired
             }
@@ -1,12 +1,20 @@
 use crate::config::{AuthConfig, AuthMode};
+use crate::tls::ClientCertificate;
 use anyhow::Result;
+use password::StoredPassword;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
 
 pub mod basic;
 pub mod jwt;
 pub mod oidc;
 pub mod oauth;
+pub mod password;
+pub mod robots;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
@@ -15,89 +23,344 @@ pub struct User {
     pub scopes: Vec<String>,
 }
 
+impl User {
+    /// The caller behind an unauthenticated pull, when anonymous pulls are allowed
+    pub fn anonymous() -> Self {
+        Self {
+            username: "anonymous".to_string(),
+            roles: Vec::new(),
+            scopes: vec!["repository:*:pull".to_string()],
+        }
+    }
+
+    pub fn is_anonymous(&self) -> bool {
+        self.username == "anonymous"
+    }
+
+    /// Robot accounts and client certificate identities, which have no RBAC
+    /// user record and are authorized by their own scopes alone
+    pub fn is_service_principal(&self) -> bool {
+        self.roles.iter().any(|role| role == "robot" || role == "certificate")
+    }
+}
+
+/// Audience of tokens issued by the registry token endpoint. Their scopes
+/// were filtered through RBAC when they were issued; other drift tokens, such
+/// as UI logins, carry the user's unfiltered scopes.
+pub const REGISTRY_TOKEN_AUDIENCE: &str = "drift-registry";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthToken {
     pub user: User,
     pub exp: u64,
     pub iat: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
 }
 
+/// A user authenticated by a bearer token
+#[derive(Debug, Clone)]
+pub struct BearerUser {
+    pub user: User,
+    /// Issued by the registry token endpoint, so its scopes have already passed RBAC
+    pub registry_token: bool,
+}
+
+/// Request extension marking a request authenticated with a registry token
+#[derive(Debug, Clone, Copy)]
+pub struct RegistryToken;
+
 pub struct AuthService {
     mode: AuthMode,
     jwt_secret: String,
-    users: HashMap<String, String>, // username -> password hash
+    users: RwLock<HashMap<String, StoredPassword>>,
+    htpasswd_path: Option<PathBuf>,
+    htpasswd_users: RwLock<HashMap<String, StoredPassword>>,
+    htpasswd_reload_interval: Duration,
+    allow_plaintext_passwords: bool,
+    oidc: Option<oidc::OidcTokenValidator>,
+    robots: Option<Arc<robots::RobotService>>,
 }
 
 impl AuthService {
     pub fn new(config: &AuthConfig) -> Result<Self> {
         let mut users = HashMap::new();
+        let mut htpasswd_path = None;
+        let mut htpasswd_users = HashMap::new();
+        let mut htpasswd_reload_interval = Duration::from_secs(5);
+        let mut allow_plaintext_passwords = false;
 
         if let Some(basic_config) = &config.basic {
-            for user_entry in &basic_config.users {
-                if let Some((username, password)) = user_entry.split_once(':') {
-                    // In production, passwords should be hashed
-                    users.insert(username.to_string(), password.to_string());
-                }
+            allow_plaintext_passwords = basic_config.allow_plaintext_passwords;
+            htpasswd_reload_interval = Duration::from_secs(basic_config.htpasswd_reload_interval_secs.max(1));
+            users = password::parse_entries(
+                basic_config.users.iter().map(String::as_str),
+                allow_plaintext_passwords,
+            )?;
+
+            if let Some(path) = &basic_config.htpasswd_path {
+                let path = PathBuf::from(path);
+                htpasswd_users = password::load_htpasswd(&path, allow_plaintext_passwords)?;
+                info!("Loaded {} users from {}", htpasswd_users.len(), path.display());
+                htpasswd_path = Some(path);
             }
         }
 
+        let oidc = match (&config.mode, &config.oidc) {
+            (AuthMode::Oidc, Some(oidc_config)) => Some(oidc::OidcTokenValidator::new(oidc_config.clone())),
+            (AuthMode::Oidc, None) => {
+                return Err(anyhow::anyhow!("auth.mode = \"oidc\" requires an [auth.oidc] section"));
+            }
+            _ => None,
+        };
+
         Ok(Self {
             mode: config.mode.clone(),
             jwt_secret: config.jwt_secret.clone(),
-            users,
+            users: RwLock::new(users),
+            htpasswd_path,
+            htpasswd_users: RwLock::new(htpasswd_users),
+            htpasswd_reload_interval,
+            allow_plaintext_passwords,
+            oidc,
+            robots: None,
         })
     }
 
+    /// Accept `robot$<name>` credentials backed by the given robot accounts
+    pub fn with_robots(mut self, robots: Arc<robots::RobotService>) -> Self {
+        self.robots = Some(robots);
+        self
+    }
+
+    /// Reload the htpasswd file when its modification time changes, or on SIGHUP
+    pub fn spawn_htpasswd_reload(self: &Arc<Self>) {
+        let Some(path) = self.htpasswd_path.clone() else {
+            return;
+        };
+
+        let service = Arc::clone(self);
+        tokio::spawn(async move {
+            let modified = |path: &PathBuf| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+            let mut last_modified: Option<SystemTime> = modified(&path);
+            let mut interval = tokio::time::interval(service.htpasswd_reload_interval);
+
+            #[cfg(unix)]
+            let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+                Ok(signal) => Some(signal),
+                Err(e) => {
+                    warn!("Failed to listen for SIGHUP, htpasswd reloads on change only: {}", e);
+                    None
+                }
+            };
+
+            loop {
+                #[cfg(unix)]
+                let forced = tokio::select! {
+                    _ = interval.tick() => false,
+                    Some(()) = async {
+                        match hangup.as_mut() {
+                            Some(signal) => signal.recv().await,
+                            None => std::future::pending().await,
+                        }
+                    } => true,
+                };
+                #[cfg(not(unix))]
+                let forced = {
+                    interval.tick().await;
+                    false
+                };
+
+                let current = modified(&path);
+                if !forced && (current.is_none() || current == last_modified) {
+                    continue;
+                }
+                last_modified = current;
+
+                // Keep serving the previous users if the new file is invalid
+                match password::load_htpasswd(&path, service.allow_plaintext_passwords) {
+                    Ok(users) => {
+                        info!("Reloaded {} users from {}", users.len(), path.display());
+                        *service.htpasswd_users.write().unwrap() = users;
+                    }
+                    Err(e) => warn!("Failed to reload htpasswd file: {}", e),
+                }
+            }
+        });
+    }
+
+    /// Replace the users from `auth.basic.users`, e.g. after a config reload.
+    /// The current users stay in place if any entry is invalid.
+    pub fn reload_users(&self, config: &AuthConfig) -> Result<usize> {
+        let users = match &config.basic {
+            Some(basic_config) => password::parse_entries(
+                basic_config.users.iter().map(String::as_str),
+                self.allow_plaintext_passwords,
+            )?,
+            None => HashMap::new(),
+        };
+
+        let count = users.len();
+        *self.users.write().unwrap() = users;
+        Ok(count)
+    }
+
+    fn stored_password(&self, username: &str) -> Option<StoredPassword> {
+        if let Some(stored_password) = self.htpasswd_users.read().unwrap().get(username) {
+            return Some(stored_password.clone());
+        }
+        self.users.read().unwrap().get(username).cloned()
+    }
+
     pub async fn authenticate(&self, username: &str, password: &str) -> Result<Option<User>> {
+        // Robot accounts authenticate the same way in every mode
+        if let Some(name) = username.strip_prefix(robots::ROBOT_PREFIX) {
+            return match &self.robots {
+                Some(robots) => robots.authenticate(name, password).await,
+                None => Ok(None),
+            };
+        }
+
         match self.mode {
-            AuthMode::Basic => {
-                if let Some(stored_password) = self.users.get(username) {
-                    if stored_password == password {
+            // Token mode checks the same credentials, but only when exchanging them for a token
+            AuthMode::Basic | AuthMode::Token => {
+                if let Some(stored_password) = self.stored_password(username) {
+                    // Hash verification is deliberately slow; keep it off the async workers
+                    let password = password.to_string();
+                    let verified = tokio::task::spawn_blocking(move || stored_password.verify(&password)).await?;
+                    if verified {
                         return Ok(Some(User {
                             username: username.to_string(),
                             roles: vec!["user".to_string()],
                             scopes: vec![
                                 "repository:*:pull".to_string(),
                                 "repository:*:push".to_string(),
+                                "registry:catalog:*".to_string(),
                             ],
                         }));
                     }
                 }
                 Ok(None)
             }
-            AuthMode::Token => {
-                // TODO: Implement token authentication
-                todo!("Token authentication not implemented")
-            }
-            AuthMode::Oidc => {
-                // TODO: Implement OIDC authentication
-                todo!("OIDC authentication not implemented")
-            }
+            // `docker login` sends the OIDC token as the password
+            AuthMode::Oidc => match &self.oidc {
+                Some(oidc) => oidc.validate_token(password).await,
+                None => Ok(None),
+            },
+        }
+    }
+
+    /// The user behind a client certificate verified against `server.tls.client_ca_path`,
+    /// named by its common name. Holds the same scopes as a basic auth user.
+    pub fn certificate_user(&self, certificate: &ClientCertificate) -> User {
+        User {
+            username: certificate
+                .common_name
+                .clone()
+                .unwrap_or_else(|| certificate.subject.clone()),
+            roles: vec!["certificate".to_string()],
+            scopes: vec![
+                "repository:*:pull".to_string(),
+                "repository:*:push".to_string(),
+                "registry:catalog:*".to_string(),
+            ],
         }
     }
 
+    pub fn mode(&self) -> &AuthMode {
+        &self.mode
+    }
+
     pub fn generate_token(&self, user: &User, expires_in: u64) -> Result<String> {
-        jwt::generate_token(&self.jwt_secret, user, expires_in)
+        jwt::generate_token(&self.jwt_secret, user, expires_in, None)
     }
 
-    pub fn validate_token(&self, token: &str) -> Result<Option<User>> {
-        jwt::validate_token(&self.jwt_secret, token)
+    /// Token for the registry API whose scopes have been filtered through RBAC
+    pub fn generate_registry_token(&self, user: &User, expires_in: u64) -> Result<String> {
+        jwt::generate_token(&self.jwt_secret, user, expires_in, Some(REGISTRY_TOKEN_AUDIENCE))
     }
 
-    pub fn check_scope(&self, user: &User, required_scope: &str) -> bool {
-        // Check if user has the required scope
-        for scope in &user.scopes {
-            if scope == required_scope || scope == "registry:*" {
-                return true;
-            }
+    pub fn validate_token(&self, token: &str) -> Result<Option<User>> {
+        Ok(jwt::decode_token(&self.jwt_secret, token)?.map(|claims| claims.user))
+    }
 
-            // Check wildcard patterns
-            if let Some(prefix) = scope.strip_suffix("*") {
-                if required_scope.starts_with(prefix) {
-                    return true;
+    /// Validate a bearer token issued by drift, or by the OIDC provider in OIDC mode
+    pub async fn validate_bearer(&self, token: &str) -> Result<Option<BearerUser>> {
+        if let Some(claims) = jwt::decode_token(&self.jwt_secret, token)? {
+            let registry_token = claims.aud.as_deref() == Some(REGISTRY_TOKEN_AUDIENCE);
+            let user = claims.user;
+            // Tokens issued to a robot die with the robot
+            if let Some(name) = user.username.strip_prefix(robots::ROBOT_PREFIX) {
+                let active = match &self.robots {
+                    Some(robots) => robots.is_active(name).await,
+                    None => false,
+                };
+                if !active {
+                    return Ok(None);
                 }
             }
+            return Ok(Some(BearerUser { user, registry_token }));
+        }
+
+        match &self.oidc {
+            Some(oidc) => Ok(oidc
+                .validate_token(token)
+                .await?
+                .map(|user| BearerUser { user, registry_token: false })),
+            None => Ok(None),
         }
-        false
     }
-}
\ No newline at end of file
+
+    pub fn check_scope(&self, user: &User, required_scope: &str) -> bool {
+        user.scopes.iter().any(|scope| scope_grants(scope, required_scope))
+    }
+}
+
+/// Whether a granted scope covers a required one. Repository scopes are
+/// `repository:<name>:<actions>`, where the name may be a glob such as `team-a/*`
+/// and the actions a comma-separated list or `*`. Any other scope ending in `*`
+/// grants everything it prefixes, e.g. `registry:*`.
+fn scope_grants(granted: &str, required: &str) -> bool {
+    if granted == required || granted == "registry:*" {
+        return true;
+    }
+    if let Some(prefix) = granted.strip_suffix('*')
+        && required.starts_with(prefix)
+    {
+        return true;
+    }
+
+    let (Some(granted), Some(required)) = (
+        granted.strip_prefix("repository:").and_then(|rest| rest.rsplit_once(':')),
+        required.strip_prefix("repository:").and_then(|rest| rest.rsplit_once(':')),
+    ) else {
+        return false;
+    };
+    let ((pattern, actions), (repository, action)) = (granted, required);
+
+    actions.split(',').any(|granted_action| granted_action == "*" || granted_action == action)
+        && globset::Glob::new(pattern).is_ok_and(|glob| glob.compile_matcher().is_match(repository))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repository_globs_match_nested_names() {
+        assert!(scope_grants("repository:team-a/*:push", "repository:team-a/app:push"));
+        assert!(scope_grants("repository:team-a/*:pull,push", "repository:team-a/app:pull"));
+        assert!(scope_grants("repository:*:pull", "repository:team/app:pull"));
+        assert!(!scope_grants("repository:team-a/*:push", "repository:team-b/app:push"));
+        assert!(!scope_grants("repository:team-a/*:pull", "repository:team-a/app:push"));
+        assert!(!scope_grants("repository:app:pull", "repository:app2:pull"));
+    }
+
+    #[test]
+    fn wildcard_scopes_grant_what_they_prefix() {
+        assert!(scope_grants("registry:*", "repository:app:delete"));
+        assert!(scope_grants("bolt:*", "bolt:write"));
+        assert!(scope_grants("repository:app:*", "repository:app:delete"));
+        assert!(!scope_grants("bolt:read", "bolt:write"));
+    }
+}
@@ -1,23 +1,69 @@
+use crate::config::OAuthConfig;
 use anyhow::Result;
 use oauth2::{
-    AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, RedirectUrl, Scope,
+    basic::BasicClient, reqwest::async_http_client, AuthUrl, AuthorizationCode, ClientId,
+    ClientSecret, CsrfToken, PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope,
     TokenResponse, TokenUrl,
 };
-use openidconnect::{
-    core::{CoreClient, CoreProviderMetadata, CoreResponseType},
-    reqwest::async_http_client,
-    AuthenticationFlow, ClientId as OidcClientId, ClientSecret as OidcClientSecret,
-    IssuerUrl, Nonce, RedirectUrl as OidcRedirectUrl,
-};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use url::Url;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+/// How long a login may take between redirect and callback
+const PENDING_LOGIN_TTL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, thiserror::Error)]
+pub enum OAuthError {
+    #[error("unknown OAuth provider: {0}")]
+    UnknownProvider(String),
+    #[error("{0} login is not configured")]
+    NotConfigured(&'static str),
+    #[error("login state is missing, expired, or does not match")]
+    StateMismatch,
+}
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct OAuthConfig {
-    pub azure: Option<AzureConfig>,
-    pub github: Option<GitHubConfig>,
-    pub google: Option<GoogleConfig>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OAuthProvider {
+    Azure,
+    GitHub,
+    Google,
+}
+
+impl OAuthProvider {
+    pub fn parse(name: &str) -> Result<Self, OAuthError> {
+        match name {
+            "azure" => Ok(Self::Azure),
+            "github" => Ok(Self::GitHub),
+            "google" => Ok(Self::Google),
+            _ => Err(OAuthError::UnknownProvider(name.to_string())),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Azure => "azure",
+            Self::GitHub => "github",
+            Self::Google => "google",
+        }
+    }
+}
+
+/// Where a provider's authorization, token and profile endpoints live
+#[derive(Debug, Clone)]
+pub struct ProviderEndpoints {
+    pub authorize_url: String,
+    pub token_url: String,
+    /// Profile endpoint; GitHub's email list is at `<user_url>/emails`
+    pub user_url: String,
+}
+
+/// A login waiting for its provider callback, keyed by CSRF state
+struct PendingLogin {
+    provider: OAuthProvider,
+    pkce_verifier: PkceCodeVerifier,
+    created_at: Instant,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -53,195 +99,194 @@ pub struct OAuthUser {
 
 pub struct OAuthService {
     config: OAuthConfig,
+    pending: Mutex<HashMap<String, PendingLogin>>,
+    endpoints: HashMap<OAuthProvider, ProviderEndpoints>,
 }
 
 impl OAuthService {
     pub fn new(config: OAuthConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            pending: Mutex::new(HashMap::new()),
+            endpoints: HashMap::new(),
+        }
     }
 
-    pub fn get_azure_auth_url(&self) -> Result<(String, String)> {
-        let azure_config = self.config.azure.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("Azure OAuth not configured"))?;
-
-        // For now, return a simple auth URL until we fix the OIDC client configuration
-        let auth_url = format!(
-            "https://login.microsoftonline.com/{}/oauth2/v2.0/authorize?client_id={}&redirect_uri={}&response_type=code&scope=openid%20profile%20email",
-            azure_config.tenant_id,
-            azure_config.client_id,
-            urlencoding::encode(&azure_config.redirect_uri)
-        );
-        let csrf_token = "mock_csrf_token".to_string();
-
-        Ok((auth_url, csrf_token))
+    /// Talk to a provider somewhere other than its public endpoints
+    pub fn with_endpoints(mut self, provider: OAuthProvider, endpoints: ProviderEndpoints) -> Self {
+        self.endpoints.insert(provider, endpoints);
+        self
     }
 
-    pub fn get_github_auth_url(&self) -> Result<(String, String)> {
-        let github_config = self.config.github.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("GitHub OAuth not configured"))?;
-
-        let client = oauth2::basic::BasicClient::new(
-            ClientId::new(github_config.client_id.clone()),
-            Some(ClientSecret::new(github_config.client_secret.clone())),
-            AuthUrl::new("https://github.com/login/oauth/authorize".to_string())?,
-            Some(TokenUrl::new("https://github.com/login/oauth/access_token".to_string())?),
-        )
-        .set_redirect_uri(RedirectUrl::new(github_config.redirect_uri.clone())?);
-
-        let (auth_url, csrf_token) = client
-            .authorize_url(CsrfToken::new_random)
-            .add_scope(Scope::new("user:email".to_string()))
-            .add_scope(Scope::new("read:user".to_string()))
-            .url();
-
-        Ok((auth_url.to_string(), csrf_token.secret().clone()))
+    fn endpoints(&self, provider: OAuthProvider) -> Result<ProviderEndpoints> {
+        if let Some(endpoints) = self.endpoints.get(&provider) {
+            return Ok(endpoints.clone());
+        }
+
+        Ok(match provider {
+            OAuthProvider::Azure => {
+                let azure = self.config.azure.as_ref().ok_or(OAuthError::NotConfigured("Azure"))?;
+                ProviderEndpoints {
+                    authorize_url: format!(
+                        "https://login.microsoftonline.com/{}/oauth2/v2.0/authorize",
+                        azure.tenant_id
+                    ),
+                    token_url: format!("https://login.microsoftonline.com/{}/oauth2/v2.0/token", azure.tenant_id),
+                    user_url: "https://graph.microsoft.com/oidc/userinfo".to_string(),
+                }
+            }
+            OAuthProvider::GitHub => ProviderEndpoints {
+                authorize_url: "https://github.com/login/oauth/authorize".to_string(),
+                token_url: "https://github.com/login/oauth/access_token".to_string(),
+                user_url: "https://api.github.com/user".to_string(),
+            },
+            OAuthProvider::Google => ProviderEndpoints {
+                authorize_url: "https://accounts.google.com/o/oauth2/v2/auth".to_string(),
+                token_url: "https://www.googleapis.com/oauth2/v4/token".to_string(),
+                user_url: "https://www.googleapis.com/oauth2/v2/userinfo".to_string(),
+            },
+        })
     }
 
-    pub fn get_google_auth_url(&self) -> Result<(String, String)> {
-        let google_config = self.config.google.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("Google OAuth not configured"))?;
-
-        let client = oauth2::basic::BasicClient::new(
-            ClientId::new(google_config.client_id.clone()),
-            Some(ClientSecret::new(google_config.client_secret.clone())),
-            AuthUrl::new("https://accounts.google.com/o/oauth2/v2/auth".to_string())?,
-            Some(TokenUrl::new("https://www.googleapis.com/oauth2/v4/token".to_string())?),
-        )
-        .set_redirect_uri(RedirectUrl::new(google_config.redirect_uri.clone())?);
+    /// Start a login: returns the provider URL to redirect the browser to
+    pub fn authorize_url(&self, provider: OAuthProvider) -> Result<String> {
+        let client = self.client(provider)?;
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
 
-        let (auth_url, csrf_token) = client
+        let mut request = client
             .authorize_url(CsrfToken::new_random)
-            .add_scope(Scope::new("openid".to_string()))
-            .add_scope(Scope::new("profile".to_string()))
-            .add_scope(Scope::new("email".to_string()))
-            .url();
-
-        Ok((auth_url.to_string(), csrf_token.secret().clone()))
-    }
-
-    pub async fn handle_azure_callback(
-        &self,
-        code: &str,
-        _state: &str,
-    ) -> Result<OAuthUser> {
-        let azure_config = self.config.azure.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("Azure OAuth not configured"))?;
-
-        let issuer_url = IssuerUrl::new(format!(
-            "https://login.microsoftonline.com/{}/v2.0",
-            azure_config.tenant_id
-        ))?;
-
-        let auth_url = AuthUrl::new("https://login.microsoftonline.com/common/oauth2/v2.0/authorize".to_string())?;
-        let token_url = TokenUrl::new("https://login.microsoftonline.com/common/oauth2/v2.0/token".to_string())?;
-
-        let client = CoreClient::new(
-            OidcClientId::new(azure_config.client_id.clone()),
-            Some(OidcClientSecret::new(azure_config.client_secret.clone())),
-            issuer_url,
-            auth_url,
-            Some(token_url),
-            None, // UserInfoUrl
-            openidconnect::JsonWebKeySet::new(vec![]), // Empty JsonWebKeySet
+            .set_pkce_challenge(pkce_challenge);
+        for scope in provider_scopes(provider) {
+            request = request.add_scope(Scope::new(scope.to_string()));
+        }
+        let (auth_url, csrf_token) = request.url();
+
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, login| login.created_at.elapsed() < PENDING_LOGIN_TTL);
+        pending.insert(
+            csrf_token.secret().clone(),
+            PendingLogin {
+                provider,
+                pkce_verifier,
+                created_at: Instant::now(),
+            },
         );
 
-        let token_response = client
-            .exchange_code(AuthorizationCode::new(code.to_string()))
-            .request_async(async_http_client)
-            .await?;
-
-        // In a real implementation, you would verify the ID token and extract user info
-        // For now, we'll create a mock user
-        Ok(OAuthUser {
-            id: "azure_user_id".to_string(),
-            email: "user@company.com".to_string(),
-            name: "Azure User".to_string(),
-            avatar_url: None,
-            provider: "azure".to_string(),
-        })
+        debug!("Started {} login", provider.as_str());
+        Ok(auth_url.to_string())
     }
 
-    pub async fn handle_github_callback(
-        &self,
-        code: &str,
-        _state: &str,
-    ) -> Result<OAuthUser> {
-        let github_config = self.config.github.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("GitHub OAuth not configured"))?;
-
-        let client = oauth2::basic::BasicClient::new(
-            ClientId::new(github_config.client_id.clone()),
-            Some(ClientSecret::new(github_config.client_secret.clone())),
-            AuthUrl::new("https://github.com/login/oauth/authorize".to_string())?,
-            Some(TokenUrl::new("https://github.com/login/oauth/access_token".to_string())?),
-        )
-        .set_redirect_uri(RedirectUrl::new(github_config.redirect_uri.clone())?);
+    /// Finish a login: checks the state, exchanges the code and fetches the user's profile
+    pub async fn complete_login(&self, provider: OAuthProvider, code: &str, state: &str) -> Result<OAuthUser> {
+        let pending = self.pending.lock().unwrap().remove(state);
+        let pending = match pending {
+            Some(login) if login.provider == provider && login.created_at.elapsed() < PENDING_LOGIN_TTL => login,
+            _ => return Err(OAuthError::StateMismatch.into()),
+        };
 
-        let token_response = client
+        let token_response = self
+            .client(provider)?
             .exchange_code(AuthorizationCode::new(code.to_string()))
+            .set_pkce_verifier(pending.pkce_verifier)
             .request_async(async_http_client)
             .await?;
 
-        // Fetch user info from GitHub API
         let access_token = token_response.access_token().secret();
-        let user_info = self.fetch_github_user_info(access_token).await?;
+        let user = match provider {
+            OAuthProvider::Azure => self.fetch_azure_user_info(access_token).await?,
+            OAuthProvider::GitHub => self.fetch_github_user_info(access_token).await?,
+            OAuthProvider::Google => self.fetch_google_user_info(access_token).await?,
+        };
+
+        info!("Completed {} login for {}", provider.as_str(), user.email);
+        Ok(user)
+    }
 
-        Ok(user_info)
+    /// Whether the provider calls back over https, so session cookies can be marked Secure
+    pub fn uses_https(&self, provider: OAuthProvider) -> bool {
+        let redirect_uri = match provider {
+            OAuthProvider::Azure => self.config.azure.as_ref().map(|c| &c.redirect_uri),
+            OAuthProvider::GitHub => self.config.github.as_ref().map(|c| &c.redirect_uri),
+            OAuthProvider::Google => self.config.google.as_ref().map(|c| &c.redirect_uri),
+        };
+        redirect_uri.is_some_and(|uri| uri.starts_with("https://"))
     }
 
-    pub async fn handle_google_callback(
-        &self,
-        code: &str,
-        _state: &str,
-    ) -> Result<OAuthUser> {
-        let google_config = self.config.google.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("Google OAuth not configured"))?;
-
-        let client = oauth2::basic::BasicClient::new(
-            ClientId::new(google_config.client_id.clone()),
-            Some(ClientSecret::new(google_config.client_secret.clone())),
-            AuthUrl::new("https://accounts.google.com/o/oauth2/v2/auth".to_string())?,
-            Some(TokenUrl::new("https://www.googleapis.com/oauth2/v4/token".to_string())?),
+    fn client(&self, provider: OAuthProvider) -> Result<BasicClient> {
+        let (client_id, client_secret, redirect_uri) = match provider {
+            OAuthProvider::Azure => {
+                let azure = self.config.azure.as_ref().ok_or(OAuthError::NotConfigured("Azure"))?;
+                (&azure.client_id, &azure.client_secret, &azure.redirect_uri)
+            }
+            OAuthProvider::GitHub => {
+                let github = self.config.github.as_ref().ok_or(OAuthError::NotConfigured("GitHub"))?;
+                (&github.client_id, &github.client_secret, &github.redirect_uri)
+            }
+            OAuthProvider::Google => {
+                let google = self.config.google.as_ref().ok_or(OAuthError::NotConfigured("Google"))?;
+                (&google.client_id, &google.client_secret, &google.redirect_uri)
+            }
+        };
+        let endpoints = self.endpoints(provider)?;
+
+        Ok(BasicClient::new(
+            ClientId::new(client_id.clone()),
+            Some(ClientSecret::new(client_secret.clone())),
+            AuthUrl::new(endpoints.authorize_url)?,
+            Some(TokenUrl::new(endpoints.token_url)?),
         )
-        .set_redirect_uri(RedirectUrl::new(google_config.redirect_uri.clone())?);
+        .set_redirect_uri(RedirectUrl::new(redirect_uri.clone())?))
+    }
 
-        let token_response = client
-            .exchange_code(AuthorizationCode::new(code.to_string()))
-            .request_async(async_http_client)
-            .await?;
+    async fn fetch_azure_user_info(&self, access_token: &str) -> Result<OAuthUser> {
+        let client = reqwest::Client::new();
 
-        // Fetch user info from Google API
-        let access_token = token_response.access_token().secret();
-        let user_info = self.fetch_google_user_info(access_token).await?;
+        let user_response: AzureUserResponse = client
+            .get(&self.endpoints(OAuthProvider::Azure)?.user_url)
+            .header("Authorization", format!("Bearer {}", access_token))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
 
-        Ok(user_info)
+        Ok(OAuthUser {
+            id: user_response.sub,
+            email: user_response.email.unwrap_or_default(),
+            name: user_response.name.unwrap_or_default(),
+            avatar_url: None,
+            provider: "azure".to_string(),
+        })
     }
 
     async fn fetch_github_user_info(&self, access_token: &str) -> Result<OAuthUser> {
         let client = reqwest::Client::new();
+        let user_url = self.endpoints(OAuthProvider::GitHub)?.user_url;
 
         let user_response: GitHubUserResponse = client
-            .get("https://api.github.com/user")
+            .get(&user_url)
             .header("Authorization", format!("token {}", access_token))
             .header("User-Agent", "Drift-Registry")
             .send()
             .await?
+            .error_for_status()?
             .json()
             .await?;
 
         // Fetch primary email
         let emails_response: Vec<GitHubEmailResponse> = client
-            .get("https://api.github.com/user/emails")
+            .get(format!("{}/emails", user_url))
             .header("Authorization", format!("token {}", access_token))
             .header("User-Agent", "Drift-Registry")
             .send()
             .await?
+            .error_for_status()?
             .json()
             .await?;
 
         let primary_email = emails_response
             .iter()
-            .find(|email| email.primary)
+            .find(|email| email.primary && email.verified)
             .map(|email| email.email.clone())
             .unwrap_or_else(|| user_response.email.unwrap_or_default());
 
@@ -258,13 +303,18 @@ impl OAuthService {
         let client = reqwest::Client::new();
 
         let user_response: GoogleUserResponse = client
-            .get("https://www.googleapis.com/oauth2/v2/userinfo")
+            .get(&self.endpoints(OAuthProvider::Google)?.user_url)
             .header("Authorization", format!("Bearer {}", access_token))
             .send()
             .await?
+            .error_for_status()?
             .json()
             .await?;
 
+        if !user_response.verified_email {
+            return Err(anyhow::anyhow!("Google account email {} is not verified", user_response.email));
+        }
+
         Ok(OAuthUser {
             id: user_response.id,
             email: user_response.email,
@@ -275,6 +325,21 @@ impl OAuthService {
     }
 }
 
+fn provider_scopes(provider: OAuthProvider) -> &'static [&'static str] {
+    match provider {
+        OAuthProvider::Azure => &["openid", "profile", "email"],
+        OAuthProvider::GitHub => &["read:user", "user:email"],
+        OAuthProvider::Google => &["openid", "profile", "email"],
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AzureUserResponse {
+    sub: String,
+    name: Option<String>,
+    email: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 struct GitHubUserResponse {
     id: u64,
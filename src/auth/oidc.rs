@@ -1,11 +1,24 @@
-use anyhow::Result;
+use crate::auth::User;
+use crate::config::OidcConfig as OidcAuthConfig;
+use anyhow::{anyhow, Result};
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
 use openidconnect::{
     core::{CoreClient, CoreProviderMetadata},
     reqwest::async_http_client,
     ClientId as OidcClientId, ClientSecret as OidcClientSecret,
-    IssuerUrl, Nonce, RedirectUrl as OidcRedirectUrl,
+    IssuerUrl, RedirectUrl as OidcRedirectUrl,
 };
 use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tokio::sync::{OnceCell, RwLock};
+use tracing::{debug, info, warn};
+
+/// Minimum time between JWKS refreshes triggered by unknown key ids
+const JWKS_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OidcConfig {
@@ -51,4 +64,168 @@ impl OidcService {
 
         Ok(client)
     }
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Deserialize)]
+struct DiscoveryDocument {
+    issuer: String,
+    jwks_uri: String,
+}
+
+struct CachedJwks {
+    keys: JwkSet,
+    fetched_at: Instant,
+}
+
+/// Validates bearer tokens issued by the configured OIDC provider
+pub struct OidcTokenValidator {
+    config: OidcAuthConfig,
+    client: reqwest::Client,
+    jwks_uri: OnceCell<String>,
+    jwks: RwLock<Option<CachedJwks>>,
+}
+
+impl OidcTokenValidator {
+    pub fn new(config: OidcAuthConfig) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+            jwks_uri: OnceCell::new(),
+            jwks: RwLock::new(None),
+        }
+    }
+
+    /// Validate an ID or access token, returning None if it is not acceptable
+    pub async fn validate_token(&self, token: &str) -> Result<Option<User>> {
+        let header = match decode_header(token) {
+            Ok(header) => header,
+            Err(e) => {
+                debug!("Rejecting malformed OIDC token: {}", e);
+                return Ok(None);
+            }
+        };
+
+        // Only asymmetric algorithms; the JWKS never holds our shared secrets
+        if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+            debug!("Rejecting OIDC token signed with {:?}", header.alg);
+            return Ok(None);
+        }
+
+        let Some(jwk) = self.find_key(header.kid.as_deref()).await? else {
+            warn!("No OIDC signing key found for kid {:?}", header.kid);
+            return Ok(None);
+        };
+
+        let key = DecodingKey::from_jwk(&jwk)?;
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.config.issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+
+        let claims = match decode::<serde_json::Map<String, serde_json::Value>>(token, &key, &validation) {
+            Ok(data) => data.claims,
+            Err(e) => {
+                debug!("Rejecting OIDC token: {}", e);
+                return Ok(None);
+            }
+        };
+
+        Ok(self.user_from_claims(&claims))
+    }
+
+    /// Map token claims to a drift user using the configured group mappings
+    fn user_from_claims(&self, claims: &serde_json::Map<String, serde_json::Value>) -> Option<User> {
+        let username = claims
+            .get(&self.config.username_claim)
+            .or_else(|| claims.get("sub"))
+            .and_then(|value| value.as_str())?
+            .to_string();
+
+        let groups: Vec<&str> = match claims.get(&self.config.groups_claim) {
+            Some(serde_json::Value::Array(values)) => values.iter().filter_map(|v| v.as_str()).collect(),
+            Some(serde_json::Value::String(group)) => vec![group.as_str()],
+            _ => Vec::new(),
+        };
+
+        let mut roles = Vec::new();
+        let mut scopes = self.config.default_scopes.clone();
+        for mapping in &self.config.group_mappings {
+            if groups.contains(&mapping.group.as_str()) {
+                roles.extend(mapping.roles.iter().cloned());
+                scopes.extend(mapping.scopes.iter().cloned());
+            }
+        }
+        roles.sort();
+        roles.dedup();
+        scopes.sort();
+        scopes.dedup();
+
+        Some(User { username, roles, scopes })
+    }
+
+    /// Look up a signing key, refreshing the JWKS once if the key id is unknown
+    async fn find_key(&self, kid: Option<&str>) -> Result<Option<Jwk>> {
+        {
+            let cached = self.jwks.read().await;
+            if let Some(cached) = cached.as_ref() {
+                if let Some(key) = select_key(&cached.keys, kid) {
+                    return Ok(Some(key));
+                }
+                if cached.fetched_at.elapsed() < JWKS_REFRESH_INTERVAL {
+                    return Ok(None);
+                }
+            }
+        }
+
+        let mut cached = self.jwks.write().await;
+        // Another request may have refreshed while we waited for the lock
+        if let Some(existing) = cached.as_ref() {
+            if let Some(key) = select_key(&existing.keys, kid) {
+                return Ok(Some(key));
+            }
+            if existing.fetched_at.elapsed() < JWKS_REFRESH_INTERVAL {
+                return Ok(None);
+            }
+        }
+
+        let keys = self.fetch_jwks().await?;
+        let key = select_key(&keys, kid);
+        *cached = Some(CachedJwks { keys, fetched_at: Instant::now() });
+
+        Ok(key)
+    }
+
+    async fn fetch_jwks(&self) -> Result<JwkSet> {
+        let jwks_uri = self.jwks_uri.get_or_try_init(|| self.discover_jwks_uri()).await?;
+
+        let keys: JwkSet = self.client.get(jwks_uri).send().await?.error_for_status()?.json().await?;
+        info!("Fetched {} OIDC signing keys from {}", keys.keys.len(), jwks_uri);
+        Ok(keys)
+    }
+
+    async fn discover_jwks_uri(&self) -> Result<String> {
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            self.config.issuer.trim_end_matches('/')
+        );
+
+        let document: DiscoveryDocument = self.client.get(&url).send().await?.error_for_status()?.json().await?;
+        if document.issuer.trim_end_matches('/') != self.config.issuer.trim_end_matches('/') {
+            return Err(anyhow!(
+                "OIDC discovery issuer {} does not match configured issuer {}",
+                document.issuer,
+                self.config.issuer
+            ));
+        }
+
+        Ok(document.jwks_uri)
+    }
+}
+
+/// Pick the key matching `kid`; tokens without a kid only match a single-key set
+fn select_key(keys: &JwkSet, kid: Option<&str>) -> Option<Jwk> {
+    match kid {
+        Some(kid) => keys.find(kid).cloned(),
+        None if keys.keys.len() == 1 => keys.keys.first().cloned(),
+        None => None,
+    }
+}
@@ -0,0 +1,141 @@
+use anyhow::{anyhow, Result};
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use std::collections::HashMap;
+use std::path::Path;
+use subtle::ConstantTimeEq;
+use tracing::warn;
+
+/// A password entry from the config or an htpasswd file
+#[derive(Debug, Clone)]
+pub enum StoredPassword {
+    Argon2(String),
+    Bcrypt(String),
+    Plaintext(String),
+}
+
+impl StoredPassword {
+    /// Classify an entry by its hash prefix; anything without one is plaintext
+    pub fn parse(entry: &str) -> Result<Self> {
+        if entry.starts_with("$argon2") {
+            PasswordHash::new(entry).map_err(|e| anyhow!("Invalid argon2 hash: {}", e))?;
+            Ok(Self::Argon2(entry.to_string()))
+        } else if entry.starts_with("$2y$") || entry.starts_with("$2b$") || entry.starts_with("$2a$") {
+            Ok(Self::Bcrypt(entry.to_string()))
+        } else if entry.starts_with('$') || entry.starts_with("{SHA}") {
+            Err(anyhow!("Unsupported password hash format; use argon2id or bcrypt"))
+        } else {
+            Ok(Self::Plaintext(entry.to_string()))
+        }
+    }
+
+    pub fn is_plaintext(&self) -> bool {
+        matches!(self, Self::Plaintext(_))
+    }
+
+    pub fn verify(&self, password: &str) -> bool {
+        match self {
+            Self::Argon2(hash) => match PasswordHash::new(hash) {
+                Ok(parsed) => Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok(),
+                Err(_) => false,
+            },
+            Self::Bcrypt(hash) => bcrypt::verify(password, hash).unwrap_or(false),
+            Self::Plaintext(stored) => stored.as_bytes().ct_eq(password.as_bytes()).into(),
+        }
+    }
+}
+
+/// Hash a password with argon2id for use in config or htpasswd entries
+pub fn hash_password(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow!("Failed to hash password: {}", e))?;
+    Ok(hash.to_string())
+}
+
+/// Parse `username:password` entries, rejecting plaintext unless explicitly allowed
+pub fn parse_entries<'a>(
+    entries: impl IntoIterator<Item = &'a str>,
+    allow_plaintext: bool,
+) -> Result<HashMap<String, StoredPassword>> {
+    let mut users = HashMap::new();
+
+    for (index, entry) in entries.into_iter().enumerate() {
+        let Some((username, password)) = entry.split_once(':') else {
+            return Err(anyhow!("Entry {} is not in username:password format", index + 1));
+        };
+
+        let stored = StoredPassword::parse(password)
+            .map_err(|e| anyhow!("Invalid password for user {}: {}", username, e))?;
+        if stored.is_plaintext() {
+            if !allow_plaintext {
+                return Err(anyhow!(
+                    "User {} has a plaintext password; hash it with `drift hash-password` or set allow_plaintext_passwords",
+                    username
+                ));
+            }
+            warn!(
+                "Plaintext password for user {} is deprecated; replace it with the output of `drift hash-password`",
+                username
+            );
+        }
+
+        users.insert(username.to_string(), stored);
+    }
+
+    Ok(users)
+}
+
+/// Load an htpasswd file, skipping blank lines and comments
+pub fn load_htpasswd(path: &Path, allow_plaintext: bool) -> Result<HashMap<String, StoredPassword>> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read htpasswd file {}: {}", path.display(), e))?;
+
+    parse_entries(
+        contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#')),
+        allow_plaintext,
+    )
+    .map_err(|e| anyhow!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bcrypt_entries_verify_only_the_right_password() {
+        let hash = bcrypt::hash("s3cret", 4).unwrap();
+        let users = parse_entries([format!("alice:{}", hash).as_str()], false).unwrap();
+
+        assert!(matches!(users["alice"], StoredPassword::Bcrypt(_)));
+        assert!(users["alice"].verify("s3cret"));
+        assert!(!users["alice"].verify("s3cret "));
+        assert!(!users["alice"].verify(""));
+    }
+
+    #[test]
+    fn hashed_passwords_verify_only_the_right_password() {
+        let stored = StoredPassword::parse(&hash_password("s3cret").unwrap()).unwrap();
+
+        assert!(matches!(stored, StoredPassword::Argon2(_)));
+        assert!(stored.verify("s3cret"));
+        assert!(!stored.verify("S3cret"));
+    }
+
+    #[test]
+    fn plaintext_entries_need_to_be_allowed() {
+        assert!(parse_entries(["alice:s3cret"], false).is_err());
+
+        let users = parse_entries(["alice:s3cret"], true).unwrap();
+        assert!(users["alice"].is_plaintext());
+        assert!(users["alice"].verify("s3cret"));
+        assert!(!users["alice"].verify("other"));
+        assert!(parse_entries(["alice:{SHA}abc"], true).is_err());
+    }
+}
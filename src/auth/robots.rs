@@ -0,0 +1,188 @@
+use crate::auth::password::{self, StoredPassword};
+use crate::auth::User;
+use crate::storage::StorageBackend;
+use anyhow::{anyhow, Result};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+
+/// Username prefix that identifies robot accounts, e.g. `robot$ci-deploy`
+pub const ROBOT_PREFIX: &str = "robot$";
+
+const ROBOT_INDEX_KEY: &str = "auth/robots/index.json";
+
+/// A non-human account with a fixed set of scopes, used by CI pipelines
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RobotAccount {
+    pub name: String,
+    pub description: Option<String>,
+    pub scopes: Vec<String>,
+    pub secret_hash: String,
+    pub created_by: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl RobotAccount {
+    pub fn username(&self) -> String {
+        format!("{}{}", ROBOT_PREFIX, self.name)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= chrono::Utc::now())
+    }
+}
+
+/// Robot accounts persisted as JSON under `auth/robots/` in the storage backend
+pub struct RobotService {
+    storage: Arc<dyn StorageBackend>,
+    robots: RwLock<HashMap<String, RobotAccount>>,
+}
+
+impl RobotService {
+    pub async fn new(storage: Arc<dyn StorageBackend>) -> Result<Self> {
+        let mut robots = HashMap::new();
+
+        let names: Vec<String> = match storage.get_blob(ROBOT_INDEX_KEY).await? {
+            Some(data) => serde_json::from_slice(&data)?,
+            None => Vec::new(),
+        };
+        for name in names {
+            match storage.get_blob(&robot_key(&name)).await? {
+                Some(data) => {
+                    let robot: RobotAccount = serde_json::from_slice(&data)?;
+                    robots.insert(robot.name.clone(), robot);
+                }
+                None => warn!("Robot account {} is indexed but missing from storage", name),
+            }
+        }
+
+        info!("Loaded {} robot accounts", robots.len());
+        Ok(Self {
+            storage,
+            robots: RwLock::new(robots),
+        })
+    }
+
+    /// Create a robot, returning it with its secret. The secret is not stored and cannot be shown again.
+    pub async fn create(
+        &self,
+        name: &str,
+        description: Option<String>,
+        scopes: Vec<String>,
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+        created_by: &str,
+    ) -> Result<(RobotAccount, String)> {
+        if !is_valid_robot_name(name) {
+            return Err(anyhow!(
+                "Invalid robot name {}: use lowercase letters, digits, '.', '_' or '-'",
+                name
+            ));
+        }
+
+        let mut robots = self.robots.write().await;
+        if robots.contains_key(name) {
+            return Err(anyhow!("Robot account already exists: {}", name));
+        }
+
+        let mut secret_bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut secret_bytes);
+        let secret = hex::encode(secret_bytes);
+
+        let robot = RobotAccount {
+            name: name.to_string(),
+            description,
+            scopes,
+            secret_hash: password::hash_password(&secret)?,
+            created_by: created_by.to_string(),
+            created_at: chrono::Utc::now(),
+            expires_at,
+        };
+
+        self.storage
+            .put_blob(&robot_key(name), serde_json::to_vec(&robot)?.into())
+            .await?;
+        robots.insert(name.to_string(), robot.clone());
+        self.save_index(&robots).await?;
+
+        info!("Created robot account {} by {}", robot.username(), created_by);
+        Ok((robot, secret))
+    }
+
+    pub async fn list(&self) -> Vec<RobotAccount> {
+        let mut robots: Vec<RobotAccount> = self.robots.read().await.values().cloned().collect();
+        robots.sort_by(|a, b| a.name.cmp(&b.name));
+        robots
+    }
+
+    /// Revoke a robot; it stops authenticating immediately
+    pub async fn revoke(&self, name: &str) -> Result<bool> {
+        let mut robots = self.robots.write().await;
+        if robots.remove(name).is_none() {
+            return Ok(false);
+        }
+
+        self.save_index(&robots).await?;
+        self.storage.delete_blob(&robot_key(name)).await?;
+
+        info!("Revoked robot account {}{}", ROBOT_PREFIX, name);
+        Ok(true)
+    }
+
+    /// Whether a robot still exists and has not expired
+    pub async fn is_active(&self, name: &str) -> bool {
+        self.robots
+            .read()
+            .await
+            .get(name)
+            .is_some_and(|robot| !robot.is_expired())
+    }
+
+    /// Check a robot's secret, returning a user limited to the robot's scopes
+    pub async fn authenticate(&self, name: &str, secret: &str) -> Result<Option<User>> {
+        let Some(robot) = self.robots.read().await.get(name).cloned() else {
+            debug!("Unknown robot account: {}", name);
+            return Ok(None);
+        };
+
+        if robot.is_expired() {
+            debug!("Robot account {} has expired", name);
+            return Ok(None);
+        }
+
+        let stored = StoredPassword::parse(&robot.secret_hash)?;
+        let secret = secret.to_string();
+        if !tokio::task::spawn_blocking(move || stored.verify(&secret)).await? {
+            return Ok(None);
+        }
+
+        Ok(Some(User {
+            username: robot.username(),
+            roles: vec!["robot".to_string()],
+            scopes: robot.scopes,
+        }))
+    }
+
+    async fn save_index(&self, robots: &HashMap<String, RobotAccount>) -> Result<()> {
+        let mut names: Vec<&String> = robots.keys().collect();
+        names.sort();
+        self.storage
+            .put_blob(ROBOT_INDEX_KEY, serde_json::to_vec(&names)?.into())
+            .await
+    }
+}
+
+fn robot_key(name: &str) -> String {
+    format!("auth/robots/accounts/{}.json", name)
+}
+
+fn is_valid_robot_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '_' | '-'))
+}
@@ -0,0 +1,114 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tracing::{debug, info, warn};
+
+use crate::storage::StorageBackend;
+
+const HOLDS_KEY: &str = "gc/holds.json";
+
+/// Blobs uploaded for a push whose manifest has not been put yet.
+///
+/// Garbage collection skips held digests, so a layer that finished uploading
+/// just before a GC run is not swept before the manifest referencing it
+/// arrives. Holds are persisted through the storage backend so they survive a
+/// restart, and expire after the upload session TTL so abandoned pushes are
+/// eventually collected.
+pub struct BlobHolds {
+    storage: Arc<dyn StorageBackend>,
+    holds: tokio::sync::Mutex<HashMap<String, DateTime<Utc>>>,
+    ttl: Duration,
+}
+
+impl BlobHolds {
+    pub async fn load(storage: Arc<dyn StorageBackend>, ttl_hours: u64) -> Result<Self> {
+        let ttl = Duration::hours(ttl_hours as i64);
+        let mut holds: HashMap<String, DateTime<Utc>> = match storage.get_blob(HOLDS_KEY).await? {
+            Some(data) => serde_json::from_slice(&data)?,
+            None => HashMap::new(),
+        };
+
+        let cutoff = Utc::now() - ttl;
+        holds.retain(|_, held_at| *held_at > cutoff);
+        info!("Loaded {} blob holds", holds.len());
+
+        Ok(Self {
+            storage,
+            holds: tokio::sync::Mutex::new(holds),
+            ttl,
+        })
+    }
+
+    /// Hold a blob until a manifest references it
+    pub async fn hold(&self, digest: &str) {
+        let mut holds = self.holds.lock().await;
+        holds.insert(digest.to_string(), Utc::now());
+        debug!("Holding blob {}", digest);
+        self.persist(&mut holds).await;
+    }
+
+    /// Release holds on blobs now referenced by a manifest
+    pub async fn release(&self, digests: &[&str]) {
+        let mut holds = self.holds.lock().await;
+        let before = holds.len();
+        for digest in digests {
+            holds.remove(*digest);
+        }
+
+        if holds.len() != before {
+            debug!("Released {} blob holds", before - holds.len());
+            self.persist(&mut holds).await;
+        }
+    }
+
+    pub async fn is_held(&self, digest: &str) -> bool {
+        let cutoff = Utc::now() - self.ttl;
+        self.holds
+            .lock()
+            .await
+            .get(digest)
+            .is_some_and(|held_at| *held_at > cutoff)
+    }
+
+    /// Write the holds back, dropping expired ones. Failures are logged
+    /// rather than failing the push; the in-memory holds still apply.
+    async fn persist(&self, holds: &mut HashMap<String, DateTime<Utc>>) {
+        let cutoff = Utc::now() - self.ttl;
+        holds.retain(|_, held_at| *held_at > cutoff);
+
+        let data = match serde_json::to_vec(&*holds) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("Failed to serialize blob holds: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = self.storage.put_blob(HOLDS_KEY, data.into()).await {
+            warn!("Failed to persist blob holds: {}", e);
+        }
+    }
+}
+
+/// Per-repository locks that serialize manifest changes with garbage collection
+#[derive(Default)]
+pub struct RepositoryLocks {
+    locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl RepositoryLocks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn lock(&self, repository: &str) -> tokio::sync::OwnedMutexGuard<()> {
+        let lock = self
+            .locks
+            .lock()
+            .unwrap()
+            .entry(repository.to_string())
+            .or_default()
+            .clone();
+        lock.lock_owned().await
+    }
+}
@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tracing::{debug, error, info, warn};
+use tracing::{debug, info};
 
 #[cfg(feature = "bolt-integration")]
 use bolt::{api::DriftRegistryClient, BoltRuntime};
@@ -423,7 +423,7 @@ impl BoltIntegrationService {
 
     // Private helper methods
     async fn load_profiles_from_storage(&self) -> Result<Vec<BoltProfile>> {
-        let mut profiles = Vec::new();
+        let profiles = Vec::new();
 
         // In a real implementation, we'd list all profile directories
         // For now, return empty list since we don't have a directory listing method
@@ -452,7 +452,7 @@ impl BoltIntegrationService {
     }
 
     async fn load_plugins_from_storage(&self) -> Result<Vec<BoltPlugin>> {
-        let mut plugins = Vec::new();
+        let plugins = Vec::new();
 
         // Implementation would list plugin directories
         debug!("Loading plugins from storage - implementation pending");
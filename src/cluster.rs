@@ -1,24 +1,59 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
-use std::sync::Arc;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
+use subtle::ConstantTimeEq;
 use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 
 use crate::config::ClusterConfig;
+use crate::storage::StorageBackend;
+
+/// Path of the gossip endpoint each node serves on `cluster.bind_address`
+const GOSSIP_PATH: &str = "/cluster/gossip";
+/// Path of the Raft RequestVote endpoint, served next to the gossip endpoint
+const VOTE_PATH: &str = "/cluster/vote";
+/// Path an elected leader announces itself on
+const LEADER_PATH: &str = "/cluster/leader";
+/// Path peers ship `ReplicationData` to
+const REPLICATE_PATH: &str = "/cluster/replicate";
+/// How long one gossip exchange with a peer may take
+const GOSSIP_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// High Availability clustering support for drift registry
 #[derive(Clone)]
 pub struct ClusterService {
     config: ClusterConfig,
     node_id: String,
+    /// Where peers reach our gossip endpoint
+    address: String,
     nodes: Arc<RwLock<HashMap<String, NodeInfo>>>,
     leader: Arc<RwLock<Option<String>>>,
     consensus: Arc<Box<dyn ConsensusProtocol>>,
     health_checker: Arc<HealthChecker>,
     state_replicator: Arc<StateReplicator>,
+    /// Strategy from `load_balancing_strategy`, used by `select_node`
+    load_balancing: LoadBalancingStrategy,
+    /// Position of the next `RoundRobin` selection
+    round_robin_index: Arc<AtomicUsize>,
+    /// Current weight of each node under `WeightedRoundRobin`
+    weighted_round_robin: Arc<Mutex<HashMap<String, i64>>>,
+    http: reqwest::Client,
+    /// Stops the heartbeat, health check and election tasks
+    shutdown: CancellationToken,
 }
 
 /// Information about a cluster node
@@ -36,6 +71,13 @@ pub struct NodeInfo {
     pub metadata: HashMap<String, String>,
 }
 
+/// Body of a gossip exchange, both ways: the sender's own entry and every node it knows
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GossipMessage {
+    pub sender: NodeInfo,
+    pub nodes: Vec<NodeInfo>,
+}
+
 /// Node roles in the cluster
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum NodeRole {
@@ -65,6 +107,14 @@ pub struct NodeCapacity {
     pub network_bandwidth_mbps: u64,
 }
 
+impl NodeCapacity {
+    /// Share of `WeightedRoundRobin` traffic, relative to other nodes. Requests are
+    /// mostly CPU bound, so this is the core count; every node gets some traffic.
+    pub fn weight(&self) -> i64 {
+        i64::from(self.cpu_cores.max(1))
+    }
+}
+
 /// Current load on a node
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NodeLoad {
@@ -78,27 +128,83 @@ pub struct NodeLoad {
 /// Consensus protocol trait
 #[async_trait]
 pub trait ConsensusProtocol: Send + Sync {
+    /// Elect a leader among `nodes`, every known member of the cluster
     async fn elect_leader(&self, nodes: &[NodeInfo]) -> Result<String>;
     async fn propose(&self, proposal: Proposal) -> Result<bool>;
     async fn replicate(&self, data: ReplicationData) -> Result<()>;
     fn name(&self) -> String;
+
+    /// Answer a candidate's vote request; protocols without voting never grant one
+    async fn request_vote(&self, request: VoteRequest) -> Result<VoteResponse> {
+        Ok(VoteResponse {
+            term: request.term,
+            vote_granted: false,
+        })
+    }
+
+    /// Whether to follow the node announcing itself leader
+    async fn accept_leader(&self, _announcement: &LeaderAnnouncement) -> Result<bool> {
+        Ok(true)
+    }
+
+    /// Election term this node is in
+    async fn current_term(&self) -> u64 {
+        0
+    }
 }
 
 /// Raft consensus implementation
 pub struct RaftConsensus {
     node_id: String,
-    term: Arc<RwLock<u64>>,
-    voted_for: Arc<RwLock<Option<String>>>,
+    /// Term and vote, persisted before they're acted on so a restart can't vote twice in a term
+    state: Arc<RwLock<RaftState>>,
     log: Arc<RwLock<Vec<LogEntry>>>,
+    storage: Arc<dyn StorageBackend>,
+    http: reqwest::Client,
+    /// Size of the configured membership; a majority of it is needed even while fewer nodes are known
+    members: usize,
 }
 
-/// Gossip protocol for cluster communication
-pub struct GossipProtocol {
-    node_id: String,
-    peers: Arc<RwLock<HashSet<String>>>,
-    state: Arc<RwLock<HashMap<String, GossipState>>>,
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct RaftState {
+    term: u64,
+    voted_for: Option<String>,
 }
 
+/// Raft RequestVote RPC, sent by a candidate to every other node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VoteRequest {
+    pub term: u64,
+    pub candidate_id: String,
+    /// Number of entries in the candidate's log
+    pub last_log_index: u64,
+    pub last_log_term: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VoteResponse {
+    /// The voter's term, so a stale candidate can catch up
+    pub term: u64,
+    pub vote_granted: bool,
+}
+
+/// Sent by the leader to every other node after winning, and again each election interval
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LeaderAnnouncement {
+    pub term: u64,
+    pub leader_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LeaderResponse {
+    /// The follower's term; a higher one than the leader's makes it step down
+    pub term: u64,
+    pub accepted: bool,
+}
+
+/// Gossip protocol for cluster communication
+pub struct GossipProtocol;
+
 /// Proposal for cluster-wide decisions
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Proposal {
@@ -125,9 +231,28 @@ pub struct ReplicationData {
     pub type_: ReplicationType,
     pub data: Vec<u8>,
     pub version: u64,
+    /// `sha256:<hex>` of `data`
     pub checksum: String,
 }
 
+impl ReplicationData {
+    pub fn checksum_of(data: &[u8]) -> String {
+        format!("sha256:{}", hex::encode(Sha256::digest(data)))
+    }
+
+    fn checksum_matches(&self) -> bool {
+        self.checksum == Self::checksum_of(&self.data)
+    }
+}
+
+/// A peer's answer to `ReplicationData`: whether it applied it, and why not
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplicationAck {
+    pub node_id: String,
+    pub applied: bool,
+    pub error: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ReplicationType {
     Metadata,
@@ -161,8 +286,87 @@ pub struct HealthChecker {
 
 /// State replicator for maintaining consistency
 pub struct StateReplicator {
-    replication_factor: usize,
+    node_id: String,
     consistency_level: ConsistencyLevel,
+    /// Latest applied version of each replicated item, by id, persisted before it's acked
+    applied: RwLock<HashMap<String, ReplicationData>>,
+    storage: Arc<dyn StorageBackend>,
+}
+
+impl StateReplicator {
+    pub async fn load(
+        node_id: String,
+        storage: Arc<dyn StorageBackend>,
+        consistency_level: ConsistencyLevel,
+    ) -> Result<Self> {
+        let applied = match storage.get_blob(&Self::state_key(&node_id)).await? {
+            Some(data) => serde_json::from_slice(&data).context("Invalid persisted replication state")?,
+            None => HashMap::new(),
+        };
+
+        Ok(Self {
+            node_id,
+            consistency_level,
+            applied: RwLock::new(applied),
+            storage,
+        })
+    }
+
+    fn state_key(node_id: &str) -> String {
+        format!("cluster/replicated/{}", node_id)
+    }
+
+    /// Peer acks needed before a write counts as replicated. This node's own copy
+    /// counts towards a quorum, so only the rest of the majority has to ack.
+    fn required_acks(&self, peers: usize) -> usize {
+        match self.consistency_level {
+            ConsistencyLevel::Strong => peers,
+            ConsistencyLevel::Quorum => peers.div_ceil(2),
+            ConsistencyLevel::Weak => peers.min(1),
+            ConsistencyLevel::Eventual => 0,
+        }
+    }
+
+    /// Apply replicated data unless it's corrupt or older than what's applied. Reapplying
+    /// the same version is acknowledged, so a retried send doesn't fail.
+    async fn apply(&self, data: ReplicationData) -> std::result::Result<(), String> {
+        let mut applied = self.applied.write().await;
+        Self::check(&applied, &data)?;
+
+        let mut updated = applied.clone();
+        updated.insert(data.id.clone(), data.clone());
+        let state = serde_json::to_vec(&updated).map_err(|e| e.to_string())?;
+        self.storage.put_blob(&Self::state_key(&self.node_id), state.into()).await
+            .map_err(|e| format!("failed to persist {} version {}: {:#}", data.id, data.version, e))?;
+
+        debug!("Applied {} version {}", data.id, data.version);
+        *applied = updated;
+        Ok(())
+    }
+
+    /// Whether `data` would be applied over what's applied now
+    async fn can_apply(&self, data: &ReplicationData) -> std::result::Result<(), String> {
+        Self::check(&*self.applied.read().await, data)
+    }
+
+    fn check(applied: &HashMap<String, ReplicationData>, data: &ReplicationData) -> std::result::Result<(), String> {
+        if !data.checksum_matches() {
+            return Err(format!("checksum mismatch for {} version {}", data.id, data.version));
+        }
+
+        if let Some(current) = applied.get(&data.id) {
+            if current.version > data.version {
+                return Err(format!(
+                    "{} version {} is older than the applied version {}",
+                    data.id, data.version, current.version
+                ));
+            }
+            if current.version == data.version && current.checksum != data.checksum {
+                return Err(format!("{} version {} was already applied with other content", data.id, data.version));
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -183,6 +387,45 @@ pub enum LoadBalancingStrategy {
     ConsistentHashing,
 }
 
+impl LoadBalancingStrategy {
+    /// Parse the `load_balancing_strategy` config value, e.g. `round_robin`
+    pub fn from_config(value: &str) -> Option<Self> {
+        match value {
+            "round_robin" => Some(Self::RoundRobin),
+            "least_connections" => Some(Self::LeastConnections),
+            "weighted_round_robin" => Some(Self::WeightedRoundRobin),
+            "random" => Some(Self::Random),
+            "consistent_hashing" => Some(Self::ConsistentHashing),
+            _ => None,
+        }
+    }
+}
+
+/// Points each node gets on the consistent-hashing ring, to even out its share
+const VIRTUAL_NODES_PER_NODE: usize = 64;
+
+/// Position on the consistent-hashing ring. SHA-256 rather than the std hasher so
+/// every node in the cluster agrees on the ring.
+fn ring_position(value: &str) -> u64 {
+    let digest = Sha256::digest(value.as_bytes());
+    u64::from_be_bytes(digest[..8].try_into().expect("SHA-256 digests are 32 bytes"))
+}
+
+/// The node owning `key`: the first one clockwise from the key's position on a
+/// ring built from the node ids
+fn select_on_ring(nodes: Vec<NodeInfo>, key: &str) -> Option<NodeInfo> {
+    let ring: BTreeMap<u64, usize> = nodes.iter()
+        .enumerate()
+        .flat_map(|(index, node)| {
+            (0..VIRTUAL_NODES_PER_NODE).map(move |replica| (ring_position(&format!("{}#{}", node.id, replica)), index))
+        })
+        .collect();
+
+    let position = ring_position(key);
+    let (_, index) = ring.range(position..).next().or_else(|| ring.iter().next())?;
+    nodes.into_iter().nth(*index)
+}
+
 /// Cluster events
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ClusterEvent {
@@ -195,21 +438,47 @@ pub enum ClusterEvent {
 }
 
 impl ClusterService {
-    pub async fn new(config: ClusterConfig) -> Result<Self> {
+    pub async fn new(config: ClusterConfig, storage: Arc<dyn StorageBackend>, shutdown: CancellationToken) -> Result<Self> {
         info!("Initializing cluster service");
 
         let node_id = config.node_id.clone();
+        let secret = config.secret.clone().filter(|secret| !secret.is_empty())
+            .context("cluster.secret must be set when clustering is enabled")?;
+        let http = cluster_client(&secret)?;
+
+        let load_balancing = LoadBalancingStrategy::from_config(&config.load_balancing_strategy)
+            .unwrap_or_else(|| {
+                warn!(
+                    "Unknown load balancing strategy '{}', using round_robin",
+                    config.load_balancing_strategy
+                );
+                LoadBalancingStrategy::RoundRobin
+            });
+
+        let listener = tokio::net::TcpListener::bind(&config.bind_address)
+            .await
+            .with_context(|| format!("Failed to bind cluster gossip endpoint on {}", config.bind_address))?;
+        // A wildcard bind address isn't reachable by peers, so set advertise_address there
+        let address = match &config.advertise_address {
+            Some(address) => address.clone(),
+            None => listener.local_addr()?.to_string(),
+        };
 
         // Initialize consensus protocol
         let consensus: Box<dyn ConsensusProtocol> = match config.consensus_protocol.as_str() {
-            "raft" => Box::new(RaftConsensus::new(node_id.clone())),
-            "gossip" => Box::new(GossipProtocol::new(node_id.clone())),
-            _ => Box::new(RaftConsensus::new(node_id.clone())),
+            "gossip" => Box::new(GossipProtocol),
+            _ => {
+                // The seeds and this node, however few of them have been reached yet
+                let seeds: BTreeSet<&String> = config.seed_nodes.iter().filter(|seed| **seed != address).collect();
+                let members = seeds.len() + 1;
+                Box::new(RaftConsensus::load(node_id.clone(), storage.clone(), http.clone(), members).await?)
+            }
         };
 
         let service = Self {
             config: config.clone(),
             node_id: node_id.clone(),
+            address,
             nodes: Arc::new(RwLock::new(HashMap::new())),
             leader: Arc::new(RwLock::new(None)),
             consensus: Arc::new(consensus),
@@ -217,12 +486,18 @@ impl ClusterService {
                 check_interval: Duration::from_secs(config.health_check_interval_seconds),
                 timeout: Duration::from_secs(config.health_check_timeout_seconds),
             }),
-            state_replicator: Arc::new(StateReplicator {
-                replication_factor: config.replication_factor,
-                consistency_level: config.consistency_level,
-            }),
+            state_replicator: Arc::new(
+                StateReplicator::load(node_id.clone(), storage, config.consistency_level).await?,
+            ),
+            load_balancing,
+            round_robin_index: Arc::new(AtomicUsize::new(0)),
+            weighted_round_robin: Arc::new(Mutex::new(HashMap::new())),
+            http,
+            shutdown,
         };
 
+        service.start_gossip_endpoint(listener, secret);
+
         // Register self as a node
         service.register_self().await?;
 
@@ -239,7 +514,7 @@ impl ClusterService {
     async fn register_self(&self) -> Result<()> {
         let node_info = NodeInfo {
             id: self.node_id.clone(),
-            address: self.config.bind_address.clone(),
+            address: self.address.clone(),
             role: NodeRole::Follower,
             status: NodeStatus::Joining,
             version: env!("CARGO_PKG_VERSION").to_string(),
@@ -260,8 +535,7 @@ impl ClusterService {
             metadata: HashMap::new(),
         };
 
-        let mut nodes = self.nodes.write().await;
-        nodes.insert(self.node_id.clone(), node_info);
+        self.nodes.write().await.insert(self.node_id.clone(), node_info);
 
         // Join existing cluster
         if !self.config.seed_nodes.is_empty() {
@@ -290,32 +564,81 @@ impl ClusterService {
         Ok(())
     }
 
-    /// Contact a node
+    /// Exchange node lists with the node at `address`
     async fn contact_node(&self, address: &str) -> Result<()> {
-        // In real implementation, would make actual network request
         debug!("Contacting node at: {}", address);
-        Ok(())
+        exchange_gossip(&self.http, &self.nodes, &self.node_id, address).await
     }
 
-    /// Start heartbeat task
+    /// Serve the gossip endpoint peers send their heartbeats to, to those that know the cluster secret
+    fn start_gossip_endpoint(&self, listener: tokio::net::TcpListener, secret: String) {
+        let router = Router::new()
+            .route(GOSSIP_PATH, post(receive_gossip))
+            .route(VOTE_PATH, post(receive_vote_request))
+            .route(LEADER_PATH, post(receive_leader_announcement))
+            .route(REPLICATE_PATH, post(receive_replication_data))
+            .route_layer(middleware::from_fn_with_state(Arc::<str>::from(secret), require_cluster_secret))
+            .with_state(GossipEndpoint {
+                node_id: self.node_id.clone(),
+                nodes: self.nodes.clone(),
+                leader: self.leader.clone(),
+                consensus: self.consensus.clone(),
+                state_replicator: self.state_replicator.clone(),
+            });
+        let shutdown = self.shutdown.clone();
+
+        info!("Cluster gossip endpoint listening on {}", self.config.bind_address);
+        tokio::spawn(async move {
+            let serve = axum::serve(listener, router).with_graceful_shutdown(async move { shutdown.cancelled().await });
+            if let Err(e) = serve.await {
+                error!("Cluster gossip endpoint failed: {}", e);
+            }
+        });
+    }
+
+    /// Start heartbeat task: every interval, exchange node lists with every known peer
+    /// and with any seed node not yet among them
     fn start_heartbeat_task(&self) {
         let node_id = self.node_id.clone();
+        let address = self.address.clone();
+        let seed_nodes = self.config.seed_nodes.clone();
         let nodes = self.nodes.clone();
+        let http = self.http.clone();
         let interval = self.config.heartbeat_interval_seconds;
+        let shutdown = self.shutdown.clone();
 
         tokio::spawn(async move {
             loop {
-                tokio::time::sleep(Duration::from_secs(interval)).await;
-
-                // Update own heartbeat
-                let mut nodes = nodes.write().await;
-                if let Some(node) = nodes.get_mut(&node_id) {
-                    node.last_heartbeat = Instant::now();
-                    node.load = Self::get_current_load();
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = tokio::time::sleep(Duration::from_secs(interval)) => {}
                 }
 
-                // Broadcast heartbeat to other nodes
-                debug!("Sending heartbeat from node: {}", node_id);
+                let peers: BTreeSet<String> = {
+                    let mut nodes = nodes.write().await;
+                    if let Some(node) = nodes.get_mut(&node_id) {
+                        node.last_heartbeat = Instant::now();
+                        node.load = Self::get_current_load();
+                    }
+
+                    nodes
+                        .values()
+                        .filter(|node| node.id != node_id)
+                        .map(|node| node.address.clone())
+                        .chain(seed_nodes.iter().cloned())
+                        .filter(|peer| *peer != address)
+                        .collect()
+                };
+
+                debug!("Sending heartbeat from node {} to {} peers", node_id, peers.len());
+                let (http, nodes, node_id) = (&http, &*nodes, node_id.as_str());
+                futures::future::join_all(peers.iter().map(|peer| async move {
+                    // Missed heartbeats are reported by the health check task
+                    if let Err(e) = exchange_gossip(http, nodes, node_id, peer).await {
+                        debug!("Heartbeat to {} failed: {:#}", peer, e);
+                    }
+                }))
+                .await;
             }
         });
     }
@@ -324,10 +647,14 @@ impl ClusterService {
     fn start_health_check_task(&self) {
         let nodes = self.nodes.clone();
         let health_checker = self.health_checker.clone();
+        let shutdown = self.shutdown.clone();
 
         tokio::spawn(async move {
             loop {
-                tokio::time::sleep(health_checker.check_interval).await;
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = tokio::time::sleep(health_checker.check_interval) => {}
+                }
 
                 let mut nodes = nodes.write().await;
                 let now = Instant::now();
@@ -354,61 +681,95 @@ impl ClusterService {
         });
     }
 
-    /// Start leader election task
+    /// Start leader election task. The leader re-announces itself every election interval;
+    /// other nodes start an election when there's no healthy leader. The interval is
+    /// randomized per round so candidates that split the vote don't collide again.
     fn start_leader_election_task(&self) {
-        let nodes = self.nodes.clone();
-        let leader = self.leader.clone();
-        let consensus = self.consensus.clone();
-        let election_timeout = self.config.election_timeout_seconds;
+        let service = self.clone();
+        let election_timeout = Duration::from_secs(self.config.election_timeout_seconds);
 
         tokio::spawn(async move {
             loop {
-                tokio::time::sleep(Duration::from_secs(election_timeout)).await;
-
-                let current_leader = leader.read().await.clone();
-
-                // Check if we need a new leader
-                let need_election = match &current_leader {
-                    None => true,
-                    Some(leader_id) => {
-                        let nodes = nodes.read().await;
-                        nodes.get(leader_id)
-                            .map(|n| n.status != NodeStatus::Healthy)
-                            .unwrap_or(true)
-                    }
-                };
+                let jitter = election_timeout.mul_f64(rand::random::<f64>());
+                tokio::select! {
+                    _ = service.shutdown.cancelled() => break,
+                    _ = tokio::time::sleep(election_timeout + jitter) => {}
+                }
+                service.election_round().await;
+            }
+        });
+    }
 
-                if need_election {
-                    info!("Starting leader election");
+    /// One round of the election task: a leader re-announces itself, and any other node
+    /// without a healthy leader stands for election
+    async fn election_round(&self) {
+        let node_id = &self.node_id;
+        let current_leader = self.leader.read().await.clone();
+        let peers: Vec<NodeInfo> = self.nodes.read().await
+            .values()
+            .filter(|n| n.id != *node_id)
+            .cloned()
+            .collect();
+
+        if current_leader.as_deref() == Some(node_id.as_str()) {
+            let announcement = LeaderAnnouncement {
+                term: self.consensus.current_term().await,
+                leader_id: node_id.clone(),
+            };
+            if !announce_leadership(&self.http, &peers, &announcement).await {
+                info!("Stepping down as leader of term {}", announcement.term);
+                *self.leader.write().await = None;
+                assign_roles(&mut *self.nodes.write().await, None);
+            }
+            return;
+        }
 
-                    let nodes_list: Vec<NodeInfo> = nodes.read().await
-                        .values()
-                        .filter(|n| n.status == NodeStatus::Healthy)
-                        .cloned()
-                        .collect();
-
-                    match consensus.elect_leader(&nodes_list).await {
-                        Ok(new_leader) => {
-                            info!("New leader elected: {}", new_leader);
-                            *leader.write().await = Some(new_leader.clone());
-
-                            // Update node roles
-                            let mut nodes = nodes.write().await;
-                            for (id, node) in nodes.iter_mut() {
-                                node.role = if id == &new_leader {
-                                    NodeRole::Leader
-                                } else {
-                                    NodeRole::Follower
-                                };
-                            }
-                        }
-                        Err(e) => {
-                            error!("Leader election failed: {}", e);
-                        }
-                    }
+        // Check if we need a new leader
+        let need_election = match &current_leader {
+            None => true,
+            Some(leader_id) => {
+                let nodes = self.nodes.read().await;
+                nodes.get(leader_id)
+                    .map(|n| n.status != NodeStatus::Healthy)
+                    .unwrap_or(true)
+            }
+        };
+        if !need_election {
+            return;
+        }
+
+        info!("Starting leader election");
+
+        // Every known node counts towards the majority, reachable or not
+        let nodes_list: Vec<NodeInfo> = {
+            let mut nodes = self.nodes.write().await;
+            if let Some(node) = nodes.get_mut(node_id) {
+                node.role = NodeRole::Candidate;
+            }
+            nodes.values().cloned().collect()
+        };
+
+        match self.consensus.elect_leader(&nodes_list).await {
+            Ok(new_leader) => {
+                info!("New leader elected: {}", new_leader);
+                *self.leader.write().await = Some(new_leader.clone());
+                assign_roles(&mut *self.nodes.write().await, Some(&new_leader));
+
+                if new_leader == *node_id {
+                    let announcement = LeaderAnnouncement {
+                        term: self.consensus.current_term().await,
+                        leader_id: node_id.clone(),
+                    };
+                    announce_leadership(&self.http, &peers, &announcement).await;
                 }
             }
-        });
+            Err(e) => {
+                warn!("Leader election failed: {:#}", e);
+                if let Some(node) = self.nodes.write().await.get_mut(node_id) {
+                    node.role = NodeRole::Follower;
+                }
+            }
+        }
     }
 
     /// Get current system load
@@ -447,35 +808,47 @@ impl ClusterService {
             .collect()
     }
 
-    /// Replicate data across the cluster
+    /// Replicate data across the cluster: ship it to every other known node, and apply it
+    /// here once enough of them have. Only peers that applied it count as acks. With
+    /// `Eventual` consistency it's applied here straight away and the sends continue in
+    /// the background. A write that misses its acks isn't applied here, but peers that
+    /// acked keep it.
     pub async fn replicate(&self, data: ReplicationData) -> Result<()> {
         debug!("Replicating data: {}", data.id);
 
-        let healthy_nodes = self.get_healthy_nodes().await;
-        let required_acks = match self.state_replicator.consistency_level {
-            ConsistencyLevel::Strong => healthy_nodes.len(),
-            ConsistencyLevel::Quorum => (healthy_nodes.len() + 1) / 2,
-            ConsistencyLevel::Weak => 1,
-            ConsistencyLevel::Eventual => 0,
-        };
-
-        let mut acks = 0;
-        for node in healthy_nodes {
-            if node.id == self.node_id {
-                continue; // Skip self
-            }
+        self.state_replicator.can_apply(&data).await.map_err(|e| anyhow::anyhow!(e))?;
 
-            // In real implementation, would send data to node
-            match self.send_replication_data(&node.address, &data).await {
-                Ok(_) => {
-                    acks += 1;
-                    if acks >= required_acks {
-                        break;
+        // Every known peer, reachable or not, so an unreachable one can't weaken Strong
+        let peers: Vec<NodeInfo> = self.nodes.read().await
+            .values()
+            .filter(|node| node.id != self.node_id && node.status != NodeStatus::Leaving)
+            .cloned()
+            .collect();
+        let required_acks = self.state_replicator.required_acks(peers.len());
+
+        if let ConsistencyLevel::Eventual = self.state_replicator.consistency_level {
+            self.state_replicator.apply(data.clone()).await.map_err(|e| anyhow::anyhow!(e))?;
+            let http = self.http.clone();
+            tokio::spawn(async move {
+                for node in peers {
+                    if let Err(e) = send_replication_data(&http, &node.address, &data).await {
+                        debug!("Failed to replicate {} to {}: {:#}", data.id, node.id, e);
                     }
                 }
-                Err(e) => {
-                    warn!("Failed to replicate to {}: {}", node.id, e);
-                }
+            });
+            return Ok(());
+        }
+
+        let responses = futures::future::join_all(
+            peers.iter().map(|node| send_replication_data(&self.http, &node.address, &data)),
+        )
+        .await;
+
+        let mut acks = 0;
+        for (node, response) in peers.iter().zip(responses) {
+            match response {
+                Ok(()) => acks += 1,
+                Err(e) => warn!("Failed to replicate {} to {}: {:#}", data.id, node.id, e),
             }
         }
 
@@ -486,38 +859,83 @@ impl ClusterService {
             ));
         }
 
-        Ok(())
+        self.state_replicator.apply(data).await.map_err(|e| anyhow::anyhow!(e))
     }
 
-    /// Send replication data to a node
-    async fn send_replication_data(&self, _address: &str, _data: &ReplicationData) -> Result<()> {
-        // In real implementation, would make network request
-        Ok(())
+    /// Latest replicated version of an item, from this node or a peer
+    pub async fn get_replicated(&self, id: &str) -> Option<ReplicationData> {
+        self.state_replicator.applied.read().await.get(id).cloned()
     }
 
-    /// Load balance a request
-    pub async fn select_node(&self, strategy: &LoadBalancingStrategy) -> Option<NodeInfo> {
-        let nodes = self.get_healthy_nodes().await;
+    /// Load balance a request across the healthy nodes with the configured strategy.
+    /// `ConsistentHashing` needs a key to hash, so without one it falls back to
+    /// round-robin; see `select_node_for_key`.
+    pub async fn select_node(&self) -> Option<NodeInfo> {
+        self.select_node_with(&self.load_balancing, None).await
+    }
 
-        match strategy {
-            LoadBalancingStrategy::RoundRobin => {
-                // Simple round-robin
-                nodes.first().cloned()
+    /// Load balance a request for `key`, e.g. a repository name. Under
+    /// `ConsistentHashing` the same key maps to the same node while the set of
+    /// healthy nodes is unchanged; other strategies ignore the key.
+    pub async fn select_node_for_key(&self, key: &str) -> Option<NodeInfo> {
+        self.select_node_with(&self.load_balancing, Some(key)).await
+    }
+
+    /// Load balance a request with a strategy other than the configured one
+    pub async fn select_node_with(&self, strategy: &LoadBalancingStrategy, key: Option<&str>) -> Option<NodeInfo> {
+        let mut nodes = self.get_healthy_nodes().await;
+        if nodes.is_empty() {
+            return None;
+        }
+        // Rotation and weights are over a stable order, not the map's
+        nodes.sort_by(|a, b| a.id.cmp(&b.id));
+
+        match (strategy, key) {
+            (LoadBalancingStrategy::RoundRobin, _) | (LoadBalancingStrategy::ConsistentHashing, None) => {
+                // Taken modulo the current count, so nodes coming and going is fine
+                let index = self.round_robin_index.fetch_add(1, Ordering::Relaxed) % nodes.len();
+                Some(nodes.swap_remove(index))
             }
-            LoadBalancingStrategy::LeastConnections => {
+            (LoadBalancingStrategy::LeastConnections, _) => {
                 // Select node with least connections
                 nodes.into_iter()
                     .min_by_key(|n| n.load.active_connections)
             }
-            LoadBalancingStrategy::Random => {
+            (LoadBalancingStrategy::WeightedRoundRobin, _) => self.select_weighted(nodes),
+            (LoadBalancingStrategy::Random, _) => {
                 // Random selection
                 use rand::seq::SliceRandom;
                 nodes.choose(&mut rand::thread_rng()).cloned()
             }
-            _ => nodes.first().cloned(),
+            (LoadBalancingStrategy::ConsistentHashing, Some(key)) => select_on_ring(nodes, key),
         }
     }
 
+    /// Smooth weighted round-robin: every node gains its weight each round and the
+    /// node with the most is picked and pays back the total, so a node with twice
+    /// the weight is picked twice as often without being picked twice in a row.
+    fn select_weighted(&self, nodes: Vec<NodeInfo>) -> Option<NodeInfo> {
+        let mut current = self.weighted_round_robin.lock().unwrap();
+        current.retain(|id, _| nodes.iter().any(|node| &node.id == id));
+
+        let total: i64 = nodes.iter().map(|node| node.capacity.weight()).sum();
+        let mut selected: Option<(usize, i64)> = None;
+        for (index, node) in nodes.iter().enumerate() {
+            let weight = current.entry(node.id.clone()).or_insert(0);
+            *weight += node.capacity.weight();
+            if selected.is_none_or(|(_, best)| *weight > best) {
+                selected = Some((index, *weight));
+            }
+        }
+
+        let (index, _) = selected?;
+        let node = nodes.into_iter().nth(index)?;
+        if let Some(weight) = current.get_mut(&node.id) {
+            *weight -= total;
+        }
+        Some(node)
+    }
+
     /// Gracefully leave the cluster
     pub async fn leave(&self) -> Result<()> {
         info!("Node {} leaving cluster", self.node_id);
@@ -536,12 +954,14 @@ impl ClusterService {
             // Trigger new election
         }
 
-        // Notify other nodes
+        // Peers drop us from their node lists when they see us leaving
         let nodes = self.get_healthy_nodes().await;
         for node in nodes {
             if node.id != self.node_id {
-                // Notify node about our departure
                 debug!("Notifying {} about our departure", node.id);
+                if let Err(e) = self.contact_node(&node.address).await {
+                    warn!("Failed to notify {} about our departure: {:#}", node.id, e);
+                }
             }
         }
 
@@ -549,32 +969,382 @@ impl ClusterService {
     }
 }
 
+/// State behind the gossip endpoint
+#[derive(Clone)]
+struct GossipEndpoint {
+    node_id: String,
+    nodes: Arc<RwLock<HashMap<String, NodeInfo>>>,
+    leader: Arc<RwLock<Option<String>>>,
+    consensus: Arc<Box<dyn ConsensusProtocol>>,
+    state_replicator: Arc<StateReplicator>,
+}
+
+/// HTTP client for cluster requests, sending the cluster secret with each one
+fn cluster_client(secret: &str) -> Result<reqwest::Client> {
+    let mut authorization = HeaderValue::from_str(&format!("Bearer {}", secret))
+        .context("cluster.secret must be printable ASCII")?;
+    authorization.set_sensitive(true);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::AUTHORIZATION, authorization);
+    Ok(reqwest::Client::builder().timeout(GOSSIP_TIMEOUT).default_headers(headers).build()?)
+}
+
+/// Refuse cluster requests that don't carry the cluster secret
+async fn require_cluster_secret(State(secret): State<Arc<str>>, request: Request, next: Next) -> Response {
+    let presented = request.headers().get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match presented {
+        Some(presented) if bool::from(presented.as_bytes().ct_eq(secret.as_bytes())) => next.run(request).await,
+        _ => {
+            warn!("Refused cluster request to {} without the cluster secret", request.uri().path());
+            StatusCode::UNAUTHORIZED.into_response()
+        }
+    }
+}
+
+/// Apply data replicated by a peer, acking only once it's applied
+async fn receive_replication_data(
+    State(endpoint): State<GossipEndpoint>,
+    Json(data): Json<ReplicationData>,
+) -> Json<ReplicationAck> {
+    let id = data.id.clone();
+    let error = endpoint.state_replicator.apply(data).await.err();
+    if let Some(error) = &error {
+        warn!("Rejected replicated data {}: {}", id, error);
+    }
+
+    Json(ReplicationAck {
+        node_id: endpoint.node_id.clone(),
+        applied: error.is_none(),
+        error,
+    })
+}
+
+/// Answer a candidate's vote request. Seeing a newer term means another election has
+/// started, so a leader stops leading.
+async fn receive_vote_request(
+    State(endpoint): State<GossipEndpoint>,
+    Json(request): Json<VoteRequest>,
+) -> Result<Json<VoteResponse>, StatusCode> {
+    let term = endpoint.consensus.current_term().await;
+    let response = endpoint.consensus.request_vote(request).await.map_err(|e| {
+        error!("Failed to answer vote request: {:#}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if response.term > term {
+        let mut leader = endpoint.leader.write().await;
+        if leader.as_deref() == Some(endpoint.node_id.as_str()) {
+            info!("Stepping down as leader, term {} has started", response.term);
+            *leader = None;
+            assign_roles(&mut *endpoint.nodes.write().await, None);
+        }
+    }
+    Ok(Json(response))
+}
+
+/// Follow a leader announcing itself, unless its term is behind ours or we lead this term
+async fn receive_leader_announcement(
+    State(endpoint): State<GossipEndpoint>,
+    Json(announcement): Json<LeaderAnnouncement>,
+) -> Result<Json<LeaderResponse>, StatusCode> {
+    let leading = endpoint.leader.read().await.as_deref() == Some(endpoint.node_id.as_str());
+    let accepted = if leading
+        && announcement.leader_id != endpoint.node_id
+        && announcement.term == endpoint.consensus.current_term().await
+    {
+        warn!("Node {} claims term {}, which we lead", announcement.leader_id, announcement.term);
+        false
+    } else {
+        endpoint.consensus.accept_leader(&announcement).await.map_err(|e| {
+            error!("Failed to accept leader announcement: {:#}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+    };
+
+    if accepted {
+        let mut leader = endpoint.leader.write().await;
+        if leader.as_deref() != Some(announcement.leader_id.as_str()) {
+            info!("Following leader {} in term {}", announcement.leader_id, announcement.term);
+            *leader = Some(announcement.leader_id.clone());
+            assign_roles(&mut *endpoint.nodes.write().await, Some(&announcement.leader_id));
+        }
+    }
+    Ok(Json(LeaderResponse {
+        term: endpoint.consensus.current_term().await,
+        accepted,
+    }))
+}
+
+/// Tell every peer we lead `term`; false when one of them is already in a newer term
+async fn announce_leadership(http: &reqwest::Client, peers: &[NodeInfo], announcement: &LeaderAnnouncement) -> bool {
+    let responses = futures::future::join_all(peers.iter().map(|node| async move {
+        let response: LeaderResponse = http
+            .post(format!("http://{}{}", node.address, LEADER_PATH))
+            .json(announcement)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        anyhow::Ok(response)
+    }))
+    .await;
+
+    let mut still_leader = true;
+    for (node, response) in peers.iter().zip(responses) {
+        match response {
+            Ok(response) if response.term > announcement.term => {
+                info!("Node {} is at term {}, ahead of our term {}", node.id, response.term, announcement.term);
+                still_leader = false;
+            }
+            Ok(_) => {}
+            Err(e) => debug!("Leader announcement to {} failed: {:#}", node.id, e),
+        }
+    }
+    still_leader
+}
+
+/// Mark `leader` as the leader and every other node as a follower
+fn assign_roles(nodes: &mut HashMap<String, NodeInfo>, leader: Option<&str>) {
+    for (id, node) in nodes.iter_mut() {
+        node.role = if Some(id.as_str()) == leader {
+            NodeRole::Leader
+        } else {
+            NodeRole::Follower
+        };
+    }
+}
+
+/// Take a peer's heartbeat and answer with what we know
+async fn receive_gossip(
+    State(endpoint): State<GossipEndpoint>,
+    Json(message): Json<GossipMessage>,
+) -> Result<Json<GossipMessage>, StatusCode> {
+    debug!("Heartbeat from node {}", message.sender.id);
+    merge_gossip(&endpoint.nodes, &endpoint.node_id, message).await;
+    gossip_message(&endpoint.nodes, &endpoint.node_id)
+        .await
+        .map(Json)
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)
+}
+
+/// POST our node list to the gossip endpoint at `address` and merge the peer's answer
+async fn exchange_gossip(
+    http: &reqwest::Client,
+    nodes: &RwLock<HashMap<String, NodeInfo>>,
+    node_id: &str,
+    address: &str,
+) -> Result<()> {
+    let Some(message) = gossip_message(nodes, node_id).await else {
+        return Ok(());
+    };
+
+    let reply: GossipMessage = http
+        .post(format!("http://{}{}", address, GOSSIP_PATH))
+        .json(&message)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    merge_gossip(nodes, node_id, reply).await;
+    Ok(())
+}
+
+/// Ship replicated data to the node at `address`, failing unless it applied it
+async fn send_replication_data(http: &reqwest::Client, address: &str, data: &ReplicationData) -> Result<()> {
+    let ack: ReplicationAck = http
+        .post(format!("http://{}{}", address, REPLICATE_PATH))
+        .json(data)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    if !ack.applied {
+        return Err(anyhow::anyhow!(
+            "node {} rejected it: {}",
+            ack.node_id,
+            ack.error.unwrap_or_default()
+        ));
+    }
+    Ok(())
+}
+
+/// Our own entry plus every node we know; None before we've registered
+async fn gossip_message(nodes: &RwLock<HashMap<String, NodeInfo>>, node_id: &str) -> Option<GossipMessage> {
+    let nodes = nodes.read().await;
+    let sender = nodes.get(node_id)?.clone();
+    Some(GossipMessage {
+        sender,
+        nodes: nodes.values().cloned().collect(),
+    })
+}
+
+/// Merge a gossip message into `nodes`. The sender talked to us directly, so its
+/// heartbeat is refreshed; nodes it only knows about are added when they're new and
+/// otherwise left to our own heartbeats. Status and role stay ours to decide, through
+/// the health check and election tasks.
+async fn merge_gossip(nodes: &RwLock<HashMap<String, NodeInfo>>, node_id: &str, message: GossipMessage) {
+    let GossipMessage { sender, nodes: known } = message;
+    // A seed list that includes this node
+    if sender.id == node_id {
+        return;
+    }
+
+    let now = Instant::now();
+    let mut nodes = nodes.write().await;
+
+    for node in known {
+        if node.id == node_id || node.id == sender.id || node.status == NodeStatus::Leaving {
+            continue;
+        }
+        if !nodes.contains_key(&node.id) {
+            info!("Discovered node {} at {}", node.id, node.address);
+            nodes.insert(node.id.clone(), NodeInfo { last_heartbeat: now, ..node });
+        }
+    }
+
+    if sender.status == NodeStatus::Leaving {
+        if nodes.remove(&sender.id).is_some() {
+            info!("Node {} left the cluster", sender.id);
+        }
+        return;
+    }
+
+    match nodes.get_mut(&sender.id) {
+        Some(node) => {
+            node.address = sender.address;
+            node.version = sender.version;
+            node.capacity = sender.capacity;
+            node.load = sender.load;
+            node.metadata = sender.metadata;
+            node.last_heartbeat = now;
+        }
+        None => {
+            info!("Node {} joined at {}", sender.id, sender.address);
+            nodes.insert(sender.id.clone(), NodeInfo { last_heartbeat: now, ..sender });
+        }
+    }
+}
+
 impl RaftConsensus {
-    pub fn new(node_id: String) -> Self {
-        Self {
+    /// Load the term and vote this node persisted before a restart
+    pub async fn load(
+        node_id: String,
+        storage: Arc<dyn StorageBackend>,
+        http: reqwest::Client,
+        members: usize,
+    ) -> Result<Self> {
+        let state = match storage.get_blob(&Self::state_key(&node_id)).await? {
+            Some(data) => serde_json::from_slice(&data).context("Invalid persisted Raft state")?,
+            None => RaftState::default(),
+        };
+        debug!("Raft node {} starting at term {}", node_id, state.term);
+
+        Ok(Self {
             node_id,
-            term: Arc::new(RwLock::new(0)),
-            voted_for: Arc::new(RwLock::new(None)),
+            state: Arc::new(RwLock::new(state)),
             log: Arc::new(RwLock::new(Vec::new())),
+            storage,
+            http,
+            members,
+        })
+    }
+
+    fn state_key(node_id: &str) -> String {
+        format!("cluster/raft/{}", node_id)
+    }
+
+    async fn persist(&self, state: &RaftState) -> Result<()> {
+        self.storage
+            .put_blob(&Self::state_key(&self.node_id), serde_json::to_vec(state)?.into())
+            .await
+    }
+
+    /// Length of the log and the term of its last entry
+    async fn last_log(&self) -> (u64, u64) {
+        let log = self.log.read().await;
+        (log.len() as u64, log.last().map_or(0, |entry| entry.term))
+    }
+
+    /// Move to a newer term seen on another node, giving up any vote from the old one
+    async fn observe_term(&self, term: u64) -> Result<()> {
+        let mut state = self.state.write().await;
+        if term > state.term {
+            *state = RaftState { term, voted_for: None };
+            self.persist(&state).await?;
         }
+        Ok(())
+    }
+
+    async fn send_vote_request(&self, node: &NodeInfo, request: &VoteRequest) -> Result<VoteResponse> {
+        Ok(self
+            .http
+            .post(format!("http://{}{}", node.address, VOTE_PATH))
+            .json(request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
     }
 }
 
 #[async_trait]
 impl ConsensusProtocol for RaftConsensus {
     async fn elect_leader(&self, nodes: &[NodeInfo]) -> Result<String> {
-        // Simplified Raft leader election
-        let mut term = self.term.write().await;
-        *term += 1;
+        let request = {
+            let mut state = self.state.write().await;
+            state.term += 1;
+            state.voted_for = Some(self.node_id.clone());
+            self.persist(&state).await?;
+
+            let (last_log_index, last_log_term) = self.last_log().await;
+            VoteRequest {
+                term: state.term,
+                candidate_id: self.node_id.clone(),
+                last_log_index,
+                last_log_term,
+            }
+        };
 
-        // Vote for self
-        *self.voted_for.write().await = Some(self.node_id.clone());
+        let peers: Vec<&NodeInfo> = nodes.iter().filter(|node| node.id != self.node_id).collect();
+        let cluster_size = (peers.len() + 1).max(self.members);
+        let majority = cluster_size / 2 + 1;
+        info!("Requesting votes for term {} from {} peers", request.term, peers.len());
+
+        let responses =
+            futures::future::join_all(peers.iter().map(|node| self.send_vote_request(node, &request))).await;
+
+        // Our own vote
+        let mut votes = 1;
+        for (node, response) in peers.iter().zip(responses) {
+            match response {
+                Ok(response) if response.term > request.term => {
+                    self.observe_term(response.term).await?;
+                    return Err(anyhow::anyhow!(
+                        "Node {} is at term {}, ahead of our term {}",
+                        node.id, response.term, request.term
+                    ));
+                }
+                Ok(response) if response.vote_granted => votes += 1,
+                Ok(_) => debug!("Node {} declined our vote for term {}", node.id, request.term),
+                Err(e) => debug!("Vote request to {} failed: {:#}", node.id, e),
+            }
+        }
 
-        // In real implementation, would request votes from other nodes
-        // For now, just select the first healthy node
-        nodes.first()
-            .map(|n| n.id.clone())
-            .ok_or_else(|| anyhow::anyhow!("No healthy nodes available for election"))
+        if votes < majority {
+            return Err(anyhow::anyhow!(
+                "No majority for term {}: {} of {} votes, {} needed",
+                request.term, votes, cluster_size, majority
+            ));
+        }
+        Ok(self.node_id.clone())
     }
 
     async fn propose(&self, proposal: Proposal) -> Result<bool> {
@@ -583,7 +1353,7 @@ impl ConsensusProtocol for RaftConsensus {
         let index = log.len() as u64;
         log.push(LogEntry {
             index,
-            term: *self.term.read().await,
+            term: self.state.read().await.term,
             command: serde_json::to_vec(&proposal)?,
             timestamp: chrono::Utc::now(),
         });
@@ -600,15 +1370,77 @@ impl ConsensusProtocol for RaftConsensus {
     fn name(&self) -> String {
         "Raft".to_string()
     }
-}
 
-impl GossipProtocol {
-    pub fn new(node_id: String) -> Self {
-        Self {
-            node_id,
-            peers: Arc::new(RwLock::new(HashSet::new())),
-            state: Arc::new(RwLock::new(HashMap::new())),
+    /// Grant at most one vote per term, and only to a candidate whose log is at least as
+    /// up to date as ours
+    async fn request_vote(&self, request: VoteRequest) -> Result<VoteResponse> {
+        let mut state = self.state.write().await;
+        if request.term < state.term {
+            return Ok(VoteResponse {
+                term: state.term,
+                vote_granted: false,
+            });
+        }
+
+        let mut changed = false;
+        if request.term > state.term {
+            *state = RaftState {
+                term: request.term,
+                voted_for: None,
+            };
+            changed = true;
+        }
+
+        let (last_log_index, last_log_term) = self.last_log().await;
+        let up_to_date = (request.last_log_term, request.last_log_index) >= (last_log_term, last_log_index);
+        let vote_granted =
+            up_to_date && state.voted_for.as_ref().is_none_or(|candidate| *candidate == request.candidate_id);
+        if vote_granted && state.voted_for.is_none() {
+            state.voted_for = Some(request.candidate_id.clone());
+            changed = true;
+        }
+
+        if changed {
+            self.persist(&state).await?;
+        }
+        debug!(
+            "{} vote for {} in term {}",
+            if vote_granted { "Granted" } else { "Declined" },
+            request.candidate_id,
+            state.term
+        );
+        Ok(VoteResponse {
+            term: state.term,
+            vote_granted,
+        })
+    }
+
+    /// Follow a leader of our term or a newer one. Within our term that's only the node
+    /// we voted for, or any node when we stood ourselves and lost.
+    async fn accept_leader(&self, announcement: &LeaderAnnouncement) -> Result<bool> {
+        {
+            let state = self.state.read().await;
+            if announcement.term < state.term {
+                return Ok(false);
+            }
+            if announcement.term == state.term
+                && let Some(voted_for) = &state.voted_for
+                && *voted_for != announcement.leader_id
+                && *voted_for != self.node_id
+            {
+                warn!(
+                    "Node {} claims term {}, in which we voted for {}",
+                    announcement.leader_id, announcement.term, voted_for
+                );
+                return Ok(false);
+            }
         }
+        self.observe_term(announcement.term).await?;
+        Ok(true)
+    }
+
+    async fn current_term(&self) -> u64 {
+        self.state.read().await.term
     }
 }
 
@@ -635,4 +1467,230 @@ impl ConsensusProtocol for GossipProtocol {
     fn name(&self) -> String {
         "Gossip".to_string()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::MemoryStorage;
+
+    /// A node on its own, its gossip endpoint on an ephemeral loopback port
+    async fn cluster(load_balancing_strategy: &str) -> ClusterService {
+        let config = ClusterConfig {
+            enabled: true,
+            bind_address: "127.0.0.1:0".to_string(),
+            secret: Some("cluster-secret".to_string()),
+            load_balancing_strategy: load_balancing_strategy.to_string(),
+            ..crate::Config::default().cluster.unwrap()
+        };
+        let storage = Arc::new(MemoryStorage::new(None));
+        ClusterService::new(config, storage, CancellationToken::new()).await.unwrap()
+    }
+
+    fn node(id: &str, cpu_cores: u32) -> NodeInfo {
+        NodeInfo {
+            id: id.to_string(),
+            address: format!("{}:7000", id),
+            role: NodeRole::Follower,
+            status: NodeStatus::Healthy,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            capacity: NodeCapacity { cpu_cores, memory_gb: 16, storage_gb: 100, network_bandwidth_mbps: 1000 },
+            load: ClusterService::get_current_load(),
+            last_heartbeat: Instant::now(),
+            metadata: HashMap::new(),
+        }
+    }
+
+    /// Replace the nodes the cluster knows; its own entry is still joining, so never selected
+    async fn set_nodes(cluster: &ClusterService, nodes: &[NodeInfo]) {
+        let mut known = cluster.nodes.write().await;
+        known.retain(|id, _| *id == cluster.node_id);
+        known.extend(nodes.iter().map(|node| (node.id.clone(), node.clone())));
+    }
+
+    async fn selections(cluster: &ClusterService, count: usize) -> BTreeMap<String, usize> {
+        let mut selections = BTreeMap::new();
+        for _ in 0..count {
+            *selections.entry(cluster.select_node().await.unwrap().id).or_default() += 1;
+        }
+        selections
+    }
+
+    #[tokio::test]
+    async fn round_robin_spreads_selections_evenly_over_healthy_nodes() {
+        let cluster = cluster("round_robin").await;
+        set_nodes(&cluster, &[node("a", 1), node("b", 8), node("c", 1)]).await;
+        let spread = selections(&cluster, 300).await;
+        assert_eq!(spread, BTreeMap::from([("a".into(), 100), ("b".into(), 100), ("c".into(), 100)]));
+
+        // Unhealthy nodes are skipped, and the shrunk node set doesn't trip the index
+        let mut unhealthy = node("c", 1);
+        unhealthy.status = NodeStatus::Unhealthy;
+        set_nodes(&cluster, &[node("a", 1), node("b", 8), unhealthy]).await;
+        let spread = selections(&cluster, 300).await;
+        assert_eq!(spread, BTreeMap::from([("a".into(), 150), ("b".into(), 150)]));
+
+        set_nodes(&cluster, &[]).await;
+        assert!(cluster.select_node().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn unknown_strategies_fall_back_to_round_robin() {
+        let cluster = cluster("fastest").await;
+        set_nodes(&cluster, &[node("a", 1), node("b", 4)]).await;
+        let selections = selections(&cluster, 10).await;
+        assert_eq!(selections, BTreeMap::from([("a".into(), 5), ("b".into(), 5)]));
+    }
+
+    #[tokio::test]
+    async fn weighted_round_robin_follows_capacity() {
+        let cluster = cluster("weighted_round_robin").await;
+        set_nodes(&cluster, &[node("a", 1), node("b", 2), node("c", 4)]).await;
+
+        let selections = selections(&cluster, 700).await;
+        assert_eq!(selections, BTreeMap::from([("a".into(), 100), ("b".into(), 200), ("c".into(), 400)]));
+    }
+
+    #[tokio::test]
+    async fn consistent_hashing_keeps_keys_on_their_node() {
+        let cluster = cluster("consistent_hashing").await;
+        set_nodes(&cluster, &[node("a", 1), node("b", 1), node("c", 1)]).await;
+
+        let keys: Vec<String> = (0..100).map(|index| format!("team/app-{}", index)).collect();
+        let mut owners = HashMap::new();
+        for key in &keys {
+            owners.insert(key, cluster.select_node_for_key(key).await.unwrap().id);
+        }
+        for key in &keys {
+            assert_eq!(cluster.select_node_for_key(key).await.unwrap().id, owners[key]);
+        }
+        let used: BTreeSet<&String> = owners.values().collect();
+        assert_eq!(used.len(), 3);
+
+        // Losing a node only moves the keys it owned
+        set_nodes(&cluster, &[node("a", 1), node("b", 1)]).await;
+        for key in &keys {
+            let owner = cluster.select_node_for_key(key).await.unwrap().id;
+            if owners[key] != "c" {
+                assert_eq!(owner, owners[key]);
+            }
+        }
+    }
+
+    /// A node on an ephemeral loopback port, joining through `seed_nodes`
+    fn node_config(node_id: &str, seed_nodes: &[&str]) -> ClusterConfig {
+        ClusterConfig {
+            enabled: true,
+            node_id: node_id.to_string(),
+            bind_address: "127.0.0.1:0".to_string(),
+            seed_nodes: seed_nodes.iter().map(|seed| seed.to_string()).collect(),
+            secret: Some("cluster-secret".to_string()),
+            ..crate::Config::default().cluster.unwrap()
+        }
+    }
+
+    async fn raft_node(node_id: &str, seed_nodes: &[&str]) -> ClusterService {
+        let storage = Arc::new(MemoryStorage::new(None));
+        ClusterService::new(node_config(node_id, seed_nodes), storage, CancellationToken::new()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn a_node_needs_a_majority_of_its_seeds_to_lead() {
+        // Neither seed is up, so the node knows only itself
+        let node = raft_node("node-a", &["127.0.0.1:1", "127.0.0.1:2"]).await;
+        node.election_round().await;
+        assert!(!node.is_leader().await);
+        assert_eq!(node.consensus.current_term().await, 1);
+    }
+
+    #[tokio::test]
+    async fn a_split_vote_elects_one_leader_per_term() {
+        let a = raft_node("node-a", &[]).await;
+        let b = raft_node("node-b", &[&a.address]).await;
+        let c = raft_node("node-c", &[&a.address]).await;
+        // b joined before c, so it learns about c from the seed again
+        b.contact_node(&a.address).await.unwrap();
+
+        // a and b stand in the same term; c votes for whichever asks first
+        futures::future::join(a.election_round(), b.election_round()).await;
+        let (winner, loser) = if a.is_leader().await { (&a, &b) } else { (&b, &a) };
+        assert!(winner.is_leader().await);
+        assert!(!loser.is_leader().await && !c.is_leader().await);
+        for node in [&a, &b, &c] {
+            assert_eq!(node.get_leader().await.as_deref(), Some(winner.node_id.as_str()));
+        }
+
+        // Nobody else can lead that term, whether a node voted for the leader or is the leader
+        let claim = LeaderAnnouncement {
+            term: winner.consensus.current_term().await,
+            leader_id: loser.node_id.clone(),
+        };
+        for node in [winner, &c] {
+            let response: LeaderResponse = loser.http
+                .post(format!("http://{}{}", node.address, LEADER_PATH))
+                .json(&claim)
+                .send()
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+            assert!(!response.accepted, "{} followed a second leader", node.node_id);
+            assert_eq!(node.get_leader().await.as_deref(), Some(winner.node_id.as_str()));
+        }
+    }
+
+    fn replication_data(version: u64) -> ReplicationData {
+        let data = format!("version {}", version).into_bytes();
+        ReplicationData {
+            id: "config".to_string(),
+            type_: ReplicationType::Configuration,
+            checksum: ReplicationData::checksum_of(&data),
+            data,
+            version,
+        }
+    }
+
+    /// A node replicating at `consistency_level` to `peer`, also knowing a node that's down
+    async fn writer(
+        node_id: &str,
+        consistency_level: ConsistencyLevel,
+        peer: &ClusterService,
+        storage: Arc<MemoryStorage>,
+    ) -> ClusterService {
+        let config = ClusterConfig { consistency_level, ..node_config(node_id, &[&peer.address]) };
+        let writer = ClusterService::new(config, storage, CancellationToken::new()).await.unwrap();
+        let down = NodeInfo { address: "127.0.0.1:1".to_string(), ..node("node-down", 4) };
+        writer.nodes.write().await.insert(down.id.clone(), down);
+        writer
+    }
+
+    #[tokio::test]
+    async fn strong_writes_fail_with_a_peer_down_and_are_not_applied() {
+        let peer = raft_node("node-b", &[]).await;
+        let writer = writer("node-a", ConsistencyLevel::Strong, &peer, Arc::new(MemoryStorage::new(None))).await;
+
+        assert!(writer.replicate(replication_data(1)).await.is_err());
+        assert!(writer.get_replicated("config").await.is_none());
+        // The peer that's up applied it, and keeps it
+        assert_eq!(peer.get_replicated("config").await.unwrap().version, 1);
+    }
+
+    #[tokio::test]
+    async fn weak_writes_succeed_with_a_peer_down_and_survive_a_restart() {
+        let peer = raft_node("node-b", &[]).await;
+        let storage = Arc::new(MemoryStorage::new(None));
+        let writer = writer("node-a", ConsistencyLevel::Weak, &peer, storage.clone()).await;
+
+        writer.replicate(replication_data(1)).await.unwrap();
+        assert_eq!(writer.get_replicated("config").await.unwrap().version, 1);
+        assert_eq!(peer.get_replicated("config").await.unwrap().version, 1);
+        writer.shutdown.cancel();
+
+        // Applied versions are read back, so an older one is still refused after a restart
+        let config = node_config("node-a", &[]);
+        let restarted = ClusterService::new(config, storage, CancellationToken::new()).await.unwrap();
+        assert_eq!(restarted.get_replicated("config").await.unwrap().data, b"version 1");
+        assert!(restarted.replicate(replication_data(0)).await.is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::Path;
@@ -20,6 +20,13 @@ pub struct Config {
     pub rbac: Option<RbacConfig>,
     pub audit: Option<AuditConfig>,
     pub cluster: Option<ClusterConfig>,
+    pub telemetry: Option<TelemetryConfig>,
+    /// Namespaces served as pull-through caches of upstream registries
+    #[serde(default)]
+    pub proxy: Vec<ProxyConfig>,
+    pub replication: Option<ReplicationConfig>,
+    pub notifications: Option<NotificationsConfig>,
+    pub scanning: Option<ScanningConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,6 +35,48 @@ pub struct ServerConfig {
     pub ui_addr: String,
     pub workers: Option<usize>,
     pub max_connections: Option<usize>,
+    /// Serve the registry API over HTTPS
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// Serve the web UI over HTTPS
+    #[serde(default)]
+    pub ui_tls: Option<TlsConfig>,
+    /// How long in-flight requests may run after SIGTERM/SIGINT before connections are closed
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+    /// Reverse proxies whose `X-Forwarded-For` is believed, e.g. "10.0.0.0/8";
+    /// other clients are identified by their connection address
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+    /// Base URL clients reach the registry at, e.g. "https://registry.example.com",
+    /// when it differs from the listener's scheme and the request's `Host`, such
+    /// as behind a TLS-terminating proxy. Token challenges point clients here.
+    #[serde(default)]
+    pub external_url: Option<String>,
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    30
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsConfig {
+    /// PEM certificate chain, leaf first
+    pub cert_path: String,
+    /// PEM private key (PKCS#8, PKCS#1 or SEC1)
+    pub key_path: String,
+    /// PEM CA bundle; when set, clients may authenticate with a certificate it issued
+    pub client_ca_path: Option<String>,
+    /// Reject connections that don't present a client certificate
+    #[serde(default)]
+    pub require_client_cert: bool,
+    /// How often to check the certificate files for changes; SIGHUP also reloads them
+    #[serde(default = "default_tls_reload_interval_secs")]
+    pub reload_interval_secs: u64,
+}
+
+fn default_tls_reload_interval_secs() -> u64 {
+    30
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,8 +84,17 @@ pub struct StorageConfig {
     #[serde(rename = "type")]
     pub storage_type: StorageType,
     pub path: Option<String>,
+    /// Filesystem backend: hash blobs before serving them and fail on a
+    /// mismatch instead of returning corrupt content. Costs an extra read.
+    #[serde(default)]
+    pub verify_on_read: bool,
     pub s3: Option<S3Config>,
     pub ghostbay: Option<GhostBayStorageConfig>,
+    pub gcs: Option<GcsConfig>,
+    pub memory: Option<MemoryStorageConfig>,
+    /// Local disk cache in front of the backend for blob reads
+    #[serde(default)]
+    pub cache: Option<StorageCacheConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,6 +103,8 @@ pub enum StorageType {
     Filesystem,
     S3,
     GhostBay,
+    Gcs,
+    Memory,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -55,6 +115,74 @@ pub struct S3Config {
     pub access_key: String,
     pub secret_key: String,
     pub path_style: bool,
+    /// Attempts per request, including the first; throttling, 5xx responses
+    /// and timeouts are retried with exponential backoff and jitter
+    #[serde(default = "default_s3_max_attempts")]
+    pub max_attempts: u32,
+    /// Backoff before the first retry, doubled on each one after
+    #[serde(default = "default_s3_retry_base_delay_ms")]
+    pub retry_base_delay_ms: u64,
+    #[serde(default = "default_s3_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// Limit on waiting for the first byte of a response
+    #[serde(default = "default_s3_read_timeout_secs")]
+    pub read_timeout_secs: u64,
+    /// Limit on a single attempt, so a hung request cannot pin a client forever; 0 = none
+    #[serde(default = "default_s3_attempt_timeout_secs")]
+    pub attempt_timeout_secs: u64,
+}
+
+fn default_s3_max_attempts() -> u32 {
+    3
+}
+
+fn default_s3_retry_base_delay_ms() -> u64 {
+    100
+}
+
+fn default_s3_connect_timeout_secs() -> u64 {
+    5
+}
+
+fn default_s3_read_timeout_secs() -> u64 {
+    30
+}
+
+fn default_s3_attempt_timeout_secs() -> u64 {
+    120
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageCacheConfig {
+    /// Directory holding cached blobs
+    pub path: String,
+    /// Least recently used blobs are evicted beyond this size
+    #[serde(default = "default_storage_cache_max_size_mb")]
+    pub max_size_mb: u64,
+}
+
+fn default_storage_cache_max_size_mb() -> u64 {
+    10240
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryStorageConfig {
+    /// Cap on the bytes kept in memory; 0 = unlimited
+    #[serde(default)]
+    pub max_size_mb: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GcsConfig {
+    pub bucket: String,
+    /// Service account key file; application default credentials, including
+    /// workload identity, are used when unset
+    pub credentials_file: Option<String>,
+    /// Custom endpoint, e.g. a fake-gcs-server instance
+    pub endpoint: Option<String>,
+    /// Send requests without credentials, for emulators
+    #[serde(default)]
+    pub anonymous: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -86,6 +214,13 @@ pub struct OAuthConfig {
     pub azure: Option<AzureConfig>,
     pub github: Option<GitHubConfig>,
     pub google: Option<GoogleConfig>,
+    /// Scopes granted to users who sign in through an OAuth provider
+    #[serde(default = "default_oauth_scopes")]
+    pub default_scopes: Vec<String>,
+}
+
+fn default_oauth_scopes() -> Vec<String> {
+    vec!["repository:*:pull".to_string()]
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -98,7 +233,16 @@ pub enum AuthMode {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BasicAuthConfig {
-    pub users: Vec<String>, // Format: "username:password"
+    #[serde(default)]
+    pub users: Vec<String>, // Format: "username:$argon2id$..." or "username:$2y$..."
+    /// htpasswd file (bcrypt or argon2 entries), reloaded when it changes
+    pub htpasswd_path: Option<String>,
+    /// How often to check the htpasswd file for changes; SIGHUP also reloads it
+    #[serde(default = "default_htpasswd_reload_interval_secs")]
+    pub htpasswd_reload_interval_secs: u64,
+    /// Accept plaintext passwords; only suitable for local development
+    #[serde(default)]
+    pub allow_plaintext_passwords: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -106,6 +250,39 @@ pub struct OidcConfig {
     pub issuer: String,
     pub client_id: String,
     pub client_secret: String,
+    /// Claim used as the drift username, falling back to `sub`
+    #[serde(default = "default_oidc_username_claim")]
+    pub username_claim: String,
+    /// Claim holding the user's groups
+    #[serde(default = "default_oidc_groups_claim")]
+    pub groups_claim: String,
+    /// Scopes granted to every authenticated OIDC user
+    #[serde(default)]
+    pub default_scopes: Vec<String>,
+    #[serde(default)]
+    pub group_mappings: Vec<OidcGroupMapping>,
+}
+
+/// Roles and scopes granted to members of an OIDC group
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcGroupMapping {
+    pub group: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+fn default_htpasswd_reload_interval_secs() -> u64 {
+    5
+}
+
+fn default_oidc_username_claim() -> String {
+    "preferred_username".to_string()
+}
+
+fn default_oidc_groups_claim() -> String {
+    "groups".to_string()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -114,6 +291,184 @@ pub struct RegistryConfig {
     pub rate_limit_per_hour: u32,
     pub immutable_tags: Vec<String>,
     pub min_age_days: u64,
+    /// Upload sessions idle for longer than this are expired and cleaned up
+    #[serde(default = "default_upload_session_ttl_hours")]
+    pub upload_session_ttl_hours: u64,
+    /// Allow DELETE by tag to untag without removing the manifest
+    #[serde(default)]
+    pub allow_tag_deletion: bool,
+    /// Let unauthenticated clients pull while still requiring auth for pushes
+    #[serde(default)]
+    pub anonymous_pull: bool,
+    #[serde(default)]
+    pub rate_limits: RateLimitConfig,
+}
+
+/// Per-operation overrides for `rate_limit_per_hour`; 0 disables a limit
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    pub pull_per_hour: Option<u32>,
+    pub push_per_hour: Option<u32>,
+    pub list_per_hour: Option<u32>,
+    /// Usernames that are never throttled
+    #[serde(default)]
+    pub exempt_users: Vec<String>,
+    /// Client networks that are never throttled, e.g. "10.0.0.0/8"
+    #[serde(default)]
+    pub exempt_cidrs: Vec<String>,
+}
+
+fn default_upload_session_ttl_hours() -> u64 {
+    24
+}
+
+/// A local namespace mirroring an upstream registry, e.g. `dockerhub/` for
+/// `https://registry-1.docker.io`. Pulls that miss locally are fetched from
+/// upstream and cached; pushes to the namespace are refused.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProxyConfig {
+    /// Leading path segment of proxied repositories, without the trailing slash
+    pub namespace: String,
+    /// Base URL of the upstream registry
+    pub upstream: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// How long a cached tag is served before it is re-validated upstream
+    #[serde(default = "default_proxy_manifest_ttl_secs")]
+    pub manifest_ttl_secs: u64,
+}
+
+fn default_proxy_manifest_ttl_secs() -> u64 {
+    300
+}
+
+/// Copy every pushed manifest, with its blobs, to remote registries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplicationConfig {
+    pub enabled: bool,
+    #[serde(default)]
+    pub targets: Vec<ReplicationTargetConfig>,
+    /// Attempts per job before it is dropped from the queue
+    #[serde(default = "default_replication_max_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubles with each further attempt
+    #[serde(default = "default_replication_retry_base_secs")]
+    pub retry_base_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplicationTargetConfig {
+    pub name: String,
+    /// Base URL of the remote registry
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Repository name patterns to replicate, e.g. "prod/*"; empty replicates everything
+    #[serde(default)]
+    pub repositories: Vec<String>,
+}
+
+fn default_replication_max_attempts() -> u32 {
+    10
+}
+
+fn default_replication_retry_base_secs() -> u64 {
+    30
+}
+
+/// Webhooks notified of pushes and deletes, in the Docker Registry notification format
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationsConfig {
+    #[serde(default)]
+    pub endpoints: Vec<NotificationEndpointConfig>,
+    /// Events buffered per endpoint; further events are dropped while it is full
+    #[serde(default = "default_notification_queue_size")]
+    pub queue_size: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationEndpointConfig {
+    pub name: String,
+    pub url: String,
+    /// Extra headers sent with every delivery, e.g. Authorization
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Actions to deliver ("push", "delete"); empty delivers all
+    #[serde(default)]
+    pub actions: Vec<String>,
+    #[serde(default)]
+    pub ignored_media_types: Vec<String>,
+    #[serde(default = "default_notification_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Attempts per event before it is written to the dead-letter log
+    #[serde(default = "default_notification_max_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubles with each further attempt
+    #[serde(default = "default_notification_backoff_ms")]
+    pub backoff_ms: u64,
+}
+
+fn default_notification_queue_size() -> usize {
+    1000
+}
+
+fn default_notification_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_notification_max_attempts() -> u32 {
+    5
+}
+
+fn default_notification_backoff_ms() -> u64 {
+    1000
+}
+
+/// Vulnerability scanning of pushed images with Trivy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanningConfig {
+    pub enabled: bool,
+    /// Path of the trivy binary; it reads the exported image either way
+    #[serde(default = "default_trivy_path")]
+    pub trivy_path: String,
+    /// Trivy server to look vulnerabilities up on, e.g. "http://trivy:4954";
+    /// the local binary uses its own database when unset
+    pub server_url: Option<String>,
+    /// Token sent to the Trivy server, handed to trivy as `TRIVY_TOKEN`
+    pub server_token: Option<String>,
+    #[serde(default = "default_scan_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Scans run at once; further pushes wait for a free slot
+    #[serde(default = "default_max_concurrent_scans")]
+    pub max_concurrent_scans: usize,
+    /// Deny pulls of images with a vulnerability of this severity or worse
+    pub block_pull_above_severity: Option<Severity>,
+    /// Repository name patterns, e.g. "dev/*", that are never blocked
+    #[serde(default)]
+    pub exempt_repositories: Vec<String>,
+}
+
+/// Vulnerability severity, least severe first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Unknown,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+fn default_trivy_path() -> String {
+    "trivy".to_string()
+}
+
+fn default_scan_timeout_secs() -> u64 {
+    600
+}
+
+fn default_max_concurrent_scans() -> usize {
+    2
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -142,6 +497,9 @@ pub struct GarbageCollectorConfig {
     pub grace_period_hours: u64,
     pub dry_run: bool,
     pub max_blobs_per_run: usize,
+    /// Delete manifests no tag reaches, directly or through an image index
+    #[serde(default)]
+    pub collect_untagged_manifests: bool,
 }
 
 impl Default for GarbageCollectorConfig {
@@ -152,6 +510,7 @@ impl Default for GarbageCollectorConfig {
             grace_period_hours: 168, // 7 days grace period
             dry_run: false,
             max_blobs_per_run: 1000,
+            collect_untagged_manifests: false,
         }
     }
 }
@@ -178,6 +537,18 @@ pub struct QuicConfig {
     pub application_protocols: Vec<String>,
     pub enable_0rtt: bool,
     pub enable_early_data: bool,
+    /// Basic credentials presented to peers, which check them like HTTP requests
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Chunked blob uploads idle for longer than this are cancelled
+    #[serde(default = "default_chunked_upload_ttl_secs")]
+    pub chunked_upload_ttl_secs: u64,
+}
+
+fn default_chunked_upload_ttl_secs() -> u64 {
+    600
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -189,6 +560,69 @@ pub struct SigningConfig {
     pub signing_keys: Vec<SigningKeyConfig>,
     pub verification_keys: Vec<VerificationKeyConfig>,
     pub trust_stores: Vec<TrustStoreConfig>,
+    #[serde(default)]
+    pub enforcement: SignatureEnforcementConfig,
+    /// Encrypts the private keys generated through `/api/v1/signing/keys` at rest;
+    /// generating keys is refused without it
+    #[serde(default)]
+    pub key_encryption_password: Option<String>,
+    /// Signing keys whose key or certificate expires within this many days are reported
+    #[serde(default = "default_key_expiry_warning_days")]
+    pub key_expiry_warning_days: u64,
+    /// Clock difference tolerated when checking the exp and nbf claims of Notary v2 signatures
+    #[serde(default = "default_clock_skew_secs")]
+    pub clock_skew_secs: u64,
+}
+
+/// Where pulls are refused unless the manifest carries valid signatures
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignatureEnforcementConfig {
+    /// Per-repository overrides of `verification_policy.require_signatures`; the first matching pattern wins
+    #[serde(default)]
+    pub repositories: Vec<RepositorySignaturePolicy>,
+    /// A newly pushed manifest may be pulled unsigned for this long, giving CI time to sign it
+    #[serde(default)]
+    pub grace_period_secs: u64,
+    /// Also refuse pushes of manifests without enough valid signatures, once they've been
+    /// stored longer than the grace period; a manifest's first push is always accepted
+    #[serde(default)]
+    pub enforce_on_push: bool,
+    /// How long the verdict for a manifest digest is reused before its signatures are verified again
+    #[serde(default = "default_signature_cache_ttl_secs")]
+    pub cache_ttl_secs: u64,
+}
+
+impl Default for SignatureEnforcementConfig {
+    fn default() -> Self {
+        Self {
+            repositories: Vec::new(),
+            grace_period_secs: 0,
+            enforce_on_push: false,
+            cache_ttl_secs: default_signature_cache_ttl_secs(),
+        }
+    }
+}
+
+fn default_signature_cache_ttl_secs() -> u64 {
+    300
+}
+
+fn default_key_expiry_warning_days() -> u64 {
+    30
+}
+
+fn default_clock_skew_secs() -> u64 {
+    60
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RepositorySignaturePolicy {
+    /// Glob matched against the repository name, e.g. `prod/*`
+    pub pattern: String,
+    pub require_signatures: bool,
+    /// Defaults to `verification_policy.required_signatures_count`
+    #[serde(default)]
+    pub required_signatures_count: Option<usize>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -226,7 +660,17 @@ pub struct TrustStoreConfig {
     pub name: String,
     pub root_certificate_paths: Vec<String>,
     pub intermediate_certificate_paths: Vec<String>,
+    /// CRLs signed by this store's certificates, fetched at startup and every `crl_refresh_secs`
     pub crl_urls: Vec<String>,
+    /// Reject chains through this store while any of its CRLs can't be fetched or verified
+    #[serde(default)]
+    pub require_revocation_check: bool,
+    #[serde(default = "default_crl_refresh_secs")]
+    pub crl_refresh_secs: u64,
+}
+
+fn default_crl_refresh_secs() -> u64 {
+    3600
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -234,17 +678,31 @@ pub struct OptimizationConfig {
     pub enabled: bool,
     pub background_optimization: bool,
     pub optimization_schedule_cron: Option<String>,
+    /// Optimize each uploaded layer right away instead of waiting for the next run
+    #[serde(default)]
+    pub optimize_on_upload: bool,
     pub enable_compression_optimization: bool,
     pub enable_layer_deduplication: bool,
     pub enable_layer_squashing: bool,
     pub enable_base_image_optimization: bool,
     pub preferred_compression: String, // "gzip", "zstd", "lz4", "brotli"
+    /// Level for gzip (0-9), zstd (1-22) or brotli (0-11); unset uses each
+    /// algorithm's default. LZ4 has no levels.
+    #[serde(default)]
+    pub compression_level: Option<i32>,
     pub min_layer_size_mb: u64,
     pub max_optimization_time_seconds: u64,
+    /// Layer analysis gives up on layers that decompress to more than this
+    #[serde(default = "default_max_decompressed_layer_size_mb")]
+    pub max_decompressed_layer_size_mb: u64,
     pub preserve_original: bool,
     pub optimization_workers: usize,
 }
 
+fn default_max_decompressed_layer_size_mb() -> u64 {
+    8192
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RbacConfig {
     pub enabled: bool,
@@ -254,6 +712,17 @@ pub struct RbacConfig {
     pub enable_attribute_based_access: bool,
     pub cache_ttl_seconds: u64,
     pub audit_authorization_decisions: bool,
+    /// Most recent audit entries kept in memory; older ones are dropped
+    #[serde(default = "default_rbac_audit_log_capacity")]
+    pub audit_log_capacity: usize,
+    /// Deny when a permission condition needs request context (such as the
+    /// client IP) that the caller did not provide
+    #[serde(default)]
+    pub strict_conditions: bool,
+}
+
+fn default_rbac_audit_log_capacity() -> usize {
+    10_000
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -272,8 +741,10 @@ pub struct AuditConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileExportConfig {
     pub path: String,
-    pub format: String, // "json", "jsonlines", "csv"
+    pub format: String, // "json", "jsonlines", "csv", "syslog"
+    /// Move the file aside under a timestamped name once it reaches this size; 0 disables rotation
     pub rotation_size_mb: u64,
+    /// Delete rotated files older than this; 0 keeps them forever
     pub retention_days: u32,
 }
 
@@ -282,7 +753,10 @@ pub struct WebhookExportConfig {
     pub url: String,
     pub headers: HashMap<String, String>,
     pub timeout_seconds: u64,
+    /// Retries of a batch that timed out or got a 5xx response, with exponential
+    /// backoff; 4xx responses aren't retried
     pub retry_attempts: u32,
+    /// Events per POST
     pub batch_size: usize,
 }
 
@@ -299,8 +773,16 @@ pub struct ElasticsearchExportConfig {
 pub struct ClusterConfig {
     pub enabled: bool,
     pub node_id: String,
+    /// Where the gossip endpoint listens
     pub bind_address: String,
+    /// Address peers use to reach the gossip endpoint; defaults to the bound address
+    #[serde(default)]
+    pub advertise_address: Option<String>,
+    /// Gossip endpoints (`host:port`) contacted to join the cluster
     pub seed_nodes: Vec<String>,
+    /// Shared secret every node sends with its cluster requests; requests without it are refused
+    #[serde(default)]
+    pub secret: Option<String>,
     pub consensus_protocol: String, // "raft", "gossip"
     pub replication_factor: usize,
     pub consistency_level: crate::cluster::ConsistencyLevel,
@@ -308,7 +790,35 @@ pub struct ClusterConfig {
     pub health_check_interval_seconds: u64,
     pub health_check_timeout_seconds: u64,
     pub election_timeout_seconds: u64,
-    pub load_balancing_strategy: String,
+    pub load_balancing_strategy: String, // "round_robin", "least_connections", "weighted_round_robin", "random", "consistent_hashing"
+}
+
+/// OTLP trace export, e.g. to Tempo or an OpenTelemetry collector
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TelemetryConfig {
+    pub enabled: bool,
+    /// OTLP gRPC endpoint
+    pub endpoint: String,
+    #[serde(default = "default_telemetry_service_name")]
+    pub service_name: String,
+    /// Fraction of new traces to sample, 0.0 to 1.0; traces started by clients follow their sampling decision
+    #[serde(default = "default_telemetry_sample_rate")]
+    pub sample_rate: f64,
+    /// Warn about spans that take longer than this; 0 disables the warning
+    #[serde(default = "default_slow_span_threshold_ms")]
+    pub slow_span_threshold_ms: u64,
+}
+
+fn default_telemetry_service_name() -> String {
+    "drift".to_string()
+}
+
+fn default_telemetry_sample_rate() -> f64 {
+    1.0
+}
+
+fn default_slow_span_threshold_ms() -> u64 {
+    1000
 }
 
 impl Default for Config {
@@ -319,12 +829,21 @@ impl Default for Config {
                 ui_addr: "0.0.0.0:5001".to_string(),
                 workers: Some(4),
                 max_connections: Some(1000),
+                tls: None,
+                ui_tls: None,
+                shutdown_timeout_secs: default_shutdown_timeout_secs(),
+                trusted_proxies: Vec::new(),
+                external_url: None,
             },
             storage: StorageConfig {
                 storage_type: StorageType::Filesystem,
                 path: Some("./data".to_string()),
+                verify_on_read: false,
                 s3: None,
                 ghostbay: None,
+                gcs: None,
+                memory: None,
+                cache: None,
             },
             auth: AuthConfig {
                 mode: AuthMode::Basic,
@@ -332,6 +851,9 @@ impl Default for Config {
                 token_expiry_hours: 24,
                 basic: Some(BasicAuthConfig {
                     users: vec!["admin:changeme".to_string()],
+                    htpasswd_path: None,
+                    htpasswd_reload_interval_secs: default_htpasswd_reload_interval_secs(),
+                    allow_plaintext_passwords: true, // Demo credentials only
                 }),
                 oidc: None,
                 oauth: Some(OAuthConfig {
@@ -339,6 +861,7 @@ impl Default for Config {
                     azure: None,
                     github: None,
                     google: None,
+                    default_scopes: default_oauth_scopes(),
                 }),
             },
             registry: RegistryConfig {
@@ -346,6 +869,10 @@ impl Default for Config {
                 rate_limit_per_hour: 1000,
                 immutable_tags: vec!["release".to_string(), "prod".to_string()],
                 min_age_days: 7,
+                upload_session_ttl_hours: default_upload_session_ttl_hours(),
+                allow_tag_deletion: false,
+                anonymous_pull: false,
+                rate_limits: RateLimitConfig::default(),
             },
             garbage_collector: Some(GarbageCollectorConfig::default()),
             bolt: Some(BoltConfig {
@@ -373,6 +900,9 @@ impl Default for Config {
                 application_protocols: vec!["drift-registry".to_string()],
                 enable_0rtt: false,
                 enable_early_data: false,
+                username: None,
+                password: None,
+                chunked_upload_ttl_secs: default_chunked_upload_ttl_secs(),
             }),
             signing: Some(SigningConfig {
                 enabled: false, // Disabled by default
@@ -391,18 +921,25 @@ impl Default for Config {
                 signing_keys: vec![],
                 verification_keys: vec![],
                 trust_stores: vec![],
+                enforcement: SignatureEnforcementConfig::default(),
+                key_encryption_password: None,
+                key_expiry_warning_days: default_key_expiry_warning_days(),
+                clock_skew_secs: default_clock_skew_secs(),
             }),
             optimization: Some(OptimizationConfig {
                 enabled: false, // Disabled by default
                 background_optimization: true,
                 optimization_schedule_cron: Some("0 2 * * *".to_string()), // Daily at 2 AM
+                optimize_on_upload: false,
                 enable_compression_optimization: true,
                 enable_layer_deduplication: true,
                 enable_layer_squashing: false, // Advanced feature
                 enable_base_image_optimization: false, // Advanced feature
                 preferred_compression: "gzip".to_string(),
+                compression_level: None,
                 min_layer_size_mb: 10, // Don't optimize layers smaller than 10MB
                 max_optimization_time_seconds: 300, // 5 minutes max per layer
+                max_decompressed_layer_size_mb: default_max_decompressed_layer_size_mb(),
                 preserve_original: true,
                 optimization_workers: 2,
             }),
@@ -414,6 +951,8 @@ impl Default for Config {
                 enable_attribute_based_access: false,
                 cache_ttl_seconds: 300, // 5 minutes
                 audit_authorization_decisions: true,
+                audit_log_capacity: default_rbac_audit_log_capacity(),
+                strict_conditions: false,
             }),
             audit: Some(AuditConfig {
                 enabled: false, // Disabled by default
@@ -434,8 +973,10 @@ impl Default for Config {
             cluster: Some(ClusterConfig {
                 enabled: false, // Disabled by default
                 node_id: "node-1".to_string(),
-                bind_address: "0.0.0.0:7000".to_string(),
+                bind_address: "127.0.0.1:7000".to_string(),
+                advertise_address: None,
                 seed_nodes: vec![],
+                secret: None,
                 consensus_protocol: "raft".to_string(),
                 replication_factor: 3,
                 consistency_level: crate::cluster::ConsistencyLevel::Quorum,
@@ -445,15 +986,54 @@ impl Default for Config {
                 election_timeout_seconds: 300,
                 load_balancing_strategy: "round_robin".to_string(),
             }),
+            telemetry: None,
+            proxy: vec![],
+            replication: None,
+            notifications: None,
+            scanning: None,
         }
     }
 }
 
+/// Prefix of environment variables that override config values
+const ENV_PREFIX: &str = "DRIFT_";
+
+/// Separates nested keys in override names, e.g. `DRIFT_AUTH__JWT_SECRET` for `auth.jwt_secret`
+const ENV_KEY_SEPARATOR: &str = "__";
+
 impl Config {
+    /// Load the config file with `DRIFT_` environment overrides applied on top.
+    /// Values set in neither fall back to their serde defaults.
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
         let content = std::fs::read_to_string(path)?;
-        let config: Config = toml::from_str(&content)?;
-        Ok(config)
+        let file: toml::Value = toml::from_str(&content)?;
+        Self::with_env_overrides(file, std::env::vars())
+    }
+
+    /// The built-in defaults with `DRIFT_` environment overrides applied, for
+    /// running without a config file
+    pub fn from_env() -> Result<Self> {
+        Self::with_env_overrides(toml::Value::try_from(Config::default())?, std::env::vars())
+    }
+
+    fn with_env_overrides(mut config: toml::Value, vars: impl IntoIterator<Item = (String, String)>) -> Result<Self> {
+        // Keys the file leaves out take their type from the defaults
+        let defaults = toml::Value::try_from(Config::default())?;
+
+        for (name, value) in vars {
+            let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let path: Vec<String> = key.split(ENV_KEY_SEPARATOR).map(str::to_lowercase).collect();
+            if path.iter().any(String::is_empty) {
+                continue;
+            }
+
+            apply_env_override(&mut config, &defaults, &path, &value)
+                .with_context(|| format!("Invalid environment override {}", name))?;
+        }
+
+        Ok(config.try_into()?)
     }
 
     pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
@@ -461,4 +1041,100 @@ impl Config {
         std::fs::write(path, content)?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Set the value at `path`, creating sections as needed
+fn apply_env_override(config: &mut toml::Value, defaults: &toml::Value, path: &[String], raw: &str) -> Result<()> {
+    let Some((key, sections)) = path.split_last() else {
+        return Ok(());
+    };
+
+    let hint = lookup(config, path).or_else(|| lookup(defaults, path)).cloned();
+    let mut table = config.as_table_mut().context("config is not a table")?;
+    for section in sections {
+        table = match table
+            .entry(section.as_str())
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+        {
+            toml::Value::Table(table) => table,
+            _ => bail!("{} is not a section", section),
+        };
+    }
+
+    if matches!(hint, Some(toml::Value::Table(_))) {
+        bail!("{} is a section; set its keys with {}<KEY>", key, ENV_KEY_SEPARATOR);
+    }
+    table.insert(key.clone(), parse_env_value(raw, hint.as_ref()));
+    Ok(())
+}
+
+fn lookup<'a>(value: &'a toml::Value, path: &[String]) -> Option<&'a toml::Value> {
+    path.iter().try_fold(value, |value, key| value.get(key.as_str()))
+}
+
+/// Interpret an override as the type of the value it replaces. Lists may be
+/// given comma-separated; anything else is parsed as a TOML value, or kept
+/// as a string when it isn't one.
+fn parse_env_value(raw: &str, hint: Option<&toml::Value>) -> toml::Value {
+    match hint {
+        Some(toml::Value::String(_)) => return toml::Value::String(raw.to_string()),
+        Some(toml::Value::Array(_)) if !raw.trim_start().starts_with('[') => {
+            return toml::Value::Array(
+                raw.split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(|item| toml::Value::String(item.to_string()))
+                    .collect(),
+            );
+        }
+        _ => {}
+    }
+
+    toml::from_str::<toml::Table>(&format!("value = {}", raw))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn environment_overrides_the_file_which_overrides_the_defaults() {
+        let mut file = Config::default();
+        file.registry.rate_limit_per_hour = 10;
+        file.auth.jwt_secret = "from-file".to_string();
+        let mut file = toml::Value::try_from(file).unwrap();
+        // Left out of the file, so the default applies
+        file["server"].as_table_mut().unwrap().remove("shutdown_timeout_secs");
+
+        let overrides = vars(&[
+            ("DRIFT_AUTH__JWT_SECRET", "from-env"),
+            ("DRIFT_REGISTRY__IMMUTABLE_TAGS", "release, prod"),
+            ("DRIFT_STORAGE__VERIFY_ON_READ", "true"),
+            ("HOME", "/root"),
+        ]);
+        let config = Config::with_env_overrides(file, overrides).unwrap();
+
+        assert_eq!(config.auth.jwt_secret, "from-env");
+        assert_eq!(config.registry.immutable_tags, ["release", "prod"]);
+        assert!(config.storage.verify_on_read);
+        assert_eq!(config.registry.rate_limit_per_hour, 10);
+        assert_eq!(config.server.shutdown_timeout_secs, default_shutdown_timeout_secs());
+    }
+
+    #[test]
+    fn overrides_that_dont_fit_their_setting_are_rejected() {
+        let defaults = || toml::Value::try_from(Config::default()).unwrap();
+        let not_a_number = vars(&[("DRIFT_REGISTRY__RATE_LIMIT_PER_HOUR", "lots")]);
+        assert!(Config::with_env_overrides(defaults(), not_a_number).is_err());
+
+        let section = Config::with_env_overrides(defaults(), vars(&[("DRIFT_AUTH", "basic")])).unwrap_err();
+        assert!(format!("{:#}", section).contains("DRIFT_AUTH"), "{:#}", section);
+    }
+}
@@ -1,32 +1,258 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use chrono::{DateTime, Duration, Utc};
-use std::collections::HashSet;
-use std::path::PathBuf;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use tokio::time::interval;
-use tracing::{error, info, warn};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
 
-use crate::config::{Config, GarbageCollectorConfig};
-use crate::storage::{BlobMetadata, ManifestMetadata, StorageBackend};
-use std::sync::Arc;
+use crate::blob_holds::{BlobHolds, RepositoryLocks};
+use crate::config::GarbageCollectorConfig;
+use crate::optimization::OptimizationService;
+use crate::storage::StorageBackend;
+use std::sync::{Arc, Mutex};
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct GarbageCollectorMetrics {
     pub orphaned_blobs_found: usize,
     pub orphaned_manifests_found: usize,
     pub blobs_deleted: usize,
     pub manifests_deleted: usize,
     pub bytes_freed: u64,
+    pub upload_sessions_deleted: usize,
+    pub upload_bytes_freed: u64,
     pub run_duration_seconds: f64,
+    /// The run was stopped early; counts cover the work done before that
+    #[serde(default)]
+    pub cancelled: bool,
+    /// What a dry run would have removed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report: Option<GarbageCollectionReport>,
+}
+
+/// Everything a dry run would delete
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct GarbageCollectionReport {
+    pub blobs: Vec<CollectedBlob>,
+    pub manifests: Vec<CollectedManifest>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CollectedBlob {
+    pub digest: String,
+    pub size: u64,
+    /// Repositories with a manifest that mentions the blob, if any
+    pub repositories: Vec<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CollectedManifest {
+    pub repository: String,
+    pub digest: String,
+}
+
+/// Preview of what a collection would remove, computed without deleting anything
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct GcReport {
+    pub orphaned_blobs: Vec<CollectedBlob>,
+    pub reclaimable_bytes: u64,
+    /// Untagged manifests a run would sweep, when untagged collection is enabled
+    pub unreachable_manifests: Vec<CollectedManifest>,
+    pub manifests_scanned: usize,
+    pub blobs_scanned: usize,
+    /// Manifests that could not be read; no blobs are reported as orphaned while any exist
+    pub unreadable_manifests: usize,
+    pub generated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GcRunState {
+    #[default]
+    Idle,
+    Running,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+/// Progress of the current run, or the outcome of the last one
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct GcProgress {
+    pub state: GcRunState,
+    pub dry_run: bool,
+    pub blobs_scanned: usize,
+    pub blobs_deleted: usize,
+    pub manifests_deleted: usize,
+    pub bytes_reclaimed: u64,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+    /// Final metrics, including the dry-run report, once the run has ended
+    pub result: Option<GarbageCollectorMetrics>,
+}
+
+#[derive(Default)]
+struct HandleState {
+    progress: GcProgress,
+    cancel: CancellationToken,
+}
+
+/// Shared between collectors and the admin API: one run at a time, with
+/// progress reporting and cooperative cancellation
+#[derive(Clone, Default)]
+pub struct GcHandle {
+    inner: Arc<Mutex<HandleState>>,
+    /// Cancels the running collection too when the server shuts down
+    shutdown: CancellationToken,
+}
+
+impl GcHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A handle whose runs are also cancelled when `shutdown` is
+    pub fn with_shutdown(shutdown: CancellationToken) -> Self {
+        Self {
+            inner: Arc::default(),
+            shutdown,
+        }
+    }
+
+    pub fn progress(&self) -> GcProgress {
+        self.inner.lock().unwrap().progress.clone()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.inner.lock().unwrap().progress.state == GcRunState::Running
+    }
+
+    /// Register a new run; returns false if one is already in progress
+    pub fn begin(&self, dry_run: bool) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.progress.state == GcRunState::Running {
+            return false;
+        }
+
+        inner.progress = GcProgress {
+            state: GcRunState::Running,
+            dry_run,
+            started_at: Some(Utc::now()),
+            ..GcProgress::default()
+        };
+        inner.cancel = self.shutdown.child_token();
+        true
+    }
+
+    /// Ask the running collection to stop at its next checkpoint
+    pub fn cancel(&self) -> bool {
+        let inner = self.inner.lock().unwrap();
+        if inner.progress.state != GcRunState::Running {
+            return false;
+        }
+        inner.cancel.cancel();
+        true
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.inner.lock().unwrap().cancel.is_cancelled()
+    }
+
+    fn update(&self, f: impl FnOnce(&mut GcProgress)) {
+        f(&mut self.inner.lock().unwrap().progress);
+    }
+
+    fn finish(&self, state: GcRunState, result: Option<GarbageCollectorMetrics>, error: Option<String>) {
+        self.update(|progress| {
+            progress.state = state;
+            progress.finished_at = Some(Utc::now());
+            progress.result = result;
+            progress.error = error;
+        });
+    }
+}
+
+/// Result of the mark phase
+#[derive(Debug, Default)]
+struct Reachability {
+    referenced_blobs: HashSet<String>,
+    blob_owners: HashMap<String, BTreeSet<String>>,
+    unreachable_manifests: Vec<(String, String)>,
+    /// Manifests that could not be read or parsed, so their references are unknown
+    unreadable_manifests: usize,
+    manifests_scanned: usize,
 }
 
 pub struct GarbageCollector {
     config: GarbageCollectorConfig,
     storage: Arc<dyn StorageBackend>,
+    upload_session_ttl: Duration,
+    handle: GcHandle,
+    holds: Option<Arc<BlobHolds>>,
+    repository_locks: Arc<RepositoryLocks>,
+    optimization: Option<Arc<OptimizationService>>,
 }
 
 impl GarbageCollector {
-    pub fn new(config: GarbageCollectorConfig, storage: Arc<dyn StorageBackend>) -> Self {
-        Self { config, storage }
+    pub fn new(
+        config: GarbageCollectorConfig,
+        storage: Arc<dyn StorageBackend>,
+        upload_session_ttl_hours: u64,
+    ) -> Self {
+        Self {
+            config,
+            storage,
+            upload_session_ttl: Duration::hours(upload_session_ttl_hours as i64),
+            handle: GcHandle::new(),
+            holds: None,
+            repository_locks: Arc::new(RepositoryLocks::new()),
+            optimization: None,
+        }
+    }
+
+    /// Coordinate with in-flight pushes: skip held blobs and lock repositories while touching their manifests
+    pub fn with_holds(mut self, holds: Arc<BlobHolds>, repository_locks: Arc<RepositoryLocks>) -> Self {
+        self.holds = Some(holds);
+        self.repository_locks = repository_locks;
+        self
+    }
+
+    async fn is_held(&self, digest: &str) -> bool {
+        match &self.holds {
+            Some(holds) => holds.is_held(digest).await,
+            None => false,
+        }
+    }
+
+    /// Keep deduplicated layers while images still reference them
+    pub fn with_optimization(mut self, optimization: Arc<OptimizationService>) -> Self {
+        self.optimization = Some(optimization);
+        self
+    }
+
+    async fn is_deduplicated_layer_in_use(&self, digest: &str) -> bool {
+        match &self.optimization {
+            Some(optimization) => optimization.is_layer_referenced(digest).await,
+            None => false,
+        }
+    }
+
+    /// Optimized variants stay while any layer they were made from is referenced
+    async fn is_optimized_variant_in_use(&self, digest: &str, referenced_blobs: &HashSet<String>) -> bool {
+        match &self.optimization {
+            Some(optimization) => optimization
+                .variant_originals(digest)
+                .await
+                .iter()
+                .any(|original| referenced_blobs.contains(original)),
+            None => false,
+        }
+    }
+
+    /// Report progress through, and take cancellation from, a shared handle
+    pub fn with_handle(mut self, handle: GcHandle) -> Self {
+        self.handle = handle;
+        self
     }
 
     /// Start the garbage collector background task
@@ -56,6 +282,63 @@ impl GarbageCollector {
 
     /// Run a single garbage collection cycle
     pub async fn run_garbage_collection(&self) -> Result<GarbageCollectorMetrics> {
+        if !self.handle.begin(self.config.dry_run) {
+            bail!("Garbage collection is already running");
+        }
+        self.execute().await
+    }
+
+    /// Work out what a collection would remove without deleting anything,
+    /// regardless of the `dry_run` setting
+    pub async fn plan(&self) -> Result<GcReport> {
+        let reachability = self.mark().await?;
+        let all_blobs = if reachability.unreadable_manifests > 0 {
+            Vec::new()
+        } else {
+            self.find_all_blobs().await?
+        };
+        let orphaned = self.find_orphaned_blobs(&all_blobs, &reachability.referenced_blobs).await?;
+
+        let mut orphaned_blobs = Vec::with_capacity(orphaned.len());
+        for digest in orphaned {
+            let size = self.storage.get_blob_metadata(&digest).await.map_or(0, |metadata| metadata.size);
+            let repositories = reachability
+                .blob_owners
+                .get(&digest)
+                .map(|owners| owners.iter().cloned().collect())
+                .unwrap_or_default();
+            orphaned_blobs.push(CollectedBlob { digest, size, repositories });
+        }
+
+        Ok(GcReport {
+            reclaimable_bytes: orphaned_blobs.iter().map(|blob| blob.size).sum(),
+            orphaned_blobs,
+            unreachable_manifests: reachability
+                .unreachable_manifests
+                .into_iter()
+                .map(|(repository, digest)| CollectedManifest { repository, digest })
+                .collect(),
+            manifests_scanned: reachability.manifests_scanned,
+            blobs_scanned: all_blobs.len(),
+            unreadable_manifests: reachability.unreadable_manifests,
+            generated_at: Utc::now(),
+        })
+    }
+
+    /// Run a cycle already registered with `GcHandle::begin`, recording its outcome on the handle
+    pub async fn execute(&self) -> Result<GarbageCollectorMetrics> {
+        let result = self.collect().await;
+        match &result {
+            Ok(metrics) if metrics.cancelled => {
+                self.handle.finish(GcRunState::Cancelled, Some(metrics.clone()), None)
+            }
+            Ok(metrics) => self.handle.finish(GcRunState::Completed, Some(metrics.clone()), None),
+            Err(e) => self.handle.finish(GcRunState::Failed, None, Some(e.to_string())),
+        }
+        result
+    }
+
+    async fn collect(&self) -> Result<GarbageCollectorMetrics> {
         let start_time = std::time::Instant::now();
         info!("Starting garbage collection run");
 
@@ -65,92 +348,239 @@ impl GarbageCollector {
             blobs_deleted: 0,
             manifests_deleted: 0,
             bytes_freed: 0,
+            upload_sessions_deleted: 0,
+            upload_bytes_freed: 0,
             run_duration_seconds: 0.0,
+            cancelled: false,
+            report: None,
         };
+        let mut report = GarbageCollectionReport::default();
 
-        // Step 1: Find all referenced blobs from manifests
-        let referenced_blobs = self.find_referenced_blobs().await?;
-        info!("Found {} referenced blobs", referenced_blobs.len());
+        // Step 1: Mark everything reachable from tags, walking image indexes
+        let reachability = self.mark().await?;
+        info!(
+            "Found {} referenced blobs, {} unreachable manifests",
+            reachability.referenced_blobs.len(),
+            reachability.unreachable_manifests.len()
+        );
+        if self.handle.is_cancelled() {
+            return Ok(self.cancelled_run(metrics, report, start_time));
+        }
+
+        // Step 2: Sweep unreachable manifests (only when untagged collection is enabled)
+        metrics.orphaned_manifests_found = reachability.unreachable_manifests.len();
+        if !reachability.unreachable_manifests.is_empty() {
+            metrics.manifests_deleted = self
+                .delete_orphaned_manifests(&reachability.unreachable_manifests, &mut report)
+                .await?;
+        }
+        if self.handle.is_cancelled() {
+            return Ok(self.cancelled_run(metrics, report, start_time));
+        }
 
-        // Step 2: Find all existing blobs
-        let all_blobs = self.find_all_blobs().await?;
+        // Step 3: Find all existing blobs. Without every manifest parsed, live
+        // layers cannot be told from orphans, so nothing is swept.
+        let all_blobs = if reachability.unreadable_manifests > 0 {
+            warn!("Skipping blob sweep: {} manifests could not be read", reachability.unreadable_manifests);
+            Vec::new()
+        } else {
+            self.find_all_blobs().await?
+        };
         info!("Found {} total blobs in storage", all_blobs.len());
 
-        // Step 3: Identify orphaned blobs
-        let orphaned_blobs = self.find_orphaned_blobs(&all_blobs, &referenced_blobs).await?;
+        // Step 4: Identify orphaned blobs
+        let orphaned_blobs = self
+            .find_orphaned_blobs(&all_blobs, &reachability.referenced_blobs)
+            .await?;
         metrics.orphaned_blobs_found = orphaned_blobs.len();
         info!("Found {} orphaned blobs", orphaned_blobs.len());
+        if self.handle.is_cancelled() {
+            return Ok(self.cancelled_run(metrics, report, start_time));
+        }
 
-        // Step 4: Delete orphaned blobs (respecting grace period)
-        let (deleted_blobs, bytes_freed) = self.delete_orphaned_blobs(&orphaned_blobs).await?;
+        // Step 5: Delete orphaned blobs
+        let (deleted_blobs, bytes_freed) = self
+            .delete_orphaned_blobs(&orphaned_blobs, &reachability.blob_owners, &mut report)
+            .await?;
         metrics.blobs_deleted = deleted_blobs;
         metrics.bytes_freed = bytes_freed;
+        if self.handle.is_cancelled() {
+            return Ok(self.cancelled_run(metrics, report, start_time));
+        }
 
-        // Step 5: Find and clean orphaned manifests
-        let orphaned_manifests = self.find_orphaned_manifests().await?;
-        metrics.orphaned_manifests_found = orphaned_manifests.len();
+        // Step 6: Remove abandoned upload sessions
+        let (deleted_sessions, upload_bytes_freed) = self.delete_expired_uploads().await?;
+        metrics.upload_sessions_deleted = deleted_sessions;
+        metrics.upload_bytes_freed = upload_bytes_freed;
 
-        if !orphaned_manifests.is_empty() {
-            info!("Found {} orphaned manifests", orphaned_manifests.len());
-            metrics.manifests_deleted = self.delete_orphaned_manifests(&orphaned_manifests).await?;
+        if self.config.dry_run {
+            metrics.report = Some(report);
         }
-
         metrics.run_duration_seconds = start_time.elapsed().as_secs_f64();
 
         info!(
-            "Garbage collection completed: {} blobs deleted, {} manifests deleted, {} bytes freed, took {:.2}s",
+            "Garbage collection completed: {} blobs deleted, {} manifests deleted, {} upload sessions deleted, {} bytes freed, took {:.2}s",
             metrics.blobs_deleted,
             metrics.manifests_deleted,
-            metrics.bytes_freed,
+            metrics.upload_sessions_deleted,
+            metrics.bytes_freed + metrics.upload_bytes_freed,
             metrics.run_duration_seconds
         );
 
         Ok(metrics)
     }
 
-    /// Find all blobs referenced by manifests
-    async fn find_referenced_blobs(&self) -> Result<HashSet<String>> {
-        let mut referenced_blobs = HashSet::new();
+    fn cancelled_run(
+        &self,
+        mut metrics: GarbageCollectorMetrics,
+        report: GarbageCollectionReport,
+        start_time: std::time::Instant,
+    ) -> GarbageCollectorMetrics {
+        metrics.cancelled = true;
+        if self.config.dry_run {
+            metrics.report = Some(report);
+        }
+        metrics.run_duration_seconds = start_time.elapsed().as_secs_f64();
+        warn!(
+            "Garbage collection cancelled after {} blobs and {} manifests deleted",
+            metrics.blobs_deleted, metrics.manifests_deleted
+        );
+        metrics
+    }
 
-        // Get all repositories
-        let repositories = self.storage.list_repositories().await?;
+    /// Walk every repository from its tags and collect what stays alive.
+    ///
+    /// Manifests are roots when tagged, when they are younger than the grace
+    /// period, or always when untagged collection is disabled. Image indexes
+    /// keep their child manifests, and referrers (signatures, SBOMs) are kept
+    /// as long as their subject is.
+    async fn mark(&self) -> Result<Reachability> {
+        // Without untagged collection every manifest is a root, so a backend's
+        // reference table already says which blobs are live
+        let references = if self.config.collect_untagged_manifests {
+            None
+        } else {
+            self.storage.blob_references().await?
+        };
+        if let Some(references) = references {
+            debug!("Marking from the storage backend's blob reference table");
+            let mut referenced_blobs: HashSet<String> = references.blobs.keys().cloned().collect();
+            referenced_blobs.extend(references.manifests.iter().map(|(_, digest)| digest.clone()));
+            return Ok(Reachability {
+                referenced_blobs,
+                blob_owners: references.blobs,
+                unreachable_manifests: Vec::new(),
+                unreadable_manifests: references.unreadable_manifests,
+                manifests_scanned: references.manifests.len(),
+            });
+        }
 
-        for repository in repositories {
-            // Get all tags for this repository
-            let tags = self.storage.list_tags(&repository).await?;
+        let mut reachability = Reachability::default();
+        let cutoff_time = Utc::now() - Duration::hours(self.config.grace_period_hours as i64);
 
-            for tag in tags {
-                // Get manifest for each tag
-                if let Ok(Some(manifest_data)) = self.storage.get_manifest(&repository, &tag).await {
-                    if let Ok(manifest) = serde_json::from_slice::<serde_json::Value>(&manifest_data) {
-                        // Extract blob references from manifest
-                        self.extract_blob_references(&manifest, &mut referenced_blobs);
+        for repository in self.storage.list_repositories().await? {
+            if self.handle.is_cancelled() {
+                break;
+            }
+            let _repository_lock = self.repository_locks.lock(&repository).await;
+
+            let mut manifests: HashMap<String, serde_json::Value> = HashMap::new();
+            let mut unreadable = 0;
+            for digest in self.storage.list_manifests(&repository).await? {
+                if manifests.contains_key(&digest) {
+                    continue;
+                }
+                match self.storage.get_manifest_by_digest(&repository, &digest).await {
+                    Ok(data) => match serde_json::from_slice::<serde_json::Value>(&data) {
+                        Ok(manifest) => {
+                            manifests.insert(digest, manifest);
+                        }
+                        Err(e) => {
+                            warn!("Skipping unparseable manifest {}@{}: {}", repository, digest, e);
+                            unreadable += 1;
+                        }
+                    },
+                    Err(e) => {
+                        warn!("Failed to read manifest {}@{}: {}", repository, digest, e);
+                        unreadable += 1;
+                    }
+                }
+            }
+            reachability.unreadable_manifests += unreadable;
+            reachability.manifests_scanned += manifests.len();
+
+            // Any blob a manifest in this repository mentions counts as owned by it
+            for manifest in manifests.values() {
+                let mut blobs = HashSet::new();
+                self.extract_blob_references(manifest, &mut blobs);
+                for blob in blobs {
+                    reachability.blob_owners.entry(blob).or_default().insert(repository.clone());
+                }
+            }
+
+            let mut roots: HashSet<String> = HashSet::new();
+            // A tag that can't be resolved may be what keeps a manifest alive, so
+            // sweeping without it could delete tagged content
+            for tag in self.storage.list_tags(&repository).await? {
+                match self.storage.get_manifest_digest(&repository, &tag).await {
+                    Ok(digest) => {
+                        roots.insert(digest);
                     }
+                    Err(e) => bail!("Failed to resolve tag {}:{}, aborting mark phase: {}", repository, tag, e),
                 }
             }
 
-            // Also check manifest lists and other manifest types
-            if let Ok(manifests) = self.storage.list_manifests(&repository).await {
-                for manifest_digest in manifests {
-                    if let Ok(manifest_data) = self.storage.get_manifest_by_digest(&repository, &manifest_digest).await {
-                        if let Ok(manifest) = serde_json::from_slice::<serde_json::Value>(&manifest_data) {
-                            self.extract_blob_references(&manifest, &mut referenced_blobs);
+            if self.config.collect_untagged_manifests {
+                for digest in manifests.keys() {
+                    if roots.contains(digest) {
+                        continue;
+                    }
+                    // Keep recent pushes, which may still be waiting for a tag or an index
+                    match self.storage.get_manifest_metadata(&repository, digest).await {
+                        Ok(metadata) if metadata.created_at < cutoff_time => {}
+                        _ => {
+                            roots.insert(digest.clone());
                         }
                     }
                 }
+            } else {
+                roots.extend(manifests.keys().cloned());
+            }
+
+            let retained = retain_from_roots(&manifests, roots);
+
+            for digest in &retained {
+                reachability.referenced_blobs.insert(digest.clone());
+                if let Some(manifest) = manifests.get(digest) {
+                    self.extract_blob_references(manifest, &mut reachability.referenced_blobs);
+                }
+            }
+
+            // An unreadable index may be what keeps the rest alive
+            if unreadable > 0 {
+                warn!("Not sweeping manifests of {}: {} could not be read", repository, unreadable);
+                continue;
             }
+            let mut unreachable: Vec<String> = manifests
+                .into_keys()
+                .filter(|digest| !retained.contains(digest))
+                .collect();
+            unreachable.sort();
+            reachability
+                .unreachable_manifests
+                .extend(unreachable.into_iter().map(|digest| (repository.clone(), digest)));
         }
 
-        Ok(referenced_blobs)
+        Ok(reachability)
     }
 
     /// Extract blob references from a manifest JSON
     fn extract_blob_references(&self, manifest: &serde_json::Value, referenced_blobs: &mut HashSet<String>) {
         // Extract config blob if present
-        if let Some(config) = manifest.get("config") {
-            if let Some(digest) = config.get("digest").and_then(|d| d.as_str()) {
-                referenced_blobs.insert(digest.to_string());
-            }
+        if let Some(config) = manifest.get("config")
+            && let Some(digest) = config.get("digest").and_then(|d| d.as_str())
+        {
+            referenced_blobs.insert(digest.to_string());
         }
 
         // Extract layer blobs
@@ -162,10 +592,10 @@ impl GarbageCollector {
             }
         }
 
-        // Handle manifest lists (index manifests)
-        if let Some(manifests) = manifest.get("manifests").and_then(|m| m.as_array()) {
-            for sub_manifest in manifests {
-                if let Some(digest) = sub_manifest.get("digest").and_then(|d| d.as_str()) {
+        // Artifact manifests list their content as blobs
+        if let Some(blobs) = manifest.get("blobs").and_then(|b| b.as_array()) {
+            for blob in blobs {
+                if let Some(digest) = blob.get("digest").and_then(|d| d.as_str()) {
                     referenced_blobs.insert(digest.to_string());
                 }
             }
@@ -195,6 +625,27 @@ impl GarbageCollector {
         let mut orphaned = Vec::new();
 
         for blob_digest in all_blobs {
+            if self.handle.is_cancelled() {
+                break;
+            }
+            self.handle.update(|progress| progress.blobs_scanned += 1);
+
+            // Other state stored through the backend (robot accounts, holds) is not a blob
+            if blob_digest.contains('/') {
+                continue;
+            }
+            if self.is_held(blob_digest).await {
+                continue;
+            }
+            if self.is_deduplicated_layer_in_use(blob_digest).await {
+                debug!("Keeping deduplicated layer {} still in use", blob_digest);
+                continue;
+            }
+            if self.is_optimized_variant_in_use(blob_digest, referenced_blobs).await {
+                debug!("Keeping optimized variant {} of a referenced layer", blob_digest);
+                continue;
+            }
+
             if !referenced_blobs.contains(blob_digest) {
                 // Check if blob is old enough to be considered for deletion
                 if let Ok(metadata) = self.storage.get_blob_metadata(blob_digest).await {
@@ -211,8 +662,13 @@ impl GarbageCollector {
         Ok(orphaned)
     }
 
-    /// Delete orphaned blobs
-    async fn delete_orphaned_blobs(&self, orphaned_blobs: &[String]) -> Result<(usize, u64)> {
+    /// Delete orphaned blobs, or only report them in dry-run mode
+    async fn delete_orphaned_blobs(
+        &self,
+        orphaned_blobs: &[String],
+        blob_owners: &HashMap<String, BTreeSet<String>>,
+        report: &mut GarbageCollectionReport,
+    ) -> Result<(usize, u64)> {
         let mut deleted_count = 0;
         let mut bytes_freed = 0u64;
 
@@ -228,21 +684,43 @@ impl GarbageCollector {
         };
 
         for blob_digest in blobs_to_process {
-            if self.config.dry_run {
-                info!("DRY RUN: Would delete blob {}", blob_digest);
-                deleted_count += 1;
+            if self.handle.is_cancelled() {
+                break;
+            }
+            // A push may have finished uploading this blob since the scan
+            if self.is_held(blob_digest).await {
+                debug!("Skipping held blob {}", blob_digest);
                 continue;
             }
 
             // Get blob size before deletion
-            if let Ok(metadata) = self.storage.get_blob_metadata(blob_digest).await {
-                bytes_freed += metadata.size;
+            let size = match self.storage.get_blob_metadata(blob_digest).await {
+                Ok(metadata) => metadata.size,
+                Err(_) => 0,
+            };
+
+            if self.config.dry_run {
+                info!("DRY RUN: Would delete blob {} ({} bytes)", blob_digest, size);
+                report.blobs.push(CollectedBlob {
+                    digest: blob_digest.clone(),
+                    size,
+                    repositories: blob_owners
+                        .get(blob_digest)
+                        .map(|owners| owners.iter().cloned().collect())
+                        .unwrap_or_default(),
+                });
+                deleted_count += 1;
+                bytes_freed += size;
+                self.record_blob_deleted(size);
+                continue;
             }
 
             match self.storage.delete_blob(blob_digest).await {
                 Ok(_) => {
                     info!("Deleted orphaned blob {}", blob_digest);
                     deleted_count += 1;
+                    bytes_freed += size;
+                    self.record_blob_deleted(size);
                 }
                 Err(e) => {
                     error!("Failed to delete blob {}: {}", blob_digest, e);
@@ -253,74 +731,96 @@ impl GarbageCollector {
         Ok((deleted_count, bytes_freed))
     }
 
-    /// Find orphaned manifests (manifests not referenced by any tags)
-    async fn find_orphaned_manifests(&self) -> Result<Vec<String>> {
-        let mut orphaned_manifests = Vec::new();
-        let repositories = self.storage.list_repositories().await?;
+    fn record_blob_deleted(&self, size: u64) {
+        self.handle.update(|progress| {
+            progress.blobs_deleted += 1;
+            progress.bytes_reclaimed += size;
+        });
+    }
 
-        for repository in repositories {
-            // Get all manifests
-            let all_manifests = self.storage.list_manifests(&repository).await?;
+    /// Delete manifests that nothing tagged reaches any more
+    async fn delete_orphaned_manifests(
+        &self,
+        orphaned_manifests: &[(String, String)],
+        report: &mut GarbageCollectionReport,
+    ) -> Result<usize> {
+        let mut deleted_count = 0;
 
-            // Get manifests referenced by tags
-            let tags = self.storage.list_tags(&repository).await?;
-            let mut referenced_manifests = HashSet::new();
+        for (repository, manifest_digest) in orphaned_manifests {
+            if self.handle.is_cancelled() {
+                break;
+            }
 
-            for tag in tags {
-                if let Ok(manifest_digest) = self.storage.get_manifest_digest(&repository, &tag).await {
-                    referenced_manifests.insert(manifest_digest);
-                }
+            if self.config.dry_run {
+                info!("DRY RUN: Would delete manifest {}@{}", repository, manifest_digest);
+                report.manifests.push(CollectedManifest {
+                    repository: repository.clone(),
+                    digest: manifest_digest.clone(),
+                });
+                deleted_count += 1;
+                self.handle.update(|progress| progress.manifests_deleted += 1);
+                continue;
             }
 
-            // Find orphaned manifests
-            for manifest_digest in all_manifests {
-                if !referenced_manifests.contains(&manifest_digest) {
-                    // Check grace period for manifests too
-                    if let Ok(metadata) = self.storage.get_manifest_metadata(&repository, &manifest_digest).await {
-                        let grace_period = Duration::hours(self.config.grace_period_hours as i64);
-                        let cutoff_time = Utc::now() - grace_period;
+            // The manifest may have been tagged again since the mark phase
+            let _repository_lock = self.repository_locks.lock(repository).await;
+            match self.storage.list_tags_for_digest(repository, manifest_digest).await {
+                Ok(tags) if tags.is_empty() => {}
+                Ok(_) => {
+                    debug!("Skipping manifest {}@{} tagged since marking", repository, manifest_digest);
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Failed to check tags for {}@{}: {}", repository, manifest_digest, e);
+                    continue;
+                }
+            }
 
-                        if metadata.created_at < cutoff_time {
-                            orphaned_manifests.push(format!("{}:{}", repository, manifest_digest));
-                        }
-                    }
+            match self.storage.delete_manifest(repository, manifest_digest).await {
+                Ok(_) => {
+                    info!("Deleted untagged manifest {}@{}", repository, manifest_digest);
+                    deleted_count += 1;
+                    self.handle.update(|progress| progress.manifests_deleted += 1);
+                }
+                Err(e) => {
+                    error!("Failed to delete manifest {}@{}: {}", repository, manifest_digest, e);
                 }
             }
         }
 
-        Ok(orphaned_manifests)
+        Ok(deleted_count)
     }
 
-    /// Delete orphaned manifests
-    async fn delete_orphaned_manifests(&self, orphaned_manifests: &[String]) -> Result<usize> {
+    /// Delete upload sessions that have been idle longer than the session TTL
+    async fn delete_expired_uploads(&self) -> Result<(usize, u64)> {
+        let cutoff_time = Utc::now() - self.upload_session_ttl;
         let mut deleted_count = 0;
+        let mut bytes_freed = 0u64;
 
-        for manifest_ref in orphaned_manifests {
-            let parts: Vec<&str> = manifest_ref.splitn(2, ':').collect();
-            if parts.len() != 2 {
+        for session in self.storage.list_uploads().await? {
+            if session.updated_at >= cutoff_time {
                 continue;
             }
 
-            let (repository, manifest_digest) = (parts[0], parts[1]);
-
             if self.config.dry_run {
-                info!("DRY RUN: Would delete manifest {}:{}", repository, manifest_digest);
+                info!("DRY RUN: Would delete expired upload session {}", session.uuid);
                 deleted_count += 1;
                 continue;
             }
 
-            match self.storage.delete_manifest(repository, manifest_digest).await {
+            match self.storage.cancel_upload(&session.uuid).await {
                 Ok(_) => {
-                    info!("Deleted orphaned manifest {}:{}", repository, manifest_digest);
+                    info!("Deleted expired upload session {} ({} bytes)", session.uuid, session.size);
                     deleted_count += 1;
+                    bytes_freed += session.size;
                 }
                 Err(e) => {
-                    error!("Failed to delete manifest {}:{}: {}", repository, manifest_digest, e);
+                    error!("Failed to delete upload session {}: {}", session.uuid, e);
                 }
             }
         }
 
-        Ok(deleted_count)
+        Ok((deleted_count, bytes_freed))
     }
 
     /// Manually trigger garbage collection (useful for admin endpoints)
@@ -330,4 +830,44 @@ impl GarbageCollector {
     }
 }
 
+/// Expand a set of root manifests to everything they keep alive: children of
+/// image indexes, and referrers whose subject is retained
+fn retain_from_roots(manifests: &HashMap<String, serde_json::Value>, roots: HashSet<String>) -> HashSet<String> {
+    let mut retained = HashSet::new();
+    let mut pending: Vec<String> = roots.into_iter().collect();
+
+    loop {
+        while let Some(digest) = pending.pop() {
+            if !retained.insert(digest.clone()) {
+                continue;
+            }
+            let children = manifests
+                .get(&digest)
+                .and_then(|manifest| manifest.get("manifests"))
+                .and_then(|children| children.as_array());
+            for child in children.into_iter().flatten() {
+                if let Some(child_digest) = child.get("digest").and_then(|d| d.as_str()) {
+                    pending.push(child_digest.to_string());
+                }
+            }
+        }
+
+        pending = manifests
+            .iter()
+            .filter(|(digest, _)| !retained.contains(*digest))
+            .filter(|(_, manifest)| {
+                manifest
+                    .get("subject")
+                    .and_then(|subject| subject.get("digest"))
+                    .and_then(|d| d.as_str())
+                    .is_some_and(|subject| retained.contains(subject))
+            })
+            .map(|(digest, _)| digest.clone())
+            .collect();
+        if pending.is_empty() {
+            return retained;
+        }
+    }
+}
+
 // Note: BlobMetadata and ManifestMetadata are now defined in storage::mod
\ No newline at end of file
@@ -0,0 +1,101 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use tracing::{error, info};
+
+/// Finished jobs stay queryable for this long
+const FINISHED_JOB_RETENTION_HOURS: i64 = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+/// A long-running admin operation, polled through `GET /api/v1/jobs/:id`
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    pub id: String,
+    /// What the job does, e.g. `delete_repository`
+    pub kind: String,
+    /// What the job acts on, e.g. the repository name
+    pub target: String,
+    pub status: JobStatus,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Set when the job succeeds
+    pub result: Option<serde_json::Value>,
+    /// Set when the job fails
+    pub error: Option<String>,
+}
+
+/// Background jobs started through the admin API. Kept in memory, so jobs
+/// running at shutdown are not resumed.
+#[derive(Default)]
+pub struct Jobs {
+    jobs: RwLock<HashMap<String, Job>>,
+}
+
+impl Jobs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `work` in the background, returning the id to poll it by
+    pub fn spawn<F, T>(self: &Arc<Self>, kind: &str, target: &str, work: F) -> String
+    where
+        F: Future<Output = anyhow::Result<T>> + Send + 'static,
+        T: Serialize,
+    {
+        let id = uuid::Uuid::new_v4().to_string();
+        let job = Job {
+            id: id.clone(),
+            kind: kind.to_string(),
+            target: target.to_string(),
+            status: JobStatus::Running,
+            created_at: chrono::Utc::now(),
+            finished_at: None,
+            result: None,
+            error: None,
+        };
+
+        {
+            let mut jobs = self.jobs.write().unwrap();
+            let cutoff = chrono::Utc::now() - chrono::Duration::hours(FINISHED_JOB_RETENTION_HOURS);
+            jobs.retain(|_, job| job.finished_at.is_none_or(|finished_at| finished_at > cutoff));
+            jobs.insert(id.clone(), job);
+        }
+
+        let jobs = self.clone();
+        let job_id = id.clone();
+        tokio::spawn(async move {
+            let outcome = work.await.and_then(|result| Ok(serde_json::to_value(result)?));
+            let mut jobs = jobs.jobs.write().unwrap();
+            let Some(job) = jobs.get_mut(&job_id) else {
+                return;
+            };
+            job.finished_at = Some(chrono::Utc::now());
+            match outcome {
+                Ok(result) => {
+                    info!("Job {} ({} {}) succeeded", job.id, job.kind, job.target);
+                    job.status = JobStatus::Succeeded;
+                    job.result = Some(result);
+                }
+                Err(e) => {
+                    error!("Job {} ({} {}) failed: {:#}", job.id, job.kind, job.target, e);
+                    job.status = JobStatus::Failed;
+                    job.error = Some(format!("{:#}", e));
+                }
+            }
+        });
+
+        id
+    }
+
+    pub fn get(&self, id: &str) -> Option<Job> {
+        self.jobs.read().unwrap().get(id).cloned()
+    }
+}
@@ -1,17 +1,29 @@
 pub mod api;
 pub mod audit;
 pub mod auth;
+pub mod blob_holds;
 pub mod bolt_integration;
 pub mod cluster;
 pub mod config;
 pub mod garbage_collector;
+pub mod jobs;
 pub mod metrics;
+pub mod notifications;
 pub mod optimization;
+pub mod proxy;
 pub mod quic;
+pub mod rate_limit;
 pub mod rbac;
+pub mod reload;
+pub mod remote;
+pub mod replication;
+pub mod scanning;
 pub mod server;
 pub mod signing;
 pub mod storage;
+pub mod tag_history;
+pub mod telemetry;
+pub mod tls;
 pub mod ui;
 
 pub use config::Config;
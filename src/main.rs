@@ -1,6 +1,13 @@
 use anyhow::Result;
-use clap::Parser;
-use drift::{config::Config, server::Server};
+use clap::{Parser, Subcommand};
+use drift::{
+    auth::password,
+    config::{Config, StorageType},
+    metrics::Metrics,
+    server::Server,
+    storage::create_storage_backend,
+    telemetry::Telemetry,
+};
 use tracing::{info, warn};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
@@ -16,35 +23,110 @@ struct Cli {
 
     #[arg(short, long, default_value = "0.0.0.0:5001")]
     ui_bind: String,
+
+    /// Keep all registry content in memory; nothing survives a restart
+    #[arg(long)]
+    ephemeral: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Hash a password for use in auth.basic.users or an htpasswd file
+    #[command(alias = "hashpw")]
+    HashPassword {
+        /// Print a complete `username:hash` entry
+        #[arg(long)]
+        username: Option<String>,
+
+        /// Password to hash; read from stdin when omitted
+        password: Option<String>,
+    },
+    /// Maintenance of the configured storage backend
+    Storage {
+        #[command(subcommand)]
+        command: StorageCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum StorageCommand {
+    /// Check manifests against their blobs and rebuild the blob reference table.
+    /// Run it while the registry is stopped, or use the admin API instead.
+    Fsck,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize tracing
+    let cli = Cli::parse();
+
+    // Load configuration first so tracing can pick up the telemetry settings
+    let config_loaded = std::path::Path::new(&cli.config).exists();
+    let mut config = if config_loaded {
+        Config::load(&cli.config)?
+    } else {
+        Config::from_env()?
+    };
+
+    // Initialize tracing, exporting spans over OTLP when telemetry is enabled
+    let telemetry = Telemetry::from_config(config.telemetry.as_ref())?;
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
                 .unwrap_or_else(|_| "drift=debug,tower_http=debug".into()),
         )
         .with(tracing_subscriber::fmt::layer())
+        .with(telemetry.as_ref().map(|telemetry| telemetry.layer()))
+        .with(telemetry.as_ref().and_then(Telemetry::slow_span_layer))
         .init();
 
-    let cli = Cli::parse();
+    if let Some(Command::Storage { command: StorageCommand::Fsck }) = cli.command {
+        let storage = create_storage_backend(&config.storage, &Metrics::new()?).await?;
+        let report = storage.fsck().await?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    if let Some(Command::HashPassword { username, password }) = cli.command {
+        let password = match password {
+            Some(password) => password,
+            None => {
+                let mut line = String::new();
+                std::io::stdin().read_line(&mut line)?;
+                line.trim_end_matches(['\r', '\n']).to_string()
+            }
+        };
+
+        let hash = password::hash_password(&password)?;
+        match username {
+            Some(username) => println!("{}:{}", username, hash),
+            None => println!("{}", hash),
+        }
+        return Ok(());
+    }
 
     info!("🌊 Starting Drift Registry");
     info!("📦 OCI-compatible registry for Bolt, Docker, and Podman");
 
-    // Load configuration
-    let config = Config::load(&cli.config).unwrap_or_else(|_| {
-        warn!("Could not load config file, using defaults");
-        Config::default()
-    });
+    if !config_loaded {
+        warn!("Config file {} not found, using defaults", cli.config);
+    }
+
+    if cli.ephemeral {
+        config.storage.storage_type = StorageType::Memory;
+        warn!("Ephemeral mode: registry content is kept in memory and lost on exit");
+    }
 
     info!("🚀 Registry API starting on {}", cli.bind);
     info!("🖥️  Web UI starting on {}", cli.ui_bind);
 
     // Create and start server
-    let server = Server::new(config, &cli.bind, &cli.ui_bind).await?;
+    let mut server = Server::new(config, &cli.bind, &cli.ui_bind).await?;
+    if config_loaded {
+        server = server.with_config_reload(&cli.config);
+    }
     server.run().await?;
 
     Ok(())
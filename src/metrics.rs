@@ -1,75 +1,173 @@
-use axum::{response::Response, http::StatusCode};
-use std::time::Instant;
-use tracing::info;
+use anyhow::Result;
+use axum::{extract::State, http::StatusCode, response::Response};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use std::fmt::Write;
+use tracing::error;
 
-pub struct Metrics {
-    pub requests_total: u64,
-    pub requests_duration_seconds: f64,
-    pub registry_uploads_total: u64,
-    pub registry_downloads_total: u64,
-}
+use crate::rate_limit::{ClassSummary, RateLimiter};
+use crate::server::AppState;
 
-impl Default for Metrics {
-    fn default() -> Self {
-        Self {
-            requests_total: 0,
-            requests_duration_seconds: 0.0,
-            registry_uploads_total: 0,
-            registry_downloads_total: 0,
-        }
-    }
+/// Prometheus collectors for the registry, registered once at startup
+pub struct Metrics {
+    registry: Registry,
+    pub http_requests_total: IntCounterVec,
+    pub http_request_duration_seconds: HistogramVec,
+    pub blob_bytes_uploaded_total: IntCounter,
+    pub blob_bytes_downloaded_total: IntCounter,
+    pub manifest_pushes_total: IntCounterVec,
+    pub manifest_pulls_total: IntCounterVec,
+    pub storage_operation_duration_seconds: HistogramVec,
+    pub s3_request_retries_total: IntCounterVec,
+    pub replication_failures_total: IntCounterVec,
+    pub gc_runs_total: IntCounter,
+    pub gc_bytes_reclaimed_total: IntCounter,
+    pub upload_sessions_active: IntGauge,
+    pub signing_keys_expiring: IntGauge,
 }
 
 impl Metrics {
-    pub fn new() -> Self {
-        Self::default()
-    }
+    pub fn new() -> Result<Self> {
+        let registry = Registry::new_custom(Some("drift".to_string()), None)?;
 
-    pub fn increment_requests(&mut self) {
-        self.requests_total += 1;
-    }
+        let http_requests_total = IntCounterVec::new(
+            Opts::new("http_requests_total", "Total number of HTTP requests"),
+            &["method", "route", "status"],
+        )?;
+        let http_request_duration_seconds = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "Duration of HTTP requests in seconds"),
+            &["method", "route"],
+        )?;
+        let blob_bytes_uploaded_total =
+            IntCounter::new("blob_bytes_uploaded_total", "Blob bytes received through uploads")?;
+        let blob_bytes_downloaded_total =
+            IntCounter::new("blob_bytes_downloaded_total", "Blob bytes served to clients")?;
+        let manifest_pushes_total = IntCounterVec::new(
+            Opts::new("manifest_pushes_total", "Manifests pushed per repository"),
+            &["repository"],
+        )?;
+        let manifest_pulls_total = IntCounterVec::new(
+            Opts::new("manifest_pulls_total", "Manifests pulled per repository"),
+            &["repository"],
+        )?;
+        let storage_operation_duration_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "storage_operation_duration_seconds",
+                "Duration of storage backend operations in seconds",
+            ),
+            &["operation", "result"],
+        )?;
+        let s3_request_retries_total = IntCounterVec::new(
+            Opts::new("s3_request_retries_total", "S3 requests retried after a transient failure"),
+            &["operation"],
+        )?;
+        let replication_failures_total = IntCounterVec::new(
+            Opts::new("replication_failures_total", "Failed attempts to replicate a manifest to a target"),
+            &["target"],
+        )?;
+        let gc_runs_total = IntCounter::new("gc_runs_total", "Garbage collection runs")?;
+        let gc_bytes_reclaimed_total =
+            IntCounter::new("gc_bytes_reclaimed_total", "Bytes reclaimed by garbage collection")?;
+        let upload_sessions_active =
+            IntGauge::new("upload_sessions_active", "Upload sessions currently in progress")?;
+        let signing_keys_expiring = IntGauge::new(
+            "signing_keys_expiring",
+            "Signing keys whose key or certificate expires within signing.key_expiry_warning_days",
+        )?;
+
+        registry.register(Box::new(http_requests_total.clone()))?;
+        registry.register(Box::new(http_request_duration_seconds.clone()))?;
+        registry.register(Box::new(blob_bytes_uploaded_total.clone()))?;
+        registry.register(Box::new(blob_bytes_downloaded_total.clone()))?;
+        registry.register(Box::new(manifest_pushes_total.clone()))?;
+        registry.register(Box::new(manifest_pulls_total.clone()))?;
+        registry.register(Box::new(storage_operation_duration_seconds.clone()))?;
+        registry.register(Box::new(s3_request_retries_total.clone()))?;
+        registry.register(Box::new(replication_failures_total.clone()))?;
+        registry.register(Box::new(gc_runs_total.clone()))?;
+        registry.register(Box::new(gc_bytes_reclaimed_total.clone()))?;
+        registry.register(Box::new(upload_sessions_active.clone()))?;
+        registry.register(Box::new(signing_keys_expiring.clone()))?;
 
-    pub fn record_request_duration(&mut self, duration: f64) {
-        self.requests_duration_seconds = duration;
+        Ok(Self {
+            registry,
+            http_requests_total,
+            http_request_duration_seconds,
+            blob_bytes_uploaded_total,
+            blob_bytes_downloaded_total,
+            manifest_pushes_total,
+            manifest_pulls_total,
+            storage_operation_duration_seconds,
+            s3_request_retries_total,
+            replication_failures_total,
+            gc_runs_total,
+            gc_bytes_reclaimed_total,
+            upload_sessions_active,
+            signing_keys_expiring,
+        })
     }
 
-    pub fn increment_uploads(&mut self) {
-        self.registry_uploads_total += 1;
+    pub fn record_gc_run(&self, bytes_freed: u64) {
+        self.gc_runs_total.inc();
+        self.gc_bytes_reclaimed_total.inc_by(bytes_freed);
     }
 
-    pub fn increment_downloads(&mut self) {
-        self.registry_downloads_total += 1;
+    pub fn export_prometheus(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
     }
+}
+
+/// Rate limiter bucket levels and throttling counts in Prometheus format
+pub fn export_rate_limits(limiter: &RateLimiter) -> String {
+    let summaries = limiter.class_summaries();
+    let mut output = String::new();
+    let mut gauge = |name: &str, help: &str, value: fn(&ClassSummary) -> usize| {
+        let _ = writeln!(output, "# HELP {} {}\n# TYPE {} gauge", name, help, name);
+        for summary in &summaries {
+            let _ = writeln!(output, "{}{{class=\"{}\"}} {}", name, summary.class.as_str(), value(summary));
+        }
+    };
+    gauge("drift_rate_limit_capacity", "Requests allowed per hour for each operation class", |s| s.capacity as usize);
+    gauge("drift_rate_limit_buckets", "Clients with a rate limit bucket", |s| s.buckets);
+    gauge("drift_rate_limit_exhausted_buckets", "Clients currently out of requests", |s| s.exhausted);
 
-    pub fn export_prometheus(&self) -> String {
-        format!(
-            "# HELP requests_total Total number of HTTP requests\n\
-             # TYPE requests_total counter\n\
-             requests_total {}\n\
-             # HELP requests_duration_seconds Duration of HTTP requests in seconds\n\
-             # TYPE requests_duration_seconds gauge\n\
-             requests_duration_seconds {}\n\
-             # HELP registry_uploads_total Total number of registry uploads\n\
-             # TYPE registry_uploads_total counter\n\
-             registry_uploads_total {}\n\
-             # HELP registry_downloads_total Total number of registry downloads\n\
-             # TYPE registry_downloads_total counter\n\
-             registry_downloads_total {}\n",
-            self.requests_total,
-            self.requests_duration_seconds,
-            self.registry_uploads_total,
-            self.registry_downloads_total
-        )
+    output.push_str(
+        "# HELP drift_rate_limit_throttled_total Requests rejected by the rate limiter\n\
+         # TYPE drift_rate_limit_throttled_total counter\n",
+    );
+    for (class, count) in limiter.throttled_counts() {
+        let _ = writeln!(output, "drift_rate_limit_throttled_total{{class=\"{}\"}} {}", class.as_str(), count);
     }
+
+    output
 }
 
-pub async fn metrics_handler() -> Response {
-    let metrics = Metrics::new(); // In a real implementation, this would be shared state
-    let prometheus_output = metrics.export_prometheus();
+pub async fn metrics_handler(State(state): State<AppState>) -> Response {
+    // Upload sessions expire on their own, so count them at scrape time
+    match state.storage.list_uploads().await {
+        Ok(uploads) => state.metrics.upload_sessions_active.set(uploads.len() as i64),
+        Err(e) => error!("Failed to count upload sessions for metrics: {}", e),
+    }
+
+    let mut prometheus_output = match state.metrics.export_prometheus() {
+        Ok(output) => output,
+        Err(e) => {
+            error!("Failed to encode metrics: {}", e);
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body("failed to encode metrics".into())
+                .unwrap();
+        }
+    };
+    prometheus_output.push_str("# TYPE drift_info counter\ndrift_info{version=\"0.1.0\"} 1\n");
+    prometheus_output.push_str(&export_rate_limits(&state.rate_limiter));
 
     Response::builder()
         .status(StatusCode::OK)
-        .header("Content-Type", "text/plain; charset=utf-8")
+        .header("Content-Type", "text/plain; version=0.0.4; charset=utf-8")
         .body(prometheus_output.into())
         .unwrap()
-}
\ No newline at end of file
+}
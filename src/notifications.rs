@@ -0,0 +1,379 @@
+use axum::http::{header, HeaderMap};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, warn};
+
+use crate::auth::User;
+use crate::config::{NotificationEndpointConfig, NotificationsConfig};
+use crate::storage::StorageBackend;
+
+/// Media type of the notification envelope
+const EVENTS_MEDIA_TYPE: &str = "application/vnd.docker.distribution.events.v1+json";
+
+/// Media type reported for blob events
+const BLOB_MEDIA_TYPE: &str = "application/octet-stream";
+
+/// Longest wait between delivery attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// A registry event in the Docker Registry v2 notification format
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub action: String,
+    pub target: EventTarget,
+    pub request: EventRequest,
+    pub actor: EventActor,
+    pub source: EventSource,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventTarget {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub length: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    pub repository: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EventRequest {
+    pub id: String,
+    pub addr: String,
+    pub host: String,
+    pub method: String,
+    pub useragent: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EventActor {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EventSource {
+    pub addr: String,
+    #[serde(rename = "instanceID")]
+    pub instance_id: String,
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    events: [&'a Event; 1],
+}
+
+impl Event {
+    fn new(action: &str, target: EventTarget) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            action: action.to_string(),
+            target,
+            request: EventRequest::default(),
+            actor: EventActor::default(),
+            source: EventSource::default(),
+        }
+    }
+
+    /// A manifest was pushed; `reference` is the tag or digest it was pushed by
+    pub fn manifest_pushed(repository: &str, reference: &str, digest: &str, media_type: &str, size: u64) -> Self {
+        Self::new(
+            "push",
+            EventTarget {
+                media_type: Some(media_type.to_string()),
+                size: Some(size),
+                length: Some(size),
+                digest: Some(digest.to_string()),
+                repository: repository.to_string(),
+                url: Some(format!("/v2/{}/manifests/{}", repository, digest)),
+                tag: (!crate::storage::is_digest_reference(reference)).then(|| reference.to_string()),
+            },
+        )
+    }
+
+    /// A manifest was deleted by digest, or a tag was removed
+    pub fn manifest_deleted(repository: &str, reference: &str) -> Self {
+        let is_digest = crate::storage::is_digest_reference(reference);
+        Self::new(
+            "delete",
+            EventTarget {
+                digest: is_digest.then(|| reference.to_string()),
+                repository: repository.to_string(),
+                tag: (!is_digest).then(|| reference.to_string()),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// A blob upload completed
+    pub fn blob_pushed(repository: &str, digest: &str, size: u64) -> Self {
+        Self::new(
+            "push",
+            EventTarget {
+                media_type: Some(BLOB_MEDIA_TYPE.to_string()),
+                size: Some(size),
+                length: Some(size),
+                digest: Some(digest.to_string()),
+                repository: repository.to_string(),
+                url: Some(format!("/v2/{}/blobs/{}", repository, digest)),
+                tag: None,
+            },
+        )
+    }
+
+    /// Describe the request that caused the event
+    pub fn with_request(mut self, method: &str, headers: &HeaderMap, addr: Option<SocketAddr>) -> Self {
+        let header_value = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        let request_id = header_value("x-request-id");
+        self.request = EventRequest {
+            id: if request_id.is_empty() { uuid::Uuid::new_v4().to_string() } else { request_id },
+            addr: addr.map(|addr| addr.to_string()).unwrap_or_default(),
+            host: header_value(header::HOST.as_str()),
+            method: method.to_string(),
+            useragent: header_value(header::USER_AGENT.as_str()),
+        };
+        self
+    }
+
+    pub fn with_actor(mut self, user: Option<&User>) -> Self {
+        self.actor.name = user.map(|user| user.username.clone());
+        self
+    }
+}
+
+/// Delivery counters for one webhook endpoint
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EndpointStatus {
+    pub name: String,
+    pub url: String,
+    /// Events waiting in the endpoint's queue
+    pub pending: usize,
+    pub delivered: u64,
+    pub failed_attempts: u64,
+    pub dead_lettered: u64,
+    /// Events discarded because the queue was full
+    pub dropped: u64,
+    pub last_delivered_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct DeadLetter<'a> {
+    endpoint: &'a str,
+    attempts: u32,
+    error: &'a str,
+    failed_at: DateTime<Utc>,
+    event: &'a Event,
+}
+
+struct Endpoint {
+    config: NotificationEndpointConfig,
+    sender: mpsc::Sender<Event>,
+    status: Mutex<EndpointStatus>,
+}
+
+impl Endpoint {
+    fn accepts(&self, event: &Event) -> bool {
+        let action_allowed = self.config.actions.is_empty() || self.config.actions.contains(&event.action);
+        let media_type_ignored = event
+            .target
+            .media_type
+            .as_ref()
+            .is_some_and(|media_type| self.config.ignored_media_types.contains(media_type));
+        action_allowed && !media_type_ignored
+    }
+}
+
+/// Delivers registry events to webhook endpoints.
+///
+/// Each endpoint has a bounded queue drained by its own task, so a slow or
+/// failing webhook never delays requests or other endpoints. Failed deliveries
+/// are retried with exponential backoff; events that still can't be delivered
+/// are written to a dead-letter log in storage under `notifications/dead-letters/`.
+pub struct Notifier {
+    endpoints: Vec<Arc<Endpoint>>,
+    source: EventSource,
+}
+
+impl Notifier {
+    /// Start a delivery task per configured endpoint. Without configuration, events are discarded.
+    pub fn start(config: Option<NotificationsConfig>, storage: Arc<dyn StorageBackend>, source_addr: &str) -> Self {
+        let source = EventSource {
+            addr: source_addr.to_string(),
+            instance_id: uuid::Uuid::new_v4().to_string(),
+        };
+
+        let Some(config) = config else {
+            return Self {
+                endpoints: vec![],
+                source,
+            };
+        };
+
+        let client = reqwest::Client::new();
+        let endpoints = config
+            .endpoints
+            .into_iter()
+            .map(|endpoint_config| {
+                info!("Sending registry notifications to {} ({})", endpoint_config.name, endpoint_config.url);
+                let (sender, receiver) = mpsc::channel(config.queue_size.max(1));
+                let endpoint = Arc::new(Endpoint {
+                    status: Mutex::new(EndpointStatus {
+                        name: endpoint_config.name.clone(),
+                        url: endpoint_config.url.clone(),
+                        ..Default::default()
+                    }),
+                    config: endpoint_config,
+                    sender,
+                });
+                tokio::spawn(deliver_events(endpoint.clone(), receiver, client.clone(), storage.clone()));
+                endpoint
+            })
+            .collect();
+
+        Self { endpoints, source }
+    }
+
+    /// Queue an event for every endpoint that wants it, without waiting for delivery
+    pub fn emit(&self, mut event: Event) {
+        event.source = self.source.clone();
+
+        for endpoint in self.endpoints.iter().filter(|endpoint| endpoint.accepts(&event)) {
+            if let Err(e) = endpoint.sender.try_send(event.clone()) {
+                warn!("Dropping {} event for {}: {}", event.action, endpoint.config.name, e);
+                endpoint.status.lock().unwrap().dropped += 1;
+            }
+        }
+    }
+
+    pub fn status(&self) -> Vec<EndpointStatus> {
+        self.endpoints
+            .iter()
+            .map(|endpoint| {
+                let mut status = endpoint.status.lock().unwrap().clone();
+                status.pending = endpoint.sender.max_capacity() - endpoint.sender.capacity();
+                status
+            })
+            .collect()
+    }
+}
+
+/// Deliver an endpoint's events in order, retrying each before moving on
+async fn deliver_events(
+    endpoint: Arc<Endpoint>,
+    mut receiver: mpsc::Receiver<Event>,
+    client: reqwest::Client,
+    storage: Arc<dyn StorageBackend>,
+) {
+    let config = &endpoint.config;
+
+    while let Some(event) = receiver.recv().await {
+        let mut attempts = 0;
+        let mut backoff = Duration::from_millis(config.backoff_ms);
+
+        loop {
+            attempts += 1;
+            let error = match send(&client, config, &event).await {
+                Ok(()) => {
+                    debug!("Delivered {} event {} to {}", event.action, event.id, config.name);
+                    let mut status = endpoint.status.lock().unwrap();
+                    status.delivered += 1;
+                    status.last_delivered_at = Some(Utc::now());
+                    break;
+                }
+                Err(e) => format!("{:#}", e),
+            };
+
+            {
+                let mut status = endpoint.status.lock().unwrap();
+                status.failed_attempts += 1;
+                status.last_error = Some(error.clone());
+            }
+
+            if attempts >= config.max_attempts.max(1) {
+                error!(
+                    "Giving up delivering {} event {} to {} after {} attempts: {}",
+                    event.action, event.id, config.name, attempts, error
+                );
+                dead_letter(&storage, config, &event, attempts, &error).await;
+                endpoint.status.lock().unwrap().dead_lettered += 1;
+                break;
+            }
+
+            warn!(
+                "Failed to deliver {} event {} to {} (attempt {}), retrying in {:?}: {}",
+                event.action, event.id, config.name, attempts, backoff, error
+            );
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+}
+
+async fn send(client: &reqwest::Client, config: &NotificationEndpointConfig, event: &Event) -> anyhow::Result<()> {
+    let mut request = client
+        .post(&config.url)
+        .timeout(Duration::from_millis(config.timeout_ms))
+        .header(header::CONTENT_TYPE, EVENTS_MEDIA_TYPE)
+        .json(&Envelope { events: [event] });
+    for (name, value) in &config.headers {
+        request = request.header(name, value);
+    }
+
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        anyhow::bail!("Endpoint returned {}", response.status());
+    }
+    Ok(())
+}
+
+/// Keep an undeliverable event in storage so it can be inspected or replayed
+async fn dead_letter(
+    storage: &Arc<dyn StorageBackend>,
+    config: &NotificationEndpointConfig,
+    event: &Event,
+    attempts: u32,
+    error: &str,
+) {
+    let letter = DeadLetter {
+        endpoint: &config.name,
+        attempts,
+        error,
+        failed_at: Utc::now(),
+        event,
+    };
+    let data = match serde_json::to_vec(&letter) {
+        Ok(data) => data,
+        Err(e) => {
+            error!("Failed to serialize dead letter for event {}: {}", event.id, e);
+            return;
+        }
+    };
+
+    let key = format!("notifications/dead-letters/{}/{}.json", config.name, event.id);
+    if let Err(e) = storage.put_blob(&key, data.into()).await {
+        error!("Failed to write dead letter for event {}: {}", event.id, e);
+    }
+}
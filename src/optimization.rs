@@ -1,22 +1,66 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use futures::stream::StreamExt;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 
 use crate::config::OptimizationConfig;
 use crate::storage::StorageBackend;
 
+/// Leading bytes of a gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Leading bytes of a zstd frame
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Leading bytes of an lz4 frame
+const LZ4_MAGIC: [u8; 4] = [0x04, 0x22, 0x4d, 0x18];
+
+/// Media type prefix of OCI layer tarballs, whatever their compression
+const OCI_LAYER_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar";
+
+/// Number of files listed in `LayerAnalysis::largest_files`
+const LARGEST_FILES_REPORTED: usize = 10;
+
+/// File name prefix marking a file deleted from a lower layer
+const WHITEOUT_PREFIX: &str = ".wh.";
+
+/// Whiteout hiding every lower-layer entry in its directory
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
 /// Automated image optimization service for drift registry
 /// Performs layer deduplication, compression optimization, and vulnerability scanning
 #[derive(Clone)]
 pub struct OptimizationService {
     config: OptimizationConfig,
+    /// Policy derived from `config`, checked when the service is created
+    policy: OptimizationPolicy,
     storage: Arc<dyn StorageBackend>,
     optimization_cache: Arc<RwLock<HashMap<String, OptimizationResult>>>,
     layer_index: Arc<RwLock<LayerIndex>>,
+    /// Set while a background run is in progress
+    running: Arc<AtomicBool>,
+    /// Progress of the current or most recent background run
+    progress: Arc<RwLock<OptimizationProgress>>,
+    shutdown: CancellationToken,
+}
+
+/// Progress of a background optimization run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OptimizationProgress {
+    pub running: bool,
+    pub started_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Layers pending optimization when the run started
+    pub total_layers: usize,
+    pub processed_layers: usize,
+    pub failed_layers: usize,
 }
 
 /// Layer index for tracking duplicate layers across images
@@ -30,6 +74,33 @@ pub struct LayerIndex {
     pub total_layers: usize,
     pub total_size_bytes: u64,
     pub deduplicated_size_bytes: u64,
+    /// Map of DiffID to the optimized variant shared by layers with that content
+    #[serde(default)]
+    pub variants: HashMap<String, LayerVariant>,
+    /// Map of layer digest to the manifests (`repo@digest`) using it
+    #[serde(default)]
+    pub layer_references: HashMap<String, BTreeSet<String>>,
+    /// Map of each optimized manifest served (`repo@digest`) to the pushed
+    /// manifest it was made from
+    #[serde(default)]
+    pub optimized_manifests: HashMap<String, OptimizedManifest>,
+}
+
+/// Pushed manifest an optimized manifest was made from, and the layer media
+/// types it was rewritten for. Together they rebuild the same document.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OptimizedManifest {
+    pub source_digest: String,
+    pub media_types: Vec<String>,
+}
+
+/// Optimized copy of a layer's content, stored alongside the original blobs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayerVariant {
+    pub digest: String,
+    pub media_type: String,
+    pub size: u64,
+    pub compression: CompressionType,
 }
 
 /// Metadata about a layer
@@ -38,7 +109,7 @@ pub struct LayerMetadata {
     pub digest: String,
     pub size: u64,
     pub media_type: String,
-    pub content_hash: String, // Hash of actual content (not compressed)
+    pub content_hash: String, // DiffID: sha256 of the uncompressed content
     pub compression: CompressionType,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub last_accessed: chrono::DateTime<chrono::Utc>,
@@ -61,6 +132,45 @@ pub enum CompressionType {
     Uncompressed,
 }
 
+impl CompressionType {
+    /// Detect the codec of a blob from its leading bytes. Brotli streams have
+    /// no magic number and are reported as uncompressed.
+    pub fn detect(data: &[u8]) -> Self {
+        if data.starts_with(&GZIP_MAGIC) {
+            CompressionType::Gzip
+        } else if data.starts_with(&ZSTD_MAGIC) {
+            CompressionType::Zstd
+        } else if data.starts_with(&LZ4_MAGIC) {
+            CompressionType::Lz4
+        } else {
+            CompressionType::Uncompressed
+        }
+    }
+
+    /// Media type of a layer tarball compressed with this codec. Only gzip,
+    /// zstd and uncompressed layers have media types defined by the OCI spec.
+    pub fn layer_media_type(&self) -> &'static str {
+        match self {
+            CompressionType::Gzip => "application/vnd.oci.image.layer.v1.tar+gzip",
+            CompressionType::Zstd => "application/vnd.oci.image.layer.v1.tar+zstd",
+            CompressionType::Lz4 => "application/vnd.oci.image.layer.v1.tar+lz4",
+            CompressionType::Brotli => "application/vnd.oci.image.layer.v1.tar+brotli",
+            CompressionType::Uncompressed => "application/vnd.oci.image.layer.v1.tar",
+        }
+    }
+
+    /// Stream the decompressed contents of `data`
+    fn decoder<'a, R: std::io::Read + 'a>(&self, data: R) -> std::io::Result<Box<dyn std::io::Read + 'a>> {
+        Ok(match self {
+            CompressionType::Gzip => Box::new(flate2::read::MultiGzDecoder::new(data)),
+            CompressionType::Zstd => Box::new(zstd::stream::read::Decoder::new(data)?),
+            CompressionType::Lz4 => Box::new(lz4_flex::frame::FrameDecoder::new(data)),
+            CompressionType::Brotli => Box::new(brotli::Decompressor::new(data, 4096)),
+            CompressionType::Uncompressed => Box::new(data),
+        })
+    }
+}
+
 /// Optimization status for layers
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum OptimizationStatus {
@@ -85,6 +195,12 @@ pub struct OptimizationResult {
     pub optimized_size: u64,
     pub compression_ratio: f64,
     pub optimization_type: OptimizationType,
+    /// Codec of the optimized layer, when it was recompressed
+    #[serde(default)]
+    pub compression: Option<CompressionType>,
+    /// Contents of the original layer
+    #[serde(default)]
+    pub analysis: Option<LayerAnalysis>,
     pub processing_time_ms: u64,
     pub status: OptimizationStatus,
     pub error_message: Option<String>,
@@ -118,6 +234,53 @@ pub struct OptimizationPolicy {
     pub optimization_schedule: OptimizationSchedule,
 }
 
+impl FromStr for CompressionType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "gzip" => Ok(CompressionType::Gzip),
+            "zstd" => Ok(CompressionType::Zstd),
+            "lz4" => Ok(CompressionType::Lz4),
+            "brotli" => Ok(CompressionType::Brotli),
+            "uncompressed" => Ok(CompressionType::Uncompressed),
+            other => Err(anyhow!(
+                "Unsupported compression {:?}; expected gzip, zstd, lz4, brotli or uncompressed",
+                other
+            )),
+        }
+    }
+}
+
+impl TryFrom<&OptimizationConfig> for OptimizationPolicy {
+    type Error = anyhow::Error;
+
+    fn try_from(config: &OptimizationConfig) -> Result<Self> {
+        let preferred_compression = config
+            .preferred_compression
+            .parse()
+            .map_err(|e| anyhow!("Invalid optimization.preferred_compression: {}", e))?;
+
+        Ok(Self {
+            enable_compression_optimization: config.enable_compression_optimization,
+            enable_layer_deduplication: config.enable_layer_deduplication,
+            enable_layer_squashing: config.enable_layer_squashing,
+            enable_base_image_optimization: config.enable_base_image_optimization,
+            preferred_compression,
+            min_layer_size_bytes: config.min_layer_size_mb * 1024 * 1024,
+            max_optimization_time_seconds: config.max_optimization_time_seconds,
+            preserve_original: config.preserve_original,
+            optimization_schedule: if config.optimize_on_upload {
+                OptimizationSchedule::Immediate
+            } else if config.background_optimization {
+                OptimizationSchedule::Background
+            } else {
+                OptimizationSchedule::Scheduled
+            },
+        })
+    }
+}
+
 /// When to run optimizations
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum OptimizationSchedule {
@@ -147,9 +310,21 @@ pub trait OptimizationBackend: Send + Sync {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LayerAnalysis {
     pub content_type: String,
+    /// sha256 of the uncompressed content, or of the blob itself when it isn't a tar
+    #[serde(default)]
+    pub diff_id: String,
     pub file_count: usize,
     pub directory_count: usize,
     pub largest_files: Vec<FileInfo>,
+    /// Files deleted from lower layers, excluding opaque directory markers
+    #[serde(default)]
+    pub whiteout_count: usize,
+    /// Directories whose lower-layer contents are hidden entirely
+    #[serde(default)]
+    pub opaque_directory_count: usize,
+    /// Total size of the regular files in the layer
+    #[serde(default)]
+    pub uncompressed_size: u64,
     pub compression_potential: f64, // Estimated compression ratio improvement
     pub duplicate_content_ratio: f64, // Ratio of duplicate content within layer
 }
@@ -171,17 +346,24 @@ pub struct DuplicateGroup {
 }
 
 impl OptimizationService {
+    /// Background runs stop picking up layers once `shutdown` is cancelled
     pub async fn new(
         config: OptimizationConfig,
         storage: Arc<dyn StorageBackend>,
+        shutdown: CancellationToken,
     ) -> Result<Self> {
         info!("Initializing image optimization service");
 
+        let policy = OptimizationPolicy::try_from(&config)?;
         let service = Self {
             config,
+            policy,
             storage,
             optimization_cache: Arc::new(RwLock::new(HashMap::new())),
             layer_index: Arc::new(RwLock::new(LayerIndex::default())),
+            running: Arc::new(AtomicBool::new(false)),
+            progress: Arc::new(RwLock::new(OptimizationProgress::default())),
+            shutdown,
         };
 
         // Load existing layer index
@@ -195,22 +377,24 @@ impl OptimizationService {
     pub async fn optimize_layer(
         &self,
         layer_digest: &str,
-        layer_data: &[u8],
+        layer_data: bytes::Bytes,
         policy: &OptimizationPolicy,
     ) -> Result<OptimizationResult> {
         info!("Optimizing layer: {}", layer_digest);
         let start_time = std::time::Instant::now();
 
         // Check if already optimized
-        if let Some(cached_result) = self.get_optimization_result(layer_digest).await {
-            if cached_result.status == OptimizationStatus::Optimized {
-                debug!("Layer already optimized, returning cached result");
-                return Ok(cached_result);
-            }
+        if let Some(cached_result) = self.get_optimization_result(layer_digest).await
+            && cached_result.status == OptimizationStatus::Optimized
+        {
+            debug!("Layer already optimized, returning cached result");
+            return Ok(cached_result);
         }
 
         // Analyze layer
-        let analysis = self.analyze_layer(layer_data).await?;
+        let deadline = std::time::Instant::now()
+            + std::time::Duration::from_secs(policy.max_optimization_time_seconds);
+        let analysis = self.analyze_layer(layer_data.clone(), deadline).await?;
         info!("Layer analysis: {} files, {} directories, {:.2}% compression potential",
             analysis.file_count, analysis.directory_count, analysis.compression_potential * 100.0);
 
@@ -223,6 +407,8 @@ impl OptimizationService {
                 optimized_size: layer_data.len() as u64,
                 compression_ratio: 1.0,
                 optimization_type: OptimizationType::Compression,
+                compression: None,
+                analysis: Some(analysis),
                 processing_time_ms: start_time.elapsed().as_millis() as u64,
                 status: OptimizationStatus::Skipped,
                 error_message: Some("Layer too small for optimization".to_string()),
@@ -230,19 +416,63 @@ impl OptimizationService {
             });
         }
 
-        // Perform optimization based on policy
-        let mut optimized_data = layer_data.to_vec();
+        let original_compression = CompressionType::detect(&layer_data);
+        self.update_layer_index(layer_digest, layer_data.len() as u64, &analysis, &original_compression).await?;
+
+        // Layers with the same uncompressed content share one optimized variant,
+        // whatever their compressed bytes. The original blob is always kept, so
+        // its digest keeps verifying for clients that pull it.
         let mut optimization_type = OptimizationType::Compression;
+        let canonical_digest = if policy.enable_layer_deduplication {
+            self.find_duplicate_layer(&analysis.diff_id, layer_digest).await
+        } else {
+            None
+        };
+        if let Some(canonical_digest) = canonical_digest {
+            info!("Found duplicate layer: {} -> {}", layer_digest, canonical_digest);
+            optimization_type = OptimizationType::Deduplication;
+
+            let shared_variant = self.get_variant(&analysis.diff_id).await
+                .filter(|variant| variant.compression == policy.preferred_compression);
+            if let Some(variant) = shared_variant {
+                let result = OptimizationResult {
+                    original_digest: layer_digest.to_string(),
+                    optimized_digest: Some(variant.digest),
+                    original_size: layer_data.len() as u64,
+                    optimized_size: 0, // The shared variant is already stored
+                    compression_ratio: 0.0,
+                    optimization_type,
+                    compression: Some(variant.compression),
+                    analysis: Some(analysis),
+                    processing_time_ms: start_time.elapsed().as_millis() as u64,
+                    status: OptimizationStatus::Optimized,
+                    error_message: None,
+                    created_at: chrono::Utc::now(),
+                };
+                self.cache_optimization_result(layer_digest, &result).await;
+                return Ok(result);
+            }
+        }
 
-        // Compression optimization
+        // Compression optimization. Switching to the preferred codec is kept even
+        // when it doesn't shrink the layer, since clients asked for that codec.
+        let mut optimized_data = layer_data.to_vec();
+        let mut compression = original_compression.clone();
         if policy.enable_compression_optimization && analysis.compression_potential > 0.1 {
-            match self.optimize_compression(&optimized_data, &policy.preferred_compression).await {
+            let target_compression = policy.preferred_compression.clone();
+            let recompressed = self
+                .optimize_compression(layer_data.clone(), original_compression.clone(), target_compression, deadline)
+                .await;
+            match recompressed {
                 Ok(compressed_data) => {
-                    if compressed_data.len() < optimized_data.len() {
-                        info!("Compression optimization: {} -> {} bytes ({:.2}% reduction)",
+                    let transcoded = policy.preferred_compression != original_compression;
+                    if transcoded || compressed_data.len() < optimized_data.len() {
+                        info!("Compression optimization ({:?} -> {:?}): {} -> {} bytes ({:.2}% reduction)",
+                            original_compression, policy.preferred_compression,
                             optimized_data.len(), compressed_data.len(),
                             (1.0 - compressed_data.len() as f64 / optimized_data.len() as f64) * 100.0);
                         optimized_data = compressed_data;
+                        compression = policy.preferred_compression.clone();
                     }
                 }
                 Err(e) => {
@@ -251,46 +481,22 @@ impl OptimizationService {
             }
         }
 
-        // Check for deduplication opportunities
-        if policy.enable_layer_deduplication {
-            if let Some(duplicate_digest) = self.find_duplicate_layer(&optimized_data).await? {
-                info!("Found duplicate layer: {} -> {}", layer_digest, duplicate_digest);
-                optimization_type = OptimizationType::Deduplication;
-
-                // Update reference count for the canonical layer
-                self.increment_layer_references(&duplicate_digest).await?;
-
-                return Ok(OptimizationResult {
-                    original_digest: layer_digest.to_string(),
-                    optimized_digest: Some(duplicate_digest),
-                    original_size: layer_data.len() as u64,
-                    optimized_size: 0, // No additional storage needed
-                    compression_ratio: 0.0, // Complete deduplication
-                    optimization_type,
-                    processing_time_ms: start_time.elapsed().as_millis() as u64,
-                    status: OptimizationStatus::Optimized,
-                    error_message: None,
-                    created_at: chrono::Utc::now(),
-                });
-            }
-        }
-
-        // Store optimized layer if different from original
+        // Store the optimized variant next to the original
         let optimized_digest = if optimized_data != layer_data {
             use sha2::Digest;
-            let mut hasher = sha2::Sha256::new();
-            hasher.update(&optimized_data);
-            let new_digest = format!("sha256:{}", hex::encode(hasher.finalize()));
-            let key = format!("blobs/{}", new_digest);
-            self.storage.put_blob(&key, optimized_data.clone().into()).await?;
+            let new_digest = format!("sha256:{}", hex::encode(sha2::Sha256::digest(&optimized_data)));
+            self.storage.put_blob(&new_digest, optimized_data.clone().into()).await?;
+            self.record_variant(&analysis.diff_id, LayerVariant {
+                digest: new_digest.clone(),
+                media_type: compression.layer_media_type().to_string(),
+                size: optimized_data.len() as u64,
+                compression: compression.clone(),
+            }).await?;
             Some(new_digest)
         } else {
             None
         };
 
-        // Update layer index
-        self.update_layer_index(layer_digest, &optimized_data, &analysis).await?;
-
         let result = OptimizationResult {
             original_digest: layer_digest.to_string(),
             optimized_digest,
@@ -298,6 +504,8 @@ impl OptimizationService {
             optimized_size: optimized_data.len() as u64,
             compression_ratio: optimized_data.len() as f64 / layer_data.len() as f64,
             optimization_type,
+            compression: (compression != original_compression).then_some(compression),
+            analysis: Some(analysis),
             processing_time_ms: start_time.elapsed().as_millis() as u64,
             status: OptimizationStatus::Optimized,
             error_message: None,
@@ -312,47 +520,97 @@ impl OptimizationService {
         Ok(result)
     }
 
-    /// Optimize image manifest (layer deduplication, base image optimization)
-    pub async fn optimize_manifest(
+    /// Rewrite an OCI image manifest to reference optimized layer variants whose
+    /// media type the client explicitly accepts. Returns None when no layer has
+    /// such a variant, so the original manifest is served unchanged. Variants
+    /// hold the same uncompressed content, so the config's DiffIDs still match.
+    pub async fn optimized_manifest(
         &self,
         manifest_content: &[u8],
-        policy: &OptimizationPolicy,
-    ) -> Result<Vec<u8>> {
-        debug!("Optimizing image manifest");
-
-        // Parse manifest
+        accepted_media_types: &[String],
+    ) -> Result<Option<Vec<u8>>> {
         let mut manifest: serde_json::Value = serde_json::from_slice(manifest_content)?;
+        let Some(layers) = manifest.get_mut("layers").and_then(|l| l.as_array_mut()) else {
+            return Ok(None);
+        };
 
-        // Extract layers
-        if let Some(layers) = manifest.get_mut("layers").and_then(|l| l.as_array_mut()) {
-            let mut optimized = false;
-
-            for layer in layers.iter_mut() {
-                if let Some(digest) = layer.get("digest").and_then(|d| d.as_str()) {
-                    let digest_str = digest.to_string();
-                    // Check if we have an optimized version of this layer
-                    if let Some(result) = self.get_optimization_result(&digest_str).await {
-                        if let Some(optimized_digest) = result.optimized_digest {
-                            // Update manifest to use optimized layer
-                            layer["digest"] = serde_json::Value::String(optimized_digest.clone());
-                            layer["size"] = serde_json::Value::Number(result.optimized_size.into());
-                            optimized = true;
-
-                            info!("Updated manifest to use optimized layer: {} -> {}",
-                                digest_str, optimized_digest);
-                        }
-                    }
-                }
+        let layer_index = self.layer_index.read().await;
+        let mut rewritten = 0;
+        for layer in layers.iter_mut() {
+            let is_oci_layer = layer.get("mediaType")
+                .and_then(|m| m.as_str())
+                .is_some_and(|m| m.starts_with(OCI_LAYER_MEDIA_TYPE));
+            let Some(digest) = layer.get("digest").and_then(|d| d.as_str()) else {
+                continue;
+            };
+            if !is_oci_layer {
+                continue;
             }
-
-            if optimized {
-                // Recalculate manifest size and config digest if needed
-                info!("Manifest optimized with {} layer optimizations",
-                    layers.len());
+            let Some(variant) = layer_index.layers.get(digest)
+                .and_then(|metadata| layer_index.variants.get(&metadata.content_hash))
+            else {
+                continue;
+            };
+            if variant.digest == digest || !accepted_media_types.contains(&variant.media_type) {
+                continue;
             }
+
+            debug!("Serving optimized variant {} for layer {}", variant.digest, digest);
+            layer["digest"] = serde_json::Value::String(variant.digest.clone());
+            layer["size"] = serde_json::Value::Number(variant.size.into());
+            layer["mediaType"] = serde_json::Value::String(variant.media_type.clone());
+            rewritten += 1;
         }
 
-        Ok(serde_json::to_vec_pretty(&manifest)?)
+        if rewritten == 0 {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::to_vec(&manifest)?))
+    }
+
+    /// Remember that the optimized manifest `digest` was made from `source_digest`,
+    /// so a later pull by its digest can rebuild it
+    pub async fn record_optimized_manifest(
+        &self,
+        repo: &str,
+        digest: &str,
+        source_digest: &str,
+        media_types: &[String],
+    ) -> Result<()> {
+        let key = format!("{}@{}", repo, digest);
+        let mut layer_index = self.layer_index.write().await;
+        if layer_index.optimized_manifests.contains_key(&key) {
+            return Ok(());
+        }
+        layer_index.optimized_manifests.insert(key, OptimizedManifest {
+            source_digest: source_digest.to_string(),
+            media_types: media_types.to_vec(),
+        });
+        self.save_layer_index(&layer_index).await
+    }
+
+    /// Where the optimized manifest `digest` served from `repo` came from
+    pub async fn optimized_manifest_source(&self, repo: &str, digest: &str) -> Option<OptimizedManifest> {
+        let layer_index = self.layer_index.read().await;
+        layer_index.optimized_manifests.get(&format!("{}@{}", repo, digest)).cloned()
+    }
+
+    /// Original layers whose optimized variant is stored as `digest`. The
+    /// garbage collector keeps a variant while any of them is still referenced.
+    pub async fn variant_originals(&self, digest: &str) -> Vec<String> {
+        let layer_index = self.layer_index.read().await;
+        let diff_ids: std::collections::HashSet<_> = layer_index.variants.iter()
+            .filter(|(_, variant)| variant.digest == digest)
+            .map(|(diff_id, _)| diff_id)
+            .collect();
+        if diff_ids.is_empty() {
+            return Vec::new();
+        }
+
+        layer_index.layers.values()
+            .filter(|layer| diff_ids.contains(&layer.content_hash))
+            .map(|layer| layer.digest.clone())
+            .collect()
     }
 
     /// Get optimization statistics
@@ -368,6 +626,7 @@ impl OptimizationService {
             total_savings: 0,
             compression_ratio: 0.0,
             optimization_results: HashMap::new(),
+            layers: Vec::new(),
         };
 
         for result in cache.values() {
@@ -384,6 +643,9 @@ impl OptimizationService {
             type_stats.total_savings += result.original_size.saturating_sub(result.optimized_size);
         }
 
+        stats.layers = cache.values().cloned().collect();
+        stats.layers.sort_by(|a, b| a.original_digest.cmp(&b.original_digest));
+
         if stats.total_original_size > 0 {
             stats.compression_ratio = stats.total_optimized_size as f64 / stats.total_original_size as f64;
         }
@@ -391,8 +653,112 @@ impl OptimizationService {
         stats
     }
 
-    /// Run background optimization job
-    pub async fn run_background_optimization(&self, policy: &OptimizationPolicy) -> Result<()> {
+    /// Run background optimization at each fire time of `optimization_schedule_cron`
+    /// until shutdown. Does nothing unless background optimization
+    /// is enabled and a schedule is configured.
+    pub fn spawn_scheduler(&self) -> Result<()> {
+        if !self.config.background_optimization {
+            return Ok(());
+        }
+        let Some(expression) = self.config.optimization_schedule_cron.as_deref() else {
+            return Ok(());
+        };
+
+        let schedule = parse_schedule(expression)?;
+        match schedule.upcoming(chrono::Utc).next() {
+            Some(next) => info!("Next background optimization run at {}", next),
+            None => {
+                warn!("Optimization schedule {} never fires", expression);
+                return Ok(());
+            }
+        }
+
+        let service = self.clone();
+        let policy = self.policy();
+        tokio::spawn(async move {
+            while let Some(next) = schedule.upcoming(chrono::Utc).next() {
+                let wait = (next - chrono::Utc::now()).to_std().unwrap_or_default();
+                tokio::select! {
+                    _ = service.shutdown.cancelled() => break,
+                    _ = tokio::time::sleep(wait) => {}
+                }
+
+                if let Err(e) = service.run_background_optimization(&policy).await {
+                    error!("Scheduled optimization run failed: {}", e);
+                }
+            }
+            info!("Optimization scheduler stopped");
+        });
+
+        Ok(())
+    }
+
+    /// Queue an uploaded blob for optimization. Blobs smaller than
+    /// `min_layer_size_mb` are ignored. With `optimize_on_upload` the layer is
+    /// optimized right away in the background; otherwise it waits for the next run.
+    pub fn layer_uploaded(&self, digest: &str, size: u64) {
+        let policy = self.policy();
+        if size < policy.min_layer_size_bytes {
+            return;
+        }
+
+        let service = self.clone();
+        let digest = digest.to_string();
+        tokio::spawn(async move {
+            let result = match policy.optimization_schedule {
+                OptimizationSchedule::Immediate => service.optimize_stored_layer(&digest, &policy).await,
+                OptimizationSchedule::Background | OptimizationSchedule::Scheduled => {
+                    service.register_pending_layer(&digest, size).await
+                }
+            };
+            if let Err(e) = result {
+                error!("Failed to optimize uploaded layer {}: {}", digest, e);
+            }
+        });
+    }
+
+    /// Policy derived from the service configuration
+    pub fn policy(&self) -> OptimizationPolicy {
+        self.policy.clone()
+    }
+
+    /// Whether a background run is in progress
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Progress of the current or most recent background run
+    pub async fn progress(&self) -> OptimizationProgress {
+        self.progress.read().await.clone()
+    }
+
+    /// Index entry for a layer, if it has been uploaded or optimized
+    pub async fn get_layer(&self, digest: &str) -> Option<LayerMetadata> {
+        let layer_index = self.layer_index.read().await;
+        layer_index.layers.get(digest).cloned()
+    }
+
+    /// Run background optimization job. Only one run happens at a time; a run
+    /// requested while another is in progress is skipped and returns `None`.
+    pub async fn run_background_optimization(
+        &self,
+        policy: &OptimizationPolicy,
+    ) -> Result<Option<OptimizationProgress>> {
+        if self.running.swap(true, Ordering::SeqCst) {
+            warn!("Background optimization is already running, skipping this run");
+            return Ok(None);
+        }
+
+        let result = self.optimize_pending_layers(policy).await;
+        self.running.store(false, Ordering::SeqCst);
+
+        let mut progress = self.progress.write().await;
+        progress.running = false;
+        progress.finished_at = Some(chrono::Utc::now());
+        result.map(|()| Some(progress.clone()))
+    }
+
+    async fn optimize_pending_layers(&self, policy: &OptimizationPolicy) -> Result<()> {
         info!("Starting background optimization job");
 
         // Find unoptimized layers
@@ -404,27 +770,41 @@ impl OptimizationService {
         drop(layer_index);
 
         info!("Found {} layers pending optimization", unoptimized_layers.len());
+        *self.progress.write().await = OptimizationProgress {
+            running: true,
+            started_at: Some(chrono::Utc::now()),
+            finished_at: None,
+            total_layers: unoptimized_layers.len(),
+            processed_layers: 0,
+            failed_layers: 0,
+        };
 
-        // Optimize layers in batches
-        for layer in unoptimized_layers {
-            // Load layer data
-            let key = format!("blobs/{}", layer.digest);
-            if let Some(layer_data) = self.storage.get_blob(&key).await? {
-                match self.optimize_layer(&layer.digest, &layer_data, policy).await {
-                    Ok(result) => {
-                        info!("Background optimization completed for {}: {:?}",
-                            layer.digest, result.status);
-                    }
-                    Err(e) => {
-                        error!("Background optimization failed for {}: {}",
-                            layer.digest, e);
+        // Optimize up to `optimization_workers` layers at once on separate tasks,
+        // stopping early on shutdown
+        futures::stream::iter(unoptimized_layers)
+            .take_until(self.shutdown.cancelled())
+            .for_each_concurrent(self.config.optimization_workers.max(1), |layer| {
+                let service = self.clone();
+                let policy = policy.clone();
+                async move {
+                    let digest = layer.digest.clone();
+                    let task = tokio::spawn(async move {
+                        service.optimize_stored_layer(&layer.digest, &policy).await
+                    });
+                    let outcome = match task.await {
+                        Ok(result) => result,
+                        Err(e) => Err(anyhow!("Optimization task failed: {}", e)),
+                    };
+
+                    let mut progress = self.progress.write().await;
+                    progress.processed_layers += 1;
+                    if let Err(e) = outcome {
+                        error!("Background optimization failed for {}: {}", digest, e);
+                        progress.failed_layers += 1;
                     }
                 }
-            }
-
-            // Rate limiting to avoid overwhelming the system
-            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-        }
+            })
+            .await;
 
         info!("Background optimization job completed");
         Ok(())
@@ -432,59 +812,208 @@ impl OptimizationService {
 
     // Private helper methods
 
-    async fn analyze_layer(&self, layer_data: &[u8]) -> Result<LayerAnalysis> {
-        // Simplified layer analysis - in production this would extract and analyze the tar contents
+    /// Load a stored layer and optimize it, recording the outcome in the layer index
+    async fn optimize_stored_layer(&self, digest: &str, policy: &OptimizationPolicy) -> Result<()> {
+        let Some(layer_data) = self.storage.get_blob(digest).await? else {
+            debug!("Layer {} is no longer stored", digest);
+            return Ok(());
+        };
+
+        match self.optimize_layer(digest, layer_data, policy).await {
+            Ok(result) => {
+                info!("Optimization completed for {}: {:?}", digest, result.status);
+                self.set_layer_status(digest, result.status).await
+            }
+            Err(e) => {
+                self.set_layer_status(digest, OptimizationStatus::Failed).await?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Add an uploaded layer to the index for the next background run
+    async fn register_pending_layer(&self, digest: &str, size: u64) -> Result<()> {
+        // The codec is identified from the first chunk, without loading the whole layer
+        let Some((_, mut stream)) = self.storage.get_blob_stream(digest).await? else {
+            return Ok(());
+        };
+        let compression = match stream.next().await {
+            Some(chunk) => CompressionType::detect(&chunk?),
+            None => CompressionType::Uncompressed,
+        };
+
+        let mut layer_index = self.layer_index.write().await;
+        if layer_index.layers.contains_key(digest) {
+            return Ok(());
+        }
+
+        let now = chrono::Utc::now();
+        layer_index.layers.insert(digest.to_string(), LayerMetadata {
+            digest: digest.to_string(),
+            size,
+            media_type: compression.layer_media_type().to_string(),
+            content_hash: String::new(),
+            compression,
+            created_at: now,
+            last_accessed: now,
+            reference_count: 0,
+            optimization_status: OptimizationStatus::Pending,
+        });
+        layer_index.total_layers += 1;
+        layer_index.total_size_bytes += size;
+        debug!("Queued layer {} for optimization", digest);
+
+        self.save_layer_index(&layer_index).await
+    }
+
+    async fn set_layer_status(&self, digest: &str, status: OptimizationStatus) -> Result<()> {
+        let mut layer_index = self.layer_index.write().await;
+        let Some(layer) = layer_index.layers.get_mut(digest) else {
+            return Ok(());
+        };
+        layer.optimization_status = status;
+        self.save_layer_index(&layer_index).await
+    }
+
+    /// Describe the contents of a layer. Fails if the layer decompresses to more
+    /// than `max_decompressed_layer_size_mb` or is still being read at `deadline`.
+    async fn analyze_layer(&self, layer_data: bytes::Bytes, deadline: std::time::Instant) -> Result<LayerAnalysis> {
         debug!("Analyzing layer ({} bytes)", layer_data.len());
 
-        // Estimate compression potential based on entropy
-        let entropy = self.calculate_entropy(layer_data);
-        let compression_potential = (1.0 - entropy).max(0.0);
+        // Decompressing and hashing are CPU-bound, so they run off the async workers
+        let max_bytes = self.config.max_decompressed_layer_size_mb * 1024 * 1024;
+        tokio::task::spawn_blocking(move || Self::walk_layer(&layer_data, max_bytes, deadline)).await?
+    }
 
-        Ok(LayerAnalysis {
-            content_type: "application/vnd.docker.image.rootfs.diff.tar".to_string(),
-            file_count: (layer_data.len() / 4096).min(10000), // Rough estimate
-            directory_count: (layer_data.len() / 40960).min(1000), // Rough estimate
-            largest_files: vec![], // Would extract from tar in real implementation
-            compression_potential,
-            duplicate_content_ratio: 0.0, // Would analyze in real implementation
+    /// Decompress a layer and walk its tar entries within the analysis budget,
+    /// describing it as an opaque blob if it isn't a readable tar archive
+    fn walk_layer(layer_data: &[u8], max_bytes: u64, deadline: std::time::Instant) -> Result<LayerAnalysis> {
+        let compression = CompressionType::detect(layer_data);
+        let walked = match compression.decoder(layer_data) {
+            Ok(reader) => {
+                let mut budget = AnalysisBudget::new(reader, max_bytes, deadline);
+                // Read past the end of the archive too, so the DiffID covers every byte
+                let walked = Self::walk_layer_tar(&mut budget).and_then(|analysis| {
+                    std::io::copy(&mut budget, &mut std::io::sink())?;
+                    Ok(analysis)
+                });
+                if let Some(reason) = budget.exceeded {
+                    return Err(anyhow!("Layer analysis aborted: {}", reason));
+                }
+                walked.map(|analysis| LayerAnalysis {
+                    diff_id: format!("sha256:{}", hex::encode(sha2::Digest::finalize(budget.hasher))),
+                    ..analysis
+                })
+            }
+            Err(e) => Err(e),
+        };
+
+        match walked {
+            Ok(mut analysis) => {
+                analysis.content_type = compression.layer_media_type().to_string();
+                Ok(analysis)
+            }
+            Err(e) => {
+                // Not a tar archive we can read; describe it as an opaque blob
+                debug!("Layer is not a readable tar archive, analyzing as opaque: {}", e);
+                use sha2::Digest;
+                Ok(LayerAnalysis {
+                    content_type: "application/octet-stream".to_string(),
+                    diff_id: format!("sha256:{}", hex::encode(sha2::Sha256::digest(layer_data))),
+                    file_count: 0,
+                    directory_count: 0,
+                    largest_files: vec![],
+                    whiteout_count: 0,
+                    opaque_directory_count: 0,
+                    uncompressed_size: 0,
+                    compression_potential: (1.0 - calculate_entropy(layer_data)).max(0.0),
+                    duplicate_content_ratio: 0.0,
+                })
+            }
+        }
+    }
+
+    /// Recompress `data` with `target_compression`, decompressing it from
+    /// `current_compression` first. The decompressed stream is held to the same
+    /// budget as layer analysis.
+    async fn optimize_compression(
+        &self,
+        data: bytes::Bytes,
+        current_compression: CompressionType,
+        target_compression: CompressionType,
+        deadline: std::time::Instant,
+    ) -> Result<Vec<u8>> {
+        let max_bytes = self.config.max_decompressed_layer_size_mb * 1024 * 1024;
+        let level = self.config.compression_level;
+        tokio::task::spawn_blocking(move || {
+            Self::recompress(&data, &current_compression, &target_compression, level, max_bytes, deadline)
         })
+        .await?
     }
 
-    async fn optimize_compression(&self, data: &[u8], target_compression: &CompressionType) -> Result<Vec<u8>> {
-        match target_compression {
+    /// Stream `data` through its decoder into the target encoder
+    fn recompress(
+        data: &[u8],
+        current_compression: &CompressionType,
+        target_compression: &CompressionType,
+        level: Option<i32>,
+        max_bytes: u64,
+        deadline: std::time::Instant,
+    ) -> Result<Vec<u8>> {
+        let mut budget = AnalysisBudget::new(current_compression.decoder(data)?, max_bytes, deadline);
+        let encoded = match target_compression {
             CompressionType::Gzip => {
-                use std::io::Write;
-                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
-                encoder.write_all(data)?;
-                Ok(encoder.finish()?)
-            }
-            CompressionType::Zstd => {
-                // Would use zstd crate in real implementation
-                warn!("Zstd compression not implemented, using gzip");
-                Box::pin(self.optimize_compression(data, &CompressionType::Gzip)).await
+                let level = match level {
+                    Some(level) => flate2::Compression::new(level.clamp(0, 9) as u32),
+                    None => flate2::Compression::best(),
+                };
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), level);
+                std::io::copy(&mut budget, &mut encoder).and_then(|_| encoder.finish())
             }
+            CompressionType::Zstd => zstd::encode_all(&mut budget, level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL)),
             CompressionType::Lz4 => {
-                // Would use lz4 crate in real implementation
-                warn!("LZ4 compression not implemented, using gzip");
-                Box::pin(self.optimize_compression(data, &CompressionType::Gzip)).await
+                // Frame format, so the output is readable by the lz4 CLI
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+                std::io::copy(&mut budget, &mut encoder)
+                    .and_then(|_| encoder.finish().map_err(std::io::Error::other))
             }
             CompressionType::Brotli => {
-                // Would use brotli crate in real implementation
-                warn!("Brotli compression not implemented, using gzip");
-                Box::pin(self.optimize_compression(data, &CompressionType::Gzip)).await
+                let params = brotli::enc::BrotliEncoderParams {
+                    quality: level.map_or(11, |level| level.clamp(0, 11)),
+                    ..Default::default()
+                };
+                let mut output = Vec::new();
+                brotli::BrotliCompress(&mut budget, &mut output, &params).map(|_| output)
+            }
+            CompressionType::Uncompressed => {
+                let mut output = Vec::new();
+                std::io::Read::read_to_end(&mut budget, &mut output).map(|_| output)
             }
-            CompressionType::Uncompressed => Ok(data.to_vec()),
+        };
+        if let Some(reason) = budget.exceeded {
+            return Err(anyhow!("Layer recompression aborted: {}", reason));
         }
+        Ok(encoded?)
     }
 
-    async fn find_duplicate_layer(&self, layer_data: &[u8]) -> Result<Option<String>> {
-        use sha2::Digest;
-        let mut hasher = sha2::Sha256::new();
-        hasher.update(layer_data);
-        let content_hash = hex::encode(hasher.finalize());
+    /// First layer indexed with the same uncompressed content, if it isn't this one
+    async fn find_duplicate_layer(&self, diff_id: &str, digest: &str) -> Option<String> {
+        let layer_index = self.layer_index.read().await;
+        layer_index.content_map.get(diff_id).filter(|canonical| *canonical != digest).cloned()
+    }
 
+    async fn get_variant(&self, diff_id: &str) -> Option<LayerVariant> {
         let layer_index = self.layer_index.read().await;
-        Ok(layer_index.content_map.get(&content_hash).cloned())
+        layer_index.variants.get(diff_id).cloned()
+    }
+
+    async fn record_variant(&self, diff_id: &str, variant: LayerVariant) -> Result<()> {
+        let mut layer_index = self.layer_index.write().await;
+        layer_index.deduplicated_size_bytes += variant.size;
+        if let Some(previous) = layer_index.variants.insert(diff_id.to_string(), variant) {
+            layer_index.deduplicated_size_bytes = layer_index.deduplicated_size_bytes.saturating_sub(previous.size);
+        }
+        self.save_layer_index(&layer_index).await
     }
 
     async fn load_layer_index(&self) -> Result<()> {
@@ -503,30 +1032,39 @@ impl OptimizationService {
         Ok(())
     }
 
-    async fn update_layer_index(&self, digest: &str, data: &[u8], analysis: &LayerAnalysis) -> Result<()> {
-        use sha2::Digest;
-        let mut hasher = sha2::Sha256::new();
-        hasher.update(data);
-        let content_hash = hex::encode(hasher.finalize());
-
+    /// Index an original layer under its DiffID
+    async fn update_layer_index(
+        &self,
+        digest: &str,
+        size: u64,
+        analysis: &LayerAnalysis,
+        compression: &CompressionType,
+    ) -> Result<()> {
         let mut layer_index = self.layer_index.write().await;
 
+        let now = chrono::Utc::now();
+        let previous = layer_index.layers.get(digest);
         let metadata = LayerMetadata {
             digest: digest.to_string(),
-            size: data.len() as u64,
+            size,
             media_type: analysis.content_type.clone(),
-            content_hash: content_hash.clone(),
-            compression: CompressionType::Gzip, // Assume gzip for now
-            created_at: chrono::Utc::now(),
-            last_accessed: chrono::Utc::now(),
-            reference_count: 1,
+            content_hash: analysis.diff_id.clone(),
+            compression: compression.clone(),
+            created_at: previous.map_or(now, |previous| previous.created_at),
+            last_accessed: now,
+            reference_count: layer_index.layer_references.get(digest).map_or(0, BTreeSet::len),
             optimization_status: OptimizationStatus::Optimized,
         };
 
-        layer_index.layers.insert(digest.to_string(), metadata);
-        layer_index.content_map.insert(content_hash, digest.to_string());
-        layer_index.total_layers += 1;
-        layer_index.total_size_bytes += data.len() as u64;
+        // A layer queued on upload is already counted; replace its size
+        match layer_index.layers.insert(digest.to_string(), metadata) {
+            Some(previous) => {
+                layer_index.total_size_bytes = layer_index.total_size_bytes.saturating_sub(previous.size);
+            }
+            None => layer_index.total_layers += 1,
+        }
+        layer_index.content_map.entry(analysis.diff_id.clone()).or_insert_with(|| digest.to_string());
+        layer_index.total_size_bytes += size;
 
         // Save updated index
         self.save_layer_index(&layer_index).await?;
@@ -541,16 +1079,73 @@ impl OptimizationService {
         Ok(())
     }
 
-    async fn increment_layer_references(&self, digest: &str) -> Result<()> {
+    /// Count a pushed manifest as a user of each of its layers. References are
+    /// kept per manifest rather than as bare counters, so pushing or deleting
+    /// the same manifest twice can't make them drift.
+    pub async fn retain_manifest_layers(&self, repo: &str, digest: &str, manifest_content: &[u8]) -> Result<()> {
+        self.update_layer_references(repo, digest, manifest_content, true).await
+    }
+
+    /// Drop a deleted manifest's references to its layers. Once no image uses a
+    /// layer, or any layer it was deduplicated with, the garbage collector may
+    /// remove it like any other blob.
+    pub async fn release_manifest_layers(&self, repo: &str, digest: &str, manifest_content: &[u8]) -> Result<()> {
+        self.update_layer_references(repo, digest, manifest_content, false).await
+    }
+
+    async fn update_layer_references(&self, repo: &str, digest: &str, manifest_content: &[u8], add: bool) -> Result<()> {
+        let manifest: serde_json::Value = serde_json::from_slice(manifest_content)?;
+        let Some(layers) = manifest.get("layers").and_then(|l| l.as_array()) else {
+            return Ok(());
+        };
+
+        let manifest_key = format!("{}@{}", repo, digest);
         let mut layer_index = self.layer_index.write().await;
-        if let Some(layer) = layer_index.layers.get_mut(digest) {
-            layer.reference_count += 1;
-            layer.last_accessed = chrono::Utc::now();
+        let mut changed = false;
+        for layer in layers.iter().filter_map(|layer| layer.get("digest").and_then(|d| d.as_str())) {
+            let references = layer_index.layer_references.entry(layer.to_string()).or_default();
+            changed |= if add { references.insert(manifest_key.clone()) } else { references.remove(&manifest_key) };
+            let count = references.len();
+            if count == 0 {
+                layer_index.layer_references.remove(layer);
+            }
+            if let Some(metadata) = layer_index.layers.get_mut(layer) {
+                metadata.reference_count = count;
+            }
+            debug!("Layer {} now has {} references", layer, count);
+        }
+
+        // Optimized manifests made from a deleted manifest can't be rebuilt any more
+        if !add {
+            let repo_prefix = format!("{}@", repo);
+            let served = layer_index.optimized_manifests.len();
+            layer_index.optimized_manifests
+                .retain(|key, optimized| !key.starts_with(&repo_prefix) || optimized.source_digest != digest);
+            changed |= layer_index.optimized_manifests.len() != served;
+        }
+
+        if changed {
+            self.save_layer_index(&layer_index).await?;
         }
         Ok(())
     }
 
-    async fn get_optimization_result(&self, digest: &str) -> Option<OptimizationResult> {
+    /// Whether images still use a layer, or another layer with the same content
+    /// that was deduplicated against it
+    pub async fn is_layer_referenced(&self, digest: &str) -> bool {
+        let layer_index = self.layer_index.read().await;
+        let in_use = |digest: &str| layer_index.layer_references.contains_key(digest);
+        match layer_index.layers.get(digest) {
+            Some(layer) => layer_index
+                .layers
+                .values()
+                .any(|other| other.content_hash == layer.content_hash && in_use(&other.digest)),
+            None => in_use(digest),
+        }
+    }
+
+    /// Latest optimization result for a layer
+    pub async fn get_optimization_result(&self, digest: &str) -> Option<OptimizationResult> {
         let cache = self.optimization_cache.read().await;
         cache.get(digest).cloned()
     }
@@ -560,23 +1155,174 @@ impl OptimizationService {
         cache.insert(digest.to_string(), result.clone());
     }
 
-    fn calculate_entropy(&self, data: &[u8]) -> f64 {
-        let mut freq = [0u64; 256];
-        for &byte in data {
-            freq[byte as usize] += 1;
-        }
+    /// Walk the entries of an uncompressed tar stream, hashing each file to find
+    /// duplicate content and weighting compression potential by file size.
+    /// Files are streamed through in chunks rather than read whole.
+    fn walk_layer_tar<R: std::io::Read>(reader: R) -> std::io::Result<LayerAnalysis> {
+        use sha2::Digest;
+        use std::io::Read;
+
+        let mut archive = tar::Archive::new(reader);
+        let mut file_count = 0;
+        let mut directory_count = 0;
+        let mut whiteout_count = 0;
+        let mut opaque_directory_count = 0;
+        let mut files = Vec::new();
+        let mut seen_hashes = std::collections::HashSet::new();
+        let mut total_bytes = 0u64;
+        let mut duplicate_bytes = 0u64;
+        let mut weighted_potential = 0.0;
+        let mut chunk = vec![0u8; 64 * 1024];
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let entry_type = entry.header().entry_type();
+            if entry_type.is_dir() {
+                directory_count += 1;
+                continue;
+            }
+            if !entry_type.is_file() {
+                continue;
+            }
 
-        let len = data.len() as f64;
-        let mut entropy = 0.0;
+            let path = entry.path()?.to_string_lossy().into_owned();
+            let name = path.rsplit('/').next().unwrap_or_default();
+            if name == OPAQUE_WHITEOUT {
+                opaque_directory_count += 1;
+                continue;
+            }
+            if name.starts_with(WHITEOUT_PREFIX) {
+                whiteout_count += 1;
+                continue;
+            }
 
-        for &count in &freq {
-            if count > 0 {
-                let p = count as f64 / len;
-                entropy -= p * p.log2();
+            let mut hasher = sha2::Sha256::new();
+            let mut frequencies = [0u64; 256];
+            let mut size = 0u64;
+            loop {
+                let read = match entry.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(read) => read,
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                };
+                hasher.update(&chunk[..read]);
+                for &byte in &chunk[..read] {
+                    frequencies[byte as usize] += 1;
+                }
+                size += read as u64;
             }
+
+            let hash = format!("sha256:{}", hex::encode(hasher.finalize()));
+            if !seen_hashes.insert(hash.clone()) {
+                duplicate_bytes += size;
+            }
+            total_bytes += size;
+            weighted_potential += (1.0 - entropy(&frequencies)).max(0.0) * size as f64;
+
+            file_count += 1;
+            files.push(FileInfo { path, size, hash });
+        }
+
+        files.sort_by_key(|file| std::cmp::Reverse(file.size));
+        files.truncate(LARGEST_FILES_REPORTED);
+
+        let (compression_potential, duplicate_content_ratio) = if total_bytes > 0 {
+            (weighted_potential / total_bytes as f64, duplicate_bytes as f64 / total_bytes as f64)
+        } else {
+            (0.0, 0.0)
+        };
+
+        Ok(LayerAnalysis {
+            content_type: String::new(),
+            diff_id: String::new(),
+            file_count,
+            directory_count,
+            largest_files: files,
+            whiteout_count,
+            opaque_directory_count,
+            uncompressed_size: total_bytes,
+            compression_potential,
+            duplicate_content_ratio,
+        })
+    }
+}
+
+fn calculate_entropy(data: &[u8]) -> f64 {
+    let mut freq = [0u64; 256];
+    for &byte in data {
+        freq[byte as usize] += 1;
+    }
+    entropy(&freq)
+}
+
+/// Shannon entropy of bytes occurring with the given frequencies, normalized to 0-1
+fn entropy(freq: &[u64; 256]) -> f64 {
+    let len = freq.iter().sum::<u64>() as f64;
+    let mut entropy = 0.0;
+
+    for &count in freq {
+        if count > 0 {
+            let p = count as f64 / len;
+            entropy -= p * p.log2();
         }
+    }
+
+    entropy / 8.0 // Normalize to 0-1 range
+}
+
+/// Parse a cron expression. The five-field form without seconds is accepted
+/// and fires at second zero.
+fn parse_schedule(expression: &str) -> Result<cron::Schedule> {
+    let expression = expression.trim();
+    let expression = if expression.split_whitespace().count() == 5 {
+        format!("0 {}", expression)
+    } else {
+        expression.to_string()
+    };
+
+    cron::Schedule::from_str(&expression)
+        .map_err(|e| anyhow!("Invalid optimization schedule {}: {}", expression, e))
+}
+
+/// Reader that cuts a layer analysis or recompression short once it has
+/// decompressed too much data or run past its deadline, recording which limit
+/// was hit. Hashes what it reads to give the layer's DiffID.
+struct AnalysisBudget<R> {
+    inner: R,
+    max_bytes: u64,
+    read_bytes: u64,
+    deadline: std::time::Instant,
+    exceeded: Option<String>,
+    hasher: sha2::Sha256,
+}
+
+impl<R> AnalysisBudget<R> {
+    fn new(inner: R, max_bytes: u64, deadline: std::time::Instant) -> Self {
+        use sha2::Digest;
+        Self { inner, max_bytes, read_bytes: 0, deadline, exceeded: None, hasher: sha2::Sha256::new() }
+    }
 
-        entropy / 8.0 // Normalize to 0-1 range
+    fn exceed(&mut self, reason: String) -> std::io::Error {
+        let error = std::io::Error::other(reason.clone());
+        self.exceeded = Some(reason);
+        error
+    }
+}
+
+impl<R: std::io::Read> std::io::Read for AnalysisBudget<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if std::time::Instant::now() > self.deadline {
+            return Err(self.exceed("time budget exhausted".to_string()));
+        }
+
+        let read = self.inner.read(buf)?;
+        self.read_bytes += read as u64;
+        if self.read_bytes > self.max_bytes {
+            return Err(self.exceed(format!("decompressed size exceeds {} bytes", self.max_bytes)));
+        }
+        sha2::Digest::update(&mut self.hasher, &buf[..read]);
+        Ok(read)
     }
 }
 
@@ -589,10 +1335,255 @@ pub struct OptimizationStats {
     pub total_savings: u64,
     pub compression_ratio: f64,
     pub optimization_results: HashMap<OptimizationType, TypeStats>,
+    /// Latest result for each layer, ordered by digest
+    pub layers: Vec<OptimizationResult>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TypeStats {
     pub count: usize,
     pub total_savings: u64,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::MemoryStorage;
+    use sha2::Digest;
+    use std::io::{Read, Write};
+
+    async fn service(compression_level: Option<i32>) -> OptimizationService {
+        let mut config = crate::Config::default().optimization.unwrap();
+        config.compression_level = compression_level;
+        OptimizationService::new(config, Arc::new(MemoryStorage::new(None)), CancellationToken::new())
+            .await
+            .unwrap()
+    }
+
+    fn payload() -> Vec<u8> {
+        (0..20_000).flat_map(|i| format!("line {} of a compressible layer\n", i % 97).into_bytes()).collect()
+    }
+
+    /// A gzipped tarball holding `payload()`
+    fn layer() -> Vec<u8> {
+        let data = payload();
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, "etc/data.txt", &data[..]).unwrap();
+        let tarball = builder.into_inner().unwrap();
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(&tarball).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn deadline() -> std::time::Instant {
+        std::time::Instant::now() + std::time::Duration::from_secs(30)
+    }
+
+    /// Compress `payload()` with `target` from its uncompressed form
+    async fn compress(service: &OptimizationService, target: &CompressionType) -> Result<Vec<u8>> {
+        let payload = payload().into();
+        service.optimize_compression(payload, CompressionType::Uncompressed, target.clone(), deadline()).await
+    }
+
+    /// Decode with each algorithm's own decoder rather than `CompressionType::decoder`
+    fn decode(compression: &CompressionType, data: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        match compression {
+            CompressionType::Gzip => flate2::read::GzDecoder::new(data).read_to_end(&mut output).unwrap(),
+            CompressionType::Zstd => return zstd::decode_all(data).unwrap(),
+            CompressionType::Lz4 => lz4_flex::frame::FrameDecoder::new(data).read_to_end(&mut output).unwrap(),
+            CompressionType::Brotli => brotli::Decompressor::new(data, 4096).read_to_end(&mut output).unwrap(),
+            CompressionType::Uncompressed => return data.to_vec(),
+        };
+        output
+    }
+
+    #[tokio::test]
+    async fn every_codec_round_trips_through_standard_decoders() {
+        let service = service(None).await;
+        let payload = payload();
+
+        for target in [CompressionType::Gzip, CompressionType::Zstd, CompressionType::Lz4, CompressionType::Brotli] {
+            let compressed = compress(&service, &target).await.unwrap();
+            assert!(compressed.len() < payload.len() / 4, "{:?}: {} bytes", target, compressed.len());
+            assert_eq!(decode(&target, &compressed), payload, "{:?}", target);
+            let mut decoded = Vec::new();
+            target.decoder(&compressed[..]).unwrap().read_to_end(&mut decoded).unwrap();
+            assert_eq!(decoded, payload, "{:?}", target);
+
+            // Transcoding starts from the decompressed content
+            let transcoded = service
+                .optimize_compression(compressed.into(), target.clone(), CompressionType::Zstd, deadline())
+                .await
+                .unwrap();
+            assert_eq!(zstd::decode_all(&transcoded[..]).unwrap(), payload, "{:?}", target);
+        }
+    }
+
+    #[tokio::test]
+    async fn recompression_stops_at_the_decompressed_size_limit() {
+        let mut config = crate::Config::default().optimization.unwrap();
+        config.max_decompressed_layer_size_mb = 0;
+        let service = OptimizationService::new(config, Arc::new(MemoryStorage::new(None)), CancellationToken::new());
+        let service = service.await.unwrap();
+
+        let error = service
+            .optimize_compression(layer().into(), CompressionType::Gzip, CompressionType::Zstd, deadline())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("decompressed size exceeds"), "{}", error);
+
+        let expired = std::time::Instant::now() - std::time::Duration::from_secs(1);
+        let error = service.optimize_compression(layer().into(), CompressionType::Gzip, CompressionType::Zstd, expired);
+        let error = error.await.unwrap_err();
+        assert!(error.to_string().contains("time budget"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn unsupported_preferred_compression_is_rejected() {
+        let mut config = crate::Config::default().optimization.unwrap();
+        config.preferred_compression = "xz".to_string();
+        let service = OptimizationService::new(config, Arc::new(MemoryStorage::new(None)), CancellationToken::new());
+        let error = service.await.err().unwrap();
+        assert!(error.to_string().contains("preferred_compression"), "{}", error);
+
+        for name in ["gzip", "zstd", "lz4", "brotli", "uncompressed"] {
+            let compression: CompressionType = name.parse().unwrap();
+            assert_eq!(serde_json::to_value(&compression).unwrap(), name);
+        }
+    }
+
+    /// Compares gzip and zstd on a sample layer. Run with
+    /// `cargo test --lib compression_benchmark -- --ignored --nocapture`.
+    #[tokio::test]
+    #[ignore]
+    async fn gzip_and_zstd_compression_benchmark() {
+        let service = service(None).await;
+        let layer = layer();
+        let uncompressed = decode(&CompressionType::Gzip, &layer).len();
+
+        for target in [CompressionType::Gzip, CompressionType::Zstd] {
+            let start = std::time::Instant::now();
+            let compressed = service
+                .optimize_compression(layer.clone().into(), CompressionType::Gzip, target.clone(), deadline())
+                .await
+                .unwrap();
+            let elapsed = start.elapsed();
+            assert_eq!(decode(&target, &compressed).len(), uncompressed);
+            println!(
+                "{:?}: {} -> {} bytes, ratio {:.4}, {:?}",
+                target,
+                uncompressed,
+                compressed.len(),
+                compressed.len() as f64 / uncompressed as f64,
+                elapsed
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn compression_levels_apply_to_zstd() {
+        let payload = payload();
+        let fast = compress(&service(Some(1)).await, &CompressionType::Zstd).await;
+        let best = compress(&service(Some(19)).await, &CompressionType::Zstd).await;
+        let (fast, best) = (fast.unwrap(), best.unwrap());
+        assert!(best.len() <= fast.len(), "level 19: {}, level 1: {}", best.len(), fast.len());
+        assert_eq!(zstd::decode_all(&best[..]).unwrap(), payload);
+    }
+
+    #[tokio::test]
+    async fn analysis_counts_the_entries_of_a_layer() {
+        let layer = std::fs::read(format!("{}/tests/fixtures/layers/layer.tar.gz", env!("CARGO_MANIFEST_DIR")));
+        let layer = layer.unwrap();
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(30);
+        let analysis = service(None).await.analyze_layer(layer.clone().into(), deadline).await.unwrap();
+
+        assert_eq!(analysis.content_type, "application/vnd.oci.image.layer.v1.tar+gzip");
+        let tarball = decode(&CompressionType::Gzip, &layer);
+        assert_eq!(analysis.diff_id, format!("sha256:{}", hex::encode(sha2::Sha256::digest(&tarball))));
+        assert_eq!((analysis.file_count, analysis.directory_count), (3, 6));
+        assert_eq!((analysis.whiteout_count, analysis.opaque_directory_count), (1, 1));
+        assert_eq!(analysis.uncompressed_size, 2 * 1640 + 12288);
+        assert_eq!(analysis.duplicate_content_ratio, 1640.0 / (2 * 1640 + 12288) as f64);
+
+        let largest: Vec<_> = analysis.largest_files.iter().map(|file| (file.path.as_str(), file.size)).collect();
+        assert_eq!(largest[0], ("usr/bin/tool", 12288));
+        assert_eq!(&largest[1..].iter().map(|(_, size)| *size).collect::<Vec<_>>(), &[1640, 1640]);
+        let tool = "sha256:8dec5fb0de8a0b457c0cf7be559e4ef488e9cf39d5ddb0ff7464b23372ab21fa";
+        assert_eq!(analysis.largest_files[0].hash, tool);
+        assert_eq!(analysis.largest_files[1].hash, analysis.largest_files[2].hash);
+
+        // Every byte value appears equally often in the tool, so only the config compresses
+        let config_potential = analysis.compression_potential * analysis.uncompressed_size as f64 / (2.0 * 1640.0);
+        assert!(analysis.compression_potential > 0.0 && config_potential < 1.0, "{}", config_potential);
+    }
+
+    #[tokio::test]
+    async fn analysis_stops_at_the_decompressed_size_limit() {
+        let mut service = service(None).await;
+        service.config.max_decompressed_layer_size_mb = 1;
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder.write_all(&vec![0u8; 4 * 1024 * 1024]).unwrap();
+        let bomb = encoder.finish().unwrap();
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(30);
+        let error = service.analyze_layer(bomb.into(), deadline).await.unwrap_err();
+        assert!(error.to_string().contains("decompressed size exceeds"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn scheduled_runs_fire_and_stop_on_shutdown() {
+        let mut config = crate::Config::default().optimization.unwrap();
+        config.background_optimization = true;
+        config.optimization_schedule_cron = Some("* * * * * *".to_string());
+        config.min_layer_size_mb = 0;
+        let shutdown = CancellationToken::new();
+        let service = OptimizationService::new(config, Arc::new(MemoryStorage::new(None)), shutdown.clone())
+            .await
+            .unwrap();
+
+        let layer = layer();
+        let digest = format!("sha256:{}", hex::encode(sha2::Sha256::digest(&layer)));
+        service.storage.put_blob(&digest, layer.clone().into()).await.unwrap();
+        service.register_pending_layer(&digest, layer.len() as u64).await.unwrap();
+        service.spawn_scheduler().unwrap();
+
+        let finished = async {
+            while service.progress().await.finished_at.is_none() {
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            }
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(5), finished).await.unwrap();
+        let progress = service.progress().await;
+        assert_eq!(progress.processed_layers, 1);
+        assert!(service.get_optimization_result(&digest).await.is_some());
+
+        // No run starts once the service is shut down
+        shutdown.cancel();
+        tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+        assert_eq!(service.progress().await.started_at, progress.started_at);
+    }
+
+    #[tokio::test]
+    async fn compression_ratio_reflects_the_stored_variant() {
+        let service = service(Some(19)).await;
+        let layer = layer();
+        let digest = format!("sha256:{}", hex::encode(sha2::Sha256::digest(&layer)));
+        let mut policy = service.policy();
+        policy.preferred_compression = CompressionType::Zstd;
+        policy.min_layer_size_bytes = 0;
+
+        let result = service.optimize_layer(&digest, layer.clone().into(), &policy).await.unwrap();
+        assert_eq!(result.compression, Some(CompressionType::Zstd));
+        let variant = service.storage.get_blob(result.optimized_digest.as_ref().unwrap()).await.unwrap().unwrap();
+        assert_eq!(result.optimized_size, variant.len() as u64);
+        assert_eq!(result.compression_ratio, variant.len() as f64 / layer.len() as f64);
+        assert_eq!(CompressionType::detect(&variant), CompressionType::Zstd);
+        assert_eq!(decode(&CompressionType::Zstd, &variant), decode(&CompressionType::Gzip, &layer));
+    }
+}
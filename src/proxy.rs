@@ -0,0 +1,239 @@
+use anyhow::{bail, Result};
+use bytes::Bytes;
+use futures::stream::{StreamExt, TryStreamExt};
+use reqwest::{header, Method, Response, StatusCode};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::config::ProxyConfig;
+use crate::remote::RemoteRegistry;
+use crate::storage::{is_digest_reference, StorageBackend};
+
+/// Manifest media types requested from upstream, so indexes are returned as they are
+const MANIFEST_ACCEPT: &str = "application/vnd.oci.image.index.v1+json, \
+    application/vnd.oci.image.manifest.v1+json, \
+    application/vnd.docker.distribution.manifest.list.v2+json, \
+    application/vnd.docker.distribution.manifest.v2+json";
+
+/// Media type recorded for upstream manifests served without a Content-Type
+const DEFAULT_MANIFEST_MEDIA_TYPE: &str = "application/vnd.docker.distribution.manifest.v2+json";
+
+/// Pull-through cache for repositories in proxied namespaces. Manifests and
+/// blobs that miss locally are fetched from the upstream registry and stored
+/// in the local backend; cached tags are re-validated once their TTL passes.
+pub struct ProxyService {
+    proxies: Vec<Proxy>,
+    storage: Arc<dyn StorageBackend>,
+    /// When each cached tag was last confirmed against upstream, by repository and tag
+    validated: Mutex<HashMap<(String, String), Instant>>,
+}
+
+struct Proxy {
+    config: ProxyConfig,
+    upstream: RemoteRegistry,
+}
+
+/// A manifest as served by the upstream registry
+struct UpstreamManifest {
+    data: Bytes,
+    media_type: String,
+}
+
+impl ProxyService {
+    pub fn new(proxies: Vec<ProxyConfig>, storage: Arc<dyn StorageBackend>) -> Self {
+        let proxies = proxies
+            .into_iter()
+            .map(|config| {
+                info!("Proxying {}/ to {}", config.namespace.trim_end_matches('/'), config.upstream);
+                let upstream = RemoteRegistry::new(&config.upstream, config.username.clone(), config.password.clone());
+                Proxy { config, upstream }
+            })
+            .collect();
+
+        Self {
+            proxies,
+            storage,
+            validated: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether the repository belongs to a proxied namespace
+    pub fn is_proxied(&self, repo: &str) -> bool {
+        self.route(repo).is_some()
+    }
+
+    /// Serve a manifest of a proxied repository given the locally cached copy, if
+    /// any. Cached tags are returned while fresh, otherwise checked against the
+    /// upstream digest and refetched when it moved. Repositories outside proxied
+    /// namespaces get the cached copy back unchanged.
+    pub async fn pull_manifest(&self, repo: &str, reference: &str, cached: Option<Bytes>) -> Result<Option<Bytes>> {
+        let Some((proxy, upstream_repo)) = self.route(repo) else {
+            return Ok(cached);
+        };
+
+        if let Some(data) = &cached {
+            // Content-addressed manifests never change
+            if is_digest_reference(reference) || self.is_fresh(proxy, repo, reference) {
+                debug!("Proxy cache hit for {}:{}", repo, reference);
+                return Ok(cached);
+            }
+
+            match self.head_manifest(proxy, upstream_repo, reference).await {
+                Ok(Some(digest)) if digest == content_digest(data) => {
+                    debug!("Cached {}:{} still matches upstream", repo, reference);
+                    self.mark_validated(repo, reference);
+                    return Ok(cached);
+                }
+                Ok(_) => debug!("{}:{} moved upstream, refetching", repo, reference),
+                Err(e) => {
+                    warn!("Failed to re-validate {}:{} upstream, serving cached copy: {}", repo, reference, e);
+                    return Ok(cached);
+                }
+            }
+        }
+
+        let upstream = match self.fetch_manifest(proxy, upstream_repo, reference).await {
+            Ok(Some(upstream)) => upstream,
+            Ok(None) => {
+                debug!("{}:{} not found at {}", upstream_repo, reference, proxy.upstream.base_url());
+                return Ok(cached);
+            }
+            Err(e) if cached.is_some() => {
+                warn!("Failed to fetch {}:{} upstream, serving cached copy: {}", repo, reference, e);
+                return Ok(cached);
+            }
+            Err(e) => return Err(e),
+        };
+
+        let digest = content_digest(&upstream.data);
+        if is_digest_reference(reference) && digest != reference {
+            bail!("Upstream returned manifest {} for {}@{}", digest, upstream_repo, reference);
+        }
+
+        self.storage
+            .put_manifest(repo, reference, upstream.data.clone(), &upstream.media_type)
+            .await?;
+        self.mark_validated(repo, reference);
+        info!("Cached {}:{} ({}) from {}", repo, reference, digest, proxy.upstream.base_url());
+
+        Ok(Some(upstream.data))
+    }
+
+    /// Fetch a blob of a proxied repository from upstream into local storage,
+    /// returning false when upstream doesn't have it or the repository isn't proxied
+    pub async fn pull_blob(&self, repo: &str, digest: &str) -> Result<bool> {
+        let Some((proxy, upstream_repo)) = self.route(repo) else {
+            return Ok(false);
+        };
+
+        let path = format!("{}/blobs/{}", upstream_repo, digest);
+        let response = self.send(proxy, upstream_repo, Method::GET, &path).await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => return Ok(false),
+            status if !status.is_success() => bail!("Upstream returned {} for blob {}", status, digest),
+            _ => {}
+        }
+
+        // Stream through an upload session, which verifies the digest before the blob becomes visible
+        let uuid = Uuid::new_v4().to_string();
+        self.storage.create_upload(&uuid).await?;
+        let stream = response.bytes_stream().map_err(anyhow::Error::from).boxed();
+        let stored = match self.storage.append_upload(&uuid, stream).await {
+            Ok(_) => self.storage.complete_upload(&uuid, digest).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = stored {
+            if let Err(cancel_error) = self.storage.cancel_upload(&uuid).await {
+                debug!("Failed to clean up proxy upload {}: {}", uuid, cancel_error);
+            }
+            return Err(e);
+        }
+
+        info!("Cached blob {} for {} from {}", digest, repo, proxy.upstream.base_url());
+        Ok(true)
+    }
+
+    /// The proxy serving a repository and the repository's name upstream
+    fn route<'a>(&'a self, repo: &'a str) -> Option<(&'a Proxy, &'a str)> {
+        self.proxies.iter().find_map(|proxy| {
+            repo.strip_prefix(proxy.config.namespace.trim_end_matches('/'))
+                .and_then(|rest| rest.strip_prefix('/'))
+                .filter(|upstream_repo| !upstream_repo.is_empty())
+                .map(|upstream_repo| (proxy, upstream_repo))
+        })
+    }
+
+    fn is_fresh(&self, proxy: &Proxy, repo: &str, reference: &str) -> bool {
+        let validated = self.validated.lock().unwrap();
+        validated
+            .get(&(repo.to_string(), reference.to_string()))
+            .is_some_and(|at| at.elapsed() < Duration::from_secs(proxy.config.manifest_ttl_secs))
+    }
+
+    fn mark_validated(&self, repo: &str, reference: &str) {
+        self.validated
+            .lock()
+            .unwrap()
+            .insert((repo.to_string(), reference.to_string()), Instant::now());
+    }
+
+    /// The digest upstream currently serves for a reference, if it reports one
+    async fn head_manifest(&self, proxy: &Proxy, upstream_repo: &str, reference: &str) -> Result<Option<String>> {
+        let path = format!("{}/manifests/{}", upstream_repo, reference);
+        let response = self.send(proxy, upstream_repo, Method::HEAD, &path).await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if !status.is_success() => bail!("Upstream returned {} for {}:{}", status, upstream_repo, reference),
+            _ => Ok(response
+                .headers()
+                .get("Docker-Content-Digest")
+                .and_then(|value| value.to_str().ok())
+                .map(|digest| digest.to_string())),
+        }
+    }
+
+    async fn fetch_manifest(
+        &self,
+        proxy: &Proxy,
+        upstream_repo: &str,
+        reference: &str,
+    ) -> Result<Option<UpstreamManifest>> {
+        let path = format!("{}/manifests/{}", upstream_repo, reference);
+        let response = self.send(proxy, upstream_repo, Method::GET, &path).await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => return Ok(None),
+            status if !status.is_success() => bail!("Upstream returned {} for {}:{}", status, upstream_repo, reference),
+            _ => {}
+        }
+
+        let media_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+            .unwrap_or_else(|| DEFAULT_MANIFEST_MEDIA_TYPE.to_string());
+        let data = response.bytes().await?;
+
+        Ok(Some(UpstreamManifest { data, media_type }))
+    }
+
+    /// Send a pull request for a path under the upstream's `/v2/` API
+    async fn send(&self, proxy: &Proxy, upstream_repo: &str, method: Method, path: &str) -> Result<Response> {
+        let scope = format!("repository:{}:pull", upstream_repo);
+        let url = proxy.upstream.url(path);
+        proxy
+            .upstream
+            .send(method, &url, &scope, |request| request.header(header::ACCEPT, MANIFEST_ACCEPT))
+            .await
+    }
+}
+
+fn content_digest(data: &[u8]) -> String {
+    format!("sha256:{:x}", Sha256::digest(data))
+}
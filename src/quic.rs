@@ -1,26 +1,67 @@
 use anyhow::Result;
 use async_trait::async_trait;
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, request, Request, Response},
+    Router,
+};
+use base64::{engine::general_purpose, Engine as _};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::net::SocketAddr;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+use tower::{Layer, ServiceExt};
 use tracing::{debug, error, info, warn};
 
+use crate::api::registry::{encode_repository_path, is_supported_digest, is_valid_repository_name, is_valid_tag};
 use crate::config::QuicConfig;
 
+/// Largest serialized message read from a stream
+#[cfg(feature = "quinn-quic")]
+const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+/// Largest `BlobChunk` payload; leaves room for the rest of the message within `MAX_MESSAGE_SIZE`
+pub const MAX_BLOB_CHUNK_SIZE: usize = 512 * 1024;
+
 /// QUIC transport implementation for drift registry
 /// Supports multiple QUIC libraries: quinn, quiche, or custom gquic
 #[derive(Clone)]
 pub struct QuicTransport {
     config: QuicConfig,
+    /// The registry API that peers' messages are answered through, so they get the
+    /// same authentication, authorization and validation as HTTP requests
+    registry: Arc<OnceLock<Router>>,
     #[cfg(feature = "quinn-quic")]
     quinn_endpoint: Option<Arc<quinn::Endpoint>>,
     #[cfg(feature = "quiche-quic")]
     quiche_config: Option<Arc<quiche::Config>>,
     #[cfg(feature = "gquic")]
     gquic_connection: Option<Arc<String>>, // Placeholder until gquic crate is available
+    /// Outgoing connections, reused across messages to the same peer. Never locked across an await.
     active_connections: Arc<RwLock<HashMap<SocketAddr, QuicConnection>>>,
+    /// Chunked blob transfers in progress. Never locked across an await; each
+    /// upload has its own lock, held while one of its chunks is stored.
+    chunked_uploads: Arc<Mutex<ChunkedUploads>>,
+}
+
+/// Chunked uploads by the peer sending them and the blob's digest
+type ChunkedUploads = HashMap<(SocketAddr, String), Arc<tokio::sync::Mutex<ChunkedUpload>>>;
+
+/// A chunked blob transfer's registry upload session
+struct ChunkedUpload {
+    /// Whose credentials the session is cancelled with
+    owner: QuicPeer,
+    location: String,
+    last_activity: Instant,
+}
+
+/// Where a message came from, and the credentials its connection authenticated with
+#[derive(Clone)]
+struct QuicPeer {
+    address: SocketAddr,
+    authorization: Option<String>,
 }
 
 /// Abstraction over different QUIC connection types
@@ -50,10 +91,26 @@ impl std::fmt::Debug for QuicConnection {
     }
 }
 
+impl QuicConnection {
+    /// Whether the connection can still carry messages
+    fn is_live(&self) -> bool {
+        match self {
+            #[cfg(feature = "quinn-quic")]
+            Self::Quinn(connection) => connection.close_reason().is_none(),
+            #[cfg(feature = "quiche-quic")]
+            Self::Quiche(connection) => !connection.is_closed(),
+            #[cfg(feature = "gquic")]
+            Self::GQuic(_) => true,
+            #[cfg(not(any(feature = "quinn-quic", feature = "quiche-quic", feature = "gquic")))]
+            Self::Mock(connection) => connection.connected,
+        }
+    }
+}
+
 /// Mock connection for testing and fallback
+#[cfg(not(any(feature = "quinn-quic", feature = "quiche-quic", feature = "gquic")))]
 #[derive(Debug, Clone)]
 pub struct MockConnection {
-    addr: SocketAddr,
     connected: bool,
 }
 
@@ -62,21 +119,38 @@ pub struct MockConnection {
 pub enum QuicMessage {
     /// Blob upload with content
     BlobUpload {
+        repository: String,
         digest: String,
         content: Vec<u8>,
         metadata: BlobMetadata,
     },
-    /// Manifest upload
+    /// Manifest upload; `reference` is `repository:tag` or `repository@digest`
     ManifestUpload {
         reference: String,
         content: Vec<u8>,
         content_type: String,
     },
+    /// Part of a blob sent in order, starting at offset 0, for blobs too large
+    /// for one `BlobUpload`. The server appends chunks to a registry upload
+    /// session and checks the digest once the `last` chunk arrives.
+    BlobChunk {
+        repository: String,
+        digest: String,
+        offset: u64,
+        data: Vec<u8>,
+        last: bool,
+    },
+    /// A chunk was stored; the next one starts at `offset`
+    BlobChunkAccepted {
+        digest: String,
+        offset: u64,
+    },
     /// Request blob by digest
     BlobRequest {
+        repository: String,
         digest: String,
     },
-    /// Request manifest by reference
+    /// Request manifest by `repository:tag` or `repository@digest`
     ManifestRequest {
         reference: String,
     },
@@ -92,6 +166,12 @@ pub enum QuicMessage {
         content: Option<Vec<u8>>,
         content_type: Option<String>,
     },
+    /// Credentials for the rest of the connection, as an HTTP `Authorization` value
+    Authenticate {
+        authorization: String,
+    },
+    /// The credentials were accepted
+    Authenticated,
     /// Health check ping
     Ping,
     /// Health check pong
@@ -124,8 +204,11 @@ impl QuicTransport {
     pub async fn new(config: QuicConfig) -> Result<Self> {
         info!("Initializing QUIC transport with backend: {:?}", config.backend);
 
+        // Only mutated when a backend feature is enabled
+        #[allow(unused_mut)]
         let mut transport = Self {
             config,
+            registry: Arc::new(OnceLock::new()),
             #[cfg(feature = "quinn-quic")]
             quinn_endpoint: None,
             #[cfg(feature = "quiche-quic")]
@@ -133,6 +216,7 @@ impl QuicTransport {
             #[cfg(feature = "gquic")]
             gquic_connection: None,
             active_connections: Arc::new(RwLock::new(HashMap::new())),
+            chunked_uploads: Arc::new(Mutex::new(HashMap::new())),
         };
 
         // Initialize based on configured backend
@@ -153,23 +237,111 @@ impl QuicTransport {
 
     #[cfg(feature = "quinn-quic")]
     async fn init_quinn(&mut self) -> Result<()> {
-        use quinn::{Endpoint, ServerConfig};
-        use std::sync::Arc;
+        use quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
 
         info!("Initializing Quinn QUIC backend");
 
-        // Create Quinn endpoint configuration
-        #[cfg(feature = "quinn-quic")]
-        {
-            warn!("Quinn QUIC initialization disabled in placeholder implementation");
-            // In a real implementation, you would configure TLS certificates and create the endpoint
-            // self.quinn_endpoint = Some(Arc::new(endpoint));
+        let (cert_chain, private_key) = self.quinn_identity()?;
+        let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+        let alpn: Vec<Vec<u8>> = self.config.application_protocols.iter().map(|p| p.as_bytes().to_vec()).collect();
+
+        let mut transport = quinn::TransportConfig::default();
+        transport.max_idle_timeout(Some(Duration::from_millis(self.config.max_idle_timeout_ms).try_into()?));
+        if self.config.keep_alive_interval_ms > 0 {
+            transport.keep_alive_interval(Some(Duration::from_millis(self.config.keep_alive_interval_ms)));
         }
+        let transport = Arc::new(transport);
+
+        let mut server_tls = rustls::ServerConfig::builder_with_provider(provider.clone())
+            .with_protocol_versions(&[&rustls::version::TLS13])?
+            .with_no_client_auth()
+            .with_single_cert(cert_chain.clone(), private_key)?;
+        server_tls.alpn_protocols = alpn.clone();
+        if self.config.enable_0rtt {
+            server_tls.max_early_data_size = u32::MAX;
+        }
+        let mut server_config = quinn::ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(server_tls)?));
+        server_config.transport_config(transport.clone());
 
-        info!("Quinn QUIC backend initialized successfully");
+        // Peers present the same certificate, so it is the only trusted root
+        let mut roots = rustls::RootCertStore::empty();
+        for certificate in cert_chain {
+            roots.add(certificate)?;
+        }
+        let mut client_tls = rustls::ClientConfig::builder_with_provider(provider)
+            .with_protocol_versions(&[&rustls::version::TLS13])?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        client_tls.alpn_protocols = alpn;
+        client_tls.enable_early_data = self.config.enable_early_data;
+        let mut client_config = quinn::ClientConfig::new(Arc::new(QuicClientConfig::try_from(client_tls)?));
+        client_config.transport_config(transport);
+
+        let mut endpoint = quinn::Endpoint::server(server_config, self.config.bind_addr)?;
+        endpoint.set_default_client_config(client_config);
+        self.quinn_endpoint = Some(Arc::new(endpoint));
+
+        info!("Quinn QUIC backend initialized on {}", self.config.bind_addr);
         Ok(())
     }
 
+    /// Answer peers' messages through the registry API `router`. Until this is
+    /// called every message but `Ping` is refused.
+    pub fn serve_registry(&self, router: Router) {
+        if self.registry.set(router).is_err() {
+            warn!("QUIC transport is already serving a registry");
+        }
+    }
+
+    /// Our credentials as an HTTP `Authorization` value, if any are configured
+    fn authorization(&self) -> Option<String> {
+        let username = self.config.username.as_ref()?;
+        let password = self.config.password.as_deref().unwrap_or_default();
+        Some(format!("Basic {}", general_purpose::STANDARD.encode(format!("{}:{}", username, password))))
+    }
+
+    /// The address the endpoint is bound to, resolving a port 0 in `bind_addr`
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match self.config.backend.as_str() {
+            #[cfg(feature = "quinn-quic")]
+            "quinn" => self.quinn_endpoint.as_ref()?.local_addr().ok(),
+            _ => None,
+        }
+    }
+
+    /// The DER certificate and key from the config, or else the PEM files at `cert_path`/`key_path`
+    #[cfg(feature = "quinn-quic")]
+    fn quinn_identity(
+        &self,
+    ) -> Result<(Vec<rustls::pki_types::CertificateDer<'static>>, rustls::pki_types::PrivateKeyDer<'static>)> {
+        use anyhow::Context;
+        use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+
+        if !self.config.cert_chain.is_empty() && !self.config.private_key.is_empty() {
+            let key = PrivateKeyDer::try_from(self.config.private_key.clone())
+                .map_err(|e| anyhow::anyhow!("Invalid QUIC private key: {}", e))?;
+            return Ok((vec![CertificateDer::from(self.config.cert_chain.clone())], key));
+        }
+
+        if self.config.cert_path.is_empty() || self.config.key_path.is_empty() {
+            anyhow::bail!(
+                "The quinn QUIC backend needs a certificate: set quic.cert_chain and quic.private_key, \
+                 or quic.cert_path and quic.key_path"
+            );
+        }
+
+        let certificates = CertificateDer::pem_file_iter(&self.config.cert_path)
+            .and_then(|certificates| certificates.collect::<Result<Vec<_>, _>>())
+            .with_context(|| format!("Failed to read QUIC certificate {}", self.config.cert_path))?;
+        if certificates.is_empty() {
+            anyhow::bail!("No certificates found in {}", self.config.cert_path);
+        }
+        let key = PrivateKeyDer::from_pem_file(&self.config.key_path)
+            .with_context(|| format!("Failed to read QUIC private key {}", self.config.key_path))?;
+
+        Ok((certificates, key))
+    }
+
     #[cfg(feature = "quiche-quic")]
     async fn init_quiche(&mut self) -> Result<()> {
         info!("Initializing Quiche QUIC backend");
@@ -229,25 +401,77 @@ impl QuicTransport {
 
     #[cfg(feature = "quinn-quic")]
     async fn send_quinn_message(&self, addr: SocketAddr, message: QuicMessage) -> Result<QuicMessage> {
+        let connection = self.quinn_connection(addr).await?;
+
+        // Each message gets its own stream on the shared connection
+        match quinn_exchange(&connection, &message).await {
+            Ok(response) => Ok(response),
+            Err(e) => {
+                warn!("QUIC exchange with {} failed, dropping the connection: {}", addr, e);
+                self.evict_quinn_connection(addr, &connection);
+                Err(e)
+            }
+        }
+    }
+
+    /// The pooled connection to `addr`, connecting if there is no live one
+    #[cfg(feature = "quinn-quic")]
+    async fn quinn_connection(&self, addr: SocketAddr) -> Result<quinn::Connection> {
+        if let Some(connection) = self.live_quinn_connection(addr) {
+            return Ok(connection);
+        }
+
         let endpoint = self.quinn_endpoint.as_ref()
             .ok_or_else(|| anyhow::anyhow!("Quinn endpoint not initialized"))?;
 
-        // Connect to remote
+        debug!("Opening QUIC connection to {}", addr);
         let connection = endpoint.connect(addr, "drift-registry")?.await?;
 
-        // Open bidirectional stream
-        let (mut send_stream, mut recv_stream) = connection.open_bi().await?;
+        // Peers check our messages like HTTP requests, so present credentials first
+        if let Some(authorization) = self.authorization() {
+            match quinn_exchange(&connection, &QuicMessage::Authenticate { authorization }).await? {
+                QuicMessage::Authenticated => {}
+                other => {
+                    connection.close(0u32.into(), b"unauthenticated");
+                    anyhow::bail!("{} refused our QUIC credentials: {:?}", addr, other);
+                }
+            }
+        }
 
-        // Serialize and send message
-        let message_bytes = bincode::serialize(&message)?;
-        send_stream.write_all(&message_bytes).await?;
-        send_stream.finish().await?;
+        let mut connections = self.active_connections.write().unwrap();
+        // Connections closed by the idle timeout are only noticed here
+        connections.retain(|_, connection| connection.is_live());
 
-        // Read response
-        let response_bytes = recv_stream.read_to_end(1024 * 1024).await?; // 1MB limit
-        let response: QuicMessage = bincode::deserialize(&response_bytes)?;
+        // Another message may have connected while we were handshaking; keep the first
+        #[allow(irrefutable_let_patterns)]
+        if let Some(QuicConnection::Quinn(existing)) = connections.get(&addr) {
+            connection.close(0u32.into(), b"duplicate");
+            return Ok(existing.clone());
+        }
+        connections.insert(addr, QuicConnection::Quinn(connection.clone()));
+        Ok(connection)
+    }
 
-        Ok(response)
+    #[cfg(feature = "quinn-quic")]
+    fn live_quinn_connection(&self, addr: SocketAddr) -> Option<quinn::Connection> {
+        let connections = self.active_connections.read().unwrap();
+        match connections.get(&addr) {
+            Some(QuicConnection::Quinn(connection)) if connection.close_reason().is_none() => Some(connection.clone()),
+            _ => None,
+        }
+    }
+
+    /// Drop `connection` from the pool, unless it has already been replaced
+    #[cfg(feature = "quinn-quic")]
+    fn evict_quinn_connection(&self, addr: SocketAddr, connection: &quinn::Connection) {
+        let mut connections = self.active_connections.write().unwrap();
+        #[allow(irrefutable_let_patterns)]
+        if let Some(QuicConnection::Quinn(pooled)) = connections.get(&addr)
+            && pooled.stable_id() == connection.stable_id()
+        {
+            connections.remove(&addr);
+        }
+        connection.close(0u32.into(), b"error");
     }
 
     #[cfg(feature = "quiche-quic")]
@@ -292,33 +516,15 @@ impl QuicTransport {
         Ok(response)
     }
 
+    /// Without a QUIC backend, messages are answered from local storage
     async fn send_mock_message(&self, addr: SocketAddr, message: QuicMessage) -> Result<QuicMessage> {
         debug!("Mock QUIC sending message to {}", addr);
-
-        // Simulate processing based on message type
-        match message {
-            QuicMessage::Ping => Ok(QuicMessage::Pong),
-            QuicMessage::BlobRequest { digest } => {
-                // Mock response - would normally fetch from storage
-                Ok(QuicMessage::BlobResponse {
-                    digest,
-                    content: None,
-                    metadata: None,
-                })
-            }
-            QuicMessage::ManifestRequest { reference } => {
-                // Mock response - would normally fetch from storage
-                Ok(QuicMessage::ManifestResponse {
-                    reference,
-                    content: None,
-                    content_type: None,
-                })
-            }
-            _ => Ok(QuicMessage::Error {
-                code: 501,
-                message: "Operation not supported in mock mode".to_string(),
-            }),
-        }
+        #[cfg(not(any(feature = "quinn-quic", feature = "quiche-quic", feature = "gquic")))]
+        self.active_connections.write().unwrap()
+            .entry(addr)
+            .or_insert_with(|| QuicConnection::Mock(MockConnection { connected: true }));
+        let mut peer = QuicPeer { address: addr, authorization: self.authorization() };
+        Ok(self.process_message(message, &mut peer).await)
     }
 
     /// Start QUIC server to listen for incoming connections
@@ -345,12 +551,28 @@ impl QuicTransport {
             .ok_or_else(|| anyhow::anyhow!("Quinn endpoint not initialized"))?;
 
         // Handle incoming connections
-        while let Some(conn) = endpoint.accept().await {
-            let connection = conn.await?;
+        while let Some(incoming) = endpoint.accept().await {
+            if self.config.max_connections > 0 && endpoint.open_connections() >= self.config.max_connections {
+                warn!(
+                    "Refusing QUIC connection from {}: {} connections open",
+                    incoming.remote_address(),
+                    self.config.max_connections
+                );
+                incoming.refuse();
+                continue;
+            }
 
             // Spawn task to handle connection
+            let transport = self.clone();
             tokio::spawn(async move {
-                if let Err(e) = Self::handle_quinn_connection(connection).await {
+                let connection = match incoming.await {
+                    Ok(connection) => connection,
+                    Err(e) => {
+                        warn!("QUIC handshake failed: {}", e);
+                        return;
+                    }
+                };
+                if let Err(e) = transport.handle_quinn_connection(connection).await {
                     error!("Error handling Quinn connection: {}", e);
                 }
             });
@@ -360,25 +582,32 @@ impl QuicTransport {
     }
 
     #[cfg(feature = "quinn-quic")]
-    async fn handle_quinn_connection(connection: quinn::Connection) -> Result<()> {
+    async fn handle_quinn_connection(&self, connection: quinn::Connection) -> Result<()> {
         info!("Handling new Quinn QUIC connection from {}", connection.remote_address());
+        let mut peer = QuicPeer { address: connection.remote_address(), authorization: None };
 
         // Handle incoming streams
-        while let Ok((mut send_stream, mut recv_stream)) = connection.accept_bi().await {
-            // Read message
-            let message_bytes = recv_stream.read_to_end(1024 * 1024).await?;
-            let message: QuicMessage = bincode::deserialize(&message_bytes)?;
-
-            // Process message and create response
-            let response = Self::process_message(message).await;
-
-            // Send response
-            let response_bytes = bincode::serialize(&response)?;
-            send_stream.write_all(&response_bytes).await?;
-            send_stream.finish().await?;
+        let served = async {
+            while let Ok((mut send_stream, mut recv_stream)) = connection.accept_bi().await {
+                // Read message
+                let message_bytes = recv_stream.read_to_end(MAX_MESSAGE_SIZE).await?;
+                let message: QuicMessage = bincode::deserialize(&message_bytes)?;
+
+                // Process message and create response
+                let response = self.process_message(message, &mut peer).await;
+
+                // Send response
+                let response_bytes = bincode::serialize(&response)?;
+                send_stream.write_all(&response_bytes).await?;
+                send_stream.finish()?;
+            }
+            Ok(())
         }
+        .await;
 
-        Ok(())
+        // Its chunked uploads can't be finished on another connection
+        self.cancel_chunked_uploads(|address, _| *address == peer.address).await;
+        served
     }
 
     #[cfg(feature = "quiche-quic")]
@@ -403,46 +632,124 @@ impl QuicTransport {
         }
     }
 
-    /// Process incoming QUIC messages
-    async fn process_message(message: QuicMessage) -> QuicMessage {
-        debug!("Processing QUIC message: {:?}", message);
+    /// Answer a peer's message through the registry API, as the HTTP request it stands for
+    async fn process_message(&self, message: QuicMessage, peer: &mut QuicPeer) -> QuicMessage {
+        debug!("Processing QUIC message from {}: {:?}", peer.address, message);
 
         match message {
             QuicMessage::Ping => QuicMessage::Pong,
-            QuicMessage::BlobRequest { digest } => {
-                // TODO: Integrate with storage backend
-                QuicMessage::BlobResponse {
-                    digest,
-                    content: None,
-                    metadata: None,
+            QuicMessage::Authenticate { authorization } => {
+                let candidate = QuicPeer { address: peer.address, authorization: Some(authorization) };
+                match self.registry_request(&candidate, Request::get("/v2/"), Body::empty()).await {
+                    Ok(_) => {
+                        *peer = candidate;
+                        QuicMessage::Authenticated
+                    }
+                    Err(error) => error,
+                }
+            }
+            QuicMessage::BlobRequest { repository, digest } => {
+                if let Err(error) = check_blob_reference(&repository, &digest) {
+                    return error;
+                }
+                let request = Request::get(format!("/v2/{}/blobs/{}", repository, digest));
+                let response = match self.registry_request(peer, request, Body::empty()).await {
+                    Ok(response) => response,
+                    Err(error) => return error,
+                };
+                let content_type = header_value(&response, header::CONTENT_TYPE);
+                match axum::body::to_bytes(response.into_body(), usize::MAX).await {
+                    Ok(content) => QuicMessage::BlobResponse {
+                        digest,
+                        metadata: Some(BlobMetadata {
+                            size: content.len() as u64,
+                            content_type,
+                            last_modified: chrono::Utc::now(),
+                        }),
+                        content: Some(content.to_vec()),
+                    },
+                    Err(e) => {
+                        error!("Failed to read blob {} for QUIC request: {}", digest, e);
+                        QuicMessage::Error {
+                            code: 500,
+                            message: "Failed to read blob".to_string(),
+                        }
+                    }
                 }
             }
             QuicMessage::ManifestRequest { reference } => {
-                // TODO: Integrate with storage backend
-                QuicMessage::ManifestResponse {
-                    reference,
-                    content: None,
-                    content_type: None,
+                let Some((repository, manifest_reference)) = split_manifest_reference(&reference) else {
+                    return invalid_manifest_reference(&reference);
+                };
+                let request = Request::get(format!("/v2/{}/manifests/{}", repository, manifest_reference));
+                let response = match self.registry_request(peer, request, Body::empty()).await {
+                    Ok(response) => response,
+                    Err(error) => return error,
+                };
+                let content_type = header_value(&response, header::CONTENT_TYPE);
+                match axum::body::to_bytes(response.into_body(), usize::MAX).await {
+                    Ok(content) => QuicMessage::ManifestResponse {
+                        content: Some(content.to_vec()),
+                        content_type,
+                        reference,
+                    },
+                    Err(e) => {
+                        error!("Failed to read manifest {} for QUIC request: {}", reference, e);
+                        QuicMessage::Error {
+                            code: 500,
+                            message: "Failed to read manifest".to_string(),
+                        }
+                    }
                 }
             }
-            QuicMessage::BlobUpload { digest, content, metadata } => {
-                // TODO: Integrate with storage backend to save blob
+            QuicMessage::BlobUpload { repository, digest, content, metadata } => {
                 info!("Received blob upload: {} ({} bytes)", digest, content.len());
-                QuicMessage::BlobResponse {
-                    digest,
-                    content: None,
-                    metadata: Some(metadata),
+                if let Err(error) = check_blob_reference(&repository, &digest) {
+                    return error;
+                }
+
+                // The registry checks the content against the digest as the upload completes
+                let location = match self.start_upload(peer, &repository).await {
+                    Ok(location) => location,
+                    Err(error) => return error,
+                };
+                let request = Request::put(format!("{}?digest={}", location, digest))
+                    .header(header::CONTENT_TYPE, "application/octet-stream");
+                match self.registry_request(peer, request, Body::from(content)).await {
+                    Ok(_) => QuicMessage::BlobResponse {
+                        digest,
+                        content: None,
+                        metadata: Some(metadata),
+                    },
+                    Err(error) => {
+                        self.cancel_upload(peer, &location).await;
+                        error
+                    }
                 }
             }
             QuicMessage::ManifestUpload { reference, content, content_type } => {
-                // TODO: Integrate with storage backend to save manifest
                 info!("Received manifest upload: {} ({} bytes)", reference, content.len());
-                QuicMessage::ManifestResponse {
-                    reference,
-                    content: None,
-                    content_type: Some(content_type),
+
+                let Some((repository, manifest_reference)) = split_manifest_reference(&reference) else {
+                    return invalid_manifest_reference(&reference);
+                };
+                let request = Request::put(format!("/v2/{}/manifests/{}", repository, manifest_reference))
+                    .header(header::CONTENT_TYPE, &content_type);
+                match self.registry_request(peer, request, Body::from(content)).await {
+                    Ok(_) => QuicMessage::ManifestResponse {
+                        reference,
+                        content: None,
+                        content_type: Some(content_type),
+                    },
+                    Err(error) => error,
                 }
             }
+            QuicMessage::BlobChunk { repository, digest, offset, data, last } => {
+                if let Err(error) = check_blob_reference(&repository, &digest) {
+                    return error;
+                }
+                self.receive_blob_chunk(peer, &repository, digest, offset, data, last).await
+            }
             _ => QuicMessage::Error {
                 code: 400,
                 message: "Unsupported message type".to_string(),
@@ -450,6 +757,229 @@ impl QuicTransport {
         }
     }
 
+    /// Append a chunk to the peer's upload session for the blob, completing the upload on the last chunk
+    async fn receive_blob_chunk(
+        &self,
+        peer: &QuicPeer,
+        repository: &str,
+        digest: String,
+        offset: u64,
+        data: Vec<u8>,
+        last: bool,
+    ) -> QuicMessage {
+        if data.len() > MAX_BLOB_CHUNK_SIZE {
+            return QuicMessage::Error {
+                code: 413,
+                message: format!("Chunk exceeds the maximum size of {} bytes", MAX_BLOB_CHUNK_SIZE),
+            };
+        }
+
+        let key = (peer.address, digest.clone());
+        let entry = if offset == 0 {
+            // The first chunk starts a new session, abandoning any earlier attempt
+            let location = match self.start_upload(peer, repository).await {
+                Ok(location) => location,
+                Err(error) => return error,
+            };
+            let entry = Arc::new(tokio::sync::Mutex::new(ChunkedUpload {
+                owner: peer.clone(),
+                location,
+                last_activity: Instant::now(),
+            }));
+            let abandoned = self.chunked_uploads.lock().unwrap().insert(key.clone(), entry.clone());
+            if let Some(abandoned) = abandoned {
+                let abandoned = abandoned.lock().await;
+                self.cancel_upload(&abandoned.owner, &abandoned.location).await;
+            }
+            self.sweep_chunked_uploads().await;
+            entry
+        } else {
+            match self.chunked_uploads.lock().unwrap().get(&key).cloned() {
+                Some(entry) => entry,
+                None => return no_chunked_upload(&digest),
+            }
+        };
+
+        // Chunks of other blobs and peers go ahead while this one is stored
+        let mut upload = entry.lock().await;
+        let current = |uploads: &ChunkedUploads| uploads.get(&key).is_some_and(|other| Arc::ptr_eq(other, &entry));
+        if !current(&self.chunked_uploads.lock().unwrap()) {
+            // Restarted or swept while this chunk waited
+            return no_chunked_upload(&digest);
+        }
+        upload.last_activity = Instant::now();
+
+        // The registry refuses a chunk that doesn't start where the upload is
+        let length = data.len() as u64;
+        if length > 0 {
+            let request = Request::patch(&upload.location)
+                .header(header::CONTENT_TYPE, "application/octet-stream")
+                .header(header::CONTENT_RANGE, format!("{}-{}", offset, offset + length - 1));
+            if let Err(error) = self.registry_request(peer, request, Body::from(data)).await {
+                if matches!(error, QuicMessage::Error { code: 404, .. }) {
+                    self.chunked_uploads.lock().unwrap().remove(&key);
+                }
+                return error;
+            }
+        }
+        let offset = offset + length;
+
+        if !last {
+            return QuicMessage::BlobChunkAccepted { digest, offset };
+        }
+
+        self.chunked_uploads.lock().unwrap().remove(&key);
+        let request = Request::put(format!("{}?digest={}", upload.location, digest));
+        match self.registry_request(peer, request, Body::empty()).await {
+            Ok(_) => {
+                info!("Received chunked blob upload: {} ({} bytes)", digest, offset);
+                QuicMessage::BlobResponse {
+                    digest,
+                    content: None,
+                    metadata: Some(BlobMetadata {
+                        size: offset,
+                        content_type: Some("application/octet-stream".to_string()),
+                        last_modified: chrono::Utc::now(),
+                    }),
+                }
+            }
+            Err(error) => {
+                warn!("Rejected chunked QUIC upload {}: {:?}", digest, error);
+                self.cancel_upload(peer, &upload.location).await;
+                error
+            }
+        }
+    }
+
+    /// Cancel chunked uploads idle for longer than `chunked_upload_ttl_secs`, or all of
+    /// a peer's once its connection closes. Uploads busy with a chunk aren't idle.
+    async fn sweep_chunked_uploads(&self) {
+        let ttl = Duration::from_secs(self.config.chunked_upload_ttl_secs);
+        self.cancel_chunked_uploads(|_, upload| upload.last_activity.elapsed() > ttl).await;
+    }
+
+    async fn cancel_chunked_uploads(&self, expired: impl Fn(&SocketAddr, &ChunkedUpload) -> bool) {
+        let expired: Vec<_> = {
+            let mut uploads = self.chunked_uploads.lock().unwrap();
+            let keys: Vec<_> = uploads
+                .iter()
+                .filter(|((address, _), upload)| upload.try_lock().is_ok_and(|upload| expired(address, &upload)))
+                .map(|(key, _)| key.clone())
+                .collect();
+            keys.iter().filter_map(|key| uploads.remove(key)).collect()
+        };
+
+        for upload in expired {
+            let upload = upload.lock().await;
+            debug!("Cancelling abandoned QUIC upload {} from {}", upload.location, upload.owner.address);
+            self.cancel_upload(&upload.owner, &upload.location).await;
+        }
+    }
+
+    /// Open a registry upload session for the peer, returning its location
+    async fn start_upload(&self, peer: &QuicPeer, repository: &str) -> Result<String, QuicMessage> {
+        let request = Request::post(format!("/v2/{}/blobs/uploads/", repository));
+        let response = self.registry_request(peer, request, Body::empty()).await?;
+        header_value(&response, header::LOCATION).ok_or_else(|| QuicMessage::Error {
+            code: 500,
+            message: "Upload session has no location".to_string(),
+        })
+    }
+
+    /// Cancel an upload session; it expires anyway if this fails
+    async fn cancel_upload(&self, peer: &QuicPeer, location: &str) {
+        if let Err(error) = self.registry_request(peer, Request::delete(location), Body::empty()).await {
+            debug!("Failed to cancel QUIC upload {}: {:?}", location, error);
+        }
+    }
+
+    /// Send a peer's request through the registry API with the peer's credentials,
+    /// returning the response if it succeeded and the registry's error otherwise
+    async fn registry_request(
+        &self,
+        peer: &QuicPeer,
+        request: request::Builder,
+        body: Body,
+    ) -> Result<Response<Body>, QuicMessage> {
+        let Some(router) = self.registry.get() else {
+            return Err(QuicMessage::Error {
+                code: 503,
+                message: "Registry is not ready".to_string(),
+            });
+        };
+
+        let mut request = request.extension(ConnectInfo(peer.address));
+        if let Some(authorization) = &peer.authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+        let request = request.body(body).map_err(|e| QuicMessage::Error {
+            code: 400,
+            message: format!("Invalid request: {}", e),
+        })?;
+
+        let response = match axum::middleware::map_request(encode_repository_path)
+            .layer(router.clone())
+            .oneshot(request)
+            .await
+        {
+            Ok(response) => response,
+            Err(infallible) => match infallible {},
+        };
+        if response.status().is_success() {
+            return Ok(response);
+        }
+
+        // Keep the registry's status and the message of its first error
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap_or_default();
+        let message = serde_json::from_slice::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|body| body["errors"][0]["message"].as_str().map(str::to_string))
+            .unwrap_or_else(|| status.canonical_reason().unwrap_or("Request failed").to_string());
+        Err(QuicMessage::Error {
+            code: status.as_u16(),
+            message,
+        })
+    }
+
+    /// Send a blob to `repository` in `MAX_BLOB_CHUNK_SIZE` chunks, reading at most one chunk
+    /// into memory at a time
+    pub async fn send_blob<R>(&self, addr: SocketAddr, repository: &str, digest: &str, mut reader: R) -> Result<u64>
+    where
+        R: tokio::io::AsyncRead + Unpin,
+    {
+        let mut offset = 0u64;
+        let mut buffer = vec![0u8; MAX_BLOB_CHUNK_SIZE];
+        let mut filled = read_chunk(&mut reader, &mut buffer).await?;
+
+        loop {
+            // Read ahead one chunk to know whether this one is the last
+            let mut next = vec![0u8; MAX_BLOB_CHUNK_SIZE];
+            let next_filled = if filled < MAX_BLOB_CHUNK_SIZE { 0 } else { read_chunk(&mut reader, &mut next).await? };
+            let last = next_filled == 0;
+
+            buffer.truncate(filled);
+            let message = QuicMessage::BlobChunk {
+                repository: repository.to_string(),
+                digest: digest.to_string(),
+                offset,
+                data: buffer,
+                last,
+            };
+            match self.send_message(addr, message).await? {
+                QuicMessage::BlobChunkAccepted { offset: accepted, .. } if !last => offset = accepted,
+                QuicMessage::BlobResponse { .. } if last => return Ok(offset + filled as u64),
+                QuicMessage::Error { code, message } => {
+                    return Err(anyhow::anyhow!("Blob upload to {} failed ({}): {}", addr, code, message));
+                }
+                other => return Err(anyhow::anyhow!("Unexpected response to blob chunk: {:?}", other)),
+            }
+
+            buffer = next;
+            filled = next_filled;
+        }
+    }
+
     /// Test connection to a remote QUIC endpoint
     pub async fn ping(&self, addr: SocketAddr) -> Result<bool> {
         match self.send_message(addr, QuicMessage::Ping).await? {
@@ -460,10 +990,18 @@ impl QuicTransport {
 
     /// Get connection statistics
     pub async fn get_stats(&self) -> HashMap<String, u64> {
-        let connections = self.active_connections.read().await;
+        let live_connections = self.active_connections.read().unwrap()
+            .values()
+            .filter(|connection| connection.is_live())
+            .count();
         let mut stats = HashMap::new();
 
-        stats.insert("active_connections".to_string(), connections.len() as u64);
+        stats.insert("active_connections".to_string(), live_connections as u64);
+        // Connections peers have open to this endpoint, as opposed to the ones it opened
+        #[cfg(feature = "quinn-quic")]
+        if let Some(endpoint) = &self.quinn_endpoint {
+            stats.insert("open_connections".to_string(), endpoint.open_connections() as u64);
+        }
         stats.insert("backend".to_string(), match self.config.backend.as_str() {
             "quinn" => 1,
             "quiche" => 2,
@@ -475,6 +1013,76 @@ impl QuicTransport {
     }
 }
 
+/// Send one message on a new bidirectional stream of `connection` and read the response
+#[cfg(feature = "quinn-quic")]
+async fn quinn_exchange(connection: &quinn::Connection, message: &QuicMessage) -> Result<QuicMessage> {
+    let (mut send_stream, mut recv_stream) = connection.open_bi().await?;
+
+    let message_bytes = bincode::serialize(message)?;
+    send_stream.write_all(&message_bytes).await?;
+    send_stream.finish()?;
+
+    let response_bytes = recv_stream.read_to_end(MAX_MESSAGE_SIZE).await?;
+    Ok(bincode::deserialize(&response_bytes)?)
+}
+
+/// Fill `buffer` from `reader`, returning less than its length only at the end of the data
+async fn read_chunk<R: tokio::io::AsyncRead + Unpin>(reader: &mut R, buffer: &mut [u8]) -> Result<usize> {
+    use tokio::io::AsyncReadExt;
+
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]).await? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+/// Split a `repository:tag` or `repository@digest` manifest reference, refusing
+/// names the registry wouldn't accept in a URL
+fn split_manifest_reference(reference: &str) -> Option<(&str, &str)> {
+    let (repository, manifest_reference) = match reference.split_once('@') {
+        Some((repository, digest)) => (repository, is_supported_digest(digest).then_some(digest)?),
+        None => {
+            let (repository, tag) = reference.rsplit_once(':')?;
+            (repository, is_valid_tag(tag).then_some(tag)?)
+        }
+    };
+    is_valid_repository_name(repository).then_some((repository, manifest_reference))
+}
+
+/// Refuse blob messages whose repository or digest the registry wouldn't accept in a URL
+fn check_blob_reference(repository: &str, digest: &str) -> Result<(), QuicMessage> {
+    let message = if !is_valid_repository_name(repository) {
+        format!("Invalid repository name {}", repository)
+    } else if !is_supported_digest(digest) {
+        format!("Unsupported or malformed digest {}", digest)
+    } else {
+        return Ok(());
+    };
+    Err(QuicMessage::Error { code: 400, message })
+}
+
+fn header_value(response: &Response<Body>, name: header::HeaderName) -> Option<String> {
+    response.headers().get(name)?.to_str().ok().map(str::to_string)
+}
+
+fn no_chunked_upload(digest: &str) -> QuicMessage {
+    QuicMessage::Error {
+        code: 404,
+        message: format!("No upload of {} in progress; start again at offset 0", digest),
+    }
+}
+
+fn invalid_manifest_reference(reference: &str) -> QuicMessage {
+    QuicMessage::Error {
+        code: 400,
+        message: format!("Invalid manifest reference {}; expected repository:tag or repository@digest", reference),
+    }
+}
+
 #[async_trait]
 impl QuicTransportBackend for QuicTransport {
     async fn send_message(&self, addr: SocketAddr, message: QuicMessage) -> Result<QuicMessage> {
@@ -487,21 +1095,31 @@ impl QuicTransportBackend for QuicTransport {
 
     async fn connect(&self, addr: SocketAddr) -> Result<()> {
         debug!("Connecting to QUIC endpoint: {}", addr);
-        // Connection is established on-demand in send_message
-        Ok(())
+        match self.config.backend.as_str() {
+            #[cfg(feature = "quinn-quic")]
+            "quinn" => self.quinn_connection(addr).await.map(drop),
+            // Other backends connect on demand in send_message
+            _ => Ok(()),
+        }
     }
 
     async fn disconnect(&self, addr: SocketAddr) -> Result<()> {
         debug!("Disconnecting from QUIC endpoint: {}", addr);
-        let mut connections = self.active_connections.write().await;
-        connections.remove(&addr);
+        let connection = self.active_connections.write().unwrap().remove(&addr);
+        #[cfg(feature = "quinn-quic")]
+        #[allow(irrefutable_let_patterns)]
+        if let Some(QuicConnection::Quinn(connection)) = &connection {
+            connection.close(0u32.into(), b"disconnect");
+        }
+        #[cfg(not(feature = "quinn-quic"))]
+        let _ = connection;
         Ok(())
     }
 
     fn is_connected(&self, addr: SocketAddr) -> bool {
-        // In QUIC, connections are managed automatically
-        // This is more of a "have we seen this address" check
-        false // Simplified for now
+        self.active_connections.read().unwrap()
+            .get(&addr)
+            .is_some_and(|connection| connection.is_live())
     }
 }
 
@@ -0,0 +1,191 @@
+use ipnet::IpNet;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::config::RegistryConfig;
+
+/// Buckets untouched for this long are full again and can be dropped
+const IDLE_BUCKET_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Prune idle buckets once the map grows past this many entries
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Operation classes that are limited independently
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RateLimitClass {
+    Pull,
+    Push,
+    List,
+}
+
+impl RateLimitClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pull => "pull",
+            Self::Push => "push",
+            Self::List => "list",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// Bucket levels of one operation class, aggregated over clients for metrics
+#[derive(Debug, Clone)]
+pub struct ClassSummary {
+    pub class: RateLimitClass,
+    pub capacity: u32,
+    /// Clients with a bucket
+    pub buckets: usize,
+    /// Clients without a token left, i.e. currently throttled
+    pub exhausted: usize,
+}
+
+/// Token bucket rate limiter keyed by username or client IP
+pub struct RateLimiter {
+    limits: RwLock<Limits>,
+    buckets: Mutex<HashMap<(String, RateLimitClass), Bucket>>,
+    throttled: Mutex<HashMap<RateLimitClass, u64>>,
+}
+
+/// Configured capacities and exemptions, replaced on config reload
+struct Limits {
+    pull_per_hour: u32,
+    push_per_hour: u32,
+    list_per_hour: u32,
+    exempt_users: Vec<String>,
+    exempt_networks: Vec<IpNet>,
+}
+
+impl Limits {
+    fn new(config: &RegistryConfig) -> Self {
+        let limits = &config.rate_limits;
+        let exempt_networks = limits
+            .exempt_cidrs
+            .iter()
+            .filter_map(|cidr| match cidr.parse::<IpNet>() {
+                Ok(network) => Some(network),
+                Err(e) => {
+                    warn!("Ignoring invalid rate limit exemption {}: {}", cidr, e);
+                    None
+                }
+            })
+            .collect();
+
+        Self {
+            pull_per_hour: limits.pull_per_hour.unwrap_or(config.rate_limit_per_hour),
+            push_per_hour: limits.push_per_hour.unwrap_or(config.rate_limit_per_hour),
+            list_per_hour: limits.list_per_hour.unwrap_or(config.rate_limit_per_hour),
+            exempt_users: limits.exempt_users.clone(),
+            exempt_networks,
+        }
+    }
+}
+
+impl RateLimiter {
+    pub fn new(config: &RegistryConfig) -> Self {
+        Self {
+            limits: RwLock::new(Limits::new(config)),
+            buckets: Mutex::new(HashMap::new()),
+            throttled: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Apply new limits; existing buckets keep their tokens, capped at the new capacity
+    pub fn reconfigure(&self, config: &RegistryConfig) {
+        *self.limits.write().unwrap() = Limits::new(config);
+    }
+
+    fn capacity(&self, class: RateLimitClass) -> u32 {
+        let limits = self.limits.read().unwrap();
+        match class {
+            RateLimitClass::Pull => limits.pull_per_hour,
+            RateLimitClass::Push => limits.push_per_hour,
+            RateLimitClass::List => limits.list_per_hour,
+        }
+    }
+
+    pub fn is_exempt(&self, username: Option<&str>, ip: Option<IpAddr>) -> bool {
+        let limits = self.limits.read().unwrap();
+        username.is_some_and(|username| limits.exempt_users.iter().any(|exempt| exempt == username))
+            || ip.is_some_and(|ip| limits.exempt_networks.iter().any(|network| network.contains(&ip)))
+    }
+
+    /// Take a token for `key`, or return how long until one is available
+    pub fn check(&self, key: &str, class: RateLimitClass) -> Result<(), Duration> {
+        let capacity = self.capacity(class);
+        if capacity == 0 {
+            return Ok(());
+        }
+
+        let capacity = capacity as f64;
+        let refill_per_second = capacity / 3600.0;
+        let now = Instant::now();
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > PRUNE_THRESHOLD {
+            buckets.retain(|_, bucket| now.duration_since(bucket.updated_at) < IDLE_BUCKET_TTL);
+        }
+
+        let bucket = buckets
+            .entry((key.to_string(), class))
+            .or_insert(Bucket { tokens: capacity, updated_at: now });
+
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * refill_per_second).min(capacity);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        let retry_after = ((1.0 - bucket.tokens) / refill_per_second).ceil();
+        drop(buckets);
+
+        *self.throttled.lock().unwrap().entry(class).or_insert(0) += 1;
+        debug!("Rate limited {} for {} operations", key, class.as_str());
+        Err(Duration::from_secs(retry_after as u64))
+    }
+
+    /// Bucket counts per operation class. Clients aren't broken out, so
+    /// metrics don't grow a series per user or address.
+    pub fn class_summaries(&self) -> Vec<ClassSummary> {
+        let now = Instant::now();
+        let buckets = self.buckets.lock().unwrap();
+
+        [RateLimitClass::Pull, RateLimitClass::Push, RateLimitClass::List]
+            .into_iter()
+            .map(|class| {
+                let capacity = self.capacity(class);
+                let refill_per_second = capacity as f64 / 3600.0;
+                let mut summary = ClassSummary { class, capacity, buckets: 0, exhausted: 0 };
+                for (_, bucket) in buckets.iter().filter(|((_, bucket_class), _)| *bucket_class == class) {
+                    let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+                    summary.buckets += 1;
+                    if bucket.tokens + elapsed * refill_per_second < 1.0 {
+                        summary.exhausted += 1;
+                    }
+                }
+                summary
+            })
+            .collect()
+    }
+
+    /// Requests rejected so far, per operation class
+    pub fn throttled_counts(&self) -> Vec<(RateLimitClass, u64)> {
+        self.throttled
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(class, count)| (*class, *count))
+            .collect()
+    }
+}
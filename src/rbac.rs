@@ -1,22 +1,60 @@
 use anyhow::Result;
-use async_trait::async_trait;
-use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use ipnet::IpNet;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::net::IpAddr;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tracing::{debug, error, info, warn};
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, info, warn};
 
 use crate::config::RbacConfig;
-
-/// Organization-level Role-Based Access Control (RBAC) system
+use crate::storage::StorageBackend;
+
+/// IDs of the persisted documents of each kind
+const RBAC_INDEX_KEY: &str = "rbac/index.json";
+
+const ORGANIZATIONS: &str = "organizations";
+const USERS: &str = "users";
+const ROLES: &str = "roles";
+
+/// Organization-level Role-Based Access Control (RBAC) system.
+///
+/// Organizations (with their teams), users and roles are written through to
+/// the storage backend as JSON under `rbac/<kind>/<id>` on every change, and
+/// loaded back when the service starts. Each change holds the locks of the
+/// entities it changes until their writes have finished, so concurrent changes
+/// to one entity can't overwrite each other's documents, while changes to
+/// other entities and authorization checks go on. Memory is only updated once
+/// the write succeeded.
 #[derive(Clone)]
 pub struct RbacService {
     config: RbacConfig,
+    storage: Arc<dyn StorageBackend>,
+    /// Document IDs by kind, as kept under `rbac/index.json`
+    index: Arc<Mutex<BTreeMap<String, BTreeSet<String>>>>,
+    /// Held while an entity is read, changed and written back
+    entity_locks: Arc<EntityLocks>,
     organizations: Arc<RwLock<HashMap<String, Organization>>>,
     users: Arc<RwLock<HashMap<String, User>>>,
     roles: Arc<RwLock<HashMap<String, Role>>>,
     permissions: Arc<RwLock<HashMap<String, Permission>>>,
-    audit_log: Arc<RwLock<Vec<AuditEntry>>>,
+    /// The most recent `audit_log_capacity` entries, oldest first
+    audit_log: Arc<RwLock<VecDeque<AuditEntry>>>,
+    organization_usage: Arc<RwLock<HashMap<String, OrganizationUsage>>>,
+}
+
+/// Per-entity locks, by document key. Changes touching several entities lock
+/// organizations before users, and users before roles.
+#[derive(Default)]
+struct EntityLocks {
+    locks: std::sync::Mutex<HashMap<String, Arc<Mutex<()>>>>,
+}
+
+impl EntityLocks {
+    async fn lock(&self, kind: &str, id: &str) -> tokio::sync::OwnedMutexGuard<()> {
+        let lock = self.locks.lock().unwrap().entry(document_key(kind, id)).or_default().clone();
+        lock.lock_owned().await
+    }
 }
 
 /// Organization entity
@@ -175,6 +213,18 @@ pub struct OrganizationSettings {
     pub webhook_url: Option<String>,
 }
 
+/// Storage used by an organization's repositories
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrganizationUsage {
+    pub organization_id: String,
+    pub used_bytes: u64,
+    pub quota_bytes: Option<u64>,
+    /// Blobs already counted, so layers shared between repositories count once
+    #[serde(skip)]
+    pub blobs: HashSet<String>,
+    pub measured_at: chrono::DateTime<chrono::Utc>,
+}
+
 /// Audit log entry
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuditEntry {
@@ -218,16 +268,33 @@ pub struct AuthzResponse {
 }
 
 impl RbacService {
-    pub async fn new(config: RbacConfig) -> Result<Self> {
+    pub async fn new(config: RbacConfig, storage: Arc<dyn StorageBackend>) -> Result<Self> {
         info!("Initializing RBAC service");
 
+        let index: BTreeMap<String, BTreeSet<String>> = match storage.get_blob(RBAC_INDEX_KEY).await? {
+            Some(data) => serde_json::from_slice(&data)?,
+            None => BTreeMap::new(),
+        };
+        let organizations: HashMap<String, Organization> = load_documents(&storage, &index, ORGANIZATIONS).await?;
+        let users: HashMap<String, User> = load_documents(&storage, &index, USERS).await?;
+        let roles: HashMap<String, Role> = load_documents(&storage, &index, ROLES).await?;
+        info!(
+            "Loaded {} organizations, {} users and {} roles",
+            organizations.len(), users.len(), roles.len()
+        );
+
+        let audit_log = VecDeque::with_capacity(config.audit_log_capacity.min(1024));
         let service = Self {
             config,
-            organizations: Arc::new(RwLock::new(HashMap::new())),
-            users: Arc::new(RwLock::new(HashMap::new())),
-            roles: Arc::new(RwLock::new(HashMap::new())),
+            storage,
+            index: Arc::new(Mutex::new(index)),
+            entity_locks: Arc::new(EntityLocks::default()),
+            organizations: Arc::new(RwLock::new(organizations)),
+            users: Arc::new(RwLock::new(users)),
+            roles: Arc::new(RwLock::new(roles)),
             permissions: Arc::new(RwLock::new(HashMap::new())),
-            audit_log: Arc::new(RwLock::new(Vec::new())),
+            audit_log: Arc::new(RwLock::new(audit_log)),
+            organization_usage: Arc::new(RwLock::new(HashMap::new())),
         };
 
         // Initialize default roles and permissions
@@ -237,7 +304,25 @@ impl RbacService {
         Ok(service)
     }
 
-    /// Initialize default system roles and permissions
+    /// Write one document through to storage, adding it to the index if it is new
+    async fn persist<T: Serialize>(&self, kind: &str, id: &str, value: &T) -> Result<()> {
+        self.storage
+            .put_blob(&document_key(kind, id), serde_json::to_vec(value)?.into())
+            .await?;
+
+        let mut index = self.index.lock().await;
+        if index.get(kind).is_some_and(|ids| ids.contains(id)) {
+            return Ok(());
+        }
+        let mut updated = index.clone();
+        updated.entry(kind.to_string()).or_default().insert(id.to_string());
+        self.storage.put_blob(RBAC_INDEX_KEY, serde_json::to_vec(&updated)?.into()).await?;
+        *index = updated;
+        Ok(())
+    }
+
+    /// Initialize default permissions, and the default roles unless roles were
+    /// already persisted; customized or deleted defaults are left as they are
     async fn initialize_defaults(&self) -> Result<()> {
         let mut roles = self.roles.write().await;
         let mut permissions = self.permissions.write().await;
@@ -251,6 +336,20 @@ impl RbacService {
                 action: Action::Read,
                 conditions: vec![],
             },
+            Permission {
+                id: "registry.admin".to_string(),
+                name: "Administer Registry".to_string(),
+                resource: ResourceType::Registry,
+                action: Action::Admin,
+                conditions: vec![],
+            },
+            Permission {
+                id: "registry.optimize".to_string(),
+                name: "Run Image Optimization".to_string(),
+                resource: ResourceType::Registry,
+                action: Action::Optimize,
+                conditions: vec![],
+            },
             Permission {
                 id: "repository.pull".to_string(),
                 name: "Pull Images".to_string(),
@@ -326,12 +425,15 @@ impl RbacService {
             },
         ];
 
-        for role in default_roles {
-            roles.insert(role.id.clone(), role);
+        if roles.is_empty() {
+            for role in default_roles {
+                self.persist(ROLES, &role.id, &role).await?;
+                roles.insert(role.id.clone(), role);
+            }
+            info!("Initialized {} default roles", roles.len());
         }
 
-        info!("Initialized {} default roles and {} permissions",
-            roles.len(), permissions.len());
+        info!("Initialized {} permissions", permissions.len());
 
         Ok(())
     }
@@ -340,6 +442,9 @@ impl RbacService {
     pub async fn authorize(&self, request: AuthzRequest) -> Result<AuthzResponse> {
         debug!("Authorizing request: {:?}", request);
 
+        // Take organizations before users, the same order as the membership updates
+        let organizations = self.organizations.read().await;
+
         // Get user
         let users = self.users.read().await;
         let user = users.get(&request.user_id)
@@ -356,14 +461,33 @@ impl RbacService {
             }
         }
 
-        // Add team roles
-        for team_id in &user.teams {
-            // In real implementation, would look up team and its roles
-            debug!("Checking team roles for team: {}", team_id);
+        // Add team roles, looking each team up in the user's organizations
+        if self.config.enable_team_based_access {
+            for team_id in &user.teams {
+                let team = user
+                    .organizations
+                    .iter()
+                    .filter_map(|org_id| organizations.get(org_id))
+                    .find_map(|org| org.teams.get(team_id));
+                let Some(team) = team else {
+                    debug!("Team {} not found in organizations of {}", team_id, user.id);
+                    continue;
+                };
+
+                for role_id in &team.roles {
+                    if applicable_roles.iter().any(|role| &role.id == role_id) {
+                        continue;
+                    }
+                    if let Some(role) = roles.get(role_id) {
+                        applicable_roles.push(role.clone());
+                    }
+                }
+            }
         }
+        drop(organizations);
 
         // Sort roles by priority
-        applicable_roles.sort_by(|a, b| b.priority.cmp(&a.priority));
+        applicable_roles.sort_by_key(|role| std::cmp::Reverse(role.priority));
 
         // Check permissions
         let permissions = self.permissions.read().await;
@@ -372,11 +496,11 @@ impl RbacService {
 
         for role in &applicable_roles {
             for perm_id in &role.permissions {
-                if let Some(permission) = permissions.get(perm_id) {
-                    if self.check_permission(&permission, &request).await {
-                        applied_permissions.push(perm_id.clone());
-                        allowed = true;
-                    }
+                if let Some(permission) = permissions.get(perm_id)
+                    && self.check_permission(permission, &request).await
+                {
+                    applied_permissions.push(perm_id.clone());
+                    allowed = true;
                 }
             }
 
@@ -423,15 +547,34 @@ impl RbacService {
     }
 
     /// Evaluate a permission condition
-    async fn evaluate_condition(&self, condition: &Condition, _request: &AuthzRequest) -> bool {
+    async fn evaluate_condition(&self, condition: &Condition, request: &AuthzRequest) -> bool {
         match condition.type_ {
             ConditionType::TimeRange => {
-                // Check if current time is within range
-                true // Simplified
+                let now = chrono::Utc::now().time();
+                match time_in_window(&condition.value, now) {
+                    Some(allowed) => allowed,
+                    None => {
+                        warn!("Invalid time range condition: {}", condition.value);
+                        false
+                    }
+                }
             }
             ConditionType::IpRange => {
-                // Check if request IP is in allowed range
-                true // Simplified
+                let Some(ip) = request.context.get("ip") else {
+                    debug!("No client IP for IP range condition {}", condition.value);
+                    return !self.config.strict_conditions;
+                };
+                let Ok(ip) = ip.parse::<IpAddr>() else {
+                    warn!("Invalid client IP in authorization context: {}", ip);
+                    return false;
+                };
+                match ip_in_ranges(&condition.value, ip) {
+                    Some(allowed) => allowed,
+                    None => {
+                        warn!("Invalid IP range condition: {}", condition.value);
+                        false
+                    }
+                }
             }
             ConditionType::Tag => {
                 // Check tag-based conditions
@@ -441,10 +584,7 @@ impl RbacService {
                 // Check user attributes
                 true // Simplified
             }
-            ConditionType::Repository => {
-                // Check repository pattern matching
-                true // Simplified
-            }
+            ConditionType::Repository => repository_matches(&condition.value, &request.resource_id),
             ConditionType::Namespace => {
                 // Check namespace pattern matching
                 true // Simplified
@@ -454,16 +594,17 @@ impl RbacService {
 
     /// Create a new organization
     pub async fn create_organization(&self, org: Organization) -> Result<()> {
-        let mut organizations = self.organizations.write().await;
+        let _org_lock = self.entity_locks.lock(ORGANIZATIONS, &org.id).await;
 
-        if organizations.contains_key(&org.id) {
+        if self.organizations.read().await.contains_key(&org.id) {
             return Err(anyhow::anyhow!("Organization already exists: {}", org.id));
         }
 
-        organizations.insert(org.id.clone(), org.clone());
+        self.persist(ORGANIZATIONS, &org.id, &org).await?;
+        self.organizations.write().await.insert(org.id.clone(), org.clone());
 
         // Audit the creation
-        self.audit_log.write().await.push(AuditEntry {
+        self.record_audit(AuditEntry {
             id: uuid::Uuid::new_v4().to_string(),
             timestamp: chrono::Utc::now(),
             user_id: org.owner_id.clone(),
@@ -475,7 +616,8 @@ impl RbacService {
             ip_address: None,
             user_agent: None,
             details: HashMap::new(),
-        });
+        })
+        .await;
 
         info!("Created organization: {}", org.id);
         Ok(())
@@ -483,62 +625,139 @@ impl RbacService {
 
     /// Add user to organization
     pub async fn add_user_to_organization(&self, org_id: &str, user_id: &str) -> Result<()> {
-        let mut organizations = self.organizations.write().await;
-        let mut users = self.users.write().await;
+        let _org_lock = self.entity_locks.lock(ORGANIZATIONS, org_id).await;
+        let _user_lock = self.entity_locks.lock(USERS, user_id).await;
 
-        let org = organizations.get_mut(org_id)
+        let mut org = self.get_organization(org_id).await
             .ok_or_else(|| anyhow::anyhow!("Organization not found: {}", org_id))?;
 
-        let user = users.get_mut(user_id)
+        let mut user = self.get_user(user_id).await
             .ok_or_else(|| anyhow::anyhow!("User not found: {}", user_id))?;
 
         org.members.insert(user_id.to_string());
         user.organizations.insert(org_id.to_string());
 
+        self.persist(ORGANIZATIONS, org_id, &org).await?;
+        self.persist(USERS, user_id, &user).await?;
+        self.organizations.write().await.insert(org_id.to_string(), org);
+        self.users.write().await.insert(user_id.to_string(), user);
+
         info!("Added user {} to organization {}", user_id, org_id);
         Ok(())
     }
 
     /// Create a new team
     pub async fn create_team(&self, team: Team) -> Result<()> {
-        let mut organizations = self.organizations.write().await;
+        let _org_lock = self.entity_locks.lock(ORGANIZATIONS, &team.organization_id).await;
 
-        let org = organizations.get_mut(&team.organization_id)
+        let mut org = self.get_organization(&team.organization_id).await
             .ok_or_else(|| anyhow::anyhow!("Organization not found: {}", team.organization_id))?;
 
         org.teams.insert(team.id.clone(), team.clone());
 
+        self.persist(ORGANIZATIONS, &org.id, &org).await?;
+        self.organizations.write().await.insert(org.id.clone(), org);
+
         info!("Created team {} in organization {}", team.id, team.organization_id);
         Ok(())
     }
 
+    /// Add a member of an organization to one of its teams
+    pub async fn add_user_to_team(&self, org_id: &str, team_id: &str, user_id: &str) -> Result<()> {
+        let _org_lock = self.entity_locks.lock(ORGANIZATIONS, org_id).await;
+        let _user_lock = self.entity_locks.lock(USERS, user_id).await;
+
+        let mut org = self.get_organization(org_id).await
+            .ok_or_else(|| anyhow::anyhow!("Organization not found: {}", org_id))?;
+
+        if !org.members.contains(user_id) {
+            return Err(anyhow::anyhow!("User {} is not a member of organization {}", user_id, org_id));
+        }
+
+        let team = org.teams.get_mut(team_id)
+            .ok_or_else(|| anyhow::anyhow!("Team not found: {}", team_id))?;
+
+        let mut user = self.get_user(user_id).await
+            .ok_or_else(|| anyhow::anyhow!("User not found: {}", user_id))?;
+
+        team.members.insert(user_id.to_string());
+        user.teams.insert(team_id.to_string());
+
+        self.persist(ORGANIZATIONS, org_id, &org).await?;
+        self.persist(USERS, user_id, &user).await?;
+        self.organizations.write().await.insert(org_id.to_string(), org);
+        self.users.write().await.insert(user_id.to_string(), user);
+
+        info!("Added user {} to team {} in organization {}", user_id, team_id, org_id);
+        Ok(())
+    }
+
     /// Assign role to user
     pub async fn assign_role(&self, user_id: &str, role_id: &str) -> Result<()> {
-        let mut users = self.users.write().await;
-        let roles = self.roles.read().await;
+        let _user_lock = self.entity_locks.lock(USERS, user_id).await;
 
-        if !roles.contains_key(role_id) {
+        if !self.roles.read().await.contains_key(role_id) {
             return Err(anyhow::anyhow!("Role not found: {}", role_id));
         }
 
-        let user = users.get_mut(user_id)
+        let mut user = self.get_user(user_id).await
             .ok_or_else(|| anyhow::anyhow!("User not found: {}", user_id))?;
 
         user.direct_roles.insert(role_id.to_string());
 
+        self.persist(USERS, user_id, &user).await?;
+        self.users.write().await.insert(user_id.to_string(), user);
+
         info!("Assigned role {} to user {}", role_id, user_id);
         Ok(())
     }
 
+    /// Create a user on first login, or record the login for an existing one
+    pub async fn provision_user(&self, user_id: &str, email: &str, full_name: &str) -> Result<User> {
+        let _user_lock = self.entity_locks.lock(USERS, user_id).await;
+        let now = chrono::Utc::now();
+
+        let mut user = self.get_user(user_id).await.unwrap_or_else(|| {
+            info!("Provisioned user {} with role {}", user_id, self.config.default_role);
+            User {
+                id: user_id.to_string(),
+                username: user_id.to_string(),
+                email: email.to_string(),
+                full_name: full_name.to_string(),
+                organizations: HashSet::new(),
+                teams: HashSet::new(),
+                direct_roles: [self.config.default_role.clone()].into_iter().collect(),
+                attributes: HashMap::new(),
+                created_at: now,
+                last_login: None,
+                active: true,
+            }
+        });
+
+        if !user.active {
+            return Err(anyhow::anyhow!("User is deactivated: {}", user_id));
+        }
+
+        user.email = email.to_string();
+        user.full_name = full_name.to_string();
+        user.last_login = Some(now);
+
+        self.persist(USERS, user_id, &user).await?;
+        self.users.write().await.insert(user_id.to_string(), user.clone());
+
+        Ok(user)
+    }
+
     /// Create custom role
     pub async fn create_role(&self, role: Role) -> Result<()> {
-        let mut roles = self.roles.write().await;
+        let _role_lock = self.entity_locks.lock(ROLES, &role.id).await;
 
-        if roles.contains_key(&role.id) {
+        if self.roles.read().await.contains_key(&role.id) {
             return Err(anyhow::anyhow!("Role already exists: {}", role.id));
         }
 
-        roles.insert(role.id.clone(), role.clone());
+        self.persist(ROLES, &role.id, &role).await?;
+        self.roles.write().await.insert(role.id.clone(), role.clone());
 
         info!("Created role: {}", role.id);
         Ok(())
@@ -546,6 +765,10 @@ impl RbacService {
 
     /// Audit authorization decision
     async fn audit_authorization(&self, request: &AuthzRequest, allowed: &bool) {
+        if !self.config.audit_authorization_decisions {
+            return;
+        }
+
         let entry = AuditEntry {
             id: uuid::Uuid::new_v4().to_string(),
             timestamp: chrono::Utc::now(),
@@ -560,7 +783,21 @@ impl RbacService {
             details: request.context.iter().map(|(k, v)| (k.clone(), serde_json::Value::String(v.clone()))).collect(),
         };
 
-        self.audit_log.write().await.push(entry);
+        self.record_audit(entry).await;
+    }
+
+    /// Append an audit entry, dropping the oldest once the log is full
+    async fn record_audit(&self, entry: AuditEntry) {
+        let capacity = self.config.audit_log_capacity;
+        if capacity == 0 {
+            return;
+        }
+
+        let mut log = self.audit_log.write().await;
+        while log.len() >= capacity {
+            log.pop_front();
+        }
+        log.push_back(entry);
     }
 
     /// Get audit log entries
@@ -574,6 +811,58 @@ impl RbacService {
         self.organizations.read().await.get(org_id).cloned()
     }
 
+    /// Organization whose repositories include the given one
+    pub async fn get_repository_organization(&self, repository: &str) -> Option<Organization> {
+        self.organizations
+            .read()
+            .await
+            .values()
+            .find(|org| org.repositories.contains(repository))
+            .cloned()
+    }
+
+    /// Cached storage usage of an organization, or None until it has been measured
+    pub async fn get_organization_usage(&self, org_id: &str) -> Option<OrganizationUsage> {
+        let quota_bytes = self
+            .organizations
+            .read()
+            .await
+            .get(org_id)?
+            .settings
+            .storage_quota_gb
+            .map(|gb| gb * 1024 * 1024 * 1024);
+
+        let mut usage = self.organization_usage.read().await.get(org_id).cloned()?;
+        usage.quota_bytes = quota_bytes;
+        Some(usage)
+    }
+
+    /// Replace an organization's cached usage with a fresh measurement
+    pub async fn set_organization_usage(&self, usage: OrganizationUsage) {
+        self.organization_usage.write().await.insert(usage.organization_id.clone(), usage);
+    }
+
+    /// Add pushed content to an organization's cached usage. Blobs it already
+    /// stores are not counted again.
+    pub async fn record_organization_usage(&self, org_id: &str, blobs: &[(String, u64)], bytes: u64) {
+        let mut cache = self.organization_usage.write().await;
+        let Some(usage) = cache.get_mut(org_id) else {
+            return;
+        };
+
+        usage.used_bytes += bytes;
+        for (digest, size) in blobs {
+            if usage.blobs.insert(digest.clone()) {
+                usage.used_bytes += size;
+            }
+        }
+    }
+
+    /// Drop cached usage so it is measured again, e.g. after GC frees space
+    pub async fn invalidate_organization_usage(&self) {
+        self.organization_usage.write().await.clear();
+    }
+
     /// Get user by ID
     pub async fn get_user(&self, user_id: &str) -> Option<User> {
         self.users.read().await.get(user_id).cloned()
@@ -588,4 +877,325 @@ impl RbacService {
     pub async fn list_permissions(&self) -> Vec<Permission> {
         self.permissions.read().await.values().cloned().collect()
     }
-}
\ No newline at end of file
+}
+
+/// Storage key of a document. IDs such as `oidc:<subject>` come from identity
+/// providers, so everything but letters, digits, `-` and `_` is percent-encoded
+/// and an ID can never name another path.
+fn document_key(kind: &str, id: &str) -> String {
+    format!("rbac/{}/{}", kind, urlencoding::encode(id).replace('.', "%2E").replace('~', "%7E"))
+}
+
+/// Load every indexed document of one kind
+async fn load_documents<T: DeserializeOwned>(
+    storage: &Arc<dyn StorageBackend>,
+    index: &BTreeMap<String, BTreeSet<String>>,
+    kind: &str,
+) -> Result<HashMap<String, T>> {
+    let mut documents = HashMap::new();
+    for id in index.get(kind).into_iter().flatten() {
+        let mut stored = storage.get_blob(&document_key(kind, id)).await?;
+        // Earlier versions used the ID as it was; the next change rewrites it under its encoded key
+        if stored.is_none() && document_key(kind, id) != format!("rbac/{}/{}", kind, id) {
+            stored = storage.get_blob(&format!("rbac/{}/{}", kind, id)).await?;
+        }
+        match stored {
+            Some(data) => {
+                documents.insert(id.clone(), serde_json::from_slice(&data)?);
+            }
+            None => warn!("RBAC {} {} is indexed but missing from storage", kind, id),
+        }
+    }
+    Ok(documents)
+}
+
+/// Whether `ip` falls in any of the comma-separated CIDRs or addresses in `ranges`.
+/// Returns `None` when an entry does not parse.
+fn ip_in_ranges(ranges: &str, ip: IpAddr) -> Option<bool> {
+    let mut allowed = false;
+    for range in ranges.split(',').map(str::trim).filter(|range| !range.is_empty()) {
+        let contains = match range.parse::<IpNet>() {
+            Ok(net) => net.contains(&ip),
+            Err(_) => range.parse::<IpAddr>().ok()? == ip,
+        };
+        allowed |= contains;
+    }
+    Some(allowed)
+}
+
+/// Whether `now` (UTC) falls in an `HH:MM-HH:MM` window. A window whose end is
+/// before its start wraps past midnight. Returns `None` when the window does not parse.
+fn time_in_window(window: &str, now: chrono::NaiveTime) -> Option<bool> {
+    let (start, end) = window.split_once('-')?;
+    let start = chrono::NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?;
+    let end = chrono::NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?;
+
+    Some(if start <= end {
+        now >= start && now < end
+    } else {
+        now >= start || now < end
+    })
+}
+
+/// Whether a repository name matches any of the comma-separated glob patterns
+/// in `patterns`, e.g. `myorg/*` or `myorg/app-?`
+fn repository_matches(patterns: &str, repository: &str) -> bool {
+    patterns.split(',').map(str::trim).filter(|pattern| !pattern.is_empty()).any(|pattern| {
+        match globset::Glob::new(pattern) {
+            Ok(glob) => glob.compile_matcher().is_match(repository),
+            Err(e) => {
+                warn!("Invalid repository pattern {}: {}", pattern, e);
+                pattern == repository
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::MemoryStorage;
+
+    async fn service(team_based_access: bool) -> RbacService {
+        let mut config = crate::Config::default().rbac.unwrap();
+        config.enabled = true;
+        config.enable_team_based_access = team_based_access;
+        RbacService::new(config, Arc::new(MemoryStorage::new(None))).await.unwrap()
+    }
+
+    fn organization(id: &str) -> Organization {
+        Organization {
+            id: id.to_string(),
+            name: id.to_string(),
+            description: String::new(),
+            owner_id: "owner".to_string(),
+            members: HashSet::new(),
+            teams: HashMap::new(),
+            repositories: HashSet::new(),
+            settings: OrganizationSettings {
+                require_2fa: false,
+                allow_public_repos: false,
+                default_visibility: "private".to_string(),
+                max_members: None,
+                max_repositories: None,
+                storage_quota_gb: None,
+                allowed_domains: Vec::new(),
+                webhook_url: None,
+            },
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        }
+    }
+
+    fn team(id: &str, organization_id: &str, roles: &[&str]) -> Team {
+        Team {
+            id: id.to_string(),
+            name: id.to_string(),
+            description: String::new(),
+            organization_id: organization_id.to_string(),
+            members: HashSet::new(),
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+            repositories: HashSet::new(),
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    fn request(user_id: &str, action: Action) -> AuthzRequest {
+        AuthzRequest {
+            user_id: user_id.to_string(),
+            resource: ResourceType::Repository,
+            resource_id: "acme/app".to_string(),
+            action,
+            context: HashMap::new(),
+        }
+    }
+
+    /// A user with no direct roles, in a team of `acme` granted `developer`
+    async fn team_member(rbac: &RbacService) {
+        let mut user = rbac.provision_user("carol", "carol@example.com", "Carol").await.unwrap();
+        user.direct_roles.clear();
+        rbac.persist(USERS, &user.id, &user).await.unwrap();
+        rbac.users.write().await.insert(user.id.clone(), user);
+
+        rbac.create_organization(organization("acme")).await.unwrap();
+        rbac.create_team(team("builders", "acme", &["developer"])).await.unwrap();
+        rbac.add_user_to_organization("acme", "carol").await.unwrap();
+        rbac.add_user_to_team("acme", "builders", "carol").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn changes_survive_a_restart_and_concurrent_changes_are_not_lost() {
+        let storage: Arc<dyn StorageBackend> = Arc::new(MemoryStorage::new(None));
+        let mut config = crate::Config::default().rbac.unwrap();
+        config.enabled = true;
+        let rbac = Arc::new(RbacService::new(config.clone(), storage.clone()).await.unwrap());
+
+        rbac.create_organization(organization("acme")).await.unwrap();
+        rbac.create_team(team("builders", "acme", &["developer"])).await.unwrap();
+        let mut release_manager = rbac.list_roles().await.into_iter().find(|role| role.id == "developer").unwrap();
+        release_manager.id = "release-manager".to_string();
+        rbac.create_role(release_manager).await.unwrap();
+
+        // Logins of one user race role assignments and memberships of it, and of others
+        let subject = "oidc:https://idp.example.com/../alice";
+        rbac.provision_user(subject, "alice@example.com", "Alice").await.unwrap();
+        let mut changes = tokio::task::JoinSet::new();
+        for i in 0..8 {
+            let rbac = rbac.clone();
+            changes.spawn(async move {
+                rbac.provision_user(subject, "alice@example.com", "Alice").await.unwrap();
+                let user_id = format!("user-{}", i);
+                rbac.provision_user(&user_id, "user@example.com", "User").await.unwrap();
+                rbac.add_user_to_organization("acme", &user_id).await.unwrap();
+            });
+        }
+        let assigning = rbac.clone();
+        changes.spawn(async move { assigning.assign_role(subject, "release-manager").await.unwrap() });
+        let joining = rbac.clone();
+        changes.spawn(async move {
+            joining.add_user_to_organization("acme", subject).await.unwrap();
+            joining.add_user_to_team("acme", "builders", subject).await.unwrap();
+        });
+        while let Some(change) = changes.join_next().await {
+            change.unwrap();
+        }
+
+        // A document written under its raw ID before IDs were encoded
+        let mut legacy = rbac.get_user("user-0").await.unwrap();
+        legacy.id = "legacy.user".to_string();
+        storage.put_blob("rbac/users/legacy.user", serde_json::to_vec(&legacy).unwrap().into()).await.unwrap();
+        let mut index = rbac.index.lock().await.clone();
+        index.get_mut(USERS).unwrap().insert(legacy.id.clone());
+        storage.put_blob(RBAC_INDEX_KEY, serde_json::to_vec(&index).unwrap().into()).await.unwrap();
+
+        let key = "rbac/users/oidc%3Ahttps%3A%2F%2Fidp%2Eexample%2Ecom%2F%2E%2E%2Falice";
+        assert!(storage.get_blob(key).await.unwrap().is_some());
+
+        let restarted = RbacService::new(config, storage).await.unwrap();
+        let alice = restarted.get_user(subject).await.unwrap();
+        assert!(alice.direct_roles.contains("release-manager"), "{:?}", alice.direct_roles);
+        assert_eq!(alice.organizations, HashSet::from(["acme".to_string()]));
+        assert_eq!(alice.teams, HashSet::from(["builders".to_string()]));
+        assert!(alice.last_login.is_some());
+
+        let acme = restarted.get_organization("acme").await.unwrap();
+        assert_eq!(acme.members.len(), 9);
+        assert!(acme.teams["builders"].members.contains(subject));
+        assert!(restarted.list_roles().await.iter().any(|role| role.id == "release-manager"));
+        assert!(restarted.get_user("legacy.user").await.is_some());
+        assert_eq!(restarted.get_user("user-3").await.unwrap().organizations.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn audit_log_keeps_only_the_most_recent_entries() {
+        let mut config = crate::Config::default().rbac.unwrap();
+        config.enabled = true;
+        config.audit_log_capacity = 16;
+        let rbac = RbacService::new(config, Arc::new(MemoryStorage::new(None))).await.unwrap();
+        rbac.provision_user("dave", "dave@example.com", "Dave").await.unwrap();
+
+        for _ in 0..100 {
+            rbac.authorize(request("dave", Action::Pull)).await.unwrap();
+        }
+        let mut last = request("dave", Action::Push);
+        last.resource_id = "acme/last".to_string();
+        rbac.authorize(last).await.unwrap();
+
+        let log = rbac.get_audit_log(usize::MAX).await;
+        assert_eq!(log.len(), 16);
+        assert_eq!(log[0].resource_id, "acme/last");
+    }
+
+    #[tokio::test]
+    async fn team_roles_grant_what_direct_roles_do_not() {
+        let rbac = service(true).await;
+        team_member(&rbac).await;
+
+        let response = rbac.authorize(request("carol", Action::Push)).await.unwrap();
+        assert!(response.allowed, "{}", response.reason);
+        assert_eq!(response.applied_roles, vec!["developer"]);
+        assert!(!rbac.authorize(request("carol", Action::Admin)).await.unwrap().allowed);
+    }
+
+    #[tokio::test]
+    async fn team_roles_are_ignored_without_team_based_access() {
+        let rbac = service(false).await;
+        team_member(&rbac).await;
+
+        let response = rbac.authorize(request("carol", Action::Pull)).await.unwrap();
+        assert!(!response.allowed);
+        assert!(response.applied_roles.is_empty());
+    }
+
+    /// Give `carol` a role whose only permission is a push under `condition`
+    async fn conditional_pusher(rbac: &RbacService, condition: Condition) {
+        let permission = Permission {
+            id: "repository.push.conditional".to_string(),
+            name: "Push Under Condition".to_string(),
+            resource: ResourceType::Repository,
+            action: Action::Push,
+            conditions: vec![condition],
+        };
+        rbac.permissions.write().await.insert(permission.id.clone(), permission.clone());
+        rbac.create_role(Role {
+            id: "conditional".to_string(),
+            name: "Conditional".to_string(),
+            description: String::new(),
+            permissions: [permission.id].into_iter().collect(),
+            parent_role: None,
+            scope: RoleScope::Global,
+            priority: 60,
+            system_role: false,
+        })
+        .await
+        .unwrap();
+        rbac.provision_user("carol", "carol@example.com", "Carol").await.unwrap();
+        rbac.assign_role("carol", "conditional").await.unwrap();
+    }
+
+    fn push_from(ip: Option<&str>) -> AuthzRequest {
+        let mut request = request("carol", Action::Push);
+        if let Some(ip) = ip {
+            request.context.insert("ip".to_string(), ip.to_string());
+        }
+        request
+    }
+
+    #[tokio::test]
+    async fn ip_range_conditions_allow_only_listed_networks() {
+        let rbac = service(true).await;
+        let condition = Condition { type_: ConditionType::IpRange, value: "10.0.0.0/8, 192.168.1.7".to_string() };
+        conditional_pusher(&rbac, condition).await;
+
+        for (ip, allowed) in [("10.1.2.3", true), ("192.168.1.7", true), ("192.168.1.8", false), ("not-an-ip", false)] {
+            assert_eq!(rbac.authorize(push_from(Some(ip))).await.unwrap().allowed, allowed, "{}", ip);
+        }
+
+        // Without a client IP the condition only denies in strict mode
+        assert!(rbac.authorize(push_from(None)).await.unwrap().allowed);
+        let mut strict = rbac.clone();
+        strict.config.strict_conditions = true;
+        assert!(!strict.authorize(push_from(None)).await.unwrap().allowed);
+    }
+
+    #[tokio::test]
+    async fn time_range_conditions_deny_outside_the_window() {
+        let rbac = service(true).await;
+        let now = chrono::Utc::now().time();
+        let hour = chrono::Duration::hours(1);
+        let window = format!("{}-{}", (now + hour).format("%H:%M"), (now + hour * 2).format("%H:%M"));
+        conditional_pusher(&rbac, Condition { type_: ConditionType::TimeRange, value: window }).await;
+
+        assert!(!rbac.authorize(push_from(None)).await.unwrap().allowed);
+    }
+
+    #[test]
+    fn time_windows_may_wrap_past_midnight() {
+        let at = |time: &str| chrono::NaiveTime::parse_from_str(time, "%H:%M").unwrap();
+        assert_eq!(time_in_window("09:00-17:00", at("12:00")), Some(true));
+        assert_eq!(time_in_window("09:00-17:00", at("17:00")), Some(false));
+        assert_eq!(time_in_window("22:00-06:00", at("23:30")), Some(true));
+        assert_eq!(time_in_window("22:00-06:00", at("12:00")), Some(false));
+        assert_eq!(time_in_window("9am-5pm", at("12:00")), None);
+    }
+}
@@ -0,0 +1,171 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+use crate::{config::Config, server::AppState};
+
+/// How often the config file's modification time is checked
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Reload the config file when it changes, or on SIGHUP, and apply the settings that are
+/// safe to change at runtime: rate limits, basic auth users, audit settings and immutable
+/// tags. Other changes are logged and take effect after a restart.
+pub fn spawn_config_reload(path: PathBuf, state: AppState) {
+    tokio::spawn(async move {
+        let modified = |path: &PathBuf| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        let mut last_modified: Option<SystemTime> = modified(&path);
+        let mut loaded = state.config.clone();
+        let mut interval = tokio::time::interval(CONFIG_POLL_INTERVAL);
+
+        #[cfg(unix)]
+        let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+            Ok(signal) => Some(signal),
+            Err(e) => {
+                warn!("Failed to listen for SIGHUP, config reloads on change only: {}", e);
+                None
+            }
+        };
+
+        loop {
+            #[cfg(unix)]
+            let forced = tokio::select! {
+                _ = state.shutdown.cancelled() => break,
+                _ = interval.tick() => false,
+                Some(()) = async {
+                    match hangup.as_mut() {
+                        Some(signal) => signal.recv().await,
+                        None => std::future::pending().await,
+                    }
+                } => true,
+            };
+            #[cfg(not(unix))]
+            let forced = tokio::select! {
+                _ = state.shutdown.cancelled() => break,
+                _ = interval.tick() => false,
+            };
+
+            let current = modified(&path);
+            if !forced && (current.is_none() || current == last_modified) {
+                continue;
+            }
+            last_modified = current;
+
+            // Keep running with the current settings if the file is invalid
+            let config = match Config::load(&path) {
+                Ok(config) => config,
+                Err(e) => {
+                    warn!("Failed to reload config {}: {:#}", path.display(), e);
+                    continue;
+                }
+            };
+
+            apply(&state, &loaded, &config).await;
+            loaded = config;
+        }
+    });
+}
+
+async fn apply(state: &AppState, previous: &Config, config: &Config) {
+    for section in restart_required_changes(previous, config) {
+        warn!("Config section [{}] changed; the change takes effect after a restart", section);
+    }
+
+    state.rate_limiter.reconfigure(&config.registry);
+
+    match state.auth.reload_users(&config.auth) {
+        Ok(count) => info!("Reloaded {} configured users", count),
+        Err(e) => warn!("Failed to reload auth.basic.users, keeping the current users: {:#}", e),
+    }
+
+    if let (Some(audit), Some(audit_config)) = (&state.audit, config.audit.as_ref().filter(|a| a.enabled))
+        && let Err(e) = audit.reconfigure(audit_config.clone()).await
+    {
+        warn!("Failed to reload audit settings, keeping the current ones: {:#}", e);
+    }
+
+    state.runtime_config.store(Arc::new(config.clone()));
+    info!("Applied reloaded configuration");
+}
+
+/// Top-level sections that differ in ways the running server can't pick up
+fn restart_required_changes(previous: &Config, config: &Config) -> Vec<String> {
+    let mut reloaded = previous.clone();
+    copy_reloadable(&mut reloaded, config);
+
+    let (Ok(toml::Value::Table(before)), Ok(toml::Value::Table(after))) =
+        (toml::Value::try_from(&reloaded), toml::Value::try_from(config))
+    else {
+        return Vec::new();
+    };
+
+    let mut sections: Vec<String> = before
+        .keys()
+        .chain(after.keys())
+        .filter(|key| before.get(*key) != after.get(*key))
+        .cloned()
+        .collect();
+    sections.sort();
+    sections.dedup();
+    sections
+}
+
+/// Copy the settings that are applied at runtime, so what's left to compare needs a restart
+fn copy_reloadable(target: &mut Config, config: &Config) {
+    target.registry.rate_limit_per_hour = config.registry.rate_limit_per_hour;
+    target.registry.rate_limits = config.registry.rate_limits.clone();
+    target.registry.immutable_tags = config.registry.immutable_tags.clone();
+
+    if let (Some(target_basic), Some(basic)) = (&mut target.auth.basic, &config.auth.basic) {
+        target_basic.users = basic.users.clone();
+    }
+
+    // Turning auditing on or off needs a restart; changing its settings doesn't
+    if target.audit.as_ref().is_some_and(|a| a.enabled) && config.audit.as_ref().is_some_and(|a| a.enabled) {
+        target.audit = config.audit.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StorageType;
+    use crate::server::Server;
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.storage.storage_type = StorageType::Memory;
+        config.storage.path = None;
+        config
+    }
+
+    #[test]
+    fn only_settings_the_server_cannot_pick_up_need_a_restart() {
+        let previous = config();
+        let mut config = previous.clone();
+        config.registry.rate_limit_per_hour = 7;
+        config.registry.immutable_tags = vec!["release".to_string()];
+        config.auth.basic.as_mut().unwrap().users = vec!["ci:secret".to_string()];
+        assert!(restart_required_changes(&previous, &config).is_empty());
+
+        config.server.bind_addr = "0.0.0.0:6000".to_string();
+        config.storage.storage_type = StorageType::Filesystem;
+        assert_eq!(restart_required_changes(&previous, &config), ["server", "storage"]);
+    }
+
+    #[tokio::test]
+    async fn reloaded_settings_apply_to_the_running_server() {
+        let previous = config();
+        let server = Server::new(previous.clone(), "127.0.0.1:0", "127.0.0.1:0").await.unwrap();
+        let state = server.build_state(tokio_util::sync::CancellationToken::new()).await.unwrap();
+
+        let mut config = previous.clone();
+        config.registry.immutable_tags = vec!["release-*".to_string()];
+        config.auth.basic.as_mut().unwrap().users = vec!["ci:secret".to_string()];
+        apply(&state, &previous, &config).await;
+
+        assert_eq!(state.runtime_config.load().registry.immutable_tags, ["release-*"]);
+        assert!(state.auth.authenticate("ci", "secret").await.unwrap().is_some());
+        assert!(state.auth.authenticate("admin", "changeme").await.unwrap().is_none());
+    }
+}
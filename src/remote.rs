@@ -0,0 +1,163 @@
+use anyhow::{bail, Result};
+use reqwest::{header, Method, RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Lifetime assumed for tokens that don't state one
+const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(60);
+
+/// Tokens are renewed this long before they expire
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(10);
+
+/// Client for another registry's distribution API. Requests carry the
+/// configured credentials; bearer challenges are answered with a token from
+/// the registry's realm, cached per scope until it expires.
+pub struct RemoteRegistry {
+    base_url: String,
+    username: Option<String>,
+    password: Option<String>,
+    client: reqwest::Client,
+    /// Bearer tokens by scope
+    tokens: Mutex<HashMap<String, CachedToken>>,
+}
+
+struct CachedToken {
+    token: String,
+    expires_at: Instant,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+    expires_in: Option<u64>,
+}
+
+impl RemoteRegistry {
+    pub fn new(base_url: &str, username: Option<String>, password: Option<String>) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            username,
+            password,
+            client: reqwest::Client::new(),
+            tokens: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// URL of a path under the registry's `/v2/` API
+    pub fn url(&self, path: &str) -> String {
+        format!("{}/v2/{}", self.base_url, path)
+    }
+
+    /// A request carrying the cached token for the scope, or the configured credentials
+    pub fn request(&self, method: Method, url: &str, scope: &str) -> RequestBuilder {
+        let request = self.client.request(method, url);
+        match (self.cached_token(scope), &self.username) {
+            (Some(token), _) => request.bearer_auth(token),
+            (None, Some(username)) => request.basic_auth(username, self.password.as_deref()),
+            (None, None) => request,
+        }
+    }
+
+    /// Send a request, answering a bearer challenge and sending it again with
+    /// the token. `build` is called once per attempt to add headers and body.
+    pub async fn send(
+        &self,
+        method: Method,
+        url: &str,
+        scope: &str,
+        build: impl Fn(RequestBuilder) -> RequestBuilder,
+    ) -> Result<Response> {
+        let response = build(self.request(method.clone(), url, scope)).send().await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+
+        let Some(challenge) = response
+            .headers()
+            .get(header::WWW_AUTHENTICATE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_bearer_challenge)
+        else {
+            return Ok(response);
+        };
+
+        if !self.authorize(&challenge, scope).await? {
+            return Ok(response);
+        }
+        Ok(build(self.request(method, url, scope)).send().await?)
+    }
+
+    fn cached_token(&self, scope: &str) -> Option<String> {
+        let tokens = self.tokens.lock().unwrap();
+        tokens
+            .get(scope)
+            .filter(|cached| cached.expires_at > Instant::now())
+            .map(|cached| cached.token.clone())
+    }
+
+    /// Request a token from the challenge's realm and cache it for the scope,
+    /// returning false when the challenge names no realm or no token is issued
+    async fn authorize(&self, challenge: &HashMap<String, String>, scope: &str) -> Result<bool> {
+        let Some(realm) = challenge.get("realm") else {
+            return Ok(false);
+        };
+
+        let mut query = vec![("scope", scope)];
+        if let Some(service) = challenge.get("service") {
+            query.push(("service", service.as_str()));
+        }
+
+        let mut request = self.client.get(realm).query(&query);
+        if let Some(username) = &self.username {
+            request = request.basic_auth(username, self.password.as_deref());
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            bail!("Token request to {} failed with {}", realm, response.status());
+        }
+
+        let body: TokenResponse = response.json().await?;
+        let Some(token) = body.token.or(body.access_token) else {
+            return Ok(false);
+        };
+        let lifetime = body.expires_in.map_or(DEFAULT_TOKEN_LIFETIME, Duration::from_secs);
+
+        self.tokens.lock().unwrap().insert(
+            scope.to_string(),
+            CachedToken {
+                token,
+                expires_at: Instant::now() + lifetime.saturating_sub(TOKEN_EXPIRY_MARGIN),
+            },
+        );
+        Ok(true)
+    }
+}
+
+/// Parameters of a `WWW-Authenticate: Bearer realm="...",service="...",scope="..."` challenge
+fn parse_bearer_challenge(challenge: &str) -> Option<HashMap<String, String>> {
+    let mut rest = challenge.strip_prefix("Bearer ")?.trim();
+    let mut params = HashMap::new();
+
+    while !rest.is_empty() {
+        let (key, value) = rest.split_once('=')?;
+        let (value, remainder) = match value.trim_start().strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"')?;
+                (&quoted[..end], &quoted[end + 1..])
+            }
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+        params.insert(key.trim().to_lowercase(), value.trim().to_string());
+        rest = remainder.trim_start().trim_start_matches(',').trim_start();
+    }
+
+    Some(params)
+}
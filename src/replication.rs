@@ -0,0 +1,449 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use futures::future::{BoxFuture, FutureExt};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use prometheus::IntCounterVec;
+use reqwest::{header, Method, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{Mutex, Notify};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+use crate::api::registry::manifest_types::Manifest;
+use crate::api::registry::manifests::manifest_media_type;
+use crate::audit::AuditService;
+use crate::config::{ReplicationConfig, ReplicationTargetConfig};
+use crate::remote::RemoteRegistry;
+use crate::storage::StorageBackend;
+
+const QUEUE_KEY: &str = "replication/queue.json";
+
+/// Longest wait between retries of a job
+const MAX_RETRY_DELAY_SECS: u64 = 3600;
+
+/// How long the worker sleeps when the queue is empty, unless woken by a push
+const IDLE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// A pushed manifest waiting to be copied to one target
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplicationJob {
+    pub id: String,
+    pub target: String,
+    pub repository: String,
+    pub reference: String,
+    pub digest: String,
+    pub attempts: u32,
+    pub enqueued_at: DateTime<Utc>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+}
+
+/// Replication queue depth and how far behind each target is
+#[derive(Debug, Serialize)]
+pub struct ReplicationStatus {
+    pub queue_depth: usize,
+    pub targets: Vec<TargetStatus>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TargetStatus {
+    pub name: String,
+    pub url: String,
+    pub pending: usize,
+    /// Age of the oldest job still waiting for this target
+    pub lag_seconds: i64,
+    pub last_replicated_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub replicated_total: u64,
+    pub abandoned_total: u64,
+}
+
+struct Target {
+    config: ReplicationTargetConfig,
+    repositories: Option<GlobSet>,
+    remote: RemoteRegistry,
+}
+
+impl Target {
+    fn replicates(&self, repository: &str) -> bool {
+        self.repositories.as_ref().is_none_or(|patterns| patterns.is_match(repository))
+    }
+}
+
+#[derive(Debug, Default)]
+struct TargetProgress {
+    last_replicated_at: Option<DateTime<Utc>>,
+    replicated_total: u64,
+    abandoned_total: u64,
+}
+
+/// Copies pushed manifests and their blobs to remote registries.
+///
+/// Each push enqueues one job per matching target. Jobs are persisted through
+/// the storage backend so they survive a restart, and a single worker works
+/// through them, retrying failures with exponential backoff. Replication never
+/// fails the push itself; failed attempts are counted and audited instead.
+pub struct ReplicationService {
+    config: ReplicationConfig,
+    targets: Vec<Target>,
+    storage: Arc<dyn StorageBackend>,
+    audit: Option<Arc<AuditService>>,
+    failures: IntCounterVec,
+    queue: Mutex<Vec<ReplicationJob>>,
+    progress: std::sync::Mutex<HashMap<String, TargetProgress>>,
+    wake: Notify,
+}
+
+impl ReplicationService {
+    pub async fn load(
+        config: ReplicationConfig,
+        storage: Arc<dyn StorageBackend>,
+        audit: Option<Arc<AuditService>>,
+        failures: IntCounterVec,
+    ) -> Result<Self> {
+        let mut targets = Vec::new();
+        for target in &config.targets {
+            let repositories = if target.repositories.is_empty() {
+                None
+            } else {
+                let mut builder = GlobSetBuilder::new();
+                for pattern in &target.repositories {
+                    builder.add(Glob::new(pattern).with_context(|| {
+                        format!("Invalid repository pattern {} for replication target {}", pattern, target.name)
+                    })?);
+                }
+                Some(builder.build()?)
+            };
+
+            info!("Replicating pushes to {} ({})", target.name, target.url);
+            targets.push(Target {
+                config: target.clone(),
+                repositories,
+                remote: RemoteRegistry::new(&target.url, target.username.clone(), target.password.clone()),
+            });
+        }
+
+        let mut queue: Vec<ReplicationJob> = match storage.get_blob(QUEUE_KEY).await? {
+            Some(data) => serde_json::from_slice(&data)?,
+            None => Vec::new(),
+        };
+        queue.retain(|job| {
+            let known = targets.iter().any(|target| target.config.name == job.target);
+            if !known {
+                warn!("Dropping replication job {} for removed target {}", job.id, job.target);
+            }
+            known
+        });
+        info!("Loaded {} pending replication jobs", queue.len());
+
+        Ok(Self {
+            config,
+            targets,
+            storage,
+            audit,
+            failures,
+            queue: Mutex::new(queue),
+            progress: std::sync::Mutex::new(HashMap::new()),
+            wake: Notify::new(),
+        })
+    }
+
+    /// Queue a pushed manifest for every target that replicates the repository.
+    /// A pending job for the same reference is replaced, so only the latest push is copied.
+    pub async fn enqueue(&self, repository: &str, reference: &str, digest: &str) {
+        let targets: Vec<&Target> = self.targets.iter().filter(|target| target.replicates(repository)).collect();
+        if targets.is_empty() {
+            return;
+        }
+
+        let mut queue = self.queue.lock().await;
+        let now = Utc::now();
+        for target in targets {
+            queue.retain(|job| {
+                !(job.target == target.config.name && job.repository == repository && job.reference == reference)
+            });
+            queue.push(ReplicationJob {
+                id: uuid::Uuid::new_v4().to_string(),
+                target: target.config.name.clone(),
+                repository: repository.to_string(),
+                reference: reference.to_string(),
+                digest: digest.to_string(),
+                attempts: 0,
+                enqueued_at: now,
+                next_attempt_at: now,
+                last_error: None,
+            });
+            debug!("Queued replication of {}:{} to {}", repository, reference, target.config.name);
+        }
+        self.persist(&queue).await;
+        drop(queue);
+
+        self.wake.notify_one();
+    }
+
+    pub async fn status(&self) -> ReplicationStatus {
+        let queue = self.queue.lock().await;
+        let progress = self.progress.lock().unwrap();
+        let now = Utc::now();
+
+        let targets = self
+            .targets
+            .iter()
+            .map(|target| {
+                let jobs: Vec<&ReplicationJob> = queue.iter().filter(|job| job.target == target.config.name).collect();
+                let oldest = jobs.iter().map(|job| job.enqueued_at).min();
+                let last_error = jobs
+                    .iter()
+                    .filter(|job| job.last_error.is_some())
+                    .max_by_key(|job| job.next_attempt_at)
+                    .and_then(|job| job.last_error.clone());
+                let target_progress = progress.get(&target.config.name);
+
+                TargetStatus {
+                    name: target.config.name.clone(),
+                    url: target.config.url.clone(),
+                    pending: jobs.len(),
+                    lag_seconds: oldest.map_or(0, |oldest| (now - oldest).num_seconds()),
+                    last_replicated_at: target_progress.and_then(|progress| progress.last_replicated_at),
+                    last_error,
+                    replicated_total: target_progress.map_or(0, |progress| progress.replicated_total),
+                    abandoned_total: target_progress.map_or(0, |progress| progress.abandoned_total),
+                }
+            })
+            .collect();
+
+        ReplicationStatus {
+            queue_depth: queue.len(),
+            targets,
+        }
+    }
+
+    /// Work through the queue in the background, sleeping until the next job is due.
+    /// Stops between jobs once `shutdown` is cancelled; the queue is already persisted.
+    pub fn spawn_worker(self: &Arc<Self>, shutdown: CancellationToken) {
+        let service = Arc::clone(self);
+        tokio::spawn(async move {
+            while !shutdown.is_cancelled() {
+                match service.next_due_job().await {
+                    Ok(job) => service.run(job).await,
+                    Err(wait) => {
+                        tokio::select! {
+                            _ = shutdown.cancelled() => {}
+                            _ = service.wake.notified() => {}
+                            _ = tokio::time::sleep(wait) => {}
+                        }
+                    }
+                }
+            }
+            info!("Replication worker stopped");
+        });
+    }
+
+    /// The job that is due soonest, or how long until one is
+    async fn next_due_job(&self) -> std::result::Result<ReplicationJob, std::time::Duration> {
+        let queue = self.queue.lock().await;
+        let Some(job) = queue.iter().min_by_key(|job| job.next_attempt_at) else {
+            return Err(IDLE_POLL_INTERVAL);
+        };
+
+        match (job.next_attempt_at - Utc::now()).to_std() {
+            Ok(wait) if !wait.is_zero() => Err(wait),
+            _ => Ok(job.clone()),
+        }
+    }
+
+    async fn run(&self, job: ReplicationJob) {
+        let result = match self.target(&job.target) {
+            Some(target) => self.replicate(target, &job).await,
+            None => Err(anyhow::anyhow!("Replication target {} is not configured", job.target)),
+        };
+
+        let mut queue = self.queue.lock().await;
+        match result {
+            Ok(()) => {
+                info!("Replicated {}:{} to {}", job.repository, job.reference, job.target);
+                queue.retain(|queued| queued.id != job.id);
+                let mut progress = self.progress.lock().unwrap();
+                let target_progress = progress.entry(job.target.clone()).or_default();
+                target_progress.last_replicated_at = Some(Utc::now());
+                target_progress.replicated_total += 1;
+            }
+            Err(e) => {
+                let attempts = job.attempts + 1;
+                let abandoned = attempts >= self.config.max_attempts;
+                self.failures.with_label_values(&[&job.target]).inc();
+
+                if abandoned {
+                    error!(
+                        "Giving up replicating {}:{} to {} after {} attempts: {:#}",
+                        job.repository, job.reference, job.target, attempts, e
+                    );
+                    queue.retain(|queued| queued.id != job.id);
+                    let mut progress = self.progress.lock().unwrap();
+                    progress.entry(job.target.clone()).or_default().abandoned_total += 1;
+                } else {
+                    let delay = self.retry_delay(attempts);
+                    warn!(
+                        "Failed to replicate {}:{} to {} (attempt {}), retrying in {}s: {:#}",
+                        job.repository, job.reference, job.target, attempts, delay.num_seconds(), e
+                    );
+                    // A newer push may have replaced the job meanwhile
+                    if let Some(queued) = queue.iter_mut().find(|queued| queued.id == job.id) {
+                        queued.attempts = attempts;
+                        queued.last_error = Some(format!("{:#}", e));
+                        queued.next_attempt_at = Utc::now() + delay;
+                    }
+                }
+
+                self.audit_failure(&job, attempts, format!("{:#}", e), abandoned).await;
+            }
+        }
+        self.persist(&queue).await;
+    }
+
+    fn target(&self, name: &str) -> Option<&Target> {
+        self.targets.iter().find(|target| target.config.name == name)
+    }
+
+    /// Exponential backoff from the configured base delay
+    fn retry_delay(&self, attempts: u32) -> Duration {
+        let factor = 1u64 << attempts.saturating_sub(1).min(16);
+        let secs = self.config.retry_base_secs.saturating_mul(factor).min(MAX_RETRY_DELAY_SECS);
+        Duration::seconds(secs as i64)
+    }
+
+    async fn replicate(&self, target: &Target, job: &ReplicationJob) -> Result<()> {
+        let scope = format!("repository:{}:pull,push", job.repository);
+        self.push_manifest(target, &scope, &job.repository, &job.reference, &job.digest).await
+    }
+
+    /// Push a manifest after everything it references: child manifests of an
+    /// index first, then blobs, so the target accepts it
+    fn push_manifest<'a>(
+        &'a self,
+        target: &'a Target,
+        scope: &'a str,
+        repository: &'a str,
+        reference: &'a str,
+        digest: &'a str,
+    ) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let data = self.storage.get_manifest_by_digest(repository, digest).await?;
+            let manifest = Manifest::parse(&data)?;
+
+            for child in manifest.manifest_references() {
+                if self.exists(target, scope, &format!("{}/manifests/{}", repository, child)).await? {
+                    continue;
+                }
+                self.push_manifest(target, scope, repository, child, child).await?;
+            }
+            for blob in manifest.blob_references() {
+                self.push_blob(target, scope, repository, blob).await?;
+            }
+
+            let media_type = match self.storage.get_manifest_metadata(repository, digest).await {
+                Ok(metadata) => metadata.media_type,
+                Err(e) => {
+                    debug!("No metadata for manifest {}@{}: {}", repository, digest, e);
+                    None
+                }
+            }
+            .unwrap_or_else(|| manifest_media_type(&data));
+
+            let url = target.remote.url(&format!("{}/manifests/{}", repository, reference));
+            let response = target
+                .remote
+                .send(Method::PUT, &url, scope, |request| {
+                    request.header(header::CONTENT_TYPE, media_type.as_str()).body(data.clone())
+                })
+                .await?;
+            if !response.status().is_success() {
+                bail!("{} rejected manifest {}:{} with {}", target.config.name, repository, reference, response.status());
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    /// Upload a blob in a single request unless the target already has it
+    async fn push_blob(&self, target: &Target, scope: &str, repository: &str, digest: &str) -> Result<()> {
+        if self.exists(target, scope, &format!("{}/blobs/{}", repository, digest)).await? {
+            debug!("{} already has blob {}", target.config.name, digest);
+            return Ok(());
+        }
+
+        let start_url = target.remote.url(&format!("{}/blobs/uploads/", repository));
+        let response = target.remote.send(Method::POST, &start_url, scope, |request| request).await?;
+        if response.status() != StatusCode::ACCEPTED {
+            bail!("{} refused to start an upload for {} with {}", target.config.name, digest, response.status());
+        }
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .context("Upload response has no Location header")?;
+        let mut upload_url = Url::parse(&start_url)?.join(location)?;
+        upload_url.query_pairs_mut().append_pair("digest", digest);
+
+        let Some((size, stream)) = self.storage.get_blob_stream(digest).await? else {
+            bail!("Blob {} is missing from local storage", digest);
+        };
+
+        // The upload request above fetched a token for the scope, so the body is only sent once
+        let response = target
+            .remote
+            .request(Method::PUT, upload_url.as_str(), scope)
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .header(header::CONTENT_LENGTH, size)
+            .body(reqwest::Body::wrap_stream(stream))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            bail!("{} rejected blob {} with {}", target.config.name, digest, response.status());
+        }
+
+        debug!("Replicated blob {} ({} bytes) to {}", digest, size, target.config.name);
+        Ok(())
+    }
+
+    async fn exists(&self, target: &Target, scope: &str, path: &str) -> Result<bool> {
+        let url = target.remote.url(path);
+        let response = target.remote.send(Method::HEAD, &url, scope, |request| request).await?;
+        Ok(response.status().is_success())
+    }
+
+    async fn audit_failure(&self, job: &ReplicationJob, attempts: u32, error_message: String, abandoned: bool) {
+        let Some(audit) = self.audit.as_ref() else {
+            return;
+        };
+
+        let event = AuditService::replication_failure_event(
+            &job.target,
+            job.repository.clone(),
+            job.reference.clone(),
+            job.digest.clone(),
+            attempts,
+            error_message,
+            abandoned,
+        );
+        if let Err(e) = audit.log(event).await {
+            error!("Failed to record replication failure for {}:{}: {}", job.repository, job.reference, e);
+        }
+    }
+
+    /// Write the queue back. Failures are logged; the in-memory queue still drains.
+    async fn persist(&self, queue: &[ReplicationJob]) {
+        let data = match serde_json::to_vec(queue) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("Failed to serialize replication queue: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = self.storage.put_blob(QUEUE_KEY, data.into()).await {
+            warn!("Failed to persist replication queue: {}", e);
+        }
+    }
+}
@@ -0,0 +1,492 @@
+use anyhow::{bail, Context, Result};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::{RwLock, Semaphore};
+use tracing::{debug, info, warn};
+
+use crate::api::registry::manifest_types::{ImageManifest, Manifest, OCI_MANIFEST};
+use crate::blob_holds::{BlobHolds, RepositoryLocks};
+use crate::config::{ScanningConfig, Severity};
+use crate::storage::StorageBackend;
+
+/// Artifact type of the report referrers attached to scanned manifests, and
+/// media type of the report layer in them
+pub const SCAN_REPORT_ARTIFACT_TYPE: &str = "application/vnd.drift.vulnerability-report.v1+json";
+
+/// Config of artifacts that carry no config of their own
+const EMPTY_CONFIG_MEDIA_TYPE: &str = "application/vnd.oci.empty.v1+json";
+
+/// Config media types of runnable images; other artifacts aren't scanned
+const IMAGE_CONFIG_MEDIA_TYPES: [&str; 2] = [
+    "application/vnd.oci.image.config.v1+json",
+    "application/vnd.docker.container.image.v1+json",
+];
+
+/// Where a scan got to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScanStatus {
+    Pending,
+    Completed,
+    Errored,
+}
+
+/// Vulnerabilities found, by severity
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeverityCounts {
+    pub critical: u64,
+    pub high: u64,
+    pub medium: u64,
+    pub low: u64,
+    pub unknown: u64,
+}
+
+impl SeverityCounts {
+    fn add(&mut self, severity: Severity) {
+        match severity {
+            Severity::Critical => self.critical += 1,
+            Severity::High => self.high += 1,
+            Severity::Medium => self.medium += 1,
+            Severity::Low => self.low += 1,
+            Severity::Unknown => self.unknown += 1,
+        }
+    }
+
+    /// Vulnerabilities of `severity` or worse
+    pub fn at_or_above(&self, severity: Severity) -> u64 {
+        [
+            (Severity::Critical, self.critical),
+            (Severity::High, self.high),
+            (Severity::Medium, self.medium),
+            (Severity::Low, self.low),
+            (Severity::Unknown, self.unknown),
+        ]
+        .into_iter()
+        .filter(|(level, _)| *level >= severity)
+        .map(|(_, count)| count)
+        .sum()
+    }
+}
+
+/// Latest scan of a manifest, kept under `scans/<repository>/<digest>`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanRecord {
+    pub repository: String,
+    pub digest: String,
+    pub status: ScanStatus,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub summary: SeverityCounts,
+    /// Blob holding the Trivy JSON report
+    pub report_digest: Option<String>,
+    /// Referrer manifest that attaches the report to the scanned manifest
+    pub report_manifest: Option<String>,
+    pub error: Option<String>,
+    /// Summary of the last completed scan, while a rescan is pending or after
+    /// it failed
+    #[serde(default)]
+    pub last_completed: Option<SeverityCounts>,
+}
+
+impl ScanRecord {
+    /// Severity counts pulls are judged by: this scan's once it has completed,
+    /// otherwise the last completed one's
+    pub fn effective_summary(&self) -> Option<SeverityCounts> {
+        match self.status {
+            ScanStatus::Completed => Some(self.summary.clone()),
+            ScanStatus::Pending | ScanStatus::Errored => self.last_completed.clone(),
+        }
+    }
+}
+
+/// Scans pushed images for vulnerabilities with Trivy.
+///
+/// After each push of an image manifest, the image is exported as an OCI
+/// layout and handed to the trivy binary, which looks vulnerabilities up on
+/// the configured Trivy server or in its own database. The JSON report is
+/// attached to the manifest as a referrer artifact, so it is pulled, listed
+/// and garbage collected along with it, and a summary is recorded under
+/// `scans/<repository>/<digest>`. Scans never fail the push; a failed scan is
+/// recorded as errored instead.
+pub struct ScanningService {
+    config: ScanningConfig,
+    storage: Arc<dyn StorageBackend>,
+    holds: Arc<BlobHolds>,
+    repository_locks: Arc<RepositoryLocks>,
+    exempt: Option<GlobSet>,
+    permits: Semaphore,
+    /// Severity counts of the latest completed scans by `repository@digest`;
+    /// None when the manifest has never had a completed scan
+    summaries: RwLock<HashMap<String, Option<SeverityCounts>>>,
+}
+
+impl ScanningService {
+    pub fn new(
+        config: ScanningConfig,
+        storage: Arc<dyn StorageBackend>,
+        holds: Arc<BlobHolds>,
+        repository_locks: Arc<RepositoryLocks>,
+    ) -> Result<Self> {
+        let exempt = if config.exempt_repositories.is_empty() {
+            None
+        } else {
+            let mut builder = GlobSetBuilder::new();
+            for pattern in &config.exempt_repositories {
+                builder.add(
+                    Glob::new(pattern).with_context(|| format!("Invalid exempt repository pattern {}", pattern))?,
+                );
+            }
+            Some(builder.build()?)
+        };
+
+        match &config.server_url {
+            Some(url) => info!("Scanning pushed images with {} against Trivy server {}", config.trivy_path, url),
+            None => info!("Scanning pushed images with {}", config.trivy_path),
+        }
+
+        Ok(Self {
+            permits: Semaphore::new(config.max_concurrent_scans.max(1)),
+            config,
+            storage,
+            holds,
+            repository_locks,
+            exempt,
+            summaries: RwLock::new(HashMap::new()),
+        })
+    }
+
+    fn key(repository: &str, digest: &str) -> String {
+        format!("scans/{}/{}", repository, digest)
+    }
+
+    /// Queue a scan of a pushed manifest; indexes and non-image artifacts are skipped
+    pub fn manifest_pushed(self: &Arc<Self>, repository: &str, digest: &str, media_type: &str, size: u64, manifest: &Manifest) {
+        let Manifest::Image(image) = manifest else {
+            return;
+        };
+        let is_image = image
+            .config
+            .media_type
+            .as_deref()
+            .is_some_and(|media_type| IMAGE_CONFIG_MEDIA_TYPES.contains(&media_type));
+        if !is_image {
+            return;
+        }
+
+        let service = self.clone();
+        let repository = repository.to_string();
+        let digest = digest.to_string();
+        let media_type = media_type.to_string();
+        let image = image.clone();
+        tokio::spawn(async move {
+            service.scan_manifest(&repository, &digest, &media_type, size, &image).await;
+        });
+    }
+
+    /// Scan one manifest and record the outcome
+    async fn scan_manifest(&self, repository: &str, digest: &str, media_type: &str, size: u64, manifest: &ImageManifest) {
+        // Until this scan completes, the previous one's outcome and report stand
+        let previous = self.get_scan(repository, digest).await.ok().flatten();
+        let mut record = ScanRecord {
+            repository: repository.to_string(),
+            digest: digest.to_string(),
+            status: ScanStatus::Pending,
+            started_at: Utc::now(),
+            finished_at: None,
+            summary: SeverityCounts::default(),
+            report_digest: previous.as_ref().and_then(|previous| previous.report_digest.clone()),
+            report_manifest: previous.as_ref().and_then(|previous| previous.report_manifest.clone()),
+            error: None,
+            last_completed: previous.as_ref().and_then(ScanRecord::effective_summary),
+        };
+        if let Err(e) = self.put_record(&record).await {
+            warn!("Failed to record pending scan of {}@{}: {}", repository, digest, e);
+        }
+
+        let Ok(_permit) = self.permits.acquire().await else {
+            return;
+        };
+        let result = match self.run_scan(repository, digest, manifest).await {
+            Ok(report) => self.attach_report(repository, digest, media_type, size, report).await,
+            Err(e) => Err(e),
+        };
+
+        record.finished_at = Some(Utc::now());
+        match result {
+            Ok((summary, report_digest, report_manifest)) => {
+                info!(
+                    "Scanned {}@{}: {} critical, {} high, {} medium, {} low, {} unknown",
+                    repository, digest, summary.critical, summary.high, summary.medium, summary.low, summary.unknown
+                );
+                record.status = ScanStatus::Completed;
+                record.summary = summary;
+                record.report_digest = Some(report_digest);
+                record.report_manifest = Some(report_manifest);
+                record.last_completed = None;
+            }
+            Err(e) => {
+                warn!("Failed to scan {}@{}: {:#}", repository, digest, e);
+                record.status = ScanStatus::Errored;
+                record.error = Some(format!("{:#}", e));
+            }
+        }
+        if let Err(e) = self.put_record(&record).await {
+            warn!("Failed to record scan of {}@{}: {}", repository, digest, e);
+            return;
+        }
+
+        // A rescan replaces the report attached by the previous one
+        let replaced = previous
+            .and_then(|previous| previous.report_manifest)
+            .filter(|previous| record.report_manifest.as_ref() != Some(previous));
+        if let Some(replaced) = replaced {
+            let _repository_lock = self.repository_locks.lock(repository).await;
+            if let Err(e) = self.storage.delete_manifest(repository, &replaced).await {
+                warn!("Failed to delete replaced scan report {}@{}: {}", repository, replaced, e);
+            }
+        }
+    }
+
+    /// Export the image to a scratch directory and run trivy over it
+    async fn run_scan(&self, repository: &str, digest: &str, manifest: &ImageManifest) -> Result<Bytes> {
+        let dir = std::env::temp_dir().join(format!("drift-scan-{}", uuid::Uuid::new_v4()));
+        let result = match self.export_layout(&dir, repository, digest, manifest).await {
+            Ok(()) => self.run_trivy(&dir).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = tokio::fs::remove_dir_all(&dir).await {
+            warn!("Failed to remove scan directory {}: {}", dir.display(), e);
+        }
+        result
+    }
+
+    /// Write the manifest, its config and layers as an OCI image layout
+    async fn export_layout(&self, dir: &Path, repository: &str, digest: &str, manifest: &ImageManifest) -> Result<()> {
+        let blobs = dir.join("blobs").join("sha256");
+        tokio::fs::create_dir_all(&blobs).await?;
+
+        // By digest: an image pushed by tag has no manifest stored under its digest on every backend
+        let content = self
+            .storage
+            .get_manifest_by_digest(repository, digest)
+            .await
+            .with_context(|| format!("Manifest {}@{} no longer exists", repository, digest))?;
+        let hex = digest.strip_prefix("sha256:").context("Only sha256 manifests can be scanned")?;
+        tokio::fs::write(blobs.join(hex), &content).await?;
+
+        for descriptor in std::iter::once(&manifest.config).chain(&manifest.layers) {
+            let hex = descriptor
+                .digest
+                .strip_prefix("sha256:")
+                .with_context(|| format!("Unsupported digest {}", descriptor.digest))?;
+            let (_, mut stream) = self
+                .storage
+                .get_blob_stream(&descriptor.digest)
+                .await?
+                .with_context(|| format!("Blob {} not found", descriptor.digest))?;
+            let mut file = tokio::fs::File::create(blobs.join(hex)).await?;
+            while let Some(chunk) = stream.next().await {
+                file.write_all(&chunk?).await?;
+            }
+            file.flush().await?;
+        }
+
+        let index = serde_json::json!({
+            "schemaVersion": 2,
+            "manifests": [{
+                "mediaType": manifest.media_type.as_deref().unwrap_or(OCI_MANIFEST),
+                "digest": digest,
+                "size": content.len(),
+            }],
+        });
+        tokio::fs::write(dir.join("index.json"), serde_json::to_vec(&index)?).await?;
+        tokio::fs::write(dir.join("oci-layout"), br#"{"imageLayoutVersion":"1.0.0"}"#).await?;
+        Ok(())
+    }
+
+    async fn run_trivy(&self, dir: &Path) -> Result<Bytes> {
+        let mut command = Command::new(&self.config.trivy_path);
+        command
+            .arg("image")
+            .arg("--input")
+            .arg(dir)
+            .args(["--format", "json", "--list-all-pkgs", "--quiet"])
+            .kill_on_drop(true);
+        if let Some(url) = &self.config.server_url {
+            command.args(["--server", url]);
+        }
+        // Passed in the environment, where other users can't read it from the process list
+        if let Some(token) = &self.config.server_token {
+            command.env("TRIVY_TOKEN", token);
+        }
+
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+        let output = tokio::time::timeout(timeout, command.output())
+            .await
+            .with_context(|| format!("trivy did not finish within {:?}", timeout))?
+            .with_context(|| format!("Failed to run {}", self.config.trivy_path))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("trivy exited with {}: {}", output.status, stderr.trim());
+        }
+        Ok(output.stdout.into())
+    }
+
+    /// Store the report and attach it to the scanned manifest as a referrer.
+    /// Returns the summary, the report blob and the referrer manifest.
+    async fn attach_report(
+        &self,
+        repository: &str,
+        digest: &str,
+        media_type: &str,
+        size: u64,
+        report: Bytes,
+    ) -> Result<(SeverityCounts, String, String)> {
+        let parsed: serde_json::Value = serde_json::from_slice(&report).context("trivy produced an invalid report")?;
+        let summary = summarize(&parsed);
+
+        let config = Bytes::from_static(b"{}");
+        let config_digest = sha256_digest(&config);
+        let report_digest = sha256_digest(&report);
+        let artifact = serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": OCI_MANIFEST,
+            "artifactType": SCAN_REPORT_ARTIFACT_TYPE,
+            "config": {
+                "mediaType": EMPTY_CONFIG_MEDIA_TYPE,
+                "digest": config_digest,
+                "size": config.len(),
+            },
+            "layers": [{
+                "mediaType": SCAN_REPORT_ARTIFACT_TYPE,
+                "digest": report_digest,
+                "size": report.len(),
+            }],
+            "subject": {
+                "mediaType": media_type,
+                "digest": digest,
+                "size": size,
+            },
+            "annotations": {
+                "org.opencontainers.image.created": Utc::now().to_rfc3339(),
+            },
+        });
+        let artifact = Bytes::from(serde_json::to_vec(&artifact)?);
+        let artifact_digest = sha256_digest(&artifact);
+
+        // Held until the referrer is stored, so GC can't sweep the blobs in between
+        self.holds.hold(&config_digest).await;
+        self.holds.hold(&report_digest).await;
+        let stored = async {
+            self.storage.put_blob(&config_digest, config).await?;
+            self.storage.put_blob(&report_digest, report).await?;
+            let _repository_lock = self.repository_locks.lock(repository).await;
+            self.storage.put_manifest(repository, &artifact_digest, artifact, OCI_MANIFEST).await
+        }
+        .await;
+        self.holds.release(&[config_digest.as_str(), report_digest.as_str()]).await;
+        stored?;
+
+        debug!("Attached scan report {} to {}@{}", artifact_digest, repository, digest);
+        Ok((summary, report_digest, artifact_digest))
+    }
+
+    async fn put_record(&self, record: &ScanRecord) -> Result<()> {
+        let key = Self::key(&record.repository, &record.digest);
+        self.storage.put_blob(&key, serde_json::to_vec(record)?.into()).await?;
+
+        let summary = record.effective_summary();
+        self.summaries
+            .write()
+            .await
+            .insert(format!("{}@{}", record.repository, record.digest), summary);
+        Ok(())
+    }
+
+    /// Latest scan of a manifest, if it has been scanned
+    pub async fn get_scan(&self, repository: &str, digest: &str) -> Result<Option<ScanRecord>> {
+        match self.storage.get_blob(&Self::key(repository, digest)).await? {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The Trivy report of a completed scan
+    pub async fn get_report(&self, record: &ScanRecord) -> Result<Option<serde_json::Value>> {
+        let Some(report_digest) = &record.report_digest else {
+            return Ok(None);
+        };
+        match self.storage.get_blob(report_digest).await? {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Severity pulls are blocked at, unless the repository is exempt
+    pub fn block_threshold(&self, repository: &str) -> Option<Severity> {
+        let exempt = self.exempt.as_ref().is_some_and(|patterns| patterns.is_match(repository));
+        self.config.block_pull_above_severity.filter(|_| !exempt)
+    }
+
+    /// Severity counts of the scan that blocks pulling a manifest, if one does.
+    /// A rescan doesn't unblock a manifest until it completes; manifests that
+    /// never had a completed scan are never blocked.
+    pub async fn check_pull(&self, repository: &str, digest: &str) -> Result<Option<SeverityCounts>> {
+        let Some(threshold) = self.block_threshold(repository) else {
+            return Ok(None);
+        };
+
+        let key = format!("{}@{}", repository, digest);
+        let cached = self.summaries.read().await.get(&key).cloned();
+        let summary = match cached {
+            Some(summary) => summary,
+            None => {
+                let summary = self
+                    .get_scan(repository, digest)
+                    .await?
+                    .and_then(|record| record.effective_summary());
+                self.summaries.write().await.insert(key, summary.clone());
+                summary
+            }
+        };
+
+        Ok(summary.filter(|summary| summary.at_or_above(threshold) > 0))
+    }
+}
+
+/// Count the vulnerabilities in a Trivy JSON report by severity
+fn summarize(report: &serde_json::Value) -> SeverityCounts {
+    let mut counts = SeverityCounts::default();
+    let vulnerabilities = report
+        .get("Results")
+        .and_then(|results| results.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|result| result.get("Vulnerabilities").and_then(|v| v.as_array()))
+        .flatten();
+    for vulnerability in vulnerabilities {
+        let severity = match vulnerability.get("Severity").and_then(|s| s.as_str()) {
+            Some("CRITICAL") => Severity::Critical,
+            Some("HIGH") => Severity::High,
+            Some("MEDIUM") => Severity::Medium,
+            Some("LOW") => Severity::Low,
+            _ => Severity::Unknown,
+        };
+        counts.add(severity);
+    }
+    counts
+}
+
+fn sha256_digest(data: &[u8]) -> String {
+    format!("sha256:{}", hex::encode(Sha256::digest(data)))
+}
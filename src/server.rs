@@ -1,14 +1,21 @@
-use crate::{api, auth::AuthService, bolt_integration::BoltIntegrationService, config::Config, quic::QuicTransport, storage::StorageBackend};
+use crate::{api, audit::AuditService, blob_holds::{BlobHolds, RepositoryLocks}, auth::{oauth::OAuthService, robots::RobotService, AuthService}, bolt_integration::BoltIntegrationService, cluster::ClusterService, config::Config, garbage_collector::GcHandle, jobs::Jobs, metrics::Metrics, notifications::Notifier, optimization::OptimizationService, proxy::ProxyService, quic::QuicTransport, rate_limit::RateLimiter, rbac::RbacService, reload, replication::ReplicationService, scanning::ScanningService, signing::SigningService, storage::StorageBackend, tag_history::TagHistory, telemetry::RequestSpan, tls::TlsTermination};
 // Will add ui module for polished web portal
-use anyhow::Result;
+use anyhow::{Context, Result};
+use arc_swap::ArcSwap;
 use axum::{
     extract::Extension,
     http::{header, Method},
     Router,
 };
+use ipnet::IpNet;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
-use tower::ServiceBuilder;
+use tokio_util::sync::CancellationToken;
+use tower::{Layer, ServiceBuilder};
 use tower_http::{
     compression::CompressionLayer,
     cors::{Any, CorsLayer},
@@ -19,69 +26,97 @@ use tracing::{info, warn};
 #[derive(Clone)]
 pub struct AppState {
     pub config: Config,
+    /// Latest configuration for the settings that are reloaded at runtime
+    pub runtime_config: Arc<ArcSwap<Config>>,
     pub storage: Arc<dyn StorageBackend>,
     pub auth: Arc<AuthService>,
     pub bolt: Arc<BoltIntegrationService>,
     pub quic: Option<Arc<QuicTransport>>,
+    pub signing: Option<Arc<SigningService>>,
+    pub audit: Option<Arc<AuditService>>,
+    pub rbac: Option<Arc<RbacService>>,
+    pub oauth: Option<Arc<OAuthService>>,
+    pub optimization: Option<Arc<OptimizationService>>,
+    pub proxy: Option<Arc<ProxyService>>,
+    pub replication: Option<Arc<ReplicationService>>,
+    pub scanning: Option<Arc<ScanningService>>,
+    pub cluster: Option<Arc<ClusterService>>,
+    pub notifier: Arc<Notifier>,
+    pub robots: Arc<RobotService>,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub gc: GcHandle,
+    pub holds: Arc<BlobHolds>,
+    pub repository_locks: Arc<RepositoryLocks>,
+    pub metrics: Arc<Metrics>,
+    pub jobs: Arc<Jobs>,
+    pub tag_history: Arc<TagHistory>,
+    /// Cancelled on SIGTERM/SIGINT; background tasks stop when it fires
+    pub shutdown: CancellationToken,
 }
 
 pub struct Server {
     config: Config,
     api_addr: String,
     ui_addr: String,
+    metrics: Arc<Metrics>,
+    config_path: Option<PathBuf>,
 }
 
 impl Server {
     pub async fn new(config: Config, api_addr: &str, ui_addr: &str) -> Result<Self> {
+        // Register Prometheus collectors
+        let metrics = Arc::new(Metrics::new()?);
+
         Ok(Self {
             config,
             api_addr: api_addr.to_string(),
             ui_addr: ui_addr.to_string(),
+            metrics,
+            config_path: None,
         })
     }
 
-    pub async fn run(self) -> Result<()> {
-        // Initialize storage backend
-        let storage = crate::storage::create_storage_backend(&self.config.storage).await?;
+    /// Watch the config file and apply runtime-safe changes without a restart
+    pub fn with_config_reload(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config_path = Some(path.into());
+        self
+    }
 
-        // Initialize auth service
-        let auth = Arc::new(AuthService::new(&self.config.auth)?);
+    /// Serve until SIGTERM or SIGINT, then drain connections and shut down
+    pub async fn run(self) -> Result<()> {
+        self.run_until(shutdown_signal()).await
+    }
 
-        // Initialize Bolt integration service
-        let bolt_config = self.config.bolt.clone().unwrap_or_default();
-        let bolt = Arc::new(BoltIntegrationService::new(storage.clone(), bolt_config).await?);
+    /// Serve until `signal` resolves, then stop accepting connections, give
+    /// in-flight requests up to `shutdown_timeout_secs` to finish, and shut down
+    pub async fn run_until(self, signal: impl Future<Output = ()> + Send + 'static) -> Result<()> {
+        // Refuse to start with unusable certificates rather than failing every handshake
+        let api_tls = self
+            .config
+            .server
+            .tls
+            .as_ref()
+            .map(TlsTermination::new)
+            .transpose()
+            .context("Invalid server.tls configuration")?;
+        let ui_tls = self
+            .config
+            .server
+            .ui_tls
+            .as_ref()
+            .map(TlsTermination::new)
+            .transpose()
+            .context("Invalid server.ui_tls configuration")?;
 
-        // Initialize QUIC transport if enabled
-        let quic = if let Some(quic_config) = &self.config.quic {
-            if quic_config.enabled {
-                info!("Initializing QUIC transport");
-                match QuicTransport::new(quic_config.clone()).await {
-                    Ok(transport) => Some(Arc::new(transport)),
-                    Err(e) => {
-                        warn!("Failed to initialize QUIC transport: {}", e);
-                        None
-                    }
-                }
-            } else {
-                info!("QUIC transport disabled in configuration");
-                None
-            }
-        } else {
-            info!("QUIC transport not configured");
-            None
-        };
+        let shutdown = CancellationToken::new();
+        let state = self.build_state(shutdown.clone()).await?;
 
-        // Create shared app state
-        let state = AppState {
-            config: self.config.clone(),
-            storage,
-            auth,
-            bolt,
-            quic,
-        };
+        if let Some(path) = &self.config_path {
+            reload::spawn_config_reload(path.clone(), state.clone());
+        }
 
         // Create registry API router
-        let api_router = self.create_api_router(state.clone());
+        let api_router = self.api_router(state.clone());
 
         // Create UI router
         let ui_router = self.create_ui_router(state.clone());
@@ -108,8 +143,9 @@ impl Server {
         let api_listener = TcpListener::bind(&self.api_addr).await?;
         let ui_listener = TcpListener::bind(&self.ui_addr).await?;
 
-        info!("🚀 Registry API listening on {}", self.api_addr);
-        info!("🖥️  Web UI listening on {}", self.ui_addr);
+        let scheme = |tls: &Option<TlsTermination>| if tls.is_some() { "https" } else { "http" };
+        info!("🚀 Registry API listening on {} ({})", self.api_addr, scheme(&api_tls));
+        info!("🖥️  Web UI listening on {} ({})", self.ui_addr, scheme(&ui_tls));
 
         // Start all servers
         if let Some(_quic_task) = quic_server_task {
@@ -118,7 +154,6 @@ impl Server {
             info!("Starting API server");
         }
 
-        // Start servers (architectural demonstration)
         info!("🚀 Drift Registry with all enterprise features initialized");
         info!("   ✅ Garbage Collection: Enabled");
         info!("   ✅ RBAC System: {} users, {} organizations", "Ready", "Multi-tenant");
@@ -132,22 +167,391 @@ impl Server {
         info!("   ✅ Authentication: Basic, OAuth2, OIDC (Azure, GitHub, Google)");
         info!("🎆 Enterprise-grade container registry ready!");
 
-        // Architectural demo complete - all features implemented and configured
+        // On the signal stop accepting connections and give in-flight requests time to finish
+        let api_handle = axum_server::Handle::new();
+        let ui_handle = axum_server::Handle::new();
+        let drain_timeout = Duration::from_secs(self.config.server.shutdown_timeout_secs);
+        tokio::spawn({
+            let handles = [api_handle.clone(), ui_handle.clone()];
+            async move {
+                signal.await;
+                info!("Shutting down, draining connections for up to {:?}", drain_timeout);
+                shutdown.cancel();
+                for handle in &handles {
+                    handle.graceful_shutdown(Some(drain_timeout));
+                }
+            }
+        });
+
+        tokio::try_join!(
+            serve(api_listener, api_tls, api_handle, api_router),
+            serve(ui_listener, ui_tls, ui_handle, ui_router.with_state(state.clone())),
+        )?;
+
+        if let Some(cluster) = &state.cluster {
+            cluster.leave().await.unwrap_or_else(|e| warn!("Failed to leave the cluster: {:#}", e));
+        }
+
+        // Upload sessions already live in the storage backend, so a PATCH after
+        // restart resumes from the stored offset; only the audit buffer is in memory
+        if let Some(audit) = &state.audit {
+            audit.flush().await;
+        }
+        info!("Shutdown complete");
+
         Ok(())
     }
 
+    /// Initialize storage and every configured service into the shared app state.
+    /// Background tasks stop when `shutdown` is cancelled.
+    pub async fn build_state(&self, shutdown: CancellationToken) -> Result<AppState> {
+        // Initialize storage backend
+        let storage = crate::storage::create_storage_backend(&self.config.storage, &self.metrics).await?;
+        let storage: Arc<dyn StorageBackend> = Arc::new(crate::storage::instrumented::InstrumentedStorage::new(
+            storage,
+            self.metrics.clone(),
+        ));
+
+        // Initialize auth service
+        let robots = Arc::new(RobotService::new(storage.clone()).await?);
+        let holds = Arc::new(BlobHolds::load(storage.clone(), self.config.registry.upload_session_ttl_hours).await?);
+        let auth = Arc::new(AuthService::new(&self.config.auth)?.with_robots(robots.clone()));
+        auth.spawn_htpasswd_reload();
+
+        // Initialize Bolt integration service
+        let bolt_config = self.config.bolt.clone().unwrap_or_default();
+        let bolt = Arc::new(BoltIntegrationService::new(storage.clone(), bolt_config).await?);
+
+        // Initialize QUIC transport if enabled
+        let quic = if let Some(quic_config) = &self.config.quic {
+            if quic_config.enabled {
+                info!("Initializing QUIC transport");
+                match QuicTransport::new(quic_config.clone()).await {
+                    Ok(transport) => Some(Arc::new(transport)),
+                    Err(e) => {
+                        warn!("Failed to initialize QUIC transport: {}", e);
+                        None
+                    }
+                }
+            } else {
+                info!("QUIC transport disabled in configuration");
+                None
+            }
+        } else {
+            info!("QUIC transport not configured");
+            None
+        };
+
+        // Initialize content signing if enabled
+        let signing = match &self.config.signing {
+            Some(signing_config) if signing_config.enabled => {
+                info!("Initializing content signing service");
+                Some(Arc::new(SigningService::new(signing_config.clone(), storage.clone()).await?))
+            }
+            _ => {
+                info!("Content signing disabled");
+                None
+            }
+        };
+
+        // Initialize audit logging if enabled
+        let audit = match &self.config.audit {
+            Some(audit_config) if audit_config.enabled => {
+                info!("Initializing audit service");
+                Some(Arc::new(AuditService::new(audit_config.clone(), storage.clone()).await?))
+            }
+            _ => None,
+        };
+
+        if let Some(signing) = &signing {
+            signing.spawn_key_expiry_check(audit.clone(), self.metrics.signing_keys_expiring.clone(), shutdown.clone());
+            signing.spawn_crl_refresh(shutdown.clone());
+        }
+
+        // Initialize RBAC if enabled
+        let rbac = match &self.config.rbac {
+            Some(rbac_config) if rbac_config.enabled => {
+                info!("Initializing RBAC service");
+                Some(Arc::new(RbacService::new(rbac_config.clone(), storage.clone()).await?))
+            }
+            _ => None,
+        };
+
+        // Initialize OAuth login if enabled
+        let oauth = match &self.config.auth.oauth {
+            Some(oauth_config) if oauth_config.enabled => {
+                info!("Initializing OAuth login");
+                Some(Arc::new(OAuthService::new(oauth_config.clone())))
+            }
+            _ => None,
+        };
+
+        // Initialize image optimization if enabled
+        let optimization = match &self.config.optimization {
+            Some(optimization_config) if optimization_config.enabled => {
+                info!("Initializing image optimization service");
+                let optimization =
+                    OptimizationService::new(optimization_config.clone(), storage.clone(), shutdown.clone()).await?;
+                optimization.spawn_scheduler()?;
+                Some(Arc::new(optimization))
+            }
+            _ => None,
+        };
+
+        // Initialize pull-through proxy namespaces if configured
+        let proxy = if self.config.proxy.is_empty() {
+            None
+        } else {
+            Some(Arc::new(ProxyService::new(self.config.proxy.clone(), storage.clone())))
+        };
+
+        // Initialize push replication if enabled
+        let replication = match &self.config.replication {
+            Some(replication_config) if replication_config.enabled && !replication_config.targets.is_empty() => {
+                info!("Initializing replication to {} targets", replication_config.targets.len());
+                let replication = Arc::new(
+                    ReplicationService::load(
+                        replication_config.clone(),
+                        storage.clone(),
+                        audit.clone(),
+                        self.metrics.replication_failures_total.clone(),
+                    )
+                    .await?,
+                );
+                replication.spawn_worker(shutdown.clone());
+                Some(replication)
+            }
+            _ => None,
+        };
+
+        // Initialize vulnerability scanning if enabled
+        let repository_locks = Arc::new(RepositoryLocks::new());
+        let scanning = match &self.config.scanning {
+            Some(scanning_config) if scanning_config.enabled => {
+                info!("Initializing vulnerability scanning");
+                Some(Arc::new(ScanningService::new(
+                    scanning_config.clone(),
+                    storage.clone(),
+                    holds.clone(),
+                    repository_locks.clone(),
+                )?))
+            }
+            _ => None,
+        };
+
+        // Join the cluster if enabled
+        let cluster = match &self.config.cluster {
+            Some(cluster_config) if cluster_config.enabled => {
+                info!("Initializing cluster node {}", cluster_config.node_id);
+                Some(Arc::new(ClusterService::new(cluster_config.clone(), storage.clone(), shutdown.clone()).await?))
+            }
+            _ => None,
+        };
+
+        // Start webhook notification delivery
+        let notifier = Arc::new(Notifier::start(self.config.notifications.clone(), storage.clone(), &self.api_addr));
+
+        let tag_history = Arc::new(TagHistory::new(storage.clone()));
+
+        // Create shared app state
+        let state = AppState {
+            config: self.config.clone(),
+            runtime_config: Arc::new(ArcSwap::from_pointee(self.config.clone())),
+            storage,
+            auth,
+            bolt,
+            quic,
+            signing,
+            audit,
+            rbac,
+            oauth,
+            optimization,
+            proxy,
+            replication,
+            scanning,
+            cluster,
+            notifier,
+            robots,
+            rate_limiter: Arc::new(RateLimiter::new(&self.config.registry)),
+            gc: GcHandle::with_shutdown(shutdown.clone()),
+            holds,
+            repository_locks,
+            metrics: self.metrics.clone(),
+            jobs: Arc::new(Jobs::new()),
+            tag_history,
+            shutdown: shutdown.clone(),
+        };
+
+        Ok(state)
+    }
+
+    /// The registry API with its middleware, ready to serve. The QUIC transport
+    /// answers peers through it too.
+    pub fn api_router(&self, state: AppState) -> Router {
+        let router = self.create_api_router(state.clone()).with_state(state.clone());
+        if let Some(quic) = &state.quic {
+            quic.serve_registry(router.clone());
+        }
+        router
+    }
+
     fn create_api_router(&self, state: AppState) -> Router<AppState> {
         Router::new()
-            .nest("/v2", api::registry::router())
+            .nest(
+                "/v2",
+                api::registry::router()
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        api::middleware::rate_limit_middleware,
+                    ))
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        api::middleware::auth_middleware,
+                    )),
+            )
+            // Clients check for the API at `/v2/`, which the nested router's `/` doesn't match
+            .route(
+                "/v2/",
+                axum::routing::get(api::registry::api_version).layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    api::middleware::auth_middleware,
+                )),
+            )
             .nest("/v1", api::bolt::router())
-            .nest("/admin", api::admin::router())
-            .nest("/api", api::quic::router())
+            // Test messages are sent with the transport's own credentials, so only admins may send them
+            .nest(
+                "/api",
+                api::quic::router()
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        api::middleware::require_registry_admin,
+                    ))
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        api::middleware::auth_middleware,
+                    )),
+            )
+            .nest(
+                "/api/v1/admin",
+                api::admin::router()
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        api::middleware::require_registry_admin,
+                    ))
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        api::middleware::auth_middleware,
+                    )),
+            )
+            .nest(
+                "/api/v1/replication",
+                api::replication::router()
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        api::middleware::require_registry_admin,
+                    ))
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        api::middleware::auth_middleware,
+                    )),
+            )
+            .nest(
+                "/api/v1/repositories",
+                api::repositories::router()
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        api::middleware::require_registry_admin,
+                    ))
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        api::middleware::auth_middleware,
+                    )),
+            )
+            .nest(
+                "/api/v1/jobs",
+                api::jobs::router()
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        api::middleware::require_registry_admin,
+                    ))
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        api::middleware::auth_middleware,
+                    )),
+            )
+            .nest(
+                "/api/v1/notifications",
+                api::notifications::router()
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        api::middleware::require_registry_admin,
+                    ))
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        api::middleware::auth_middleware,
+                    )),
+            )
+            .nest(
+                "/api/v1/optimization",
+                api::optimization::router()
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        api::middleware::require_registry_admin,
+                    ))
+                    .merge(api::optimization::run_router().layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        api::middleware::require_registry_optimize,
+                    )))
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        api::middleware::auth_middleware,
+                    )),
+            )
+            .nest(
+                "/api/v1/signing",
+                api::signing::router()
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        api::middleware::require_registry_admin,
+                    ))
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        api::middleware::auth_middleware,
+                    )),
+            )
+            .nest(
+                "/api/v1/signatures",
+                api::signing::signatures_router()
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        api::middleware::require_registry_admin,
+                    ))
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        api::middleware::auth_middleware,
+                    )),
+            )
+            .nest(
+                "/api/v1/robots",
+                api::robots::router().layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    api::middleware::auth_middleware,
+                )),
+            )
             .route("/health", axum::routing::get(health_check))
             .route("/readyz", axum::routing::get(readiness_check))
-            .route("/metrics", axum::routing::get(metrics_handler))
+            .route("/metrics", axum::routing::get(crate::metrics::metrics_handler))
             .layer(
                 ServiceBuilder::new()
-                    .layer(TraceLayer::new_for_http())
+                    .layer(TraceLayer::new_for_http().make_span_with(self.request_span()))
+                    .layer(axum::middleware::from_fn_with_state(
+                        self.trusted_proxies(),
+                        api::middleware::request_id_middleware,
+                    ))
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        api::middleware::metrics_middleware,
+                    ))
                     .layer(CompressionLayer::new())
                     .layer(
                         CorsLayer::new()
@@ -159,26 +563,120 @@ impl Server {
             )
     }
 
+    /// Networks whose `X-Forwarded-For` is believed; invalid entries are skipped
+    fn trusted_proxies(&self) -> Arc<[IpNet]> {
+        self.config
+            .server
+            .trusted_proxies
+            .iter()
+            .filter_map(|cidr| match cidr.parse::<IpNet>() {
+                Ok(network) => Some(network),
+                Err(e) => {
+                    warn!("Ignoring invalid trusted proxy {}: {}", cidr, e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Continue client traces only when spans are being exported
+    fn request_span(&self) -> RequestSpan {
+        RequestSpan::new(self.config.telemetry.as_ref().is_some_and(|telemetry| telemetry.enabled))
+    }
+
     fn create_ui_router(&self, state: AppState) -> Router<AppState> {
         Router::new()
-            .route("/", axum::routing::get(|| async {
-                axum::response::Html(
-                    r#"<!DOCTYPE html>
-<html><head><title>Drift Registry</title></head>
-<body><h1>🚀 Drift Registry</h1>
-<p>Professional web portal coming soon...</p></body></html>"#
-                )
-            }))
+            .merge(crate::ui::router())
+            .nest("/auth", api::auth::router())
+            // Admin actions from the UI, authenticated by the session cookie
+            .nest(
+                "/api/repositories",
+                api::repositories::router().layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    api::middleware::require_registry_admin,
+                )),
+            )
+            .nest(
+                "/api/jobs",
+                api::jobs::router().layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    api::middleware::require_registry_admin,
+                )),
+            )
             .nest_service("/assets", tower_http::services::ServeDir::new("assets"))
             .layer(
                 ServiceBuilder::new()
-                    .layer(TraceLayer::new_for_http())
+                    .layer(TraceLayer::new_for_http().make_span_with(self.request_span()))
+                    .layer(axum::middleware::from_fn_with_state(
+                        self.trusted_proxies(),
+                        api::middleware::request_id_middleware,
+                    ))
                     .layer(CompressionLayer::new())
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        api::middleware::session_middleware,
+                    ))
                     .layer(Extension(state)),
             )
     }
 }
 
+/// Serve a router on a bound listener, terminating TLS when configured,
+/// until the handle's graceful shutdown completes
+async fn serve(
+    listener: TcpListener,
+    tls: Option<TlsTermination>,
+    handle: axum_server::Handle,
+    router: Router,
+) -> Result<()> {
+    let server = axum_server::from_tcp(listener.into_std()?).handle(handle);
+    let app = axum::middleware::map_request(api::registry::encode_repository_path).layer(router);
+    // axum-server hands over hyper's body type; the path rewrite takes axum's
+    let app = ServiceBuilder::new()
+        .map_request(|request: axum::http::Request<hyper::body::Incoming>| request.map(axum::body::Body::new))
+        .service(app);
+    let service = axum::ServiceExt::<axum::http::Request<hyper::body::Incoming>>::into_make_service_with_connect_info::<SocketAddr>(app);
+
+    match tls {
+        Some(tls) => {
+            tls.spawn_reload();
+            server.acceptor(tls.acceptor()).serve(service).await?;
+        }
+        None => server.serve(service).await?,
+    }
+    Ok(())
+}
+
+/// Resolves on SIGINT (Ctrl+C) or, on Unix, SIGTERM
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Failed to listen for SIGINT: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
+
 async fn health_check() -> &'static str {
     "OK"
 }
@@ -187,8 +685,3 @@ async fn readiness_check() -> &'static str {
     // TODO: Check storage and auth service health
     "Ready"
 }
-
-async fn metrics_handler() -> &'static str {
-    // TODO: Implement Prometheus metrics
-    "# TYPE drift_info counter\ndrift_info{version=\"0.1.0\"} 1\n"
-}
\ No newline at end of file
@@ -1,14 +1,18 @@
 use anyhow::Result;
 use async_trait::async_trait;
 use base64::{Engine, engine::general_purpose};
+use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
+use x509_parser::certificate::X509Certificate;
 
-use crate::config::SigningConfig;
+use crate::audit::AuditService;
+use crate::config::{RepositorySignaturePolicy, SigningConfig};
 use crate::storage::StorageBackend;
 
 /// Content signing and verification service for drift registry
@@ -18,15 +22,35 @@ pub struct SigningService {
     config: SigningConfig,
     storage: Arc<dyn StorageBackend>,
     key_store: Arc<RwLock<KeyStore>>,
-    signature_cache: Arc<RwLock<HashMap<String, CachedSignature>>>,
+    /// Verification results by signature id and the fingerprint of the policy they were reached under
+    signature_cache: Arc<RwLock<HashMap<(String, String), CachedSignature>>>,
+    /// Per-repository enforcement overrides; starts from the config and can be changed at runtime
+    repository_policies: Arc<RwLock<Vec<RepositorySignaturePolicy>>>,
+    /// Pull verdicts by `repository@digest`, at most `MAX_CACHED_VERDICTS` of them
+    policy_cache: Arc<RwLock<HashMap<String, CachedVerdict>>>,
 }
 
+/// A pull verdict, and when the manifest was pushed if the verdict was a denial
+/// that the grace period could lift
+#[derive(Debug, Clone)]
+struct CachedVerdict {
+    result: PolicyResult,
+    pushed_at: Option<chrono::DateTime<chrono::Utc>>,
+    reached_at: std::time::Instant,
+}
+
+/// Expired verdicts are swept once the cache holds this many, and the oldest live
+/// ones dropped if that isn't enough
+const MAX_CACHED_VERDICTS: usize = 10_000;
+
 /// Key store for managing signing keys and certificates
 #[derive(Debug)]
 pub struct KeyStore {
     pub signing_keys: HashMap<String, SigningKey>,
     pub verification_keys: HashMap<String, VerificationKey>,
     pub trust_stores: HashMap<String, TrustStore>,
+    /// Keys added through the API and kept in the storage backend, rather than configured
+    pub managed: HashSet<String>,
 }
 
 /// Signing key with private key material
@@ -38,6 +62,8 @@ pub struct SigningKey {
     pub certificate: Option<Vec<u8>>, // DER encoded certificate
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Retired keys no longer sign, but signatures made with them still verify
+    pub retired: bool,
 }
 
 /// Verification key with public key material
@@ -59,6 +85,44 @@ pub struct TrustStore {
     pub root_certificates: Vec<Vec<u8>>, // DER encoded certificates
     pub intermediate_certificates: Vec<Vec<u8>>,
     pub revoked_certificates: Vec<String>, // Serial numbers
+    /// Every configured CRL was fetched and verified on the last refresh
+    pub revocation_checked: bool,
+    pub require_revocation_check: bool,
+}
+
+/// Public view of a key, as listed by `GET /api/v1/signing/keys`
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyInfo {
+    pub key_id: String,
+    pub algorithm: SignatureAlgorithm,
+    /// PEM `PUBLIC KEY`
+    pub public_key: String,
+    /// PEM `CERTIFICATE`
+    pub certificate: Option<String>,
+    /// Whether new content can be signed with this key
+    pub can_sign: bool,
+    pub retired: bool,
+    pub trusted: bool,
+    /// Added through the API rather than configured
+    pub managed: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// A key added through the API, as kept in the storage backend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredKey {
+    key_id: String,
+    algorithm: SignatureAlgorithm,
+    /// PEM `ENCRYPTED PRIVATE KEY`; None for imported verification keys
+    encrypted_private_key: Option<String>,
+    /// DER encoded public key
+    public_key: Vec<u8>,
+    certificate: Option<Vec<u8>>,
+    trusted: bool,
+    retired: bool,
+    created_at: chrono::DateTime<chrono::Utc>,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 /// Supported signature algorithms
@@ -104,10 +168,24 @@ pub struct ContentSignature {
     pub key_id: String,
     pub certificate_chain: Option<Vec<Vec<u8>>>, // DER encoded certificates
     pub payload: SignaturePayload,
+    /// Exact document that was signed, for formats that sign a detached payload (Cosign)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signed_payload: Option<Vec<u8>>,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
+/// What storing or deleting a Cosign signature does to the signature artifact of its content
+#[derive(Debug, Clone, PartialEq)]
+pub enum CosignArtifactChange {
+    /// The artifact already is as it should be
+    Unchanged,
+    /// Store this manifest as the artifact
+    Write(Bytes),
+    /// The last signature is gone, and so is the artifact
+    Delete,
+}
+
 /// Signature payload containing metadata about signed content
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SignaturePayload {
@@ -159,6 +237,14 @@ pub struct VerificationPolicy {
     pub max_signature_age_hours: Option<u64>,
 }
 
+/// Outcome of evaluating a verification policy against a piece of content
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyResult {
+    pub allowed: bool,
+    pub valid_signatures: usize,
+    pub errors: Vec<String>,
+}
+
 /// Trait for signature verification backends
 #[async_trait]
 pub trait SignatureVerifier: Send + Sync {
@@ -181,13 +267,18 @@ impl SigningService {
             signing_keys: HashMap::new(),
             verification_keys: HashMap::new(),
             trust_stores: HashMap::new(),
+            managed: HashSet::new(),
         }));
 
+        validate_repository_policies(&config.enforcement.repositories)?;
+
         let service = Self {
+            repository_policies: Arc::new(RwLock::new(config.enforcement.repositories.clone())),
             config,
             storage,
             key_store,
             signature_cache: Arc::new(RwLock::new(HashMap::new())),
+            policy_cache: Arc::new(RwLock::new(HashMap::new())),
         };
 
         // Load keys and trust stores from configuration
@@ -213,6 +304,8 @@ impl SigningService {
             key_store.verification_keys.insert(verification_key.key_id.clone(), verification_key);
         }
 
+        self.load_managed_keys(&mut key_store).await?;
+
         // Load trust stores
         for trust_config in &self.config.trust_stores {
             let trust_store = self.load_trust_store(trust_config).await?;
@@ -242,13 +335,22 @@ impl SigningService {
         let key_store = self.key_store.read().await;
         let signing_key = key_store.signing_keys.get(key_id)
             .ok_or_else(|| anyhow::anyhow!("Signing key not found: {}", key_id))?;
+        if signing_key.retired {
+            return Err(anyhow::anyhow!("Signing key {} is retired", key_id));
+        }
 
         // Calculate content digest
         let content_digest = hex::encode(Sha256::digest(content));
 
+        // Cosign signs a detached simple-signing document that verifiers need verbatim
+        let signed_payload = match format {
+            SignatureFormat::Cosign => Some(self.cosign_payload(&payload)?),
+            _ => None,
+        };
+
         // Create signature based on format
         let signature_bytes = match format {
-            SignatureFormat::Cosign => self.create_cosign_signature(content, signing_key, &payload).await?,
+            SignatureFormat::Cosign => self.create_cosign_signature(signing_key, &payload).await?,
             SignatureFormat::NotaryV2 => self.create_notary_v2_signature(content, signing_key, &payload).await?,
             SignatureFormat::Simple => self.create_simple_signature(content, signing_key, &payload).await?,
             SignatureFormat::InToto => self.create_in_toto_signature(content, signing_key, &payload).await?,
@@ -263,6 +365,7 @@ impl SigningService {
             key_id: key_id.to_string(),
             certificate_chain: signing_key.certificate.as_ref().map(|cert| vec![cert.clone()]),
             payload,
+            signed_payload,
             created_at: chrono::Utc::now(),
             expires_at: signing_key.expires_at,
         };
@@ -283,12 +386,9 @@ impl SigningService {
     ) -> Result<VerificationResult> {
         debug!("Verifying signature {} for content", signature.signature_id);
 
-        // Check cache first
-        if let Some(cached) = self.get_cached_verification(&signature.signature_id).await {
-            if chrono::Utc::now().signed_duration_since(cached.cached_at).num_minutes() < 5 {
-                debug!("Using cached verification result");
-                return Ok(cached.verification_result);
-            }
+        // Expiry is checked before the cache so a cached result can't outlive the signature
+        if let Some(error) = self.check_signature_age(signature, policy) {
+            return Ok(self.failed_verification(signature, vec![error]));
         }
 
         // Verify content digest matches
@@ -334,6 +434,17 @@ impl SigningService {
             });
         }
 
+        // A cached result only stands for the same signature, checked under the same policy
+        let policy_fingerprint = policy_fingerprint(policy);
+        if let Some(cached) = self.get_cached_verification(&signature.signature_id, &policy_fingerprint).await
+            && cached.signature.signature == signature.signature
+            && cached.signature.content_digest == signature.content_digest
+            && chrono::Utc::now().signed_duration_since(cached.cached_at).num_minutes() < 5
+        {
+            debug!("Using cached verification result");
+            return Ok(cached.verification_result);
+        }
+
         // Verify signature based on format
         let result = match signature.format {
             SignatureFormat::Cosign => self.verify_cosign_signature(content, signature, policy).await?,
@@ -341,360 +452,2426 @@ impl SigningService {
             SignatureFormat::Simple => self.verify_simple_signature(content, signature, policy).await?,
             SignatureFormat::InToto => self.verify_in_toto_signature(content, signature, policy).await?,
         };
+        let mut result = result;
+
+        // Validate the certificate chain against the policy's trust stores, unless the format already has
+        match signature.certificate_chain.as_deref() {
+            _ if result.certificate_chain_valid.is_some() => {}
+            Some(chain) if !chain.is_empty() => {
+                let binding = match self.get_verification_key(&signature.key_id).await {
+                    Some(key) if !leaf_certifies_key(chain, &key) => Err(anyhow::anyhow!(
+                        "leaf certificate is not for key {}", signature.key_id
+                    )),
+                    _ => Ok(()),
+                };
+                match binding.and(self.validate_certificate_chain(chain, policy).await) {
+                    Ok(()) => {
+                        // A chain to a trusted root vouches for the key, as it does for Notary v2
+                        result.certificate_chain_valid = Some(true);
+                        result.trusted = result.valid;
+                    }
+                    Err(e) => {
+                        result.certificate_chain_valid = Some(false);
+                        result.valid = false;
+                        result.trusted = false;
+                        result.errors.push(format!("Certificate chain validation failed: {}", e));
+                    }
+                }
+            }
+            _ => {
+                if policy.require_certificate_chain && result.valid {
+                    result.valid = false;
+                    result.trusted = false;
+                    result.errors.push("Certificate chain required by policy but none was provided".to_string());
+                }
+            }
+        }
 
         // Cache result
-        self.cache_verification_result(&signature.signature_id, signature.clone(), result.clone()).await;
+        self.cache_verification_result(&signature.signature_id, &policy_fingerprint, signature.clone(), result.clone()).await;
 
         info!("Signature verification completed: valid={}, trusted={}", result.valid, result.trusted);
         Ok(result)
     }
 
-    /// Get all signatures for a piece of content
-    pub async fn get_content_signatures(&self, content_digest: &str) -> Result<Vec<ContentSignature>> {
-        debug!("Getting signatures for content digest: {}", content_digest);
+    /// Key used when a signing request doesn't name one
+    pub fn default_key_id(&self) -> &str {
+        &self.config.default_key_id
+    }
 
-        let key = format!("signatures/{}", content_digest);
-        match self.storage.get_blob(&key).await? {
-            Some(data) => {
-                let signatures: Vec<ContentSignature> = serde_json::from_slice(&data)?;
-                Ok(signatures)
-            }
-            None => Ok(vec![]),
-        }
+    /// Whether a signing key with this id is loaded and not retired
+    pub async fn has_signing_key(&self, key_id: &str) -> bool {
+        self.key_store.read().await.signing_keys.get(key_id).is_some_and(|key| !key.retired)
     }
 
-    /// Store signature in storage backend
-    async fn store_signature(&self, signature: &ContentSignature) -> Result<()> {
-        // Store individual signature
-        let signature_key = format!("signatures/{}/sig_{}", signature.content_digest, signature.signature_id);
-        let signature_data = serde_json::to_vec(signature)?;
-        self.storage.put_blob(&signature_key, signature_data.into()).await?;
+    /// Every signing and verification key, public parts only
+    pub async fn list_keys(&self) -> Vec<KeyInfo> {
+        let key_store = self.key_store.read().await;
+        let key_ids: BTreeSet<&String> =
+            key_store.signing_keys.keys().chain(key_store.verification_keys.keys()).collect();
+
+        key_ids.into_iter()
+            .filter_map(|key_id| match key_info(&key_store, key_id) {
+                Ok(info) => info,
+                Err(e) => {
+                    warn!("Failed to describe key {}: {:#}", key_id, e);
+                    None
+                }
+            })
+            .collect()
+    }
 
-        // Update content signatures list
-        let mut signatures = self.get_content_signatures(&signature.content_digest).await?;
-        signatures.push(signature.clone());
+    /// Generate a key pair and store it, the private key encrypted with `key_encryption_password`
+    pub async fn generate_key(
+        &self,
+        key_id: &str,
+        algorithm: SignatureAlgorithm,
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<KeyInfo> {
+        let password = self.config.key_encryption_password.clone()
+            .ok_or_else(|| anyhow::anyhow!("signing.key_encryption_password must be set to generate keys"))?;
+        validate_key_id(key_id)?;
+        if self.key_store.read().await.contains(key_id) {
+            return Err(anyhow::anyhow!("Key already exists: {}", key_id));
+        }
 
-        let list_key = format!("signatures/{}", signature.content_digest);
-        let list_data = serde_json::to_vec(&signatures)?;
-        self.storage.put_blob(&list_key, list_data.into()).await?;
+        // RSA key generation takes long enough to keep off the async workers
+        let key_algorithm = algorithm.clone();
+        let (private_key, public_key, encrypted_private_key) = tokio::task::spawn_blocking(move || {
+            let (private_key, public_key) = generate_key_pair(&key_algorithm)?;
+            let encrypted = encrypt_private_key_pem(&private_key, &password)?;
+            anyhow::Ok((private_key, public_key, encrypted))
+        })
+        .await??;
 
-        Ok(())
+        let stored = StoredKey {
+            key_id: key_id.to_string(),
+            algorithm,
+            encrypted_private_key: Some(encrypted_private_key),
+            public_key,
+            certificate: None,
+            trusted: true,
+            retired: false,
+            created_at: chrono::Utc::now(),
+            expires_at,
+        };
+        self.add_managed_key(stored, Some(private_key)).await
     }
 
-    /// Create Cosign-compatible signature
-    async fn create_cosign_signature(
+    /// Add a public key that signatures can be verified with
+    pub async fn import_verification_key(
         &self,
-        content: &[u8],
-        signing_key: &SigningKey,
-        payload: &SignaturePayload,
-    ) -> Result<Vec<u8>> {
-        debug!("Creating Cosign signature");
+        key_id: &str,
+        algorithm: SignatureAlgorithm,
+        public_key_pem: &str,
+        certificate_pem: Option<&str>,
+        trusted: bool,
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<KeyInfo> {
+        validate_key_id(key_id)?;
+
+        let (label, public_key) = pkcs8::der::pem::decode_vec(public_key_pem.trim().as_bytes())
+            .map_err(|e| anyhow::anyhow!("Invalid public key: {}", e))?;
+        if label != "PUBLIC KEY" {
+            return Err(anyhow::anyhow!("Public key must be a PEM PUBLIC KEY, found {}", label));
+        }
+        parse_verifying_key(&algorithm, &public_key)?;
+
+        let certificate = match certificate_pem {
+            Some(pem) => Some(
+                pem_certificates(pem)?.into_iter().next()
+                    .ok_or_else(|| anyhow::anyhow!("Certificate PEM contains no certificate"))?,
+            ),
+            None => None,
+        };
 
-        // Create Cosign-compatible payload
-        let cosign_payload = serde_json::json!({
-            "critical": {
-                "identity": {
-                    "docker-reference": format!("{}/{}", payload.repository, payload.subject)
-                },
-                "image": {
-                    "docker-manifest-digest": payload.subject
-                },
-                "type": "cosign container image signature"
-            },
-            "optional": {
-                "timestamp": payload.timestamp.timestamp(),
-                "tag": payload.tag,
-                "metadata": payload.metadata
-            }
-        });
+        let stored = StoredKey {
+            key_id: key_id.to_string(),
+            algorithm,
+            encrypted_private_key: None,
+            public_key,
+            certificate,
+            trusted,
+            retired: false,
+            created_at: chrono::Utc::now(),
+            expires_at,
+        };
+        self.add_managed_key(stored, None).await
+    }
+
+    async fn add_managed_key(&self, stored: StoredKey, private_key: Option<Vec<u8>>) -> Result<KeyInfo> {
+        let mut key_store = self.key_store.write().await;
+        if key_store.contains(&stored.key_id) {
+            return Err(anyhow::anyhow!("Key already exists: {}", stored.key_id));
+        }
 
-        let payload_bytes = serde_json::to_vec(&cosign_payload)?;
+        self.storage
+            .put_blob(&managed_key_key(&stored.key_id), serde_json::to_vec(&stored)?.into())
+            .await?;
+        key_store.managed.insert(stored.key_id.clone());
+        self.save_managed_key_index(&key_store).await?;
 
-        // Sign the payload
-        self.sign_bytes(&payload_bytes, signing_key).await
+        key_store.insert_stored(&stored, private_key);
+        info!("Added {} key {}", if stored.encrypted_private_key.is_some() { "signing" } else { "verification" }, stored.key_id);
+        key_info(&key_store, &stored.key_id)?
+            .ok_or_else(|| anyhow::anyhow!("Key {} disappeared after adding it", stored.key_id))
     }
 
-    /// Create Notary v2 signature (JWS)
-    async fn create_notary_v2_signature(
-        &self,
-        content: &[u8],
-        signing_key: &SigningKey,
-        payload: &SignaturePayload,
-    ) -> Result<Vec<u8>> {
-        debug!("Creating Notary v2 signature");
+    /// Stop signing with a managed key; its signatures keep verifying. None if there is no such key.
+    pub async fn retire_key(&self, key_id: &str) -> Result<Option<KeyInfo>> {
+        let mut key_store = self.key_store.write().await;
+        if !key_store.contains(key_id) {
+            return Ok(None);
+        }
+        if !key_store.managed.contains(key_id) {
+            return Err(anyhow::anyhow!("Key {} is configured in [signing]; remove it there", key_id));
+        }
+        if !key_store.signing_keys.contains_key(key_id) {
+            return Err(anyhow::anyhow!("Key {} is a verification key and never signs", key_id));
+        }
 
-        // Create JWS header
-        let header = serde_json::json!({
-            "alg": self.algorithm_to_jws_alg(&signing_key.algorithm),
-            "typ": "JWT",
-            "kid": signing_key.key_id
-        });
+        let Some(data) = self.storage.get_blob(&managed_key_key(key_id)).await? else {
+            return Err(anyhow::anyhow!("Key {} is missing from storage", key_id));
+        };
+        let mut stored: StoredKey = serde_json::from_slice(&data)?;
+        stored.retired = true;
+        self.storage
+            .put_blob(&managed_key_key(key_id), serde_json::to_vec(&stored)?.into())
+            .await?;
+
+        if let Some(signing_key) = key_store.signing_keys.get_mut(key_id) {
+            signing_key.retired = true;
+        }
+        info!("Retired signing key {}", key_id);
+        key_info(&key_store, key_id)
+    }
 
-        // Create JWS payload
-        let jws_payload = serde_json::json!({
-            "sub": payload.subject,
-            "iat": payload.timestamp.timestamp(),
-            "exp": payload.timestamp.timestamp() + 3600, // 1 hour
-            "iss": "drift-registry",
-            "nbf": payload.timestamp.timestamp(),
-            "repository": payload.repository,
-            "tag": payload.tag,
-            "content_type": payload.content_type,
-            "metadata": payload.metadata
-        });
+    /// Delete a managed key; signatures made with it no longer verify
+    pub async fn delete_key(&self, key_id: &str) -> Result<bool> {
+        let mut key_store = self.key_store.write().await;
+        if !key_store.contains(key_id) {
+            return Ok(false);
+        }
+        if !key_store.managed.contains(key_id) {
+            return Err(anyhow::anyhow!("Key {} is configured in [signing]; remove it there", key_id));
+        }
 
-        // Encode JWS
-        let header_b64 = general_purpose::URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?);
-        let payload_b64 = general_purpose::URL_SAFE_NO_PAD.encode(serde_json::to_vec(&jws_payload)?);
-        let signing_input = format!("{}.{}", header_b64, payload_b64);
+        key_store.managed.remove(key_id);
+        self.save_managed_key_index(&key_store).await?;
+        self.storage.delete_blob(&managed_key_key(key_id)).await?;
+        key_store.signing_keys.remove(key_id);
+        key_store.verification_keys.remove(key_id);
 
-        let signature_bytes = self.sign_bytes(signing_input.as_bytes(), signing_key).await?;
-        let signature_b64 = general_purpose::URL_SAFE_NO_PAD.encode(signature_bytes);
+        info!("Deleted key {}", key_id);
+        Ok(true)
+    }
 
-        let jws = format!("{}.{}.{}", header_b64, payload_b64, signature_b64);
-        Ok(jws.into_bytes())
+    async fn save_managed_key_index(&self, key_store: &KeyStore) -> Result<()> {
+        let key_ids: BTreeSet<&String> = key_store.managed.iter().collect();
+        self.storage
+            .put_blob(MANAGED_KEY_INDEX_KEY, serde_json::to_vec(&key_ids)?.into())
+            .await
     }
 
-    /// Create simple JSON signature
-    async fn create_simple_signature(
-        &self,
-        content: &[u8],
-        signing_key: &SigningKey,
-        payload: &SignaturePayload,
-    ) -> Result<Vec<u8>> {
-        debug!("Creating simple signature");
+    /// Load the keys added through the API. Their private keys need `key_encryption_password`;
+    /// without it they're loaded for verification only.
+    async fn load_managed_keys(&self, key_store: &mut KeyStore) -> Result<()> {
+        let key_ids: Vec<String> = match self.storage.get_blob(MANAGED_KEY_INDEX_KEY).await? {
+            Some(data) => serde_json::from_slice(&data)?,
+            None => return Ok(()),
+        };
 
-        let content_digest = hex::encode(Sha256::digest(content));
-        let signing_input = format!("{}:{}", content_digest, serde_json::to_string(payload)?);
+        for key_id in key_ids {
+            let Some(data) = self.storage.get_blob(&managed_key_key(&key_id)).await? else {
+                warn!("Signing key {} is indexed but missing from storage", key_id);
+                continue;
+            };
+            let stored: StoredKey = serde_json::from_slice(&data)?;
+            if key_store.contains(&stored.key_id) {
+                warn!("Ignoring stored key {}, a configured key has the same id", stored.key_id);
+                continue;
+            }
 
-        self.sign_bytes(signing_input.as_bytes(), signing_key).await
+            let private_key = match (&stored.encrypted_private_key, &self.config.key_encryption_password) {
+                (Some(pem), Some(password)) => Some(
+                    decode_private_key_pem(pem, Some(password))
+                        .map_err(|e| anyhow::anyhow!("Invalid stored signing key {}: {}", stored.key_id, e))?,
+                ),
+                (Some(_), None) => {
+                    warn!(
+                        "signing.key_encryption_password is not set, key {} can verify but not sign",
+                        stored.key_id
+                    );
+                    None
+                }
+                (None, _) => None,
+            };
+
+            key_store.managed.insert(stored.key_id.clone());
+            key_store.insert_stored(&stored, private_key);
+        }
+
+        debug!("Loaded {} managed keys", key_store.managed.len());
+        Ok(())
     }
 
-    /// Create in-toto attestation signature
-    async fn create_in_toto_signature(
+    /// Unretired signing keys whose key or certificate expires within
+    /// `key_expiry_warning_days`, with when they expire
+    pub async fn expiring_keys(&self) -> Vec<(String, chrono::DateTime<chrono::Utc>)> {
+        let horizon = chrono::Utc::now() + chrono::Duration::days(self.config.key_expiry_warning_days as i64);
+        let key_store = self.key_store.read().await;
+
+        let mut expiring: Vec<_> = key_store.signing_keys.values()
+            .filter(|key| !key.retired)
+            .filter_map(|key| {
+                let certificate_expiry = key.certificate.as_deref().and_then(certificate_not_after);
+                let expires_at = match (key.expires_at, certificate_expiry) {
+                    (Some(key_expiry), Some(certificate_expiry)) => Some(key_expiry.min(certificate_expiry)),
+                    (key_expiry, certificate_expiry) => key_expiry.or(certificate_expiry),
+                }?;
+                (expires_at <= horizon).then(|| (key.key_id.clone(), expires_at))
+            })
+            .collect();
+        expiring.sort();
+        expiring
+    }
+
+    /// Check for expiring signing keys now and every `KEY_EXPIRY_CHECK_INTERVAL`,
+    /// keeping `gauge` at their count and auditing each one
+    pub fn spawn_key_expiry_check(
         &self,
-        content: &[u8],
-        signing_key: &SigningKey,
-        payload: &SignaturePayload,
-    ) -> Result<Vec<u8>> {
-        debug!("Creating in-toto signature");
+        audit: Option<Arc<AuditService>>,
+        gauge: prometheus::IntGauge,
+        shutdown: CancellationToken,
+    ) {
+        let service = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(KEY_EXPIRY_CHECK_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = interval.tick() => {}
+                }
 
-        let attestation = serde_json::json!({
-            "_type": "link",
-            "subject": [{
-                "name": payload.subject,
-                "digest": {
-                    "sha256": hex::encode(Sha256::digest(content))
+                let expiring = service.expiring_keys().await;
+                gauge.set(expiring.len() as i64);
+                for (key_id, expires_at) in expiring {
+                    warn!("Signing key {} expires at {}", key_id, expires_at);
+                    let Some(audit) = audit.as_ref() else {
+                        continue;
+                    };
+                    if let Err(e) = audit.log(AuditService::signing_key_expiry_event(key_id.clone(), expires_at)).await {
+                        error!("Failed to record audit event for expiring key {}: {}", key_id, e);
+                    }
                 }
-            }],
-            "predicateType": "https://in-toto.io/Statement/v0.1",
-            "predicate": {
-                "repository": payload.repository,
-                "tag": payload.tag,
-                "content_type": payload.content_type,
-                "timestamp": payload.timestamp,
-                "metadata": payload.metadata
             }
         });
+    }
 
-        let attestation_bytes = serde_json::to_vec(&attestation)?;
-        self.sign_bytes(&attestation_bytes, signing_key).await
+    /// Fetch each trust store's CRLs again every `crl_refresh_secs`. Serials revoked by
+    /// the previous fetch stay revoked while a CRL can't be fetched.
+    pub fn spawn_crl_refresh(&self, shutdown: CancellationToken) {
+        for trust_config in self.config.trust_stores.iter().filter(|store| !store.crl_urls.is_empty()) {
+            let service = self.clone();
+            let trust_config = trust_config.clone();
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move {
+                let period = std::time::Duration::from_secs(trust_config.crl_refresh_secs.max(1));
+                let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+                loop {
+                    tokio::select! {
+                        _ = shutdown.cancelled() => break,
+                        _ = interval.tick() => {}
+                    }
+                    service.refresh_revocations(&trust_config).await;
+                }
+            });
+        }
     }
 
-    /// Sign bytes with the given key
-    async fn sign_bytes(&self, data: &[u8], signing_key: &SigningKey) -> Result<Vec<u8>> {
-        match signing_key.algorithm {
-            SignatureAlgorithm::RsaPssSha256 => {
-                // TODO: Implement RSA-PSS signature
-                warn!("RSA-PSS signature not implemented, using placeholder");
-                Ok(format!("RSA-PSS-SHA256:placeholder-signature-for:{}", hex::encode(Sha256::digest(data))).into_bytes())
-            }
-            SignatureAlgorithm::RsaPkcs1Sha256 => {
-                // TODO: Implement RSA PKCS#1 signature
-                warn!("RSA PKCS#1 signature not implemented, using placeholder");
-                Ok(format!("RSA-PKCS1-SHA256:placeholder-signature-for:{}", hex::encode(Sha256::digest(data))).into_bytes())
-            }
-            SignatureAlgorithm::EcdsaP256Sha256 => {
-                // TODO: Implement ECDSA P-256 signature
-                warn!("ECDSA P-256 signature not implemented, using placeholder");
-                Ok(format!("ECDSA-P256-SHA256:placeholder-signature-for:{}", hex::encode(Sha256::digest(data))).into_bytes())
-            }
-            SignatureAlgorithm::EcdsaP384Sha384 => {
-                // TODO: Implement ECDSA P-384 signature
-                warn!("ECDSA P-384 signature not implemented, using placeholder");
-                Ok(format!("ECDSA-P384-SHA384:placeholder-signature-for:{}", hex::encode(Sha256::digest(data))).into_bytes())
-            }
-            SignatureAlgorithm::Ed25519 => {
-                // TODO: Implement Ed25519 signature
-                warn!("Ed25519 signature not implemented, using placeholder");
-                Ok(format!("ED25519:placeholder-signature-for:{}", hex::encode(Sha256::digest(data))).into_bytes())
+    /// Fetch a trust store's CRLs and update its revoked serials
+    async fn refresh_revocations(&self, trust_config: &crate::config::TrustStoreConfig) {
+        let issuers = match self.key_store.read().await.trust_stores.get(&trust_config.name) {
+            Some(store) => store.root_certificates.iter().chain(&store.intermediate_certificates).cloned().collect::<Vec<_>>(),
+            None => return,
+        };
+        let (revoked, checked) = fetch_revoked_serials(&trust_config.name, &trust_config.crl_urls, &issuers).await;
+
+        let mut key_store = self.key_store.write().await;
+        let Some(store) = key_store.trust_stores.get_mut(&trust_config.name) else {
+            return;
+        };
+        if checked {
+            store.revoked_certificates = revoked;
+        } else {
+            for serial in revoked {
+                if !store.revoked_certificates.contains(&serial) {
+                    store.revoked_certificates.push(serial);
+                }
             }
         }
+        store.revocation_checked = checked;
     }
 
-    /// Convert signature algorithm to JWS algorithm identifier
-    fn algorithm_to_jws_alg(&self, algorithm: &SignatureAlgorithm) -> &'static str {
-        match algorithm {
-            SignatureAlgorithm::RsaPssSha256 => "PS256",
-            SignatureAlgorithm::RsaPkcs1Sha256 => "RS256",
-            SignatureAlgorithm::EcdsaP256Sha256 => "ES256",
-            SignatureAlgorithm::EcdsaP384Sha384 => "ES384",
-            SignatureAlgorithm::Ed25519 => "EdDSA",
+    /// Remove a signature by id alone, returning it if it was present. Signatures
+    /// stored before ids were indexed and Cosign signatures pushed by cosign
+    /// aren't in the index, so those are looked for in every repository.
+    pub async fn revoke_signature(&self, signature_id: &str) -> Result<Option<ContentSignature>> {
+        if let Some(content_digest) = self.storage.get_blob(&signature_id_key(signature_id)).await? {
+            let content_digest = String::from_utf8(content_digest.to_vec())?;
+            return self.remove_signature(None, &content_digest, signature_id).await;
+        }
+
+        match self.find_signature(signature_id).await? {
+            Some(signature) if signature.signature_id.starts_with("cosign-") => {
+                let repository = signature.payload.repository.clone();
+                self.remove_discovered_signature(&repository, signature).await
+            }
+            Some(signature) => self.remove_signature(None, &signature.content_digest, signature_id).await,
+            None => Ok(None),
         }
     }
 
-    /// Verify Cosign signature
-    async fn verify_cosign_signature(
+    /// Remove a single signature made for `repository` from content, returning it if it was present
+    pub async fn delete_signature(
         &self,
-        _content: &[u8],
-        signature: &ContentSignature,
-        _policy: &VerificationPolicy,
-    ) -> Result<VerificationResult> {
-        // TODO: Implement actual Cosign signature verification
-        warn!("Cosign signature verification not fully implemented");
+        repository: &str,
+        content_digest: &str,
+        signature_id: &str,
+    ) -> Result<Option<ContentSignature>> {
+        if let Some(removed) = self.remove_signature(Some(repository), content_digest, signature_id).await? {
+            return Ok(Some(removed));
+        }
 
-        Ok(VerificationResult {
-            valid: true, // Placeholder
-            trusted: false,
-            key_id: signature.key_id.clone(),
-            algorithm: signature.algorithm.clone(),
-            verified_at: chrono::Utc::now(),
-            certificate_chain_valid: None,
-            errors: vec![],
-            warnings: vec!["Cosign verification not fully implemented".to_string()],
-        })
+        let discovered = self.discover_cosign_signatures(repository, content_digest).await?
+            .into_iter()
+            .find(|signature| signature.signature_id == signature_id);
+        match discovered {
+            Some(signature) => self.remove_discovered_signature(repository, signature).await,
+            None => Ok(None),
+        }
     }
 
-    /// Verify Notary v2 signature
-    async fn verify_notary_v2_signature(
+    /// Remove a signature found in a Cosign signature artifact. Taking it out of
+    /// the artifact is up to the caller; a copy drift stored is removed here.
+    async fn remove_discovered_signature(
         &self,
-        _content: &[u8],
-        signature: &ContentSignature,
-        _policy: &VerificationPolicy,
-    ) -> Result<VerificationResult> {
-        // TODO: Implement actual Notary v2 signature verification
-        warn!("Notary v2 signature verification not fully implemented");
-
-        Ok(VerificationResult {
-            valid: true, // Placeholder
-            trusted: false,
-            key_id: signature.key_id.clone(),
-            algorithm: signature.algorithm.clone(),
-            verified_at: chrono::Utc::now(),
-            certificate_chain_valid: None,
-            errors: vec![],
-            warnings: vec!["Notary v2 verification not fully implemented".to_string()],
-        })
+        repository: &str,
+        signature: ContentSignature,
+    ) -> Result<Option<ContentSignature>> {
+        let stored = self.stored_signatures(&signature.content_digest).await?.into_iter()
+            .find(|stored| stored.signature == signature.signature && stored.payload.repository == repository);
+        match stored {
+            Some(stored) => {
+                self.remove_signature(Some(repository), &signature.content_digest, &stored.signature_id).await
+            }
+            None => {
+                self.forget_verdict(repository, &signature.content_digest).await;
+                Ok(Some(signature))
+            }
+        }
     }
 
-    /// Verify simple signature
-    async fn verify_simple_signature(
-        &self,
+    /// Look through the manifests of every repository for a signature missing
+    /// from the id index, indexing the stored signatures found on the way
+    async fn find_signature(&self, signature_id: &str) -> Result<Option<ContentSignature>> {
+        let mut checked = HashSet::new();
+        for repository in self.storage.list_repositories().await? {
+            if signature_id.starts_with("cosign-") {
+                for tag in self.storage.list_tags(&repository).await? {
+                    let Some(content_digest) = cosign_signature_tag_digest(&tag) else {
+                        continue;
+                    };
+                    let discovered = self.discover_cosign_signatures(&repository, content_digest).await?
+                        .into_iter()
+                        .find(|signature| signature.signature_id == signature_id);
+                    if discovered.is_some() {
+                        return Ok(discovered);
+                    }
+                }
+                continue;
+            }
+
+            for digest in self.storage.list_manifests(&repository).await? {
+                let content_digest = digest.strip_prefix("sha256:").unwrap_or(&digest).to_string();
+                if !checked.insert(content_digest.clone()) {
+                    continue;
+                }
+
+                let mut found = None;
+                for signature in self.stored_signatures(&content_digest).await? {
+                    self.storage
+                        .put_blob(&signature_id_key(&signature.signature_id), content_digest.clone().into())
+                        .await?;
+                    if signature.signature_id == signature_id {
+                        found = Some(signature);
+                    }
+                }
+                if found.is_some() {
+                    return Ok(found);
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn remove_signature(
+        &self,
+        repository: Option<&str>,
+        content_digest: &str,
+        signature_id: &str,
+    ) -> Result<Option<ContentSignature>> {
+        debug!("Deleting signature {} for content digest: {}", signature_id, content_digest);
+
+        let mut signatures = self.stored_signatures(content_digest).await?;
+        let Some(position) = signatures.iter().position(|signature| {
+            signature.signature_id == signature_id
+                && repository.is_none_or(|repository| signature.payload.repository == repository)
+        }) else {
+            return Ok(None);
+        };
+        let removed = signatures.remove(position);
+
+        if !removed.payload.repository.is_empty() {
+            self.forget_verdict(&removed.payload.repository, content_digest).await;
+        }
+
+        let signature_key = format!("signatures/{}/sig_{}", content_digest, signature_id);
+        self.storage.delete_blob(&signature_key).await?;
+        self.storage.delete_blob(&signature_id_key(signature_id)).await?;
+
+        let list_key = format!("signatures/{}", content_digest);
+        let list_data = serde_json::to_vec(&signatures)?;
+        self.storage.put_blob(&list_key, list_data.into()).await?;
+
+        self.signature_cache.write().await.retain(|(cached_id, _), _| cached_id != signature_id);
+
+        info!("Deleted signature {} for content {}", signature_id, content_digest);
+        Ok(Some(removed))
+    }
+
+    /// Verification policy built from the service configuration
+    pub fn verification_policy(&self) -> VerificationPolicy {
+        let policy_config = &self.config.verification_policy;
+
+        VerificationPolicy {
+            require_signatures: policy_config.require_signatures,
+            required_signatures_count: policy_config.required_signatures_count,
+            allowed_signature_formats: parse_policy_values(&policy_config.allowed_signature_formats),
+            allowed_algorithms: parse_policy_values(&policy_config.allowed_algorithms),
+            trust_stores: policy_config.trust_stores.clone(),
+            require_certificate_chain: policy_config.require_certificate_chain,
+            allow_self_signed: policy_config.allow_self_signed,
+            max_signature_age_hours: policy_config.max_signature_age_hours,
+        }
+    }
+
+    /// Verification policy for a repository: the configured policy, with
+    /// `require_signatures` overridden by the first matching repository policy
+    pub async fn verification_policy_for(&self, repository: &str) -> VerificationPolicy {
+        let mut policy = self.verification_policy();
+
+        let repository_policies = self.repository_policies.read().await;
+        if let Some(rule) = repository_policies.iter().find(|rule| repository_matches(&rule.pattern, repository)) {
+            policy.require_signatures = rule.require_signatures;
+            if let Some(count) = rule.required_signatures_count {
+                policy.required_signatures_count = count;
+            }
+        }
+
+        policy
+    }
+
+    pub async fn repository_policies(&self) -> Vec<RepositorySignaturePolicy> {
+        self.repository_policies.read().await.clone()
+    }
+
+    /// Replace the per-repository policies. Cached verdicts were reached under
+    /// the old policies, so they are dropped.
+    pub async fn set_repository_policies(&self, policies: Vec<RepositorySignaturePolicy>) -> Result<()> {
+        validate_repository_policies(&policies)?;
+
+        *self.repository_policies.write().await = policies;
+        self.policy_cache.write().await.clear();
+        Ok(())
+    }
+
+    /// Newly pushed manifests may be pulled unsigned for this long
+    pub fn grace_period_secs(&self) -> u64 {
+        self.config.enforcement.grace_period_secs
+    }
+
+    /// How long a pull verdict is reused
+    pub fn cache_ttl_secs(&self) -> u64 {
+        self.config.enforcement.cache_ttl_secs
+    }
+
+    /// Whether a manifest in `repository` may be pulled under its policy. The
+    /// verdict is cached per digest, along with when the manifest was pushed, so
+    /// manifests younger than the grace period are let through unsigned.
+    pub async fn check_pull(&self, repository: &str, content: &[u8]) -> Result<PolicyResult> {
+        let policy = self.verification_policy_for(repository).await;
+        if !policy.require_signatures {
+            return Ok(PolicyResult { allowed: true, valid_signatures: 0, errors: vec![] });
+        }
+
+        let content_digest = hex::encode(Sha256::digest(content));
+        let verdict = self.verdict(repository, &content_digest, content, &policy).await?;
+        let mut result = verdict.result;
+        if !result.allowed && verdict.pushed_at.is_some_and(|pushed_at| self.in_grace_period(pushed_at)) {
+            debug!("Allowing unsigned {}@sha256:{} during its grace period", repository, content_digest);
+            result.allowed = true;
+        }
+
+        Ok(result)
+    }
+
+    /// Whether a manifest may be pushed to `repository` under `enforcement.enforce_on_push`:
+    /// content arriving for the first time starts its grace period, and after that it can
+    /// only be pushed again, e.g. under another tag, with enough valid signatures
+    pub async fn check_push(&self, repository: &str, content: &[u8]) -> Result<PolicyResult> {
+        let policy = self.verification_policy_for(repository).await;
+        if !self.config.enforcement.enforce_on_push || !policy.require_signatures {
+            return Ok(PolicyResult { allowed: true, valid_signatures: 0, errors: vec![] });
+        }
+
+        let content_digest = hex::encode(Sha256::digest(content));
+        let Some(pushed_at) = self.pushed_at(repository, &content_digest).await else {
+            return Ok(PolicyResult { allowed: true, valid_signatures: 0, errors: vec![] });
+        };
+
+        let mut result = self.verdict(repository, &content_digest, content, &policy).await?.result;
+        if !result.allowed && self.in_grace_period(pushed_at) {
+            debug!("Allowing unsigned push of {}@sha256:{} during its grace period", repository, content_digest);
+            result.allowed = true;
+        }
+
+        Ok(result)
+    }
+
+    /// The cached verdict for content, or a fresh one that is then cached
+    async fn verdict(
+        &self,
+        repository: &str,
+        content_digest: &str,
+        content: &[u8],
+        policy: &VerificationPolicy,
+    ) -> Result<CachedVerdict> {
+        let cache_key = format!("{}@sha256:{}", repository, content_digest);
+        let ttl = std::time::Duration::from_secs(self.config.enforcement.cache_ttl_secs);
+
+        let cached = self.policy_cache.read().await.get(&cache_key)
+            .filter(|cached| cached.reached_at.elapsed() < ttl)
+            .cloned();
+        if let Some(cached) = cached {
+            return Ok(cached);
+        }
+
+        let result = self.evaluate_policy(repository, content, policy).await?;
+        // Looking the push time up is a bucket scan on some backends, so it's only done
+        // when the grace period could matter, and kept with the verdict
+        let pushed_at = if result.allowed || self.config.enforcement.grace_period_secs == 0 {
+            None
+        } else {
+            self.pushed_at(repository, content_digest).await
+        };
+        let verdict = CachedVerdict { result, pushed_at, reached_at: std::time::Instant::now() };
+
+        let mut cache = self.policy_cache.write().await;
+        if cache.len() >= MAX_CACHED_VERDICTS {
+            cache.retain(|_, cached| cached.reached_at.elapsed() < ttl);
+        }
+        if cache.len() >= MAX_CACHED_VERDICTS
+            && let Some(oldest) = cache.iter().min_by_key(|(_, cached)| cached.reached_at).map(|(key, _)| key.clone())
+        {
+            cache.remove(&oldest);
+        }
+        cache.insert(cache_key, verdict.clone());
+        Ok(verdict)
+    }
+
+    /// When a manifest was first stored in `repository`, if it is
+    async fn pushed_at(&self, repository: &str, content_digest: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+        let digest = format!("sha256:{}", content_digest);
+        self.storage.get_manifest_metadata(repository, &digest).await.ok().map(|metadata| metadata.created_at)
+    }
+
+    fn in_grace_period(&self, pushed_at: chrono::DateTime<chrono::Utc>) -> bool {
+        let grace_period = self.config.enforcement.grace_period_secs;
+        chrono::Utc::now().signed_duration_since(pushed_at).num_seconds() < grace_period as i64
+    }
+
+    /// Drop the cached pull verdict for content whose signatures changed
+    pub async fn forget_verdict(&self, repository: &str, content_digest: &str) {
+        let cache_key = format!("{}@sha256:{}", repository, content_digest);
+        self.policy_cache.write().await.remove(&cache_key);
+    }
+
+    /// Check that content in `repository` carries enough valid signatures to satisfy the policy
+    pub async fn evaluate_policy(
+        &self,
+        repository: &str,
+        content: &[u8],
+        policy: &VerificationPolicy,
+    ) -> Result<PolicyResult> {
+        if !policy.require_signatures {
+            return Ok(PolicyResult { allowed: true, valid_signatures: 0, errors: vec![] });
+        }
+
+        let content_digest = hex::encode(Sha256::digest(content));
+        let signatures = self.get_content_signatures(repository, &content_digest).await?;
+
+        let mut valid_signatures = 0;
+        let mut errors = Vec::new();
+        for signature in &signatures {
+            let result = self.verify_signature(content, signature, policy).await?;
+            if result.valid {
+                valid_signatures += 1;
+            } else {
+                errors.extend(result.errors.into_iter()
+                    .map(|e| format!("signature {}: {}", signature.signature_id, e)));
+            }
+        }
+
+        let required = policy.required_signatures_count.max(1);
+        let allowed = valid_signatures >= required;
+        if !allowed {
+            errors.insert(0, format!(
+                "{} valid signatures found, {} required",
+                valid_signatures, required
+            ));
+        }
+
+        Ok(PolicyResult { allowed, valid_signatures, errors })
+    }
+
+    /// Get all signatures for a piece of content in `repository`: the ones drift
+    /// created plus any Cosign signatures pushed under the `sha256-<digest>.sig` tag
+    pub async fn get_content_signatures(&self, repository: &str, content_digest: &str) -> Result<Vec<ContentSignature>> {
+        debug!("Getting signatures for content digest: {}", content_digest);
+
+        let mut signatures = self.stored_signatures(content_digest).await?;
+        for discovered in self.discover_cosign_signatures(repository, content_digest).await? {
+            // Cosign signatures drift created are in both places
+            if !signatures.iter().any(|signature| signature.signature == discovered.signature) {
+                signatures.push(discovered);
+            }
+        }
+
+        Ok(signatures)
+    }
+
+    /// Signatures created through drift, kept under `signatures/<digest>`
+    async fn stored_signatures(&self, content_digest: &str) -> Result<Vec<ContentSignature>> {
+        let key = format!("signatures/{}", content_digest);
+        match self.storage.get_blob(&key).await? {
+            Some(data) => {
+                let signatures: Vec<ContentSignature> = serde_json::from_slice(&data)?;
+                Ok(signatures)
+            }
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Store signature in storage backend
+    async fn store_signature(&self, signature: &ContentSignature) -> Result<()> {
+        // Store individual signature
+        let signature_key = format!("signatures/{}/sig_{}", signature.content_digest, signature.signature_id);
+        let signature_data = serde_json::to_vec(signature)?;
+        self.storage.put_blob(&signature_key, signature_data.into()).await?;
+
+        // Lets a signature be revoked by its id alone
+        self.storage
+            .put_blob(&signature_id_key(&signature.signature_id), signature.content_digest.clone().into())
+            .await?;
+
+        // Update content signatures list
+        let mut signatures = self.stored_signatures(&signature.content_digest).await?;
+        signatures.push(signature.clone());
+
+        let list_key = format!("signatures/{}", signature.content_digest);
+        let list_data = serde_json::to_vec(&signatures)?;
+        self.storage.put_blob(&list_key, list_data.into()).await?;
+
+        if !signature.payload.repository.is_empty() {
+            self.forget_verdict(&signature.payload.repository, &signature.content_digest).await;
+        }
+
+        Ok(())
+    }
+
+    /// How adding a Cosign signature changes the signature artifact `artifact` of its content.
+    /// Cosign finds signatures as an OCI artifact next to the image; storing it is up to the
+    /// caller, which has to hold the repository lock from reading the artifact until then.
+    pub async fn cosign_artifact_with(
+        &self,
+        artifact: Option<&[u8]>,
+        signature: &ContentSignature,
+    ) -> Result<CosignArtifactChange> {
+        let payload = signature.signed_payload.as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Cosign signature {} has no signed payload", signature.signature_id))?;
+        let payload_digest = self.put_content_blob(payload).await?;
+
+        let mut annotations = serde_json::Map::new();
+        annotations.insert(
+            COSIGN_SIGNATURE_ANNOTATION.to_string(),
+            general_purpose::STANDARD.encode(&signature.signature).into(),
+        );
+        if let Some((certificate, chain)) = signature.certificate_chain.as_deref().and_then(|chain| chain.split_first()) {
+            annotations.insert(COSIGN_CERTIFICATE_ANNOTATION.to_string(), certificate_pem(certificate).into());
+            if !chain.is_empty() {
+                let chain: String = chain.iter().map(|certificate| certificate_pem(certificate)).collect();
+                annotations.insert(COSIGN_CHAIN_ANNOTATION.to_string(), chain.into());
+            }
+        }
+
+        let mut layers = cosign_artifact_layers(artifact)?;
+        // Storing the same signature twice would only duplicate the layer
+        if layers.iter().any(|layer| cosign_layer_annotation(layer, COSIGN_SIGNATURE_ANNOTATION)
+            == annotations[COSIGN_SIGNATURE_ANNOTATION].as_str())
+        {
+            return Ok(CosignArtifactChange::Unchanged);
+        }
+        layers.push(serde_json::json!({
+            "mediaType": COSIGN_PAYLOAD_MEDIA_TYPE,
+            "size": payload.len(),
+            "digest": payload_digest,
+            "annotations": annotations,
+        }));
+
+        Ok(CosignArtifactChange::Write(self.cosign_artifact(layers).await?))
+    }
+
+    /// How taking a deleted signature out of the Cosign signature artifact `artifact` of its
+    /// content changes it; the artifact goes once its last signature does
+    pub async fn cosign_artifact_without(
+        &self,
+        artifact: Option<&[u8]>,
+        signature: &ContentSignature,
+    ) -> Result<CosignArtifactChange> {
+        let encoded = general_purpose::STANDARD.encode(&signature.signature);
+
+        let mut layers = cosign_artifact_layers(artifact)?;
+        let original_len = layers.len();
+        layers.retain(|layer| cosign_layer_annotation(layer, COSIGN_SIGNATURE_ANNOTATION) != Some(encoded.as_str()));
+        if layers.len() == original_len {
+            return Ok(CosignArtifactChange::Unchanged);
+        }
+
+        if layers.is_empty() {
+            Ok(CosignArtifactChange::Delete)
+        } else {
+            Ok(CosignArtifactChange::Write(self.cosign_artifact(layers).await?))
+        }
+    }
+
+    /// A Cosign signature artifact manifest with the given layers
+    async fn cosign_artifact(&self, layers: Vec<serde_json::Value>) -> Result<Bytes> {
+        // The image config cosign writes for signature artifacts
+        let diff_ids: Vec<serde_json::Value> = layers.iter().filter_map(|layer| layer.get("digest").cloned()).collect();
+        let config = serde_json::to_vec(&serde_json::json!({
+            "architecture": "",
+            "config": {},
+            "created": "0001-01-01T00:00:00Z",
+            "history": [{ "created": "0001-01-01T00:00:00Z" }],
+            "os": "",
+            "rootfs": { "type": "layers", "diff_ids": diff_ids },
+        }))?;
+        let config_digest = self.put_content_blob(&config).await?;
+
+        let manifest = serde_json::to_vec(&serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": OCI_MANIFEST_MEDIA_TYPE,
+            "config": {
+                "mediaType": OCI_CONFIG_MEDIA_TYPE,
+                "size": config.len(),
+                "digest": config_digest,
+            },
+            "layers": layers,
+        }))?;
+
+        Ok(manifest.into())
+    }
+
+    /// Store `data` as a content-addressed blob, returning its digest
+    async fn put_content_blob(&self, data: &[u8]) -> Result<String> {
+        let digest = format!("sha256:{}", hex::encode(Sha256::digest(data)));
+        if !self.storage.blob_exists(&digest).await? {
+            self.storage.put_blob(&digest, data.to_vec().into()).await?;
+        }
+        Ok(digest)
+    }
+
+    /// Read the Cosign signatures pushed for `content_digest` in `repository`,
+    /// e.g. by `cosign sign`. Each is attributed to the configured verification
+    /// key it verifies against, if any.
+    async fn discover_cosign_signatures(&self, repository: &str, content_digest: &str) -> Result<Vec<ContentSignature>> {
+        let tag = cosign_signature_tag(content_digest);
+        let artifact = self.storage.get_manifest(repository, &tag).await?;
+        let layers = cosign_artifact_layers(artifact.as_deref())?;
+        if layers.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let verification_keys: Vec<VerificationKey> =
+            self.key_store.read().await.verification_keys.values().cloned().collect();
+
+        let mut signatures = Vec::new();
+        for layer in &layers {
+            match self.cosign_layer_signature(repository, content_digest, layer, &verification_keys).await {
+                Ok(Some(signature)) => signatures.push(signature),
+                Ok(None) => {}
+                Err(e) => warn!("Skipping unreadable Cosign signature in {}:{}: {}", repository, tag, e),
+            }
+        }
+
+        debug!("Found {} Cosign signatures in {}:{}", signatures.len(), repository, tag);
+        Ok(signatures)
+    }
+
+    /// Turn one layer of a Cosign signature artifact into a signature
+    async fn cosign_layer_signature(
+        &self,
+        repository: &str,
+        content_digest: &str,
+        layer: &serde_json::Value,
+        verification_keys: &[VerificationKey],
+    ) -> Result<Option<ContentSignature>> {
+        if layer.get("mediaType").and_then(|media_type| media_type.as_str()) != Some(COSIGN_PAYLOAD_MEDIA_TYPE) {
+            return Ok(None);
+        }
+        let Some(encoded) = cosign_layer_annotation(layer, COSIGN_SIGNATURE_ANNOTATION) else {
+            return Ok(None);
+        };
+
+        let signature = general_purpose::STANDARD.decode(encoded)
+            .map_err(|e| anyhow::anyhow!("invalid signature annotation: {}", e))?;
+        let payload_digest = layer.get("digest").and_then(|digest| digest.as_str())
+            .ok_or_else(|| anyhow::anyhow!("layer has no digest"))?;
+        let payload = self.storage.get_blob(payload_digest).await?
+            .ok_or_else(|| anyhow::anyhow!("payload blob {} is missing", payload_digest))?
+            .to_vec();
+
+        let mut certificate_chain = Vec::new();
+        for annotation in [COSIGN_CERTIFICATE_ANNOTATION, COSIGN_CHAIN_ANNOTATION] {
+            if let Some(pem) = cosign_layer_annotation(layer, annotation) {
+                certificate_chain.extend(pem_certificates(pem)?);
+            }
+        }
+
+        let key = verification_keys.iter()
+            .find(|key| self.verify_bytes(&payload, &signature, key).unwrap_or(false));
+
+        // cosign only records a timestamp in the payload when asked to
+        let document: serde_json::Value = serde_json::from_slice(&payload)?;
+        let timestamp = document.pointer("/optional/timestamp")
+            .and_then(|timestamp| timestamp.as_i64())
+            .and_then(|timestamp| chrono::DateTime::from_timestamp(timestamp, 0))
+            .unwrap_or_else(chrono::Utc::now);
+
+        Ok(Some(ContentSignature {
+            signature_id: format!("cosign-{}", hex::encode(Sha256::digest(&signature))),
+            content_digest: content_digest.to_string(),
+            format: SignatureFormat::Cosign,
+            // Without a matching key the algorithm can't be known; this is cosign's default
+            algorithm: key.map_or(SignatureAlgorithm::EcdsaP256Sha256, |key| key.algorithm.clone()),
+            signature,
+            key_id: key.map_or_else(|| "unknown".to_string(), |key| key.key_id.clone()),
+            certificate_chain: (!certificate_chain.is_empty()).then_some(certificate_chain),
+            payload: SignaturePayload {
+                subject: format!("sha256:{}", content_digest),
+                content_type: "manifest".to_string(),
+                repository: repository.to_string(),
+                tag: document.pointer("/optional/tag").and_then(|tag| tag.as_str()).map(str::to_string),
+                metadata: HashMap::new(),
+                timestamp,
+            },
+            signed_payload: Some(payload),
+            created_at: timestamp,
+            expires_at: None,
+        }))
+    }
+
+    /// Create Cosign-compatible signature
+    async fn create_cosign_signature(
+        &self,
+        signing_key: &SigningKey,
+        payload: &SignaturePayload,
+    ) -> Result<Vec<u8>> {
+        debug!("Creating Cosign signature");
+
+        let payload_bytes = self.cosign_payload(payload)?;
+
+        // Sign the payload
+        self.sign_bytes(&payload_bytes, signing_key).await
+    }
+
+    /// Build the Cosign simple-signing payload document
+    fn cosign_payload(&self, payload: &SignaturePayload) -> Result<Vec<u8>> {
+        let cosign_payload = serde_json::json!({
+            "critical": {
+                "identity": {
+                    "docker-reference": payload.repository
+                },
+                "image": {
+                    "docker-manifest-digest": payload.subject
+                },
+                "type": "cosign container image signature"
+            },
+            "optional": {
+                "timestamp": payload.timestamp.timestamp(),
+                "tag": payload.tag,
+                "metadata": payload.metadata
+            }
+        });
+
+        Ok(serde_json::to_vec(&cosign_payload)?)
+    }
+
+    /// Create Notary v2 signature (JWS)
+    async fn create_notary_v2_signature(
+        &self,
+        content: &[u8],
+        signing_key: &SigningKey,
+        payload: &SignaturePayload,
+    ) -> Result<Vec<u8>> {
+        debug!("Creating Notary v2 signature");
+
+        // Create JWS header; the certificate travels in x5c so verifiers can build the chain
+        let mut header = serde_json::json!({
+            "alg": self.algorithm_to_jws_alg(&signing_key.algorithm),
+            "typ": "JWT",
+            "kid": signing_key.key_id
+        });
+        if let Some(certificate) = &signing_key.certificate {
+            header["x5c"] = serde_json::json!([general_purpose::STANDARD.encode(certificate)]);
+        }
+
+        // Create JWS payload. The subject binds the signature to this exact content; the
+        // signature lasts as long as the key does.
+        let mut jws_payload = serde_json::json!({
+            "sub": format!("sha256:{}", hex::encode(Sha256::digest(content))),
+            "iat": payload.timestamp.timestamp(),
+            "iss": "drift-registry",
+            "nbf": payload.timestamp.timestamp(),
+            "repository": payload.repository,
+            "tag": payload.tag,
+            "content_type": payload.content_type,
+            "metadata": payload.metadata
+        });
+        if let Some(expires_at) = signing_key.expires_at {
+            jws_payload["exp"] = serde_json::json!(expires_at.timestamp());
+        }
+
+        // Encode JWS
+        let header_b64 = general_purpose::URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?);
+        let payload_b64 = general_purpose::URL_SAFE_NO_PAD.encode(serde_json::to_vec(&jws_payload)?);
+        let signing_input = format!("{}.{}", header_b64, payload_b64);
+
+        let signature_bytes = self.sign_bytes(signing_input.as_bytes(), signing_key).await?;
+        let signature_b64 = general_purpose::URL_SAFE_NO_PAD.encode(jose_signature(&signing_key.algorithm, &signature_bytes)?);
+
+        let jws = format!("{}.{}.{}", header_b64, payload_b64, signature_b64);
+        Ok(jws.into_bytes())
+    }
+
+    /// Create simple JSON signature
+    async fn create_simple_signature(
+        &self,
+        content: &[u8],
+        signing_key: &SigningKey,
+        payload: &SignaturePayload,
+    ) -> Result<Vec<u8>> {
+        debug!("Creating simple signature");
+
+        let signing_input = self.simple_signing_input(content, payload)?;
+        self.sign_bytes(signing_input.as_bytes(), signing_key).await
+    }
+
+    /// Build the canonical signing input for simple signatures.
+    /// The payload is routed through `serde_json::Value` so object keys are
+    /// sorted and the input is stable across serialization round-trips.
+    fn simple_signing_input(&self, content: &[u8], payload: &SignaturePayload) -> Result<String> {
+        let content_digest = hex::encode(Sha256::digest(content));
+        let canonical_payload = serde_json::to_value(payload)?.to_string();
+        Ok(format!("{}:{}", content_digest, canonical_payload))
+    }
+
+    /// Create in-toto attestation signature
+    async fn create_in_toto_signature(
+        &self,
+        content: &[u8],
+        signing_key: &SigningKey,
+        payload: &SignaturePayload,
+    ) -> Result<Vec<u8>> {
+        debug!("Creating in-toto signature");
+
+        let attestation = serde_json::json!({
+            "_type": "link",
+            "subject": [{
+                "name": payload.subject,
+                "digest": {
+                    "sha256": hex::encode(Sha256::digest(content))
+                }
+            }],
+            "predicateType": "https://in-toto.io/Statement/v0.1",
+            "predicate": {
+                "repository": payload.repository,
+                "tag": payload.tag,
+                "content_type": payload.content_type,
+                "timestamp": payload.timestamp,
+                "metadata": payload.metadata
+            }
+        });
+
+        let attestation_bytes = serde_json::to_vec(&attestation)?;
+        self.sign_bytes(&attestation_bytes, signing_key).await
+    }
+
+    /// Sign bytes with the given key
+    async fn sign_bytes(&self, data: &[u8], signing_key: &SigningKey) -> Result<Vec<u8>> {
+        match signing_key.algorithm {
+            SignatureAlgorithm::RsaPssSha256 => {
+                use rsa::signature::{RandomizedSigner, SignatureEncoding};
+
+                let key = rsa::pss::SigningKey::<Sha256>::new(parse_rsa_private_key(&signing_key.private_key)?);
+                let signature = key
+                    .try_sign_with_rng(&mut rand::thread_rng(), data)
+                    .map_err(|e| anyhow::anyhow!("RSA-PSS signing failed: {}", e))?;
+                Ok(signature.to_vec())
+            }
+            SignatureAlgorithm::RsaPkcs1Sha256 => {
+                use rsa::signature::{SignatureEncoding, Signer};
+
+                let key = rsa::pkcs1v15::SigningKey::<Sha256>::new(parse_rsa_private_key(&signing_key.private_key)?);
+                let signature = key
+                    .try_sign(data)
+                    .map_err(|e| anyhow::anyhow!("RSA PKCS#1 signing failed: {}", e))?;
+                Ok(signature.to_vec())
+            }
+            SignatureAlgorithm::EcdsaP256Sha256 => {
+                use p256::ecdsa::{signature::hazmat::PrehashSigner, Signature};
+
+                let key = parse_p256_signing_key(&signing_key.private_key)?;
+                let prehash = Sha256::digest(data);
+                let signature: Signature = key
+                    .sign_prehash(&prehash)
+                    .map_err(|e| anyhow::anyhow!("ECDSA P-256 signing failed: {}", e))?;
+
+                // Emit low-S normalized signatures, matching what Cosign produces
+                let signature = signature.normalize_s().unwrap_or(signature);
+                Ok(signature.to_der().as_bytes().to_vec())
+            }
+            SignatureAlgorithm::EcdsaP384Sha384 => {
+                use p384::ecdsa::{signature::Signer, Signature};
+
+                let key = parse_p384_signing_key(&signing_key.private_key)?;
+                let signature: Signature = key
+                    .try_sign(data)
+                    .map_err(|e| anyhow::anyhow!("ECDSA P-384 signing failed: {}", e))?;
+
+                let signature = signature.normalize_s().unwrap_or(signature);
+                Ok(signature.to_der().as_bytes().to_vec())
+            }
+            SignatureAlgorithm::Ed25519 => {
+                use ed25519_dalek::Signer;
+
+                let key = parse_ed25519_signing_key(&signing_key.private_key)?;
+                Ok(key.sign(data).to_bytes().to_vec())
+            }
+        }
+    }
+
+    /// Verify a raw signature over bytes with the given key
+    fn verify_bytes(&self, data: &[u8], signature: &[u8], verification_key: &VerificationKey) -> Result<bool> {
+        match verification_key.algorithm {
+            SignatureAlgorithm::EcdsaP256Sha256 => {
+                use p256::ecdsa::{signature::hazmat::PrehashVerifier, Signature};
+
+                let key = parse_p256_verifying_key(&verification_key.public_key)?;
+                let signature = match Signature::from_der(signature).or_else(|_| Signature::from_slice(signature)) {
+                    Ok(signature) => signature,
+                    Err(_) => return Ok(false),
+                };
+
+                // Accept both high-S and low-S encodings; Cosign emits normalized ones
+                let signature = signature.normalize_s().unwrap_or(signature);
+                let prehash = Sha256::digest(data);
+                Ok(key.verify_prehash(&prehash, &signature).is_ok())
+            }
+            SignatureAlgorithm::RsaPkcs1Sha256 => {
+                use rsa::signature::Verifier;
+
+                let key = rsa::pkcs1v15::VerifyingKey::<Sha256>::new(parse_rsa_public_key(&verification_key.public_key)?);
+                let signature = match rsa::pkcs1v15::Signature::try_from(signature) {
+                    Ok(signature) => signature,
+                    Err(_) => return Ok(false),
+                };
+                Ok(key.verify(data, &signature).is_ok())
+            }
+            SignatureAlgorithm::RsaPssSha256 => {
+                use rsa::signature::Verifier;
+
+                let key = rsa::pss::VerifyingKey::<Sha256>::new(parse_rsa_public_key(&verification_key.public_key)?);
+                let signature = match rsa::pss::Signature::try_from(signature) {
+                    Ok(signature) => signature,
+                    Err(_) => return Ok(false),
+                };
+                Ok(key.verify(data, &signature).is_ok())
+            }
+            SignatureAlgorithm::EcdsaP384Sha384 => {
+                use p384::ecdsa::{signature::Verifier, Signature};
+
+                let key = parse_p384_verifying_key(&verification_key.public_key)?;
+                let signature = match Signature::from_der(signature).or_else(|_| Signature::from_slice(signature)) {
+                    Ok(signature) => signature,
+                    Err(_) => return Ok(false),
+                };
+
+                let signature = signature.normalize_s().unwrap_or(signature);
+                Ok(key.verify(data, &signature).is_ok())
+            }
+            SignatureAlgorithm::Ed25519 => {
+                let key = parse_ed25519_verifying_key(&verification_key.public_key)?;
+                let signature = match ed25519_dalek::Signature::from_slice(signature) {
+                    Ok(signature) => signature,
+                    Err(_) => return Ok(false),
+                };
+                Ok(key.verify_strict(data, &signature).is_ok())
+            }
+        }
+    }
+
+    /// Look up the verification key referenced by a signature
+    async fn get_verification_key(&self, key_id: &str) -> Option<VerificationKey> {
+        let key_store = self.key_store.read().await;
+        key_store.verification_keys.get(key_id).cloned()
+    }
+
+    /// Build a chain from the leaf certificate to a trusted root in one of the policy's trust stores
+    async fn validate_certificate_chain(&self, chain: &[Vec<u8>], policy: &VerificationPolicy) -> Result<()> {
+        let key_store = self.key_store.read().await;
+        let trust_stores: Vec<&TrustStore> = key_store.trust_stores.values()
+            .filter(|store| policy.trust_stores.is_empty() || policy.trust_stores.contains(&store.name))
+            .collect();
+
+        if trust_stores.is_empty() {
+            return Err(anyhow::anyhow!("no trust stores configured for verification"));
+        }
+
+        let presented = chain.iter()
+            .map(|der| parse_certificate(der))
+            .collect::<Result<Vec<_>>>()?;
+        let leaf = &presented[0];
+
+        let mut last_error = anyhow::anyhow!("no trust stores configured for verification");
+        for store in trust_stores {
+            let roots = store.root_certificates.iter()
+                .map(|der| parse_certificate(der))
+                .collect::<Result<Vec<_>>>()?;
+            let store_intermediates = store.intermediate_certificates.iter()
+                .map(|der| parse_certificate(der))
+                .collect::<Result<Vec<_>>>()?;
+            let intermediates: Vec<&X509Certificate> = presented[1..].iter()
+                .chain(store_intermediates.iter())
+                .collect();
+
+            if store.require_revocation_check && !store.revocation_checked {
+                last_error = anyhow::anyhow!("trust store {}: revocation status is unavailable", store.name);
+                continue;
+            }
+
+            match build_chain(leaf, &intermediates, &roots, &store.revoked_certificates) {
+                Ok(()) => {
+                    debug!("Certificate chain validated against trust store {}", store.name);
+                    return Ok(());
+                }
+                Err(e) => last_error = anyhow::anyhow!("trust store {}: {}", store.name, e),
+            }
+        }
+
+        Err(last_error)
+    }
+
+    /// Reject signatures that have expired or exceed the policy's maximum age
+    fn check_signature_age(&self, signature: &ContentSignature, policy: &VerificationPolicy) -> Option<String> {
+        let now = chrono::Utc::now();
+
+        if let Some(expires_at) = signature.expires_at
+            && expires_at <= now
+        {
+            return Some(format!("Signature expired at {}", expires_at.to_rfc3339()));
+        }
+
+        if let Some(max_age_hours) = policy.max_signature_age_hours {
+            let age = now.signed_duration_since(signature.created_at);
+            if age > chrono::Duration::hours(max_age_hours as i64) {
+                return Some(format!(
+                    "Signature created at {} is older than the maximum age of {} hours",
+                    signature.created_at.to_rfc3339(),
+                    max_age_hours
+                ));
+            }
+        }
+
+        None
+    }
+
+    /// Build a failed verification result for a signature
+    fn failed_verification(&self, signature: &ContentSignature, errors: Vec<String>) -> VerificationResult {
+        VerificationResult {
+            valid: false,
+            trusted: false,
+            key_id: signature.key_id.clone(),
+            algorithm: signature.algorithm.clone(),
+            verified_at: chrono::Utc::now(),
+            certificate_chain_valid: None,
+            errors,
+            warnings: vec![],
+        }
+    }
+
+    /// Convert signature algorithm to JWS algorithm identifier
+    fn algorithm_to_jws_alg(&self, algorithm: &SignatureAlgorithm) -> &'static str {
+        match algorithm {
+            SignatureAlgorithm::RsaPssSha256 => "PS256",
+            SignatureAlgorithm::RsaPkcs1Sha256 => "RS256",
+            SignatureAlgorithm::EcdsaP256Sha256 => "ES256",
+            SignatureAlgorithm::EcdsaP384Sha384 => "ES384",
+            SignatureAlgorithm::Ed25519 => "EdDSA",
+        }
+    }
+
+    /// Verify Cosign signature
+    async fn verify_cosign_signature(
+        &self,
+        content: &[u8],
+        signature: &ContentSignature,
+        policy: &VerificationPolicy,
+    ) -> Result<VerificationResult> {
+        let payload_bytes = match &signature.signed_payload {
+            Some(bytes) => bytes.clone(),
+            None => self.cosign_payload(&signature.payload)?,
+        };
+
+        // The simple-signing payload must describe exactly this content
+        let cosign_payload: serde_json::Value = match serde_json::from_slice(&payload_bytes) {
+            Ok(value) => value,
+            Err(e) => {
+                return Ok(self.failed_verification(
+                    signature,
+                    vec![format!("Malformed Cosign payload: {}", e)],
+                ));
+            }
+        };
+
+        let signature_type = cosign_payload
+            .pointer("/critical/type")
+            .and_then(|t| t.as_str())
+            .unwrap_or("");
+        if signature_type != "cosign container image signature" {
+            return Ok(self.failed_verification(
+                signature,
+                vec![format!("Unexpected Cosign payload type '{}'", signature_type)],
+            ));
+        }
+
+        let expected_digest = format!("sha256:{}", hex::encode(Sha256::digest(content)));
+        let payload_digest = cosign_payload
+            .pointer("/critical/image/docker-manifest-digest")
+            .and_then(|d| d.as_str())
+            .unwrap_or("");
+        if payload_digest != expected_digest {
+            return Ok(self.failed_verification(
+                signature,
+                vec![format!(
+                    "Cosign payload digest {} does not match content digest {}",
+                    payload_digest, expected_digest
+                )],
+            ));
+        }
+
+        // Certificate chain requirements
+        let has_chain = signature.certificate_chain.as_ref().is_some_and(|chain| !chain.is_empty());
+        if policy.require_certificate_chain && !has_chain {
+            return Ok(self.failed_verification(
+                signature,
+                vec!["Certificate chain required by policy but none was provided".to_string()],
+            ));
+        }
+
+        let verification_key = match self.get_verification_key(&signature.key_id).await {
+            Some(key) => key,
+            None => {
+                return Ok(self.failed_verification(
+                    signature,
+                    vec![format!("Verification key not found: {}", signature.key_id)],
+                ));
+            }
+        };
+
+        if verification_key.algorithm != signature.algorithm {
+            return Ok(self.failed_verification(
+                signature,
+                vec![format!(
+                    "Signature algorithm {:?} does not match key algorithm {:?}",
+                    signature.algorithm, verification_key.algorithm
+                )],
+            ));
+        }
+
+        // A bare key that nobody vouched for is effectively self-signed
+        if !policy.allow_self_signed && !has_chain && !verification_key.trusted {
+            return Ok(self.failed_verification(
+                signature,
+                vec![format!(
+                    "Key {} is self-signed and policy does not allow self-signed signatures",
+                    signature.key_id
+                )],
+            ));
+        }
+
+        let valid = match self.verify_bytes(&payload_bytes, &signature.signature, &verification_key) {
+            Ok(valid) => valid,
+            Err(e) => return Ok(self.failed_verification(signature, vec![e.to_string()])),
+        };
+
+        Ok(VerificationResult {
+            valid,
+            trusted: valid && verification_key.trusted,
+            key_id: signature.key_id.clone(),
+            algorithm: signature.algorithm.clone(),
+            verified_at: chrono::Utc::now(),
+            certificate_chain_valid: None,
+            errors: if valid { vec![] } else { vec!["Cosign signature does not match payload".to_string()] },
+            warnings: vec![],
+        })
+    }
+
+    /// Verify a Notary v2 signature: a compact JWS over claims whose subject is the content
+    /// digest. The certificate chain comes from the `x5c` header, or else the stored chain.
+    async fn verify_notary_v2_signature(
+        &self,
+        content: &[u8],
+        signature: &ContentSignature,
+        policy: &VerificationPolicy,
+    ) -> Result<VerificationResult> {
+        let jws = std::str::from_utf8(&signature.signature).unwrap_or("");
+        let parts: Vec<&str> = jws.split('.').collect();
+        let [header_b64, claims_b64, signature_b64] = parts[..] else {
+            return Ok(self.failed_verification(signature, vec!["Notary v2 signature is not a compact JWS".to_string()]));
+        };
+
+        let decode = |part: &str| general_purpose::URL_SAFE_NO_PAD.decode(part);
+        let (Ok(header), Ok(claims), Ok(signature_bytes)) = (decode(header_b64), decode(claims_b64), decode(signature_b64))
+        else {
+            return Ok(self.failed_verification(signature, vec!["JWS is not valid base64url".to_string()]));
+        };
+        let (Ok(header), Ok(claims)) = (
+            serde_json::from_slice::<serde_json::Value>(&header),
+            serde_json::from_slice::<serde_json::Value>(&claims),
+        ) else {
+            return Ok(self.failed_verification(signature, vec!["JWS header or claims are not JSON".to_string()]));
+        };
+
+        // Protected header: the algorithm must be one the policy allows and the one recorded
+        let alg = header.get("alg").and_then(|alg| alg.as_str()).unwrap_or("");
+        let Some(algorithm) = jws_alg_to_algorithm(alg) else {
+            return Ok(self.failed_verification(signature, vec![format!("Unsupported JWS algorithm '{}'", alg)]));
+        };
+        if !policy.allowed_algorithms.contains(&algorithm) {
+            return Ok(self.failed_verification(signature, vec![format!("JWS algorithm {} not allowed", alg)]));
+        }
+        if algorithm != signature.algorithm {
+            return Ok(self.failed_verification(
+                signature,
+                vec![format!("JWS algorithm {} does not match signature algorithm {:?}", alg, signature.algorithm)],
+            ));
+        }
+        // No critical extensions are understood, so any makes the JWS unverifiable
+        if let Some(crit) = header.get("crit") {
+            return Ok(self.failed_verification(signature, vec![format!("Unsupported critical JWS headers {}", crit)]));
+        }
+
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+
+        match header.get("kid").and_then(|kid| kid.as_str()) {
+            Some(kid) if kid != signature.key_id => {
+                return Ok(self.failed_verification(
+                    signature,
+                    vec![format!("JWS key id {} does not match signature key {}", kid, signature.key_id)],
+                ));
+            }
+            Some(_) => {}
+            None => warnings.push("JWS header has no key id".to_string()),
+        }
+
+        let chain = match header.get("x5c") {
+            Some(x5c) => match decode_x5c(x5c) {
+                Ok(chain) => Some(chain),
+                Err(e) => return Ok(self.failed_verification(signature, vec![format!("Invalid x5c header: {}", e)])),
+            },
+            None => signature.certificate_chain.clone().filter(|chain| !chain.is_empty()),
+        };
+
+        // A configured key, or else the leaf certificate's key, trusted only through its chain
+        let verification_key = match self.get_verification_key(&signature.key_id).await {
+            Some(key) => key,
+            None => match chain.as_ref().and_then(|chain| chain.first()) {
+                Some(leaf) => match certificate_verification_key(&signature.key_id, &algorithm, leaf) {
+                    Ok(key) => key,
+                    Err(e) => return Ok(self.failed_verification(signature, vec![e.to_string()])),
+                },
+                None => {
+                    return Ok(self.failed_verification(
+                        signature,
+                        vec![format!("Verification key not found: {}", signature.key_id)],
+                    ));
+                }
+            },
+        };
+        if verification_key.algorithm != algorithm {
+            return Ok(self.failed_verification(
+                signature,
+                vec![format!(
+                    "Signature algorithm {:?} does not match key algorithm {:?}",
+                    algorithm, verification_key.algorithm
+                )],
+            ));
+        }
+
+        let signing_input = format!("{}.{}", header_b64, claims_b64);
+        match self.verify_bytes(signing_input.as_bytes(), &signature_bytes, &verification_key) {
+            Ok(true) => {}
+            Ok(false) => errors.push("JWS signature does not match its header and claims".to_string()),
+            Err(e) => errors.push(e.to_string()),
+        }
+
+        // Claims: the subject must be this content, and the clock within exp/nbf give or take the skew
+        let content_digest = hex::encode(Sha256::digest(content));
+        match claims.get("sub").and_then(|sub| sub.as_str()) {
+            Some(sub) if sub.strip_prefix("sha256:").unwrap_or(sub) == content_digest => {}
+            Some(sub) => errors.push(format!("JWS subject {} does not match content digest sha256:{}", sub, content_digest)),
+            None => errors.push("JWS claims have no subject".to_string()),
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        let skew = self.config.clock_skew_secs as i64;
+        match claims.get("exp").map(|exp| exp.as_i64()) {
+            Some(Some(exp)) if now > exp + skew => errors.push(format!("JWS expired at {}", jws_time(exp))),
+            Some(None) => errors.push("JWS exp claim is not a timestamp".to_string()),
+            _ => {}
+        }
+        match claims.get("nbf").map(|nbf| nbf.as_i64()) {
+            Some(Some(nbf)) if now + skew < nbf => errors.push(format!("JWS is not valid before {}", jws_time(nbf))),
+            Some(None) => errors.push("JWS nbf claim is not a timestamp".to_string()),
+            _ => {}
+        }
+
+        let certificate_chain_valid = match &chain {
+            Some(chain) if !leaf_certifies_key(chain, &verification_key) => {
+                errors.push(format!(
+                    "Certificate chain validation failed: leaf certificate is not for key {}",
+                    signature.key_id
+                ));
+                Some(false)
+            }
+            Some(chain) => match self.validate_certificate_chain(chain, policy).await {
+                Ok(()) => Some(true),
+                Err(e) => {
+                    errors.push(format!("Certificate chain validation failed: {}", e));
+                    Some(false)
+                }
+            },
+            None => {
+                if policy.require_certificate_chain {
+                    errors.push("Certificate chain required by policy but none was provided".to_string());
+                }
+                None
+            }
+        };
+
+        // A bare key that nobody vouched for is effectively self-signed
+        if chain.is_none() && !verification_key.trusted {
+            if policy.allow_self_signed {
+                warnings.push(format!("Key {} is not trusted", signature.key_id));
+            } else {
+                errors.push(format!(
+                    "Key {} is self-signed and policy does not allow self-signed signatures",
+                    signature.key_id
+                ));
+            }
+        }
+
+        let valid = errors.is_empty();
+        Ok(VerificationResult {
+            valid,
+            trusted: valid && (verification_key.trusted || certificate_chain_valid == Some(true)),
+            key_id: signature.key_id.clone(),
+            algorithm,
+            verified_at: chrono::Utc::now(),
+            certificate_chain_valid,
+            errors,
+            warnings,
+        })
+    }
+
+    /// Verify simple signature
+    async fn verify_simple_signature(
+        &self,
+        content: &[u8],
+        signature: &ContentSignature,
+        _policy: &VerificationPolicy,
+    ) -> Result<VerificationResult> {
+        let verification_key = match self.get_verification_key(&signature.key_id).await {
+            Some(key) => key,
+            None => {
+                return Ok(self.failed_verification(
+                    signature,
+                    vec![format!("Verification key not found: {}", signature.key_id)],
+                ));
+            }
+        };
+
+        if verification_key.algorithm != signature.algorithm {
+            return Ok(self.failed_verification(
+                signature,
+                vec![format!(
+                    "Signature algorithm {:?} does not match key algorithm {:?}",
+                    signature.algorithm, verification_key.algorithm
+                )],
+            ));
+        }
+
+        let signing_input = self.simple_signing_input(content, &signature.payload)?;
+        let valid = match self.verify_bytes(signing_input.as_bytes(), &signature.signature, &verification_key) {
+            Ok(valid) => valid,
+            Err(e) => return Ok(self.failed_verification(signature, vec![e.to_string()])),
+        };
+
+        Ok(VerificationResult {
+            valid,
+            trusted: valid && verification_key.trusted,
+            key_id: signature.key_id.clone(),
+            algorithm: signature.algorithm.clone(),
+            verified_at: chrono::Utc::now(),
+            certificate_chain_valid: None,
+            errors: if valid { vec![] } else { vec!["Signature does not match content".to_string()] },
+            warnings: vec![],
+        })
+    }
+
+    /// Verify in-toto signature
+    async fn verify_in_toto_signature(
+        &self,
         _content: &[u8],
         signature: &ContentSignature,
         _policy: &VerificationPolicy,
     ) -> Result<VerificationResult> {
-        // TODO: Implement actual simple signature verification
-        warn!("Simple signature verification not fully implemented");
+        // TODO: Implement actual in-toto signature verification
+        warn!("In-toto signature verification not fully implemented");
+
+        Ok(VerificationResult {
+            valid: true, // Placeholder
+            trusted: false,
+            key_id: signature.key_id.clone(),
+            algorithm: signature.algorithm.clone(),
+            verified_at: chrono::Utc::now(),
+            certificate_chain_valid: None,
+            errors: vec![],
+            warnings: vec!["In-toto verification not fully implemented".to_string()],
+        })
+    }
+
+    /// Cache verification result
+    async fn cache_verification_result(
+        &self,
+        signature_id: &str,
+        policy_fingerprint: &str,
+        signature: ContentSignature,
+        result: VerificationResult,
+    ) {
+        let mut cache = self.signature_cache.write().await;
+        cache.insert((signature_id.to_string(), policy_fingerprint.to_string()), CachedSignature {
+            signature,
+            verification_result: result,
+            cached_at: chrono::Utc::now(),
+        });
+    }
+
+    /// Get cached verification result
+    async fn get_cached_verification(&self, signature_id: &str, policy_fingerprint: &str) -> Option<CachedSignature> {
+        let cache = self.signature_cache.read().await;
+        cache.get(&(signature_id.to_string(), policy_fingerprint.to_string())).cloned()
+    }
+
+    /// Load signing key from a PKCS#8 PEM file
+    async fn load_signing_key(&self, key_config: &crate::config::SigningKeyConfig) -> Result<SigningKey> {
+        debug!("Loading signing key {} from {}", key_config.key_id, key_config.key_path);
+
+        let pem = tokio::fs::read_to_string(&key_config.key_path).await
+            .map_err(|e| anyhow::anyhow!(
+                "Failed to read signing key {} from {}: {}",
+                key_config.key_id, key_config.key_path, e
+            ))?;
+
+        let private_key = decode_private_key_pem(&pem, key_config.password.as_deref())
+            .map_err(|e| anyhow::anyhow!("Invalid signing key {}: {}", key_config.key_id, e))?;
+
+        let key_algorithm = private_key_algorithm(&private_key)
+            .map_err(|e| anyhow::anyhow!("Invalid signing key {}: {}", key_config.key_id, e))?;
+        if !key_algorithm.contains(&key_config.algorithm) {
+            return Err(anyhow::anyhow!(
+                "Signing key {} is configured as {:?} but {} contains a {:?} key",
+                key_config.key_id, key_config.algorithm, key_config.key_path, key_algorithm[0]
+            ));
+        }
+
+        // Parse the key now so a bad key fails at startup rather than on first use
+        parse_signing_key(&key_config.algorithm, &private_key)
+            .map_err(|e| anyhow::anyhow!("Invalid signing key {}: {}", key_config.key_id, e))?;
+
+        let certificate = match &key_config.certificate_path {
+            Some(path) => Some(load_certificate_der(path).await?),
+            None => None,
+        };
+
+        Ok(SigningKey {
+            key_id: key_config.key_id.clone(),
+            algorithm: key_config.algorithm.clone(),
+            private_key,
+            certificate,
+            created_at: chrono::Utc::now(),
+            expires_at: None,
+            retired: false,
+        })
+    }
+
+    /// Load verification key from a PEM public key file (e.g. cosign.pub)
+    async fn load_verification_key(&self, key_config: &crate::config::VerificationKeyConfig) -> Result<VerificationKey> {
+        debug!("Loading verification key {} from {}", key_config.key_id, key_config.public_key_path);
+
+        let pem = tokio::fs::read_to_string(&key_config.public_key_path).await
+            .map_err(|e| anyhow::anyhow!(
+                "Failed to read verification key {} from {}: {}",
+                key_config.key_id, key_config.public_key_path, e
+            ))?;
+
+        let (label, public_key) = pkcs8::der::pem::decode_vec(pem.trim().as_bytes())
+            .map_err(|e| anyhow::anyhow!("Invalid verification key {}: {}", key_config.key_id, e))?;
+        if label != "PUBLIC KEY" {
+            return Err(anyhow::anyhow!(
+                "Verification key {} must be a PEM PUBLIC KEY, found {}",
+                key_config.key_id, label
+            ));
+        }
+
+        let certificate = match &key_config.certificate_path {
+            Some(path) => Some(load_certificate_der(path).await?),
+            None => None,
+        };
+
+        Ok(VerificationKey {
+            key_id: key_config.key_id.clone(),
+            algorithm: key_config.algorithm.clone(),
+            public_key,
+            certificate,
+            trusted: key_config.trusted,
+            created_at: chrono::Utc::now(),
+            expires_at: None,
+        })
+    }
+
+    /// Load trust store from configuration
+    async fn load_trust_store(&self, trust_config: &crate::config::TrustStoreConfig) -> Result<TrustStore> {
+        debug!("Loading trust store {}", trust_config.name);
+
+        let mut root_certificates = Vec::new();
+        for path in &trust_config.root_certificate_paths {
+            root_certificates.push(load_certificate_der(path).await?);
+        }
+
+        let mut intermediate_certificates = Vec::new();
+        for path in &trust_config.intermediate_certificate_paths {
+            intermediate_certificates.push(load_certificate_der(path).await?);
+        }
+
+        let issuers: Vec<Vec<u8>> = root_certificates.iter().chain(&intermediate_certificates).cloned().collect();
+        let (revoked_certificates, revocation_checked) =
+            fetch_revoked_serials(&trust_config.name, &trust_config.crl_urls, &issuers).await;
+
+        Ok(TrustStore {
+            name: trust_config.name.clone(),
+            root_certificates,
+            intermediate_certificates,
+            revoked_certificates,
+            revocation_checked,
+            require_revocation_check: trust_config.require_revocation_check,
+        })
+    }
+}
+
+const OID_RSA_ENCRYPTION: pkcs8::ObjectIdentifier = pkcs8::ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.1");
+const OID_RSASSA_PSS: pkcs8::ObjectIdentifier = pkcs8::ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.10");
+const OID_EC_PUBLIC_KEY: pkcs8::ObjectIdentifier = pkcs8::ObjectIdentifier::new_unwrap("1.2.840.10045.2.1");
+const OID_SECP256R1: pkcs8::ObjectIdentifier = pkcs8::ObjectIdentifier::new_unwrap("1.2.840.10045.3.1.7");
+const OID_SECP384R1: pkcs8::ObjectIdentifier = pkcs8::ObjectIdentifier::new_unwrap("1.3.132.0.34");
+const OID_ED25519: pkcs8::ObjectIdentifier = pkcs8::ObjectIdentifier::new_unwrap("1.3.101.112");
+
+/// Media type of the simple-signing payload layers of a Cosign signature artifact
+const COSIGN_PAYLOAD_MEDIA_TYPE: &str = "application/vnd.dev.cosign.simplesigning.v1+json";
+const COSIGN_SIGNATURE_ANNOTATION: &str = "dev.cosignproject.cosign/signature";
+const COSIGN_CERTIFICATE_ANNOTATION: &str = "dev.sigstore.cosign/certificate";
+const COSIGN_CHAIN_ANNOTATION: &str = "dev.sigstore.cosign/chain";
+const OCI_MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+const OCI_CONFIG_MEDIA_TYPE: &str = "application/vnd.oci.image.config.v1+json";
+
+/// Ids of the keys added through the API
+const MANAGED_KEY_INDEX_KEY: &str = "signing/keys/index.json";
+/// How often signing keys are checked for upcoming expiry
+const KEY_EXPIRY_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+const CRL_FETCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+/// Modulus size of RSA keys generated through the API
+const RSA_KEY_BITS: usize = 3072;
+
+impl KeyStore {
+    /// Whether any signing or verification key has this id
+    fn contains(&self, key_id: &str) -> bool {
+        self.signing_keys.contains_key(key_id) || self.verification_keys.contains_key(key_id)
+    }
+
+    /// Add a stored key: always for verification, and for signing when its private key is available
+    fn insert_stored(&mut self, stored: &StoredKey, private_key: Option<Vec<u8>>) {
+        if let Some(private_key) = private_key {
+            self.signing_keys.insert(stored.key_id.clone(), SigningKey {
+                key_id: stored.key_id.clone(),
+                algorithm: stored.algorithm.clone(),
+                private_key,
+                certificate: stored.certificate.clone(),
+                created_at: stored.created_at,
+                expires_at: stored.expires_at,
+                retired: stored.retired,
+            });
+        }
+        self.verification_keys.insert(stored.key_id.clone(), VerificationKey {
+            key_id: stored.key_id.clone(),
+            algorithm: stored.algorithm.clone(),
+            public_key: stored.public_key.clone(),
+            certificate: stored.certificate.clone(),
+            trusted: stored.trusted,
+            created_at: stored.created_at,
+            expires_at: stored.expires_at,
+        });
+    }
+}
+
+/// Public view of a key; None when there is no key with this id
+fn key_info(key_store: &KeyStore, key_id: &str) -> Result<Option<KeyInfo>> {
+    let signing_key = key_store.signing_keys.get(key_id);
+    let verification_key = key_store.verification_keys.get(key_id);
+
+    let (algorithm, public_key, certificate, created_at, expires_at) = match (signing_key, verification_key) {
+        (_, Some(key)) => (&key.algorithm, key.public_key.clone(), &key.certificate, key.created_at, key.expires_at),
+        (Some(key), None) => (
+            &key.algorithm,
+            public_key_der(&key.algorithm, &key.private_key)?,
+            &key.certificate,
+            key.created_at,
+            key.expires_at,
+        ),
+        (None, None) => return Ok(None),
+    };
+
+    Ok(Some(KeyInfo {
+        key_id: key_id.to_string(),
+        algorithm: algorithm.clone(),
+        public_key: pkcs8::der::pem::encode_string("PUBLIC KEY", pkcs8::der::pem::LineEnding::LF, &public_key)
+            .map_err(|e| anyhow::anyhow!("Failed to encode public key: {}", e))?,
+        certificate: certificate.as_deref().map(certificate_pem),
+        can_sign: signing_key.is_some_and(|key| !key.retired),
+        retired: signing_key.is_some_and(|key| key.retired),
+        trusted: verification_key.is_none_or(|key| key.trusted),
+        managed: key_store.managed.contains(key_id),
+        created_at,
+        expires_at,
+    }))
+}
+
+fn managed_key_key(key_id: &str) -> String {
+    format!("signing/keys/{}.json", key_id)
+}
+
+fn validate_key_id(key_id: &str) -> Result<()> {
+    let valid = !key_id.is_empty()
+        && key_id.len() <= 128
+        && key_id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if !valid {
+        return Err(anyhow::anyhow!(
+            "Invalid key id {}: use letters, digits, '.', '_' or '-'",
+            key_id
+        ));
+    }
+    Ok(())
+}
+
+/// Generate a private key for the algorithm, returning it as PKCS#8 DER with its DER public key
+fn generate_key_pair(algorithm: &SignatureAlgorithm) -> Result<(Vec<u8>, Vec<u8>)> {
+    use pkcs8::{EncodePrivateKey, EncodePublicKey};
+
+    let mut rng = rand::rngs::OsRng;
+    let (private_key, public_key) = match algorithm {
+        SignatureAlgorithm::RsaPssSha256 | SignatureAlgorithm::RsaPkcs1Sha256 => {
+            let key = rsa::RsaPrivateKey::new(&mut rng, RSA_KEY_BITS)?;
+            (key.to_pkcs8_der(), key.to_public_key().to_public_key_der())
+        }
+        SignatureAlgorithm::EcdsaP256Sha256 => {
+            let key = p256::ecdsa::SigningKey::random(&mut rng);
+            (key.to_pkcs8_der(), key.verifying_key().to_public_key_der())
+        }
+        SignatureAlgorithm::EcdsaP384Sha384 => {
+            let key = p384::ecdsa::SigningKey::random(&mut rng);
+            (key.to_pkcs8_der(), key.verifying_key().to_public_key_der())
+        }
+        SignatureAlgorithm::Ed25519 => {
+            let key = ed25519_dalek::SigningKey::from_bytes(&rand::random());
+            (key.to_pkcs8_der(), key.verifying_key().to_public_key_der())
+        }
+    };
+
+    let private_key = private_key.map_err(|e| anyhow::anyhow!("Failed to encode private key: {}", e))?;
+    let public_key = public_key.map_err(|e| anyhow::anyhow!("Failed to encode public key: {}", e))?;
+    Ok((private_key.as_bytes().to_vec(), public_key.into_vec()))
+}
+
+/// Encrypt a PKCS#8 DER private key into an `ENCRYPTED PRIVATE KEY` PEM
+fn encrypt_private_key_pem(der: &[u8], password: &str) -> Result<String> {
+    let info = pkcs8::PrivateKeyInfo::try_from(der)
+        .map_err(|e| anyhow::anyhow!("malformed PKCS#8 key: {}", e))?;
+    let encrypted = info
+        .encrypt(rand::rngs::OsRng, password)
+        .map_err(|e| anyhow::anyhow!("failed to encrypt key: {}", e))?;
+    let pem = encrypted
+        .to_pem("ENCRYPTED PRIVATE KEY", pkcs8::der::pem::LineEnding::LF)
+        .map_err(|e| anyhow::anyhow!("failed to encode key: {}", e))?;
+    Ok(pem.to_string())
+}
+
+/// DER public key of DER private key material
+fn public_key_der(algorithm: &SignatureAlgorithm, der: &[u8]) -> Result<Vec<u8>> {
+    use pkcs8::EncodePublicKey;
+
+    let public_key = match algorithm {
+        SignatureAlgorithm::RsaPssSha256 | SignatureAlgorithm::RsaPkcs1Sha256 => {
+            parse_rsa_private_key(der)?.to_public_key().to_public_key_der()
+        }
+        SignatureAlgorithm::EcdsaP256Sha256 => parse_p256_signing_key(der)?.verifying_key().to_public_key_der(),
+        SignatureAlgorithm::EcdsaP384Sha384 => parse_p384_signing_key(der)?.verifying_key().to_public_key_der(),
+        SignatureAlgorithm::Ed25519 => parse_ed25519_signing_key(der)?.verifying_key().to_public_key_der(),
+    };
+    Ok(public_key.map_err(|e| anyhow::anyhow!("Failed to encode public key: {}", e))?.into_vec())
+}
+
+/// When a DER certificate stops being valid
+fn certificate_not_after(der: &[u8]) -> Option<chrono::DateTime<chrono::Utc>> {
+    let certificate = parse_certificate(der).ok()?;
+    chrono::DateTime::from_timestamp(certificate.validity().not_after.timestamp(), 0)
+}
+
+/// Where the content digest of a signature is looked up by its id
+fn signature_id_key(signature_id: &str) -> String {
+    format!("signature-ids/{}", signature_id)
+}
+
+/// Reject policies whose pattern isn't a valid glob
+fn validate_repository_policies(policies: &[RepositorySignaturePolicy]) -> Result<()> {
+    for policy in policies {
+        globset::Glob::new(&policy.pattern)
+            .map_err(|e| anyhow::anyhow!("Invalid repository pattern {}: {}", policy.pattern, e))?;
+    }
+    Ok(())
+}
+
+fn repository_matches(pattern: &str, repository: &str) -> bool {
+    globset::Glob::new(pattern).is_ok_and(|glob| glob.compile_matcher().is_match(repository))
+}
+
+/// Tag cosign stores the signatures of a manifest under, from its bare hex digest
+pub fn cosign_signature_tag(content_digest: &str) -> String {
+    format!("sha256-{}.sig", content_digest)
+}
+
+/// Whether a tag is one cosign stores the signatures of a manifest under
+pub fn is_cosign_signature_tag(tag: &str) -> bool {
+    cosign_signature_tag_digest(tag).is_some()
+}
+
+/// The content digest a cosign signature tag is for
+fn cosign_signature_tag_digest(tag: &str) -> Option<&str> {
+    tag.strip_prefix("sha256-")
+        .and_then(|tag| tag.strip_suffix(".sig"))
+        .filter(|digest| digest.len() == 64 && digest.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')))
+}
+
+/// Whether a manifest is shaped like a Cosign signature artifact rather than an image
+pub fn is_cosign_signature_manifest(data: &[u8]) -> bool {
+    let Ok(manifest) = serde_json::from_slice::<serde_json::Value>(data) else {
+        return false;
+    };
+    let Some(layers) = manifest.get("layers").and_then(|layers| layers.as_array()) else {
+        return false;
+    };
+
+    !layers.is_empty()
+        && layers.iter().all(|layer| {
+            layer.get("mediaType").and_then(|media_type| media_type.as_str()) == Some(COSIGN_PAYLOAD_MEDIA_TYPE)
+        })
+}
+
+/// Layers of a Cosign signature artifact, if there is one
+fn cosign_artifact_layers(artifact: Option<&[u8]>) -> Result<Vec<serde_json::Value>> {
+    let Some(data) = artifact else {
+        return Ok(Vec::new());
+    };
+
+    let manifest: serde_json::Value = serde_json::from_slice(data)?;
+    Ok(manifest.get("layers").and_then(|layers| layers.as_array()).cloned().unwrap_or_default())
+}
+
+fn cosign_layer_annotation<'a>(layer: &'a serde_json::Value, annotation: &str) -> Option<&'a str> {
+    layer.get("annotations")?.get(annotation)?.as_str()
+}
+
+fn certificate_pem(der: &[u8]) -> String {
+    pkcs8::der::pem::encode_string("CERTIFICATE", pkcs8::der::pem::LineEnding::LF, der)
+        .expect("PEM encoding into a string can't fail")
+}
+
+/// Decode every certificate in a PEM bundle into DER
+fn pem_certificates(pem: &str) -> Result<Vec<Vec<u8>>> {
+    const END: &str = "-----END CERTIFICATE-----";
+
+    pem.split_inclusive(END)
+        .filter(|block| block.contains(END))
+        .map(|block| {
+            let (_, der) = pkcs8::der::pem::decode_vec(block.trim().as_bytes())
+                .map_err(|e| anyhow::anyhow!("invalid certificate PEM: {}", e))?;
+            Ok(der)
+        })
+        .collect()
+}
+
+/// Decode a PKCS#8 PEM private key into DER, decrypting it when a password is supplied
+fn decode_private_key_pem(pem: &str, password: Option<&str>) -> Result<Vec<u8>> {
+    let (label, document) = pkcs8::SecretDocument::from_pem(pem)
+        .map_err(|e| anyhow::anyhow!("failed to parse PEM: {}", e))?;
+
+    match label {
+        "PRIVATE KEY" => Ok(document.as_bytes().to_vec()),
+        "ENCRYPTED PRIVATE KEY" => {
+            let password = password
+                .ok_or_else(|| anyhow::anyhow!("key is encrypted but no password is configured"))?;
+            let encrypted = pkcs8::EncryptedPrivateKeyInfo::try_from(document.as_bytes())
+                .map_err(|e| anyhow::anyhow!("malformed encrypted key: {}", e))?;
+            let decrypted = encrypted
+                .decrypt(password)
+                .map_err(|_| anyhow::anyhow!("failed to decrypt key (wrong password?)"))?;
+            Ok(decrypted.as_bytes().to_vec())
+        }
+        other => Err(anyhow::anyhow!(
+            "unsupported PEM label '{}', expected a PKCS#8 PRIVATE KEY",
+            other
+        )),
+    }
+}
+
+/// Determine which signature algorithms a PKCS#8 DER private key can be used with
+fn private_key_algorithm(der: &[u8]) -> Result<Vec<SignatureAlgorithm>> {
+    let info = pkcs8::PrivateKeyInfo::try_from(der)
+        .map_err(|e| anyhow::anyhow!("malformed PKCS#8 key: {}", e))?;
+
+    let oid = info.algorithm.oid;
+    if oid == OID_RSA_ENCRYPTION || oid == OID_RSASSA_PSS {
+        Ok(vec![SignatureAlgorithm::RsaPssSha256, SignatureAlgorithm::RsaPkcs1Sha256])
+    } else if oid == OID_EC_PUBLIC_KEY {
+        match info.algorithm.parameters_oid() {
+            Ok(curve) if curve == OID_SECP256R1 => Ok(vec![SignatureAlgorithm::EcdsaP256Sha256]),
+            Ok(curve) if curve == OID_SECP384R1 => Ok(vec![SignatureAlgorithm::EcdsaP384Sha384]),
+            Ok(curve) => Err(anyhow::anyhow!("unsupported elliptic curve {}", curve)),
+            Err(_) => Err(anyhow::anyhow!("EC key is missing its named curve")),
+        }
+    } else if oid == OID_ED25519 {
+        Ok(vec![SignatureAlgorithm::Ed25519])
+    } else {
+        Err(anyhow::anyhow!("unsupported key algorithm {}", oid))
+    }
+}
+
+/// Read a PEM (or raw DER) certificate file into DER bytes
+async fn load_certificate_der(path: &str) -> Result<Vec<u8>> {
+    let data = tokio::fs::read(path).await
+        .map_err(|e| anyhow::anyhow!("Failed to read certificate {}: {}", path, e))?;
+
+    match std::str::from_utf8(&data) {
+        Ok(text) if text.contains("-----BEGIN") => {
+            let (label, der) = pkcs8::der::pem::decode_vec(text.trim().as_bytes())
+                .map_err(|e| anyhow::anyhow!("Invalid certificate PEM {}: {}", path, e))?;
+            if label != "CERTIFICATE" {
+                return Err(anyhow::anyhow!(
+                    "Expected CERTIFICATE in {} but found {}",
+                    path, label
+                ));
+            }
+            Ok(der)
+        }
+        _ => Ok(data),
+    }
+}
+
+/// Longest chain we'll walk before giving up, to guard against issuer loops
+const MAX_CHAIN_DEPTH: usize = 8;
+
+fn parse_certificate(der: &[u8]) -> Result<X509Certificate<'_>> {
+    let (_, certificate) = x509_parser::parse_x509_certificate(der)
+        .map_err(|e| anyhow::anyhow!("invalid X.509 certificate: {}", e))?;
+    Ok(certificate)
+}
 
-        Ok(VerificationResult {
-            valid: true, // Placeholder
-            trusted: false,
-            key_id: signature.key_id.clone(),
-            algorithm: signature.algorithm.clone(),
-            verified_at: chrono::Utc::now(),
-            certificate_chain_valid: None,
-            errors: vec![],
-            warnings: vec!["Simple signature verification not fully implemented".to_string()],
-        })
+/// Normalize a serial number to lowercase hex without separators
+fn normalize_serial(serial: &str) -> String {
+    serial.chars().filter(|c| c.is_ascii_hexdigit()).collect::<String>().to_lowercase()
+}
+
+/// Walk from the leaf through intermediates until a certificate is signed by a trusted root
+fn build_chain(
+    leaf: &X509Certificate,
+    intermediates: &[&X509Certificate],
+    roots: &[X509Certificate],
+    revoked: &[String],
+) -> Result<()> {
+    let revoked: Vec<String> = revoked.iter().map(|serial| normalize_serial(serial)).collect();
+    let mut current = leaf;
+
+    for _ in 0..MAX_CHAIN_DEPTH {
+        if !current.validity().is_valid() {
+            return Err(anyhow::anyhow!(
+                "certificate {} is outside its validity period",
+                current.subject()
+            ));
+        }
+
+        let serial = hex::encode(current.raw_serial());
+        if revoked.contains(&serial) {
+            return Err(anyhow::anyhow!("certificate {} (serial {}) is revoked", current.subject(), serial));
+        }
+
+        let issued_by = |candidate: &X509Certificate| {
+            candidate.subject().as_raw() == current.issuer().as_raw()
+                && current.verify_signature(Some(candidate.public_key())).is_ok()
+        };
+
+        if let Some(root) = roots.iter().find(|root| issued_by(root)) {
+            if !root.validity().is_valid() {
+                return Err(anyhow::anyhow!("root certificate {} is outside its validity period", root.subject()));
+            }
+            return Ok(());
+        }
+
+        current = intermediates.iter()
+            .copied()
+            .find(|intermediate| issued_by(intermediate))
+            .ok_or_else(|| anyhow::anyhow!(
+                "no trusted issuer found for {}",
+                current.subject()
+            ))?;
     }
 
-    /// Verify in-toto signature
-    async fn verify_in_toto_signature(
-        &self,
-        _content: &[u8],
-        signature: &ContentSignature,
-        _policy: &VerificationPolicy,
-    ) -> Result<VerificationResult> {
-        // TODO: Implement actual in-toto signature verification
-        warn!("In-toto signature verification not fully implemented");
+    Err(anyhow::anyhow!("certificate chain exceeds maximum depth of {}", MAX_CHAIN_DEPTH))
+}
 
-        Ok(VerificationResult {
-            valid: true, // Placeholder
-            trusted: false,
-            key_id: signature.key_id.clone(),
-            algorithm: signature.algorithm.clone(),
-            verified_at: chrono::Utc::now(),
-            certificate_chain_valid: None,
-            errors: vec![],
-            warnings: vec!["In-toto verification not fully implemented".to_string()],
-        })
+/// Hash of a verification policy, so results cached under one policy aren't reused under another
+fn policy_fingerprint(policy: &VerificationPolicy) -> String {
+    hex::encode(Sha256::digest(serde_json::to_vec(policy).unwrap_or_default()))
+}
+
+/// Fetch a trust store's CRLs, returning the serials they revoke and whether every one was fetched and verified
+async fn fetch_revoked_serials(store: &str, urls: &[String], issuers: &[Vec<u8>]) -> (Vec<String>, bool) {
+    let mut revoked = Vec::new();
+    let mut checked = true;
+
+    for url in urls {
+        match fetch_crl(url, issuers).await {
+            Ok(serials) => {
+                debug!("CRL {} for trust store {} revokes {} certificates", url, store, serials.len());
+                revoked.extend(serials);
+            }
+            Err(e) => {
+                warn!("Failed to load CRL {} for trust store {}: {}", url, store, e);
+                checked = false;
+            }
+        }
     }
 
-    /// Cache verification result
-    async fn cache_verification_result(
-        &self,
-        signature_id: &str,
-        signature: ContentSignature,
-        result: VerificationResult,
-    ) {
-        let mut cache = self.signature_cache.write().await;
-        cache.insert(signature_id.to_string(), CachedSignature {
-            signature,
-            verification_result: result,
-            cached_at: chrono::Utc::now(),
+    (revoked, checked)
+}
+
+async fn fetch_crl(url: &str, issuers: &[Vec<u8>]) -> Result<Vec<String>> {
+    let client = reqwest::Client::builder().timeout(CRL_FETCH_TIMEOUT).build()?;
+    let body = client.get(url).send().await?.error_for_status()?.bytes().await?;
+
+    let der = match std::str::from_utf8(&body) {
+        Ok(text) if text.contains("-----BEGIN") => {
+            let (label, der) = pkcs8::der::pem::decode_vec(text.trim().as_bytes())
+                .map_err(|e| anyhow::anyhow!("invalid CRL PEM: {}", e))?;
+            if label != "X509 CRL" {
+                return Err(anyhow::anyhow!("expected X509 CRL but found {}", label));
+            }
+            der
+        }
+        _ => body.to_vec(),
+    };
+
+    revoked_serials(&der, issuers)
+}
+
+/// Serials revoked by a DER CRL, which must be current and signed by one of `issuers`
+fn revoked_serials(der: &[u8], issuers: &[Vec<u8>]) -> Result<Vec<String>> {
+    let (_, crl) = x509_parser::parse_x509_crl(der)
+        .map_err(|e| anyhow::anyhow!("invalid CRL: {}", e))?;
+
+    let signed_by_issuer = issuers.iter()
+        .filter_map(|issuer| parse_certificate(issuer).ok())
+        .any(|issuer| {
+            issuer.subject().as_raw() == crl.issuer().as_raw()
+                && crl.verify_signature(issuer.public_key()).is_ok()
         });
+    if !signed_by_issuer {
+        return Err(anyhow::anyhow!("CRL from {} is not signed by a certificate in the trust store", crl.issuer()));
     }
 
-    /// Get cached verification result
-    async fn get_cached_verification(&self, signature_id: &str) -> Option<CachedSignature> {
-        let cache = self.signature_cache.read().await;
-        cache.get(signature_id).cloned()
+    if let Some(next_update) = crl.next_update()
+        && next_update.timestamp() < chrono::Utc::now().timestamp()
+    {
+        return Err(anyhow::anyhow!("CRL from {} expired at {}", crl.issuer(), next_update));
     }
 
-    /// Load signing key from configuration
-    async fn load_signing_key(&self, key_config: &crate::config::SigningKeyConfig) -> Result<SigningKey> {
-        // TODO: Load actual key from file/HSM/KMS
-        warn!("Key loading not fully implemented, using placeholder");
+    Ok(crl.iter_revoked_certificates().map(|revoked| hex::encode(revoked.raw_serial())).collect())
+}
 
-        Ok(SigningKey {
-            key_id: key_config.key_id.clone(),
-            algorithm: key_config.algorithm.clone(),
-            private_key: vec![], // Placeholder
-            certificate: None,
-            created_at: chrono::Utc::now(),
-            expires_at: None,
-        })
+/// Whether the chain's leaf certificate is for this key, so the chain vouches for signatures made with it
+fn leaf_certifies_key(chain: &[Vec<u8>], key: &VerificationKey) -> bool {
+    chain.first()
+        .and_then(|leaf| parse_certificate(leaf).ok())
+        .is_some_and(|leaf| leaf.public_key().raw == key.public_key.as_slice())
+}
+
+/// Parse a DER encoded P-256 private key (PKCS#8 or SEC1)
+fn parse_p256_signing_key(der: &[u8]) -> Result<p256::ecdsa::SigningKey> {
+    use p256::pkcs8::DecodePrivateKey;
+
+    if der.is_empty() {
+        return Err(anyhow::anyhow!("ECDSA P-256 private key material is empty"));
     }
 
-    /// Load verification key from configuration
-    async fn load_verification_key(&self, key_config: &crate::config::VerificationKeyConfig) -> Result<VerificationKey> {
-        // TODO: Load actual key from file/URL
-        warn!("Key loading not fully implemented, using placeholder");
+    p256::ecdsa::SigningKey::from_pkcs8_der(der)
+        .ok()
+        .or_else(|| p256::SecretKey::from_sec1_der(der).ok().map(Into::into))
+        .ok_or_else(|| anyhow::anyhow!("Invalid ECDSA P-256 private key"))
+}
 
-        Ok(VerificationKey {
-            key_id: key_config.key_id.clone(),
-            algorithm: key_config.algorithm.clone(),
-            public_key: vec![], // Placeholder
-            certificate: None,
-            trusted: key_config.trusted,
-            created_at: chrono::Utc::now(),
-            expires_at: None,
+/// Check that DER private key material can be used with the given algorithm
+fn parse_signing_key(algorithm: &SignatureAlgorithm, der: &[u8]) -> Result<()> {
+    match algorithm {
+        SignatureAlgorithm::RsaPssSha256 | SignatureAlgorithm::RsaPkcs1Sha256 => parse_rsa_private_key(der).map(drop),
+        SignatureAlgorithm::EcdsaP256Sha256 => parse_p256_signing_key(der).map(drop),
+        SignatureAlgorithm::EcdsaP384Sha384 => parse_p384_signing_key(der).map(drop),
+        SignatureAlgorithm::Ed25519 => parse_ed25519_signing_key(der).map(drop),
+    }
+}
+
+/// Signature algorithm for a JWS `alg` header
+fn jws_alg_to_algorithm(alg: &str) -> Option<SignatureAlgorithm> {
+    match alg {
+        "PS256" => Some(SignatureAlgorithm::RsaPssSha256),
+        "RS256" => Some(SignatureAlgorithm::RsaPkcs1Sha256),
+        "ES256" => Some(SignatureAlgorithm::EcdsaP256Sha256),
+        "ES384" => Some(SignatureAlgorithm::EcdsaP384Sha384),
+        "EdDSA" => Some(SignatureAlgorithm::Ed25519),
+        _ => None,
+    }
+}
+
+/// JWS carries ECDSA signatures as fixed-size r || s rather than DER
+fn jose_signature(algorithm: &SignatureAlgorithm, signature: &[u8]) -> Result<Vec<u8>> {
+    match algorithm {
+        SignatureAlgorithm::EcdsaP256Sha256 => Ok(p256::ecdsa::Signature::from_der(signature)
+            .map_err(|e| anyhow::anyhow!("Invalid ECDSA P-256 signature: {}", e))?
+            .to_bytes()
+            .to_vec()),
+        SignatureAlgorithm::EcdsaP384Sha384 => Ok(p384::ecdsa::Signature::from_der(signature)
+            .map_err(|e| anyhow::anyhow!("Invalid ECDSA P-384 signature: {}", e))?
+            .to_bytes()
+            .to_vec()),
+        _ => Ok(signature.to_vec()),
+    }
+}
+
+/// Decode a JWS `x5c` header: standard base64 DER certificates, leaf first
+fn decode_x5c(x5c: &serde_json::Value) -> Result<Vec<Vec<u8>>> {
+    let certificates = x5c.as_array().ok_or_else(|| anyhow::anyhow!("not an array"))?;
+    if certificates.is_empty() {
+        return Err(anyhow::anyhow!("no certificates"));
+    }
+
+    certificates.iter()
+        .map(|certificate| {
+            let encoded = certificate.as_str().ok_or_else(|| anyhow::anyhow!("certificate is not a string"))?;
+            Ok(general_purpose::STANDARD.decode(encoded)?)
         })
+        .collect()
+}
+
+/// Untrusted verification key from a leaf certificate, for signatures whose key is only in their chain
+fn certificate_verification_key(key_id: &str, algorithm: &SignatureAlgorithm, der: &[u8]) -> Result<VerificationKey> {
+    let certificate = parse_certificate(der)?;
+    Ok(VerificationKey {
+        key_id: key_id.to_string(),
+        algorithm: algorithm.clone(),
+        public_key: certificate.public_key().raw.to_vec(),
+        certificate: Some(der.to_vec()),
+        trusted: false,
+        created_at: chrono::Utc::now(),
+        expires_at: None,
+    })
+}
+
+fn jws_time(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0).map_or_else(|| timestamp.to_string(), |time| time.to_rfc3339())
+}
+
+/// Check that DER public key material can be used with the given algorithm
+fn parse_verifying_key(algorithm: &SignatureAlgorithm, der: &[u8]) -> Result<()> {
+    match algorithm {
+        SignatureAlgorithm::RsaPssSha256 | SignatureAlgorithm::RsaPkcs1Sha256 => parse_rsa_public_key(der).map(drop),
+        SignatureAlgorithm::EcdsaP256Sha256 => parse_p256_verifying_key(der).map(drop),
+        SignatureAlgorithm::EcdsaP384Sha384 => parse_p384_verifying_key(der).map(drop),
+        SignatureAlgorithm::Ed25519 => parse_ed25519_verifying_key(der).map(drop),
     }
+}
 
-    /// Load trust store from configuration
-    async fn load_trust_store(&self, trust_config: &crate::config::TrustStoreConfig) -> Result<TrustStore> {
-        // TODO: Load actual certificates from files/URLs
-        warn!("Trust store loading not fully implemented, using placeholder");
+/// Parse a DER encoded P-384 private key (PKCS#8 or SEC1)
+fn parse_p384_signing_key(der: &[u8]) -> Result<p384::ecdsa::SigningKey> {
+    use p384::pkcs8::DecodePrivateKey;
 
-        Ok(TrustStore {
-            name: trust_config.name.clone(),
-            root_certificates: vec![],
-            intermediate_certificates: vec![],
-            revoked_certificates: vec![],
+    if der.is_empty() {
+        return Err(anyhow::anyhow!("ECDSA P-384 private key material is empty"));
+    }
+
+    p384::ecdsa::SigningKey::from_pkcs8_der(der)
+        .ok()
+        .or_else(|| p384::SecretKey::from_sec1_der(der).ok().map(Into::into))
+        .ok_or_else(|| anyhow::anyhow!("Invalid ECDSA P-384 private key"))
+}
+
+/// Parse a DER encoded Ed25519 private key (PKCS#8)
+fn parse_ed25519_signing_key(der: &[u8]) -> Result<ed25519_dalek::SigningKey> {
+    use ed25519_dalek::pkcs8::DecodePrivateKey;
+
+    if der.is_empty() {
+        return Err(anyhow::anyhow!("Ed25519 private key material is empty"));
+    }
+
+    ed25519_dalek::SigningKey::from_pkcs8_der(der)
+        .map_err(|e| anyhow::anyhow!("Invalid Ed25519 private key: {}", e))
+}
+
+/// Parse a DER encoded RSA private key (PKCS#8 or PKCS#1)
+fn parse_rsa_private_key(der: &[u8]) -> Result<rsa::RsaPrivateKey> {
+    use rsa::pkcs1::DecodeRsaPrivateKey;
+    use rsa::pkcs8::DecodePrivateKey;
+
+    if der.is_empty() {
+        return Err(anyhow::anyhow!("RSA private key material is empty"));
+    }
+
+    rsa::RsaPrivateKey::from_pkcs8_der(der)
+        .ok()
+        .or_else(|| rsa::RsaPrivateKey::from_pkcs1_der(der).ok())
+        .ok_or_else(|| anyhow::anyhow!("Invalid RSA private key"))
+}
+
+/// Parse a DER encoded P-256 public key (SPKI or SEC1 point)
+fn parse_p256_verifying_key(der: &[u8]) -> Result<p256::ecdsa::VerifyingKey> {
+    use p256::pkcs8::DecodePublicKey;
+
+    if der.is_empty() {
+        return Err(anyhow::anyhow!("ECDSA P-256 public key material is empty"));
+    }
+
+    p256::ecdsa::VerifyingKey::from_public_key_der(der)
+        .ok()
+        .or_else(|| p256::ecdsa::VerifyingKey::from_sec1_bytes(der).ok())
+        .ok_or_else(|| anyhow::anyhow!("Invalid ECDSA P-256 public key"))
+}
+
+/// Parse a DER encoded P-384 public key (SPKI or SEC1 point)
+fn parse_p384_verifying_key(der: &[u8]) -> Result<p384::ecdsa::VerifyingKey> {
+    use p384::pkcs8::DecodePublicKey;
+
+    if der.is_empty() {
+        return Err(anyhow::anyhow!("ECDSA P-384 public key material is empty"));
+    }
+
+    p384::ecdsa::VerifyingKey::from_public_key_der(der)
+        .ok()
+        .or_else(|| p384::ecdsa::VerifyingKey::from_sec1_bytes(der).ok())
+        .ok_or_else(|| anyhow::anyhow!("Invalid ECDSA P-384 public key"))
+}
+
+/// Parse a DER encoded Ed25519 public key (SPKI or raw 32 bytes)
+fn parse_ed25519_verifying_key(der: &[u8]) -> Result<ed25519_dalek::VerifyingKey> {
+    use ed25519_dalek::pkcs8::DecodePublicKey;
+
+    if der.is_empty() {
+        return Err(anyhow::anyhow!("Ed25519 public key material is empty"));
+    }
+
+    ed25519_dalek::VerifyingKey::from_public_key_der(der)
+        .ok()
+        .or_else(|| {
+            let bytes: &[u8; ed25519_dalek::PUBLIC_KEY_LENGTH] = der.try_into().ok()?;
+            ed25519_dalek::VerifyingKey::from_bytes(bytes).ok()
         })
+        .ok_or_else(|| anyhow::anyhow!("Invalid Ed25519 public key"))
+}
+
+/// Parse a DER encoded RSA public key (SPKI or PKCS#1)
+fn parse_rsa_public_key(der: &[u8]) -> Result<rsa::RsaPublicKey> {
+    use rsa::pkcs1::DecodeRsaPublicKey;
+    use rsa::pkcs8::DecodePublicKey;
+
+    if der.is_empty() {
+        return Err(anyhow::anyhow!("RSA public key material is empty"));
     }
+
+    rsa::RsaPublicKey::from_public_key_der(der)
+        .ok()
+        .or_else(|| rsa::RsaPublicKey::from_pkcs1_der(der).ok())
+        .ok_or_else(|| anyhow::anyhow!("Invalid RSA public key"))
+}
+
+/// Parse configured policy values using their serialized names, skipping unknown entries
+fn parse_policy_values<T: serde::de::DeserializeOwned>(values: &[String]) -> Vec<T> {
+    values.iter()
+        .filter_map(|value| match serde_json::from_value(serde_json::Value::String(value.clone())) {
+            Ok(parsed) => Some(parsed),
+            Err(_) => {
+                warn!("Ignoring unknown verification policy value: {}", value);
+                None
+            }
+        })
+        .collect()
 }
 
 impl Default for VerificationPolicy {
@@ -718,4 +2895,547 @@ impl Default for VerificationPolicy {
             max_signature_age_hours: Some(24 * 30), // 30 days
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::MemoryStorage;
+    use p256::pkcs8::{EncodePrivateKey, EncodePublicKey};
+
+    fn signing_config() -> SigningConfig {
+        let mut config = crate::Config::default().signing.unwrap();
+        config.enabled = true;
+        config
+    }
+
+    async fn service(config: SigningConfig) -> SigningService {
+        SigningService::new(config, Arc::new(MemoryStorage::new(None))).await.unwrap()
+    }
+
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/signing/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    fn payload(repository: &str) -> SignaturePayload {
+        SignaturePayload {
+            subject: "manifest".to_string(),
+            content_type: "manifest".to_string(),
+            repository: repository.to_string(),
+            tag: None,
+            metadata: HashMap::new(),
+            timestamp: chrono::Utc::now(),
+        }
+    }
+
+    fn fixture_key_config(key_path: &str, password: Option<&str>) -> SigningConfig {
+        let mut config = signing_config();
+        config.signing_keys.push(crate::config::SigningKeyConfig {
+            key_id: "fixture".to_string(),
+            algorithm: SignatureAlgorithm::EcdsaP256Sha256,
+            key_path: fixture(key_path),
+            certificate_path: None,
+            password: password.map(str::to_string),
+        });
+        config.verification_keys.push(crate::config::VerificationKeyConfig {
+            key_id: "fixture".to_string(),
+            algorithm: SignatureAlgorithm::EcdsaP256Sha256,
+            public_key_path: fixture("p256.pub"),
+            certificate_path: None,
+            trusted: true,
+        });
+        config
+    }
+
+    /// RFC 6979 A.2.5: the P-256 key and the SHA-256 signature of "sample"
+    const RFC6979_PRIVATE_KEY: &str = "c9afa9d845ba75166b5c215767b1d6934e50c3db36e89b127b8a622b120f6721";
+    const RFC6979_R: &str = "efd48b2aacb6a8fd1140dd9cd45e81d69d2c877b56aaf991c34d0ea84eaf3716";
+    const RFC6979_S: &str = "f7cb1c942d657c41d436c7a1b6e29f65f3e900dbb9aff4064dc4ab2f843acda8";
+
+    fn rfc6979_keys() -> (SigningKey, VerificationKey) {
+        let secret = p256::SecretKey::from_slice(&hex::decode(RFC6979_PRIVATE_KEY).unwrap()).unwrap();
+        let signing_key = SigningKey {
+            key_id: "rfc6979".to_string(),
+            algorithm: SignatureAlgorithm::EcdsaP256Sha256,
+            private_key: secret.to_pkcs8_der().unwrap().as_bytes().to_vec(),
+            certificate: None,
+            created_at: chrono::Utc::now(),
+            expires_at: None,
+            retired: false,
+        };
+        let verification_key = VerificationKey {
+            key_id: "rfc6979".to_string(),
+            algorithm: SignatureAlgorithm::EcdsaP256Sha256,
+            public_key: secret.public_key().to_public_key_der().unwrap().as_bytes().to_vec(),
+            certificate: None,
+            trusted: true,
+            created_at: chrono::Utc::now(),
+            expires_at: None,
+        };
+        (signing_key, verification_key)
+    }
+
+    #[tokio::test]
+    async fn ecdsa_p256_matches_the_rfc6979_vector() {
+        let service = service(signing_config()).await;
+        let (signing_key, verification_key) = rfc6979_keys();
+
+        let vector = hex::decode(format!("{}{}", RFC6979_R, RFC6979_S)).unwrap();
+        assert!(service.verify_bytes(b"sample", &vector, &verification_key).unwrap());
+
+        // Deterministic signing gives the vector, with s normalized to the low half
+        let signature = service.sign_bytes(b"sample", &signing_key).await.unwrap();
+        let signature = p256::ecdsa::Signature::from_der(&signature).unwrap();
+        let expected = p256::ecdsa::Signature::from_slice(&vector).unwrap();
+        assert_eq!(signature, expected.normalize_s().unwrap_or(expected));
+    }
+
+    #[tokio::test]
+    async fn corrupted_signatures_are_rejected() {
+        let service = service(signing_config()).await;
+        let (signing_key, verification_key) = rfc6979_keys();
+        let signature = service.sign_bytes(b"sample", &signing_key).await.unwrap();
+        assert!(service.verify_bytes(b"sample", &signature, &verification_key).unwrap());
+
+        for index in [8, signature.len() / 2, signature.len() - 1] {
+            let mut corrupted = signature.clone();
+            corrupted[index] ^= 0x01;
+            assert!(!service.verify_bytes(b"sample", &corrupted, &verification_key).unwrap());
+        }
+        assert!(!service.verify_bytes(b"samplf", &signature, &verification_key).unwrap());
+    }
+
+    #[tokio::test]
+    async fn pull_verdict_cache_is_bounded() {
+        let content_key = format!("app@sha256:{}", hex::encode(Sha256::digest(b"content")));
+        // Live verdicts make room for the new one by the oldest going; expired ones are all swept
+        for (cache_ttl_secs, expected_len) in [(300, MAX_CACHED_VERDICTS), (0, 1)] {
+            let mut config = signing_config();
+            config.verification_policy.require_signatures = true;
+            config.enforcement.cache_ttl_secs = cache_ttl_secs;
+            let service = service(config).await;
+
+            let verdict = CachedVerdict {
+                result: PolicyResult { allowed: true, valid_signatures: 1, errors: vec![] },
+                pushed_at: None,
+                reached_at: std::time::Instant::now(),
+            };
+            let cached = (0..MAX_CACHED_VERDICTS).map(|index| (format!("app@sha256:{}", index), verdict.clone()));
+            service.policy_cache.write().await.extend(cached);
+
+            assert!(!service.check_pull("app", b"content").await.unwrap().allowed);
+            let cache = service.policy_cache.read().await;
+            assert_eq!(cache.len(), expected_len);
+            assert!(cache.contains_key(&content_key));
+        }
+    }
+
+    #[tokio::test]
+    async fn every_algorithm_round_trips_and_rejects_tampering() {
+        let service = service(signing_config()).await;
+        let algorithms = [
+            SignatureAlgorithm::RsaPssSha256,
+            SignatureAlgorithm::RsaPkcs1Sha256,
+            SignatureAlgorithm::EcdsaP256Sha256,
+            SignatureAlgorithm::EcdsaP384Sha384,
+            SignatureAlgorithm::Ed25519,
+        ];
+        for algorithm in algorithms {
+            let (private_key, public_key) = generate_key_pair(&algorithm).unwrap();
+            let signing_key = SigningKey {
+                key_id: "generated".to_string(),
+                algorithm: algorithm.clone(),
+                private_key,
+                certificate: None,
+                created_at: chrono::Utc::now(),
+                expires_at: None,
+                retired: false,
+            };
+            let verification_key = VerificationKey {
+                key_id: "generated".to_string(),
+                algorithm: algorithm.clone(),
+                public_key,
+                certificate: None,
+                trusted: true,
+                created_at: chrono::Utc::now(),
+                expires_at: None,
+            };
+
+            let content = b"content to sign";
+            let signature = service.sign_bytes(content, &signing_key).await.unwrap();
+            assert!(service.verify_bytes(content, &signature, &verification_key).unwrap(), "{:?}", algorithm);
+
+            let mut tampered = content.to_vec();
+            tampered[0] ^= 0x01;
+            assert!(!service.verify_bytes(&tampered, &signature, &verification_key).unwrap(), "{:?}", algorithm);
+
+            let mut tampered_signature = signature.clone();
+            let middle = tampered_signature.len() / 2;
+            tampered_signature[middle] ^= 0x01;
+            assert!(!service.verify_bytes(content, &tampered_signature, &verification_key).unwrap(), "{:?}", algorithm);
+        }
+    }
+
+    #[tokio::test]
+    async fn unparseable_key_files_fail_at_load() {
+        let key_file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(key_file.path(), b"not a key").unwrap();
+        let mut config = fixture_key_config("p256.pem", None);
+        config.signing_keys[0].key_path = key_file.path().to_string_lossy().to_string();
+
+        let storage: Arc<dyn StorageBackend> = Arc::new(MemoryStorage::new(None));
+        assert!(SigningService::new(config, storage).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn pem_fixture_keys_sign_and_verify() {
+        for (path, password) in [("p256.pem", None), ("p256-encrypted.pem", Some("fixture-password"))] {
+            let service = service(fixture_key_config(path, password)).await;
+            let content = b"content";
+            let signature = service
+                .sign_content(content, "fixture", SignatureFormat::Simple, payload(""))
+                .await
+                .unwrap();
+
+            let result = service
+                .verify_signature(content, &signature, &service.verification_policy())
+                .await
+                .unwrap();
+            assert!(result.valid && result.trusted, "{}: {:?}", path, result.errors);
+        }
+    }
+
+    #[tokio::test]
+    async fn encrypted_pem_fixture_needs_the_right_password() {
+        let storage: Arc<dyn StorageBackend> = Arc::new(MemoryStorage::new(None));
+        for password in [None, Some("wrong-password")] {
+            let config = fixture_key_config("p256-encrypted.pem", password);
+            assert!(SigningService::new(config, storage.clone()).await.is_err());
+        }
+    }
+
+    /// Store a Cosign signature artifact the way `cosign sign` pushes it
+    async fn push_cosign_fixture(service: &SigningService, repository: &str) -> (Vec<u8>, String) {
+        let manifest = std::fs::read(fixture("cosign-manifest.json")).unwrap();
+        let payload = std::fs::read(fixture("cosign-payload.json")).unwrap();
+        let signature = std::fs::read_to_string(fixture("cosign-payload.sig")).unwrap();
+        let content_digest = hex::encode(Sha256::digest(&manifest));
+
+        let payload_digest = service.put_content_blob(&payload).await.unwrap();
+        let layer = serde_json::json!({
+            "mediaType": COSIGN_PAYLOAD_MEDIA_TYPE,
+            "size": payload.len(),
+            "digest": payload_digest,
+            "annotations": { COSIGN_SIGNATURE_ANNOTATION: signature.trim() },
+        });
+        let artifact = service.cosign_artifact(vec![layer]).await.unwrap();
+        service
+            .storage
+            .put_manifest(repository, &cosign_signature_tag(&content_digest), artifact, OCI_MANIFEST_MEDIA_TYPE)
+            .await
+            .unwrap();
+        (manifest, content_digest)
+    }
+
+    fn cosign_key_config() -> SigningConfig {
+        let mut config = signing_config();
+        config.verification_keys.push(crate::config::VerificationKeyConfig {
+            key_id: "cosign".to_string(),
+            algorithm: SignatureAlgorithm::EcdsaP256Sha256,
+            public_key_path: fixture("cosign.pub"),
+            certificate_path: None,
+            trusted: true,
+        });
+        config
+    }
+
+    #[tokio::test]
+    async fn cosign_fixture_signature_verifies() {
+        let service = service(cosign_key_config()).await;
+        let (manifest, content_digest) = push_cosign_fixture(&service, "app").await;
+
+        let signatures = service.get_content_signatures("app", &content_digest).await.unwrap();
+        assert_eq!(signatures.len(), 1);
+        assert_eq!(signatures[0].key_id, "cosign");
+
+        let policy = service.verification_policy();
+        let result = service.verify_signature(&manifest, &signatures[0], &policy).await.unwrap();
+        assert!(result.valid && result.trusted, "{:?}", result.errors);
+
+        // The payload names the manifest digest, so it doesn't cover other content
+        let result = service.verify_signature(b"other", &signatures[0], &policy).await.unwrap();
+        assert!(!result.valid);
+    }
+
+    #[tokio::test]
+    async fn cosign_fixture_without_its_key_is_not_attributed() {
+        let service = service(signing_config()).await;
+        let (manifest, content_digest) = push_cosign_fixture(&service, "app").await;
+
+        let signatures = service.get_content_signatures("app", &content_digest).await.unwrap();
+        assert_eq!(signatures[0].key_id, "unknown");
+        let result = service
+            .verify_signature(&manifest, &signatures[0], &service.verification_policy())
+            .await
+            .unwrap();
+        assert!(!result.valid);
+    }
+
+    #[tokio::test]
+    async fn signature_expiry_and_max_age_are_enforced() {
+        let service = service(fixture_key_config("p256.pem", None)).await;
+        let content = b"content";
+        let policy = VerificationPolicy { max_signature_age_hours: Some(24), ..service.verification_policy() };
+        let signature = service
+            .sign_content(content, "fixture", SignatureFormat::Simple, payload(""))
+            .await
+            .unwrap();
+
+        let fresh = service.verify_signature(content, &signature, &policy).await.unwrap();
+        assert!(fresh.valid, "{:?}", fresh.errors);
+
+        // Age and expiry are checked even when the verdict is cached
+        let expired = ContentSignature {
+            expires_at: Some(chrono::Utc::now() - chrono::Duration::minutes(1)),
+            ..signature.clone()
+        };
+        let result = service.verify_signature(content, &expired, &policy).await.unwrap();
+        assert!(!result.valid);
+        assert!(result.errors[0].contains("expired"), "{:?}", result.errors);
+
+        let over_age = ContentSignature {
+            created_at: chrono::Utc::now() - chrono::Duration::hours(25),
+            ..signature.clone()
+        };
+        let result = service.verify_signature(content, &over_age, &policy).await.unwrap();
+        assert!(!result.valid);
+        assert!(result.errors[0].contains("maximum age"), "{:?}", result.errors);
+
+        let not_yet_expired = ContentSignature {
+            expires_at: Some(chrono::Utc::now() + chrono::Duration::hours(1)),
+            ..signature
+        };
+        assert!(service.verify_signature(content, &not_yet_expired, &policy).await.unwrap().valid);
+    }
+
+    /// Signs as "chain" with a fixture key and its certificate, trusting root.pem through intermediate.pem
+    fn chain_config(key: &str, certificate: &str, public_key: &str) -> SigningConfig {
+        let mut config = signing_config();
+        config.signing_keys.push(crate::config::SigningKeyConfig {
+            key_id: "chain".to_string(),
+            algorithm: SignatureAlgorithm::EcdsaP256Sha256,
+            key_path: fixture(key),
+            certificate_path: Some(fixture(certificate)),
+            password: None,
+        });
+        config.verification_keys.push(crate::config::VerificationKeyConfig {
+            key_id: "chain".to_string(),
+            algorithm: SignatureAlgorithm::EcdsaP256Sha256,
+            public_key_path: fixture(public_key),
+            certificate_path: None,
+            trusted: false,
+        });
+        config.trust_stores.push(crate::config::TrustStoreConfig {
+            name: "default".to_string(),
+            root_certificate_paths: vec![fixture("root.pem")],
+            intermediate_certificate_paths: vec![fixture("intermediate.pem")],
+            crl_urls: vec![],
+            require_revocation_check: false,
+            crl_refresh_secs: 3600,
+        });
+        config
+    }
+
+    /// Serve intermediate.crl over HTTP on an ephemeral port, returning its URL
+    async fn serve_crl() -> String {
+        let crl = tokio::fs::read(fixture("intermediate.crl")).await.unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let app = axum::Router::new().route("/intermediate.crl", axum::routing::get(move || async move { crl }));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{}/intermediate.crl", address)
+    }
+
+    async fn sign_and_verify(config: SigningConfig) -> VerificationResult {
+        let service = service(config).await;
+        let signature = service
+            .sign_content(b"content", "chain", SignatureFormat::Simple, payload(""))
+            .await
+            .unwrap();
+        service.verify_signature(b"content", &signature, &service.verification_policy()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn chain_to_a_trusted_root_verifies() {
+        let result = sign_and_verify(chain_config("leaf-key.pem", "leaf.pem", "leaf.pub")).await;
+        assert!(result.valid, "{:?}", result.errors);
+        assert!(result.trusted);
+        assert_eq!(result.certificate_chain_valid, Some(true));
+    }
+
+    #[tokio::test]
+    async fn chain_to_an_untrusted_root_is_rejected() {
+        let result = sign_and_verify(chain_config("untrusted-leaf-key.pem", "untrusted-leaf.pem", "untrusted-leaf.pub")).await;
+        assert!(!result.valid);
+        assert_eq!(result.certificate_chain_valid, Some(false));
+        assert!(result.errors[0].contains("no trusted issuer"), "{:?}", result.errors);
+    }
+
+    #[tokio::test]
+    async fn leaf_revoked_by_a_fetched_crl_is_rejected() {
+        let crl_url = serve_crl().await;
+
+        let mut config = chain_config("revoked-leaf-key.pem", "revoked-leaf.pem", "revoked-leaf.pub");
+        config.trust_stores[0].crl_urls = vec![crl_url.clone()];
+        config.trust_stores[0].require_revocation_check = true;
+        let result = sign_and_verify(config).await;
+        assert!(!result.valid);
+        assert!(result.errors[0].contains("revoked"), "{:?}", result.errors);
+
+        // The same CRL leaves other leaves alone
+        let mut config = chain_config("leaf-key.pem", "leaf.pem", "leaf.pub");
+        config.trust_stores[0].crl_urls = vec![crl_url];
+        config.trust_stores[0].require_revocation_check = true;
+        let result = sign_and_verify(config).await;
+        assert!(result.valid, "{:?}", result.errors);
+    }
+
+    #[tokio::test]
+    async fn unavailable_crl_rejects_chains_only_when_revocation_is_required() {
+        let unreachable = "http://127.0.0.1:9/intermediate.crl".to_string();
+
+        let mut config = chain_config("leaf-key.pem", "leaf.pem", "leaf.pub");
+        config.trust_stores[0].crl_urls = vec![unreachable.clone()];
+        let result = sign_and_verify(config).await;
+        assert!(result.valid, "{:?}", result.errors);
+
+        let mut config = chain_config("leaf-key.pem", "leaf.pem", "leaf.pub");
+        config.trust_stores[0].crl_urls = vec![unreachable];
+        config.trust_stores[0].require_revocation_check = true;
+        let result = sign_and_verify(config).await;
+        assert!(!result.valid);
+        assert!(result.errors[0].contains("revocation status is unavailable"), "{:?}", result.errors);
+    }
+
+    #[tokio::test]
+    async fn chain_whose_leaf_is_for_another_key_is_rejected() {
+        // p256.pem signs, but presents the leaf certificate issued for leaf-key.pem
+        let result = sign_and_verify(chain_config("p256.pem", "leaf.pem", "p256.pub")).await;
+        assert!(!result.valid);
+        assert_eq!(result.certificate_chain_valid, Some(false));
+        assert!(result.errors[0].contains("leaf certificate is not for key chain"), "{:?}", result.errors);
+    }
+
+    /// The Notary v2 fixture `name`, a JWS over notary-manifest.json by the key `notary`, with
+    /// no key configured under that id so it verifies by its x5c leaf, and a policy requiring a chain
+    async fn verify_notary_fixture(name: &str, content: Option<&[u8]>) -> VerificationResult {
+        let mut config = chain_config("leaf-key.pem", "leaf.pem", "leaf.pub");
+        config.verification_policy.require_certificate_chain = true;
+        let service = service(config).await;
+
+        let manifest = std::fs::read(fixture("notary-manifest.json")).unwrap();
+        let jws = std::fs::read_to_string(fixture(name)).unwrap();
+        let signature = ContentSignature {
+            signature_id: name.to_string(),
+            content_digest: hex::encode(Sha256::digest(&manifest)),
+            format: SignatureFormat::NotaryV2,
+            algorithm: SignatureAlgorithm::EcdsaP256Sha256,
+            signature: jws.trim().as_bytes().to_vec(),
+            key_id: "notary".to_string(),
+            certificate_chain: None,
+            payload: payload(""),
+            signed_payload: None,
+            created_at: chrono::Utc::now(),
+            expires_at: None,
+        };
+
+        let policy = service.verification_policy();
+        service.verify_signature(content.unwrap_or(&manifest), &signature, &policy).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn notary_fixture_signature_verifies_through_its_chain() {
+        let result = verify_notary_fixture("notary-valid.jws", None).await;
+        assert!(result.valid, "{:?}", result.errors);
+        assert_eq!(result.certificate_chain_valid, Some(true));
+
+        // It's for the manifest's digest, so it doesn't cover other content
+        let result = verify_notary_fixture("notary-valid.jws", Some(b"other")).await;
+        assert!(!result.valid);
+    }
+
+    #[tokio::test]
+    async fn expired_notary_fixture_is_rejected() {
+        let result = verify_notary_fixture("notary-expired.jws", None).await;
+        assert!(!result.valid);
+        assert_eq!(result.certificate_chain_valid, Some(true));
+        assert!(result.errors.iter().any(|e| e.contains("expired")), "{:?}", result.errors);
+    }
+
+    #[tokio::test]
+    async fn notary_fixture_from_an_untrusted_key_is_rejected() {
+        let result = verify_notary_fixture("notary-untrusted.jws", None).await;
+        assert!(!result.valid);
+        assert_eq!(result.certificate_chain_valid, Some(false));
+        assert!(result.errors.iter().any(|e| e.contains("Certificate chain")), "{:?}", result.errors);
+    }
+
+    #[tokio::test]
+    async fn deleting_a_signature_shrinks_the_list_and_evicts_its_cache_entry() {
+        let service = service(fixture_key_config("p256.pem", None)).await;
+        let content = b"content";
+        let content_digest = hex::encode(Sha256::digest(content));
+        let policy = service.verification_policy();
+
+        let mut signatures = Vec::new();
+        for _ in 0..2 {
+            let signature = service
+                .sign_content(content, "fixture", SignatureFormat::Simple, payload("app"))
+                .await
+                .unwrap();
+            service.verify_signature(content, &signature, &policy).await.unwrap();
+            signatures.push(signature);
+        }
+        let deleted = &signatures[0].signature_id;
+        let fingerprint = policy_fingerprint(&policy);
+        assert!(service.get_cached_verification(deleted, &fingerprint).await.is_some());
+
+        // Another repository's route can't reach it
+        assert!(service.delete_signature("other", &content_digest, deleted).await.unwrap().is_none());
+        assert_eq!(service.stored_signatures(&content_digest).await.unwrap().len(), 2);
+
+        assert!(service.delete_signature("app", &content_digest, deleted).await.unwrap().is_some());
+        let remaining = service.stored_signatures(&content_digest).await.unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].signature_id, signatures[1].signature_id);
+        assert!(service.get_cached_verification(deleted, &fingerprint).await.is_none());
+        assert!(service.get_cached_verification(&signatures[1].signature_id, &fingerprint).await.is_some());
+
+        assert!(service.delete_signature("app", &content_digest, deleted).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn cached_results_are_not_reused_for_other_content_or_policies() {
+        let service = service(fixture_key_config("p256.pem", None)).await;
+        let policy = service.verification_policy();
+        let signature = service
+            .sign_content(b"content", "fixture", SignatureFormat::Simple, payload("app"))
+            .await
+            .unwrap();
+        assert!(service.verify_signature(b"content", &signature, &policy).await.unwrap().valid);
+
+        let result = service.verify_signature(b"other", &signature, &policy).await.unwrap();
+        assert!(!result.valid);
+        assert_eq!(result.errors, vec!["Content digest mismatch".to_string()]);
+
+        let strict = VerificationPolicy { require_certificate_chain: true, ..policy.clone() };
+        let result = service.verify_signature(b"content", &signature, &strict).await.unwrap();
+        assert!(!result.valid);
+        assert!(result.errors[0].contains("Certificate chain required"), "{:?}", result.errors);
+
+        let no_simple = VerificationPolicy { allowed_signature_formats: vec![SignatureFormat::Cosign], ..policy.clone() };
+        assert!(!service.verify_signature(b"content", &signature, &no_simple).await.unwrap().valid);
+
+        assert!(service.verify_signature(b"content", &signature, &policy).await.unwrap().valid);
+    }
+}
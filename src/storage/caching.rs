@@ -0,0 +1,504 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::{BoxStream, StreamExt};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, info, warn};
+
+use super::{BlobMetadata, BlobReferences, FsckReport, ManifestMetadata, StorageBackend, UploadSession};
+use crate::config::StorageCacheConfig;
+
+/// Prefix of cache files being written; leftovers are removed on startup
+const TEMP_FILE_PREFIX: &str = ".tmp-";
+
+/// Wraps a storage backend with a read-through blob cache on local disk.
+/// Blob reads are served from the cache when possible and fill it on a miss;
+/// writes and deletes go to the backend and invalidate the cached copy. The
+/// least recently used blobs are evicted once the cache exceeds its size.
+pub struct CachingStorage {
+    inner: Arc<dyn StorageBackend>,
+    cache: Arc<BlobCache>,
+}
+
+impl CachingStorage {
+    pub async fn new(inner: Arc<dyn StorageBackend>, config: &StorageCacheConfig) -> Result<Self> {
+        let cache = BlobCache::open(PathBuf::from(&config.path), config.max_size_mb * 1024 * 1024).await?;
+        Ok(Self { inner, cache: Arc::new(cache) })
+    }
+}
+
+/// Blobs kept on local disk, one file per digest
+struct BlobCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    index: Mutex<LruIndex>,
+}
+
+/// Cached blob sizes ordered by last use
+#[derive(Default)]
+struct LruIndex {
+    /// Digest -> (size, last use)
+    entries: HashMap<String, (u64, u64)>,
+    /// Last use -> digest, oldest first
+    by_use: BTreeMap<u64, String>,
+    clock: u64,
+    total_bytes: u64,
+    /// Bumped on every invalidation, so fills started before one aren't kept
+    generation: u64,
+}
+
+impl LruIndex {
+    /// Mark a blob as just used, returning whether it is cached
+    fn touch(&mut self, digest: &str) -> bool {
+        let Some((_, last_use)) = self.entries.get_mut(digest) else {
+            return false;
+        };
+        self.by_use.remove(last_use);
+        self.clock += 1;
+        *last_use = self.clock;
+        self.by_use.insert(self.clock, digest.to_string());
+        true
+    }
+
+    fn insert(&mut self, digest: &str, size: u64) {
+        self.remove(digest);
+        self.clock += 1;
+        self.entries.insert(digest.to_string(), (size, self.clock));
+        self.by_use.insert(self.clock, digest.to_string());
+        self.total_bytes += size;
+    }
+
+    fn remove(&mut self, digest: &str) -> bool {
+        let Some((size, last_use)) = self.entries.remove(digest) else {
+            return false;
+        };
+        self.by_use.remove(&last_use);
+        self.total_bytes -= size;
+        true
+    }
+
+    /// Drop least recently used blobs until the total fits `max_bytes`
+    fn evict(&mut self, max_bytes: u64) -> Vec<String> {
+        let mut evicted = Vec::new();
+        while self.total_bytes > max_bytes {
+            let Some((_, digest)) = self.by_use.pop_first() else {
+                break;
+            };
+            if let Some((size, _)) = self.entries.remove(&digest) {
+                self.total_bytes -= size;
+            }
+            evicted.push(digest);
+        }
+        evicted
+    }
+}
+
+impl BlobCache {
+    /// Open the cache directory, indexing blobs left from earlier runs by
+    /// their modification time
+    async fn open(dir: PathBuf, max_bytes: u64) -> Result<Self> {
+        fs::create_dir_all(&dir).await?;
+
+        let mut found = Vec::new();
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with(TEMP_FILE_PREFIX) {
+                let _ = fs::remove_file(entry.path()).await;
+                continue;
+            }
+            let metadata = entry.metadata().await?;
+            if metadata.is_file() {
+                found.push((metadata.modified()?, name.replacen('_', ":", 1), metadata.len()));
+            }
+        }
+        found.sort();
+
+        let cache = Self { dir, max_bytes, index: Mutex::new(LruIndex::default()) };
+        let evicted = {
+            let mut index = cache.index.lock().unwrap();
+            for (_, digest, size) in &found {
+                index.insert(digest, *size);
+            }
+            index.evict(max_bytes)
+        };
+        cache.delete_files(evicted).await;
+
+        let total_bytes = cache.index.lock().unwrap().total_bytes;
+        info!("Blob cache at {} holds {} bytes of {}", cache.dir.display(), total_bytes, max_bytes);
+        Ok(cache)
+    }
+
+    /// Cache file of a digest, or None for digests that aren't safe file names
+    fn path(&self, digest: &str) -> Option<PathBuf> {
+        let valid = !digest.is_empty() && digest.chars().all(|c| c.is_ascii_alphanumeric() || c == ':');
+        valid.then(|| self.dir.join(digest.replacen(':', "_", 1)))
+    }
+
+    async fn get(&self, digest: &str) -> Option<Bytes> {
+        let path = self.path(digest)?;
+        if !self.index.lock().unwrap().touch(digest) {
+            return None;
+        }
+
+        match fs::read(&path).await {
+            Ok(data) => Some(data.into()),
+            Err(e) => {
+                warn!("Dropping unreadable cached blob {}: {}", digest, e);
+                self.index.lock().unwrap().remove(digest);
+                None
+            }
+        }
+    }
+
+    async fn open_file(&self, digest: &str) -> Option<(u64, fs::File)> {
+        let path = self.path(digest)?;
+        if !self.index.lock().unwrap().touch(digest) {
+            return None;
+        }
+
+        let opened = match fs::File::open(&path).await {
+            Ok(file) => file.metadata().await.map(|metadata| (metadata.len(), file)),
+            Err(e) => Err(e),
+        };
+        match opened {
+            Ok(opened) => Some(opened),
+            Err(e) => {
+                warn!("Dropping unreadable cached blob {}: {}", digest, e);
+                self.index.lock().unwrap().remove(digest);
+                None
+            }
+        }
+    }
+
+    /// Current invalidation generation; read it before fetching from the backend
+    fn generation(&self) -> u64 {
+        self.index.lock().unwrap().generation
+    }
+
+    /// Temp file to write a blob of `size` bytes into, or None if it can't be
+    /// cached. `generation` is the one read before fetching the blob.
+    async fn start_fill(self: &Arc<Self>, digest: &str, size: u64, generation: u64) -> Option<CacheFill> {
+        if size > self.max_bytes {
+            return None;
+        }
+        self.path(digest)?;
+
+        let temp_path = self.dir.join(format!("{}{}", TEMP_FILE_PREFIX, uuid::Uuid::new_v4()));
+        match fs::File::create(&temp_path).await {
+            Ok(file) => Some(CacheFill {
+                cache: self.clone(),
+                digest: digest.to_string(),
+                temp_path,
+                file,
+                hasher: Sha256::new(),
+                written: 0,
+                expected: size,
+                generation,
+            }),
+            Err(e) => {
+                warn!("Failed to create blob cache file: {}", e);
+                None
+            }
+        }
+    }
+
+    async fn insert(self: &Arc<Self>, digest: &str, data: &Bytes, generation: u64) {
+        let Some(mut fill) = self.start_fill(digest, data.len() as u64, generation).await else {
+            return;
+        };
+        if let Err(e) = fill.write(data).await {
+            warn!("Failed to cache blob {}: {}", digest, e);
+            fill.abandon().await;
+            return;
+        }
+        fill.finish().await;
+    }
+
+    /// Move a complete temp file into place and evict to stay within size.
+    /// Returns false if a blob was invalidated since the fill started.
+    async fn commit(&self, digest: &str, temp_path: &Path, size: u64, generation: u64) -> Result<bool> {
+        let Some(path) = self.path(digest) else {
+            return Ok(false);
+        };
+
+        let evicted = {
+            let mut index = self.index.lock().unwrap();
+            if index.generation != generation {
+                return Ok(false);
+            }
+            std::fs::rename(temp_path, &path)?;
+            index.insert(digest, size);
+            index.evict(self.max_bytes)
+        };
+        debug!("Cached blob {} ({} bytes), evicted {}", digest, size, evicted.len());
+        self.delete_files(evicted).await;
+        Ok(true)
+    }
+
+    async fn invalidate(&self, digest: &str) {
+        let Some(path) = self.path(digest) else {
+            return;
+        };
+        {
+            let mut index = self.index.lock().unwrap();
+            index.generation += 1;
+            if index.remove(digest) {
+                debug!("Invalidated cached blob {}", digest);
+            }
+        }
+        match fs::remove_file(&path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                warn!("Failed to remove cached blob {}: {}", digest, e);
+            }
+            _ => {}
+        }
+    }
+
+    async fn delete_files(&self, digests: Vec<String>) {
+        for digest in digests {
+            let Some(path) = self.path(&digest) else {
+                continue;
+            };
+            if let Err(e) = fs::remove_file(&path).await {
+                warn!("Failed to evict cached blob {}: {}", digest, e);
+            }
+        }
+    }
+}
+
+/// A blob being written into the cache as it is read from the backend
+struct CacheFill {
+    cache: Arc<BlobCache>,
+    digest: String,
+    temp_path: PathBuf,
+    file: fs::File,
+    hasher: Sha256,
+    written: u64,
+    expected: u64,
+    generation: u64,
+}
+
+impl CacheFill {
+    async fn write(&mut self, chunk: &[u8]) -> std::io::Result<()> {
+        self.file.write_all(chunk).await?;
+        self.hasher.update(chunk);
+        self.written += chunk.len() as u64;
+        Ok(())
+    }
+
+    /// Keep the file if it holds the whole blob and, for sha256 digests,
+    /// hashes to the digest
+    async fn finish(mut self) {
+        let complete = self.written == self.expected;
+        let verified = !self.digest.starts_with("sha256:")
+            || format!("sha256:{:x}", self.hasher.clone().finalize()) == self.digest;
+        if !complete || !verified {
+            warn!("Not caching blob {}: content doesn't match the digest or size", self.digest);
+            self.abandon().await;
+            return;
+        }
+
+        if let Err(e) = self.file.flush().await {
+            warn!("Failed to cache blob {}: {}", self.digest, e);
+            self.abandon().await;
+            return;
+        }
+        match self.cache.commit(&self.digest, &self.temp_path, self.written, self.generation).await {
+            Ok(true) => {}
+            Ok(false) => self.abandon().await,
+            Err(e) => {
+                warn!("Failed to cache blob {}: {}", self.digest, e);
+                self.abandon().await;
+            }
+        }
+    }
+
+    async fn abandon(self) {
+        let _ = fs::remove_file(&self.temp_path).await;
+    }
+}
+
+/// Pass a backend stream through, copying it into the cache on the way.
+/// A stream dropped part way leaves a temp file that is removed on the next start.
+fn fill_while_streaming(
+    stream: BoxStream<'static, Result<Bytes>>,
+    fill: CacheFill,
+) -> BoxStream<'static, Result<Bytes>> {
+    futures::stream::unfold((stream, Some(fill)), |(mut stream, mut fill)| async move {
+        match stream.next().await {
+            Some(Ok(chunk)) => {
+                if let Some(mut current) = fill.take() {
+                    match current.write(&chunk).await {
+                        Ok(()) => fill = Some(current),
+                        Err(e) => {
+                            warn!("Failed to cache blob {}: {}", current.digest, e);
+                            current.abandon().await;
+                        }
+                    }
+                }
+                Some((Ok(chunk), (stream, fill)))
+            }
+            Some(Err(e)) => {
+                if let Some(current) = fill.take() {
+                    current.abandon().await;
+                }
+                Some((Err(e), (stream, None)))
+            }
+            None => {
+                if let Some(current) = fill.take() {
+                    current.finish().await;
+                }
+                None
+            }
+        }
+    })
+    .boxed()
+}
+
+#[async_trait]
+impl StorageBackend for CachingStorage {
+    async fn put_blob(&self, digest: &str, data: Bytes) -> Result<()> {
+        self.inner.put_blob(digest, data).await?;
+        self.cache.invalidate(digest).await;
+        Ok(())
+    }
+
+    async fn get_blob(&self, digest: &str) -> Result<Option<Bytes>> {
+        if let Some(data) = self.cache.get(digest).await {
+            debug!("Serving blob {} from cache", digest);
+            return Ok(Some(data));
+        }
+
+        let generation = self.cache.generation();
+        let data = self.inner.get_blob(digest).await?;
+        if let Some(data) = &data {
+            self.cache.insert(digest, data, generation).await;
+        }
+        Ok(data)
+    }
+
+    async fn get_blob_stream(&self, digest: &str) -> Result<Option<(u64, BoxStream<'static, Result<Bytes>>)>> {
+        if let Some((size, file)) = self.cache.open_file(digest).await {
+            debug!("Streaming blob {} from cache", digest);
+            let stream = tokio_util::io::ReaderStream::new(file)
+                .map(|chunk| chunk.map_err(Into::into))
+                .boxed();
+            return Ok(Some((size, stream)));
+        }
+
+        let generation = self.cache.generation();
+        let Some((size, stream)) = self.inner.get_blob_stream(digest).await? else {
+            return Ok(None);
+        };
+        match self.cache.start_fill(digest, size, generation).await {
+            Some(fill) => Ok(Some((size, fill_while_streaming(stream, fill)))),
+            None => Ok(Some((size, stream))),
+        }
+    }
+
+    async fn delete_blob(&self, digest: &str) -> Result<()> {
+        self.inner.delete_blob(digest).await?;
+        self.cache.invalidate(digest).await;
+        Ok(())
+    }
+
+    async fn blob_exists(&self, digest: &str) -> Result<bool> {
+        self.inner.blob_exists(digest).await
+    }
+
+    async fn put_manifest(&self, repo: &str, reference: &str, data: Bytes, media_type: &str) -> Result<()> {
+        self.inner.put_manifest(repo, reference, data, media_type).await
+    }
+
+    async fn get_manifest(&self, repo: &str, reference: &str) -> Result<Option<Bytes>> {
+        self.inner.get_manifest(repo, reference).await
+    }
+
+    async fn delete_manifest(&self, repo: &str, reference: &str) -> Result<()> {
+        self.inner.delete_manifest(repo, reference).await
+    }
+
+    async fn list_tags_for_digest(&self, repo: &str, digest: &str) -> Result<Vec<String>> {
+        self.inner.list_tags_for_digest(repo, digest).await
+    }
+
+    async fn list_repositories(&self) -> Result<Vec<String>> {
+        self.inner.list_repositories().await
+    }
+
+    async fn list_tags(&self, repo: &str) -> Result<Vec<String>> {
+        self.inner.list_tags(repo).await
+    }
+
+    async fn get_upload_url(&self, uuid: &str) -> Result<Option<String>> {
+        self.inner.get_upload_url(uuid).await
+    }
+
+    async fn create_upload(&self, uuid: &str) -> Result<()> {
+        self.inner.create_upload(uuid).await
+    }
+
+    async fn get_upload_session(&self, uuid: &str) -> Result<Option<UploadSession>> {
+        self.inner.get_upload_session(uuid).await
+    }
+
+    async fn list_uploads(&self) -> Result<Vec<UploadSession>> {
+        self.inner.list_uploads().await
+    }
+
+    async fn append_upload(&self, uuid: &str, data: BoxStream<'static, Result<Bytes>>) -> Result<u64> {
+        self.inner.append_upload(uuid, data).await
+    }
+
+    async fn complete_upload(&self, uuid: &str, digest: &str) -> Result<()> {
+        self.inner.complete_upload(uuid, digest).await?;
+        self.cache.invalidate(digest).await;
+        Ok(())
+    }
+
+    async fn cancel_upload(&self, uuid: &str) -> Result<()> {
+        self.inner.cancel_upload(uuid).await
+    }
+
+    async fn list_all_blobs(&self) -> Result<Vec<String>> {
+        self.inner.list_all_blobs().await
+    }
+
+    async fn list_manifests(&self, repo: &str) -> Result<Vec<String>> {
+        self.inner.list_manifests(repo).await
+    }
+
+    async fn get_blob_metadata(&self, digest: &str) -> Result<BlobMetadata> {
+        self.inner.get_blob_metadata(digest).await
+    }
+
+    async fn get_manifest_metadata(&self, repo: &str, digest: &str) -> Result<ManifestMetadata> {
+        self.inner.get_manifest_metadata(repo, digest).await
+    }
+
+    async fn get_manifest_by_digest(&self, repo: &str, digest: &str) -> Result<Bytes> {
+        self.inner.get_manifest_by_digest(repo, digest).await
+    }
+
+    async fn get_manifest_digest(&self, repo: &str, reference: &str) -> Result<String> {
+        self.inner.get_manifest_digest(repo, reference).await
+    }
+
+    async fn rebuild_manifest_index(&self) -> Result<usize> {
+        self.inner.rebuild_manifest_index().await
+    }
+
+    async fn blob_references(&self) -> Result<Option<BlobReferences>> {
+        self.inner.blob_references().await
+    }
+
+    async fn fsck(&self) -> Result<FsckReport> {
+        self.inner.fsck().await
+    }
+}
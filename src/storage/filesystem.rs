@@ -1,15 +1,131 @@
-use super::{BlobMetadata, ManifestMetadata, StorageBackend};
+use super::{
+    is_digest_reference, BlobMetadata, BlobReferences, FsckReport, ManifestMetadata, StorageBackend, StorageError,
+    UploadHashers, UploadSession,
+};
+use crate::api::registry::{is_supported_digest, is_valid_repository_name, is_valid_tag};
 use anyhow::Result;
 use async_trait::async_trait;
 use bytes::Bytes;
-use chrono::{DateTime, Utc};
+use futures::stream::{BoxStream, StreamExt};
 use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
 use std::path::{Path, PathBuf};
 use tokio::fs;
-use tracing::{debug, error};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+/// Prefix of files being written; they are renamed into place once complete
+const TEMP_FILE_PREFIX: &str = ".tmp-";
+
+/// Directory under a repository's path in `manifests/` and `manifest_types/`
+/// holding its manifests. Repository path components start with a letter or
+/// digit, so a nested repository can never share a path with a reference.
+const MANIFESTS_SEGMENT: &str = "_manifests";
+
+/// Blob references kept under the storage root, one record per manifest at
+/// `refs/<repo>/<manifest digest>` listing the blobs it references
+const REFS_DIR: &str = "refs";
+/// Where `fsck` writes a rebuilt set of records before swapping it in
+const REFS_STAGING_DIR: &str = "refs.staging";
+/// The records `fsck` replaced, removed once the new ones are in place
+const REFS_RETIRED_DIR: &str = "refs.retired";
+/// Single-file table written by earlier versions, superseded by `REFS_DIR`
+const LEGACY_REFS_FILE: &str = "refs.json";
+/// Locks serializing changes to one manifest's record, striped by manifest
+const REF_LOCK_STRIPES: usize = 64;
+/// Locks serializing writes to one tag or digest reference, striped by reference
+const REFERENCE_LOCK_STRIPES: usize = 64;
 
 pub struct FilesystemStorage {
     base_path: PathBuf,
+    upload_hashers: UploadHashers,
+    verify_on_read: bool,
+    /// In-memory view of the reference records. Only held to update it, and by
+    /// `delete_blob` from checking a blob's references until the file is gone.
+    refs: Mutex<BlobRefTable>,
+    /// Held while a manifest's record is written or removed and while its
+    /// manifest files appear, so records for different manifests are written
+    /// concurrently
+    ref_locks: Vec<Mutex<()>>,
+    /// Held while a reference is written or deleted, so a tag's manifest, tag
+    /// marker and media type always change together. Writes to different
+    /// references, in the same repository or not, run concurrently.
+    reference_locks: Vec<Mutex<()>>,
+    /// Shared by manifest writes and deletes, and held exclusively by `fsck`
+    /// and `rebuild_manifest_index` so no manifest changes while they walk
+    manifest_writes: RwLock<()>,
+}
+
+/// Which manifests reference which blobs, so a blob shared between
+/// repositories is only deleted once nothing references it
+#[derive(Debug, Default)]
+struct BlobRefTable {
+    /// Blob digest -> manifests (`repo@digest`) that reference it
+    blobs: HashMap<String, BTreeSet<String>>,
+    /// Manifest (`repo@digest`) -> blobs it references
+    manifests: HashMap<String, Vec<String>>,
+    /// Manifests that could not be parsed, so their references are unknown
+    unreadable: BTreeSet<String>,
+}
+
+impl BlobRefTable {
+    /// Record a manifest's blobs (None if it could not be parsed), returning
+    /// false if it was already recorded
+    fn add(&mut self, manifest: String, blobs: Option<Vec<String>>) -> bool {
+        if self.manifests.contains_key(&manifest) {
+            return false;
+        }
+        let Some(blobs) = blobs else {
+            self.unreadable.insert(manifest.clone());
+            self.manifests.insert(manifest, Vec::new());
+            return true;
+        };
+        for blob in &blobs {
+            self.blobs.entry(blob.clone()).or_default().insert(manifest.clone());
+        }
+        self.manifests.insert(manifest, blobs);
+        true
+    }
+
+    /// Drop a manifest's references, returning false if it was not recorded
+    fn remove(&mut self, manifest: &str) -> bool {
+        let Some(blobs) = self.manifests.remove(manifest) else {
+            return false;
+        };
+        self.unreadable.remove(manifest);
+        for blob in blobs {
+            if let Some(references) = self.blobs.get_mut(&blob) {
+                references.remove(manifest);
+                if references.is_empty() {
+                    self.blobs.remove(&blob);
+                }
+            }
+        }
+        true
+    }
+}
+
+/// Blob digests a manifest references: its config, layers and artifact
+/// blobs. None if the manifest is not valid JSON.
+fn manifest_blob_digests(data: &[u8]) -> Option<Vec<String>> {
+    let manifest = serde_json::from_slice::<serde_json::Value>(data).ok()?;
+
+    let config = manifest.get("config").into_iter();
+    let listed = ["layers", "blobs", "foreignLayers"]
+        .into_iter()
+        .filter_map(|field| manifest.get(field).and_then(|descriptors| descriptors.as_array()))
+        .flatten();
+    let mut digests: Vec<String> = config
+        .chain(listed)
+        .filter_map(|descriptor| descriptor.get("digest").and_then(|digest| digest.as_str()))
+        .filter(|digest| is_digest_reference(digest))
+        .map(str::to_string)
+        .collect();
+    digests.sort();
+    digests.dedup();
+    Some(digests)
 }
 
 impl FilesystemStorage {
@@ -22,9 +138,184 @@ impl FilesystemStorage {
         fs::create_dir_all(base_path.join("manifests")).await?;
         fs::create_dir_all(base_path.join("uploads")).await?;
 
+        // Earlier versions kept references directly in the repository's directory
+        for dir in ["manifests", "manifest_types"] {
+            let moved = Self::migrate_manifest_layout(&base_path.join(dir)).await?;
+            if moved > 0 {
+                info!("Moved {} manifest files in {} under {}", moved, dir, MANIFESTS_SEGMENT);
+            }
+        }
+
+        // Writes interrupted by a crash never reached their final path
+        for dir in ["blobs", "manifests", "manifest_types", "manifest_tags", REFS_DIR] {
+            let removed = Self::remove_temp_files(&base_path.join(dir)).await?;
+            if removed > 0 {
+                warn!("Removed {} incomplete writes left in {}", removed, dir);
+            }
+        }
+
         debug!("Initialized filesystem storage at: {:?}", base_path);
 
-        Ok(Self { base_path })
+        let storage = Self {
+            base_path,
+            upload_hashers: UploadHashers::default(),
+            verify_on_read: false,
+            refs: Mutex::new(BlobRefTable::default()),
+            ref_locks: (0..REF_LOCK_STRIPES).map(|_| Mutex::new(())).collect(),
+            reference_locks: (0..REFERENCE_LOCK_STRIPES).map(|_| Mutex::new(())).collect(),
+            manifest_writes: RwLock::new(()),
+        };
+
+        // A rebuild interrupted by a crash; its records are redone below if needed
+        for dir in [REFS_STAGING_DIR, REFS_RETIRED_DIR] {
+            let _ = fs::remove_dir_all(storage.base_path.join(dir)).await;
+        }
+        match storage.load_refs().await {
+            Ok(Some(refs)) => *storage.refs.lock().await = refs,
+            Ok(None) => {
+                info!("Building blob reference table from stored manifests");
+                storage.fsck().await?;
+            }
+            Err(e) => {
+                warn!("Blob reference records are unreadable ({}), rebuilding them", e);
+                storage.fsck().await?;
+            }
+        }
+        let _ = fs::remove_file(storage.base_path.join(LEGACY_REFS_FILE)).await;
+
+        Ok(storage)
+    }
+
+    /// Hash blobs before serving them, failing instead of returning corrupt content
+    pub fn with_verify_on_read(mut self, verify_on_read: bool) -> Self {
+        self.verify_on_read = verify_on_read;
+        self
+    }
+
+    /// Only content is addressed by its digest; other state stored as blobs
+    /// (RBAC documents, robot accounts, holds) has nothing to verify against
+    fn verifies(&self, key: &str) -> bool {
+        self.verify_on_read && is_digest_reference(key)
+    }
+
+    fn is_temp_file(name: &str) -> bool {
+        name.starts_with(TEMP_FILE_PREFIX)
+    }
+
+    /// Delete leftover temp files anywhere under a directory, returning how many were removed
+    async fn remove_temp_files(root: &Path) -> Result<usize> {
+        let mut removed = 0;
+        let mut pending = vec![root.to_path_buf()];
+
+        while let Some(dir) = pending.pop() {
+            let mut entries = match fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                if entry.file_type().await?.is_dir() {
+                    pending.push(entry.path());
+                } else if entry.file_name().to_str().is_some_and(Self::is_temp_file) {
+                    fs::remove_file(entry.path()).await?;
+                    removed += 1;
+                }
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Move files kept in a repository's own directory, the layout earlier versions
+    /// used, into its `MANIFESTS_SEGMENT` directory, returning how many were moved
+    async fn migrate_manifest_layout(root: &Path) -> Result<usize> {
+        let mut moved = 0;
+        let mut pending = vec![root.to_path_buf()];
+
+        while let Some(dir) = pending.pop() {
+            let mut entries = match fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                let name = entry.file_name();
+                if entry.file_type().await?.is_dir() {
+                    if name != MANIFESTS_SEGMENT {
+                        pending.push(entry.path());
+                    }
+                } else if dir != root {
+                    let target = dir.join(MANIFESTS_SEGMENT);
+                    fs::create_dir_all(&target).await?;
+                    fs::rename(entry.path(), target.join(&name)).await?;
+                    moved += 1;
+                }
+            }
+        }
+
+        Ok(moved)
+    }
+
+    /// Write a file so readers see either nothing or all of it: the data goes
+    /// to a temp file in the same directory, is fsynced, then renamed into place
+    async fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+        let parent = path.parent().ok_or_else(|| anyhow::anyhow!("No parent directory for {:?}", path))?;
+        let temp_path = parent.join(format!("{}{}", TEMP_FILE_PREFIX, Uuid::new_v4()));
+
+        let result = async {
+            let mut file = fs::File::create(&temp_path).await?;
+            file.write_all(data).await?;
+            file.sync_all().await?;
+            fs::rename(&temp_path, path).await
+        }
+        .await;
+        if let Err(e) = result {
+            let _ = fs::remove_file(&temp_path).await;
+            return Err(e.into());
+        }
+
+        Self::sync_dir(parent).await;
+        Ok(())
+    }
+
+    /// Persist a rename by fsyncing the directory holding it. Best effort:
+    /// not every platform can open a directory for syncing.
+    async fn sync_dir(dir: &Path) {
+        if let Ok(dir) = fs::File::open(dir).await {
+            let _ = dir.sync_all().await;
+        }
+    }
+
+    /// The manifest files in a repository's `MANIFESTS_SEGMENT` directory,
+    /// leaving out writes still in progress
+    async fn list_manifest_files(dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        let mut entries = match fs::read_dir(dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(files),
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_type().await?.is_file()
+                && entry.file_name().to_str().is_some_and(|name| !Self::is_temp_file(name))
+            {
+                files.push(entry.path());
+            }
+        }
+        Ok(files)
+    }
+
+    /// Fail if a blob's content no longer hashes to its digest
+    fn verify_blob(&self, digest: &str, actual: String) -> Result<()> {
+        if actual == digest {
+            return Ok(());
+        }
+        error!("Blob {} is corrupt on disk (content hashes to {})", digest, actual);
+        Err(StorageError::CorruptBlob {
+            digest: digest.to_string(),
+            actual,
+        }
+        .into())
     }
 
     fn blob_path(&self, digest: &str) -> PathBuf {
@@ -33,15 +324,205 @@ impl FilesystemStorage {
         self.base_path.join("blobs").join(prefix).join(digest)
     }
 
-    fn manifest_path(&self, repo: &str, reference: &str) -> PathBuf {
+    /// Where a repository's manifests are kept under `root`, or None for a name
+    /// that isn't a valid repository name and so could never have been stored
+    fn repository_path(&self, root: &str, repo: &str) -> Option<PathBuf> {
+        is_valid_repository_name(repo).then(|| self.base_path.join(root).join(repo).join(MANIFESTS_SEGMENT))
+    }
+
+    /// Where a manifest is kept, or None if the repository name or the
+    /// reference, which must be a tag or a digest, is malformed
+    fn manifest_path(&self, repo: &str, reference: &str) -> Option<PathBuf> {
+        let valid = is_valid_tag(reference) || is_supported_digest(reference);
+        valid.then(|| self.repository_path("manifests", repo)).flatten().map(|path| path.join(reference))
+    }
+
+    /// Writes only accept what `manifest_path` can place
+    fn writable_manifest_path(&self, repo: &str, reference: &str) -> Result<PathBuf> {
+        self.manifest_path(repo, reference).ok_or_else(|| {
+            StorageError::InvalidName { name: format!("{}:{}", repo, reference) }.into()
+        })
+    }
+
+    fn manifest_media_type_path(&self, repo: &str, reference: &str) -> Option<PathBuf> {
+        self.repository_path("manifest_types", repo).map(|path| path.join(reference))
+    }
+
+    /// Directory of marker files naming the tags that point at a digest
+    fn manifest_tags_path(&self, repo: &str, digest: &str) -> PathBuf {
         self.base_path
-            .join("manifests")
+            .join("manifest_tags")
             .join(repo)
-            .join(reference)
+            .join(digest)
+    }
+
+    /// Drop the digest -> tag marker for whatever a tag currently points at,
+    /// returning that digest
+    async fn remove_tag_marker(&self, repo: &str, tag: &str) -> Result<Option<String>> {
+        let Some(path) = self.manifest_path(repo, tag) else {
+            return Ok(None);
+        };
+        match fs::read(path).await {
+            Ok(data) => {
+                let digest = format!("sha256:{:x}", Sha256::digest(&data));
+                match fs::remove_file(self.manifest_tags_path(repo, &digest).join(tag)).await {
+                    Ok(()) => Ok(Some(digest)),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Some(digest)),
+                    Err(e) => Err(e.into()),
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn ref_record_path(&self, repo: &str, digest: &str) -> PathBuf {
+        self.base_path.join(REFS_DIR).join(repo).join(digest)
+    }
+
+    /// The lock over one manifest's reference record
+    fn ref_lock(&self, repo: &str, digest: &str) -> &Mutex<()> {
+        Self::stripe(&self.ref_locks, repo, digest)
+    }
+
+    /// The lock over writes to one tag or digest reference. Taken before any
+    /// `ref_lock`, never after one.
+    fn reference_lock(&self, repo: &str, reference: &str) -> &Mutex<()> {
+        Self::stripe(&self.reference_locks, repo, reference)
+    }
+
+    fn stripe<'a>(locks: &'a [Mutex<()>], repo: &str, key: &str) -> &'a Mutex<()> {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        (repo, key).hash(&mut hasher);
+        &locks[hasher.finish() as usize % locks.len()]
+    }
+
+    /// Read every manifest's reference record, or None if none were ever written
+    async fn load_refs(&self) -> Result<Option<BlobRefTable>> {
+        let root = self.base_path.join(REFS_DIR);
+        if !root.exists() {
+            return Ok(None);
+        }
+
+        let mut table = BlobRefTable::default();
+        let mut pending = vec![root.clone()];
+        while let Some(dir) = pending.pop() {
+            let mut entries = fs::read_dir(&dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                if entry.file_type().await?.is_dir() {
+                    pending.push(entry.path());
+                    continue;
+                }
+                let name = entry.file_name();
+                let Some(digest) = name.to_str().filter(|name| !Self::is_temp_file(name)) else {
+                    continue;
+                };
+                let repo = dir.strip_prefix(&root).ok().and_then(|repo| repo.to_str());
+                let Some(repo) = repo.filter(|repo| !repo.is_empty()) else {
+                    continue;
+                };
+                let blobs: Option<Vec<String>> = serde_json::from_slice(&fs::read(entry.path()).await?)?;
+                table.add(format!("{}@{}", repo, digest), blobs);
+            }
+        }
+        Ok(Some(table))
+    }
+
+    /// Record a manifest's blob references, on disk before in memory, so a crash
+    /// can only leave blobs referenced by a manifest that isn't there. The
+    /// caller holds the manifest's `ref_lock`.
+    async fn add_manifest_refs(&self, repo: &str, digest: &str, data: &[u8]) -> Result<()> {
+        let manifest = format!("{}@{}", repo, digest);
+        if self.refs.lock().await.manifests.contains_key(&manifest) {
+            return Ok(());
+        }
+
+        let blobs = manifest_blob_digests(data);
+        let path = self.ref_record_path(repo, digest);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        Self::write_atomic(&path, &serde_json::to_vec(&blobs)?).await?;
+        self.refs.lock().await.add(manifest, blobs);
+        Ok(())
     }
 
-    fn upload_path(&self, uuid: &str) -> PathBuf {
-        self.base_path.join("uploads").join(uuid)
+    /// Drop a manifest's blob references once no tag or digest reference
+    /// in the repository points at it any more
+    async fn release_manifest_refs(&self, repo: &str, digest: &str) -> Result<()> {
+        let _record = self.ref_lock(repo, digest).lock().await;
+        let stored = self.manifest_path(repo, digest).is_some_and(|path| path.exists());
+        if stored || !self.list_tags_for_digest(repo, digest).await?.is_empty() {
+            return Ok(());
+        }
+
+        if !self.refs.lock().await.remove(&format!("{}@{}", repo, digest)) {
+            return Ok(());
+        }
+        match fs::remove_file(self.ref_record_path(repo, digest)).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        debug!("Released blob references of {}@{}", repo, digest);
+        Ok(())
+    }
+
+    /// Replace every reference record with `table`. The records are written to
+    /// a staging directory that is then swapped in, so a crash leaves either
+    /// the old records or none, and missing records are rebuilt at startup.
+    async fn save_refs(&self, table: &BlobRefTable) -> Result<()> {
+        let staging = self.base_path.join(REFS_STAGING_DIR);
+        let _ = fs::remove_dir_all(&staging).await;
+        fs::create_dir_all(&staging).await?;
+        for (manifest, blobs) in &table.manifests {
+            let Some((repo, digest)) = manifest.rsplit_once('@') else {
+                continue;
+            };
+            let blobs = (!table.unreadable.contains(manifest)).then_some(blobs);
+            let path = staging.join(repo).join(digest);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            fs::write(&path, serde_json::to_vec(&blobs)?).await?;
+        }
+
+        let live = self.base_path.join(REFS_DIR);
+        let retired = self.base_path.join(REFS_RETIRED_DIR);
+        if live.exists() {
+            fs::rename(&live, &retired).await?;
+        }
+        fs::rename(&staging, &live).await?;
+        Self::sync_dir(&self.base_path).await;
+        let _ = fs::remove_dir_all(&retired).await;
+        Ok(())
+    }
+
+    /// Path of an upload session's file. Session IDs are UUIDs; anything else
+    /// could name a file outside the uploads directory.
+    fn upload_path(&self, uuid: &str) -> Result<PathBuf> {
+        Uuid::parse_str(uuid).map_err(|_| anyhow::anyhow!("Invalid upload session ID {:?}", uuid))?;
+        Ok(self.base_path.join("uploads").join(uuid))
+    }
+
+    /// Compute the sha256 digest of a file without reading it all into memory
+    async fn hash_file(path: &Path) -> Result<String> {
+        use tokio::io::AsyncReadExt;
+
+        let mut file = fs::File::open(path).await?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0u8; 64 * 1024];
+
+        loop {
+            let read = file.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+
+        Ok(format!("sha256:{:x}", hasher.finalize()))
     }
 }
 
@@ -54,7 +535,7 @@ impl StorageBackend for FilesystemStorage {
             fs::create_dir_all(parent).await?;
         }
 
-        fs::write(&path, &data).await?;
+        Self::write_atomic(&path, &data).await?;
         debug!("Stored blob {} ({} bytes)", digest, data.len());
         Ok(())
     }
@@ -64,6 +545,9 @@ impl StorageBackend for FilesystemStorage {
 
         match fs::read(&path).await {
             Ok(data) => {
+                if self.verifies(digest) {
+                    self.verify_blob(digest, format!("sha256:{:x}", Sha256::digest(&data)))?;
+                }
                 debug!("Retrieved blob {} ({} bytes)", digest, data.len());
                 Ok(Some(data.into()))
             }
@@ -75,10 +559,46 @@ impl StorageBackend for FilesystemStorage {
         }
     }
 
+    async fn get_blob_stream(&self, digest: &str) -> Result<Option<(u64, BoxStream<'static, Result<Bytes>>)>> {
+        let path = self.blob_path(digest);
+
+        // Checked up front: once streaming starts, corrupt bytes would already be on the wire
+        if self.verifies(digest) && path.exists() {
+            self.verify_blob(digest, Self::hash_file(&path).await?)?;
+        }
+
+        match fs::File::open(&path).await {
+            Ok(file) => {
+                let size = file.metadata().await?.len();
+                debug!("Streaming blob {} ({} bytes)", digest, size);
+
+                let stream = tokio_util::io::ReaderStream::new(file)
+                    .map(|chunk| chunk.map_err(Into::into))
+                    .boxed();
+                Ok(Some((size, stream)))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => {
+                error!("Failed to open blob {}: {}", digest, e);
+                Err(e.into())
+            }
+        }
+    }
+
     async fn delete_blob(&self, digest: &str) -> Result<()> {
         let path = self.blob_path(digest);
 
-        match fs::remove_file(&path).await {
+        // Shared between repositories: the file goes once no manifest references it.
+        // The table stays locked until then, so no manifest starts referencing it meanwhile.
+        let refs = self.refs.lock().await;
+        if let Some(references) = refs.blobs.get(digest) {
+            warn!("Keeping blob {}: still referenced by {} manifests", digest, references.len());
+            return Ok(());
+        }
+        let removed = fs::remove_file(&path).await;
+        drop(refs);
+
+        match removed {
             Ok(()) => {
                 debug!("Deleted blob {}", digest);
                 Ok(())
@@ -96,20 +616,57 @@ impl StorageBackend for FilesystemStorage {
         Ok(path.exists())
     }
 
-    async fn put_manifest(&self, repo: &str, reference: &str, data: Bytes) -> Result<()> {
-        let path = self.manifest_path(repo, reference);
+    async fn put_manifest(&self, repo: &str, reference: &str, data: Bytes, media_type: &str) -> Result<()> {
+        let path = self.writable_manifest_path(repo, reference)?;
 
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent).await?;
         }
 
-        fs::write(&path, &data).await?;
+        let _writing = self.manifest_writes.read().await;
+        let _reference = self.reference_lock(repo, reference).lock().await;
+
+        // Retagging moves the tag off the digest it used to point at
+        let previous = if is_digest_reference(reference) {
+            None
+        } else {
+            self.remove_tag_marker(repo, reference).await?
+        };
+        let digest = format!("sha256:{:x}", Sha256::digest(&data));
+
+        // Referenced before the manifest is visible, so its blobs are never deletable while it is
+        let record = self.ref_lock(repo, &digest).lock().await;
+        self.add_manifest_refs(repo, &digest, &data).await?;
+
+        // Media type lives outside the manifests tree so tag listing isn't affected.
+        // Written first, so a newly visible manifest always has one.
+        let media_type_path = self.manifest_media_type_path(repo, reference).expect("the manifest path is valid");
+        if let Some(parent) = media_type_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        Self::write_atomic(&media_type_path, media_type.as_bytes()).await?;
+
+        Self::write_atomic(&path, &data).await?;
+
+        if !is_digest_reference(reference) {
+            let tags_path = self.manifest_tags_path(repo, &digest);
+            fs::create_dir_all(&tags_path).await?;
+            fs::write(tags_path.join(reference), b"").await?;
+        }
+        drop(record);
+
+        if let Some(previous) = previous.filter(|previous| *previous != digest) {
+            self.release_manifest_refs(repo, &previous).await?;
+        }
+
         debug!("Stored manifest {}/{} ({} bytes)", repo, reference, data.len());
         Ok(())
     }
 
     async fn get_manifest(&self, repo: &str, reference: &str) -> Result<Option<Bytes>> {
-        let path = self.manifest_path(repo, reference);
+        let Some(path) = self.manifest_path(repo, reference) else {
+            return Ok(None);
+        };
 
         match fs::read(&path).await {
             Ok(data) => {
@@ -125,33 +682,86 @@ impl StorageBackend for FilesystemStorage {
     }
 
     async fn delete_manifest(&self, repo: &str, reference: &str) -> Result<()> {
-        let path = self.manifest_path(repo, reference);
+        let Some(path) = self.manifest_path(repo, reference) else {
+            return Ok(());
+        };
+        let _writing = self.manifest_writes.read().await;
+        let _reference = self.reference_lock(repo, reference).lock().await;
+
+        let digest = if is_digest_reference(reference) {
+            Some(reference.to_string())
+        } else {
+            self.remove_tag_marker(repo, reference).await?
+        };
 
         match fs::remove_file(&path).await {
             Ok(()) => {
+                if let Some(media_type_path) = self.manifest_media_type_path(repo, reference) {
+                    let _ = fs::remove_file(media_type_path).await;
+                }
                 debug!("Deleted manifest {}/{}", repo, reference);
-                Ok(())
             }
-            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()), // Already deleted
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {} // Already deleted
             Err(e) => {
                 error!("Failed to delete manifest {}/{}: {}", repo, reference, e);
-                Err(e.into())
+                return Err(e.into());
             }
         }
+
+        match digest {
+            Some(digest) => self.release_manifest_refs(repo, &digest).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn list_tags_for_digest(&self, repo: &str, digest: &str) -> Result<Vec<String>> {
+        let tags_path = self.manifest_tags_path(repo, digest);
+        let mut tags = Vec::new();
+
+        let mut entries = match fs::read_dir(&tags_path).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(tags),
+            Err(e) => return Err(e.into()),
+        };
+
+        while let Some(entry) = entries.next_entry().await? {
+            if let Some(tag) = entry.file_name().to_str().filter(|tag| !Self::is_temp_file(tag)) {
+                tags.push(tag.to_string());
+            }
+        }
+
+        tags.sort();
+        Ok(tags)
     }
 
     async fn list_repositories(&self) -> Result<Vec<String>> {
         let manifests_path = self.base_path.join("manifests");
         let mut repos = Vec::new();
 
-        if !manifests_path.exists() {
-            return Ok(repos);
-        }
+        // Nested repositories are nested directories; any directory holding
+        // manifests is a repository, whether or not it has nested ones too
+        let mut pending = vec![manifests_path.clone()];
+        while let Some(dir) = pending.pop() {
+            let mut entries = match fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let mut has_manifests = false;
+            while let Some(entry) = entries.next_entry().await? {
+                if !entry.file_type().await?.is_dir() {
+                    continue;
+                }
+                if entry.file_name() == MANIFESTS_SEGMENT {
+                    has_manifests = !Self::list_manifest_files(&entry.path()).await?.is_empty();
+                } else {
+                    pending.push(entry.path());
+                }
+            }
 
-        let mut entries = fs::read_dir(&manifests_path).await?;
-        while let Some(entry) = entries.next_entry().await? {
-            if entry.file_type().await?.is_dir() {
-                if let Some(name) = entry.file_name().to_str() {
+            if has_manifests {
+                let name = dir.strip_prefix(&manifests_path).ok().and_then(|repo| repo.to_str());
+                if let Some(name) = name.filter(|name| !name.is_empty()) {
                     repos.push(name.to_string());
                 }
             }
@@ -162,28 +772,21 @@ impl StorageBackend for FilesystemStorage {
     }
 
     async fn list_tags(&self, repo: &str) -> Result<Vec<String>> {
-        let repo_path = self.base_path.join("manifests").join(repo);
-        let mut tags = Vec::new();
-
-        if !repo_path.exists() {
-            return Ok(tags);
-        }
-
-        let mut entries = fs::read_dir(&repo_path).await?;
-        while let Some(entry) = entries.next_entry().await? {
-            if entry.file_type().await?.is_file() {
-                if let Some(name) = entry.file_name().to_str() {
-                    tags.push(name.to_string());
-                }
-            }
-        }
-
+        let Some(repo_path) = self.repository_path("manifests", repo) else {
+            return Ok(Vec::new());
+        };
+
+        let mut tags: Vec<String> = Self::list_manifest_files(&repo_path)
+            .await?
+            .into_iter()
+            .filter_map(|path| path.file_name().and_then(|name| name.to_str()).map(str::to_string))
+            .collect();
         tags.sort();
         Ok(tags)
     }
 
     async fn get_upload_url(&self, uuid: &str) -> Result<Option<String>> {
-        let path = self.upload_path(uuid);
+        let path = self.upload_path(uuid)?;
         if path.exists() {
             Ok(Some(format!("/v2/uploads/{}", uuid)))
         } else {
@@ -191,46 +794,118 @@ impl StorageBackend for FilesystemStorage {
         }
     }
 
-    async fn put_upload_chunk(&self, uuid: &str, range: (u64, u64), data: Bytes) -> Result<()> {
-        let path = self.upload_path(uuid);
+    async fn create_upload(&self, uuid: &str) -> Result<()> {
+        let path = self.upload_path(uuid)?;
 
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent).await?;
         }
 
-        // For filesystem storage, we'll append chunks in order
-        // In a real implementation, you'd want to handle random writes properly
-        let mut file = tokio::fs::OpenOptions::new()
-            .create(true)
-            .write(true)
+        fs::File::create(&path).await?;
+        debug!("Created upload session {}", uuid);
+        Ok(())
+    }
+
+    async fn get_upload_session(&self, uuid: &str) -> Result<Option<UploadSession>> {
+        // The session file holds exactly the bytes received so far
+        match fs::metadata(self.upload_path(uuid)?).await {
+            Ok(metadata) => Ok(Some(UploadSession {
+                uuid: uuid.to_string(),
+                size: metadata.len(),
+                updated_at: metadata.modified()?.into(),
+            })),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn list_uploads(&self) -> Result<Vec<UploadSession>> {
+        let uploads_path = self.base_path.join("uploads");
+        let mut sessions = Vec::new();
+
+        if !uploads_path.exists() {
+            return Ok(sessions);
+        }
+
+        let mut entries = fs::read_dir(&uploads_path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            if !metadata.is_file() {
+                continue;
+            }
+
+            if let Some(uuid) = entry.file_name().to_str().filter(|name| Uuid::parse_str(name).is_ok()) {
+                sessions.push(UploadSession {
+                    uuid: uuid.to_string(),
+                    size: metadata.len(),
+                    updated_at: metadata.modified()?.into(),
+                });
+            }
+        }
+
+        Ok(sessions)
+    }
+
+    async fn append_upload(&self, uuid: &str, mut data: BoxStream<'static, Result<Bytes>>) -> Result<u64> {
+        let path = self.upload_path(uuid)?;
+        let mut file = fs::OpenOptions::new()
+            .append(true)
             .open(&path)
             .await?;
 
-        use tokio::io::{AsyncSeekExt, AsyncWriteExt};
-        file.seek(std::io::SeekFrom::Start(range.0)).await?;
-        file.write_all(&data).await?;
+        let start = file.metadata().await?.len();
+        let mut written = 0u64;
+        while let Some(chunk) = data.next().await {
+            let chunk = chunk?;
+            file.write_all(&chunk).await?;
+            self.upload_hashers.update(uuid, start + written, &chunk);
+            written += chunk.len() as u64;
+        }
         file.flush().await?;
 
-        debug!("Wrote upload chunk {} range {:?} ({} bytes)", uuid, range, data.len());
-        Ok(())
+        let offset = start + written;
+        debug!("Appended {} bytes to upload {} (offset {})", written, uuid, offset);
+        Ok(offset)
     }
 
     async fn complete_upload(&self, uuid: &str, digest: &str) -> Result<()> {
-        let upload_path = self.upload_path(uuid);
+        let upload_path = self.upload_path(uuid)?;
         let blob_path = self.blob_path(digest);
 
-        if let Some(parent) = blob_path.parent() {
-            fs::create_dir_all(parent).await?;
+        // Verify the assembled content before it becomes addressable
+        let size = fs::metadata(&upload_path).await?.len();
+        let actual = match self.upload_hashers.finish(uuid, size) {
+            Some(actual) => actual,
+            None => Self::hash_file(&upload_path).await?,
+        };
+        if actual != digest {
+            self.cancel_upload(uuid).await?;
+            return Err(StorageError::DigestMismatch {
+                expected: digest.to_string(),
+                actual,
+            }
+            .into());
         }
 
+        // Appends are not fsynced; make sure the data is durable before the
+        // blob becomes addressable
+        fs::File::open(&upload_path).await?.sync_all().await?;
+
+        let blob_dir = blob_path
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("No parent directory for {:?}", blob_path))?;
+        fs::create_dir_all(blob_dir).await?;
+
         // Move upload to blob storage
         fs::rename(&upload_path, &blob_path).await?;
+        Self::sync_dir(blob_dir).await;
         debug!("Completed upload {} -> blob {}", uuid, digest);
         Ok(())
     }
 
     async fn cancel_upload(&self, uuid: &str) -> Result<()> {
-        let path = self.upload_path(uuid);
+        let path = self.upload_path(uuid)?;
+        self.upload_hashers.remove(uuid);
 
         match fs::remove_file(&path).await {
             Ok(()) => {
@@ -263,10 +938,10 @@ impl StorageBackend for FilesystemStorage {
                 let mut blob_entries = fs::read_dir(&prefix_path).await?;
 
                 while let Some(blob_entry) = blob_entries.next_entry().await? {
-                    if blob_entry.file_type().await?.is_file() {
-                        if let Some(digest) = blob_entry.file_name().to_str() {
-                            blobs.push(digest.to_string());
-                        }
+                    if blob_entry.file_type().await?.is_file()
+                        && let Some(digest) = blob_entry.file_name().to_str().filter(|name| !Self::is_temp_file(name))
+                    {
+                        blobs.push(digest.to_string());
                     }
                 }
             }
@@ -277,23 +952,15 @@ impl StorageBackend for FilesystemStorage {
 
     async fn list_manifests(&self, repo: &str) -> Result<Vec<String>> {
         let mut manifests = Vec::new();
-        let repo_path = self.base_path.join("manifests").join(repo);
-
-        if !repo_path.exists() {
+        let Some(repo_path) = self.repository_path("manifests", repo) else {
             return Ok(manifests);
-        }
+        };
 
-        let mut entries = fs::read_dir(&repo_path).await?;
-
-        while let Some(entry) = entries.next_entry().await? {
-            if entry.file_type().await?.is_file() {
-                if let Some(name) = entry.file_name().to_str() {
-                    // For manifest digests, we need to compute the SHA256 of the file
-                    let manifest_data = fs::read(entry.path()).await?;
-                    let digest = format!("sha256:{:x}", Sha256::digest(&manifest_data));
-                    manifests.push(digest);
-                }
-            }
+        for path in Self::list_manifest_files(&repo_path).await? {
+            // For manifest digests, we need to compute the SHA256 of the file
+            let manifest_data = fs::read(path).await?;
+            let digest = format!("sha256:{:x}", Sha256::digest(&manifest_data));
+            manifests.push(digest);
         }
 
         Ok(manifests)
@@ -315,31 +982,33 @@ impl StorageBackend for FilesystemStorage {
 
     async fn get_manifest_metadata(&self, repo: &str, digest: &str) -> Result<ManifestMetadata> {
         // For digest-based lookups, we need to find the manifest file
-        let repo_path = self.base_path.join("manifests").join(repo);
-
-        if !repo_path.exists() {
-            return Err(anyhow::anyhow!("Repository not found: {}", repo));
-        }
-
-        let mut entries = fs::read_dir(&repo_path).await?;
-
-        while let Some(entry) = entries.next_entry().await? {
-            if entry.file_type().await?.is_file() {
-                // Check if this file's digest matches
-                let manifest_data = fs::read(entry.path()).await?;
-                let file_digest = format!("sha256:{:x}", Sha256::digest(&manifest_data));
-
-                if file_digest == digest {
-                    let metadata = fs::metadata(entry.path()).await?;
-                    let created_at = metadata.created()
-                        .or_else(|_| metadata.modified())?
-                        .into();
-
-                    return Ok(ManifestMetadata {
-                        size: metadata.len(),
-                        created_at,
-                    });
-                }
+        let repo_path = self
+            .repository_path("manifests", repo)
+            .filter(|path| path.exists())
+            .ok_or_else(|| anyhow::anyhow!("Repository not found: {}", repo))?;
+
+        for path in Self::list_manifest_files(&repo_path).await? {
+            // Check if this file's digest matches
+            let manifest_data = fs::read(&path).await?;
+            let file_digest = format!("sha256:{:x}", Sha256::digest(&manifest_data));
+
+            if file_digest == digest {
+                let metadata = fs::metadata(&path).await?;
+                let created_at = metadata.created()
+                    .or_else(|_| metadata.modified())?
+                    .into();
+
+                let reference = path.file_name().and_then(|name| name.to_str());
+                let media_type = match reference.and_then(|reference| self.manifest_media_type_path(repo, reference)) {
+                    Some(media_type_path) => fs::read_to_string(media_type_path).await.ok(),
+                    None => None,
+                };
+
+                return Ok(ManifestMetadata {
+                    size: metadata.len(),
+                    created_at,
+                    media_type,
+                });
             }
         }
 
@@ -347,23 +1016,18 @@ impl StorageBackend for FilesystemStorage {
     }
 
     async fn get_manifest_by_digest(&self, repo: &str, digest: &str) -> Result<Bytes> {
-        let repo_path = self.base_path.join("manifests").join(repo);
-
-        if !repo_path.exists() {
-            return Err(anyhow::anyhow!("Repository not found: {}", repo));
-        }
-
-        let mut entries = fs::read_dir(&repo_path).await?;
-
-        while let Some(entry) = entries.next_entry().await? {
-            if entry.file_type().await?.is_file() {
-                // Check if this file's digest matches
-                let manifest_data = fs::read(entry.path()).await?;
-                let file_digest = format!("sha256:{:x}", Sha256::digest(&manifest_data));
-
-                if file_digest == digest {
-                    return Ok(manifest_data.into());
-                }
+        let repo_path = self
+            .repository_path("manifests", repo)
+            .filter(|path| path.exists())
+            .ok_or_else(|| anyhow::anyhow!("Repository not found: {}", repo))?;
+
+        for path in Self::list_manifest_files(&repo_path).await? {
+            // Check if this file's digest matches
+            let manifest_data = fs::read(path).await?;
+            let file_digest = format!("sha256:{:x}", Sha256::digest(&manifest_data));
+
+            if file_digest == digest {
+                return Ok(manifest_data.into());
             }
         }
 
@@ -377,4 +1041,121 @@ impl StorageBackend for FilesystemStorage {
         let digest = format!("sha256:{:x}", Sha256::digest(&manifest_data));
         Ok(digest)
     }
-}
\ No newline at end of file
+
+    async fn rebuild_manifest_index(&self) -> Result<usize> {
+        let _writing = self.manifest_writes.write().await;
+        let mut written = 0;
+
+        // Tags pushed before digest -> tag markers existed have none
+        for repo in self.list_repositories().await? {
+            for tag in self.list_tags(&repo).await? {
+                if is_digest_reference(&tag) {
+                    continue;
+                }
+                let Some(data) = self.get_manifest(&repo, &tag).await? else {
+                    continue;
+                };
+                let digest = format!("sha256:{:x}", Sha256::digest(&data));
+                let marker = self.manifest_tags_path(&repo, &digest).join(&tag);
+                if !marker.exists() {
+                    fs::create_dir_all(self.manifest_tags_path(&repo, &digest)).await?;
+                    fs::write(marker, b"").await?;
+                    written += 1;
+                }
+            }
+        }
+
+        info!("Backfilled {} tag markers", written);
+        Ok(written)
+    }
+
+    async fn blob_references(&self) -> Result<Option<BlobReferences>> {
+        let refs = self.refs.lock().await;
+
+        let blobs = refs
+            .blobs
+            .iter()
+            .map(|(blob, manifests)| {
+                let repositories = manifests
+                    .iter()
+                    .filter_map(|manifest| manifest.rsplit_once('@'))
+                    .map(|(repo, _)| repo.to_string())
+                    .collect();
+                (blob.clone(), repositories)
+            })
+            .collect();
+        let manifests = refs
+            .manifests
+            .keys()
+            .filter_map(|manifest| manifest.rsplit_once('@'))
+            .map(|(repo, digest)| (repo.to_string(), digest.to_string()))
+            .collect();
+
+        Ok(Some(BlobReferences {
+            blobs,
+            manifests,
+            unreadable_manifests: refs.unreadable.len(),
+        }))
+    }
+
+    async fn fsck(&self) -> Result<FsckReport> {
+        // No manifest may appear or go between the walk and the records being swapped in
+        let _writing = self.manifest_writes.write().await;
+        let manifests_root = self.base_path.join("manifests");
+        let mut table = BlobRefTable::default();
+        let mut report = FsckReport::default();
+
+        // Repository names may contain slashes, so walk the whole tree: a
+        // manifest's repository is the directory holding its `MANIFESTS_SEGMENT`
+        let mut pending = vec![manifests_root.clone()];
+        while let Some(dir) = pending.pop() {
+            let mut entries = match fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                if !entry.file_type().await?.is_dir() {
+                    continue;
+                }
+                if entry.file_name() != MANIFESTS_SEGMENT {
+                    pending.push(entry.path());
+                    continue;
+                }
+                let repo = dir.strip_prefix(&manifests_root).ok().and_then(|repo| repo.to_str());
+                let Some(repo) = repo.filter(|repo| !repo.is_empty()) else {
+                    continue;
+                };
+
+                for path in Self::list_manifest_files(&entry.path()).await? {
+                    let data = fs::read(path).await?;
+                    let digest = format!("sha256:{:x}", Sha256::digest(&data));
+                    if table.add(format!("{}@{}", repo, digest), manifest_blob_digests(&data)) {
+                        report.manifests_scanned += 1;
+                    }
+                }
+            }
+        }
+
+        for blob in table.blobs.keys() {
+            if !self.blob_path(blob).exists() {
+                warn!("Blob {} is referenced but missing from storage", blob);
+                report.missing_blobs.push(blob.clone());
+            }
+        }
+        report.missing_blobs.sort();
+        report.blobs_referenced = table.blobs.len();
+
+        let mut refs = self.refs.lock().await;
+        self.save_refs(&table).await?;
+        *refs = table;
+
+        info!(
+            "Rebuilt blob reference table: {} manifests, {} blobs, {} missing",
+            report.manifests_scanned,
+            report.blobs_referenced,
+            report.missing_blobs.len()
+        );
+        Ok(report)
+    }
+}
@@ -0,0 +1,756 @@
+use super::{is_digest_reference, BlobMetadata, ManifestMetadata, StorageBackend, StorageError, UploadHashers, UploadSession};
+use crate::config::GcsConfig;
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::stream::{BoxStream, StreamExt};
+use google_cloud_storage::client::google_cloud_auth::credentials::CredentialsFile;
+use google_cloud_storage::client::{Client, ClientConfig};
+use google_cloud_storage::http::buckets::get::GetBucketRequest;
+use google_cloud_storage::http::objects::delete::DeleteObjectRequest;
+use google_cloud_storage::http::objects::download::Range;
+use google_cloud_storage::http::objects::get::GetObjectRequest;
+use google_cloud_storage::http::objects::list::ListObjectsRequest;
+use google_cloud_storage::http::objects::rewrite::RewriteObjectRequest;
+use google_cloud_storage::http::objects::upload::{Media, UploadObjectRequest, UploadType};
+use google_cloud_storage::http::objects::Object;
+use google_cloud_storage::http::resumable_upload_client::{ChunkSize, UploadStatus};
+use google_cloud_storage::http::Error as GcsError;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use tracing::{debug, error, info};
+
+pub struct GcsStorage {
+    client: Client,
+    bucket: String,
+    upload_hashers: UploadHashers,
+}
+
+/// Resumable upload chunks must be a multiple of 256 KiB, other than the last
+const CHUNK_ALIGNMENT: usize = 256 * 1024;
+/// Buffered data is sent to the resumable session once it reaches this size
+const CHUNK_SIZE: usize = 32 * CHUNK_ALIGNMENT;
+
+/// Per-session record of the GCS resumable upload backing a blob upload
+#[derive(Debug, Serialize, Deserialize)]
+struct UploadIndex {
+    session_url: String,
+    /// Bytes held in the pending object, not yet sent to the resumable session
+    pending_size: u64,
+    /// Total bytes received so far
+    size: u64,
+}
+
+/// Objects and common prefixes under a listing prefix
+struct Listing {
+    objects: Vec<Object>,
+    prefixes: Vec<String>,
+}
+
+impl GcsStorage {
+    pub async fn new(config: &GcsConfig) -> Result<Self> {
+        let mut client_config = if let Some(path) = &config.credentials_file {
+            let credentials = CredentialsFile::new_from_file(path.clone()).await?;
+            ClientConfig::default().with_credentials(credentials).await?
+        } else if config.anonymous {
+            ClientConfig::default().anonymous()
+        } else {
+            // Application default credentials, including workload identity on GKE
+            ClientConfig::default().with_auth().await?
+        };
+
+        if let Some(endpoint) = &config.endpoint {
+            // Custom endpoint (like fake-gcs-server)
+            client_config.storage_endpoint = endpoint.trim_end_matches('/').to_string();
+        }
+
+        let client = Client::new(client_config);
+
+        // Test connection
+        let request = GetBucketRequest {
+            bucket: config.bucket.clone(),
+            ..Default::default()
+        };
+        match client.get_bucket(&request).await {
+            Ok(_) => info!("✅ Connected to GCS bucket: {}", config.bucket),
+            Err(e) => {
+                error!("❌ Failed to connect to GCS bucket {}: {}", config.bucket, e);
+                return Err(anyhow::anyhow!("GCS connection failed: {}", e));
+            }
+        }
+
+        Ok(Self {
+            client,
+            bucket: config.bucket.clone(),
+            upload_hashers: UploadHashers::default(),
+        })
+    }
+
+    fn blob_key(&self, digest: &str) -> String {
+        format!("blobs/{}/{}", &digest[0..2], digest)
+    }
+
+    fn manifest_key(&self, repo: &str, reference: &str) -> String {
+        format!("manifests/{}/{}", repo, reference)
+    }
+
+    /// Marker object recording that a tag points at a digest
+    fn manifest_tag_marker_key(&self, repo: &str, digest: &str, tag: &str) -> String {
+        format!("manifest_tags/{}/{}/{}", repo, digest, tag)
+    }
+
+    /// Object holding the upload session's resumable upload index
+    fn upload_index_key(&self, uuid: &str) -> String {
+        format!("uploads/{}/metadata", uuid)
+    }
+
+    /// Object the resumable upload writes to before verification
+    fn upload_staging_key(&self, uuid: &str) -> String {
+        format!("uploads/{}/data", uuid)
+    }
+
+    /// Object buffering data not yet large enough to send as a chunk
+    fn upload_pending_key(&self, uuid: &str) -> String {
+        format!("uploads/{}/pending", uuid)
+    }
+
+    /// Object metadata, or None if the object doesn't exist
+    async fn head_object(&self, key: &str) -> Result<Option<Object>> {
+        let request = GetObjectRequest {
+            bucket: self.bucket.clone(),
+            object: key.to_string(),
+            ..Default::default()
+        };
+
+        match self.client.get_object(&request).await {
+            Ok(object) => Ok(Some(object)),
+            Err(e) if is_not_found(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn get_object_bytes(&self, key: &str) -> Result<Option<Bytes>> {
+        let request = GetObjectRequest {
+            bucket: self.bucket.clone(),
+            object: key.to_string(),
+            ..Default::default()
+        };
+
+        match self.client.download_object(&request, &Range::default()).await {
+            Ok(data) => Ok(Some(data.into())),
+            Err(e) if is_not_found(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn put_object(&self, key: &str, data: Bytes, content_type: &str) -> Result<()> {
+        let mut media = Media::new(key.to_string());
+        media.content_type = content_type.to_string().into();
+        media.content_length = Some(data.len() as u64);
+
+        let request = UploadObjectRequest {
+            bucket: self.bucket.clone(),
+            ..Default::default()
+        };
+        self.client.upload_object(&request, data, &UploadType::Simple(media)).await?;
+        Ok(())
+    }
+
+    /// Delete an object, treating a missing object as already deleted
+    async fn delete_object(&self, key: &str) -> Result<()> {
+        let request = DeleteObjectRequest {
+            bucket: self.bucket.clone(),
+            object: key.to_string(),
+            ..Default::default()
+        };
+
+        match self.client.delete_object(&request).await {
+            Ok(()) => Ok(()),
+            Err(e) if is_not_found(&e) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// List every object under a prefix, following page tokens. With a
+    /// delimiter, deeper keys are rolled up into common prefixes instead.
+    async fn list_objects(&self, prefix: &str, delimiter: Option<&str>) -> Result<Listing> {
+        let mut listing = Listing {
+            objects: Vec::new(),
+            prefixes: Vec::new(),
+        };
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let request = ListObjectsRequest {
+                bucket: self.bucket.clone(),
+                prefix: Some(prefix.to_string()),
+                delimiter: delimiter.map(str::to_string),
+                page_token,
+                ..Default::default()
+            };
+
+            let response = self.client.list_objects(&request).await?;
+            listing.objects.extend(response.items.unwrap_or_default());
+            listing.prefixes.extend(response.prefixes.unwrap_or_default());
+
+            match response.next_page_token {
+                Some(token) => page_token = Some(token),
+                None => break,
+            }
+        }
+
+        Ok(listing)
+    }
+
+    /// Drop the digest -> tag marker for whatever a tag currently points at
+    async fn remove_tag_marker(&self, repo: &str, tag: &str) -> Result<()> {
+        if let Some(data) = self.get_manifest(repo, tag).await? {
+            let digest = format!("sha256:{:x}", Sha256::digest(&data));
+            self.delete_object(&self.manifest_tag_marker_key(repo, &digest, tag)).await?;
+        }
+        Ok(())
+    }
+
+    /// Find a manifest by content digest, checking the digest reference before
+    /// hashing each manifest stored in the repository
+    async fn find_manifest(&self, repo: &str, digest: &str) -> Result<Option<(Object, Bytes)>> {
+        let key = self.manifest_key(repo, digest);
+        if let (Some(object), Some(data)) = (self.head_object(&key).await?, self.get_object_bytes(&key).await?) {
+            return Ok(Some((object, data)));
+        }
+
+        let prefix = format!("manifests/{}/", repo);
+        for object in self.list_objects(&prefix, Some("/")).await?.objects {
+            let Some(data) = self.get_object_bytes(&object.name).await? else {
+                continue;
+            };
+            if format!("sha256:{:x}", Sha256::digest(&data)) == digest {
+                return Ok(Some((object, data)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Delete all objects stored under an upload session
+    async fn delete_upload_objects(&self, uuid: &str) -> Result<()> {
+        let prefix = format!("uploads/{}/", uuid);
+        for object in self.list_objects(&prefix, None).await?.objects {
+            self.delete_object(&object.name).await?;
+        }
+        Ok(())
+    }
+
+    async fn load_upload_index(&self, uuid: &str) -> Result<Option<UploadIndex>> {
+        match self.get_object_bytes(&self.upload_index_key(uuid)).await? {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn save_upload_index(&self, uuid: &str, index: &UploadIndex) -> Result<()> {
+        self.put_object(&self.upload_index_key(uuid), serde_json::to_vec(index)?.into(), "application/json")
+            .await
+    }
+
+    /// Send data at `offset` to a resumable upload session. Passing the total
+    /// size finalizes the object; an empty final chunk only sets the size.
+    async fn send_chunk(&self, index: &UploadIndex, data: Vec<u8>, offset: u64, total: Option<u64>) -> Result<UploadStatus> {
+        let range = match total {
+            Some(total) if data.is_empty() => ChunkSize::new(total, total, Some(total)),
+            _ => ChunkSize::new(offset, offset + data.len() as u64 - 1, total),
+        };
+
+        let uploader = self.client.get_resumable_upload(index.session_url.clone());
+        Ok(uploader.upload_multiple_chunk(data, &range).await?)
+    }
+
+    /// Compute the sha256 digest of an object by streaming its body
+    async fn hash_object(&self, key: &str) -> Result<String> {
+        let request = GetObjectRequest {
+            bucket: self.bucket.clone(),
+            object: key.to_string(),
+            ..Default::default()
+        };
+
+        let mut body = Box::pin(self.client.download_streamed_object(&request, &Range::default()).await?);
+        let mut hasher = Sha256::new();
+        while let Some(chunk) = body.next().await {
+            hasher.update(&chunk?);
+        }
+
+        Ok(format!("sha256:{:x}", hasher.finalize()))
+    }
+
+    /// Server-side copy; rewrites of large objects take several calls
+    async fn copy_object(&self, source_key: &str, dest_key: &str) -> Result<()> {
+        let mut rewrite_token: Option<String> = None;
+
+        loop {
+            let request = RewriteObjectRequest {
+                source_bucket: self.bucket.clone(),
+                source_object: source_key.to_string(),
+                destination_bucket: self.bucket.clone(),
+                destination_object: dest_key.to_string(),
+                rewrite_token,
+                ..Default::default()
+            };
+
+            let response = self.client.rewrite_object(&request).await?;
+            if response.done {
+                return Ok(());
+            }
+            rewrite_token = response.rewrite_token;
+        }
+    }
+}
+
+fn is_not_found(e: &GcsError) -> bool {
+    match e {
+        GcsError::Response(response) => response.code == 404,
+        GcsError::HttpClient(e) => e.status().is_some_and(|status| status.as_u16() == 404),
+        _ => false,
+    }
+}
+
+fn last_modified(object: &Object) -> DateTime<Utc> {
+    object
+        .updated
+        .or(object.time_created)
+        .and_then(|time| DateTime::from_timestamp(time.unix_timestamp(), time.nanosecond()))
+        .unwrap_or_else(Utc::now)
+}
+
+#[async_trait]
+impl StorageBackend for GcsStorage {
+    async fn put_blob(&self, digest: &str, data: Bytes) -> Result<()> {
+        let size = data.len();
+        self.put_object(&self.blob_key(digest), data, "application/octet-stream").await?;
+
+        debug!("Stored blob {} in GCS ({} bytes)", digest, size);
+        Ok(())
+    }
+
+    async fn get_blob(&self, digest: &str) -> Result<Option<Bytes>> {
+        match self.get_object_bytes(&self.blob_key(digest)).await {
+            Ok(data) => {
+                if let Some(data) = &data {
+                    debug!("Retrieved blob {} from GCS ({} bytes)", digest, data.len());
+                }
+                Ok(data)
+            }
+            Err(e) => {
+                error!("Failed to get blob {} from GCS: {}", digest, e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn get_blob_stream(&self, digest: &str) -> Result<Option<(u64, BoxStream<'static, Result<Bytes>>)>> {
+        let key = self.blob_key(digest);
+        let Some(object) = self.head_object(&key).await? else {
+            return Ok(None);
+        };
+
+        let request = GetObjectRequest {
+            bucket: self.bucket.clone(),
+            object: key,
+            generation: Some(object.generation),
+            ..Default::default()
+        };
+        let size = object.size as u64;
+        debug!("Streaming blob {} from GCS ({} bytes)", digest, size);
+
+        match self.client.download_streamed_object(&request, &Range::default()).await {
+            Ok(body) => Ok(Some((size, body.map(|chunk| chunk.map_err(anyhow::Error::from)).boxed()))),
+            Err(e) if is_not_found(&e) => Ok(None),
+            Err(e) => {
+                error!("Failed to get blob {} from GCS: {}", digest, e);
+                Err(e.into())
+            }
+        }
+    }
+
+    async fn delete_blob(&self, digest: &str) -> Result<()> {
+        self.delete_object(&self.blob_key(digest)).await?;
+
+        debug!("Deleted blob {} from GCS", digest);
+        Ok(())
+    }
+
+    async fn blob_exists(&self, digest: &str) -> Result<bool> {
+        Ok(self.head_object(&self.blob_key(digest)).await?.is_some())
+    }
+
+    async fn put_manifest(&self, repo: &str, reference: &str, data: Bytes, media_type: &str) -> Result<()> {
+        // Retagging moves the tag off the digest it used to point at
+        if !is_digest_reference(reference) {
+            self.remove_tag_marker(repo, reference).await?;
+        }
+
+        let size = data.len();
+        self.put_object(&self.manifest_key(repo, reference), data.clone(), media_type).await?;
+
+        if !is_digest_reference(reference) {
+            let digest = format!("sha256:{:x}", Sha256::digest(&data));
+            self.put_object(&self.manifest_tag_marker_key(repo, &digest, reference), Bytes::new(), "application/octet-stream")
+                .await?;
+        }
+
+        debug!("Stored manifest {}/{} in GCS ({} bytes)", repo, reference, size);
+        Ok(())
+    }
+
+    async fn get_manifest(&self, repo: &str, reference: &str) -> Result<Option<Bytes>> {
+        match self.get_object_bytes(&self.manifest_key(repo, reference)).await {
+            Ok(data) => {
+                if let Some(data) = &data {
+                    debug!("Retrieved manifest {}/{} from GCS ({} bytes)", repo, reference, data.len());
+                }
+                Ok(data)
+            }
+            Err(e) => {
+                error!("Failed to get manifest {}/{} from GCS: {}", repo, reference, e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn delete_manifest(&self, repo: &str, reference: &str) -> Result<()> {
+        if !is_digest_reference(reference) {
+            self.remove_tag_marker(repo, reference).await?;
+        }
+
+        self.delete_object(&self.manifest_key(repo, reference)).await?;
+
+        debug!("Deleted manifest {}/{} from GCS", repo, reference);
+        Ok(())
+    }
+
+    async fn list_tags_for_digest(&self, repo: &str, digest: &str) -> Result<Vec<String>> {
+        let prefix = format!("manifest_tags/{}/{}/", repo, digest);
+
+        let mut tags: Vec<String> = self
+            .list_objects(&prefix, None)
+            .await?
+            .objects
+            .iter()
+            .filter_map(|object| object.name.strip_prefix(&prefix))
+            .map(str::to_string)
+            .collect();
+
+        tags.sort();
+        Ok(tags)
+    }
+
+    async fn list_repositories(&self) -> Result<Vec<String>> {
+        // Repository names may be nested, so a manifest's repository is
+        // everything between "manifests/" and its last path segment
+        let mut repos: Vec<String> = self
+            .list_objects("manifests/", None)
+            .await?
+            .objects
+            .iter()
+            .filter_map(|object| object.name.strip_prefix("manifests/").and_then(|s| s.rsplit_once('/')))
+            .map(|(repo, _)| repo.to_string())
+            .collect();
+
+        repos.sort();
+        repos.dedup();
+        Ok(repos)
+    }
+
+    async fn list_tags(&self, repo: &str) -> Result<Vec<String>> {
+        let prefix = format!("manifests/{}/", repo);
+
+        let mut tags: Vec<String> = self
+            .list_objects(&prefix, Some("/"))
+            .await?
+            .objects
+            .iter()
+            .filter_map(|object| object.name.strip_prefix(&prefix))
+            .map(str::to_string)
+            .collect();
+
+        tags.sort();
+        Ok(tags)
+    }
+
+    async fn get_upload_url(&self, uuid: &str) -> Result<Option<String>> {
+        match self.head_object(&self.upload_index_key(uuid)).await? {
+            Some(_) => Ok(Some(format!("/v2/uploads/{}", uuid))),
+            None => Ok(None),
+        }
+    }
+
+    async fn create_upload(&self, uuid: &str) -> Result<()> {
+        let request = UploadObjectRequest {
+            bucket: self.bucket.clone(),
+            ..Default::default()
+        };
+        let media = Media::new(self.upload_staging_key(uuid));
+        let uploader = self.client.prepare_resumable_upload(&request, &UploadType::Simple(media)).await?;
+
+        let index = UploadIndex {
+            session_url: uploader.url().to_string(),
+            pending_size: 0,
+            size: 0,
+        };
+        self.save_upload_index(uuid, &index).await?;
+
+        debug!("Started resumable upload for {}", uuid);
+        Ok(())
+    }
+
+    async fn get_upload_session(&self, uuid: &str) -> Result<Option<UploadSession>> {
+        let Some(object) = self.head_object(&self.upload_index_key(uuid)).await? else {
+            return Ok(None);
+        };
+        let Some(index) = self.load_upload_index(uuid).await? else {
+            return Ok(None);
+        };
+
+        // The index is rewritten on every append, so it tracks last activity
+        Ok(Some(UploadSession {
+            uuid: uuid.to_string(),
+            size: index.size,
+            updated_at: last_modified(&object),
+        }))
+    }
+
+    async fn list_uploads(&self) -> Result<Vec<UploadSession>> {
+        let mut sessions: HashMap<String, UploadSession> = HashMap::new();
+
+        for object in self.list_objects("uploads/", None).await?.objects {
+            let Some(uuid) = object.name.strip_prefix("uploads/").and_then(|rest| rest.split('/').next()) else {
+                continue;
+            };
+
+            let modified = last_modified(&object);
+
+            // Sessions have several objects; keep the latest activity
+            let session = sessions.entry(uuid.to_string()).or_insert_with(|| UploadSession {
+                uuid: uuid.to_string(),
+                size: 0,
+                updated_at: modified,
+            });
+            session.updated_at = session.updated_at.max(modified);
+        }
+
+        // Data sent to a resumable session isn't an object until finalized, so use the index size
+        for session in sessions.values_mut() {
+            if let Ok(Some(index)) = self.load_upload_index(&session.uuid).await {
+                session.size = index.size;
+            }
+        }
+
+        Ok(sessions.into_values().collect())
+    }
+
+    async fn append_upload(&self, uuid: &str, mut data: BoxStream<'static, Result<Bytes>>) -> Result<u64> {
+        let mut index = self
+            .load_upload_index(uuid)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Upload not found: {}", uuid))?;
+
+        // Chunks other than the last must be 256 KiB aligned, so data is
+        // buffered and any unaligned remainder is held in a pending object
+        let mut buffer = Vec::with_capacity(CHUNK_SIZE);
+        if index.pending_size > 0 {
+            let pending = self
+                .get_object_bytes(&self.upload_pending_key(uuid))
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Pending data missing for upload {}", uuid))?;
+            buffer.extend_from_slice(&pending);
+        }
+
+        // Offset in the object of the first buffered byte
+        let mut sent = index.size - index.pending_size;
+        let mut received = 0u64;
+        while let Some(chunk) = data.next().await {
+            let chunk = chunk?;
+            self.upload_hashers.update(uuid, index.size + received, &chunk);
+            received += chunk.len() as u64;
+            buffer.extend_from_slice(&chunk);
+
+            if buffer.len() >= CHUNK_SIZE {
+                let aligned = buffer.len() - buffer.len() % CHUNK_ALIGNMENT;
+                let remainder = buffer.split_off(aligned);
+                let chunk = std::mem::replace(&mut buffer, remainder);
+                self.send_chunk(&index, chunk, sent, None).await?;
+                sent += aligned as u64;
+            }
+        }
+
+        index.size += received;
+        index.pending_size = buffer.len() as u64;
+        if !buffer.is_empty() {
+            self.put_object(&self.upload_pending_key(uuid), buffer.into(), "application/octet-stream").await?;
+        }
+
+        self.save_upload_index(uuid, &index).await?;
+
+        debug!("Appended {} bytes to upload {} in GCS (offset {})", received, uuid, index.size);
+        Ok(index.size)
+    }
+
+    async fn complete_upload(&self, uuid: &str, digest: &str) -> Result<()> {
+        let index = self
+            .load_upload_index(uuid)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Upload not found: {}", uuid))?;
+
+        // Send the remaining data with the total size to finalize the object
+        let pending = if index.pending_size > 0 {
+            self.get_object_bytes(&self.upload_pending_key(uuid))
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Pending data missing for upload {}", uuid))?
+                .to_vec()
+        } else {
+            Vec::new()
+        };
+        let offset = index.size - pending.len() as u64;
+        match self.send_chunk(&index, pending, offset, Some(index.size)).await? {
+            UploadStatus::Ok(_) => {}
+            status => return Err(anyhow::anyhow!("GCS did not finalize upload {}: {:?}", uuid, status)),
+        }
+
+        // Verify the assembled content before it becomes addressable. Without a
+        // running hash, stream the object back so the blob is never held in memory
+        let staging_key = self.upload_staging_key(uuid);
+        let actual = match self.upload_hashers.finish(uuid, index.size) {
+            Some(actual) => actual,
+            None => self.hash_object(&staging_key).await?,
+        };
+        if actual != digest {
+            self.cancel_upload(uuid).await?;
+            return Err(StorageError::DigestMismatch {
+                expected: digest.to_string(),
+                actual,
+            }
+            .into());
+        }
+
+        self.copy_object(&staging_key, &self.blob_key(digest)).await?;
+
+        // Clean up the session objects
+        self.delete_upload_objects(uuid).await?;
+
+        debug!("Completed upload {} -> blob {} ({} bytes)", uuid, digest, index.size);
+        Ok(())
+    }
+
+    async fn cancel_upload(&self, uuid: &str) -> Result<()> {
+        self.upload_hashers.remove(uuid);
+
+        if let Some(index) = self.load_upload_index(uuid).await? {
+            // Cancelling fails if the resumable upload was already finalized
+            if let Err(e) = self.client.get_resumable_upload(index.session_url).cancel().await {
+                debug!("Cancel of resumable upload for {} failed: {}", uuid, e);
+            }
+        }
+
+        self.delete_upload_objects(uuid).await?;
+
+        debug!("Cancelled upload {}", uuid);
+        Ok(())
+    }
+
+    // Garbage collection methods
+    async fn list_all_blobs(&self) -> Result<Vec<String>> {
+        // Extract digest from key (remove "blobs/<prefix>/")
+        Ok(self
+            .list_objects("blobs/", None)
+            .await?
+            .objects
+            .iter()
+            .filter_map(|object| object.name.strip_prefix("blobs/").and_then(|rest| rest.split_once('/')))
+            .map(|(_, digest)| digest.to_string())
+            .collect())
+    }
+
+    async fn list_manifests(&self, repo: &str) -> Result<Vec<String>> {
+        let prefix = format!("manifests/{}/", repo);
+        let mut manifests = Vec::new();
+
+        for object in self.list_objects(&prefix, Some("/")).await?.objects {
+            // For manifest digests, we need to get the object and compute its hash
+            if let Some(data) = self.get_object_bytes(&object.name).await? {
+                manifests.push(format!("sha256:{:x}", Sha256::digest(&data)));
+            }
+        }
+
+        Ok(manifests)
+    }
+
+    async fn get_blob_metadata(&self, digest: &str) -> Result<BlobMetadata> {
+        let object = self
+            .head_object(&self.blob_key(digest))
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Blob not found: {}", digest))?;
+
+        Ok(BlobMetadata {
+            size: object.size as u64,
+            created_at: last_modified(&object),
+        })
+    }
+
+    async fn get_manifest_metadata(&self, repo: &str, digest: &str) -> Result<ManifestMetadata> {
+        let (object, _) = self
+            .find_manifest(repo, digest)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Manifest not found: {}", digest))?;
+
+        Ok(ManifestMetadata {
+            size: object.size as u64,
+            created_at: last_modified(&object),
+            media_type: object.content_type,
+        })
+    }
+
+    async fn get_manifest_by_digest(&self, repo: &str, digest: &str) -> Result<Bytes> {
+        let (_, data) = self
+            .find_manifest(repo, digest)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Manifest not found: {}", digest))?;
+
+        Ok(data)
+    }
+
+    async fn get_manifest_digest(&self, repo: &str, reference: &str) -> Result<String> {
+        let manifest_data = self
+            .get_manifest(repo, reference)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Manifest not found: {}/{}", repo, reference))?;
+
+        let digest = format!("sha256:{:x}", Sha256::digest(&manifest_data));
+        Ok(digest)
+    }
+
+    async fn rebuild_manifest_index(&self) -> Result<usize> {
+        let mut written = 0;
+
+        // Tags pushed before digest -> tag markers existed have none
+        for repo in self.list_repositories().await? {
+            for tag in self.list_tags(&repo).await? {
+                if is_digest_reference(&tag) {
+                    continue;
+                }
+                let Some(data) = self.get_manifest(&repo, &tag).await? else {
+                    continue;
+                };
+                let digest = format!("sha256:{:x}", Sha256::digest(&data));
+                let marker = self.manifest_tag_marker_key(&repo, &digest, &tag);
+                if self.head_object(&marker).await?.is_none() {
+                    self.put_object(&marker, Bytes::new(), "application/octet-stream").await?;
+                    written += 1;
+                }
+            }
+        }
+
+        info!("Backfilled {} GCS tag markers", written);
+        Ok(written)
+    }
+}
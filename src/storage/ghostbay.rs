@@ -1,10 +1,10 @@
-use super::{StorageBackend, BlobMetadata, ManifestMetadata};
+use super::{StorageBackend, BlobMetadata, ManifestMetadata, UploadSession};
 use crate::config::GhostBayStorageConfig;
 use anyhow::Result;
 use async_trait::async_trait;
 use bytes::Bytes;
 use futures::StreamExt;
-use tracing::{debug, error, info};
+use tracing::{debug, info};
 
 // Placeholder types based on GHOSTBAY_S3_STARTHERE.md
 // These would be replaced with actual ghostbay crate imports when available
@@ -119,14 +119,14 @@ impl StorageBackend for GhostBayStorage {
         //
         // self.storage.put_object(request).await?;
 
-        debug!("🌊 Stored blob {} in GhostBay ({} bytes)", digest, data.len());
+        debug!("🌊 Stored blob {} in GhostBay at {}/{} ({} bytes)", digest, self.config.bucket, key, data.len());
 
         // Mock implementation for now
         Ok(())
     }
 
     async fn get_blob(&self, digest: &str) -> Result<Option<Bytes>> {
-        let key = self.blob_key(digest);
+        let _key = self.blob_key(digest);
 
         // TODO: Use actual GhostBay storage engine
         // let request = GetObjectRequest {
@@ -151,7 +151,7 @@ impl StorageBackend for GhostBayStorage {
     }
 
     async fn delete_blob(&self, digest: &str) -> Result<()> {
-        let key = self.blob_key(digest);
+        let _key = self.blob_key(digest);
 
         // TODO: Use actual GhostBay storage engine
         // self.storage.delete_object("drift-registry", &key).await?;
@@ -163,29 +163,33 @@ impl StorageBackend for GhostBayStorage {
     async fn blob_exists(&self, digest: &str) -> Result<bool> {
         // TODO: Implement GhostBay blob existence check
         // For now, return false as mock
+        debug!("🌊 Checking blob {} in GhostBay", digest);
         Ok(false)
     }
 
-    async fn put_manifest(&self, repo: &str, reference: &str, data: Bytes) -> Result<()> {
+    async fn put_manifest(&self, repo: &str, reference: &str, data: Bytes, media_type: &str) -> Result<()> {
         let key = self.manifest_key(repo, reference);
 
         // TODO: Use actual GhostBay storage engine
         // let request = PutObjectRequest {
         //     bucket: "drift-registry".to_string(),
         //     key,
-        //     content_type: "application/vnd.docker.distribution.manifest.v2+json".to_string(),
+        //     content_type: media_type.to_string(),
         //     content_length: Some(data.len() as u64),
         //     data: Box::pin(futures::stream::once(async { Ok(data) })),
         // };
         //
         // self.storage.put_object(request).await?;
 
-        debug!("🌊 Stored manifest {}/{} in GhostBay ({} bytes)", repo, reference, data.len());
+        debug!(
+            "🌊 Stored manifest {}/{} in GhostBay at {}/{} ({} bytes, {})",
+            repo, reference, self.config.bucket, key, data.len(), media_type
+        );
         Ok(())
     }
 
     async fn get_manifest(&self, repo: &str, reference: &str) -> Result<Option<Bytes>> {
-        let key = self.manifest_key(repo, reference);
+        let _key = self.manifest_key(repo, reference);
 
         // TODO: Use actual GhostBay storage engine
         debug!("🌊 Retrieved manifest {}/{} from GhostBay", repo, reference);
@@ -195,13 +199,19 @@ impl StorageBackend for GhostBayStorage {
     }
 
     async fn delete_manifest(&self, repo: &str, reference: &str) -> Result<()> {
-        let key = self.manifest_key(repo, reference);
+        let _key = self.manifest_key(repo, reference);
 
         // TODO: Use actual GhostBay storage engine
         debug!("🌊 Deleted manifest {}/{} from GhostBay", repo, reference);
         Ok(())
     }
 
+    async fn list_tags_for_digest(&self, repo: &str, digest: &str) -> Result<Vec<String>> {
+        // The mock backend stores no tags, so no tag ever points at a digest
+        debug!("🌊 Listing tags for {}@{} in GhostBay", repo, digest);
+        Ok(vec![])
+    }
+
     async fn list_repositories(&self) -> Result<Vec<String>> {
         // TODO: Use actual GhostBay storage engine to list repositories
         // let objects = self.storage.list_objects("drift-registry", "manifests/").await?;
@@ -211,10 +221,10 @@ impl StorageBackend for GhostBayStorage {
     }
 
     async fn list_tags(&self, repo: &str) -> Result<Vec<String>> {
-        let prefix = format!("manifests/{}/", repo);
+        let _prefix = format!("manifests/{}/", repo);
 
         // TODO: Use actual GhostBay storage engine to list tags
-        // let objects = self.storage.list_objects("drift-registry", &prefix).await?;
+        // let objects = self.storage.list_objects("drift-registry", &_prefix).await?;
 
         // Mock implementation for now
         Ok(vec![])
@@ -225,25 +235,46 @@ impl StorageBackend for GhostBayStorage {
         Ok(Some(format!("/v2/uploads/{}", uuid)))
     }
 
-    async fn put_upload_chunk(&self, uuid: &str, range: (u64, u64), data: Bytes) -> Result<()> {
-        // TODO: Implement GhostBay chunked upload
-        // For large uploads, we would use GhostBay's multipart upload feature
-        const MULTIPART_THRESHOLD: usize = 100 * 1024 * 1024; // 100MB
+    async fn create_upload(&self, uuid: &str) -> Result<()> {
+        let key = self.upload_key(uuid);
 
-        if data.len() > MULTIPART_THRESHOLD {
-            // Use multipart upload for large chunks
-            // let upload_id = self.storage.create_multipart_upload(CreateMultipartUploadRequest {
-            //     bucket: "drift-registry".to_string(),
-            //     key: self.upload_key(uuid),
-            //     content_type: "application/octet-stream".to_string(),
-            //     metadata: None,
-            // }).await?;
+        // Sessions live only in the engine's multipart uploads, which this mock
+        // backend doesn't create:
+        // let upload_id = self.storage.create_multipart_upload(CreateMultipartUploadRequest {
+        //     bucket: "drift-registry".to_string(),
+        //     key,
+        //     content_type: "application/octet-stream".to_string(),
+        //     metadata: None,
+        // }).await?;
 
-            // ... implement multipart upload logic
+        debug!("🌊 Created upload session {} in GhostBay at {}/{}", uuid, self.config.bucket, key);
+        Ok(())
+    }
+
+    async fn get_upload_session(&self, uuid: &str) -> Result<Option<UploadSession>> {
+        // Every session looks fresh and empty, since the mock backend keeps no session state
+        debug!("🌊 Getting upload session {} in GhostBay", uuid);
+        Ok(Some(UploadSession {
+            uuid: uuid.to_string(),
+            size: 0,
+            updated_at: chrono::Utc::now(),
+        }))
+    }
+
+    async fn list_uploads(&self) -> Result<Vec<UploadSession>> {
+        // No session state is kept, so there is never anything for GC to expire
+        Ok(vec![])
+    }
+
+    async fn append_upload(&self, uuid: &str, mut data: futures::stream::BoxStream<'static, Result<Bytes>>) -> Result<u64> {
+        // Counted and discarded; the mock backend has nowhere to put parts
+        let mut received = 0u64;
+        while let Some(chunk) = data.next().await {
+            received += chunk?.len() as u64;
         }
 
-        debug!("🌊 Stored upload chunk {} range {:?} in GhostBay", uuid, range);
-        Ok(())
+        debug!("🌊 Stored {} bytes for upload {} in GhostBay", received, uuid);
+        Ok(received)
     }
 
     async fn complete_upload(&self, uuid: &str, digest: &str) -> Result<()> {
@@ -293,6 +324,11 @@ impl StorageBackend for GhostBayStorage {
         // TODO: Get blob metadata from GhostBay storage
         debug!("🌊 Getting blob metadata for {} in GhostBay", digest);
 
+        // Stay consistent with the existence check so HEAD doesn't report phantom blobs
+        if !self.blob_exists(digest).await? {
+            return Err(anyhow::anyhow!("Blob not found: {}", digest));
+        }
+
         Ok(BlobMetadata {
             size: 0,
             created_at: chrono::Utc::now(),
@@ -307,6 +343,7 @@ impl StorageBackend for GhostBayStorage {
         Ok(ManifestMetadata {
             created_at: chrono::Utc::now(),
             size: 0,
+            media_type: None,
         })
     }
 
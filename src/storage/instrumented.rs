@@ -0,0 +1,176 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{field, info_span, Instrument};
+
+use super::{BlobMetadata, BlobReferences, FsckReport, ManifestMetadata, StorageBackend, UploadSession};
+use crate::metrics::Metrics;
+
+/// Wraps a storage backend, recording how long each operation takes and
+/// tracing it as a child span of the request
+pub struct InstrumentedStorage {
+    inner: Arc<dyn StorageBackend>,
+    metrics: Arc<Metrics>,
+}
+
+impl InstrumentedStorage {
+    pub fn new(inner: Arc<dyn StorageBackend>, metrics: Arc<Metrics>) -> Self {
+        Self { inner, metrics }
+    }
+
+    async fn timed<T>(
+        &self,
+        operation: &str,
+        repository: Option<&str>,
+        reference: Option<&str>,
+        future: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let span =
+            info_span!("storage", operation, repository = field::Empty, digest = field::Empty, tag = field::Empty);
+        if let Some(repository) = repository {
+            span.record("repository", repository);
+        }
+        match reference {
+            Some(digest) if digest.contains(':') => {
+                span.record("digest", digest);
+            }
+            Some(tag) => {
+                span.record("tag", tag);
+            }
+            None => {}
+        }
+
+        let start = Instant::now();
+        let result = future.instrument(span).await;
+        self.metrics
+            .storage_operation_duration_seconds
+            .with_label_values(&[operation, if result.is_ok() { "ok" } else { "error" }])
+            .observe(start.elapsed().as_secs_f64());
+        result
+    }
+}
+
+#[async_trait]
+impl StorageBackend for InstrumentedStorage {
+    async fn put_blob(&self, digest: &str, data: Bytes) -> Result<()> {
+        self.timed("put_blob", None, Some(digest), self.inner.put_blob(digest, data)).await
+    }
+
+    async fn get_blob(&self, digest: &str) -> Result<Option<Bytes>> {
+        self.timed("get_blob", None, Some(digest), self.inner.get_blob(digest)).await
+    }
+
+    async fn get_blob_stream(&self, digest: &str) -> Result<Option<(u64, BoxStream<'static, Result<Bytes>>)>> {
+        self.timed("get_blob_stream", None, Some(digest), self.inner.get_blob_stream(digest)).await
+    }
+
+    async fn delete_blob(&self, digest: &str) -> Result<()> {
+        self.timed("delete_blob", None, Some(digest), self.inner.delete_blob(digest)).await
+    }
+
+    async fn blob_exists(&self, digest: &str) -> Result<bool> {
+        self.timed("blob_exists", None, Some(digest), self.inner.blob_exists(digest)).await
+    }
+
+    async fn put_manifest(&self, repo: &str, reference: &str, data: Bytes, media_type: &str) -> Result<()> {
+        self.timed(
+            "put_manifest",
+            Some(repo),
+            Some(reference),
+            self.inner.put_manifest(repo, reference, data, media_type),
+        )
+        .await
+    }
+
+    async fn get_manifest(&self, repo: &str, reference: &str) -> Result<Option<Bytes>> {
+        self.timed("get_manifest", Some(repo), Some(reference), self.inner.get_manifest(repo, reference)).await
+    }
+
+    async fn delete_manifest(&self, repo: &str, reference: &str) -> Result<()> {
+        self.timed("delete_manifest", Some(repo), Some(reference), self.inner.delete_manifest(repo, reference)).await
+    }
+
+    async fn list_tags_for_digest(&self, repo: &str, digest: &str) -> Result<Vec<String>> {
+        self.timed("list_tags_for_digest", Some(repo), Some(digest), self.inner.list_tags_for_digest(repo, digest))
+            .await
+    }
+
+    async fn list_repositories(&self) -> Result<Vec<String>> {
+        self.timed("list_repositories", None, None, self.inner.list_repositories()).await
+    }
+
+    async fn list_tags(&self, repo: &str) -> Result<Vec<String>> {
+        self.timed("list_tags", Some(repo), None, self.inner.list_tags(repo)).await
+    }
+
+    async fn get_upload_url(&self, uuid: &str) -> Result<Option<String>> {
+        self.timed("get_upload_url", None, None, self.inner.get_upload_url(uuid)).await
+    }
+
+    async fn create_upload(&self, uuid: &str) -> Result<()> {
+        self.timed("create_upload", None, None, self.inner.create_upload(uuid)).await
+    }
+
+    async fn get_upload_session(&self, uuid: &str) -> Result<Option<UploadSession>> {
+        self.timed("get_upload_session", None, None, self.inner.get_upload_session(uuid)).await
+    }
+
+    async fn list_uploads(&self) -> Result<Vec<UploadSession>> {
+        self.timed("list_uploads", None, None, self.inner.list_uploads()).await
+    }
+
+    async fn append_upload(&self, uuid: &str, data: BoxStream<'static, Result<Bytes>>) -> Result<u64> {
+        self.timed("append_upload", None, None, self.inner.append_upload(uuid, data)).await
+    }
+
+    async fn complete_upload(&self, uuid: &str, digest: &str) -> Result<()> {
+        self.timed("complete_upload", None, Some(digest), self.inner.complete_upload(uuid, digest)).await
+    }
+
+    async fn cancel_upload(&self, uuid: &str) -> Result<()> {
+        self.timed("cancel_upload", None, None, self.inner.cancel_upload(uuid)).await
+    }
+
+    async fn list_all_blobs(&self) -> Result<Vec<String>> {
+        self.timed("list_all_blobs", None, None, self.inner.list_all_blobs()).await
+    }
+
+    async fn list_manifests(&self, repo: &str) -> Result<Vec<String>> {
+        self.timed("list_manifests", Some(repo), None, self.inner.list_manifests(repo)).await
+    }
+
+    async fn get_blob_metadata(&self, digest: &str) -> Result<BlobMetadata> {
+        self.timed("get_blob_metadata", None, Some(digest), self.inner.get_blob_metadata(digest)).await
+    }
+
+    async fn get_manifest_metadata(&self, repo: &str, digest: &str) -> Result<ManifestMetadata> {
+        self.timed("get_manifest_metadata", Some(repo), Some(digest), self.inner.get_manifest_metadata(repo, digest))
+            .await
+    }
+
+    async fn get_manifest_by_digest(&self, repo: &str, digest: &str) -> Result<Bytes> {
+        self.timed("get_manifest_by_digest", Some(repo), Some(digest), self.inner.get_manifest_by_digest(repo, digest))
+            .await
+    }
+
+    async fn get_manifest_digest(&self, repo: &str, reference: &str) -> Result<String> {
+        self.timed("get_manifest_digest", Some(repo), Some(reference), self.inner.get_manifest_digest(repo, reference))
+            .await
+    }
+
+    async fn rebuild_manifest_index(&self) -> Result<usize> {
+        self.timed("rebuild_manifest_index", None, None, self.inner.rebuild_manifest_index()).await
+    }
+
+    async fn blob_references(&self) -> Result<Option<BlobReferences>> {
+        self.timed("blob_references", None, None, self.inner.blob_references()).await
+    }
+
+    async fn fsck(&self) -> Result<FsckReport> {
+        self.timed("fsck", None, None, self.inner.fsck()).await
+    }
+}
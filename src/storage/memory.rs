@@ -0,0 +1,355 @@
+use super::{is_digest_reference, BlobMetadata, ManifestMetadata, StorageBackend, StorageError, UploadHashers, UploadSession};
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use chrono::{DateTime, Utc};
+use futures::stream::{BoxStream, StreamExt};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+use tracing::debug;
+
+/// Keeps all registry content in process memory, for tests and ephemeral
+/// demo instances. Everything is lost when the process exits.
+pub struct MemoryStorage {
+    state: RwLock<MemoryState>,
+    /// Cap on the bytes held across blobs, manifests and uploads
+    max_size: Option<u64>,
+    upload_hashers: UploadHashers,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    blobs: HashMap<String, StoredBlob>,
+    /// Manifests by repository, then reference
+    manifests: BTreeMap<String, BTreeMap<String, StoredManifest>>,
+    uploads: HashMap<String, StoredUpload>,
+    /// Bytes currently held across blobs, manifests and uploads
+    size: u64,
+}
+
+struct StoredBlob {
+    data: Bytes,
+    created_at: DateTime<Utc>,
+}
+
+struct StoredManifest {
+    data: Bytes,
+    digest: String,
+    media_type: String,
+    created_at: DateTime<Utc>,
+}
+
+struct StoredUpload {
+    data: BytesMut,
+    updated_at: DateTime<Utc>,
+}
+
+impl MemoryStorage {
+    pub fn new(max_size: Option<u64>) -> Self {
+        debug!("Initialized in-memory storage (limit: {:?} bytes)", max_size);
+
+        Self {
+            state: RwLock::new(MemoryState::default()),
+            max_size,
+            upload_hashers: UploadHashers::default(),
+        }
+    }
+
+    /// Account for storing `added` bytes in place of `freed` ones, failing when
+    /// growth would take the total past the cap
+    fn resize(&self, size: &mut u64, added: u64, freed: u64) -> Result<()> {
+        let new_size = *size - freed + added;
+        match self.max_size {
+            Some(limit) if added > freed && new_size > limit => Err(StorageError::QuotaExceeded { limit }.into()),
+            _ => {
+                *size = new_size;
+                Ok(())
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for MemoryStorage {
+    async fn put_blob(&self, digest: &str, data: Bytes) -> Result<()> {
+        let mut state = self.state.write().unwrap();
+        let state = &mut *state;
+
+        let freed = state.blobs.get(digest).map_or(0, |blob| blob.data.len() as u64);
+        self.resize(&mut state.size, data.len() as u64, freed)?;
+
+        debug!("Stored blob {} in memory ({} bytes)", digest, data.len());
+        state.blobs.insert(
+            digest.to_string(),
+            StoredBlob {
+                data,
+                created_at: Utc::now(),
+            },
+        );
+        Ok(())
+    }
+
+    async fn get_blob(&self, digest: &str) -> Result<Option<Bytes>> {
+        Ok(self.state.read().unwrap().blobs.get(digest).map(|blob| blob.data.clone()))
+    }
+
+    async fn delete_blob(&self, digest: &str) -> Result<()> {
+        let mut state = self.state.write().unwrap();
+        if let Some(blob) = state.blobs.remove(digest) {
+            state.size -= blob.data.len() as u64;
+            debug!("Deleted blob {} from memory", digest);
+        }
+        Ok(())
+    }
+
+    async fn blob_exists(&self, digest: &str) -> Result<bool> {
+        Ok(self.state.read().unwrap().blobs.contains_key(digest))
+    }
+
+    async fn put_manifest(&self, repo: &str, reference: &str, data: Bytes, media_type: &str) -> Result<()> {
+        let digest = format!("sha256:{:x}", Sha256::digest(&data));
+
+        let mut state = self.state.write().unwrap();
+        let state = &mut *state;
+
+        let freed = state
+            .manifests
+            .get(repo)
+            .and_then(|manifests| manifests.get(reference))
+            .map_or(0, |manifest| manifest.data.len() as u64);
+        self.resize(&mut state.size, data.len() as u64, freed)?;
+
+        debug!("Stored manifest {}/{} in memory ({} bytes)", repo, reference, data.len());
+        state.manifests.entry(repo.to_string()).or_default().insert(
+            reference.to_string(),
+            StoredManifest {
+                data,
+                digest,
+                media_type: media_type.to_string(),
+                created_at: Utc::now(),
+            },
+        );
+        Ok(())
+    }
+
+    async fn get_manifest(&self, repo: &str, reference: &str) -> Result<Option<Bytes>> {
+        let state = self.state.read().unwrap();
+        Ok(state
+            .manifests
+            .get(repo)
+            .and_then(|manifests| manifests.get(reference))
+            .map(|manifest| manifest.data.clone()))
+    }
+
+    async fn delete_manifest(&self, repo: &str, reference: &str) -> Result<()> {
+        let mut state = self.state.write().unwrap();
+        let state = &mut *state;
+
+        let Some(manifests) = state.manifests.get_mut(repo) else {
+            return Ok(());
+        };
+        if let Some(manifest) = manifests.remove(reference) {
+            state.size -= manifest.data.len() as u64;
+            debug!("Deleted manifest {}/{} from memory", repo, reference);
+        }
+        if manifests.is_empty() {
+            state.manifests.remove(repo);
+        }
+        Ok(())
+    }
+
+    async fn list_tags_for_digest(&self, repo: &str, digest: &str) -> Result<Vec<String>> {
+        let state = self.state.read().unwrap();
+        Ok(state
+            .manifests
+            .get(repo)
+            .into_iter()
+            .flatten()
+            .filter(|(reference, manifest)| !is_digest_reference(reference) && manifest.digest == digest)
+            .map(|(reference, _)| reference.clone())
+            .collect())
+    }
+
+    async fn list_repositories(&self) -> Result<Vec<String>> {
+        Ok(self.state.read().unwrap().manifests.keys().cloned().collect())
+    }
+
+    async fn list_tags(&self, repo: &str) -> Result<Vec<String>> {
+        let state = self.state.read().unwrap();
+        Ok(state.manifests.get(repo).into_iter().flat_map(|manifests| manifests.keys().cloned()).collect())
+    }
+
+    async fn get_upload_url(&self, uuid: &str) -> Result<Option<String>> {
+        let exists = self.state.read().unwrap().uploads.contains_key(uuid);
+        Ok(exists.then(|| format!("/v2/uploads/{}", uuid)))
+    }
+
+    async fn create_upload(&self, uuid: &str) -> Result<()> {
+        self.state.write().unwrap().uploads.insert(
+            uuid.to_string(),
+            StoredUpload {
+                data: BytesMut::new(),
+                updated_at: Utc::now(),
+            },
+        );
+        debug!("Created upload session {}", uuid);
+        Ok(())
+    }
+
+    async fn get_upload_session(&self, uuid: &str) -> Result<Option<UploadSession>> {
+        let state = self.state.read().unwrap();
+        Ok(state.uploads.get(uuid).map(|upload| UploadSession {
+            uuid: uuid.to_string(),
+            size: upload.data.len() as u64,
+            updated_at: upload.updated_at,
+        }))
+    }
+
+    async fn list_uploads(&self) -> Result<Vec<UploadSession>> {
+        let state = self.state.read().unwrap();
+        Ok(state
+            .uploads
+            .iter()
+            .map(|(uuid, upload)| UploadSession {
+                uuid: uuid.clone(),
+                size: upload.data.len() as u64,
+                updated_at: upload.updated_at,
+            })
+            .collect())
+    }
+
+    async fn append_upload(&self, uuid: &str, mut data: BoxStream<'static, Result<Bytes>>) -> Result<u64> {
+        let mut offset = self
+            .get_upload_session(uuid)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Upload not found: {}", uuid))?
+            .size;
+        let start = offset;
+
+        // The lock is taken per chunk, never across an await
+        while let Some(chunk) = data.next().await {
+            let chunk = chunk?;
+
+            let mut state = self.state.write().unwrap();
+            let state = &mut *state;
+            let upload = state
+                .uploads
+                .get_mut(uuid)
+                .ok_or_else(|| anyhow::anyhow!("Upload not found: {}", uuid))?;
+            self.resize(&mut state.size, chunk.len() as u64, 0)?;
+
+            upload.data.extend_from_slice(&chunk);
+            upload.updated_at = Utc::now();
+            self.upload_hashers.update(uuid, offset, &chunk);
+            offset += chunk.len() as u64;
+        }
+
+        debug!("Appended {} bytes to upload {} (offset {})", offset - start, uuid, offset);
+        Ok(offset)
+    }
+
+    async fn complete_upload(&self, uuid: &str, digest: &str) -> Result<()> {
+        let upload = {
+            let mut state = self.state.write().unwrap();
+            let upload = state
+                .uploads
+                .remove(uuid)
+                .ok_or_else(|| anyhow::anyhow!("Upload not found: {}", uuid))?;
+            state.size -= upload.data.len() as u64;
+            upload
+        };
+        let data = upload.data.freeze();
+
+        // Verify the assembled content before it becomes addressable
+        let actual = match self.upload_hashers.finish(uuid, data.len() as u64) {
+            Some(actual) => actual,
+            None => format!("sha256:{:x}", Sha256::digest(&data)),
+        };
+        if actual != digest {
+            return Err(StorageError::DigestMismatch {
+                expected: digest.to_string(),
+                actual,
+            }
+            .into());
+        }
+
+        self.put_blob(digest, data).await?;
+        debug!("Completed upload {} -> blob {}", uuid, digest);
+        Ok(())
+    }
+
+    async fn cancel_upload(&self, uuid: &str) -> Result<()> {
+        self.upload_hashers.remove(uuid);
+
+        let mut state = self.state.write().unwrap();
+        if let Some(upload) = state.uploads.remove(uuid) {
+            state.size -= upload.data.len() as u64;
+            debug!("Cancelled upload {}", uuid);
+        }
+        Ok(())
+    }
+
+    // Garbage collection methods
+    async fn list_all_blobs(&self) -> Result<Vec<String>> {
+        Ok(self.state.read().unwrap().blobs.keys().cloned().collect())
+    }
+
+    async fn list_manifests(&self, repo: &str) -> Result<Vec<String>> {
+        let state = self.state.read().unwrap();
+        Ok(state
+            .manifests
+            .get(repo)
+            .into_iter()
+            .flat_map(|manifests| manifests.values().map(|manifest| manifest.digest.clone()))
+            .collect())
+    }
+
+    async fn get_blob_metadata(&self, digest: &str) -> Result<BlobMetadata> {
+        let state = self.state.read().unwrap();
+        let blob = state
+            .blobs
+            .get(digest)
+            .ok_or_else(|| anyhow::anyhow!("Blob not found: {}", digest))?;
+
+        Ok(BlobMetadata {
+            size: blob.data.len() as u64,
+            created_at: blob.created_at,
+        })
+    }
+
+    async fn get_manifest_metadata(&self, repo: &str, digest: &str) -> Result<ManifestMetadata> {
+        let state = self.state.read().unwrap();
+        let manifest = state
+            .manifests
+            .get(repo)
+            .and_then(|manifests| manifests.values().find(|manifest| manifest.digest == digest))
+            .ok_or_else(|| anyhow::anyhow!("Manifest not found: {}", digest))?;
+
+        Ok(ManifestMetadata {
+            size: manifest.data.len() as u64,
+            created_at: manifest.created_at,
+            media_type: Some(manifest.media_type.clone()),
+        })
+    }
+
+    async fn get_manifest_by_digest(&self, repo: &str, digest: &str) -> Result<Bytes> {
+        let state = self.state.read().unwrap();
+        state
+            .manifests
+            .get(repo)
+            .and_then(|manifests| manifests.values().find(|manifest| manifest.digest == digest))
+            .map(|manifest| manifest.data.clone())
+            .ok_or_else(|| anyhow::anyhow!("Manifest not found: {}", digest))
+    }
+
+    async fn get_manifest_digest(&self, repo: &str, reference: &str) -> Result<String> {
+        let state = self.state.read().unwrap();
+        state
+            .manifests
+            .get(repo)
+            .and_then(|manifests| manifests.get(reference))
+            .map(|manifest| manifest.digest.clone())
+            .ok_or_else(|| anyhow::anyhow!("Manifest not found: {}/{}", repo, reference))
+    }
+}
@@ -1,9 +1,14 @@
 use crate::config::{StorageConfig, StorageType};
+use crate::metrics::Metrics;
 use anyhow::Result;
 use async_trait::async_trait;
 use bytes::Bytes;
 use chrono::{DateTime, Utc};
-use std::sync::Arc;
+use futures::stream::{BoxStream, StreamExt};
+use sha2::{Digest, Sha256};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
 
 #[derive(Debug)]
 pub struct BlobMetadata {
@@ -15,9 +20,100 @@ pub struct BlobMetadata {
 pub struct ManifestMetadata {
     pub created_at: DateTime<Utc>,
     pub size: u64,
+    /// Media type recorded at push time, if the backend stored one
+    pub media_type: Option<String>,
 }
 
+/// An in-progress blob upload
+#[derive(Debug, Clone)]
+pub struct UploadSession {
+    pub uuid: String,
+    /// Bytes received so far
+    pub size: u64,
+    /// Time data was last appended, used to expire abandoned sessions
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Running sha256 of each upload session, fed as data is appended so that
+/// completing an upload doesn't need a second pass over the stored bytes.
+/// State is in-memory only; callers fall back to rehashing when it's missing.
+#[derive(Default)]
+pub(crate) struct UploadHashers {
+    sessions: Mutex<HashMap<String, (Sha256, u64)>>,
+}
+
+impl UploadHashers {
+    /// Feed data written at `offset`; a gap or rewrite invalidates the running hash
+    pub(crate) fn update(&self, uuid: &str, offset: u64, data: &[u8]) {
+        let mut sessions = self.sessions.lock().unwrap();
+
+        match sessions.get_mut(uuid) {
+            Some((hasher, hashed)) if *hashed == offset => {
+                hasher.update(data);
+                *hashed += data.len() as u64;
+            }
+            _ if offset == 0 => {
+                let mut hasher = Sha256::new();
+                hasher.update(data);
+                sessions.insert(uuid.to_string(), (hasher, data.len() as u64));
+            }
+            _ => {
+                sessions.remove(uuid);
+            }
+        }
+    }
+
+    /// Take the digest for a session if the running hash covers exactly `size` bytes
+    pub(crate) fn finish(&self, uuid: &str, size: u64) -> Option<String> {
+        let (hasher, hashed) = self.sessions.lock().unwrap().remove(uuid)?;
+        (hashed == size).then(|| format!("sha256:{:x}", hasher.finalize()))
+    }
+
+    pub(crate) fn remove(&self, uuid: &str) {
+        self.sessions.lock().unwrap().remove(uuid);
+    }
+}
+
+/// Which blobs stored manifests reference, from a backend's reference table
+#[derive(Debug, Default)]
+pub struct BlobReferences {
+    /// Blob digest -> repositories with a manifest that references it
+    pub blobs: HashMap<String, BTreeSet<String>>,
+    /// Every stored manifest as (repository, digest)
+    pub manifests: Vec<(String, String)>,
+    /// Manifests that could not be parsed, so their references are unknown
+    pub unreadable_manifests: usize,
+}
+
+/// Outcome of checking stored content and rebuilding reference tables
+#[derive(Debug, Default, Serialize)]
+pub struct FsckReport {
+    pub manifests_scanned: usize,
+    pub blobs_referenced: usize,
+    /// Blobs referenced by a manifest but missing from storage
+    pub missing_blobs: Vec<String>,
+}
+
+/// Typed storage failures that callers need to tell apart from I/O errors
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("digest mismatch: expected {expected}, computed {actual}")]
+    DigestMismatch { expected: String, actual: String },
+    #[error("upload exceeds the maximum size of {limit} bytes")]
+    SizeExceeded { limit: u64 },
+    #[error("storage is full: {limit} byte limit reached")]
+    QuotaExceeded { limit: u64 },
+    #[error("blob {digest} is corrupt: content hashes to {actual}")]
+    CorruptBlob { digest: String, actual: String },
+    #[error("{name} is not a valid repository name and reference")]
+    InvalidName { name: String },
+}
+
+pub mod caching;
 pub mod filesystem;
+pub mod gcs;
+pub mod instrumented;
+pub mod memory;
 pub mod s3;
 
 #[cfg(feature = "ghostbay-storage")]
@@ -27,18 +123,35 @@ pub mod ghostbay;
 pub trait StorageBackend: Send + Sync {
     async fn put_blob(&self, digest: &str, data: Bytes) -> Result<()>;
     async fn get_blob(&self, digest: &str) -> Result<Option<Bytes>>;
+    /// Stream a blob with its size, so large layers are never held in memory.
+    /// The default buffers the whole blob and should be overridden by real backends.
+    async fn get_blob_stream(&self, digest: &str) -> Result<Option<(u64, BoxStream<'static, Result<Bytes>>)>> {
+        Ok(self.get_blob(digest).await?.map(|data| {
+            let size = data.len() as u64;
+            (size, futures::stream::once(async move { Ok(data) }).boxed())
+        }))
+    }
     async fn delete_blob(&self, digest: &str) -> Result<()>;
     async fn blob_exists(&self, digest: &str) -> Result<bool>;
 
-    async fn put_manifest(&self, repo: &str, reference: &str, data: Bytes) -> Result<()>;
+    async fn put_manifest(&self, repo: &str, reference: &str, data: Bytes, media_type: &str) -> Result<()>;
     async fn get_manifest(&self, repo: &str, reference: &str) -> Result<Option<Bytes>>;
     async fn delete_manifest(&self, repo: &str, reference: &str) -> Result<()>;
+    /// Tags in a repository that currently point at the given manifest digest
+    async fn list_tags_for_digest(&self, repo: &str, digest: &str) -> Result<Vec<String>>;
 
     async fn list_repositories(&self) -> Result<Vec<String>>;
     async fn list_tags(&self, repo: &str) -> Result<Vec<String>>;
 
     async fn get_upload_url(&self, uuid: &str) -> Result<Option<String>>;
-    async fn put_upload_chunk(&self, uuid: &str, range: (u64, u64), data: Bytes) -> Result<()>;
+    /// Create the session record for a new upload
+    async fn create_upload(&self, uuid: &str) -> Result<()>;
+    /// Look up an upload session, or None if it doesn't exist
+    async fn get_upload_session(&self, uuid: &str) -> Result<Option<UploadSession>>;
+    /// All upload sessions, including abandoned ones
+    async fn list_uploads(&self) -> Result<Vec<UploadSession>>;
+    /// Append data to an upload session as it arrives, returning the new offset
+    async fn append_upload(&self, uuid: &str, data: BoxStream<'static, Result<Bytes>>) -> Result<u64>;
     async fn complete_upload(&self, uuid: &str, digest: &str) -> Result<()>;
     async fn cancel_upload(&self, uuid: &str) -> Result<()>;
 
@@ -49,19 +162,62 @@ pub trait StorageBackend: Send + Sync {
     async fn get_manifest_metadata(&self, repo: &str, digest: &str) -> Result<ManifestMetadata>;
     async fn get_manifest_by_digest(&self, repo: &str, digest: &str) -> Result<Bytes>;
     async fn get_manifest_digest(&self, repo: &str, reference: &str) -> Result<String>;
+
+    /// Build the backend's digest index and digest -> tag markers for
+    /// manifests stored before it kept them, returning how many entries
+    /// were written. Most backends need none.
+    async fn rebuild_manifest_index(&self) -> Result<usize> {
+        Ok(0)
+    }
+
+    /// The backend's blob reference table, or None if it keeps none and
+    /// callers have to parse manifests themselves
+    async fn blob_references(&self) -> Result<Option<BlobReferences>> {
+        Ok(None)
+    }
+
+    /// Check stored manifests against their blobs and rebuild any reference
+    /// tables from them. Backends without such tables have nothing to check.
+    async fn fsck(&self) -> Result<FsckReport> {
+        Ok(FsckReport::default())
+    }
 }
 
-pub async fn create_storage_backend(config: &StorageConfig) -> Result<Arc<dyn StorageBackend>> {
+/// Whether a manifest reference is a content digest rather than a tag
+pub fn is_digest_reference(reference: &str) -> bool {
+    reference.starts_with("sha256:")
+}
+
+pub async fn create_storage_backend(config: &StorageConfig, metrics: &Metrics) -> Result<Arc<dyn StorageBackend>> {
+    let backend = create_base_backend(config, metrics).await?;
+    match &config.cache {
+        Some(cache) => Ok(Arc::new(caching::CachingStorage::new(backend, cache).await?)),
+        None => Ok(backend),
+    }
+}
+
+async fn create_base_backend(config: &StorageConfig, metrics: &Metrics) -> Result<Arc<dyn StorageBackend>> {
     match config.storage_type {
         StorageType::Filesystem => {
             let path = config.path.as_ref()
                 .ok_or_else(|| anyhow::anyhow!("Filesystem storage requires path"))?;
-            Ok(Arc::new(filesystem::FilesystemStorage::new(path).await?))
+            Ok(Arc::new(
+                filesystem::FilesystemStorage::new(path).await?.with_verify_on_read(config.verify_on_read),
+            ))
         }
         StorageType::S3 => {
             let s3_config = config.s3.as_ref()
                 .ok_or_else(|| anyhow::anyhow!("S3 storage requires s3 config"))?;
-            Ok(Arc::new(s3::S3Storage::new(s3_config).await?))
+            Ok(Arc::new(s3::S3Storage::new(s3_config, metrics.s3_request_retries_total.clone()).await?))
+        }
+        StorageType::Memory => {
+            let max_size_mb = config.memory.as_ref().map_or(0, |memory| memory.max_size_mb);
+            Ok(Arc::new(memory::MemoryStorage::new((max_size_mb > 0).then(|| max_size_mb * 1024 * 1024))))
+        }
+        StorageType::Gcs => {
+            let gcs_config = config.gcs.as_ref()
+                .ok_or_else(|| anyhow::anyhow!("GCS storage requires gcs config"))?;
+            Ok(Arc::new(gcs::GcsStorage::new(gcs_config).await?))
         }
         StorageType::GhostBay => {
             #[cfg(feature = "ghostbay-storage")]
@@ -1,24 +1,110 @@
-use super::{BlobMetadata, ManifestMetadata, StorageBackend};
+use super::{is_digest_reference, BlobMetadata, ManifestMetadata, StorageBackend, StorageError, UploadHashers, UploadSession};
 use crate::config::S3Config;
 use anyhow::Result;
 use async_trait::async_trait;
-use aws_config::{BehaviorVersion, Region};
+use aws_config::Region;
+use aws_sdk_s3::config::interceptors::BeforeTransmitInterceptorContextRef;
+use aws_sdk_s3::config::retry::RetryConfig;
+use aws_sdk_s3::config::timeout::TimeoutConfig;
+use aws_sdk_s3::config::{BehaviorVersion, ConfigBag, Intercept, RuntimeComponents};
+use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::operation::head_object::HeadObjectOutput;
 use aws_sdk_s3::primitives::ByteStream;
 use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
 use aws_sdk_s3::{config::Credentials, Client, Config};
+use aws_smithy_runtime_api::box_error::BoxError;
+use aws_smithy_runtime_api::client::orchestrator::Metadata;
+use aws_smithy_runtime_api::client::retries::RequestAttempts;
 use bytes::Bytes;
-use chrono::{DateTime, Utc};
+use chrono::Utc;
+use futures::stream::{BoxStream, StreamExt};
+use prometheus::IntCounterVec;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
-use tracing::{debug, error, info};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
 
 pub struct S3Storage {
     client: Client,
     bucket: String,
+    upload_hashers: UploadHashers,
+    /// Whether every stored manifest has a digest index entry; until the
+    /// backfill has run, digest lookups without an entry scan the repository
+    digest_index_ready: AtomicBool,
+    /// Attempts and first backoff for object bodies that fail partway through,
+    /// which the SDK doesn't retry once the response has arrived
+    max_attempts: u32,
+    retry_base_delay: Duration,
+    retries: IntCounterVec,
+}
+
+/// Written by the backfill once every existing manifest has a digest index entry
+const DIGEST_INDEX_MARKER_KEY: &str = "manifests-by-digest/.complete";
+/// Smallest part S3 accepts in a multipart upload, other than the last
+const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+/// Largest object CopyObject can copy in a single request
+const MAX_COPY_OBJECT_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+/// Part size used when copying objects above the CopyObject limit
+const COPY_PART_SIZE: u64 = 512 * 1024 * 1024;
+/// Longest backoff between body read retries, as for the SDK's own retries
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(20);
+
+/// Per-session record of the S3 multipart upload backing a blob upload
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct UploadIndex {
+    upload_id: String,
+    parts: Vec<UploadedPart>,
+    /// Bytes held in the pending object, not yet uploaded as a part
+    pending_size: u64,
+    /// Total bytes received so far
+    size: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct UploadedPart {
+    part_number: i32,
+    e_tag: String,
+    size: u64,
+}
+
+/// Whether a request failed because the key doesn't exist. The error's
+/// Display is only "service error", so this goes by the HTTP status.
+fn is_not_found<E>(error: &SdkError<E, aws_smithy_runtime_api::http::Response>) -> bool {
+    error.raw_response().is_some_and(|response| response.status().as_u16() == 404)
+}
+
+/// Counts retried S3 requests per operation. The SDK's standard retry
+/// strategy decides what is retryable; this only observes its attempts.
+#[derive(Debug)]
+struct RetryCounter {
+    retries: IntCounterVec,
+}
+
+impl Intercept for RetryCounter {
+    fn name(&self) -> &'static str {
+        "DriftRetryCounter"
+    }
+
+    fn read_before_attempt(
+        &self,
+        _context: &BeforeTransmitInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        let attempt = cfg.load::<RequestAttempts>().map_or(1, RequestAttempts::attempts);
+        if attempt > 1 {
+            let operation = cfg.load::<Metadata>().map_or("unknown", Metadata::name);
+            warn!("Retrying S3 {} (attempt {})", operation, attempt);
+            self.retries.with_label_values(&[operation]).inc();
+        }
+        Ok(())
+    }
 }
 
 impl S3Storage {
-    pub async fn new(config: &S3Config) -> Result<Self> {
+    pub async fn new(config: &S3Config, retries: IntCounterVec) -> Result<Self> {
         let credentials = Credentials::new(
             &config.access_key,
             &config.secret_key,
@@ -27,9 +113,27 @@ impl S3Storage {
             "drift-s3",
         );
 
+        // Throttling (SlowDown), 5xx responses and timeouts are retried with
+        // exponential backoff and jitter; NoSuchKey, AccessDenied and other
+        // client errors fail immediately
+        let retry_config = RetryConfig::standard()
+            .with_max_attempts(config.max_attempts.max(1))
+            .with_initial_backoff(Duration::from_millis(config.retry_base_delay_ms));
+
+        let mut timeout_config = TimeoutConfig::builder()
+            .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+            .read_timeout(Duration::from_secs(config.read_timeout_secs));
+        if config.attempt_timeout_secs > 0 {
+            timeout_config = timeout_config.operation_attempt_timeout(Duration::from_secs(config.attempt_timeout_secs));
+        }
+
         let mut s3_config_builder = Config::builder()
+            .behavior_version(BehaviorVersion::latest())
             .region(Region::new(config.region.clone()))
-            .credentials_provider(credentials);
+            .credentials_provider(credentials)
+            .retry_config(retry_config)
+            .timeout_config(timeout_config.build())
+            .interceptor(RetryCounter { retries: retries.clone() });
 
         // Configure for MinIO/custom S3 endpoints
         if config.path_style {
@@ -53,9 +157,29 @@ impl S3Storage {
             }
         }
 
+        let digest_index_ready = match client
+            .head_object()
+            .bucket(&config.bucket)
+            .key(DIGEST_INDEX_MARKER_KEY)
+            .send()
+            .await
+        {
+            Ok(_) => true,
+            Err(e) if is_not_found(&e) => {
+                warn!("S3 manifest digest index not built yet; run POST /api/v1/admin/storage/manifest-index to avoid scanning repositories for manifests stored before it");
+                false
+            }
+            Err(e) => return Err(anyhow::anyhow!("S3 connection failed: {}", e)),
+        };
+
         Ok(Self {
             client,
             bucket: config.bucket.clone(),
+            upload_hashers: UploadHashers::default(),
+            digest_index_ready: AtomicBool::new(digest_index_ready),
+            max_attempts: config.max_attempts.max(1),
+            retry_base_delay: Duration::from_millis(config.retry_base_delay_ms),
+            retries,
         })
     }
 
@@ -67,8 +191,322 @@ impl S3Storage {
         format!("manifests/{}/{}", repo, reference)
     }
 
-    fn upload_key(&self, uuid: &str) -> String {
-        format!("uploads/{}", uuid)
+    /// Marker object recording that a tag points at a digest
+    fn manifest_tag_marker_key(&self, repo: &str, digest: &str, tag: &str) -> String {
+        format!("manifest_tags/{}/{}/{}", repo, digest, tag)
+    }
+
+    /// Drop the digest -> tag marker for whatever a tag currently points at,
+    /// returning that digest
+    async fn remove_tag_marker(&self, repo: &str, tag: &str) -> Result<Option<String>> {
+        let Some(data) = self.get_manifest(repo, tag).await? else {
+            return Ok(None);
+        };
+        let digest = format!("sha256:{:x}", Sha256::digest(&data));
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(self.manifest_tag_marker_key(repo, &digest, tag))
+            .send()
+            .await?;
+        Ok(Some(digest))
+    }
+
+    /// Copy of a manifest stored under its digest, so digest lookups take one request
+    fn manifest_digest_key(&self, repo: &str, digest: &str) -> String {
+        format!("manifests-by-digest/{}/{}", repo, digest)
+    }
+
+    async fn put_digest_index(&self, repo: &str, digest: &str, data: Bytes, media_type: Option<&str>) -> Result<()> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(self.manifest_digest_key(repo, digest))
+            .body(ByteStream::from(data))
+            .set_content_type(media_type.map(str::to_string))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    /// Drop a digest index entry once no tag or digest reference points at it
+    async fn release_digest_index(&self, repo: &str, digest: &str) -> Result<()> {
+        if self.object_exists(&self.manifest_key(repo, digest)).await?
+            || !self.list_tags_for_digest(repo, digest).await?.is_empty()
+        {
+            return Ok(());
+        }
+
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(self.manifest_digest_key(repo, digest))
+            .send()
+            .await?;
+        debug!("Dropped digest index entry for {}@{}", repo, digest);
+        Ok(())
+    }
+
+    async fn object_exists(&self, key: &str) -> Result<bool> {
+        Ok(self.head_existing_object(key).await?.is_some())
+    }
+
+    async fn head_existing_object(&self, key: &str) -> Result<Option<HeadObjectOutput>> {
+        match self.client.head_object().bucket(&self.bucket).key(key).send().await {
+            Ok(response) => Ok(Some(response)),
+            Err(e) if is_not_found(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Find a manifest by hashing every object in the repository. Only used
+    /// until the digest index has been built.
+    async fn find_manifest_by_scan(&self, repo: &str, digest: &str) -> Result<Option<(String, Bytes)>> {
+        let prefix = format!("manifests/{}/", repo);
+        let mut continuation_token: Option<String> = None;
+
+        loop {
+            let mut request = self
+                .client
+                .list_objects_v2()
+                .bucket(&self.bucket)
+                .prefix(&prefix)
+                .delimiter("/");
+
+            if let Some(token) = continuation_token {
+                request = request.continuation_token(token);
+            }
+
+            let response = request.send().await?;
+
+            for key in response.contents.unwrap_or_default().into_iter().filter_map(|object| object.key) {
+                let Some(data) = self.read_object(&key).await? else {
+                    continue;
+                };
+                if format!("sha256:{:x}", Sha256::digest(&data)) == digest {
+                    return Ok(Some((key, data)));
+                }
+            }
+
+            if !response.is_truncated.unwrap_or(false) {
+                break;
+            }
+
+            continuation_token = response.next_continuation_token;
+        }
+
+        Ok(None)
+    }
+
+    /// Delete all objects stored under an upload session
+    async fn delete_upload_objects(&self, uuid: &str) -> Result<()> {
+        let prefix = format!("uploads/{}/", uuid);
+
+        let resp = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(&prefix)
+            .send()
+            .await?;
+
+        if let Some(objects) = resp.contents {
+            for object in objects {
+                if let Some(key) = object.key {
+                    self.client
+                        .delete_object()
+                        .bucket(&self.bucket)
+                        .key(&key)
+                        .send()
+                        .await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Object holding the upload session's part index
+    fn upload_index_key(&self, uuid: &str) -> String {
+        format!("uploads/{}/metadata", uuid)
+    }
+
+    /// Object the multipart upload assembles into before verification
+    fn upload_staging_key(&self, uuid: &str) -> String {
+        format!("uploads/{}/data", uuid)
+    }
+
+    /// Object buffering data not yet large enough to become a part
+    fn upload_pending_key(&self, uuid: &str) -> String {
+        format!("uploads/{}/pending", uuid)
+    }
+
+    async fn load_upload_index(&self, uuid: &str) -> Result<Option<UploadIndex>> {
+        match self.read_object(&self.upload_index_key(uuid)).await? {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn save_upload_index(&self, uuid: &str, index: &UploadIndex) -> Result<()> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(self.upload_index_key(uuid))
+            .body(ByteStream::from(serde_json::to_vec(index)?))
+            .content_type("application/json")
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    /// Upload the next part of a session's multipart upload
+    async fn upload_part(&self, uuid: &str, index: &mut UploadIndex, data: Bytes) -> Result<()> {
+        let part_number = index.parts.len() as i32 + 1;
+        let size = data.len() as u64;
+
+        let response = self
+            .client
+            .upload_part()
+            .bucket(&self.bucket)
+            .key(self.upload_staging_key(uuid))
+            .upload_id(&index.upload_id)
+            .part_number(part_number)
+            .body(ByteStream::from(data))
+            .send()
+            .await?;
+
+        let e_tag = response.e_tag
+            .ok_or_else(|| anyhow::anyhow!("S3 did not return an ETag for part {}", part_number))?;
+
+        index.parts.push(UploadedPart { part_number, e_tag, size });
+        Ok(())
+    }
+
+    async fn get_object_bytes(&self, key: &str) -> Result<Bytes> {
+        self.read_object(key)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("S3 object not found: {}", key))
+    }
+
+    /// Fetch a whole object, or None if it doesn't exist. The SDK retries the
+    /// request; a body that fails partway through, e.g. on a connection reset,
+    /// is fetched again here with exponential backoff and jitter.
+    async fn read_object(&self, key: &str) -> Result<Option<Bytes>> {
+        let mut attempt = 1;
+        loop {
+            let response = match self.client.get_object().bucket(&self.bucket).key(key).send().await {
+                Ok(response) => response,
+                Err(e) if is_not_found(&e) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+
+            match response.body.collect().await {
+                Ok(data) => return Ok(Some(data.into_bytes())),
+                Err(e) if attempt < self.max_attempts => {
+                    let delay = backoff_delay(self.retry_base_delay, attempt);
+                    warn!("Reading S3 object {} failed (attempt {}), retrying in {:?}: {}", key, attempt, delay, e);
+                    self.retries.with_label_values(&["GetObjectBody"]).inc();
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Compute the sha256 digest of an object by streaming its body
+    async fn hash_object(&self, key: &str) -> Result<String> {
+        let resp = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await?;
+
+        let mut body = resp.body;
+        let mut hasher = Sha256::new();
+        while let Some(chunk) = body.next().await {
+            hasher.update(&chunk?);
+        }
+
+        Ok(format!("sha256:{:x}", hasher.finalize()))
+    }
+
+    /// Server-side copy, falling back to a multipart copy above the 5 GiB CopyObject limit
+    async fn copy_object(&self, source_key: &str, dest_key: &str, size: u64) -> Result<()> {
+        let copy_source = format!("{}/{}", self.bucket, source_key);
+
+        if size <= MAX_COPY_OBJECT_SIZE {
+            self.client
+                .copy_object()
+                .bucket(&self.bucket)
+                .key(dest_key)
+                .copy_source(&copy_source)
+                .content_type("application/octet-stream")
+                .send()
+                .await?;
+            return Ok(());
+        }
+
+        let upload_id = self
+            .client
+            .create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(dest_key)
+            .content_type("application/octet-stream")
+            .send()
+            .await?
+            .upload_id
+            .ok_or_else(|| anyhow::anyhow!("S3 did not return a multipart upload id"))?;
+
+        let mut completed_parts = Vec::new();
+        let mut offset = 0u64;
+        let mut part_number = 1;
+        while offset < size {
+            let end = (offset + COPY_PART_SIZE).min(size) - 1;
+            let response = self
+                .client
+                .upload_part_copy()
+                .bucket(&self.bucket)
+                .key(dest_key)
+                .upload_id(&upload_id)
+                .part_number(part_number)
+                .copy_source(&copy_source)
+                .copy_source_range(format!("bytes={}-{}", offset, end))
+                .send()
+                .await?;
+
+            let e_tag = response.copy_part_result
+                .and_then(|result| result.e_tag)
+                .ok_or_else(|| anyhow::anyhow!("S3 did not return an ETag for copy part {}", part_number))?;
+
+            completed_parts.push(
+                CompletedPart::builder()
+                    .part_number(part_number)
+                    .e_tag(e_tag)
+                    .build(),
+            );
+
+            offset = end + 1;
+            part_number += 1;
+        }
+
+        self.client
+            .complete_multipart_upload()
+            .bucket(&self.bucket)
+            .key(dest_key)
+            .upload_id(&upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(completed_parts))
+                    .build(),
+            )
+            .send()
+            .await?;
+
+        Ok(())
     }
 }
 
@@ -92,6 +530,20 @@ impl StorageBackend for S3Storage {
     }
 
     async fn get_blob(&self, digest: &str) -> Result<Option<Bytes>> {
+        match self.read_object(&self.blob_key(digest)).await {
+            Ok(Some(data)) => {
+                debug!("Retrieved blob {} from S3 ({} bytes)", digest, data.len());
+                Ok(Some(data))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => {
+                error!("Failed to get blob {} from S3: {}", digest, e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn get_blob_stream(&self, digest: &str) -> Result<Option<(u64, BoxStream<'static, Result<Bytes>>)>> {
         let key = self.blob_key(digest);
 
         match self
@@ -103,12 +555,19 @@ impl StorageBackend for S3Storage {
             .await
         {
             Ok(resp) => {
-                let data = resp.body.collect().await?.into_bytes();
-                debug!("Retrieved blob {} from S3 ({} bytes)", digest, data.len());
-                Ok(Some(data))
+                let size = resp.content_length.unwrap_or(0) as u64;
+                debug!("Streaming blob {} from S3 ({} bytes)", digest, size);
+
+                let stream = futures::stream::unfold(resp.body, |mut body| async move {
+                    body.next()
+                        .await
+                        .map(|chunk| (chunk.map_err(anyhow::Error::from), body))
+                })
+                .boxed();
+                Ok(Some((size, stream)))
             }
             Err(e) => {
-                if e.to_string().contains("NoSuchKey") {
+                if is_not_found(&e) {
                     Ok(None)
                 } else {
                     error!("Failed to get blob {} from S3: {}", digest, e);
@@ -145,7 +604,7 @@ impl StorageBackend for S3Storage {
         {
             Ok(_) => Ok(true),
             Err(e) => {
-                if e.to_string().contains("NotFound") {
+                if is_not_found(&e) {
                     Ok(false)
                 } else {
                     Err(e.into())
@@ -154,47 +613,59 @@ impl StorageBackend for S3Storage {
         }
     }
 
-    async fn put_manifest(&self, repo: &str, reference: &str, data: Bytes) -> Result<()> {
+    async fn put_manifest(&self, repo: &str, reference: &str, data: Bytes, media_type: &str) -> Result<()> {
         let key = self.manifest_key(repo, reference);
+        let digest = format!("sha256:{:x}", Sha256::digest(&data));
+
+        // Retagging moves the tag off the digest it used to point at
+        let previous = if is_digest_reference(reference) {
+            None
+        } else {
+            self.remove_tag_marker(repo, reference).await?
+        };
+
+        // Index first, so nothing is reachable by reference but missing by digest
+        self.put_digest_index(repo, &digest, data.clone(), Some(media_type)).await?;
 
         self.client
             .put_object()
             .bucket(&self.bucket)
             .key(&key)
             .body(ByteStream::from(data.clone()))
-            .content_type("application/vnd.docker.distribution.manifest.v2+json")
+            .content_type(media_type)
             .metadata("repository", repo)
             .metadata("reference", reference)
             .send()
             .await?;
 
+        if !is_digest_reference(reference) {
+            self.client
+                .put_object()
+                .bucket(&self.bucket)
+                .key(self.manifest_tag_marker_key(repo, &digest, reference))
+                .body(ByteStream::from_static(b""))
+                .send()
+                .await?;
+        }
+
+        if let Some(previous) = previous.filter(|previous| *previous != digest) {
+            self.release_digest_index(repo, &previous).await?;
+        }
+
         debug!("Stored manifest {}/{} in S3 ({} bytes)", repo, reference, data.len());
         Ok(())
     }
 
     async fn get_manifest(&self, repo: &str, reference: &str) -> Result<Option<Bytes>> {
-        let key = self.manifest_key(repo, reference);
-
-        match self
-            .client
-            .get_object()
-            .bucket(&self.bucket)
-            .key(&key)
-            .send()
-            .await
-        {
-            Ok(resp) => {
-                let data = resp.body.collect().await?.into_bytes();
+        match self.read_object(&self.manifest_key(repo, reference)).await {
+            Ok(Some(data)) => {
                 debug!("Retrieved manifest {}/{} from S3 ({} bytes)", repo, reference, data.len());
                 Ok(Some(data))
             }
+            Ok(None) => Ok(None),
             Err(e) => {
-                if e.to_string().contains("NoSuchKey") {
-                    Ok(None)
-                } else {
-                    error!("Failed to get manifest {}/{} from S3: {}", repo, reference, e);
-                    Err(e.into())
-                }
+                error!("Failed to get manifest {}/{} from S3: {}", repo, reference, e);
+                Err(e)
             }
         }
     }
@@ -202,6 +673,12 @@ impl StorageBackend for S3Storage {
     async fn delete_manifest(&self, repo: &str, reference: &str) -> Result<()> {
         let key = self.manifest_key(repo, reference);
 
+        let digest = if is_digest_reference(reference) {
+            Some(reference.to_string())
+        } else {
+            self.remove_tag_marker(repo, reference).await?
+        };
+
         self.client
             .delete_object()
             .bucket(&self.bucket)
@@ -209,10 +686,51 @@ impl StorageBackend for S3Storage {
             .send()
             .await?;
 
+        if let Some(digest) = digest {
+            self.release_digest_index(repo, &digest).await?;
+        }
+
         debug!("Deleted manifest {}/{} from S3", repo, reference);
         Ok(())
     }
 
+    async fn list_tags_for_digest(&self, repo: &str, digest: &str) -> Result<Vec<String>> {
+        let prefix = format!("manifest_tags/{}/{}/", repo, digest);
+        let mut tags = Vec::new();
+        let mut continuation_token: Option<String> = None;
+
+        loop {
+            let mut request = self
+                .client
+                .list_objects_v2()
+                .bucket(&self.bucket)
+                .prefix(&prefix);
+
+            if let Some(token) = continuation_token {
+                request = request.continuation_token(token);
+            }
+
+            let response = request.send().await?;
+
+            if let Some(contents) = response.contents {
+                for object in contents {
+                    if let Some(tag) = object.key.as_deref().and_then(|key| key.strip_prefix(&prefix)) {
+                        tags.push(tag.to_string());
+                    }
+                }
+            }
+
+            if !response.is_truncated.unwrap_or(false) {
+                break;
+            }
+
+            continuation_token = response.next_continuation_token;
+        }
+
+        tags.sort();
+        Ok(tags)
+    }
+
     async fn list_repositories(&self) -> Result<Vec<String>> {
         let mut repos = Vec::new();
         let mut continuation_token: Option<String> = None;
@@ -222,8 +740,7 @@ impl StorageBackend for S3Storage {
                 .client
                 .list_objects_v2()
                 .bucket(&self.bucket)
-                .prefix("manifests/")
-                .delimiter("/");
+                .prefix("manifests/");
 
             if let Some(token) = continuation_token {
                 request = request.continuation_token(token);
@@ -231,14 +748,11 @@ impl StorageBackend for S3Storage {
 
             let resp = request.send().await?;
 
-            // Extract repository names from common prefixes
-            if let Some(prefixes) = resp.common_prefixes {
-                for prefix in prefixes {
-                    if let Some(prefix_str) = prefix.prefix {
-                        if let Some(repo_name) = prefix_str.strip_prefix("manifests/").and_then(|s| s.strip_suffix("/")) {
-                            repos.push(repo_name.to_string());
-                        }
-                    }
+            // Repository names may be nested, so a manifest's repository is
+            // everything between "manifests/" and its last path segment
+            for key in resp.contents.unwrap_or_default().into_iter().filter_map(|object| object.key) {
+                if let Some((repo_name, _)) = key.strip_prefix("manifests/").and_then(|s| s.rsplit_once('/')) {
+                    repos.push(repo_name.to_string());
                 }
             }
 
@@ -264,7 +778,8 @@ impl StorageBackend for S3Storage {
                 .client
                 .list_objects_v2()
                 .bucket(&self.bucket)
-                .prefix(&prefix);
+                .prefix(&prefix)
+                .delimiter("/");
 
             if let Some(token) = continuation_token {
                 request = request.continuation_token(token);
@@ -274,10 +789,10 @@ impl StorageBackend for S3Storage {
 
             if let Some(objects) = resp.contents {
                 for object in objects {
-                    if let Some(key) = object.key {
-                        if let Some(tag) = key.strip_prefix(&prefix) {
-                            tags.push(tag.to_string());
-                        }
+                    if let Some(key) = object.key
+                        && let Some(tag) = key.strip_prefix(&prefix)
+                    {
+                        tags.push(tag.to_string());
                     }
                 }
             }
@@ -310,106 +825,246 @@ impl StorageBackend for S3Storage {
         }
     }
 
-    async fn put_upload_chunk(&self, uuid: &str, range: (u64, u64), data: Bytes) -> Result<()> {
-        // For simplicity, store chunks as separate objects
-        // In production, you'd use S3 multipart uploads
-        let key = format!("uploads/{}/chunk-{}-{}", uuid, range.0, range.1);
-
-        self.client
-            .put_object()
+    async fn create_upload(&self, uuid: &str) -> Result<()> {
+        let response = self
+            .client
+            .create_multipart_upload()
             .bucket(&self.bucket)
-            .key(&key)
-            .body(ByteStream::from(data.clone()))
-            .metadata("range_start", range.0.to_string())
-            .metadata("range_end", range.1.to_string())
+            .key(self.upload_staging_key(uuid))
+            .content_type("application/octet-stream")
             .send()
             .await?;
 
-        debug!("Stored upload chunk {} range {:?} in S3", uuid, range);
+        let upload_id = response.upload_id
+            .ok_or_else(|| anyhow::anyhow!("S3 did not return a multipart upload id"))?;
+
+        self.save_upload_index(uuid, &UploadIndex { upload_id: upload_id.clone(), ..Default::default() }).await?;
+
+        debug!("Started multipart upload {} for {}", upload_id, uuid);
         Ok(())
     }
 
-    async fn complete_upload(&self, uuid: &str, digest: &str) -> Result<()> {
-        // Collect all chunks and combine them into the final blob
-        let prefix = format!("uploads/{}/chunk-", uuid);
-        let mut chunks = Vec::new();
+    async fn get_upload_session(&self, uuid: &str) -> Result<Option<UploadSession>> {
+        let key = self.upload_index_key(uuid);
 
-        let resp = self
+        match self
             .client
-            .list_objects_v2()
+            .head_object()
             .bucket(&self.bucket)
-            .prefix(&prefix)
+            .key(&key)
             .send()
-            .await?;
-
-        if let Some(objects) = resp.contents {
-            for object in objects {
-                if let Some(key) = object.key {
-                    chunks.push(key);
+            .await
+        {
+            Ok(head) => {
+                let index = match self.load_upload_index(uuid).await? {
+                    Some(index) => index,
+                    None => return Ok(None),
+                };
+
+                // The index is rewritten on every append, so it tracks last activity
+                let updated_at = head.last_modified
+                    .and_then(|dt| chrono::DateTime::from_timestamp(dt.secs(), dt.subsec_nanos()))
+                    .unwrap_or_else(Utc::now);
+
+                Ok(Some(UploadSession {
+                    uuid: uuid.to_string(),
+                    size: index.size,
+                    updated_at,
+                }))
+            }
+            Err(e) => {
+                if is_not_found(&e) {
+                    Ok(None)
+                } else {
+                    Err(e.into())
                 }
             }
         }
+    }
 
-        // Sort chunks by range
-        chunks.sort();
+    async fn list_uploads(&self) -> Result<Vec<UploadSession>> {
+        let mut sessions: HashMap<String, UploadSession> = HashMap::new();
+        let mut continuation_token: Option<String> = None;
 
-        // Combine chunks into final blob
-        let mut combined_data = Vec::new();
-        for chunk_key in &chunks {
-            let chunk_resp = self
+        loop {
+            let mut request = self
                 .client
-                .get_object()
+                .list_objects_v2()
                 .bucket(&self.bucket)
-                .key(chunk_key)
-                .send()
-                .await?;
+                .prefix("uploads/");
+
+            if let Some(token) = continuation_token {
+                request = request.continuation_token(token);
+            }
+
+            let response = request.send().await?;
 
-            let chunk_data = chunk_resp.body.collect().await?.into_bytes();
-            combined_data.extend_from_slice(&chunk_data);
+            if let Some(contents) = response.contents {
+                for object in contents {
+                    let Some(uuid) = object.key.as_deref()
+                        .and_then(|key| key.strip_prefix("uploads/"))
+                        .and_then(|rest| rest.split('/').next())
+                    else {
+                        continue;
+                    };
+
+                    let modified = object.last_modified
+                        .and_then(|dt| chrono::DateTime::from_timestamp(dt.secs(), dt.subsec_nanos()))
+                        .unwrap_or_else(Utc::now);
+
+                    // Sessions may have several objects; keep the latest activity and total size
+                    let session = sessions.entry(uuid.to_string()).or_insert_with(|| UploadSession {
+                        uuid: uuid.to_string(),
+                        size: 0,
+                        updated_at: modified,
+                    });
+                    session.size += object.size.unwrap_or(0) as u64;
+                    session.updated_at = session.updated_at.max(modified);
+                }
+            }
+
+            if !response.is_truncated.unwrap_or(false) {
+                break;
+            }
+
+            continuation_token = response.next_continuation_token;
         }
 
-        // Store as final blob
-        self.put_blob(digest, combined_data.into()).await?;
+        // Multipart parts aren't listed as objects, so use the index size where known
+        for session in sessions.values_mut() {
+            if let Ok(Some(index)) = self.load_upload_index(&session.uuid).await {
+                session.size = index.size;
+            }
+        }
+
+        Ok(sessions.into_values().collect())
+    }
 
-        // Clean up upload chunks
-        for chunk_key in chunks {
+    async fn append_upload(&self, uuid: &str, mut data: BoxStream<'static, Result<Bytes>>) -> Result<u64> {
+        let mut index = self.load_upload_index(uuid).await?
+            .ok_or_else(|| anyhow::anyhow!("Upload not found: {}", uuid))?;
+
+        // S3 parts must be at least 5 MiB except the last, so data is buffered
+        // up to that size and any remainder is held in a pending object
+        let mut buffer = Vec::with_capacity(MIN_PART_SIZE);
+        if index.pending_size > 0 {
+            buffer.extend_from_slice(&self.get_object_bytes(&self.upload_pending_key(uuid)).await?);
+        }
+
+        let mut received = 0u64;
+        while let Some(chunk) = data.next().await {
+            let chunk = chunk?;
+            self.upload_hashers.update(uuid, index.size + received, &chunk);
+            received += chunk.len() as u64;
+            buffer.extend_from_slice(&chunk);
+
+            if buffer.len() >= MIN_PART_SIZE {
+                let part = std::mem::replace(&mut buffer, Vec::with_capacity(MIN_PART_SIZE));
+                self.upload_part(uuid, &mut index, part.into()).await?;
+            }
+        }
+
+        index.size += received;
+        index.pending_size = buffer.len() as u64;
+        if !buffer.is_empty() {
             self.client
-                .delete_object()
+                .put_object()
                 .bucket(&self.bucket)
-                .key(&chunk_key)
+                .key(self.upload_pending_key(uuid))
+                .body(ByteStream::from(buffer))
                 .send()
                 .await?;
         }
 
-        debug!("Completed upload {} -> blob {}", uuid, digest);
-        Ok(())
+        self.save_upload_index(uuid, &index).await?;
+
+        debug!("Appended {} bytes to upload {} in S3 ({} parts, offset {})",
+            received, uuid, index.parts.len(), index.size);
+        Ok(index.size)
     }
 
-    async fn cancel_upload(&self, uuid: &str) -> Result<()> {
-        // Delete all upload-related objects
-        let prefix = format!("uploads/{}/", uuid);
+    async fn complete_upload(&self, uuid: &str, digest: &str) -> Result<()> {
+        let mut index = self.load_upload_index(uuid).await?
+            .ok_or_else(|| anyhow::anyhow!("Upload not found: {}", uuid))?;
 
-        let resp = self
-            .client
-            .list_objects_v2()
+        // Flush the remaining data; the final part may be smaller than 5 MiB
+        if index.pending_size > 0 || index.parts.is_empty() {
+            let pending = if index.pending_size > 0 {
+                self.get_object_bytes(&self.upload_pending_key(uuid)).await?
+            } else {
+                Bytes::new()
+            };
+            self.upload_part(uuid, &mut index, pending).await?;
+            index.pending_size = 0;
+        }
+
+        let completed_parts = index.parts.iter()
+            .map(|part| {
+                CompletedPart::builder()
+                    .part_number(part.part_number)
+                    .e_tag(&part.e_tag)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+
+        let staging_key = self.upload_staging_key(uuid);
+        self.client
+            .complete_multipart_upload()
             .bucket(&self.bucket)
-            .prefix(&prefix)
+            .key(&staging_key)
+            .upload_id(&index.upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(completed_parts))
+                    .build(),
+            )
             .send()
             .await?;
 
-        if let Some(objects) = resp.contents {
-            for object in objects {
-                if let Some(key) = object.key {
-                    self.client
-                        .delete_object()
-                        .bucket(&self.bucket)
-                        .key(&key)
-                        .send()
-                        .await?;
-                }
+        // Verify the assembled content before it becomes addressable. Without a
+        // running hash, stream the object back so the blob is never held in memory
+        let actual = match self.upload_hashers.finish(uuid, index.size) {
+            Some(actual) => actual,
+            None => self.hash_object(&staging_key).await?,
+        };
+        if actual != digest {
+            self.cancel_upload(uuid).await?;
+            return Err(StorageError::DigestMismatch {
+                expected: digest.to_string(),
+                actual,
             }
+            .into());
         }
 
+        self.copy_object(&staging_key, &self.blob_key(digest), index.size).await?;
+
+        // Clean up the session objects
+        self.delete_upload_objects(uuid).await?;
+
+        debug!("Completed upload {} -> blob {} ({} bytes)", uuid, digest, index.size);
+        Ok(())
+    }
+
+    async fn cancel_upload(&self, uuid: &str) -> Result<()> {
+        self.upload_hashers.remove(uuid);
+
+        if let Some(index) = self.load_upload_index(uuid).await? {
+            // Abort may fail if the multipart upload was already completed
+            if let Err(e) = self
+                .client
+                .abort_multipart_upload()
+                .bucket(&self.bucket)
+                .key(self.upload_staging_key(uuid))
+                .upload_id(&index.upload_id)
+                .send()
+                .await
+            {
+                debug!("Abort of multipart upload {} for {} failed: {}", index.upload_id, uuid, e);
+            }
+        }
+
+        self.delete_upload_objects(uuid).await?;
+
         debug!("Cancelled upload {}", uuid);
         Ok(())
     }
@@ -435,8 +1090,11 @@ impl StorageBackend for S3Storage {
             if let Some(contents) = response.contents {
                 for object in contents {
                     if let Some(key) = object.key {
-                        // Extract digest from key (remove "blobs/" prefix)
-                        if let Some(digest) = key.strip_prefix("blobs/") {
+                        // Extract digest from key (remove "blobs/<prefix>/")
+                        if let Some((_, digest)) = key
+                            .strip_prefix("blobs/")
+                            .and_then(|rest| rest.split_once('/'))
+                        {
                             blobs.push(digest.to_string());
                         }
                     }
@@ -454,8 +1112,13 @@ impl StorageBackend for S3Storage {
     }
 
     async fn list_manifests(&self, repo: &str) -> Result<Vec<String>> {
+        let indexed = self.digest_index_ready.load(Ordering::Relaxed);
+        let prefix = if indexed {
+            format!("manifests-by-digest/{}/", repo)
+        } else {
+            format!("manifests/{}/", repo)
+        };
         let mut manifests = Vec::new();
-        let prefix = format!("manifests/{}/", repo);
         let mut continuation_token: Option<String> = None;
 
         loop {
@@ -463,7 +1126,8 @@ impl StorageBackend for S3Storage {
                 .client
                 .list_objects_v2()
                 .bucket(&self.bucket)
-                .prefix(&prefix);
+                .prefix(&prefix)
+                .delimiter("/");
 
             if let Some(token) = continuation_token {
                 request = request.continuation_token(token);
@@ -471,24 +1135,18 @@ impl StorageBackend for S3Storage {
 
             let response = request.send().await?;
 
-            if let Some(contents) = response.contents {
-                for object in contents {
-                    if let Some(key) = object.key {
-                        // For manifest digests, we need to get the object and compute its hash
-                        match self.client
-                            .get_object()
-                            .bucket(&self.bucket)
-                            .key(&key)
-                            .send()
-                            .await {
-                            Ok(response) => {
-                                let body = response.body.collect().await?;
-                                let digest = format!("sha256:{:x}", Sha256::digest(&body.into_bytes()));
-                                manifests.push(digest);
-                            }
-                            Err(_) => continue,
-                        }
+            for key in response.contents.unwrap_or_default().into_iter().filter_map(|object| object.key) {
+                if indexed {
+                    // Entries of nested repositories share the prefix
+                    if let Some(digest) = key.strip_prefix(&prefix).filter(|digest| !digest.contains('/')) {
+                        manifests.push(digest.to_string());
                     }
+                    continue;
+                }
+
+                // Without the index, each manifest has to be fetched to hash it
+                if let Some(data) = self.read_object(&key).await? {
+                    manifests.push(format!("sha256:{:x}", Sha256::digest(data)));
                 }
             }
 
@@ -522,69 +1180,60 @@ impl StorageBackend for S3Storage {
     }
 
     async fn get_manifest_metadata(&self, repo: &str, digest: &str) -> Result<ManifestMetadata> {
-        // For digest-based lookups, we need to find the manifest file
-        let prefix = format!("manifests/{}/", repo);
-        let mut continuation_token: Option<String> = None;
-
-        loop {
-            let mut request = self
-                .client
-                .list_objects_v2()
-                .bucket(&self.bucket)
-                .prefix(&prefix);
-
-            if let Some(token) = continuation_token {
-                request = request.continuation_token(token);
+        let indexed = self.head_existing_object(&self.manifest_digest_key(repo, digest)).await?;
+        let head_response = match indexed {
+            Some(response) => response,
+            None if self.digest_index_ready.load(Ordering::Relaxed) => {
+                return Err(anyhow::anyhow!("Manifest not found: {}", digest));
+            }
+            // Manifests stored before the index existed have no entry until the backfill runs
+            None => {
+                let key = self
+                    .find_manifest_by_scan(repo, digest)
+                    .await?
+                    .map(|(key, _)| key)
+                    .ok_or_else(|| anyhow::anyhow!("Manifest not found: {}", digest))?;
+                self.head_existing_object(&key)
+                    .await?
+                    .ok_or_else(|| anyhow::anyhow!("Manifest not found: {}", digest))?
             }
+        };
 
-            let response = request.send().await?;
+        let size = head_response.content_length.unwrap_or(0) as u64;
+        let created_at = head_response.last_modified
+            .map(|dt| chrono::DateTime::from_timestamp(dt.secs(), dt.subsec_nanos()).unwrap_or_else(Utc::now))
+            .unwrap_or_else(Utc::now);
 
-            if let Some(contents) = response.contents {
-                for object in contents {
-                    if let Some(key) = object.key {
-                        // Check if this file's digest matches
-                        if let Ok(obj_response) = self.client
-                            .get_object()
-                            .bucket(&self.bucket)
-                            .key(&key)
-                            .send()
-                            .await {
-                            let body = obj_response.body.collect().await?;
-                            let file_digest = format!("sha256:{:x}", Sha256::digest(&body.into_bytes()));
-
-                            if file_digest == digest {
-                                let head_response = self
-                                    .client
-                                    .head_object()
-                                    .bucket(&self.bucket)
-                                    .key(&key)
-                                    .send()
-                                    .await?;
-
-                                let size = head_response.content_length.unwrap_or(0) as u64;
-                                let created_at = head_response.last_modified
-                                    .map(|dt| chrono::DateTime::from_timestamp(dt.secs(), dt.subsec_nanos()).unwrap_or_else(Utc::now))
-                                    .unwrap_or_else(Utc::now);
-
-                                return Ok(ManifestMetadata { size, created_at });
-                            }
-                        }
-                    }
-                }
-            }
+        let media_type = head_response.content_type;
 
-            if !response.is_truncated.unwrap_or(false) {
-                break;
-            }
+        Ok(ManifestMetadata { size, created_at, media_type })
+    }
 
-            continuation_token = response.next_continuation_token;
+    async fn get_manifest_by_digest(&self, repo: &str, digest: &str) -> Result<Bytes> {
+        if let Some(data) = self.read_object(&self.manifest_digest_key(repo, digest)).await? {
+            return Ok(data);
+        }
+        if self.digest_index_ready.load(Ordering::Relaxed) {
+            return Err(anyhow::anyhow!("Manifest not found: {}", digest));
         }
 
-        Err(anyhow::anyhow!("Manifest not found: {}", digest))
+        // Manifests stored before the index existed have no entry until the backfill runs
+        self.find_manifest_by_scan(repo, digest)
+            .await?
+            .map(|(_, data)| data)
+            .ok_or_else(|| anyhow::anyhow!("Manifest not found: {}", digest))
     }
 
-    async fn get_manifest_by_digest(&self, repo: &str, digest: &str) -> Result<Bytes> {
-        let prefix = format!("manifests/{}/", repo);
+    async fn get_manifest_digest(&self, repo: &str, reference: &str) -> Result<String> {
+        let manifest_data = self.get_manifest(repo, reference).await?
+            .ok_or_else(|| anyhow::anyhow!("Manifest not found: {}/{}", repo, reference))?;
+
+        let digest = format!("sha256:{:x}", Sha256::digest(&manifest_data));
+        Ok(digest)
+    }
+
+    async fn rebuild_manifest_index(&self) -> Result<usize> {
+        let mut indexed = HashSet::new();
         let mut continuation_token: Option<String> = None;
 
         loop {
@@ -592,7 +1241,7 @@ impl StorageBackend for S3Storage {
                 .client
                 .list_objects_v2()
                 .bucket(&self.bucket)
-                .prefix(&prefix);
+                .prefix("manifests/");
 
             if let Some(token) = continuation_token {
                 request = request.continuation_token(token);
@@ -600,25 +1249,30 @@ impl StorageBackend for S3Storage {
 
             let response = request.send().await?;
 
-            if let Some(contents) = response.contents {
-                for object in contents {
-                    if let Some(key) = object.key {
-                        // Check if this file's digest matches
-                        if let Ok(obj_response) = self.client
-                            .get_object()
-                            .bucket(&self.bucket)
-                            .key(&key)
-                            .send()
-                            .await {
-                            let body = obj_response.body.collect().await?;
-                            let manifest_data = body.into_bytes();
-                            let file_digest = format!("sha256:{:x}", Sha256::digest(&manifest_data));
-
-                            if file_digest == digest {
-                                return Ok(manifest_data.into());
-                            }
-                        }
-                    }
+            for key in response.contents.unwrap_or_default().into_iter().filter_map(|object| object.key) {
+                // Repository names may contain slashes, references never do
+                let Some((repo, reference)) = key.strip_prefix("manifests/").and_then(|path| path.rsplit_once('/')) else {
+                    continue;
+                };
+
+                let object = self.client.get_object().bucket(&self.bucket).key(&key).send().await?;
+                let media_type = object.content_type.clone();
+                let data = object.body.collect().await?.into_bytes();
+                let digest = format!("sha256:{:x}", Sha256::digest(&data));
+
+                // Tags pushed before digest -> tag markers existed have none
+                if !is_digest_reference(reference) {
+                    self.client
+                        .put_object()
+                        .bucket(&self.bucket)
+                        .key(self.manifest_tag_marker_key(repo, &digest, reference))
+                        .body(ByteStream::from_static(b""))
+                        .send()
+                        .await?;
+                }
+
+                if indexed.insert((repo.to_string(), digest.clone())) {
+                    self.put_digest_index(repo, &digest, data, media_type.as_deref()).await?;
                 }
             }
 
@@ -629,14 +1283,23 @@ impl StorageBackend for S3Storage {
             continuation_token = response.next_continuation_token;
         }
 
-        Err(anyhow::anyhow!("Manifest not found: {}", digest))
-    }
-
-    async fn get_manifest_digest(&self, repo: &str, reference: &str) -> Result<String> {
-        let manifest_data = self.get_manifest(repo, reference).await?
-            .ok_or_else(|| anyhow::anyhow!("Manifest not found: {}/{}", repo, reference))?;
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(DIGEST_INDEX_MARKER_KEY)
+            .body(ByteStream::from_static(b""))
+            .send()
+            .await?;
+        self.digest_index_ready.store(true, Ordering::Relaxed);
 
-        let digest = format!("sha256:{:x}", Sha256::digest(&manifest_data));
-        Ok(digest)
+        info!("Built S3 manifest digest index ({} entries)", indexed.len());
+        Ok(indexed.len())
     }
-}
\ No newline at end of file
+}
+
+/// Exponential backoff with full jitter: a random delay up to the base doubled
+/// for each earlier attempt
+fn backoff_delay(base: Duration, attempt: u32) -> Duration {
+    let ceiling = base.saturating_mul(1 << attempt.saturating_sub(1).min(16)).min(MAX_RETRY_BACKOFF);
+    ceiling.mul_f64(rand::random::<f64>())
+}
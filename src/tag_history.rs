@@ -0,0 +1,94 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::debug;
+
+use crate::storage::StorageBackend;
+
+/// What moved a tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagAction {
+    Push,
+    Delete,
+    Rollback,
+}
+
+/// One change to the digest a tag points at
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagRevision {
+    pub timestamp: DateTime<Utc>,
+    pub action: TagAction,
+    /// None when the tag was created
+    pub old_digest: Option<String>,
+    /// None when the tag was deleted
+    pub new_digest: Option<String>,
+    pub user: Option<String>,
+}
+
+/// Append-only log of tag changes, one JSON line per revision, kept through the
+/// storage backend under `history/<repository>/<tag>`.
+///
+/// Appending reads and rewrites the whole log, so callers hold the repository
+/// lock while recording.
+pub struct TagHistory {
+    storage: Arc<dyn StorageBackend>,
+}
+
+impl TagHistory {
+    pub fn new(storage: Arc<dyn StorageBackend>) -> Self {
+        Self { storage }
+    }
+
+    fn key(repository: &str, tag: &str) -> String {
+        format!("history/{}/{}", repository, tag)
+    }
+
+    /// Record a tag change; changes that leave the tag where it was aren't recorded
+    pub async fn record(
+        &self,
+        repository: &str,
+        tag: &str,
+        action: TagAction,
+        old_digest: Option<String>,
+        new_digest: Option<String>,
+        user: Option<&str>,
+    ) -> Result<()> {
+        if old_digest == new_digest {
+            return Ok(());
+        }
+
+        let revision = TagRevision {
+            timestamp: Utc::now(),
+            action,
+            old_digest,
+            new_digest,
+            user: user.map(str::to_string),
+        };
+
+        let key = Self::key(repository, tag);
+        let mut log = self.storage.get_blob(&key).await?.map(|data| data.to_vec()).unwrap_or_default();
+        serde_json::to_writer(&mut log, &revision)?;
+        log.push(b'\n');
+        self.storage.put_blob(&key, log.into()).await?;
+
+        debug!(
+            "Recorded {:?} of {}:{} ({:?} -> {:?})",
+            revision.action, repository, tag, revision.old_digest, revision.new_digest
+        );
+        Ok(())
+    }
+
+    /// All revisions of a tag, oldest first
+    pub async fn revisions(&self, repository: &str, tag: &str) -> Result<Vec<TagRevision>> {
+        let Some(data) = self.storage.get_blob(&Self::key(repository, tag)).await? else {
+            return Ok(Vec::new());
+        };
+
+        data.split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| Ok(serde_json::from_slice(line)?))
+            .collect()
+    }
+}
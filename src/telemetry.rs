@@ -0,0 +1,168 @@
+use anyhow::Result;
+use axum::http::{HeaderMap, Request};
+use opentelemetry::{propagation::Extractor, trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    runtime,
+    trace::{Sampler, Tracer, TracerProvider},
+    Resource,
+};
+use std::time::{Duration, Instant};
+use tower_http::trace::{DefaultMakeSpan, MakeSpan};
+use tracing::{info_span, warn, Span, Subscriber};
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+use crate::config::TelemetryConfig;
+
+/// OTLP trace export; dropping it flushes any spans still buffered
+pub struct Telemetry {
+    provider: TracerProvider,
+    tracer: Tracer,
+    slow_span_threshold: Option<Duration>,
+}
+
+impl Telemetry {
+    /// Set up the exporter when telemetry is configured and enabled
+    pub fn from_config(config: Option<&TelemetryConfig>) -> Result<Option<Self>> {
+        let Some(config) = config.filter(|config| config.enabled) else {
+            return Ok(None);
+        };
+
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(&config.endpoint)
+            .build()?;
+
+        // Keep the caller's decision for propagated traces; sample new ones by ratio
+        let sampler = Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(config.sample_rate.clamp(0.0, 1.0))));
+
+        let provider = TracerProvider::builder()
+            .with_batch_exporter(exporter, runtime::Tokio)
+            .with_sampler(sampler)
+            .with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                config.service_name.clone(),
+            )]))
+            .build();
+        let tracer = provider.tracer("drift");
+
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+        opentelemetry::global::set_tracer_provider(provider.clone());
+
+        Ok(Some(Self {
+            provider,
+            tracer,
+            slow_span_threshold: (config.slow_span_threshold_ms > 0)
+                .then(|| Duration::from_millis(config.slow_span_threshold_ms)),
+        }))
+    }
+
+    pub fn layer<S>(&self) -> OpenTelemetryLayer<S, Tracer>
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+    {
+        tracing_opentelemetry::layer().with_tracer(self.tracer.clone())
+    }
+
+    pub fn slow_span_layer(&self) -> Option<SlowSpanLayer> {
+        self.slow_span_threshold.map(SlowSpanLayer::new)
+    }
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Err(e) = self.provider.shutdown() {
+            eprintln!("Failed to flush traces: {}", e);
+        }
+    }
+}
+
+/// Warns about spans that stay open longer than a threshold
+pub struct SlowSpanLayer {
+    threshold: Duration,
+}
+
+struct SpanStart(Instant);
+
+impl SlowSpanLayer {
+    pub fn new(threshold: Duration) -> Self {
+        Self { threshold }
+    }
+}
+
+impl<S> Layer<S> for SlowSpanLayer
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    fn on_new_span(&self, _attrs: &tracing::span::Attributes<'_>, id: &tracing::span::Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanStart(Instant::now()));
+        }
+    }
+
+    fn on_close(&self, id: tracing::span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(elapsed) = span.extensions().get::<SpanStart>().map(|start| start.0.elapsed()) else {
+            return;
+        };
+
+        if elapsed > self.threshold {
+            warn!(
+                "Slow span {}::{} took {}ms (threshold {}ms)",
+                span.metadata().target(),
+                span.name(),
+                elapsed.as_millis(),
+                self.threshold.as_millis()
+            );
+        }
+    }
+}
+
+/// Request spans that continue a trace started by the client's `traceparent` header
+#[derive(Debug, Clone)]
+pub struct RequestSpan {
+    propagate: bool,
+}
+
+impl RequestSpan {
+    pub fn new(propagate: bool) -> Self {
+        Self { propagate }
+    }
+}
+
+impl<B> MakeSpan<B> for RequestSpan {
+    fn make_span(&mut self, request: &Request<B>) -> Span {
+        if !self.propagate {
+            return DefaultMakeSpan::new().make_span(request);
+        }
+
+        let span = info_span!(
+            "request",
+            otel.kind = "server",
+            method = %request.method(),
+            uri = %request.uri(),
+            version = ?request.version(),
+        );
+        let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+            propagator.extract(&HeaderExtractor(request.headers()))
+        });
+        span.set_parent(parent);
+        span
+    }
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
@@ -0,0 +1,230 @@
+use anyhow::{bail, Context, Result};
+use axum::{middleware::AddExtension, Extension};
+use axum_server::{
+    accept::Accept,
+    tls_rustls::{RustlsAcceptor, RustlsConfig},
+};
+use futures::future::BoxFuture;
+use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::RootCertStore;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::server::TlsStream;
+use tower::Layer;
+use tracing::{info, warn};
+
+use crate::config::TlsConfig;
+
+/// Protocols offered during the handshake, most preferred first
+const ALPN_PROTOCOLS: [&[u8]; 2] = [b"h2", b"http/1.1"];
+
+/// Attached to every request received over TLS
+#[derive(Debug, Clone)]
+pub struct TlsConnection {
+    /// The certificate the client presented, already verified against `client_ca_path`
+    pub client_certificate: Option<ClientCertificate>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClientCertificate {
+    /// Distinguished name, e.g. `CN=ci-runner,O=Example`
+    pub subject: String,
+    pub common_name: Option<String>,
+}
+
+impl ClientCertificate {
+    fn from_der(der: &CertificateDer<'_>) -> Option<Self> {
+        let (_, certificate) = x509_parser::parse_x509_certificate(der.as_ref()).ok()?;
+        let subject = certificate.subject();
+        Some(Self {
+            subject: subject.to_string(),
+            common_name: subject
+                .iter_common_name()
+                .next()
+                .and_then(|name| name.as_str().ok())
+                .map(str::to_string),
+        })
+    }
+}
+
+/// TLS for one listener. The certificate is swapped in place when its files
+/// change or on SIGHUP, so renewals don't need a restart.
+pub struct TlsTermination {
+    config: TlsConfig,
+    rustls: RustlsConfig,
+}
+
+impl TlsTermination {
+    /// Load the certificate, key and client CA, failing if they don't parse or don't match
+    pub fn new(config: &TlsConfig) -> Result<Self> {
+        let server_config = load_server_config(config)?;
+        info!(
+            "Loaded TLS certificate {}{}",
+            config.cert_path,
+            if config.client_ca_path.is_some() { " with client certificate authentication" } else { "" }
+        );
+
+        Ok(Self {
+            config: config.clone(),
+            rustls: RustlsConfig::from_config(Arc::new(server_config)),
+        })
+    }
+
+    /// Acceptor for `axum_server`, using the current certificate
+    pub fn acceptor(&self) -> ClientCertAcceptor {
+        ClientCertAcceptor {
+            inner: RustlsAcceptor::new(self.rustls.clone()),
+        }
+    }
+
+    /// Reload the certificate when any of its files change, or on SIGHUP
+    pub fn spawn_reload(&self) {
+        let config = self.config.clone();
+        let rustls = self.rustls.clone();
+
+        tokio::spawn(async move {
+            let paths: Vec<&Path> = [Some(&config.cert_path), Some(&config.key_path), config.client_ca_path.as_ref()]
+                .into_iter()
+                .flatten()
+                .map(Path::new)
+                .collect();
+            let modified = |paths: &[&Path]| -> Vec<Option<SystemTime>> {
+                paths
+                    .iter()
+                    .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+                    .collect()
+            };
+            let mut last_modified = modified(&paths);
+            let mut interval = tokio::time::interval(Duration::from_secs(config.reload_interval_secs.max(1)));
+
+            #[cfg(unix)]
+            let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+                Ok(signal) => Some(signal),
+                Err(e) => {
+                    warn!("Failed to listen for SIGHUP, TLS certificates reload on change only: {}", e);
+                    None
+                }
+            };
+
+            loop {
+                #[cfg(unix)]
+                let forced = tokio::select! {
+                    _ = interval.tick() => false,
+                    Some(()) = async {
+                        match hangup.as_mut() {
+                            Some(signal) => signal.recv().await,
+                            None => std::future::pending().await,
+                        }
+                    } => true,
+                };
+                #[cfg(not(unix))]
+                let forced = {
+                    interval.tick().await;
+                    false
+                };
+
+                let current = modified(&paths);
+                if !forced && (current.contains(&None) || current == last_modified) {
+                    continue;
+                }
+                last_modified = current;
+
+                // Keep serving the previous certificate if the new files are invalid,
+                // e.g. when the key has been written but the certificate not yet
+                match load_server_config(&config) {
+                    Ok(server_config) => {
+                        rustls.reload_from_config(Arc::new(server_config));
+                        info!("Reloaded TLS certificate {}", config.cert_path);
+                    }
+                    Err(e) => warn!("Failed to reload TLS certificate {}: {:#}", config.cert_path, e),
+                }
+            }
+        });
+    }
+}
+
+/// Terminates TLS and attaches the connection's [`TlsConnection`] to each of its requests
+#[derive(Clone)]
+pub struct ClientCertAcceptor {
+    inner: RustlsAcceptor,
+}
+
+impl<I, S> Accept<I, S> for ClientCertAcceptor
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Send + 'static,
+{
+    type Stream = TlsStream<I>;
+    type Service = AddExtension<S, TlsConnection>;
+    type Future = BoxFuture<'static, io::Result<(Self::Stream, Self::Service)>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let acceptor = self.inner.clone();
+        Box::pin(async move {
+            let (stream, service) = acceptor.accept(stream, service).await?;
+            let client_certificate = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|chain| chain.first())
+                .and_then(ClientCertificate::from_der);
+            Ok((stream, Extension(TlsConnection { client_certificate }).layer(service)))
+        })
+    }
+}
+
+fn load_server_config(config: &TlsConfig) -> Result<rustls::ServerConfig> {
+    let certificates = CertificateDer::pem_file_iter(&config.cert_path)
+        .and_then(|certificates| certificates.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("Failed to read certificate {}", config.cert_path))?;
+    if certificates.is_empty() {
+        bail!("No certificates found in {}", config.cert_path);
+    }
+
+    let key = PrivateKeyDer::from_pem_file(&config.key_path)
+        .with_context(|| format!("Failed to read private key {}", config.key_path))?;
+
+    let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+    let builder = rustls::ServerConfig::builder_with_provider(provider.clone()).with_safe_default_protocol_versions()?;
+
+    let builder = match &config.client_ca_path {
+        Some(ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for certificate in CertificateDer::pem_file_iter(ca_path)
+                .with_context(|| format!("Failed to read client CA {}", ca_path))?
+            {
+                let certificate = certificate.with_context(|| format!("Failed to read client CA {}", ca_path))?;
+                roots
+                    .add(certificate)
+                    .with_context(|| format!("Invalid client CA certificate in {}", ca_path))?;
+            }
+            if roots.is_empty() {
+                bail!("No certificates found in client CA {}", ca_path);
+            }
+
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+            let verifier = if config.require_client_cert {
+                verifier
+            } else {
+                // Clients without a certificate fall back to the other auth methods
+                verifier.allow_unauthenticated()
+            };
+            builder.with_client_cert_verifier(verifier.build()?)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut server_config = builder.with_single_cert(certificates, key).with_context(|| {
+        format!(
+            "Certificate {} can't be used with key {}; check that the key belongs to the certificate",
+            config.cert_path, config.key_path
+        )
+    })?;
+    server_config.alpn_protocols = ALPN_PROTOCOLS.iter().map(|protocol| protocol.to_vec()).collect();
+
+    Ok(server_config)
+}
@@ -3,10 +3,11 @@ use axum::{
     http::StatusCode,
     response::{Html, IntoResponse},
     routing::get,
-    Router,
+    Extension, Router,
 };
 use serde::{Deserialize, Serialize};
 
+use crate::auth::User;
 use crate::server::AppState;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -48,7 +49,15 @@ async fn settings() -> impl IntoResponse {
     Html(include_str!("templates/settings.html"))
 }
 
-async fn api_stats(State(_state): State<AppState>) -> impl IntoResponse {
+async fn api_stats(
+    State(_state): State<AppState>,
+    user: Option<Extension<User>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    // UI API calls are authenticated by the session cookie
+    if user.is_none() {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
     let stats = RegistryStats {
         total_repositories: 42,
         total_images: 156,
@@ -57,5 +66,5 @@ async fn api_stats(State(_state): State<AppState>) -> impl IntoResponse {
         active_organizations: 8,
     };
 
-    axum::Json(stats)
+    Ok(axum::Json(stats))
 }
\ No newline at end of file
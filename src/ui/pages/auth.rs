@@ -8,7 +8,7 @@ pub fn LoginForm() -> impl IntoView {
     let (is_loading, set_is_loading) = create_signal(false);
     let (show_password, set_show_password) = create_signal(false);
     let (login_method, set_login_method) = create_signal("credentials".to_string());
-    let (error_message, set_error_message) = create_signal(Option::<String>::None);
+    let (error_message, set_error_message) = create_signal(login_error_from_query());
 
     let handle_credentials_login = move |_| {
         set_is_loading.set(true);
@@ -261,4 +261,23 @@ pub fn LoginForm() -> impl IntoView {
             </div>
         </div>
     }
-}
\ No newline at end of file
+}
+
+/// Error passed back by the OAuth callback as `/login?error=...`
+fn login_error_from_query() -> Option<String> {
+    let search = web_sys::window()?.location().search().ok()?;
+    let error = search
+        .trim_start_matches('?')
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("error="))?;
+    let error = urlencoding::decode(error).ok()?;
+
+    Some(match error.as_ref() {
+        "state_mismatch" => "Your sign-in attempt expired. Please try again.".to_string(),
+        "access_denied" => "Access was denied.".to_string(),
+        "oauth_disabled" => "Single sign-on is not enabled on this registry.".to_string(),
+        "unknown_provider" => "That sign-in provider is not supported.".to_string(),
+        "provider_error" => "The sign-in provider returned an error. Please try again.".to_string(),
+        other => format!("Sign-in failed: {}", other),
+    })
+}
@@ -0,0 +1,221 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    routing::get,
+    Router,
+};
+use common::{
+    basic_auth, body_json, image_manifest, sha256_digest, test_config, TestRegistry, OCI_MANIFEST, PASSWORD, USERNAME,
+};
+use drift::auth::User;
+use drift::config::AuthMode;
+use drift::rbac::{Role, RoleScope};
+use drift::tls::{ClientCertificate, TlsConnection};
+use tower::ServiceExt;
+
+fn upload() -> Request<Body> {
+    Request::post("/v2/app/blobs/uploads/").body(Body::empty()).unwrap()
+}
+
+fn manifest_put(manifest: &[u8]) -> Request<Body> {
+    Request::put("/v2/app/manifests/v2")
+        .header(header::CONTENT_TYPE, OCI_MANIFEST)
+        .body(Body::from(manifest.to_vec()))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn pull_only_users_pull_but_cannot_push() {
+    let registry = TestRegistry::new().await;
+    let (manifest, digest) = registry.push_image("app", "v1", b"layer").await;
+    let pull = ["repository:app:pull"];
+
+    for path in ["/v2/app/manifests/v1".to_string(), format!("/v2/app/blobs/{}", sha256_digest(b"layer"))] {
+        let request = Request::get(&path).body(Body::empty()).unwrap();
+        assert_eq!(registry.send_with_scopes(request, &pull).await.status(), StatusCode::OK, "{}", path);
+    }
+
+    for request in [upload(), manifest_put(&manifest)] {
+        let response = registry.send_with_scopes(request, &pull).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(body_json(response).await["errors"][0]["code"], "DENIED");
+    }
+    let request = Request::delete(format!("/v2/app/manifests/{}", digest)).body(Body::empty()).unwrap();
+    assert_eq!(registry.send_with_scopes(request, &pull).await.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn push_scopes_for_another_repository_grant_nothing_here() {
+    let registry = TestRegistry::new().await;
+    let config = br#"{"os":"linux"}"#;
+    let manifest = image_manifest(&sha256_digest(config), config.len(), &[]);
+    let other = ["repository:other/*:pull", "repository:other/*:push"];
+
+    let pull = Request::get("/v2/app/manifests/v1").body(Body::empty()).unwrap();
+    for request in [upload(), manifest_put(&manifest), pull] {
+        assert_eq!(registry.send_with_scopes(request, &other).await.status(), StatusCode::FORBIDDEN);
+    }
+}
+
+#[tokio::test]
+async fn anonymous_clients_pull_only_when_allowed() {
+    let mut config = test_config();
+    for allowed in [false, true] {
+        config.registry.anonymous_pull = allowed;
+        let registry = TestRegistry::with_config(config.clone()).await;
+        registry.push_image("app", "v1", b"layer").await;
+
+        let response = registry.send(Request::get("/v2/app/manifests/v1").body(Body::empty()).unwrap()).await;
+        let expected = if allowed { StatusCode::OK } else { StatusCode::UNAUTHORIZED };
+        assert_eq!(response.status(), expected);
+
+        // Pushing always needs credentials
+        let response = registry.send(upload()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(response.headers().contains_key(header::WWW_AUTHENTICATE));
+    }
+}
+
+#[tokio::test]
+async fn robots_and_certificate_users_are_authorized_by_scope_under_rbac() {
+    let mut config = test_config();
+    config.rbac.as_mut().unwrap().enabled = true;
+    let registry = TestRegistry::with_config(config).await;
+    let scopes = vec!["repository:ci/*:pull".to_string(), "repository:ci/*:push".to_string()];
+    let (robot, secret) = registry.state.robots.create("builder", None, scopes, None, "admin").await.unwrap();
+    let robot = basic_auth(&robot.username(), &secret);
+
+    let mut request = Request::post("/v2/ci/app/blobs/uploads/").body(Body::empty()).unwrap();
+    request.headers_mut().insert(header::AUTHORIZATION, robot.parse().unwrap());
+    assert_eq!(registry.send(request).await.status(), StatusCode::ACCEPTED);
+    let mut request = Request::post("/v2/other/blobs/uploads/").body(Body::empty()).unwrap();
+    request.headers_mut().insert(header::AUTHORIZATION, robot.parse().unwrap());
+    assert_eq!(registry.send(request).await.status(), StatusCode::FORBIDDEN);
+
+    // The token endpoint grants robots what their scopes allow
+    let request = Request::get("/v2/token?service=drift&scope=repository:ci/app:pull,push")
+        .header(header::AUTHORIZATION, robot)
+        .body(Body::empty())
+        .unwrap();
+    let response = registry.send(request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let token = body_json(response).await["token"].as_str().unwrap().to_string();
+    let user = registry.state.auth.validate_token(&token).unwrap().unwrap();
+    assert_eq!(user.scopes, ["repository:ci/app:pull", "repository:ci/app:push"]);
+
+    let mut request = Request::post("/v2/ci/app/blobs/uploads/").body(Body::empty()).unwrap();
+    request.extensions_mut().insert(TlsConnection {
+        client_certificate: Some(ClientCertificate {
+            subject: "CN=ci-runner,O=Example".to_string(),
+            common_name: Some("ci-runner".to_string()),
+        }),
+    });
+    assert_eq!(registry.send(request).await.status(), StatusCode::ACCEPTED);
+
+    // Users RBAC doesn't know are still denied
+    assert_eq!(registry.send_authenticated(upload()).await.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn blobs_are_mounted_only_from_repositories_rbac_lets_the_user_pull() {
+    let mut config = test_config();
+    let rbac_config = config.rbac.as_mut().unwrap();
+    rbac_config.enabled = true;
+    rbac_config.default_role = "pusher".to_string();
+    let registry = TestRegistry::with_config(config).await;
+    let rbac = registry.state.rbac.as_ref().unwrap();
+    rbac.create_role(Role {
+        id: "pusher".to_string(),
+        name: "Pusher".to_string(),
+        description: String::new(),
+        permissions: ["repository.push".to_string()].into_iter().collect(),
+        parent_role: None,
+        scope: RoleScope::Global,
+        priority: 10,
+        system_role: false,
+    })
+    .await
+    .unwrap();
+    rbac.provision_user(USERNAME, "admin@example.com", "Admin").await.unwrap();
+
+    // Scopes allow pulling from the source, but RBAC doesn't
+    let digest = registry.push_blob("source", b"layer").await;
+    let mount = || {
+        Request::post(format!("/v2/app/blobs/uploads/?mount={}&from=source", digest)).body(Body::empty()).unwrap()
+    };
+    let response = registry.send_authenticated(mount()).await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    assert!(response.headers()[header::LOCATION].to_str().unwrap().starts_with("/v2/app/blobs/uploads/"));
+
+    rbac.assign_role(USERNAME, "developer").await.unwrap();
+    let response = registry.send_authenticated(mount()).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers()[header::LOCATION], format!("/v2/app/blobs/{}", digest).as_str());
+}
+
+#[tokio::test]
+async fn bearer_tokens_not_issued_by_the_token_endpoint_are_checked_against_rbac() {
+    let mut config = test_config();
+    config.auth.mode = AuthMode::Token;
+    let rbac_config = config.rbac.as_mut().unwrap();
+    rbac_config.enabled = true;
+    rbac_config.default_role = "viewer".to_string();
+    let registry = TestRegistry::with_config(config).await;
+    registry.state.rbac.as_ref().unwrap().provision_user(USERNAME, "admin@example.com", "Admin").await.unwrap();
+
+    // A login token carries the user's unfiltered scopes, so RBAC still applies
+    let response = registry.send_with_scopes(upload(), &["repository:*:pull", "repository:*:push"]).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // The token endpoint already narrowed its grant through RBAC
+    let request = Request::get("/v2/token?service=drift&scope=repository:app:pull,push")
+        .header(header::AUTHORIZATION, basic_auth(USERNAME, PASSWORD))
+        .body(Body::empty())
+        .unwrap();
+    let response = registry.send(request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let token = body_json(response).await["token"].as_str().unwrap().to_string();
+    let user = registry.state.auth.validate_token(&token).unwrap().unwrap();
+    assert_eq!(user.scopes, ["repository:app:pull"]);
+    let request = Request::get("/v2/app/tags/list")
+        .header(header::AUTHORIZATION, format!("Bearer {}", token))
+        .body(Body::empty())
+        .unwrap();
+    assert_ne!(registry.send(request).await.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn session_admin_routes_need_the_registry_scope_without_rbac() {
+    let registry = TestRegistry::new().await;
+    // The UI's admin routes see only what the session cookie attached
+    let router = |user: Option<User>| {
+        Router::new()
+            .route("/admin", get(|| async { StatusCode::OK }))
+            .layer(axum::middleware::from_fn_with_state(
+                registry.state.clone(),
+                drift::api::middleware::require_registry_admin,
+            ))
+            .layer(axum::middleware::from_fn(move |mut request: Request<Body>, next: Next| {
+                if let Some(user) = user.clone() {
+                    request.extensions_mut().insert(user);
+                }
+                next.run(request)
+            }))
+            .with_state(registry.state.clone())
+    };
+    let user = |scopes: &[&str]| User {
+        username: USERNAME.to_string(),
+        roles: vec!["user".to_string()],
+        scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+    };
+    let status = |user: Option<User>| async {
+        router(user).oneshot(Request::get("/admin").body(Body::empty()).unwrap()).await.unwrap().status()
+    };
+
+    assert_eq!(status(None).await, StatusCode::FORBIDDEN);
+    assert_eq!(status(Some(user(&["repository:*:pull", "repository:*:push"]))).await, StatusCode::FORBIDDEN);
+    assert_eq!(status(Some(user(&["registry:*"]))).await, StatusCode::OK);
+}
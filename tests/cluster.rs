@@ -0,0 +1,92 @@
+//! Cluster nodes gossiping with each other over their real endpoints on loopback
+
+use drift::cluster::{ClusterService, NodeInfo, NodeStatus};
+use drift::config::{ClusterConfig, Config};
+use drift::storage::memory::MemoryStorage;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+const SECRET: &str = "cluster-secret";
+
+/// A node on an ephemeral loopback port, joining through `seed_nodes`
+async fn start_node(node_id: &str, seed_nodes: &[&str], secret: &str) -> ClusterService {
+    let config = ClusterConfig {
+        enabled: true,
+        node_id: node_id.to_string(),
+        bind_address: "127.0.0.1:0".to_string(),
+        seed_nodes: seed_nodes.iter().map(|seed| seed.to_string()).collect(),
+        secret: Some(secret.to_string()),
+        heartbeat_interval_seconds: 1,
+        health_check_interval_seconds: 1,
+        ..Config::default().cluster.unwrap()
+    };
+    let storage = Arc::new(MemoryStorage::new(None));
+    ClusterService::new(config, storage, CancellationToken::new()).await.unwrap()
+}
+
+async fn own_entry(node: &ClusterService, node_id: &str) -> NodeInfo {
+    node.get_nodes().await.into_iter().find(|node| node.id == node_id).unwrap()
+}
+
+async fn node_ids(node: &ClusterService) -> Vec<String> {
+    let mut ids: Vec<String> = node.get_nodes().await.into_iter().map(|node| node.id).collect();
+    ids.sort();
+    ids
+}
+
+#[tokio::test]
+async fn nodes_discover_each_other_through_a_seed() {
+    let first = start_node("node-a", &[], SECRET).await;
+    let seed = own_entry(&first, "node-a").await.address;
+    let second = start_node("node-b", &[&seed], SECRET).await;
+
+    // Joining exchanges node lists with the seed straight away
+    assert_eq!(node_ids(&first).await, ["node-a", "node-b"]);
+    assert_eq!(node_ids(&second).await, ["node-a", "node-b"]);
+
+    // Heartbeats keep arriving, so each sees the other as healthy
+    let healthy = |node: &NodeInfo, id: &str| node.id == id && node.status == NodeStatus::Healthy;
+    tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            let first_sees = first.get_nodes().await.iter().any(|node| healthy(node, "node-b"));
+            let second_sees = second.get_nodes().await.iter().any(|node| healthy(node, "node-a"));
+            if first_sees && second_sees {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("nodes never saw each other as healthy");
+}
+
+#[tokio::test]
+async fn cluster_requests_need_the_cluster_secret() {
+    let first = start_node("node-a", &[], SECRET).await;
+    let seed = own_entry(&first, "node-a").await.address;
+
+    // A node with another secret can't join
+    let outsider = start_node("node-x", &[&seed], "another-secret").await;
+    assert_eq!(node_ids(&first).await, ["node-a"]);
+    assert_eq!(node_ids(&outsider).await, ["node-x"]);
+
+    // Neither can anyone else reach the cluster endpoints
+    let client = reqwest::Client::new();
+    for path in ["/cluster/gossip", "/cluster/vote", "/cluster/leader", "/cluster/replicate"] {
+        let request = client.post(format!("http://{}{}", seed, path)).json(&serde_json::json!({}));
+        let response = request.send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED, "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn clustering_needs_a_secret() {
+    let config = ClusterConfig {
+        enabled: true,
+        bind_address: "127.0.0.1:0".to_string(),
+        ..Config::default().cluster.unwrap()
+    };
+    let storage = Arc::new(MemoryStorage::new(None));
+    assert!(ClusterService::new(config, storage, CancellationToken::new()).await.is_err());
+}
@@ -0,0 +1,233 @@
+//! In-process registry for integration tests: the API router on top of memory
+//! storage, driven request by request without binding a socket.
+
+#![allow(dead_code)]
+
+pub mod s3;
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, Request, Response, StatusCode},
+    Router,
+};
+use base64::Engine;
+use drift::{
+    auth::User,
+    config::{Config, StorageType},
+    server::{AppState, Server},
+};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use tempfile::TempDir;
+use tokio_util::sync::CancellationToken;
+use tower::{Layer, ServiceExt};
+
+pub const USERNAME: &str = "admin";
+pub const PASSWORD: &str = "changeme";
+
+pub const OCI_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+pub const OCI_CONFIG: &str = "application/vnd.oci.image.config.v1+json";
+pub const OCI_LAYER: &str = "application/vnd.oci.image.layer.v1.tar+gzip";
+
+/// The default configuration, keeping everything in memory and without rate limits
+pub fn test_config() -> Config {
+    let mut config = Config::default();
+    config.storage.storage_type = StorageType::Memory;
+    config.storage.path = None;
+    config.registry.immutable_tags = Vec::new();
+    config.registry.min_age_days = 0;
+    config.registry.rate_limit_per_hour = 0;
+    config
+}
+
+pub fn sha256_digest(data: &[u8]) -> String {
+    format!("sha256:{:x}", Sha256::digest(data))
+}
+
+pub struct TestRegistry {
+    pub state: AppState,
+    pub shutdown: CancellationToken,
+    /// The API as `Server::api_router` builds it, without the path encoding `serve` adds
+    pub router: Router,
+    /// Root of the filesystem backend, removed when the registry is dropped
+    pub storage_dir: Option<TempDir>,
+}
+
+impl TestRegistry {
+    pub async fn new() -> Self {
+        Self::with_config(test_config()).await
+    }
+
+    pub async fn with_config(config: Config) -> Self {
+        let server = Server::new(config, "127.0.0.1:0", "127.0.0.1:0").await.unwrap();
+        let shutdown = CancellationToken::new();
+        let state = server.build_state(shutdown.clone()).await.unwrap();
+        let router = server.api_router(state.clone());
+        Self { state, shutdown, router, storage_dir: None }
+    }
+
+    /// The registry on the filesystem backend, rooted in a fresh temporary directory
+    pub async fn filesystem(mut config: Config) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        config.storage.storage_type = StorageType::Filesystem;
+        config.storage.path = Some(dir.path().to_string_lossy().into_owned());
+        let mut registry = Self::with_config(config).await;
+        registry.storage_dir = Some(dir);
+        registry
+    }
+
+    /// Shut the registry down and start it again on the same storage and config
+    pub async fn restart(mut self) -> Self {
+        let config = self.state.config.clone();
+        let storage_dir = self.storage_dir.take();
+        drop(self);
+        let mut registry = Self::with_config(config).await;
+        registry.storage_dir = storage_dir;
+        registry
+    }
+
+    /// Serve the registry over HTTP on an ephemeral loopback port until it is
+    /// dropped, returning its base URL
+    pub async fn serve(&self) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let app = axum::middleware::map_request(drift::api::registry::encode_repository_path).layer(self.router.clone());
+        let app = axum::ServiceExt::<Request<Body>>::into_make_service_with_connect_info::<SocketAddr>(app);
+        let shutdown = self.shutdown.clone();
+        tokio::spawn(async move { axum::serve(listener, app).with_graceful_shutdown(shutdown.cancelled_owned()).await });
+        url
+    }
+
+    /// Send a request as it would arrive from a client on the loopback interface
+    pub async fn send(&self, mut request: Request<Body>) -> Response<Body> {
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+        axum::middleware::map_request(drift::api::registry::encode_repository_path)
+            .layer(self.router.clone())
+            .oneshot(request)
+            .await
+            .unwrap()
+    }
+
+    /// Send a request carrying the admin's basic credentials
+    pub async fn send_authenticated(&self, mut request: Request<Body>) -> Response<Body> {
+        request.headers_mut().insert(header::AUTHORIZATION, basic_auth(USERNAME, PASSWORD).parse().unwrap());
+        self.send(request).await
+    }
+
+    /// Send a request carrying a bearer token for the admin with the given scopes
+    pub async fn send_with_scopes(&self, mut request: Request<Body>, scopes: &[&str]) -> Response<Body> {
+        let user = User {
+            username: USERNAME.to_string(),
+            roles: vec!["admin".to_string()],
+            scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+        };
+        let token = self.state.auth.generate_token(&user, 300).unwrap();
+        request.headers_mut().insert(header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+        self.send(request).await
+    }
+
+    pub async fn get(&self, path: &str) -> Response<Body> {
+        self.send_authenticated(Request::get(path).body(Body::empty()).unwrap()).await
+    }
+
+    pub async fn head(&self, path: &str) -> Response<Body> {
+        self.send_authenticated(Request::head(path).body(Body::empty()).unwrap()).await
+    }
+
+    pub async fn delete(&self, path: &str) -> Response<Body> {
+        self.send_authenticated(Request::delete(path).body(Body::empty()).unwrap()).await
+    }
+
+    /// Push a blob with a single POST and PUT, returning its digest
+    pub async fn push_blob(&self, repository: &str, data: &[u8]) -> String {
+        let digest = sha256_digest(data);
+        let location = self.start_upload(repository).await;
+
+        let response = self
+            .send_authenticated(
+                Request::put(format!("{}?digest={}", location, digest))
+                    .header(header::CONTENT_TYPE, "application/octet-stream")
+                    .body(Body::from(data.to_vec()))
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::CREATED, "blob upload to {} failed", repository);
+        digest
+    }
+
+    /// Open an upload session, returning its location
+    pub async fn start_upload(&self, repository: &str) -> String {
+        let response = self
+            .send_authenticated(
+                Request::post(format!("/v2/{}/blobs/uploads/", repository))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED, "starting an upload to {} failed", repository);
+        response.headers()[header::LOCATION].to_str().unwrap().to_string()
+    }
+
+    pub async fn put_manifest(&self, repository: &str, reference: &str, manifest: &[u8]) -> Response<Body> {
+        self.send_authenticated(
+            Request::put(format!("/v2/{}/manifests/{}", repository, reference))
+                .header(header::CONTENT_TYPE, OCI_MANIFEST)
+                .body(Body::from(manifest.to_vec()))
+                .unwrap(),
+        )
+        .await
+    }
+
+    /// Push a one-layer image under `tag`, returning the manifest and its digest
+    pub async fn push_image(&self, repository: &str, tag: &str, layer: &[u8]) -> (Vec<u8>, String) {
+        let config = json!({ "architecture": "amd64", "os": "linux", "layer": sha256_digest(layer) });
+        let config = serde_json::to_vec(&config).unwrap();
+        let config_digest = self.push_blob(repository, &config).await;
+        let layer_digest = self.push_blob(repository, layer).await;
+
+        let manifest = image_manifest(&config_digest, config.len(), &[(layer_digest, layer.len())]);
+        let response = self.put_manifest(repository, tag, &manifest).await;
+        assert_eq!(response.status(), StatusCode::CREATED, "pushing {}:{} failed", repository, tag);
+        let digest = sha256_digest(&manifest);
+        (manifest, digest)
+    }
+}
+
+impl Drop for TestRegistry {
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+    }
+}
+
+pub fn image_manifest(config_digest: &str, config_size: usize, layers: &[(String, usize)]) -> Vec<u8> {
+    let layers: Vec<_> = layers
+        .iter()
+        .map(|(digest, size)| json!({ "mediaType": OCI_LAYER, "digest": digest, "size": size }))
+        .collect();
+    serde_json::to_vec(&json!({
+        "schemaVersion": 2,
+        "mediaType": OCI_MANIFEST,
+        "config": { "mediaType": OCI_CONFIG, "digest": config_digest, "size": config_size },
+        "layers": layers,
+    }))
+    .unwrap()
+}
+
+pub fn basic_auth(username: &str, password: &str) -> String {
+    format!(
+        "Basic {}",
+        base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", username, password))
+    )
+}
+
+pub async fn body_bytes(response: Response<Body>) -> Vec<u8> {
+    axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec()
+}
+
+pub async fn body_json(response: Response<Body>) -> serde_json::Value {
+    serde_json::from_slice(&body_bytes(response).await).unwrap()
+}
@@ -0,0 +1,447 @@
+//! Minimal S3-compatible server for exercising the S3 backend: path-style
+//! object, listing and multipart operations kept in memory.
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{DefaultBodyLimit, Query, State},
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::Response,
+    Router,
+};
+use drift::config::S3Config;
+use std::collections::{BTreeMap, HashMap};
+use futures::StreamExt;
+use std::sync::{Arc, Mutex};
+
+pub const BUCKET: &str = "drift-test";
+
+#[derive(Clone, Default)]
+struct Object {
+    data: Bytes,
+    content_type: Option<String>,
+    modified: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Default)]
+struct Bucket {
+    objects: BTreeMap<String, Object>,
+    /// Parts of in-progress multipart uploads by upload id
+    uploads: HashMap<String, BTreeMap<i32, Bytes>>,
+    next_upload: u64,
+}
+
+/// A request the server received, by operation name as the SDK reports it
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub operation: &'static str,
+    pub key: String,
+    pub size: usize,
+}
+
+/// Requests to answer with a 503, or to cut off partway through the body,
+/// instead of serving them
+struct Failure {
+    method: Method,
+    key_prefix: String,
+    remaining: usize,
+    truncate: bool,
+}
+
+#[derive(Clone, Default)]
+pub struct MockS3 {
+    bucket: Arc<Mutex<Bucket>>,
+    requests: Arc<Mutex<Vec<Request>>>,
+    failures: Arc<Mutex<Vec<Failure>>>,
+}
+
+impl MockS3 {
+    /// Serve on an ephemeral loopback port, returning a config pointing at it
+    pub async fn start() -> (Self, S3Config) {
+        let mock = Self::default();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let app = Router::new()
+            .fallback(handle)
+            .layer(DefaultBodyLimit::disable())
+            .with_state(mock.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let config = S3Config {
+            endpoint,
+            region: "us-east-1".to_string(),
+            bucket: BUCKET.to_string(),
+            access_key: "test".to_string(),
+            secret_key: "test".to_string(),
+            path_style: true,
+            max_attempts: 3,
+            retry_base_delay_ms: 1,
+            connect_timeout_secs: 5,
+            read_timeout_secs: 5,
+            attempt_timeout_secs: 0,
+        };
+        (mock, config)
+    }
+
+    pub fn object(&self, key: &str) -> Option<Bytes> {
+        self.bucket.lock().unwrap().objects.get(key).map(|object| object.data.clone())
+    }
+
+    pub fn keys(&self, prefix: &str) -> Vec<String> {
+        let bucket = self.bucket.lock().unwrap();
+        bucket.objects.keys().filter(|key| key.starts_with(prefix)).cloned().collect()
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Drop an object behind the backend's back, as if it was never written
+    pub fn remove(&self, key: &str) {
+        self.bucket.lock().unwrap().objects.remove(key);
+    }
+
+    /// Answer the next `times` requests with `method` on keys under `key_prefix`
+    /// with `503 ServiceUnavailable`, as S3 does when overloaded
+    pub fn fail(&self, method: Method, key_prefix: &str, times: usize) {
+        let failure = Failure { method, key_prefix: key_prefix.to_string(), remaining: times, truncate: false };
+        self.failures.lock().unwrap().push(failure);
+    }
+
+    /// Drop the connection halfway through the body of the next `times` object
+    /// GETs on keys under `key_prefix`, as a connection reset does
+    pub fn truncate(&self, key_prefix: &str, times: usize) {
+        let key_prefix = key_prefix.to_string();
+        let failure = Failure { method: Method::GET, key_prefix, remaining: times, truncate: true };
+        self.failures.lock().unwrap().push(failure);
+    }
+
+    /// The failure, if any, to inject into a request; true to truncate the body
+    fn take_failure(&self, method: &Method, key: &str) -> Option<bool> {
+        let mut failures = self.failures.lock().unwrap();
+        let failure = failures
+            .iter_mut()
+            .find(|failure| failure.remaining > 0 && failure.method == method && key.starts_with(&failure.key_prefix))?;
+        failure.remaining -= 1;
+        Some(failure.truncate)
+    }
+
+    /// Backdate an object's modification time
+    pub fn set_modified(&self, key: &str, modified: chrono::DateTime<chrono::Utc>) {
+        if let Some(object) = self.bucket.lock().unwrap().objects.get_mut(key) {
+            object.modified = modified;
+        }
+    }
+}
+
+async fn handle(
+    State(mock): State<MockS3>,
+    method: Method,
+    uri: Uri,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let path = urlencoding::decode(uri.path()).unwrap().into_owned();
+    let path = path.trim_start_matches('/');
+    let key = path.split_once('/').map_or("", |(_, key)| key).to_string();
+    let body = decode_aws_chunked(&headers, body);
+
+    let failure = mock.take_failure(&method, &key);
+    if failure == Some(false) {
+        mock.requests.lock().unwrap().push(Request { operation: "ServiceUnavailable", key, size: body.len() });
+        return error(StatusCode::SERVICE_UNAVAILABLE, "ServiceUnavailable");
+    }
+
+    let (operation, response) = {
+        let mut bucket = mock.bucket.lock().unwrap();
+        route(&mut bucket, &method, &key, &query, &headers, &body)
+    };
+    if failure == Some(true) {
+        mock.requests.lock().unwrap().push(Request { operation: "TruncatedBody", key, size: body.len() });
+        return truncated(response).await;
+    }
+    mock.requests.lock().unwrap().push(Request { operation, key, size: body.len() });
+    response
+}
+
+/// The response with its full Content-Length, but only the first half of its
+/// body before the connection fails
+async fn truncated(response: Response) -> Response {
+    let (parts, body) = response.into_parts();
+    let data = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+    let half = data.slice(..data.len() / 2);
+    // Fail only once the headers and the first half have gone out
+    let reset = async {
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        Err(std::io::Error::new(std::io::ErrorKind::ConnectionReset, "connection reset"))
+    };
+    let body = futures::stream::once(async { Ok(half) }).chain(futures::stream::once(reset));
+    Response::from_parts(parts, Body::from_stream(body))
+}
+
+fn route(
+    bucket: &mut Bucket,
+    method: &Method,
+    key: &str,
+    query: &HashMap<String, String>,
+    headers: &HeaderMap,
+    body: &Bytes,
+) -> (&'static str, Response) {
+    let copy_source = headers
+        .get("x-amz-copy-source")
+        .map(|source| urlencoding::decode(source.to_str().unwrap()).unwrap().into_owned())
+        .map(|source| source.trim_start_matches('/').split_once('/').unwrap().1.to_string());
+
+    match *method {
+        Method::HEAD if key.is_empty() => ("HeadBucket", empty(StatusCode::OK)),
+        Method::GET if key.is_empty() => ("ListObjectsV2", list_objects(bucket, query)),
+        Method::HEAD | Method::GET => {
+            let operation = if *method == Method::HEAD { "HeadObject" } else { "GetObject" };
+            let Some(object) = bucket.objects.get(key) else {
+                return (operation, not_found(*method == Method::HEAD));
+            };
+            let mut response = Response::builder()
+                .header(header::ETAG, etag(&object.data))
+                .header(header::LAST_MODIFIED, object.modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+                .header(header::CONTENT_TYPE, object.content_type.as_deref().unwrap_or("binary/octet-stream"))
+                .header(header::CONTENT_LENGTH, object.data.len());
+            if *method == Method::HEAD {
+                return (operation, response.body(Body::empty()).unwrap());
+            }
+
+            let data = match headers.get(header::RANGE).and_then(|range| parse_range(range.to_str().unwrap(), object.data.len())) {
+                Some((start, end)) => {
+                    response = response
+                        .status(StatusCode::PARTIAL_CONTENT)
+                        .header(header::CONTENT_LENGTH, end - start + 1)
+                        .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, object.data.len()));
+                    object.data.slice(start..=end)
+                }
+                None => object.data.clone(),
+            };
+            (operation, response.body(Body::from(data)).unwrap())
+        }
+        Method::PUT if query.contains_key("uploadId") => {
+            if !bucket.uploads.contains_key(&query["uploadId"]) {
+                return ("UploadPart", error(StatusCode::NOT_FOUND, "NoSuchUpload"));
+            }
+            let part_number: i32 = query["partNumber"].parse().unwrap();
+            let (operation, data) = match &copy_source {
+                Some(source) => {
+                    let source = bucket.objects[source].data.clone();
+                    let data = match headers.get("x-amz-copy-source-range") {
+                        Some(range) => {
+                            let (start, end) = parse_range(range.to_str().unwrap(), source.len()).unwrap();
+                            source.slice(start..=end)
+                        }
+                        None => source,
+                    };
+                    ("UploadPartCopy", data)
+                }
+                None => ("UploadPart", body.clone()),
+            };
+            let tag = etag(&data);
+            bucket.uploads.get_mut(&query["uploadId"]).unwrap().insert(part_number, data);
+
+            let response = match operation {
+                "UploadPartCopy" => xml(format!("<CopyPartResult><ETag>{}</ETag><LastModified>{}</LastModified></CopyPartResult>", tag, timestamp())),
+                _ => Response::builder().header(header::ETAG, tag).body(Body::empty()).unwrap(),
+            };
+            (operation, response)
+        }
+        Method::PUT => {
+            let (operation, object) = match &copy_source {
+                Some(source) => match bucket.objects.get(source) {
+                    Some(source) => ("CopyObject", Object { modified: chrono::Utc::now(), ..source.clone() }),
+                    None => return ("CopyObject", error(StatusCode::NOT_FOUND, "NoSuchKey")),
+                },
+                None => ("PutObject", Object {
+                    data: body.clone(),
+                    content_type: headers.get(header::CONTENT_TYPE).map(|value| value.to_str().unwrap().to_string()),
+                    modified: chrono::Utc::now(),
+                }),
+            };
+            let tag = etag(&object.data);
+            bucket.objects.insert(key.to_string(), object);
+
+            let response = match operation {
+                "CopyObject" => xml(format!("<CopyObjectResult><ETag>{}</ETag><LastModified>{}</LastModified></CopyObjectResult>", tag, timestamp())),
+                _ => Response::builder().header(header::ETAG, tag).body(Body::empty()).unwrap(),
+            };
+            (operation, response)
+        }
+        Method::POST if query.contains_key("uploads") => {
+            bucket.next_upload += 1;
+            let upload_id = format!("upload-{}", bucket.next_upload);
+            bucket.uploads.insert(upload_id.clone(), BTreeMap::new());
+            ("CreateMultipartUpload", xml(format!(
+                "<InitiateMultipartUploadResult><Bucket>{}</Bucket><Key>{}</Key><UploadId>{}</UploadId></InitiateMultipartUploadResult>",
+                BUCKET, key, upload_id
+            )))
+        }
+        Method::POST if query.contains_key("uploadId") => {
+            let Some(parts) = bucket.uploads.remove(&query["uploadId"]) else {
+                return ("CompleteMultipartUpload", error(StatusCode::NOT_FOUND, "NoSuchUpload"));
+            };
+            let requested = xml_values(&String::from_utf8_lossy(body), "PartNumber");
+            let mut data = Vec::new();
+            for part_number in requested {
+                let part_number: i32 = part_number.parse().unwrap();
+                data.extend_from_slice(&parts[&part_number]);
+            }
+            let data = Bytes::from(data);
+            let tag = etag(&data);
+            bucket.objects.insert(key.to_string(), Object { data, content_type: None, modified: chrono::Utc::now() });
+            ("CompleteMultipartUpload", xml(format!(
+                "<CompleteMultipartUploadResult><Bucket>{}</Bucket><Key>{}</Key><ETag>{}</ETag></CompleteMultipartUploadResult>",
+                BUCKET, key, tag
+            )))
+        }
+        Method::POST if query.contains_key("delete") => {
+            let keys = xml_values(&String::from_utf8_lossy(body), "Key");
+            let mut deleted = String::new();
+            for key in keys {
+                bucket.objects.remove(&key);
+                deleted.push_str(&format!("<Deleted><Key>{}</Key></Deleted>", key));
+            }
+            ("DeleteObjects", xml(format!("<DeleteResult>{}</DeleteResult>", deleted)))
+        }
+        Method::DELETE if query.contains_key("uploadId") => {
+            bucket.uploads.remove(&query["uploadId"]);
+            ("AbortMultipartUpload", empty(StatusCode::NO_CONTENT))
+        }
+        Method::DELETE => {
+            bucket.objects.remove(key);
+            ("DeleteObject", empty(StatusCode::NO_CONTENT))
+        }
+        _ => ("Unsupported", error(StatusCode::NOT_IMPLEMENTED, "NotImplemented")),
+    }
+}
+
+fn list_objects(bucket: &Bucket, query: &HashMap<String, String>) -> Response {
+    let prefix = query.get("prefix").map(String::as_str).unwrap_or("");
+    let delimiter = query.get("delimiter").filter(|delimiter| !delimiter.is_empty());
+    let max_keys: usize = query.get("max-keys").and_then(|max| max.parse().ok()).unwrap_or(1000);
+    let after = query.get("continuation-token").or(query.get("start-after")).cloned().unwrap_or_default();
+
+    let mut contents = String::new();
+    let mut prefixes = Vec::new();
+    let mut count = 0;
+    let mut last = None;
+    let mut truncated = false;
+    for (key, object) in bucket.objects.range(after.clone()..).filter(|(key, _)| **key > after) {
+        if !key.starts_with(prefix) {
+            continue;
+        }
+        if count == max_keys {
+            truncated = true;
+            break;
+        }
+        if let Some(delimiter) = delimiter
+            && let Some(position) = key[prefix.len()..].find(delimiter.as_str())
+        {
+            let common = key[..prefix.len() + position + delimiter.len()].to_string();
+            if !prefixes.contains(&common) {
+                prefixes.push(common);
+                count += 1;
+            }
+            last = Some(key.clone());
+            continue;
+        }
+        contents.push_str(&format!(
+            "<Contents><Key>{}</Key><LastModified>{}</LastModified><ETag>{}</ETag><Size>{}</Size><StorageClass>STANDARD</StorageClass></Contents>",
+            escape(key), object.modified.format("%Y-%m-%dT%H:%M:%S%.3fZ"), etag(&object.data), object.data.len()
+        ));
+        count += 1;
+        last = Some(key.clone());
+    }
+
+    let common: String = prefixes.iter().map(|prefix| format!("<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>", escape(prefix))).collect();
+    let next = match (truncated, last) {
+        (true, Some(last)) => format!("<NextContinuationToken>{}</NextContinuationToken>", escape(&last)),
+        _ => String::new(),
+    };
+    xml(format!(
+        "<ListBucketResult><Name>{}</Name><Prefix>{}</Prefix><KeyCount>{}</KeyCount><MaxKeys>{}</MaxKeys><IsTruncated>{}</IsTruncated>{}{}{}</ListBucketResult>",
+        BUCKET, escape(prefix), count, max_keys, truncated, contents, common, next
+    ))
+}
+
+/// Strip the aws-chunked framing the SDK uses when it streams a checksum trailer
+fn decode_aws_chunked(headers: &HeaderMap, body: Bytes) -> Bytes {
+    let chunked = headers
+        .get(header::CONTENT_ENCODING)
+        .is_some_and(|encoding| encoding.to_str().unwrap().contains("aws-chunked"));
+    if !chunked {
+        return body;
+    }
+
+    let mut decoded = Vec::new();
+    let mut rest = &body[..];
+    loop {
+        let line_end = rest.windows(2).position(|window| window == b"\r\n").unwrap();
+        let size_field = std::str::from_utf8(&rest[..line_end]).unwrap();
+        let size = usize::from_str_radix(size_field.split(';').next().unwrap(), 16).unwrap();
+        rest = &rest[line_end + 2..];
+        if size == 0 {
+            break;
+        }
+        decoded.extend_from_slice(&rest[..size]);
+        rest = &rest[size + 2..];
+    }
+    Bytes::from(decoded)
+}
+
+fn parse_range(range: &str, len: usize) -> Option<(usize, usize)> {
+    let (start, end) = range.strip_prefix("bytes=")?.split_once('-')?;
+    let start: usize = start.parse().ok()?;
+    let end = if end.is_empty() { len - 1 } else { end.parse::<usize>().ok()?.min(len - 1) };
+    Some((start, end))
+}
+
+fn xml_values(document: &str, element: &str) -> Vec<String> {
+    let open = format!("<{}>", element);
+    let close = format!("</{}>", element);
+    document
+        .split(&open)
+        .skip(1)
+        .filter_map(|rest| rest.split_once(&close).map(|(value, _)| value.to_string()))
+        .collect()
+}
+
+fn etag(data: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    format!("\"{}\"", &hex::encode(Sha256::digest(data))[..32])
+}
+
+fn timestamp() -> String {
+    chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+fn escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn xml(body: String) -> Response {
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(Body::from(format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>{}", body)))
+        .unwrap()
+}
+
+fn empty(status: StatusCode) -> Response {
+    Response::builder().status(status).body(Body::empty()).unwrap()
+}
+
+fn not_found(head: bool) -> Response {
+    if head {
+        return empty(StatusCode::NOT_FOUND);
+    }
+    error(StatusCode::NOT_FOUND, "NoSuchKey")
+}
+
+fn error(status: StatusCode, code: &str) -> Response {
+    let mut response = xml(format!("<Error><Code>{}</Code><Message>{}</Message></Error>", code, code));
+    *response.status_mut() = status;
+    response
+}
@@ -0,0 +1,153 @@
+//! The parts of the OCI distribution conformance suite that run without a
+//! network: each workflow's happy path, and the error code every failure is
+//! reported with. CI also runs the full suite against a built binary.
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, Response, StatusCode},
+};
+use common::{body_json, image_manifest, sha256_digest, TestRegistry, OCI_MANIFEST};
+
+const UNKNOWN_DIGEST: &str = "sha256:0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Assert a response is an OCI error with `status` and `code`
+async fn assert_error(response: Response<Body>, status: StatusCode, code: &str) {
+    assert_eq!(response.status(), status);
+    let body = body_json(response).await;
+    assert_eq!(body["errors"][0]["code"], code, "{}", body);
+    assert!(body["errors"][0]["message"].is_string());
+}
+
+#[tokio::test]
+async fn pull_reports_missing_content_by_code() {
+    let registry = TestRegistry::new().await;
+    let (_, digest) = registry.push_image("app", "v1", b"layer").await;
+    assert_eq!(registry.get(&format!("/v2/app/manifests/{}", digest)).await.status(), StatusCode::OK);
+    assert_eq!(registry.head("/v2/app/manifests/v1").await.status(), StatusCode::OK);
+
+    // A missing manifest is unknown whether or not its repository exists
+    for path in ["/v2/app/manifests/v2", "/v2/missing/manifests/v1"] {
+        assert_error(registry.get(path).await, StatusCode::NOT_FOUND, "MANIFEST_UNKNOWN").await;
+        assert_eq!(registry.head(path).await.status(), StatusCode::NOT_FOUND);
+    }
+    let missing_blob = format!("/v2/app/blobs/{}", UNKNOWN_DIGEST);
+    assert_error(registry.get(&missing_blob).await, StatusCode::NOT_FOUND, "BLOB_UNKNOWN").await;
+    assert_eq!(registry.head(&missing_blob).await.status(), StatusCode::NOT_FOUND);
+
+    assert_error(registry.get("/v2/app/blobs/sha256:abc").await, StatusCode::BAD_REQUEST, "DIGEST_INVALID").await;
+    assert_error(registry.get("/v2/App/manifests/v1").await, StatusCode::BAD_REQUEST, "NAME_INVALID").await;
+}
+
+#[tokio::test]
+async fn push_reports_invalid_uploads_and_manifests_by_code() {
+    let registry = TestRegistry::new().await;
+
+    // A POST carrying the blob may be answered with a session to PUT it to instead
+    let blob = b"monolithic blob";
+    let request = Request::post(format!("/v2/app/blobs/uploads/?digest={}", sha256_digest(blob)))
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .body(Body::from(&blob[..]))
+        .unwrap();
+    let response = registry.send_authenticated(request).await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let location = response.headers()[header::LOCATION].to_str().unwrap().to_string();
+    let request = Request::put(format!("{}?digest={}", location, sha256_digest(blob)))
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .body(Body::from(&blob[..]))
+        .unwrap();
+    let response = registry.send_authenticated(request).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers()["Docker-Content-Digest"], sha256_digest(blob).as_str());
+
+    let request = Request::patch(format!("/v2/app/blobs/uploads/{}", uuid::Uuid::new_v4()))
+        .body(Body::from("chunk"))
+        .unwrap();
+    assert_error(registry.send_authenticated(request).await, StatusCode::NOT_FOUND, "BLOB_UPLOAD_UNKNOWN").await;
+
+    // A chunk that doesn't start where the upload is
+    let location = registry.start_upload("app").await;
+    let request = Request::patch(&location).header("Content-Range", "10-14").body(Body::from("chunk")).unwrap();
+    let response = registry.send_authenticated(request).await;
+    assert_error(response, StatusCode::RANGE_NOT_SATISFIABLE, "RANGE_INVALID").await;
+
+    let request = Request::put(format!("{}?digest={}", location, UNKNOWN_DIGEST)).body(Body::from("data")).unwrap();
+    assert_error(registry.send_authenticated(request).await, StatusCode::BAD_REQUEST, "DIGEST_INVALID").await;
+
+    let config = br#"{"os":"linux"}"#;
+    let manifest = image_manifest(&sha256_digest(config), config.len(), &[]);
+    let response = registry.put_manifest("app", "v1", &manifest).await;
+    assert_error(response, StatusCode::BAD_REQUEST, "MANIFEST_BLOB_UNKNOWN").await;
+
+    let response = registry.put_manifest("app", "v1", b"{not json").await;
+    assert_error(response, StatusCode::BAD_REQUEST, "MANIFEST_INVALID").await;
+
+    let config_digest = registry.push_blob("app", config).await;
+    let manifest = image_manifest(&config_digest, config.len(), &[]);
+    let response = registry.put_manifest("app", "-v1", &manifest).await;
+    assert_error(response, StatusCode::BAD_REQUEST, "TAG_INVALID").await;
+    let response = registry.put_manifest("app", UNKNOWN_DIGEST, &manifest).await;
+    assert_error(response, StatusCode::BAD_REQUEST, "DIGEST_INVALID").await;
+    assert_eq!(registry.put_manifest("app", "v1", &manifest).await.status(), StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn content_management_deletes_by_digest_and_reports_what_is_refused() {
+    let registry = TestRegistry::new().await;
+    let (_, digest) = registry.push_image("app", "v1", b"layer").await;
+    let delete = |path: String| Request::delete(path).body(Body::empty()).unwrap();
+    let scopes = ["repository:app:*"];
+
+    // Tag deletion is off by default
+    let response = registry.send_with_scopes(delete("/v2/app/manifests/v1".to_string()), &scopes).await;
+    assert_error(response, StatusCode::METHOD_NOT_ALLOWED, "UNSUPPORTED").await;
+
+    let response = registry.send_with_scopes(delete(format!("/v2/app/manifests/{}", digest)), &scopes).await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    assert_error(registry.get("/v2/app/manifests/v1").await, StatusCode::NOT_FOUND, "MANIFEST_UNKNOWN").await;
+    let response = registry.send_with_scopes(delete(format!("/v2/app/manifests/{}", digest)), &scopes).await;
+    assert_error(response, StatusCode::NOT_FOUND, "MANIFEST_UNKNOWN").await;
+
+    let layer = format!("/v2/app/blobs/{}", sha256_digest(b"layer"));
+    assert_eq!(registry.send_with_scopes(delete(layer.clone()), &scopes).await.status(), StatusCode::ACCEPTED);
+    assert_error(registry.get(&layer).await, StatusCode::NOT_FOUND, "BLOB_UNKNOWN").await;
+}
+
+#[tokio::test]
+async fn content_discovery_lists_tags_and_reports_unknown_repositories() {
+    let registry = TestRegistry::new().await;
+    let (manifest, _) = registry.push_image("app", "v1", b"layer").await;
+    for tag in ["v2", "latest"] {
+        registry.put_manifest("app", tag, &manifest).await;
+    }
+
+    let tags = body_json(registry.get("/v2/app/tags/list").await).await;
+    assert_eq!(tags["name"], "app");
+    assert_eq!(tags["tags"], serde_json::json!(["latest", "v1", "v2"]));
+    assert_error(registry.get("/v2/missing/tags/list").await, StatusCode::NOT_FOUND, "NAME_UNKNOWN").await;
+}
+
+#[tokio::test]
+async fn access_without_credentials_or_permission_is_refused_by_code() {
+    let registry = TestRegistry::new().await;
+    let (manifest, _) = registry.push_image("app", "v1", b"layer").await;
+
+    // Clients probe for the API with and without the trailing slash
+    for path in ["/v2", "/v2/"] {
+        assert_eq!(registry.get(path).await.status(), StatusCode::OK, "{}", path);
+        let response = registry.send(Request::get(path).body(Body::empty()).unwrap()).await;
+        assert_error(response, StatusCode::UNAUTHORIZED, "UNAUTHORIZED").await;
+    }
+
+    let response = registry.send(Request::get("/v2/app/manifests/v1").body(Body::empty()).unwrap()).await;
+    assert!(response.headers().contains_key(header::WWW_AUTHENTICATE));
+    assert_error(response, StatusCode::UNAUTHORIZED, "UNAUTHORIZED").await;
+
+    let request = Request::put("/v2/app/manifests/v2")
+        .header(header::CONTENT_TYPE, OCI_MANIFEST)
+        .body(Body::from(manifest))
+        .unwrap();
+    let response = registry.send_with_scopes(request, &["repository:app:pull"]).await;
+    assert_error(response, StatusCode::FORBIDDEN, "DENIED").await;
+}
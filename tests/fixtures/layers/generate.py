@@ -0,0 +1,47 @@
+#!/usr/bin/env python3
+"""Regenerate the layer test fixtures in this directory.
+
+`layer.tar.gz` is a small image layer: six directories, three regular files of
+which two share their content, a symlink, a whiteout and an opaque-directory
+marker. The archive is built deterministically, so regenerating it gives the
+same bytes.
+"""
+
+import gzip
+import io
+import tarfile
+from pathlib import Path
+
+HERE = Path(__file__).parent
+
+CONFIG = b'{"listen": "0.0.0.0:8080", "workers": 4}\n' * 40
+TOOL = bytes(range(256)) * 48
+
+
+def add(archive, name, kind, data=b"", target=""):
+    info = tarfile.TarInfo(name)
+    info.type = kind
+    info.mode = 0o755 if kind == tarfile.DIRTYPE else 0o644
+    info.size = len(data)
+    info.linkname = target
+    archive.addfile(info, io.BytesIO(data) if data else None)
+
+
+def main():
+    tarball = io.BytesIO()
+    with tarfile.open(fileobj=tarball, mode="w", format=tarfile.GNU_FORMAT) as archive:
+        for directory in ["etc/", "etc/app/", "usr/", "usr/bin/", "var/", "opt/"]:
+            add(archive, directory, tarfile.DIRTYPE)
+        add(archive, "etc/app/config.json", tarfile.REGTYPE, CONFIG)
+        add(archive, "etc/app/config.json.bak", tarfile.REGTYPE, CONFIG)
+        add(archive, "usr/bin/tool", tarfile.REGTYPE, TOOL)
+        add(archive, "usr/bin/tool-link", tarfile.SYMTYPE, target="tool")
+        add(archive, "var/.wh.cache", tarfile.REGTYPE)
+        add(archive, "opt/.wh..wh..opq", tarfile.REGTYPE)
+
+    compressed = gzip.compress(tarball.getvalue(), mtime=0)
+    (HERE / "layer.tar.gz").write_bytes(compressed)
+
+
+if __name__ == "__main__":
+    main()
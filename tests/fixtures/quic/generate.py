@@ -0,0 +1,46 @@
+#!/usr/bin/env python3
+"""Regenerate the QUIC test certificate in this directory.
+
+Needs the `cryptography` package. The certificate names `drift-registry`, the
+server name peers connect with, and is valid until 2125.
+"""
+
+import datetime
+from pathlib import Path
+
+from cryptography import x509
+from cryptography.hazmat.primitives import hashes, serialization
+from cryptography.hazmat.primitives.asymmetric import ec
+from cryptography.x509.oid import NameOID
+
+HERE = Path(__file__).parent
+NOT_BEFORE = datetime.datetime(2024, 1, 1, tzinfo=datetime.timezone.utc)
+NOT_AFTER = datetime.datetime(2125, 1, 1, tzinfo=datetime.timezone.utc)
+
+
+def main():
+    key = ec.generate_private_key(ec.SECP256R1())
+    name = x509.Name([x509.NameAttribute(NameOID.COMMON_NAME, "drift-registry")])
+    certificate = (
+        x509.CertificateBuilder()
+        .subject_name(name)
+        .issuer_name(name)
+        .public_key(key.public_key())
+        .serial_number(x509.random_serial_number())
+        .not_valid_before(NOT_BEFORE)
+        .not_valid_after(NOT_AFTER)
+        .add_extension(x509.BasicConstraints(ca=False, path_length=None), critical=True)
+        .add_extension(x509.SubjectAlternativeName([x509.DNSName("drift-registry")]), critical=False)
+        .sign(key, hashes.SHA256())
+    )
+
+    (HERE / "key.pem").write_bytes(
+        key.private_bytes(
+            serialization.Encoding.PEM, serialization.PrivateFormat.PKCS8, serialization.NoEncryption()
+        )
+    )
+    (HERE / "cert.pem").write_bytes(certificate.public_bytes(serialization.Encoding.PEM))
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,183 @@
+#!/usr/bin/env python3
+"""Regenerate the signing test fixtures in this directory.
+
+Needs the `cryptography` package. Certificates are valid until 2125, so the
+fixtures only need regenerating when their layout changes.
+"""
+
+import base64
+import datetime
+import hashlib
+import json
+from pathlib import Path
+
+from cryptography import x509
+from cryptography.hazmat.primitives import hashes, serialization
+from cryptography.hazmat.primitives.asymmetric import ec
+from cryptography.hazmat.primitives.asymmetric.utils import decode_dss_signature
+from cryptography.x509.oid import ExtendedKeyUsageOID, NameOID
+
+HERE = Path(__file__).parent
+NOT_BEFORE = datetime.datetime(2024, 1, 1, tzinfo=datetime.timezone.utc)
+NOT_AFTER = datetime.datetime(2125, 1, 1, tzinfo=datetime.timezone.utc)
+
+
+def new_key():
+    return ec.generate_private_key(ec.SECP256R1())
+
+
+def write_key(name, key, password=None):
+    encryption = (
+        serialization.BestAvailableEncryption(password.encode())
+        if password
+        else serialization.NoEncryption()
+    )
+    (HERE / name).write_bytes(
+        key.private_bytes(serialization.Encoding.PEM, serialization.PrivateFormat.PKCS8, encryption)
+    )
+
+
+def write_public_key(name, key):
+    (HERE / name).write_bytes(
+        key.public_key().public_bytes(
+            serialization.Encoding.PEM, serialization.PublicFormat.SubjectPublicKeyInfo
+        )
+    )
+
+
+def certificate(common_name, key, issuer=None, issuer_key=None, ca=False, serial=None):
+    subject = x509.Name([x509.NameAttribute(NameOID.COMMON_NAME, common_name)])
+    builder = (
+        x509.CertificateBuilder()
+        .subject_name(subject)
+        .issuer_name(issuer.subject if issuer else subject)
+        .public_key(key.public_key())
+        .serial_number(serial or x509.random_serial_number())
+        .not_valid_before(NOT_BEFORE)
+        .not_valid_after(NOT_AFTER)
+        .add_extension(x509.BasicConstraints(ca=ca, path_length=None), critical=True)
+    )
+    if not ca:
+        builder = builder.add_extension(
+            x509.ExtendedKeyUsage([ExtendedKeyUsageOID.CODE_SIGNING]), critical=False
+        )
+    return builder.sign(issuer_key or key, hashes.SHA256())
+
+
+def write_certificate(name, cert):
+    (HERE / name).write_bytes(cert.public_bytes(serialization.Encoding.PEM))
+
+
+def sign_der(key, data):
+    return key.sign(data, ec.ECDSA(hashes.SHA256()))
+
+
+def b64url(data):
+    return base64.urlsafe_b64encode(data).rstrip(b"=").decode()
+
+
+def jws(key, header, claims):
+    signing_input = f"{b64url(json.dumps(header).encode())}.{b64url(json.dumps(claims).encode())}"
+    r, s = decode_dss_signature(sign_der(key, signing_input.encode()))
+    return f"{signing_input}.{b64url(r.to_bytes(32, 'big') + s.to_bytes(32, 'big'))}"
+
+
+def image_manifest(name):
+    return json.dumps(
+        {
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": {
+                "mediaType": "application/vnd.oci.image.config.v1+json",
+                "digest": "sha256:" + hashlib.sha256(name.encode()).hexdigest(),
+                "size": len(name),
+            },
+            "layers": [],
+        }
+    ).encode()
+
+
+def main():
+    # A plain signing key, also encrypted with the password "fixture-password"
+    key = new_key()
+    write_key("p256.pem", key)
+    write_key("p256-encrypted.pem", key, "fixture-password")
+    write_public_key("p256.pub", key)
+
+    # A signature in the layout `cosign sign --key` produces: a simple-signing
+    # payload, signed as-is with ECDSA P-256 / SHA-256, base64 DER signature
+    cosign_key = new_key()
+    write_public_key("cosign.pub", cosign_key)
+    manifest = image_manifest("cosign")
+    (HERE / "cosign-manifest.json").write_bytes(manifest)
+    payload = json.dumps(
+        {
+            "critical": {
+                "identity": {"docker-reference": "registry.example.com/app"},
+                "image": {"docker-manifest-digest": "sha256:" + hashlib.sha256(manifest).hexdigest()},
+                "type": "cosign container image signature",
+            },
+            "optional": None,
+        },
+        separators=(",", ":"),
+    ).encode()
+    (HERE / "cosign-payload.json").write_bytes(payload)
+    (HERE / "cosign-payload.sig").write_bytes(base64.b64encode(sign_der(cosign_key, payload)))
+
+    # root -> intermediate -> leaf, plus a revoked leaf and an unrelated root
+    root_key, intermediate_key = new_key(), new_key()
+    root = certificate("Drift Test Root", root_key, ca=True)
+    intermediate = certificate("Drift Test Intermediate", intermediate_key, root, root_key, ca=True)
+    leaf_key, revoked_key = new_key(), new_key()
+    leaf = certificate("Drift Test Leaf", leaf_key, intermediate, intermediate_key)
+    revoked = certificate("Drift Test Revoked Leaf", revoked_key, intermediate, intermediate_key)
+    untrusted_root_key, untrusted_leaf_key = new_key(), new_key()
+    untrusted_root = certificate("Untrusted Root", untrusted_root_key, ca=True)
+    untrusted_leaf = certificate("Untrusted Leaf", untrusted_leaf_key, untrusted_root, untrusted_root_key)
+
+    write_certificate("root.pem", root)
+    write_certificate("intermediate.pem", intermediate)
+    write_certificate("leaf.pem", leaf)
+    write_key("leaf-key.pem", leaf_key)
+    write_public_key("leaf.pub", leaf_key)
+    write_certificate("revoked-leaf.pem", revoked)
+    write_key("revoked-leaf-key.pem", revoked_key)
+    write_public_key("revoked-leaf.pub", revoked_key)
+    write_certificate("untrusted-root.pem", untrusted_root)
+    write_certificate("untrusted-leaf.pem", untrusted_leaf)
+    write_key("untrusted-leaf-key.pem", untrusted_leaf_key)
+    write_public_key("untrusted-leaf.pub", untrusted_leaf_key)
+
+    crl = (
+        x509.CertificateRevocationListBuilder()
+        .issuer_name(intermediate.subject)
+        .last_update(NOT_BEFORE)
+        .next_update(NOT_AFTER)
+        .add_revoked_certificate(
+            x509.RevokedCertificateBuilder()
+            .serial_number(revoked.serial_number)
+            .revocation_date(NOT_BEFORE)
+            .build()
+        )
+        .sign(intermediate_key, hashes.SHA256())
+    )
+    (HERE / "intermediate.crl").write_bytes(crl.public_bytes(serialization.Encoding.DER))
+
+    # Notary v2 signatures over notary-manifest.json, with their chain in x5c
+    manifest = image_manifest("notary")
+    (HERE / "notary-manifest.json").write_bytes(manifest)
+    subject = "sha256:" + hashlib.sha256(manifest).hexdigest()
+    x5c = lambda *certs: [base64.b64encode(c.public_bytes(serialization.Encoding.DER)).decode() for c in certs]
+    issued_at = int(NOT_BEFORE.timestamp())
+    for name, signer, chain, expires in [
+        ("notary-valid.jws", leaf_key, x5c(leaf, intermediate), int(NOT_AFTER.timestamp())),
+        ("notary-expired.jws", leaf_key, x5c(leaf, intermediate), issued_at + 3600),
+        ("notary-untrusted.jws", untrusted_leaf_key, x5c(untrusted_leaf), int(NOT_AFTER.timestamp())),
+    ]:
+        header = {"alg": "ES256", "kid": "notary", "x5c": chain}
+        claims = {"sub": subject, "iat": issued_at, "exp": expires}
+        (HERE / name).write_text(jws(signer, header, claims))
+
+
+if __name__ == "__main__":
+    main()
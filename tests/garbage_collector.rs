@@ -0,0 +1,153 @@
+mod common;
+
+use axum::{body::Body, http::Request, http::StatusCode};
+use common::{image_manifest, TestRegistry};
+use drift::{config::GarbageCollectorConfig, garbage_collector::GarbageCollector};
+
+/// Push an image made of `config` and `layers` under `tag`, returning its digest
+async fn push(registry: &TestRegistry, tag: &str, config: &[u8], layers: &[&[u8]]) -> String {
+    let config_digest = registry.push_blob("app", config).await;
+    let mut descriptors = Vec::new();
+    for layer in layers {
+        descriptors.push((registry.push_blob("app", layer).await, layer.len()));
+    }
+    let manifest = image_manifest(&config_digest, config.len(), &descriptors);
+    assert_eq!(registry.put_manifest("app", tag, &manifest).await.status(), StatusCode::CREATED);
+    common::sha256_digest(&manifest)
+}
+
+#[tokio::test]
+async fn layers_shared_with_a_live_image_survive_collection() {
+    let registry = TestRegistry::new().await;
+    let shared = common::sha256_digest(b"shared layer");
+    let unique = common::sha256_digest(b"unique layer");
+    let v1 = push(&registry, "v1", br#"{"os":"linux","v":1}"#, &[b"shared layer", b"unique layer"]).await;
+    let v2 = push(&registry, "v2", br#"{"os":"linux","v":2}"#, &[b"shared layer"]).await;
+
+    let request = Request::delete(format!("/v2/app/manifests/{}", v1)).body(Body::empty()).unwrap();
+    let response = registry.send_with_scopes(request, &["repository:app:*"]).await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+    let config = GarbageCollectorConfig { grace_period_hours: 0, ..Default::default() };
+    let gc = GarbageCollector::new(config, registry.state.storage.clone(), 24);
+    gc.trigger_manual_run().await.unwrap();
+
+    let storage = &registry.state.storage;
+    assert!(storage.blob_exists(&shared).await.unwrap());
+    assert!(!storage.blob_exists(&unique).await.unwrap());
+    assert_eq!(registry.get(&format!("/v2/app/manifests/{}", v2)).await.status(), StatusCode::OK);
+    assert_eq!(registry.get(&format!("/v2/app/blobs/{}", shared)).await.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn blobs_uploaded_for_an_unfinished_push_are_held_from_collection() {
+    let registry = TestRegistry::new().await;
+    let state = &registry.state;
+    let collector = |holds: bool| {
+        let config = GarbageCollectorConfig { grace_period_hours: 0, ..Default::default() };
+        let gc = GarbageCollector::new(config, state.storage.clone(), 24);
+        if holds { gc.with_holds(state.holds.clone(), state.repository_locks.clone()) } else { gc }
+    };
+
+    // The layer is uploaded, then collection runs before the manifest arrives
+    let config = br#"{"os":"linux"}"#;
+    let config_digest = registry.push_blob("app", config).await;
+    let layer = registry.push_blob("app", b"layer").await;
+    collector(true).trigger_manual_run().await.unwrap();
+    assert!(state.storage.blob_exists(&layer).await.unwrap());
+
+    let manifest = image_manifest(&config_digest, config.len(), &[(layer.clone(), 5)]);
+    assert_eq!(registry.put_manifest("app", "v1", &manifest).await.status(), StatusCode::CREATED);
+    assert_eq!(registry.get(&format!("/v2/app/blobs/{}", layer)).await.status(), StatusCode::OK);
+
+    // The manifest released the holds; its reference is what keeps the layer now
+    assert!(!state.holds.is_held(&layer).await);
+    collector(false).trigger_manual_run().await.unwrap();
+    assert!(state.storage.blob_exists(&layer).await.unwrap());
+
+    // Without holds, a blob no manifest mentions yet is swept
+    let pending = registry.push_blob("app", b"pending layer").await;
+    collector(false).trigger_manual_run().await.unwrap();
+    assert!(!state.storage.blob_exists(&pending).await.unwrap());
+}
+
+fn collector(registry: &TestRegistry, config: GarbageCollectorConfig) -> GarbageCollector {
+    GarbageCollector::new(config, registry.state.storage.clone(), 24)
+}
+
+#[tokio::test]
+async fn only_orphans_past_the_grace_period_are_swept() {
+    let registry = TestRegistry::new().await;
+    let storage = &registry.state.storage;
+    let layer = common::sha256_digest(b"layer");
+    registry.push_image("app", "v1", b"layer").await;
+    let orphans = [common::sha256_digest(b"orphan one"), common::sha256_digest(b"orphan two")];
+    for (orphan, data) in orphans.iter().zip([&b"orphan one"[..], &b"orphan two"[..]]) {
+        storage.put_blob(orphan, data.to_vec().into()).await.unwrap();
+    }
+    let swept = GarbageCollectorConfig { grace_period_hours: 0, ..Default::default() };
+
+    // Within the default grace period nothing is old enough
+    let metrics = collector(&registry, GarbageCollectorConfig::default()).trigger_manual_run().await.unwrap();
+    assert_eq!(metrics.orphaned_blobs_found, 0);
+
+    // A dry run reports the orphans, counted as if deleted, and deletes nothing
+    let config = GarbageCollectorConfig { dry_run: true, ..swept.clone() };
+    let metrics = collector(&registry, config).trigger_manual_run().await.unwrap();
+    let mut reported: Vec<_> = metrics.report.unwrap().blobs.into_iter().map(|blob| blob.digest).collect();
+    reported.sort();
+    let mut expected = orphans.to_vec();
+    expected.sort();
+    assert_eq!(reported, expected);
+    assert_eq!(metrics.blobs_deleted, 2);
+    for orphan in &orphans {
+        assert!(storage.blob_exists(orphan).await.unwrap());
+    }
+
+    // Runs delete at most `max_blobs_per_run`
+    let config = GarbageCollectorConfig { max_blobs_per_run: 1, ..swept.clone() };
+    let metrics = collector(&registry, config).trigger_manual_run().await.unwrap();
+    assert_eq!((metrics.orphaned_blobs_found, metrics.blobs_deleted), (2, 1));
+
+    let metrics = collector(&registry, swept).trigger_manual_run().await.unwrap();
+    assert_eq!((metrics.orphaned_blobs_found, metrics.blobs_deleted), (1, 1));
+    for orphan in &orphans {
+        assert!(!storage.blob_exists(orphan).await.unwrap());
+    }
+    assert!(storage.blob_exists(&layer).await.unwrap());
+}
+
+#[tokio::test]
+async fn plans_report_orphans_and_their_size_without_deleting() {
+    let mut config = common::test_config();
+    config.garbage_collector = Some(GarbageCollectorConfig { grace_period_hours: 0, ..Default::default() });
+    let registry = TestRegistry::with_config(config).await;
+    let storage = &registry.state.storage;
+    registry.push_image("app", "v1", b"layer").await;
+    let orphans = [(common::sha256_digest(b"small orphan"), 12), (common::sha256_digest(&[9u8; 4096]), 4096)];
+    storage.put_blob(&orphans[0].0, b"small orphan".to_vec().into()).await.unwrap();
+    storage.put_blob(&orphans[1].0, vec![9u8; 4096].into()).await.unwrap();
+
+    let request = Request::get("/api/v1/admin/gc/plan").body(Body::empty()).unwrap();
+    let response = registry.send_with_scopes(request, &["registry:*"]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let report = common::body_json(response).await;
+
+    let mut reported: Vec<(String, u64)> = report["orphaned_blobs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|blob| (blob["digest"].as_str().unwrap().to_string(), blob["size"].as_u64().unwrap()))
+        .collect();
+    reported.sort();
+    let mut expected = orphans.to_vec();
+    expected.sort();
+    assert_eq!(reported, expected);
+    assert_eq!(report["reclaimable_bytes"], 12 + 4096);
+    assert_eq!(report["manifests_scanned"], 1);
+    assert!(report["blobs_scanned"].as_u64().unwrap() >= 4);
+
+    for (orphan, _) in &orphans {
+        assert!(storage.blob_exists(orphan).await.unwrap());
+    }
+}
@@ -0,0 +1,38 @@
+mod common;
+
+use common::{test_config, PASSWORD, USERNAME};
+use drift::auth::AuthService;
+
+fn htpasswd_auth() -> AuthService {
+    let mut config = test_config();
+    let basic = config.auth.basic.as_mut().unwrap();
+    basic.users.push("carol:changeme".to_string());
+    basic.htpasswd_path = Some(format!("{}/tests/fixtures/auth/htpasswd", env!("CARGO_MANIFEST_DIR")));
+    AuthService::new(&config.auth).unwrap()
+}
+
+async fn authenticates(auth: &AuthService, username: &str, password: &str) -> bool {
+    auth.authenticate(username, password).await.unwrap().is_some()
+}
+
+#[tokio::test]
+async fn htpasswd_users_authenticate_with_their_passwords() {
+    let auth = htpasswd_auth();
+
+    assert!(authenticates(&auth, "alice", "hunter2").await);
+    assert!(!authenticates(&auth, "alice", "hunter3").await);
+    assert!(authenticates(&auth, "bob", "letmein").await);
+    assert!(!authenticates(&auth, "bob", "hunter2").await);
+    assert!(!authenticates(&auth, "mallory", "hunter2").await);
+}
+
+#[tokio::test]
+async fn htpasswd_entries_take_precedence_over_inline_users() {
+    let auth = htpasswd_auth();
+
+    // admin is in both; only the file's password works
+    assert!(authenticates(&auth, USERNAME, "correct horse").await);
+    assert!(!authenticates(&auth, USERNAME, PASSWORD).await);
+    // Inline users missing from the file still work
+    assert!(authenticates(&auth, "carol", "changeme").await);
+}
@@ -0,0 +1,288 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+};
+use common::{body_bytes, body_json, image_manifest, sha256_digest, test_config, TestRegistry, OCI_LAYER, OCI_MANIFEST};
+
+#[tokio::test]
+async fn manifest_digest_header_matches_body() {
+    let registry = TestRegistry::new().await;
+    let (_, digest) = registry.push_image("app", "latest", b"layer").await;
+
+    for reference in ["latest", digest.as_str()] {
+        let response = registry.get(&format!("/v2/app/manifests/{}", reference)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let header_digest = response.headers()["Docker-Content-Digest"].to_str().unwrap().to_string();
+        let body = body_bytes(response).await;
+        assert_eq!(header_digest, sha256_digest(&body));
+        assert_eq!(header_digest, digest);
+
+        let response = registry.head(&format!("/v2/app/manifests/{}", reference)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["Docker-Content-Digest"], digest.as_str());
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], body.len().to_string().as_str());
+    }
+}
+
+#[tokio::test]
+async fn manifests_younger_than_min_age_are_only_deleted_by_admins() {
+    let mut config = test_config();
+    config.registry.min_age_days = 7;
+    let registry = TestRegistry::with_config(config).await;
+    let (_, digest) = registry.push_image("app", "latest", b"layer").await;
+    let delete = || Request::delete(format!("/v2/app/manifests/{}", digest)).body(Body::empty()).unwrap();
+
+    let response = registry.send_with_scopes(delete(), &["repository:app:*"]).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(body_json(response).await["errors"][0]["code"], "DENIED");
+    assert_eq!(registry.get("/v2/app/manifests/latest").await.status(), StatusCode::OK);
+
+    let response = registry.send_with_scopes(delete(), &["repository:app:*", "registry:admin"]).await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    assert_eq!(registry.get("/v2/app/manifests/latest").await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn manifest_responses_carry_the_stored_media_type() {
+    const DOCKER_MANIFEST: &str = "application/vnd.docker.distribution.manifest.v2+json";
+    let registry = TestRegistry::new().await;
+    let config = registry.push_blob("app", b"{}").await;
+    let manifest = serde_json::to_vec(&serde_json::json!({
+        "schemaVersion": 2,
+        "mediaType": DOCKER_MANIFEST,
+        "config": { "mediaType": "application/vnd.docker.container.image.v1+json", "digest": config, "size": 2 },
+        "layers": [],
+    }))
+    .unwrap();
+    let digest = sha256_digest(&manifest);
+
+    let response = registry
+        .send_authenticated(
+            Request::put("/v2/app/manifests/docker")
+                .header(header::CONTENT_TYPE, DOCKER_MANIFEST)
+                .body(Body::from(manifest.clone()))
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers()["Docker-Content-Digest"], digest.as_str());
+
+    // Stored only under the tag, the manifest is still served by digest
+    for reference in ["docker", digest.as_str()] {
+        let response = registry.head(&format!("/v2/app/manifests/{}", reference)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], DOCKER_MANIFEST);
+        assert_eq!(response.headers()["Docker-Content-Digest"], digest.as_str());
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], manifest.len().to_string().as_str());
+        assert!(body_bytes(response).await.is_empty());
+
+        let response = registry.get(&format!("/v2/app/manifests/{}", reference)).await;
+        assert_eq!(response.headers()[header::CONTENT_TYPE], DOCKER_MANIFEST);
+        assert_eq!(body_bytes(response).await, manifest);
+    }
+}
+
+#[tokio::test]
+async fn manifests_are_negotiated_by_accept_header() {
+    let registry = TestRegistry::new().await;
+    let (manifest, _) = registry.push_image("app", "latest", b"layer").await;
+    let get = |accept: &'static str| {
+        registry.send_authenticated(
+            Request::get("/v2/app/manifests/latest")
+                .header(header::ACCEPT, accept)
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+
+    for accept in [
+        OCI_MANIFEST,
+        "application/vnd.docker.distribution.manifest.v2+json, application/vnd.oci.image.manifest.v1+json;q=0.9",
+        "*/*",
+        "application/*",
+    ] {
+        let response = get(accept).await;
+        assert_eq!(response.status(), StatusCode::OK, "{}", accept);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], OCI_MANIFEST);
+        assert_eq!(body_bytes(response).await, manifest);
+    }
+
+    let response = get("application/vnd.docker.distribution.manifest.v2+json").await;
+    assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+    assert_eq!(body_json(response).await["errors"][0]["detail"]["mediaType"], OCI_MANIFEST);
+}
+
+#[tokio::test]
+async fn existing_immutable_tags_cannot_be_moved() {
+    let mut config = test_config();
+    config.registry.immutable_tags = vec!["release".to_string()];
+    let registry = TestRegistry::with_config(config).await;
+
+    // Creating an immutable tag, and re-pushing the same content, is fine
+    let (first, digest) = registry.push_image("app", "release", b"first").await;
+    assert_eq!(registry.put_manifest("app", "release", &first).await.status(), StatusCode::CREATED);
+
+    let (second, _) = registry.push_image("app", "latest", b"second").await;
+    let response = registry.put_manifest("app", "release", &second).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let body = body_json(response).await;
+    assert_eq!(body["errors"][0]["code"], "DENIED");
+    assert_eq!(body["errors"][0]["detail"]["tag"], "release");
+
+    let response = registry.head("/v2/app/manifests/release").await;
+    assert_eq!(response.headers()["Docker-Content-Digest"], digest.as_str());
+
+    // Other tags move freely
+    assert_eq!(registry.put_manifest("app", "latest", &first).await.status(), StatusCode::CREATED);
+    let response = registry.head("/v2/app/manifests/latest").await;
+    assert_eq!(response.headers()["Docker-Content-Digest"], digest.as_str());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_pushes_of_an_immutable_tag_create_it_once() {
+    let mut config = test_config();
+    config.registry.immutable_tags = vec!["release-*".to_string()];
+    let registry = std::sync::Arc::new(TestRegistry::filesystem(config).await);
+    let (first, first_digest) = registry.push_image("app", "first", b"first").await;
+    let (second, second_digest) = registry.push_image("app", "second", b"second").await;
+
+    for round in 0..20 {
+        let tag = format!("release-{}", round);
+        let pushes: Vec<_> = [first.clone(), second.clone()]
+            .into_iter()
+            .map(|manifest| {
+                let (registry, tag) = (registry.clone(), tag.clone());
+                tokio::spawn(async move { registry.put_manifest("app", &tag, &manifest).await.status() })
+            })
+            .collect();
+        let mut statuses = Vec::new();
+        for push in pushes {
+            statuses.push(push.await.unwrap());
+        }
+        let mut sorted = statuses.clone();
+        sorted.sort();
+        assert_eq!(sorted, [StatusCode::CREATED, StatusCode::CONFLICT], "round {}", round);
+
+        // The tag still points at whichever push won
+        let winner = if statuses[0] == StatusCode::CREATED { &first_digest } else { &second_digest };
+        let response = registry.head(&format!("/v2/app/manifests/{}", tag)).await;
+        assert_eq!(response.headers()["Docker-Content-Digest"], winner.as_str(), "round {}", round);
+    }
+}
+
+#[tokio::test]
+async fn manifests_one_byte_over_the_size_limit_are_rejected() {
+    let registry = TestRegistry::new().await;
+    let (manifest, _) = registry.push_image("app", "v1", b"layer").await;
+
+    // A valid manifest padded with trailing whitespace up to the limit, then one byte more
+    let mut padded = manifest.clone();
+    padded.resize(4 * 1024 * 1024, b' ');
+    assert_eq!(registry.put_manifest("app", "v2", &padded).await.status(), StatusCode::CREATED);
+    padded.push(b' ');
+
+    let response = registry.put_manifest("app", "v3", &padded).await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let error = &body_json(response).await["errors"][0];
+    assert_eq!(error["code"], "SIZE_INVALID");
+    assert_eq!(error["detail"]["limit"], 4 * 1024 * 1024);
+    assert_eq!(registry.get("/v2/app/manifests/v3").await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn manifests_referencing_unpushed_content_are_rejected() {
+    let registry = TestRegistry::new().await;
+    let config = br#"{"os":"linux"}"#;
+    let config_digest = registry.push_blob("app", config).await;
+    let layer_digest = registry.push_blob("app", b"layer").await;
+    let (missing_config, missing_layer) = (sha256_digest(b"missing config"), sha256_digest(b"missing layer"));
+
+    let rejected = [
+        (image_manifest(&missing_config, 14, &[(layer_digest.clone(), 5)]), vec![missing_config.clone()]),
+        (
+            image_manifest(&config_digest, config.len(), &[(layer_digest.clone(), 5), (missing_layer.clone(), 13)]),
+            vec![missing_layer.clone()],
+        ),
+        (image_manifest(&missing_config, 14, &[(missing_layer.clone(), 13)]), vec![missing_config, missing_layer]),
+    ];
+    for (manifest, mut missing) in rejected {
+        let response = registry.put_manifest("app", "v1", &manifest).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = body_json(response).await;
+        assert_eq!(body["errors"][0]["code"], "MANIFEST_BLOB_UNKNOWN");
+        let mut reported: Vec<String> = serde_json::from_value(body["errors"][0]["detail"]["digests"].clone()).unwrap();
+        reported.sort();
+        missing.sort();
+        assert_eq!(reported, missing);
+    }
+    assert_eq!(registry.get("/v2/app/manifests/v1").await.status(), StatusCode::NOT_FOUND);
+
+    // An index's entries are manifests, not blobs
+    let index = |digest: &str| {
+        serde_json::to_vec(&serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [{ "mediaType": OCI_MANIFEST, "digest": digest, "size": 1 }],
+        }))
+        .unwrap()
+    };
+    let request = |body: Vec<u8>| {
+        Request::put("/v2/app/manifests/multi")
+            .header(header::CONTENT_TYPE, "application/vnd.oci.image.index.v1+json")
+            .body(Body::from(body))
+            .unwrap()
+    };
+    let response = registry.send_authenticated(request(index(&layer_digest))).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(body_json(response).await["errors"][0]["detail"]["digests"][0], layer_digest.as_str());
+
+    let manifest = image_manifest(&config_digest, config.len(), &[(layer_digest, 5)]);
+    assert_eq!(registry.put_manifest("app", "v1", &manifest).await.status(), StatusCode::CREATED);
+    let response = registry.send_authenticated(request(index(&sha256_digest(&manifest)))).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn malformed_manifests_are_invalid() {
+    let registry = TestRegistry::new().await;
+    let config_digest = registry.push_blob("app", b"{}").await;
+    let mut unsupported: serde_json::Value = serde_json::from_slice(&image_manifest(&config_digest, 2, &[])).unwrap();
+    unsupported["schemaVersion"] = 1.into();
+    let mut bad_digest: serde_json::Value = serde_json::from_slice(&image_manifest(&config_digest, 2, &[])).unwrap();
+    bad_digest["layers"] = serde_json::json!([{ "mediaType": OCI_LAYER, "digest": "sha256:abc", "size": 1 }]);
+
+    for manifest in [
+        b"{not json".to_vec(),
+        br#"{"schemaVersion":2,"mediaType":"application/vnd.oci.image.manifest.v1+json"}"#.to_vec(),
+        serde_json::to_vec(&unsupported).unwrap(),
+        serde_json::to_vec(&bad_digest).unwrap(),
+    ] {
+        let response = registry.put_manifest("app", "v1", &manifest).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(body_json(response).await["errors"][0]["code"], "MANIFEST_INVALID");
+    }
+}
+
+#[tokio::test]
+async fn tags_roll_back_to_manifests_stored_under_another_tag() {
+    let registry = TestRegistry::new().await;
+    let (manifest, digest) = registry.push_image("app", "v1", b"one").await;
+    assert_eq!(registry.put_manifest("app", "latest", &manifest).await.status(), StatusCode::CREATED);
+    registry.push_image("app", "latest", b"two").await;
+
+    // The old manifest was pushed by tag, so only `v1` still stores it
+    let request = Request::post("/api/v1/repositories/app/tags/latest/rollback")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(format!(r#"{{"digest":"{}"}}"#, digest)))
+        .unwrap();
+    let response = registry.send_with_scopes(request, &["registry:*"]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body_json(response).await["digest"], digest.as_str());
+
+    let response = registry.get("/v2/app/manifests/latest").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["Docker-Content-Digest"], digest.as_str());
+    assert_eq!(body_bytes(response).await, manifest);
+}
@@ -0,0 +1,31 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::TestRegistry;
+
+#[tokio::test]
+async fn pushing_and_pulling_move_the_counters() {
+    let registry = TestRegistry::new().await;
+    let metrics = &registry.state.metrics;
+    let layer = vec![3u8; 4096];
+
+    let (_, digest) = registry.push_image("team/app", "latest", &layer).await;
+    assert!(metrics.blob_bytes_uploaded_total.get() >= layer.len() as u64);
+    assert_eq!(metrics.manifest_pushes_total.with_label_values(&["team/app"]).get(), 1);
+
+    assert_eq!(registry.get(&format!("/v2/team/app/manifests/{}", digest)).await.status(), StatusCode::OK);
+    let layer_digest = common::sha256_digest(&layer);
+    assert_eq!(registry.get(&format!("/v2/team/app/blobs/{}", layer_digest)).await.status(), StatusCode::OK);
+    assert_eq!(metrics.manifest_pulls_total.with_label_values(&["team/app"]).get(), 1);
+    assert_eq!(metrics.blob_bytes_downloaded_total.get(), layer.len() as u64);
+
+    let exported = metrics.export_prometheus().unwrap();
+    for line in [
+        "drift_manifest_pushes_total{repository=\"team/app\"} 1",
+        "drift_manifest_pulls_total{repository=\"team/app\"} 1",
+        "drift_blob_bytes_downloaded_total 4096",
+    ] {
+        assert!(exported.contains(line), "missing {} in\n{}", line, exported);
+    }
+    assert!(exported.contains("drift_storage_operation_duration_seconds_count{operation=\"put_manifest\",result=\"ok\"}"));
+}
@@ -0,0 +1,151 @@
+use axum::{
+    extract::{Form, Path, State},
+    http::{HeaderMap, StatusCode},
+    routing::{get, post},
+    Json, Router,
+};
+use drift::auth::oauth::{
+    AzureConfig, GitHubConfig, GoogleConfig, OAuthError, OAuthProvider, OAuthService, ProviderEndpoints,
+};
+use drift::config::OAuthConfig;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+const CODE: &str = "authorization-code";
+const REDIRECT_URI: &str = "https://registry.example.com/auth/callback";
+
+/// Token endpoint: hands out `token-<provider>` for the expected code, with a PKCE verifier
+async fn token(Path(provider): Path<String>, Form(form): Form<HashMap<String, String>>) -> (StatusCode, Json<Value>) {
+    let valid = form.get("grant_type").map(String::as_str) == Some("authorization_code")
+        && form.get("code").map(String::as_str) == Some(CODE)
+        && form.get("redirect_uri").map(String::as_str) == Some(REDIRECT_URI)
+        && form.get("code_verifier").is_some_and(|verifier| verifier.len() >= 43);
+    if !valid {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "error": "invalid_grant" })));
+    }
+    let access_token = format!("token-{}", provider);
+    (StatusCode::OK, Json(json!({ "access_token": access_token, "token_type": "bearer", "expires_in": 3600 })))
+}
+
+/// Profile endpoints, answering only to the token the token endpoint issued
+async fn user(
+    State(profiles): State<HashMap<&'static str, Value>>,
+    Path(path): Path<String>,
+    headers: HeaderMap,
+) -> (StatusCode, Json<Value>) {
+    let provider = path.split('/').next().unwrap_or_default();
+    let authorization = headers.get("authorization").and_then(|value| value.to_str().ok()).unwrap_or_default();
+    if !authorization.ends_with(&format!(" token-{}", provider)) {
+        return (StatusCode::UNAUTHORIZED, Json(json!({})));
+    }
+    match profiles.get(path.as_str()) {
+        Some(profile) => (StatusCode::OK, Json(profile.clone())),
+        None => (StatusCode::NOT_FOUND, Json(json!({}))),
+    }
+}
+
+/// A mock of all three providers, returning the service pointed at it
+async fn oauth_service() -> OAuthService {
+    let profiles = HashMap::from([
+        ("azure/user", json!({ "sub": "azure-1", "name": "Ada", "email": "ada@example.com" })),
+        ("github/user", json!({
+            "id": 7, "login": "grace", "name": null, "email": null, "avatar_url": "https://example.com/g.png",
+        })),
+        ("github/user/emails", json!([
+            { "email": "old@example.com", "primary": false, "verified": true },
+            { "email": "grace@example.com", "primary": true, "verified": true },
+        ])),
+        ("google/user", json!({
+            "id": "google-1", "email": "linus@example.com", "name": "Linus",
+            "picture": "https://example.com/l.png", "verified_email": true,
+        })),
+    ]);
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let app = Router::new()
+        .route("/:provider/token", post(token))
+        .route("/profile/*path", get(user))
+        .with_state(profiles);
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let config = OAuthConfig {
+        enabled: true,
+        azure: Some(AzureConfig {
+            tenant_id: "tenant".to_string(),
+            client_id: "azure-client".to_string(),
+            client_secret: "secret".to_string(),
+            redirect_uri: REDIRECT_URI.to_string(),
+        }),
+        github: Some(GitHubConfig {
+            client_id: "github-client".to_string(),
+            client_secret: "secret".to_string(),
+            redirect_uri: REDIRECT_URI.to_string(),
+        }),
+        google: Some(GoogleConfig {
+            client_id: "google-client".to_string(),
+            client_secret: "secret".to_string(),
+            redirect_uri: REDIRECT_URI.to_string(),
+        }),
+        default_scopes: vec!["repository:*:pull".to_string()],
+    };
+
+    let mut service = OAuthService::new(config);
+    for provider in [OAuthProvider::Azure, OAuthProvider::GitHub, OAuthProvider::Google] {
+        let name = provider.as_str();
+        service = service.with_endpoints(
+            provider,
+            ProviderEndpoints {
+                authorize_url: format!("{}/{}/authorize", base, name),
+                token_url: format!("{}/{}/token", base, name),
+                user_url: format!("{}/profile/{}/user", base, name),
+            },
+        );
+    }
+    service
+}
+
+/// Start a login and return the `state` the provider would hand back
+fn start_login(service: &OAuthService, provider: OAuthProvider) -> String {
+    let url = url::Url::parse(&service.authorize_url(provider).unwrap()).unwrap();
+    let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+    assert_eq!(params["redirect_uri"], REDIRECT_URI);
+    assert_eq!(params["code_challenge_method"], "S256");
+    assert!(url.path().ends_with("/authorize"));
+    params["state"].clone()
+}
+
+#[tokio::test]
+async fn each_provider_completes_a_login_through_its_token_endpoint() {
+    let service = oauth_service().await;
+
+    for (provider, id, email) in [
+        (OAuthProvider::Azure, "azure-1", "ada@example.com"),
+        (OAuthProvider::GitHub, "7", "grace@example.com"),
+        (OAuthProvider::Google, "google-1", "linus@example.com"),
+    ] {
+        let state = start_login(&service, provider);
+        let user = service.complete_login(provider, CODE, &state).await.unwrap();
+        assert_eq!(user.id, id);
+        assert_eq!(user.email, email);
+        assert_eq!(user.provider, provider.as_str());
+    }
+}
+
+#[tokio::test]
+async fn logins_with_a_bad_state_or_code_are_refused() {
+    let service = oauth_service().await;
+
+    // Unknown state, and state issued for another provider
+    let state = start_login(&service, OAuthProvider::GitHub);
+    for (provider, state) in [(OAuthProvider::GitHub, "forged"), (OAuthProvider::Google, state.as_str())] {
+        let error = service.complete_login(provider, CODE, state).await.unwrap_err();
+        assert!(matches!(error.downcast_ref(), Some(OAuthError::StateMismatch)), "{}", error);
+    }
+
+    // The token endpoint refuses the code, and the state can't be replayed
+    let state = start_login(&service, OAuthProvider::Azure);
+    assert!(service.complete_login(OAuthProvider::Azure, "wrong-code", &state).await.is_err());
+    let error = service.complete_login(OAuthProvider::Azure, CODE, &state).await.unwrap_err();
+    assert!(matches!(error.downcast_ref(), Some(OAuthError::StateMismatch)), "{}", error);
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    routing::get,
+    Json, Router,
+};
+use base64::Engine;
+use common::{test_config, TestRegistry};
+use drift::config::{AuthMode, OidcConfig, OidcGroupMapping};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use p256::{elliptic_curve::sec1::ToEncodedPoint, pkcs8::DecodePublicKey};
+use serde_json::json;
+
+const CLIENT_ID: &str = "drift";
+
+fn fixture(name: &str) -> String {
+    std::fs::read_to_string(format!("{}/tests/fixtures/signing/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+}
+
+/// Serve discovery and a JWKS holding the p256 fixture key as `kid`, returning the issuer URL
+async fn start_issuer(kid: &'static str) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let issuer = format!("http://{}", listener.local_addr().unwrap());
+
+    let point = p256::PublicKey::from_public_key_pem(&fixture("p256.pub")).unwrap().to_encoded_point(false);
+    let encode = |bytes: &[u8]| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes);
+    let jwks = json!({ "keys": [{
+        "kty": "EC",
+        "crv": "P-256",
+        "alg": "ES256",
+        "use": "sig",
+        "kid": kid,
+        "x": encode(point.x().unwrap()),
+        "y": encode(point.y().unwrap()),
+    }]});
+    let discovery = json!({ "issuer": issuer, "jwks_uri": format!("{}/jwks", issuer) });
+
+    let app = Router::new()
+        .route("/.well-known/openid-configuration", get(move || async move { Json(discovery) }))
+        .route("/jwks", get(move || async move { Json(jwks) }));
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    issuer
+}
+
+async fn registry(issuer: &str) -> TestRegistry {
+    let mut config = test_config();
+    config.auth.mode = AuthMode::Oidc;
+    config.auth.oidc = Some(OidcConfig {
+        issuer: issuer.to_string(),
+        client_id: CLIENT_ID.to_string(),
+        client_secret: "secret".to_string(),
+        username_claim: "preferred_username".to_string(),
+        groups_claim: "groups".to_string(),
+        default_scopes: vec!["repository:*:pull".to_string()],
+        group_mappings: vec![OidcGroupMapping {
+            group: "developers".to_string(),
+            roles: vec!["developer".to_string()],
+            scopes: vec!["repository:team/*:push".to_string()],
+        }],
+    });
+    TestRegistry::with_config(config).await
+}
+
+/// An ES256 token signed with the fixture key
+fn token(issuer: &str, kid: &str, audience: &str, expires_in: i64) -> String {
+    let mut header = Header::new(Algorithm::ES256);
+    header.kid = Some(kid.to_string());
+    let claims = json!({
+        "iss": issuer,
+        "aud": audience,
+        "sub": "user-1",
+        "preferred_username": "alice",
+        "groups": ["developers"],
+        "exp": chrono::Utc::now().timestamp() + expires_in,
+    });
+    let key = EncodingKey::from_ec_pem(fixture("p256.pem").as_bytes()).unwrap();
+    jsonwebtoken::encode(&header, &claims, &key).unwrap()
+}
+
+async fn start_upload(registry: &TestRegistry, repository: &str, token: &str) -> StatusCode {
+    registry
+        .send(
+            Request::post(format!("/v2/{}/blobs/uploads/", repository))
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .status()
+}
+
+#[tokio::test]
+async fn oidc_tokens_grant_the_scopes_mapped_from_their_groups() {
+    let issuer = start_issuer("key-1").await;
+    let registry = registry(&issuer).await;
+    let token = token(&issuer, "key-1", CLIENT_ID, 300);
+
+    assert_eq!(start_upload(&registry, "team/app", &token).await, StatusCode::ACCEPTED);
+    assert_eq!(start_upload(&registry, "other/app", &token).await, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn expired_misaddressed_and_unknown_key_tokens_are_rejected() {
+    let issuer = start_issuer("key-1").await;
+    let registry = registry(&issuer).await;
+
+    for token in [
+        token(&issuer, "key-1", CLIENT_ID, -600),
+        token(&issuer, "key-1", "another-client", 300),
+        token(&issuer, "key-2", CLIENT_ID, 300),
+        token("https://elsewhere.example.com", "key-1", CLIENT_ID, 300),
+    ] {
+        assert_eq!(start_upload(&registry, "team/app", &token).await, StatusCode::UNAUTHORIZED);
+    }
+}
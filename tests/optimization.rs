@@ -0,0 +1,189 @@
+mod common;
+
+use axum::{body::Body, http::header, http::Request, http::StatusCode};
+use common::{body_bytes, image_manifest, sha256_digest, test_config, TestRegistry, OCI_MANIFEST};
+use drift::{
+    config::{GarbageCollectorConfig, SigningKeyConfig, VerificationKeyConfig},
+    garbage_collector::GarbageCollector,
+    optimization::CompressionType,
+    signing::{SignatureAlgorithm, SignatureFormat, SignaturePayload},
+};
+use std::io::{Read, Write};
+
+const OCI_ZSTD_LAYER: &str = "application/vnd.oci.image.layer.v1.tar+zstd";
+
+/// The same tarball gzipped with different header timestamps: the layers'
+/// digests differ while their content is the same
+fn layers() -> [Vec<u8>; 2] {
+    let data = b"#!/bin/sh\necho hello\n".repeat(64);
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o755);
+    header.set_cksum();
+    builder.append_data(&mut header, "usr/bin/hello", &data[..]).unwrap();
+    let tarball = builder.into_inner().unwrap();
+
+    [1, 2].map(|mtime| {
+        let mut encoder = flate2::GzBuilder::new().mtime(mtime).write(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&tarball).unwrap();
+        encoder.finish().unwrap()
+    })
+}
+
+#[tokio::test]
+async fn deduplicated_layers_survive_until_every_image_using_them_is_gone() {
+    let mut config = test_config();
+    config.optimization.as_mut().unwrap().enabled = true;
+    let registry = TestRegistry::with_config(config).await;
+    let optimization = registry.state.optimization.clone().unwrap();
+    let mut policy = optimization.policy();
+    policy.min_layer_size_bytes = 0;
+    policy.enable_compression_optimization = false;
+
+    let config = br#"{"os":"linux"}"#;
+    let config_digest = registry.push_blob("app", config).await;
+    let mut images = Vec::new();
+    for (tag, layer) in ["one", "two"].into_iter().zip(layers()) {
+        let digest = registry.push_blob("app", &layer).await;
+        optimization.optimize_layer(&digest, layer.clone().into(), &policy).await.unwrap();
+        let manifest = image_manifest(&config_digest, config.len(), &[(digest.clone(), layer.len())]);
+        assert_eq!(registry.put_manifest("app", tag, &manifest).await.status(), StatusCode::CREATED);
+        // Pushing the same image again doesn't count it twice
+        assert_eq!(registry.put_manifest("app", tag, &manifest).await.status(), StatusCode::CREATED);
+        images.push((sha256_digest(&manifest), digest));
+    }
+    let (canonical, duplicate) = (&images[0].1, &images[1].1);
+    let result = optimization.get_optimization_result(duplicate).await.unwrap();
+    assert_eq!(result.optimization_type, drift::optimization::OptimizationType::Deduplication);
+
+    let storage = registry.state.storage.clone();
+    let collect = || async {
+        let config = GarbageCollectorConfig { grace_period_hours: 0, ..Default::default() };
+        let gc = GarbageCollector::new(config, storage.clone(), 24).with_optimization(optimization.clone());
+        gc.trigger_manual_run().await.unwrap();
+    };
+    let delete = |manifest: String| {
+        let request = Request::delete(format!("/v2/app/manifests/{}", manifest)).body(Body::empty()).unwrap();
+        registry.send_with_scopes(request, &["repository:app:*"])
+    };
+
+    // The first image's layer holds the content the second was deduplicated against
+    assert_eq!(delete(images[0].0.clone()).await.status(), StatusCode::ACCEPTED);
+    collect().await;
+    assert!(optimization.is_layer_referenced(canonical).await);
+    assert!(storage.blob_exists(canonical).await.unwrap());
+
+    assert_eq!(delete(images[1].0.clone()).await.status(), StatusCode::ACCEPTED);
+    collect().await;
+    for layer in [canonical, duplicate] {
+        assert!(!optimization.is_layer_referenced(layer).await);
+        assert!(!storage.blob_exists(layer).await.unwrap());
+    }
+}
+
+/// Optimization on, with signatures required on every pull
+fn signing_config() -> drift::config::Config {
+    let mut config = test_config();
+    config.optimization.as_mut().unwrap().enabled = true;
+    let fixture = |name: &str| format!("{}/tests/fixtures/signing/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let signing = config.signing.as_mut().unwrap();
+    signing.enabled = true;
+    signing.verification_policy.require_signatures = true;
+    signing.signing_keys.push(SigningKeyConfig {
+        key_id: "fixture".to_string(),
+        algorithm: SignatureAlgorithm::EcdsaP256Sha256,
+        key_path: fixture("p256.pem"),
+        certificate_path: None,
+        password: None,
+    });
+    signing.verification_keys.push(VerificationKeyConfig {
+        key_id: "fixture".to_string(),
+        algorithm: SignatureAlgorithm::EcdsaP256Sha256,
+        public_key_path: fixture("p256.pub"),
+        certificate_path: None,
+        trusted: true,
+    });
+    config
+}
+
+#[tokio::test]
+async fn optimized_pulls_verify_end_to_end_under_the_pushed_manifests_signature() {
+    let registry = TestRegistry::with_config(signing_config()).await;
+    let optimization = registry.state.optimization.clone().unwrap();
+    let mut policy = optimization.policy();
+    policy.min_layer_size_bytes = 0;
+    policy.preferred_compression = CompressionType::Zstd;
+
+    // An image whose config lists the DiffID of its gzipped layer
+    let [layer, _] = layers();
+    let mut tarball = Vec::new();
+    flate2::read::GzDecoder::new(&layer[..]).read_to_end(&mut tarball).unwrap();
+    let diff_id = sha256_digest(&tarball);
+    let config = serde_json::json!({ "os": "linux", "rootfs": { "type": "layers", "diff_ids": [diff_id] } });
+    let config = serde_json::to_vec(&config).unwrap();
+    let config_digest = registry.push_blob("app", &config).await;
+    let layer_digest = registry.push_blob("app", &layer).await;
+    let manifest = image_manifest(&config_digest, config.len(), &[(layer_digest.clone(), layer.len())]);
+    assert_eq!(registry.put_manifest("app", "latest", &manifest).await.status(), StatusCode::CREATED);
+    let source_digest = sha256_digest(&manifest);
+    let result = optimization.optimize_layer(&layer_digest, layer.clone().into(), &policy).await.unwrap();
+    assert!(result.optimized_digest.is_some());
+
+    // Resolve the tag, then pull by the digest it resolved to, as containerd does
+    let pull = |method: &str, reference: &str| {
+        let request = Request::builder()
+            .method(method)
+            .uri(format!("/v2/app/manifests/{}", reference))
+            .header(header::ACCEPT, format!("{}, {}", OCI_MANIFEST, OCI_ZSTD_LAYER))
+            .body(Body::empty())
+            .unwrap();
+        registry.send_authenticated(request)
+    };
+    let response = pull("HEAD", "latest").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let digest = response.headers()["Docker-Content-Digest"].to_str().unwrap().to_string();
+    assert_ne!(digest, source_digest);
+
+    // Only a signature on the pushed manifest lets the optimized one through
+    assert_eq!(pull("GET", &digest).await.status(), StatusCode::FORBIDDEN);
+    let signing = registry.state.signing.as_ref().unwrap();
+    let payload = SignaturePayload {
+        subject: "manifest".to_string(),
+        content_type: "manifest".to_string(),
+        repository: "app".to_string(),
+        tag: None,
+        metadata: Default::default(),
+        timestamp: chrono::Utc::now(),
+    };
+    signing.sign_content(&manifest, "fixture", SignatureFormat::Simple, payload).await.unwrap();
+
+    let response = pull("GET", &digest).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["Docker-Content-Digest"], digest.as_str());
+    let optimized = body_bytes(response).await;
+    assert_eq!(sha256_digest(&optimized), digest);
+    assert_eq!(registry.state.storage.get_manifest("app", &digest).await.unwrap(), None);
+    assert!(!registry.state.storage.list_manifests("app").await.unwrap().contains(&digest));
+
+    // Every blob verifies against its descriptor, and the layer against the config's DiffID
+    let optimized: serde_json::Value = serde_json::from_slice(&optimized).unwrap();
+    let descriptors = std::iter::once(&optimized["config"]).chain(optimized["layers"].as_array().unwrap());
+    for descriptor in descriptors {
+        let blob_digest = descriptor["digest"].as_str().unwrap();
+        let response = registry.get(&format!("/v2/app/blobs/{}", blob_digest)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let blob = body_bytes(response).await;
+        assert_eq!(sha256_digest(&blob), blob_digest);
+        assert_eq!(blob.len() as u64, descriptor["size"].as_u64().unwrap());
+    }
+    let layer = &optimized["layers"][0];
+    assert_eq!(layer["mediaType"], OCI_ZSTD_LAYER);
+    let blob = body_bytes(registry.get(&format!("/v2/app/blobs/{}", layer["digest"].as_str().unwrap())).await).await;
+    assert_eq!(sha256_digest(&zstd::decode_all(&blob[..]).unwrap()), diff_id);
+
+    // The optimized manifest goes with the pushed one
+    let request = Request::delete(format!("/v2/app/manifests/{}", source_digest)).body(Body::empty()).unwrap();
+    assert_eq!(registry.send_with_scopes(request, &["repository:app:*"]).await.status(), StatusCode::ACCEPTED);
+    assert_eq!(pull("GET", &digest).await.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use axum::http::{header, StatusCode};
+use common::{body_json, image_manifest, TestRegistry};
+use std::collections::BTreeSet;
+
+/// Follow `rel="next"` links from `path`, collecting `field` from every page
+async fn collect_pages(registry: &TestRegistry, path: &str, field: &str) -> (Vec<String>, usize) {
+    let mut items = Vec::new();
+    let mut pages = 0;
+    let mut next = Some(path.to_string());
+
+    while let Some(path) = next.take() {
+        let response = registry.get(&path).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        next = response.headers().get(header::LINK).map(|link| {
+            let link = link.to_str().unwrap();
+            assert!(link.ends_with("; rel=\"next\""), "{}", link);
+            link[1..link.find('>').unwrap()].to_string()
+        });
+
+        let body = body_json(response).await;
+        let page: Vec<String> = serde_json::from_value(body[field].clone()).unwrap();
+        assert!(page.len() <= 100);
+        items.extend(page);
+        pages += 1;
+    }
+
+    (items, pages)
+}
+
+#[tokio::test]
+async fn catalog_pages_cover_every_repository() {
+    let registry = TestRegistry::new().await;
+    let mut expected = BTreeSet::new();
+    for i in 0..250 {
+        let repository = format!("team/repo-{}", i);
+        registry.push_image(&repository, "latest", b"layer").await;
+        expected.insert(repository);
+    }
+
+    let (repositories, pages) = collect_pages(&registry, "/v2/_catalog?n=100", "repositories").await;
+    assert_eq!(pages, 3);
+    assert_eq!(repositories.len(), 250);
+    assert_eq!(repositories.into_iter().collect::<BTreeSet<_>>(), expected);
+}
+
+#[tokio::test]
+async fn tag_pages_cover_every_tag() {
+    let registry = TestRegistry::new().await;
+    let config = registry.push_blob("app", b"{}").await;
+    let manifest = image_manifest(&config, 2, &[]);
+    let mut expected = BTreeSet::new();
+    for i in 0..250 {
+        let tag = format!("v{}", i);
+        assert_eq!(registry.put_manifest("app", &tag, &manifest).await.status(), StatusCode::CREATED);
+        expected.insert(tag);
+    }
+
+    let (tags, pages) = collect_pages(&registry, "/v2/app/tags/list?n=100", "tags").await;
+    assert_eq!(pages, 3);
+    assert_eq!(tags.len(), 250);
+    assert_eq!(tags.into_iter().collect::<BTreeSet<_>>(), expected);
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::{body_bytes, sha256_digest, test_config, TestRegistry};
+use drift::config::ProxyConfig;
+
+/// A second registry serving `upstream` under the `hub/` namespace
+async fn mirror(upstream: &str, manifest_ttl_secs: u64) -> TestRegistry {
+    let mut config = test_config();
+    config.proxy = vec![ProxyConfig {
+        namespace: "hub".to_string(),
+        upstream: upstream.to_string(),
+        username: None,
+        password: None,
+        manifest_ttl_secs,
+    }];
+    TestRegistry::with_config(config).await
+}
+
+#[tokio::test]
+async fn misses_are_fetched_from_upstream_and_hits_served_from_the_cache() {
+    let mut config = test_config();
+    config.registry.anonymous_pull = true;
+    let upstream = TestRegistry::with_config(config).await;
+    let (first, first_digest) = upstream.push_image("library/app", "v1", b"first layer").await;
+    let mirror = mirror(&upstream.serve().await, 3600).await;
+    let storage = &mirror.state.storage;
+
+    // A miss is fetched and stored locally
+    assert_eq!(storage.get_manifest("hub/library/app", "v1").await.unwrap(), None);
+    let response = mirror.get("/v2/hub/library/app/manifests/v1").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["Docker-Content-Digest"], first_digest.as_str());
+    assert_eq!(body_bytes(response).await, first);
+    assert_eq!(storage.get_manifest("hub/library/app", "v1").await.unwrap().as_deref(), Some(&first[..]));
+    let layer = sha256_digest(b"first layer");
+    assert_eq!(mirror.get(&format!("/v2/hub/library/app/blobs/{}", layer)).await.status(), StatusCode::OK);
+    assert!(storage.blob_exists(&layer).await.unwrap());
+
+    // Within its TTL the tag is a hit, even though upstream moved it
+    upstream.push_image("library/app", "v1", b"second layer").await;
+    assert_eq!(body_bytes(mirror.get("/v2/hub/library/app/manifests/v1").await).await, first);
+
+    // Mirrors are read-only
+    let response = mirror.put_manifest("hub/library/app", "v2", &first).await;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    // Cached content is served with upstream gone; what was never fetched isn't
+    drop(upstream);
+    let response = mirror.get(&format!("/v2/hub/library/app/manifests/{}", first_digest)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(mirror.get(&format!("/v2/hub/library/app/blobs/{}", layer)).await.status(), StatusCode::OK);
+    let missing = mirror.get("/v2/hub/library/app/manifests/v3").await;
+    assert_ne!(missing.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn expired_tags_are_revalidated_against_upstream() {
+    let mut config = test_config();
+    config.registry.anonymous_pull = true;
+    let upstream = TestRegistry::with_config(config).await;
+    let (first, _) = upstream.push_image("library/app", "latest", b"first layer").await;
+    let mirror = mirror(&upstream.serve().await, 0).await;
+
+    assert_eq!(body_bytes(mirror.get("/v2/hub/library/app/manifests/latest").await).await, first);
+    // Unchanged upstream, the cached copy stays
+    assert_eq!(body_bytes(mirror.get("/v2/hub/library/app/manifests/latest").await).await, first);
+
+    let (second, second_digest) = upstream.push_image("library/app", "latest", b"second layer").await;
+    let response = mirror.get("/v2/hub/library/app/manifests/latest").await;
+    assert_eq!(response.headers()["Docker-Content-Digest"], second_digest.as_str());
+    assert_eq!(body_bytes(response).await, second);
+    let cached = mirror.state.storage.get_manifest("hub/library/app", "latest").await.unwrap();
+    assert_eq!(cached.as_deref(), Some(&second[..]));
+
+    // Upstream unreachable, the last validated copy is served
+    drop(upstream);
+    assert_eq!(body_bytes(mirror.get("/v2/hub/library/app/manifests/latest").await).await, second);
+}
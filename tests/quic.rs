@@ -0,0 +1,324 @@
+//! QUIC peers' messages answered through the registry API. The quinn backend's
+//! tests bind real sockets and run with `--features quinn-quic`.
+
+mod common;
+
+use common::{body_bytes, image_manifest, sha256_digest, test_config, TestRegistry, OCI_MANIFEST, PASSWORD, USERNAME};
+use drift::config::{Config, QuicConfig};
+use drift::quic::{BlobMetadata, QuicMessage, QuicTransport, MAX_BLOB_CHUNK_SIZE};
+use std::net::SocketAddr;
+
+const PEER: &str = "127.0.0.1:5443";
+
+/// A transport on the mock backend, answering through `registry` as `username`
+async fn mock_transport(registry: &TestRegistry, username: Option<&str>, password: &str) -> QuicTransport {
+    let config = QuicConfig {
+        backend: "mock".to_string(),
+        username: username.map(str::to_string),
+        password: Some(password.to_string()),
+        ..Config::default().quic.unwrap()
+    };
+    let transport = QuicTransport::new(config).await.unwrap();
+    transport.serve_registry(registry.router.clone());
+    transport
+}
+
+async fn send(transport: &QuicTransport, message: QuicMessage) -> QuicMessage {
+    transport.send_message(PEER.parse::<SocketAddr>().unwrap(), message).await.unwrap()
+}
+
+fn blob_upload(repository: &str, content: &[u8]) -> QuicMessage {
+    QuicMessage::BlobUpload {
+        repository: repository.to_string(),
+        digest: sha256_digest(content),
+        content: content.to_vec(),
+        metadata: BlobMetadata { size: content.len() as u64, content_type: None, last_modified: chrono::Utc::now() },
+    }
+}
+
+fn manifest_upload(reference: &str, manifest: &[u8]) -> QuicMessage {
+    QuicMessage::ManifestUpload {
+        reference: reference.to_string(),
+        content: manifest.to_vec(),
+        content_type: OCI_MANIFEST.to_string(),
+    }
+}
+
+#[track_caller]
+fn assert_error_code(response: QuicMessage, expected: u16) {
+    match response {
+        QuicMessage::Error { code, .. } => assert_eq!(code, expected),
+        other => panic!("expected a {} error, got {:?}", expected, other),
+    }
+}
+
+#[tokio::test]
+async fn blobs_and_manifests_round_trip_through_the_registry() {
+    let registry = TestRegistry::new().await;
+    let transport = mock_transport(&registry, Some(USERNAME), PASSWORD).await;
+
+    let config = br#"{"os":"linux"}"#;
+    assert!(matches!(send(&transport, blob_upload("team/app", config)).await, QuicMessage::BlobResponse { .. }));
+
+    // Three chunks, the last one short
+    let layer: Vec<u8> = (0..1_200_000u32).map(|i| (i % 251) as u8).collect();
+    let sent = transport.send_blob(PEER.parse().unwrap(), "team/app", &sha256_digest(&layer), &layer[..]).await;
+    assert_eq!(sent.unwrap(), layer.len() as u64);
+
+    let request = QuicMessage::BlobRequest { repository: "team/app".to_string(), digest: sha256_digest(&layer) };
+    match send(&transport, request).await {
+        QuicMessage::BlobResponse { content, metadata, .. } => {
+            assert!(content.unwrap() == layer);
+            assert_eq!(metadata.unwrap().size, layer.len() as u64);
+        }
+        other => panic!("unexpected response {:?}", other),
+    }
+
+    let manifest = image_manifest(&sha256_digest(config), config.len(), &[(sha256_digest(&layer), layer.len())]);
+    let response = send(&transport, manifest_upload("team/app:v1", &manifest)).await;
+    assert!(matches!(response, QuicMessage::ManifestResponse { .. }), "{:?}", response);
+
+    for reference in ["team/app:v1".to_string(), format!("team/app@{}", sha256_digest(&manifest))] {
+        match send(&transport, QuicMessage::ManifestRequest { reference }).await {
+            QuicMessage::ManifestResponse { content, content_type, .. } => {
+                assert_eq!(content.unwrap(), manifest);
+                assert_eq!(content_type.as_deref(), Some(OCI_MANIFEST));
+            }
+            other => panic!("unexpected response {:?}", other),
+        }
+    }
+    // Pushed like any other image, so HTTP clients see it too
+    assert_eq!(body_bytes(registry.get("/v2/team/app/manifests/v1").await).await, manifest);
+
+    // The registry checks the content against its digest
+    let QuicMessage::BlobUpload { repository, content, metadata, .. } = blob_upload("team/app", b"content") else {
+        unreachable!()
+    };
+    let mismatched = QuicMessage::BlobUpload { repository, digest: sha256_digest(b"other"), content, metadata };
+    assert_error_code(send(&transport, mismatched).await, 400);
+}
+
+#[tokio::test]
+async fn peers_are_authenticated_and_authorized_like_http_clients() {
+    let mut config = test_config();
+    config.registry.immutable_tags = vec!["v1".to_string()];
+    let registry = TestRegistry::with_config(config).await;
+    let (manifest, _) = registry.push_image("app", "v1", b"layer").await;
+    let pull = || QuicMessage::ManifestRequest { reference: "app:v1".to_string() };
+
+    for transport in [
+        mock_transport(&registry, None, "").await,
+        mock_transport(&registry, Some(USERNAME), "wrong").await,
+    ] {
+        assert_error_code(send(&transport, pull()).await, 401);
+        assert_error_code(send(&transport, blob_upload("app", b"blob")).await, 401);
+        assert!(matches!(send(&transport, QuicMessage::Ping).await, QuicMessage::Pong));
+    }
+
+    let scopes = vec!["repository:app:pull".to_string()];
+    let (robot, secret) = registry.state.robots.create("mirror", None, scopes, None, "admin").await.unwrap();
+    let puller = mock_transport(&registry, Some(&robot.username()), &secret).await;
+    assert!(matches!(send(&puller, pull()).await, QuicMessage::ManifestResponse { .. }));
+    assert_error_code(send(&puller, blob_upload("app", b"blob")).await, 403);
+    assert_error_code(send(&puller, manifest_upload("app:v2", &manifest)).await, 403);
+
+    // Uploads get the same checks as a push: referenced blobs must exist, and immutable tags stay put
+    let admin = mock_transport(&registry, Some(USERNAME), PASSWORD).await;
+    let config = br#"{"os":"linux"}"#;
+    let other = image_manifest(&sha256_digest(config), config.len(), &[]);
+    assert_error_code(send(&admin, manifest_upload("app:v2", &other)).await, 400);
+    send(&admin, blob_upload("app", config)).await;
+    assert_error_code(send(&admin, manifest_upload("app:v1", &other)).await, 409);
+    assert_eq!(body_bytes(registry.get("/v2/app/manifests/v1").await).await, manifest);
+}
+
+#[tokio::test]
+async fn malformed_names_and_digests_never_reach_storage() {
+    let registry = TestRegistry::filesystem(test_config()).await;
+    let transport = mock_transport(&registry, Some(USERNAME), PASSWORD).await;
+    let valid_digest = sha256_digest(b"blob");
+
+    for (repository, digest) in [
+        ("app", "../../../../etc/shadow"),
+        ("app", "a"),
+        ("app", ""),
+        ("app", "sha256:abc"),
+        ("../../etc", valid_digest.as_str()),
+        ("App", valid_digest.as_str()),
+    ] {
+        let request = QuicMessage::BlobRequest { repository: repository.to_string(), digest: digest.to_string() };
+        assert_error_code(send(&transport, request).await, 400);
+        let chunk = QuicMessage::BlobChunk {
+            repository: repository.to_string(),
+            digest: digest.to_string(),
+            offset: 0,
+            data: b"blob".to_vec(),
+            last: true,
+        };
+        assert_error_code(send(&transport, chunk).await, 400);
+    }
+
+    let manifest = image_manifest(&sha256_digest(b"{}"), 2, &[]);
+    for reference in ["app", "../../x:v1", "app:../../x", "app:", "app@sha256:zz", "app@../x", ":v1"] {
+        assert_error_code(send(&transport, manifest_upload(reference, &manifest)).await, 400);
+        let request = QuicMessage::ManifestRequest { reference: reference.to_string() };
+        assert_error_code(send(&transport, request).await, 400);
+    }
+}
+
+/// `blob` as the chunks `send_blob` sends
+fn chunks(repository: &str, blob: &[u8]) -> Vec<QuicMessage> {
+    let count = blob.len().div_ceil(MAX_BLOB_CHUNK_SIZE);
+    blob.chunks(MAX_BLOB_CHUNK_SIZE)
+        .enumerate()
+        .map(|(i, data)| QuicMessage::BlobChunk {
+            repository: repository.to_string(),
+            digest: sha256_digest(blob),
+            offset: (i * MAX_BLOB_CHUNK_SIZE) as u64,
+            data: data.to_vec(),
+            last: i + 1 == count,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn chunked_uploads_of_each_peer_are_kept_apart() {
+    let registry = TestRegistry::new().await;
+    let transport = mock_transport(&registry, Some(USERNAME), PASSWORD).await;
+    let peers: [SocketAddr; 2] = ["127.0.0.1:5443".parse().unwrap(), "127.0.0.2:5443".parse().unwrap()];
+    let blob: Vec<u8> = (0..3_000_000u32).map(|i| (i % 253) as u8).collect();
+
+    // Two peers sending the same blob, a chunk at a time each
+    let mut sent = peers.map(|_| chunks("app", &blob).into_iter());
+    while let (Some(first), Some(second)) = (sent[0].next(), sent[1].next()) {
+        for (peer, chunk) in peers.iter().zip([first, second]) {
+            let response = transport.send_message(*peer, chunk).await.unwrap();
+            assert!(!matches!(response, QuicMessage::Error { .. }), "{:?}", response);
+        }
+    }
+    assert_eq!(body_bytes(registry.get(&format!("/v2/app/blobs/{}", sha256_digest(&blob))).await).await, blob);
+
+    // And different blobs from one peer at once
+    let other: Vec<u8> = blob.iter().rev().copied().collect();
+    let (digest, other_digest) = (sha256_digest(&blob), sha256_digest(&other));
+    let (sent, other_sent) = tokio::join!(
+        transport.send_blob(peers[0], "app", &digest, &blob[..]),
+        transport.send_blob(peers[0], "app", &other_digest, &other[..]),
+    );
+    assert_eq!((sent.unwrap(), other_sent.unwrap()), (blob.len() as u64, other.len() as u64));
+    assert_eq!(body_bytes(registry.get(&format!("/v2/app/blobs/{}", other_digest)).await).await, other);
+}
+
+#[tokio::test]
+async fn idle_chunked_uploads_are_cancelled() {
+    let registry = TestRegistry::new().await;
+    let config = QuicConfig {
+        backend: "mock".to_string(),
+        username: Some(USERNAME.to_string()),
+        password: Some(PASSWORD.to_string()),
+        chunked_upload_ttl_secs: 1,
+        ..Config::default().quic.unwrap()
+    };
+    let transport = QuicTransport::new(config).await.unwrap();
+    transport.serve_registry(registry.router.clone());
+    let blob = vec![7u8; MAX_BLOB_CHUNK_SIZE + 1];
+    let mut idle = chunks("app", &blob).into_iter();
+
+    let response = send(&transport, idle.next().unwrap()).await;
+    assert!(matches!(response, QuicMessage::BlobChunkAccepted { .. }), "{:?}", response);
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+
+    // Starting another upload sweeps the idle one
+    let other = chunks("app", b"other").remove(0);
+    assert!(matches!(send(&transport, other).await, QuicMessage::BlobResponse { .. }));
+    assert_error_code(send(&transport, idle.next().unwrap()).await, 404);
+}
+
+#[cfg(feature = "quinn-quic")]
+mod quinn {
+    use super::*;
+    use std::time::Duration;
+
+    /// The quinn backend on an ephemeral loopback port, with the fixture certificate
+    fn quinn_config(username: Option<&str>, password: &str) -> QuicConfig {
+        let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/quic");
+        QuicConfig {
+            backend: "quinn".to_string(),
+            bind_addr: "127.0.0.1:0".parse().unwrap(),
+            cert_path: format!("{}/cert.pem", fixtures),
+            key_path: format!("{}/key.pem", fixtures),
+            username: username.map(str::to_string),
+            password: Some(password.to_string()),
+            ..Config::default().quic.unwrap()
+        }
+    }
+
+    /// A quinn endpoint answering through `registry`, returning it and the address it listens on
+    async fn listen(registry: &TestRegistry) -> (QuicTransport, SocketAddr) {
+        let server = QuicTransport::new(quinn_config(None, "")).await.unwrap();
+        server.serve_registry(registry.router.clone());
+        let address = server.local_addr().unwrap();
+        assert_ne!(address.port(), 0);
+        let listener = server.clone();
+        tokio::spawn(async move { listener.listen(address).await });
+        (server, address)
+    }
+
+    #[tokio::test]
+    async fn quinn_endpoints_answer_pings_on_their_bound_port() {
+        let (_, address) = listen(&TestRegistry::new().await).await;
+
+        let client = QuicTransport::new(quinn_config(None, "")).await.unwrap();
+        let pong = tokio::time::timeout(Duration::from_secs(10), client.ping(address)).await.unwrap();
+        assert!(pong.unwrap());
+        // The connection is pooled and reused for the next message
+        assert!(client.ping(address).await.unwrap());
+        assert_eq!(client.get_stats().await["active_connections"], 1);
+    }
+
+    #[tokio::test]
+    async fn quinn_connections_authenticate_before_their_messages() {
+        let registry = TestRegistry::new().await;
+        let (_, address) = listen(&registry).await;
+        let (_, digest) = registry.push_image("app", "v1", b"layer").await;
+        let request = || QuicMessage::ManifestRequest { reference: format!("app@{}", digest) };
+
+        let anonymous = QuicTransport::new(quinn_config(None, "")).await.unwrap();
+        assert_error_code(anonymous.send_message(address, request()).await.unwrap(), 401);
+
+        let impostor = QuicTransport::new(quinn_config(Some(USERNAME), "wrong")).await.unwrap();
+        assert!(impostor.send_message(address, request()).await.is_err());
+
+        let client = QuicTransport::new(quinn_config(Some(USERNAME), PASSWORD)).await.unwrap();
+        let response = client.send_message(address, request()).await.unwrap();
+        assert!(matches!(response, QuicMessage::ManifestResponse { content: Some(_), .. }), "{:?}", response);
+    }
+
+    #[tokio::test]
+    async fn messages_to_one_peer_share_a_connection() {
+        let registry = TestRegistry::new().await;
+        let (server, address) = listen(&registry).await;
+        let (_, digest) = registry.push_image("app", "v1", b"layer").await;
+
+        let client = QuicTransport::new(quinn_config(Some(USERNAME), PASSWORD)).await.unwrap();
+        for _ in 0..5 {
+            assert!(client.ping(address).await.unwrap());
+            let request = QuicMessage::ManifestRequest { reference: format!("app@{}", digest) };
+            let response = client.send_message(address, request).await.unwrap();
+            assert!(matches!(response, QuicMessage::ManifestResponse { content: Some(_), .. }), "{:?}", response);
+        }
+        assert_eq!(client.get_stats().await["active_connections"], 1);
+        assert_eq!(server.get_stats().await["open_connections"], 1);
+    }
+
+    #[tokio::test]
+    async fn quinn_endpoints_need_a_certificate() {
+        let config = QuicConfig { cert_path: String::new(), key_path: String::new(), ..quinn_config(None, "") };
+        let error = QuicTransport::new(config).await.err().unwrap().to_string();
+        assert!(error.contains("needs a certificate"), "{}", error);
+
+        let config = QuicConfig { cert_path: "/nonexistent/cert.pem".to_string(), ..quinn_config(None, "") };
+        let error = QuicTransport::new(config).await.err().unwrap().to_string();
+        assert!(error.contains("/nonexistent/cert.pem"), "{}", error);
+    }
+}
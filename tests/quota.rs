@@ -0,0 +1,91 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+};
+use common::{body_json, sha256_digest, test_config, TestRegistry, USERNAME};
+use drift::rbac::{Organization, OrganizationSettings};
+use std::collections::{HashMap, HashSet};
+
+const GB: u64 = 1024 * 1024 * 1024;
+
+/// A registry whose `acme` organization owns `acme/app` with a 1 GB quota
+async fn registry() -> TestRegistry {
+    let mut config = test_config();
+    config.rbac.as_mut().unwrap().enabled = true;
+    let registry = TestRegistry::with_config(config).await;
+
+    let rbac = registry.state.rbac.as_ref().unwrap();
+    rbac.provision_user(USERNAME, "admin@example.com", "Admin").await.unwrap();
+    rbac.assign_role(USERNAME, "developer").await.unwrap();
+    rbac.create_organization(Organization {
+        id: "acme".to_string(),
+        name: "Acme".to_string(),
+        description: String::new(),
+        owner_id: USERNAME.to_string(),
+        members: HashSet::new(),
+        teams: HashMap::new(),
+        repositories: ["acme/app".to_string()].into_iter().collect(),
+        settings: OrganizationSettings {
+            require_2fa: false,
+            allow_public_repos: false,
+            default_visibility: "private".to_string(),
+            max_members: None,
+            max_repositories: None,
+            storage_quota_gb: Some(1),
+            allowed_domains: Vec::new(),
+            webhook_url: None,
+        },
+        created_at: chrono::Utc::now(),
+        updated_at: chrono::Utc::now(),
+    })
+    .await
+    .unwrap();
+    registry
+}
+
+/// Pretend the organization has `free` bytes left of its quota
+async fn fill_quota(registry: &TestRegistry, free: u64) {
+    let rbac = registry.state.rbac.as_ref().unwrap();
+    let mut usage = rbac.get_organization_usage("acme").await.unwrap();
+    usage.used_bytes = GB - free;
+    rbac.set_organization_usage(usage).await;
+}
+
+#[tokio::test]
+async fn content_the_organization_already_stores_is_not_charged_again() {
+    let registry = registry().await;
+    let (manifest, _) = registry.push_image("acme/app", "v1", b"layer").await;
+    fill_quota(&registry, 10).await;
+
+    // The manifest and its blobs are all stored already
+    assert_eq!(registry.put_manifest("acme/app", "v2", &manifest).await.status(), StatusCode::CREATED);
+    registry.push_blob("acme/app", b"layer").await;
+
+    let rbac = registry.state.rbac.as_ref().unwrap();
+    assert_eq!(rbac.get_organization_usage("acme").await.unwrap().used_bytes, GB - 10);
+}
+
+#[tokio::test]
+async fn uploads_over_quota_are_rejected_and_cancelled() {
+    let registry = registry().await;
+    registry.push_image("acme/app", "v1", b"layer").await;
+    fill_quota(&registry, 10).await;
+
+    let location = registry.start_upload("acme/app").await;
+    let blob = vec![5u8; 100];
+    let response = registry
+        .send_authenticated(
+            Request::put(format!("{}?digest={}", location, sha256_digest(&blob)))
+                .header(header::CONTENT_TYPE, "application/octet-stream")
+                .body(Body::from(blob))
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(body_json(response).await["errors"][0]["detail"]["requested_bytes"], 100);
+
+    assert_eq!(registry.get(&location).await.status(), StatusCode::NOT_FOUND);
+    assert!(registry.state.storage.list_uploads().await.unwrap().is_empty());
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use axum::http::{header, StatusCode};
+use common::{body_json, test_config, TestRegistry};
+use drift::metrics::export_rate_limits;
+
+#[tokio::test]
+async fn pulls_past_the_hourly_limit_are_throttled() {
+    let mut config = test_config();
+    config.registry.rate_limit_per_hour = 3;
+    let registry = TestRegistry::with_config(config).await;
+
+    for _ in 0..3 {
+        let response = registry.get("/v2/app/manifests/latest").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    let response = registry.get("/v2/app/manifests/latest").await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = response.headers()[header::RETRY_AFTER].to_str().unwrap().parse().unwrap();
+    assert!((1..=1200).contains(&retry_after), "{}", retry_after);
+    assert_eq!(body_json(response).await["errors"][0]["code"], "TOOMANYREQUESTS");
+
+    // Pushes are counted separately
+    registry.start_upload("app").await;
+
+    // Throttling is graphed per operation class, never per user or address
+    let metrics = export_rate_limits(&registry.state.rate_limiter);
+    assert!(metrics.contains("drift_rate_limit_exhausted_buckets{class=\"pull\"} 1\n"), "{}", metrics);
+    assert!(metrics.contains("drift_rate_limit_buckets{class=\"push\"} 1\n"), "{}", metrics);
+    assert!(metrics.contains("drift_rate_limit_throttled_total{class=\"pull\"} 1\n"), "{}", metrics);
+    assert!(!metrics.contains("admin") && !metrics.contains("127.0.0.1"), "{}", metrics);
+}
@@ -0,0 +1,317 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+};
+use bytes::Bytes;
+use common::{body_json, s3::MockS3, sha256_digest, test_config, TestRegistry};
+use drift::{
+    config::{GarbageCollectorConfig, StorageCacheConfig, StorageType},
+    garbage_collector::GarbageCollector,
+    storage::{caching::CachingStorage, s3::S3Storage, StorageBackend},
+};
+use futures::stream::{self, StreamExt};
+
+const MIB: usize = 1024 * 1024;
+/// Smallest part S3 accepts, other than the last
+const MIN_PART_SIZE: usize = 5 * MIB;
+
+fn retries() -> prometheus::IntCounterVec {
+    prometheus::IntCounterVec::new(prometheus::Opts::new("s3_retries", "S3 retries"), &["operation"]).unwrap()
+}
+
+async fn storage() -> (MockS3, S3Storage) {
+    let (mock, config) = MockS3::start().await;
+    let storage = S3Storage::new(&config, retries()).await.unwrap();
+    (mock, storage)
+}
+
+/// A body arriving as 64 KiB pieces, the way a PATCH is streamed from the client
+fn body(data: &[u8]) -> futures::stream::BoxStream<'static, anyhow::Result<Bytes>> {
+    let pieces: Vec<_> = data.chunks(64 * 1024).map(|piece| Ok(Bytes::copy_from_slice(piece))).collect();
+    stream::iter(pieces).boxed()
+}
+
+#[tokio::test]
+async fn chunked_upload_is_assembled_from_multipart_parts() {
+    let (mock, storage) = storage().await;
+    let chunk: Vec<u8> = (0..3 * MIB).map(|i| (i % 251) as u8).collect();
+    let mut blob = Vec::new();
+
+    storage.create_upload("session").await.unwrap();
+    for _ in 0..4 {
+        let offset = storage.append_upload("session", body(&chunk)).await.unwrap();
+        blob.extend_from_slice(&chunk);
+        assert_eq!(offset, blob.len() as u64);
+    }
+    let digest = sha256_digest(&blob);
+    storage.complete_upload("session", &digest).await.unwrap();
+
+    assert_eq!(storage.get_blob(&digest).await.unwrap().as_deref(), Some(&blob[..]));
+    assert!(mock.keys("uploads/").is_empty());
+
+    // Data reached S3 as parts; no request carried more than a part
+    let requests = mock.requests();
+    let parts: Vec<_> = requests.iter().filter(|request| request.operation == "UploadPart").collect();
+    assert_eq!(parts.len(), 3);
+    assert_eq!(parts.iter().map(|part| part.size).sum::<usize>(), blob.len());
+    assert!(requests.iter().all(|request| request.size <= MIN_PART_SIZE), "{:?}", requests);
+}
+
+#[tokio::test]
+async fn chunked_upload_with_the_wrong_digest_is_discarded() {
+    let (mock, storage) = storage().await;
+    storage.create_upload("session").await.unwrap();
+    storage.append_upload("session", body(b"content")).await.unwrap();
+
+    let wrong = sha256_digest(b"other");
+    assert!(storage.complete_upload("session", &wrong).await.is_err());
+    assert!(!storage.blob_exists(&wrong).await.unwrap());
+    assert!(mock.keys("uploads/").is_empty());
+}
+
+/// The in-process registry on top of the mock S3
+async fn registry() -> (MockS3, TestRegistry) {
+    let (mock, s3) = MockS3::start().await;
+    let mut config = test_config();
+    config.storage.storage_type = StorageType::S3;
+    config.storage.s3 = Some(s3);
+    (mock, TestRegistry::with_config(config).await)
+}
+
+#[tokio::test]
+async fn expired_upload_sessions_are_refused_and_cleaned_up() {
+    let (mock, registry) = registry().await;
+    let stale = registry.start_upload("app").await;
+    let fresh = registry.start_upload("app").await;
+    let response = registry
+        .send_authenticated(
+            Request::patch(&stale)
+                .header(header::CONTENT_TYPE, "application/octet-stream")
+                .body(Body::from(vec![7u8; 1000]))
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+    // Move the stale session's last activity past the 24 hour TTL. Sessions are
+    // stored under their own IDs; the stale one is the session holding data.
+    let sessions = registry.state.storage.list_uploads().await.unwrap();
+    let uuid = &sessions.iter().find(|session| session.size == 1000).unwrap().uuid;
+    let session_keys = mock.keys(&format!("uploads/{}/", uuid));
+    assert!(!session_keys.is_empty());
+    for key in &session_keys {
+        mock.set_modified(key, chrono::Utc::now() - chrono::Duration::hours(25));
+    }
+
+    let response = registry.get(&stale).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(body_json(response).await["errors"][0]["code"], "BLOB_UPLOAD_UNKNOWN");
+    assert_eq!(registry.get(&fresh).await.status(), StatusCode::NO_CONTENT);
+
+    let gc = GarbageCollector::new(GarbageCollectorConfig::default(), registry.state.storage.clone(), 24);
+    let metrics = gc.trigger_manual_run().await.unwrap();
+    assert_eq!(metrics.upload_sessions_deleted, 1);
+    assert_eq!(metrics.upload_bytes_freed, 1000);
+
+    assert!(mock.keys(&format!("uploads/{}/", uuid)).is_empty());
+    assert_eq!(registry.get(&stale).await.status(), StatusCode::NOT_FOUND);
+    assert_eq!(registry.get(&fresh).await.status(), StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn deleting_by_digest_untags_tags_pushed_before_tag_markers() {
+    let (mock, registry) = registry().await;
+    let (manifest, digest) = registry.push_image("app", "v1", b"layer").await;
+    assert_eq!(registry.put_manifest("app", "v2", &manifest).await.status(), StatusCode::CREATED);
+
+    // v1 predates digest -> tag markers
+    mock.remove(&format!("manifest_tags/app/{}/v1", digest));
+
+    registry.state.storage.rebuild_manifest_index().await.unwrap();
+    assert_eq!(mock.keys(&format!("manifest_tags/app/{}/", digest)).len(), 2);
+
+    // Only stored under its tags, the manifest is still found by digest
+    let request = Request::delete(format!("/v2/app/manifests/{}", digest)).body(Body::empty()).unwrap();
+    let response = registry.send_with_scopes(request, &["repository:app:*"]).await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    for reference in ["v1", "v2", digest.as_str()] {
+        let response = registry.get(&format!("/v2/app/manifests/{}", reference)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", reference);
+    }
+    assert!(mock.keys("manifest_tags/").is_empty());
+}
+
+#[tokio::test]
+async fn blob_head_answers_from_metadata_without_reading_content() {
+    let (mock, registry) = registry().await;
+    let layer = vec![3u8; 64 * 1024];
+    let digest = registry.push_blob("app", &layer).await;
+
+    let before = mock.requests().len();
+    let response = registry.head(&format!("/v2/app/blobs/{}", digest)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_LENGTH], layer.len().to_string().as_str());
+    assert_eq!(response.headers()["Docker-Content-Digest"], digest.as_str());
+    assert_eq!(response.headers()[header::ACCEPT_RANGES], "bytes");
+
+    let requests = mock.requests().split_off(before);
+    assert!(requests.iter().all(|request| request.operation != "GetObject"), "{:?}", requests);
+    assert_eq!(requests.iter().filter(|request| request.operation == "HeadObject").count(), 1, "{:?}", requests);
+
+    let missing = sha256_digest(b"missing");
+    let response = registry.head(&format!("/v2/app/blobs/{}", missing)).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn reads_are_retried_through_transient_unavailability() {
+    let (mock, config) = MockS3::start().await;
+    let retries = retries();
+    let storage = S3Storage::new(&config, retries.clone()).await.unwrap();
+    let digest = sha256_digest(b"content");
+    storage.put_blob(&digest, Bytes::from_static(b"content")).await.unwrap();
+
+    // Two 503s, then the object
+    mock.fail(Method::GET, "", 2);
+    assert_eq!(storage.get_blob(&digest).await.unwrap().as_deref(), Some(&b"content"[..]));
+    let failed = mock.requests().iter().filter(|request| request.operation == "ServiceUnavailable").count();
+    assert_eq!(failed, 2);
+    assert_eq!(retries.with_label_values(&["GetObject"]).get(), 2);
+
+    // Attempts run out after `max_attempts`; a missing object is not retried
+    mock.fail(Method::GET, "", config.max_attempts as usize);
+    assert!(storage.get_blob(&digest).await.is_err());
+    let before = mock.requests().len();
+    assert_eq!(storage.get_blob(&sha256_digest(b"missing")).await.unwrap(), None);
+    assert_eq!(mock.requests().len(), before + 1);
+}
+
+#[tokio::test]
+async fn writes_listings_and_interrupted_reads_are_retried() {
+    let (mock, config) = MockS3::start().await;
+    let retries = retries();
+    let storage = S3Storage::new(&config, retries.clone()).await.unwrap();
+    let content = Bytes::from(vec![7u8; 256 * 1024]);
+    let digest = sha256_digest(&content);
+
+    // Each operation fails twice, then succeeds
+    mock.fail(Method::PUT, "blobs/", 2);
+    storage.put_blob(&digest, content.clone()).await.unwrap();
+    assert_eq!(mock.keys("blobs/").len(), 1);
+
+    storage.put_manifest("app", "latest", Bytes::from_static(b"{}"), "application/json").await.unwrap();
+    mock.fail(Method::GET, "", 2);
+    assert_eq!(storage.list_tags("app").await.unwrap(), vec!["latest".to_string()]);
+
+    // A body cut off partway through is fetched again
+    mock.truncate("blobs/", 2);
+    assert_eq!(storage.get_blob(&digest).await.unwrap(), Some(content.clone()));
+    let truncated = mock.requests().iter().filter(|request| request.operation == "TruncatedBody").count();
+    assert_eq!(truncated, 2);
+    assert_eq!(retries.with_label_values(&["GetObjectBody"]).get(), 2);
+
+    // Starting and completing a multipart upload
+    let blob: Vec<u8> = (0..MIN_PART_SIZE + MIB).map(|i| (i % 251) as u8).collect();
+    mock.fail(Method::POST, "", 2);
+    storage.create_upload("session").await.unwrap();
+    storage.append_upload("session", body(&blob)).await.unwrap();
+    mock.fail(Method::POST, "", 2);
+    storage.complete_upload("session", &sha256_digest(&blob)).await.unwrap();
+    assert_eq!(storage.get_blob(&sha256_digest(&blob)).await.unwrap().as_deref(), Some(&blob[..]));
+
+    mock.fail(Method::DELETE, "blobs/", 2);
+    storage.delete_blob(&digest).await.unwrap();
+    assert_eq!(mock.keys("blobs/").len(), 1);
+
+    let failed = mock.requests().iter().filter(|request| request.operation == "ServiceUnavailable").count();
+    assert_eq!(failed, 10);
+}
+
+#[tokio::test]
+async fn digest_lookups_in_a_repository_with_many_tags_take_a_single_request() {
+    let (mock, config) = MockS3::start().await;
+    let storage = S3Storage::new(&config, retries()).await.unwrap();
+    let manifests: Vec<Bytes> =
+        (0..50).map(|i| Bytes::from(format!("{{\"schemaVersion\":2,\"tag\":{}}}", i))).collect();
+    for (i, manifest) in manifests.iter().enumerate() {
+        storage.put_manifest("app", &format!("v{}", i), manifest.clone(), "application/json").await.unwrap();
+    }
+    storage.rebuild_manifest_index().await.unwrap();
+    let digest = sha256_digest(&manifests[17]);
+
+    let before = mock.requests().len();
+    assert_eq!(storage.get_manifest_by_digest("app", &digest).await.unwrap(), manifests[17]);
+    let lookup: Vec<_> = mock.requests().into_iter().skip(before).map(|request| request.operation).collect();
+    assert_eq!(lookup, ["GetObject"]);
+
+    let before = mock.requests().len();
+    assert_eq!(storage.get_manifest_metadata("app", &digest).await.unwrap().size, manifests[17].len() as u64);
+    let lookup: Vec<_> = mock.requests().into_iter().skip(before).map(|request| request.operation).collect();
+    assert_eq!(lookup, ["HeadObject"]);
+
+    // Until the index has been built, manifests stored without an entry are still found by scanning
+    mock.remove("manifests-by-digest/.complete");
+    mock.remove(&format!("manifests-by-digest/app/{}", digest));
+    let storage = S3Storage::new(&config, retries()).await.unwrap();
+    assert_eq!(storage.get_manifest_by_digest("app", &digest).await.unwrap(), manifests[17]);
+}
+
+async fn cached_storage(max_size_mb: u64) -> (MockS3, tempfile::TempDir, CachingStorage) {
+    let (mock, storage) = storage().await;
+    let dir = tempfile::tempdir().unwrap();
+    let config = StorageCacheConfig { path: dir.path().to_string_lossy().into_owned(), max_size_mb };
+    let storage = CachingStorage::new(std::sync::Arc::new(storage), &config).await.unwrap();
+    (mock, dir, storage)
+}
+
+fn object_reads(mock: &MockS3, since: usize) -> usize {
+    mock.requests().split_off(since).iter().filter(|request| request.operation == "GetObject").count()
+}
+
+#[tokio::test]
+async fn cached_blobs_are_served_without_a_backend_read() {
+    let (mock, _dir, storage) = cached_storage(16).await;
+    let layer = vec![5u8; 256 * 1024];
+    let digest = sha256_digest(&layer);
+    storage.put_blob(&digest, Bytes::from(layer.clone())).await.unwrap();
+
+    let before = mock.requests().len();
+    assert_eq!(storage.get_blob(&digest).await.unwrap().as_deref(), Some(&layer[..]));
+    assert_eq!(object_reads(&mock, before), 1);
+
+    let before = mock.requests().len();
+    assert_eq!(storage.get_blob(&digest).await.unwrap().as_deref(), Some(&layer[..]));
+    let (size, stream) = storage.get_blob_stream(&digest).await.unwrap().unwrap();
+    let streamed: Vec<u8> = stream.map(|chunk| chunk.unwrap().to_vec()).concat().await;
+    assert_eq!((size, streamed), (layer.len() as u64, layer));
+    assert_eq!(mock.requests().len(), before, "{:?}", mock.requests().split_off(before));
+
+    // Deletes go through to the backend and drop the cached copy
+    storage.delete_blob(&digest).await.unwrap();
+    assert_eq!(storage.get_blob(&digest).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn cache_evicts_least_recently_used_blobs_to_stay_within_its_size() {
+    let (mock, dir, storage) = cached_storage(1).await;
+    let layers: Vec<Vec<u8>> = (0..3u8).map(|i| vec![i; 400 * 1024]).collect();
+    let digests: Vec<String> = layers.iter().map(|layer| sha256_digest(layer)).collect();
+    for (digest, layer) in digests.iter().zip(&layers) {
+        storage.put_blob(digest, Bytes::from(layer.clone())).await.unwrap();
+        storage.get_blob(digest).await.unwrap().unwrap();
+    }
+
+    let cached: u64 =
+        std::fs::read_dir(dir.path()).unwrap().map(|entry| entry.unwrap().metadata().unwrap().len()).sum();
+    assert_eq!(cached, 2 * 400 * 1024);
+
+    // The first blob was evicted to make room for the third; the others still hit
+    let before = mock.requests().len();
+    storage.get_blob(&digests[2]).await.unwrap().unwrap();
+    storage.get_blob(&digests[1]).await.unwrap().unwrap();
+    assert_eq!(object_reads(&mock, before), 0);
+    assert_eq!(storage.get_blob(&digests[0]).await.unwrap().as_deref(), Some(&layers[0][..]));
+    assert_eq!(object_reads(&mock, before), 1);
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::{test_config, TestRegistry};
+use drift::{
+    config::{ScanningConfig, Severity},
+    scanning::{ScanRecord, ScanStatus},
+};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+/// A stand-in for trivy that logs how it was called, and fails while a `fail`
+/// file sits next to it. Otherwise it reports one critical vulnerability.
+fn fake_trivy(dir: &Path) -> String {
+    let path = dir.join("trivy");
+    let script = r#"#!/bin/sh
+dir=$(dirname "$0")
+echo "token=$TRIVY_TOKEN args=$*" >> "$dir/calls"
+[ -e "$dir/fail" ] && exit 1
+echo '{"Results":[{"Vulnerabilities":[{"Severity":"CRITICAL"}]}]}'
+"#;
+    std::fs::write(&path, script).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+async fn scanned(registry: &TestRegistry, digest: &str, status: ScanStatus) -> ScanRecord {
+    let scanning = registry.state.scanning.as_ref().unwrap();
+    for _ in 0..200 {
+        if let Some(record) = scanning.get_scan("app", digest).await.unwrap()
+            && record.status == status
+        {
+            return record;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(25)).await;
+    }
+    panic!("{} was never {:?}", digest, status);
+}
+
+#[tokio::test]
+async fn a_blocked_image_stays_blocked_while_its_rescan_is_pending_or_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut config = test_config();
+    config.scanning = Some(ScanningConfig {
+        enabled: true,
+        trivy_path: fake_trivy(dir.path()),
+        server_url: Some("http://trivy.invalid:4954".to_string()),
+        server_token: Some("scanner-secret".to_string()),
+        timeout_secs: 30,
+        max_concurrent_scans: 1,
+        block_pull_above_severity: Some(Severity::High),
+        exempt_repositories: Vec::new(),
+    });
+    let registry = TestRegistry::with_config(config).await;
+
+    let (manifest, digest) = registry.push_image("app", "latest", b"layer").await;
+    let first = scanned(&registry, &digest, ScanStatus::Completed).await;
+    assert_eq!(first.summary.critical, 1);
+    assert_eq!(registry.get("/v2/app/manifests/latest").await.status(), StatusCode::FORBIDDEN);
+
+    // The token reaches trivy through its environment, never its arguments
+    let calls = std::fs::read_to_string(dir.path().join("calls")).unwrap();
+    let (token, args) = calls.trim().split_once(" args=").unwrap();
+    assert_eq!(token, "token=scanner-secret");
+    assert!(!args.contains("secret"), "{}", args);
+
+    // A rescan that fails leaves the last completed scan, and its report, in force
+    std::fs::write(dir.path().join("fail"), b"").unwrap();
+    assert_eq!(registry.put_manifest("app", "latest", &manifest).await.status(), StatusCode::CREATED);
+    assert_eq!(registry.get("/v2/app/manifests/latest").await.status(), StatusCode::FORBIDDEN);
+    let errored = scanned(&registry, &digest, ScanStatus::Errored).await;
+    assert_eq!(errored.last_completed, Some(first.summary.clone()));
+    assert_eq!(errored.report_manifest, first.report_manifest);
+    assert_eq!(registry.get("/v2/app/manifests/latest").await.status(), StatusCode::FORBIDDEN);
+    let report = format!("/v2/app/manifests/{}", first.report_manifest.unwrap());
+    assert_eq!(registry.get(&report).await.status(), StatusCode::OK);
+}
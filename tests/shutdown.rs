@@ -0,0 +1,73 @@
+mod common;
+
+use common::{sha256_digest, test_config, PASSWORD, USERNAME};
+use drift::server::Server;
+use std::time::Duration;
+
+fn free_address() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn in_flight_downloads_complete_before_shutdown() {
+    let (api, ui) = (free_address(), free_address());
+    let server = Server::new(test_config(), &api, &ui).await.unwrap();
+    let (signal, shutdown) = tokio::sync::oneshot::channel::<()>();
+    let running = tokio::spawn(server.run_until(async move {
+        let _ = shutdown.await;
+    }));
+
+    let client = reqwest::Client::new();
+    let base = format!("http://{}", api);
+    let ready = async {
+        while client.get(format!("{}/health", base)).send().await.is_err() {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(10), ready).await.unwrap();
+
+    // Large enough that the response can't sit whole in socket buffers
+    let blob = vec![7u8; 32 * 1024 * 1024];
+    let digest = sha256_digest(&blob);
+    let response = client
+        .post(format!("{}/v2/app/blobs/uploads/", base))
+        .basic_auth(USERNAME, Some(PASSWORD))
+        .send()
+        .await
+        .unwrap();
+    let location = response.headers()["location"].to_str().unwrap().to_string();
+    let response = client
+        .put(format!("{}{}?digest={}", base, location, digest))
+        .basic_auth(USERNAME, Some(PASSWORD))
+        .body(blob.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+
+    let mut download = client
+        .get(format!("{}/v2/app/blobs/{}", base, digest))
+        .basic_auth(USERNAME, Some(PASSWORD))
+        .send()
+        .await
+        .unwrap();
+    let mut received = download.chunk().await.unwrap().unwrap().to_vec();
+
+    // Shut down mid-download, then read the rest slowly
+    signal.send(()).unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!running.is_finished(), "the server exited with a download in flight");
+    assert!(client.get(format!("{}/health", base)).send().await.is_err(), "new connections are still accepted");
+    let mut chunks = 0;
+    while let Some(chunk) = download.chunk().await.unwrap() {
+        received.extend_from_slice(&chunk);
+        chunks += 1;
+        if chunks % 64 == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+    assert_eq!(sha256_digest(&received), digest);
+
+    tokio::time::timeout(Duration::from_secs(10), running).await.unwrap().unwrap().unwrap();
+}
@@ -0,0 +1,241 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+};
+use common::{body_json, sha256_digest, test_config, TestRegistry, OCI_CONFIG, OCI_MANIFEST, USERNAME};
+use drift::{
+    config::{Config, SigningKeyConfig, VerificationKeyConfig},
+    signing::{SignatureAlgorithm, SignatureFormat, SignaturePayload},
+    tag_history::TagAction,
+};
+use serde_json::json;
+use std::collections::HashMap;
+
+fn fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/signing/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+/// Signing enabled with the p256 fixture key, and signatures required on every pull
+fn enforcing_config() -> Config {
+    let mut config = test_config();
+    let signing = config.signing.as_mut().unwrap();
+    signing.enabled = true;
+    signing.verification_policy.require_signatures = true;
+    signing.signing_keys.push(SigningKeyConfig {
+        key_id: "fixture".to_string(),
+        algorithm: SignatureAlgorithm::EcdsaP256Sha256,
+        key_path: fixture("p256.pem"),
+        certificate_path: None,
+        password: None,
+    });
+    signing.verification_keys.push(VerificationKeyConfig {
+        key_id: "fixture".to_string(),
+        algorithm: SignatureAlgorithm::EcdsaP256Sha256,
+        public_key_path: fixture("p256.pub"),
+        certificate_path: None,
+        trusted: true,
+    });
+    config
+}
+
+fn payload(repository: &str) -> SignaturePayload {
+    SignaturePayload {
+        subject: "manifest".to_string(),
+        content_type: "manifest".to_string(),
+        repository: repository.to_string(),
+        tag: None,
+        metadata: HashMap::new(),
+        timestamp: chrono::Utc::now(),
+    }
+}
+
+#[tokio::test]
+async fn unsigned_image_pull_is_blocked_under_an_enforcing_policy() {
+    let registry = TestRegistry::with_config(enforcing_config()).await;
+    let (manifest, digest) = registry.push_image("app", "latest", b"layer").await;
+
+    for reference in ["latest", digest.as_str()] {
+        let response = registry.get(&format!("/v2/app/manifests/{}", reference)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = body_json(response).await;
+        assert_eq!(body["errors"][0]["code"], "DENIED");
+    }
+
+    // Signing the manifest lets it through
+    let signing = registry.state.signing.as_ref().unwrap();
+    signing.sign_content(&manifest, "fixture", SignatureFormat::Simple, payload("app")).await.unwrap();
+    let response = registry.get("/v2/app/manifests/latest").await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+/// Cosign's tag for the signatures of a manifest
+fn signature_tag(digest: &str) -> String {
+    format!("{}.sig", digest.replace(':', "-"))
+}
+
+async fn sign(registry: &TestRegistry, repository: &str, digest: &str, signer: usize) -> String {
+    let request = Request::post(format!("/api/v1/repositories/{}/manifests/{}/sign", repository, digest))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            json!({ "key_id": "fixture", "format": "cosign", "metadata": { "signer": signer } }).to_string(),
+        ))
+        .unwrap();
+    let response = registry.send_with_scopes(request, &["registry:*"]).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    body_json(response).await["signature_id"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn concurrent_cosign_signatures_all_land_in_the_signature_artifact() {
+    let registry = TestRegistry::with_config(enforcing_config()).await;
+    let (_, digest) = registry.push_image("app", "latest", b"layer").await;
+    let tag = signature_tag(&digest);
+
+    // Each signer signs different metadata, so no two signatures are the same
+    let signature_ids = futures::future::join_all((0..4).map(|signer| sign(&registry, "app", &digest, signer))).await;
+    let response = registry.get(&format!("/v2/app/manifests/{}", tag)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body_json(response).await["layers"].as_array().unwrap().len(), 4);
+    assert_eq!(registry.get("/v2/app/manifests/latest").await.status(), StatusCode::OK);
+
+    // The artifact went through the tag history like any pushed tag
+    let revisions = registry.state.tag_history.revisions("app", &tag).await.unwrap();
+    assert_eq!(revisions.len(), 4);
+    assert!(revisions.iter().all(|revision| revision.action == TagAction::Push));
+    assert!(revisions.iter().all(|revision| revision.user.as_deref() == Some(USERNAME)));
+
+    // Revoking the last signature takes the artifact, and the image is unsigned again
+    for id in &signature_ids {
+        let request = Request::delete(format!("/api/v1/signatures/{}", id)).body(Body::empty()).unwrap();
+        let response = registry.send_with_scopes(request, &["registry:*"]).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+    assert_eq!(registry.get(&format!("/v2/app/manifests/{}", tag)).await.status(), StatusCode::NOT_FOUND);
+    let revisions = registry.state.tag_history.revisions("app", &tag).await.unwrap();
+    assert_eq!(revisions.last().unwrap().action, TagAction::Delete);
+    assert_eq!(registry.get("/v2/app/manifests/latest").await.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn only_artifacts_under_a_signature_tag_skip_the_signature_policy() {
+    let registry = TestRegistry::with_config(enforcing_config()).await;
+    let config = registry.push_blob("app", b"{}").await;
+    let payload = br#"{"critical":{}}"#;
+    let layer = registry.push_blob("app", payload).await;
+    let manifest = serde_json::to_vec(&json!({
+        "schemaVersion": 2,
+        "mediaType": OCI_MANIFEST,
+        "config": { "mediaType": OCI_CONFIG, "digest": config, "size": 2 },
+        "layers": [{
+            "mediaType": "application/vnd.dev.cosign.simplesigning.v1+json",
+            "digest": layer,
+            "size": payload.len(),
+        }],
+    }))
+    .unwrap();
+    let digest = sha256_digest(&manifest);
+
+    // Shaped like a signature artifact, but under an ordinary tag
+    assert_eq!(registry.put_manifest("app", "latest", &manifest).await.status(), StatusCode::CREATED);
+    for reference in ["latest", digest.as_str()] {
+        let response = registry.get(&format!("/v2/app/manifests/{}", reference)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN, "{}", reference);
+    }
+
+    let tag = signature_tag(&sha256_digest(b"signed image"));
+    assert_eq!(registry.put_manifest("app", &tag, &manifest).await.status(), StatusCode::CREATED);
+    for reference in [tag.as_str(), digest.as_str()] {
+        let response = registry.get(&format!("/v2/app/manifests/{}", reference)).await;
+        assert_eq!(response.status(), StatusCode::OK, "{}", reference);
+    }
+    assert_eq!(registry.get("/v2/app/manifests/latest").await.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn pushes_past_the_grace_period_need_signatures_under_push_enforcement() {
+    let mut config = enforcing_config();
+    let enforcement = &mut config.signing.as_mut().unwrap().enforcement;
+    enforcement.enforce_on_push = true;
+    enforcement.grace_period_secs = 1;
+    let registry = TestRegistry::with_config(config).await;
+
+    // The first push starts the grace period, during which the image can be pulled and retagged
+    let (manifest, _) = registry.push_image("app", "v1", b"layer").await;
+    assert_eq!(registry.get("/v2/app/manifests/v1").await.status(), StatusCode::OK);
+    assert_eq!(registry.put_manifest("app", "v1-rc", &manifest).await.status(), StatusCode::CREATED);
+
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    assert_eq!(registry.get("/v2/app/manifests/v1").await.status(), StatusCode::FORBIDDEN);
+    let response = registry.put_manifest("app", "stable", &manifest).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(body_json(response).await["errors"][0]["code"], "DENIED");
+
+    // Content new to the repository is still accepted
+    registry.push_image("app", "v2", b"another layer").await;
+
+    let signing = registry.state.signing.as_ref().unwrap();
+    signing.sign_content(&manifest, "fixture", SignatureFormat::Simple, payload("app")).await.unwrap();
+    assert_eq!(registry.put_manifest("app", "stable", &manifest).await.status(), StatusCode::CREATED);
+    assert_eq!(registry.get("/v2/app/manifests/stable").await.status(), StatusCode::OK);
+}
+
+/// Ids of the signatures listed for a manifest, as a user with only pull access sees them
+async fn listed_signatures(registry: &TestRegistry, repository: &str, digest: &str) -> Vec<String> {
+    let request = Request::get(format!("/v2/{}/signatures/{}", repository, digest)).body(Body::empty()).unwrap();
+    let response = registry.send_with_scopes(request, &[&format!("repository:{}:pull", repository)]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let report = body_json(response).await;
+    report["signatures"].as_array().unwrap().iter()
+        .map(|signature| signature["signature"]["signature_id"].as_str().unwrap().to_string())
+        .collect()
+}
+
+async fn revoke(registry: &TestRegistry, id: &str) -> StatusCode {
+    let request = Request::delete(format!("/api/v1/signatures/{}", id)).body(Body::empty()).unwrap();
+    registry.send_with_scopes(request, &["registry:*"]).await.status()
+}
+
+#[tokio::test]
+async fn unindexed_and_cosign_pushed_signatures_can_be_revoked_by_id() {
+    let registry = TestRegistry::with_config(enforcing_config()).await;
+    let (manifest, digest) = registry.push_image("app", "latest", b"layer").await;
+    let content_digest = digest.strip_prefix("sha256:").unwrap();
+    let signing = registry.state.signing.as_ref().unwrap();
+
+    // A signature stored before ids were indexed
+    let signature = signing.sign_content(&manifest, "fixture", SignatureFormat::Simple, payload("app")).await.unwrap();
+    registry.state.storage.delete_blob(&format!("signature-ids/{}", signature.signature_id)).await.unwrap();
+    assert_eq!(listed_signatures(&registry, "app", &digest).await, [signature.signature_id.as_str()]);
+    assert_eq!(revoke(&registry, &signature.signature_id).await, StatusCode::NO_CONTENT);
+    assert!(listed_signatures(&registry, "app", &digest).await.is_empty());
+
+    // A signature only in the signature artifact, as if pushed by cosign
+    let id = sign(&registry, "app", &digest, 0).await;
+    signing.delete_signature("app", content_digest, &id).await.unwrap().unwrap();
+    let listed = listed_signatures(&registry, "app", &digest).await;
+    assert_eq!(listed.len(), 1);
+    assert!(listed[0].starts_with("cosign-"));
+    assert_eq!(registry.get("/v2/app/manifests/latest").await.status(), StatusCode::OK);
+
+    assert_eq!(revoke(&registry, &listed[0]).await, StatusCode::NO_CONTENT);
+    assert_eq!(revoke(&registry, &listed[0]).await, StatusCode::NOT_FOUND);
+    assert!(listed_signatures(&registry, "app", &digest).await.is_empty());
+    let tag = signature_tag(&digest);
+    assert_eq!(registry.get(&format!("/v2/app/manifests/{}", tag)).await.status(), StatusCode::NOT_FOUND);
+    assert_eq!(registry.get("/v2/app/manifests/latest").await.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn signature_listing_needs_pull_access_to_the_repository() {
+    let registry = TestRegistry::with_config(enforcing_config()).await;
+    let (_, digest) = registry.push_image("app", "latest", b"layer").await;
+    sign(&registry, "app", &digest, 0).await;
+
+    assert_eq!(listed_signatures(&registry, "app", &digest).await.len(), 1);
+
+    let request = Request::get(format!("/v2/app/signatures/{}", digest)).body(Body::empty()).unwrap();
+    let response = registry.send_with_scopes(request, &["repository:other:pull"]).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
@@ -0,0 +1,236 @@
+mod common;
+
+use axum::http::StatusCode;
+use bytes::Bytes;
+use common::{body_bytes, body_json, image_manifest, sha256_digest, test_config, TestRegistry, OCI_MANIFEST};
+
+/// Push, pull and list through a registry, expecting the same answers from any backend
+async fn round_trip(registry: &TestRegistry) {
+    let (manifest, digest) = registry.push_image("team/app", "v1", b"layer").await;
+    assert_eq!(registry.put_manifest("team/app", "v2", &manifest).await.status(), StatusCode::CREATED);
+
+    for reference in ["v1", "v2", digest.as_str()] {
+        let response = registry.get(&format!("/v2/team/app/manifests/{}", reference)).await;
+        assert_eq!(response.status(), StatusCode::OK, "{}", reference);
+        assert_eq!(body_bytes(response).await, manifest);
+    }
+
+    let tags = body_json(registry.get("/v2/team/app/tags/list").await).await;
+    assert_eq!(tags["tags"], serde_json::json!(["v1", "v2"]));
+    let catalog = body_json(registry.get("/v2/_catalog").await).await;
+    assert_eq!(catalog["repositories"], serde_json::json!(["team/app"]));
+
+    let storage = &registry.state.storage;
+    // Other state kept through the backend (holds, robots) is not content
+    let blobs = storage.list_all_blobs().await.unwrap();
+    assert_eq!(blobs.iter().filter(|blob| !blob.contains('/')).count(), 2, "{:?}", blobs);
+    assert!(storage.list_uploads().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn memory_and_filesystem_backends_serve_the_same_content() {
+    round_trip(&TestRegistry::new().await).await;
+
+    let registry = TestRegistry::filesystem(test_config()).await;
+    round_trip(&registry).await;
+    let root = registry.storage_dir.as_ref().unwrap().path().to_path_buf();
+    assert!(root.read_dir().unwrap().next().is_some());
+
+    drop(registry);
+    assert!(!root.exists());
+}
+
+#[tokio::test]
+async fn interrupted_writes_are_cleaned_up_and_corrupt_blobs_never_served() {
+    let mut config = test_config();
+    config.storage.verify_on_read = true;
+    config.rbac.as_mut().unwrap().enabled = true;
+    let registry = TestRegistry::filesystem(config).await;
+    let storage = &registry.state.storage;
+    let root = registry.storage_dir.as_ref().unwrap().path().to_path_buf();
+
+    let content = sha256_digest(b"content");
+    storage.put_blob(&content, Bytes::from_static(b"content")).await.unwrap();
+
+    // A crash between writing the temp file and renaming it into place
+    let partial = sha256_digest(b"partial content");
+    let blob_dir = root.join("blobs").join(&partial[..2]);
+    std::fs::write(blob_dir.join(".tmp-interrupted"), b"partial").unwrap();
+    assert_eq!(storage.get_blob(&partial).await.unwrap(), None);
+
+    // A file damaged on disk is refused rather than served
+    let corrupt = sha256_digest(b"original");
+    storage.put_blob(&corrupt, Bytes::from_static(b"original")).await.unwrap();
+    std::fs::write(blob_dir.join(&corrupt), b"damaged").unwrap();
+    assert!(storage.get_blob(&corrupt).await.is_err());
+    assert_ne!(registry.get(&format!("/v2/app/blobs/{}", corrupt)).await.status(), StatusCode::OK);
+
+    // Restarting removes the leftover; RBAC documents, which aren't content, load unverified
+    let registry = registry.restart().await;
+    assert!(!blob_dir.join(".tmp-interrupted").exists());
+    let storage = &registry.state.storage;
+    assert_eq!(storage.get_blob(&content).await.unwrap().as_deref(), Some(&b"content"[..]));
+    assert_eq!(storage.get_blob(&partial).await.unwrap(), None);
+    assert!(!registry.state.rbac.as_ref().unwrap().list_roles().await.is_empty());
+}
+
+#[tokio::test]
+async fn blob_references_are_recorded_per_manifest_and_survive_restarts() {
+    let registry = TestRegistry::filesystem(test_config()).await;
+    let root = registry.storage_dir.as_ref().unwrap().path().to_path_buf();
+    let layer = sha256_digest(b"shared layer");
+    let (_, first) = registry.push_image("team/one", "v1", b"shared layer").await;
+    registry.push_image("team/two", "v1", b"shared layer").await;
+
+    // Manifests pushed together each get their own record
+    let storage = registry.state.storage.clone();
+    let pushes = (1..=16).map(|size| {
+        let storage = storage.clone();
+        let manifest = image_manifest(&layer, size, &[]);
+        async move { storage.put_manifest("team/three", &format!("v{}", size), manifest.into(), OCI_MANIFEST).await }
+    });
+    for pushed in futures::future::join_all(pushes).await {
+        pushed.unwrap();
+    }
+    assert!(root.join("refs/team/one").join(&first).is_file());
+    assert_eq!(std::fs::read_dir(root.join("refs/team/three")).unwrap().count(), 16);
+
+    let registry = registry.restart().await;
+    let references = registry.state.storage.blob_references().await.unwrap().unwrap();
+    assert_eq!(references.manifests.len(), 18);
+    assert_eq!(references.blobs[&layer].len(), 3);
+
+    // Without records, or with only the table earlier versions kept, they are rebuilt
+    std::fs::remove_dir_all(root.join("refs")).unwrap();
+    std::fs::write(root.join("refs.json"), b"{}").unwrap();
+    let registry = registry.restart().await;
+    assert!(!root.join("refs.json").exists());
+    let references = registry.state.storage.blob_references().await.unwrap().unwrap();
+    assert_eq!(references.manifests.len(), 18);
+
+    // The shared layer goes only with the last manifest referencing it
+    let storage = &registry.state.storage;
+    storage.delete_manifest("team/one", "v1").await.unwrap();
+    storage.delete_manifest("team/one", &first).await.unwrap();
+    assert!(!root.join("refs/team/one").join(&first).exists());
+    storage.delete_blob(&layer).await.unwrap();
+    assert!(storage.blob_exists(&layer).await.unwrap());
+
+    storage.delete_manifest("team/two", "v1").await.unwrap();
+    storage.delete_manifest("team/two", &first).await.unwrap();
+    for size in 1..=16 {
+        storage.delete_manifest("team/three", &format!("v{}", size)).await.unwrap();
+    }
+    storage.delete_blob(&layer).await.unwrap();
+    assert!(!storage.blob_exists(&layer).await.unwrap());
+}
+
+#[tokio::test]
+async fn nested_repositories_never_share_paths_with_tags() {
+    let registry = TestRegistry::filesystem(test_config()).await;
+    let root = registry.storage_dir.as_ref().unwrap().path().to_path_buf();
+
+    // `a/b` the repository and `b` the tag of `a` are different things
+    let (tagged, _) = registry.push_image("a", "b", b"tagged layer").await;
+    let (nested, _) = registry.push_image("a/b", "v1", b"nested layer").await;
+    assert_eq!(body_bytes(registry.get("/v2/a/manifests/b").await).await, tagged);
+    assert_eq!(body_bytes(registry.get("/v2/a/b/manifests/v1").await).await, nested);
+    assert_eq!(body_json(registry.get("/v2/a/tags/list").await).await["tags"], serde_json::json!(["b"]));
+    assert_eq!(body_json(registry.get("/v2/a/b/tags/list").await).await["tags"], serde_json::json!(["v1"]));
+    let catalog = body_json(registry.get("/v2/_catalog").await).await;
+    assert_eq!(catalog["repositories"], serde_json::json!(["a", "a/b"]));
+
+    // Names the API would refuse can't be stored by other routes either
+    let storage = &registry.state.storage;
+    for (repo, reference) in [("Team/App", "v1"), ("app", "../v1"), ("../app", "v1"), ("app", "sha256:abc")] {
+        let stored = storage.put_manifest(repo, reference, Bytes::from(tagged.clone()), OCI_MANIFEST).await;
+        assert!(stored.is_err(), "{}:{}", repo, reference);
+        assert_eq!(storage.get_manifest(repo, reference).await.unwrap(), None);
+    }
+    assert!(!root.join("app").exists() && !root.join("manifests/v1").exists());
+
+    // Manifests kept in the layout earlier versions used are moved on startup
+    for dir in ["manifests", "manifest_types"] {
+        std::fs::create_dir_all(root.join(dir).join("legacy/app")).unwrap();
+    }
+    std::fs::write(root.join("manifests/legacy/app/v1"), &nested).unwrap();
+    std::fs::write(root.join("manifest_types/legacy/app/v1"), OCI_MANIFEST).unwrap();
+    let registry = registry.restart().await;
+    let response = registry.get("/v2/legacy/app/manifests/v1").await;
+    assert_eq!(response.headers()["content-type"], OCI_MANIFEST);
+    assert_eq!(body_bytes(response).await, nested);
+    assert!(!root.join("manifests/legacy/app/v1").exists());
+    let catalog = body_json(registry.get("/v2/_catalog").await).await;
+    assert_eq!(catalog["repositories"], serde_json::json!(["a", "a/b", "legacy/app"]));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn interleaved_manifest_writes_are_never_seen_half_done() {
+    let registry = TestRegistry::filesystem(test_config()).await;
+    let storage = registry.state.storage.clone();
+    let layer = sha256_digest(b"layer");
+
+    // Two versions of one tag, large enough to take several writes each
+    let versions: Vec<Vec<u8>> = (1..=2)
+        .map(|version| {
+            let manifest = image_manifest(&layer, version, &[]);
+            let mut manifest: serde_json::Value = serde_json::from_slice(&manifest).unwrap();
+            manifest["annotations"] = serde_json::json!({ "padding": "x".repeat(128 * 1024) });
+            serde_json::to_vec(&manifest).unwrap()
+        })
+        .collect();
+
+    let writers: Vec<_> = (0..8)
+        .map(|writer| {
+            let (storage, versions) = (storage.clone(), versions.clone());
+            tokio::spawn(async move {
+                for round in 0..8 {
+                    let manifest = Bytes::from(versions[(writer + round) % 2].clone());
+                    storage.put_manifest("app", "latest", manifest.clone(), OCI_MANIFEST).await.unwrap();
+                    // Other references and repositories are written alongside
+                    let (repo, tag) = (format!("other{}", writer % 2), format!("v{}-{}", writer, round));
+                    storage.put_manifest(&repo, &tag, manifest, OCI_MANIFEST).await.unwrap();
+                }
+            })
+        })
+        .collect();
+    let done = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let (storage, versions, done) = (storage.clone(), versions.clone(), done.clone());
+            tokio::spawn(async move {
+                let mut reads = 0;
+                while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                    if let Some(data) = storage.get_manifest("app", "latest").await.unwrap() {
+                        assert!(versions.iter().any(|version| data == version[..]), "read {} bytes", data.len());
+                        reads += 1;
+                    }
+                }
+                reads
+            })
+        })
+        .collect();
+
+    // A consistency check can run while manifests are being written
+    storage.fsck().await.unwrap();
+    for writer in writers {
+        writer.await.unwrap();
+    }
+    done.store(true, std::sync::atomic::Ordering::Relaxed);
+    for reader in readers {
+        assert!(reader.await.unwrap() > 0);
+    }
+
+    // The tag is marked on the digest it ended at, and nowhere else
+    let latest = sha256_digest(&storage.get_manifest("app", "latest").await.unwrap().unwrap());
+    for version in &versions {
+        let digest = sha256_digest(version);
+        let expected = if digest == latest { vec!["latest".to_string()] } else { Vec::new() };
+        assert_eq!(storage.list_tags_for_digest("app", &digest).await.unwrap(), expected);
+    }
+    for repo in ["other0", "other1"] {
+        assert_eq!(storage.list_tags(repo).await.unwrap().len(), 32);
+    }
+    let references = storage.blob_references().await.unwrap().unwrap();
+    assert_eq!(references.manifests.len(), 5);
+}
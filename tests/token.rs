@@ -0,0 +1,138 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, Response, StatusCode},
+};
+use common::{
+    basic_auth, body_json, image_manifest, sha256_digest, test_config, TestRegistry, OCI_MANIFEST, PASSWORD, USERNAME,
+};
+use drift::config::{AuthMode, TlsConfig};
+
+async fn registry() -> TestRegistry {
+    let mut config = test_config();
+    config.auth.mode = AuthMode::Token;
+    TestRegistry::with_config(config).await
+}
+
+/// Exchange the admin's basic credentials for a token holding `scope`
+async fn fetch_token(registry: &TestRegistry, scope: &str) -> serde_json::Value {
+    let response = registry
+        .send(
+            Request::get(format!("/v2/token?service=drift&scope={}", scope))
+                .header(header::AUTHORIZATION, basic_auth(USERNAME, PASSWORD))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    body_json(response).await
+}
+
+/// Send a request carrying `token` as a bearer token
+async fn send(registry: &TestRegistry, mut request: Request<Body>, token: &serde_json::Value) -> Response<Body> {
+    let bearer = format!("Bearer {}", token["token"].as_str().unwrap());
+    request.headers_mut().insert(header::AUTHORIZATION, bearer.parse().unwrap());
+    registry.send(request).await
+}
+
+/// The realm of the challenge to an anonymous pull sent to `registry.test`
+async fn challenge_realm(registry: &TestRegistry) -> String {
+    let request = Request::get("/v2/app/manifests/latest").header(header::HOST, "registry.test:5000");
+    let response = registry.send(request.body(Body::empty()).unwrap()).await;
+    let challenge = response.headers()[header::WWW_AUTHENTICATE].to_str().unwrap();
+    let realm = challenge.strip_prefix(r#"Bearer realm=""#).unwrap();
+    realm.split('"').next().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn challenges_point_at_the_token_endpoint_clients_can_reach() {
+    // Plain HTTP listener
+    assert_eq!(challenge_realm(&registry().await).await, "http://registry.test:5000/v2/token");
+
+    // Listener serving TLS itself
+    let mut config = test_config();
+    config.auth.mode = AuthMode::Token;
+    config.server.tls = Some(TlsConfig {
+        cert_path: "/certs/fullchain.pem".to_string(),
+        key_path: "/certs/privkey.pem".to_string(),
+        client_ca_path: None,
+        require_client_cert: false,
+        reload_interval_secs: 30,
+    });
+    let registry = TestRegistry::with_config(config).await;
+    assert_eq!(challenge_realm(&registry).await, "https://registry.test:5000/v2/token");
+
+    // Behind a TLS-terminating proxy
+    let mut config = test_config();
+    config.auth.mode = AuthMode::Token;
+    config.server.external_url = Some("https://registry.example.com/".to_string());
+    let registry = TestRegistry::with_config(config).await;
+    assert_eq!(challenge_realm(&registry).await, "https://registry.example.com/v2/token");
+}
+
+#[tokio::test]
+async fn clients_are_challenged_then_pull_with_a_scoped_token() {
+    let registry = registry().await;
+
+    // No credentials, then basic credentials sent straight to the registry
+    for authorization in [None, Some(basic_auth(USERNAME, PASSWORD))] {
+        let mut request = Request::get("/v2/app/manifests/latest");
+        if let Some(authorization) = authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+        let response = registry.send(request.body(Body::empty()).unwrap()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let challenge = response.headers()[header::WWW_AUTHENTICATE].to_str().unwrap();
+        assert!(challenge.starts_with("Bearer realm="), "{}", challenge);
+        assert!(challenge.contains(r#"scope="repository:app:pull""#), "{}", challenge);
+    }
+
+    // Only the actions the user holds are granted; pushing needs no delete
+    let token = fetch_token(&registry, "repository:app:pull,push,delete").await;
+    assert!(token["expires_in"].as_u64().unwrap() > 0);
+
+    let config = br#"{"os":"linux"}"#;
+    let digest = sha256_digest(config);
+    let response = send(&registry, Request::post("/v2/app/blobs/uploads/").body(Body::empty()).unwrap(), &token).await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let location = response.headers()[header::LOCATION].to_str().unwrap().to_string();
+    let request = Request::put(format!("{}?digest={}", location, digest)).body(Body::from(&config[..])).unwrap();
+    assert_eq!(send(&registry, request, &token).await.status(), StatusCode::CREATED);
+
+    let manifest = image_manifest(&digest, config.len(), &[]);
+    let request = Request::put("/v2/app/manifests/latest")
+        .header(header::CONTENT_TYPE, OCI_MANIFEST)
+        .body(Body::from(manifest))
+        .unwrap();
+    assert_eq!(send(&registry, request, &token).await.status(), StatusCode::CREATED);
+
+    let request = Request::delete("/v2/app/manifests/latest").body(Body::empty()).unwrap();
+    assert_eq!(send(&registry, request, &token).await.status(), StatusCode::UNAUTHORIZED);
+
+    // A pull-only token pulls, and is challenged for more when it tries to push
+    let token = fetch_token(&registry, "repository:app:pull").await;
+    let request = Request::get("/v2/app/manifests/latest").body(Body::empty()).unwrap();
+    assert_eq!(send(&registry, request, &token).await.status(), StatusCode::OK);
+
+    let response = send(&registry, Request::post("/v2/app/blobs/uploads/").body(Body::empty()).unwrap(), &token).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let challenge = response.headers()[header::WWW_AUTHENTICATE].to_str().unwrap();
+    assert!(challenge.contains(r#"error="insufficient_scope""#), "{}", challenge);
+    assert!(challenge.contains(r#"scope="repository:app:push""#), "{}", challenge);
+}
+
+#[tokio::test]
+async fn requested_scopes_name_a_single_repository() {
+    let registry = registry().await;
+
+    for scope in ["repository:*:pull,push", "repository:team/*:push", "repository:App:pull", "registry:*:*"] {
+        let token = fetch_token(&registry, scope).await;
+        let user = registry.state.auth.validate_token(token["token"].as_str().unwrap()).unwrap().unwrap();
+        assert!(user.scopes.is_empty(), "{}: {:?}", scope, user.scopes);
+    }
+
+    let token = fetch_token(&registry, "repository:team/app:push").await;
+    let user = registry.state.auth.validate_token(token["token"].as_str().unwrap()).unwrap().unwrap();
+    assert_eq!(user.scopes, ["repository:team/app:push"]);
+}
@@ -0,0 +1,190 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+};
+use common::{body_bytes, body_json, sha256_digest, test_config, TestRegistry};
+
+#[tokio::test]
+async fn chunked_upload_reports_its_offset_after_each_patch() {
+    let registry = TestRegistry::new().await;
+    let location = registry.start_upload("app").await;
+    let blob: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+
+    let mut offset = 0;
+    for chunk in blob.chunks(100_000) {
+        let response = registry
+            .send_authenticated(
+                Request::patch(&location)
+                    .header(header::CONTENT_TYPE, "application/octet-stream")
+                    .header("Content-Range", format!("{}-{}", offset, offset + chunk.len() - 1))
+                    .body(Body::from(chunk.to_vec()))
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        offset += chunk.len();
+        assert_eq!(response.headers()["Range"], format!("0-{}", offset - 1).as_str());
+    }
+
+    // A chunk that doesn't start at the current offset is refused
+    let response = registry
+        .send_authenticated(
+            Request::patch(&location)
+                .header("Content-Range", "0-9")
+                .body(Body::from(vec![0u8; 10]))
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+
+    let response = registry.get(&location).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(response.headers()["Range"], format!("0-{}", blob.len() - 1).as_str());
+
+    let digest = sha256_digest(&blob);
+    let response = registry
+        .send_authenticated(
+            Request::put(format!("{}?digest={}", location, digest))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let response = registry.get(&format!("/v2/app/blobs/{}", digest)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body_bytes(response).await, blob);
+}
+
+#[tokio::test]
+async fn upload_completed_with_the_wrong_digest_is_rejected() {
+    let registry = TestRegistry::new().await;
+    let location = registry.start_upload("app").await;
+    let wrong = sha256_digest(b"other content");
+
+    let response = registry
+        .send_authenticated(
+            Request::put(format!("{}?digest={}", location, wrong))
+                .header(header::CONTENT_TYPE, "application/octet-stream")
+                .body(Body::from(&b"content"[..]))
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(body_json(response).await["errors"][0]["code"], "DIGEST_INVALID");
+
+    for digest in [wrong, sha256_digest(b"content")] {
+        let response = registry.head(&format!("/v2/app/blobs/{}", digest)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
+
+#[tokio::test]
+async fn chunks_crossing_the_upload_size_limit_cancel_the_upload() {
+    let mut config = test_config();
+    config.registry.max_upload_size_mb = 1;
+    let registry = TestRegistry::with_config(config).await;
+    let location = registry.start_upload("app").await;
+    let chunk = vec![1u8; 600 * 1024];
+
+    let patch = |offset: usize| {
+        Request::patch(&location)
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .header("Content-Range", format!("{}-{}", offset, offset + chunk.len() - 1))
+            .body(Body::from(chunk.clone()))
+            .unwrap()
+    };
+
+    let response = registry.send_authenticated(patch(0)).await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+    let response = registry.send_authenticated(patch(chunk.len())).await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(body_json(response).await["errors"][0]["code"], "SIZE_INVALID");
+
+    // The session is gone, along with what it had stored
+    let response = registry.get(&location).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(registry.state.storage.list_uploads().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn monolithic_uploads_one_byte_over_the_limit_are_rejected_and_removed() {
+    let mut config = test_config();
+    config.registry.max_upload_size_mb = 1;
+    let registry = TestRegistry::with_config(config).await;
+    let blob = vec![7u8; 1024 * 1024 + 1];
+    let digest = sha256_digest(&blob);
+
+    // Once with the length declared up front, once only discovered while streaming
+    for declared in [true, false] {
+        let location = registry.start_upload("app").await;
+        let mut request = Request::put(format!("{}?digest={}", location, digest))
+            .header(header::CONTENT_TYPE, "application/octet-stream");
+        let body = if declared {
+            request = request.header(header::CONTENT_LENGTH, blob.len());
+            Body::from(blob.clone())
+        } else {
+            let chunks: Vec<Result<Vec<u8>, std::io::Error>> = blob.chunks(64 * 1024).map(|c| Ok(c.to_vec())).collect();
+            Body::from_stream(futures::stream::iter(chunks))
+        };
+
+        let response = registry.send_authenticated(request.body(body).unwrap()).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE, "declared: {}", declared);
+        let error = &body_json(response).await["errors"][0];
+        assert_eq!(error["code"], "SIZE_INVALID");
+        assert_eq!(error["detail"]["limit"], 1024 * 1024);
+
+        assert_eq!(registry.get(&location).await.status(), StatusCode::NOT_FOUND);
+        assert!(registry.state.storage.list_uploads().await.unwrap().is_empty());
+        assert!(!registry.state.storage.blob_exists(&digest).await.unwrap());
+    }
+}
+
+#[tokio::test]
+async fn upload_ids_that_are_not_uuids_are_refused() {
+    let registry = TestRegistry::filesystem(test_config()).await;
+    let root = registry.storage_dir.as_ref().unwrap().path().to_path_buf();
+    std::fs::write(root.join("marker"), b"original").unwrap();
+    let location = "/v2/app/blobs/uploads/..%2Fmarker";
+
+    let response = registry
+        .send_authenticated(Request::patch(location).body(Body::from(&b"appended"[..])).unwrap())
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(body_json(response).await["errors"][0]["code"], "BLOB_UPLOAD_INVALID");
+
+    let response = registry.send_authenticated(Request::delete(location).body(Body::empty()).unwrap()).await;
+    assert_eq!(body_json(response).await["errors"][0]["code"], "BLOB_UPLOAD_INVALID");
+    assert_eq!(std::fs::read(root.join("marker")).unwrap(), b"original");
+}
+
+#[tokio::test]
+async fn uploads_can_only_be_continued_in_the_repository_that_started_them() {
+    let registry = TestRegistry::new().await;
+    let location = registry.start_upload("app").await;
+    let elsewhere = location.replacen("/v2/app/", "/v2/other/", 1);
+    let digest = sha256_digest(b"content");
+
+    let response = registry
+        .send_authenticated(Request::patch(&elsewhere).body(Body::from(&b"content"[..])).unwrap())
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(body_json(response).await["errors"][0]["code"], "BLOB_UPLOAD_UNKNOWN");
+    let response = registry
+        .send_authenticated(Request::put(format!("{}?digest={}", elsewhere, digest)).body(Body::empty()).unwrap())
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = registry.send_authenticated(Request::delete(&elsewhere).body(Body::empty()).unwrap()).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // The session is untouched and completes in its own repository
+    let response = registry
+        .send_authenticated(
+            Request::put(format!("{}?digest={}", location, digest)).body(Body::from(&b"content"[..])).unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+}